@@ -596,6 +596,8 @@ impl CchActor {
                                 onion_packet: None,
                                 shared_secret: NO_SHARED_SECRET.clone(),
                                 previous_tlc: None,
+                                payment_preimage: None,
+                                idempotency_key: None,
                             },
                             rpc_reply,
                         ),
@@ -7,7 +7,9 @@ use ractor::{
 
 use crate::ckb::contracts::{get_script_by_contract, Contract};
 
-use super::{funding::FundingContext, CkbConfig, FundingError, FundingRequest, FundingTx};
+use super::{
+    funding::FundingContext, CkbConfig, FeeBumpRequest, FundingError, FundingRequest, FundingTx,
+};
 
 pub struct CkbChainActor {}
 
@@ -65,6 +67,10 @@ pub enum CkbChainMessage {
         RpcReplyPort<Result<FundingTx, FundingError>>,
     ),
     Sign(FundingTx, RpcReplyPort<Result<FundingTx, FundingError>>),
+    BuildFeeBumpTx(
+        FeeBumpRequest,
+        RpcReplyPort<Result<TransactionView, FundingError>>,
+    ),
     SendTx(TransactionView, RpcReplyPort<Result<(), RpcError>>),
     TraceTx(TraceTxRequest, RpcReplyPort<TraceTxResponse>),
     GetBlockTimestamp(
@@ -109,7 +115,7 @@ impl Actor for CkbChainActor {
         message: Self::Msg,
         state: &mut Self::State,
     ) -> Result<(), ActorProcessingErr> {
-        use CkbChainMessage::{Fund, SendTx, Sign, TraceTx};
+        use CkbChainMessage::{BuildFeeBumpTx, Fund, SendTx, Sign, TraceTx};
         match message {
             Fund(tx, request, reply_port) => {
                 let context = state.build_funding_context(&request);
@@ -136,6 +142,18 @@ impl Actor for CkbChainActor {
                     });
                 }
             }
+            BuildFeeBumpTx(request, reply_port) => {
+                let context = state.build_fee_bump_context();
+                if !reply_port.is_closed() {
+                    tokio::task::block_in_place(move || {
+                        let result = request.fulfill(context);
+                        if !reply_port.is_closed() {
+                            // ignore error
+                            let _ = reply_port.send(result);
+                        }
+                    });
+                }
+            }
             SendTx(tx, reply_port) => {
                 let rpc_url = state.config.rpc_url.clone();
                 tokio::task::block_in_place(move || {
@@ -290,4 +308,14 @@ impl CkbChainState {
             funding_cell_lock_script: request.script.clone(),
         }
     }
+
+    // The fee bump transaction pays the fee from our funding source and sends the change back.
+    fn build_fee_bump_context(&self) -> FundingContext {
+        FundingContext {
+            secret_key: self.secret_key,
+            rpc_url: self.config.rpc_url.clone(),
+            funding_source_lock_script: self.funding_source_lock_script.clone(),
+            funding_cell_lock_script: self.funding_source_lock_script.clone(),
+        }
+    }
 }
@@ -172,6 +172,31 @@ pub struct UdtArgInfo {
     pub script: UdtScript,
     pub auto_accept_amount: Option<u128>,
     pub cell_deps: Vec<UdtCellDep>,
+    /// Display metadata of the UDT, only used locally and never gossiped to peers.
+    #[serde(default)]
+    pub metadata: Option<UdtMetadata>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct UdtMetadata {
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+impl UdtMetadata {
+    /// Format a raw UDT amount with the configured decimals and symbol,
+    /// e.g. `123456789` with 8 decimals and symbol `RUSD` is `1.23456789 RUSD`.
+    pub fn format_amount(&self, amount: u128) -> String {
+        let decimals = self.decimals as usize;
+        let digits = format!("{:0>width$}", amount, width = decimals + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - decimals);
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.is_empty() {
+            format!("{} {}", integer, self.symbol)
+        } else {
+            format!("{}.{} {}", integer, fraction, self.symbol)
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq, Hash)]
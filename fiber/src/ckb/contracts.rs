@@ -12,7 +12,7 @@ use tracing::info;
 
 use crate::fiber::config::FiberScript;
 
-use super::config::{UdtArgInfo, UdtCfgInfos};
+use super::config::{UdtArgInfo, UdtCfgInfos, UdtMetadata};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum Contract {
@@ -283,6 +283,10 @@ pub fn get_udt_cell_deps(script: &Script) -> Option<CellDepVec> {
     })
 }
 
+pub fn get_udt_metadata(script: &Script) -> Option<UdtMetadata> {
+    get_udt_info(script).and_then(|udt| udt.metadata)
+}
+
 pub fn get_udt_whitelist() -> UdtCfgInfos {
     get_contracts_context().get_udt_whitelist().clone()
 }
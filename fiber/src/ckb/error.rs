@@ -20,6 +20,9 @@ pub enum FundingError {
 
     #[error("The channel is invalid to fund")]
     InvalidChannel,
+
+    #[error("Some inputs of the tx can not be unlocked")]
+    LockedInputs,
 }
 
 #[derive(Error, Debug)]
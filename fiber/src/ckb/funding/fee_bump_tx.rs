@@ -0,0 +1,174 @@
+use super::super::FundingError;
+use super::FundingContext;
+use anyhow::anyhow;
+use ckb_sdk::{
+    constants::SIGHASH_TYPE_HASH,
+    traits::{
+        CellCollector, CellDepResolver, DefaultCellCollector, DefaultCellDepResolver,
+        DefaultHeaderDepResolver, DefaultTransactionDependencyProvider, HeaderDepResolver,
+        SecpCkbRawKeySigner, TransactionDependencyProvider,
+    },
+    tx_builder::{CapacityBalancer, TxBuilder, TxBuilderError},
+    unlock::{ScriptUnlocker, SecpSighashUnlocker},
+    CkbRpcClient, ScriptId,
+};
+use ckb_types::{
+    core::{BlockView, FeeRate, TransactionView},
+    packed::{self, CellInput, OutPoint},
+    prelude::*,
+};
+use std::collections::HashMap;
+use tracing::debug;
+
+// The fee of the child transaction depends on its size, which is only known after the inputs
+// paying the fee are collected, so the transaction is rebuilt at most this many times.
+const MAX_FEE_BUMP_BUILD_ATTEMPTS: usize = 3;
+
+/// A request to bump the fee of a signed transaction, e.g. the commitment transaction of a force
+/// close, with a child transaction spending its anchor output (CPFP). The child pays the fee so
+/// that the parent and the child as a package reach the target fee rate.
+#[derive(Clone, Debug)]
+pub struct FeeBumpRequest {
+    /// The transaction to bump the fee for.
+    pub parent_tx: TransactionView,
+    /// The fee already paid by the parent transaction.
+    pub parent_fee: u64,
+    /// The index of the anchor output of the parent transaction spent by the child transaction.
+    pub anchor_index: u32,
+    /// The target fee rate of the package, in shannons per KB.
+    pub fee_rate: u64,
+}
+
+impl FeeBumpRequest {
+    pub fn anchor_outpoint(&self) -> OutPoint {
+        OutPoint::new(self.parent_tx.hash(), self.anchor_index)
+    }
+
+    /// The fee the child transaction of `child_size` bytes must pay so that the package of
+    /// the parent and the child reaches the target fee rate.
+    pub fn get_child_fee(&self, child_size: u64) -> u64 {
+        let parent_size = self.parent_tx.data().serialized_size_in_block() as u64;
+        FeeRate::from_u64(self.fee_rate)
+            .fee(parent_size + child_size)
+            .as_u64()
+            .saturating_sub(self.parent_fee)
+    }
+
+    // The fee rate the child transaction of `child_size` bytes must pay by itself, which is
+    // never lower than the target fee rate.
+    fn get_child_fee_rate(&self, child_size: u64) -> u64 {
+        (self.get_child_fee(child_size) * 1000)
+            .div_ceil(child_size)
+            .max(self.fee_rate)
+    }
+
+    pub fn fulfill(self, context: FundingContext) -> Result<TransactionView, FundingError> {
+        let mut fee_rate = self.fee_rate;
+        for _ in 0..MAX_FEE_BUMP_BUILD_ATTEMPTS {
+            let builder = FeeBumpTxBuilder {
+                request: &self,
+                context: &context,
+            };
+            let tx = builder.build_with_fee_rate(fee_rate)?;
+            let required_fee_rate =
+                self.get_child_fee_rate(tx.data().serialized_size_in_block() as u64);
+            if required_fee_rate <= fee_rate {
+                debug!("Built fee bump transaction {:?} at fee rate {}", &tx, fee_rate);
+                return Ok(tx);
+            }
+            fee_rate = required_fee_rate;
+        }
+        Err(FundingError::CkbTxBuilderError(TxBuilderError::Other(anyhow!(
+            "can not build a fee bump transaction paying enough fee"
+        ))))
+    }
+}
+
+struct FeeBumpTxBuilder<'a> {
+    request: &'a FeeBumpRequest,
+    context: &'a FundingContext,
+}
+
+impl TxBuilder for FeeBumpTxBuilder<'_> {
+    fn build_base(
+        &self,
+        _cell_collector: &mut dyn CellCollector,
+        cell_dep_resolver: &dyn CellDepResolver,
+        _header_dep_resolver: &dyn HeaderDepResolver,
+        _tx_dep_provider: &dyn TransactionDependencyProvider,
+    ) -> Result<TransactionView, TxBuilderError> {
+        let anchor_output = self
+            .request
+            .parent_tx
+            .output(self.request.anchor_index as usize)
+            .ok_or_else(|| TxBuilderError::InvalidParameter(anyhow!("anchor output not found")))?;
+        let anchor_lock = anchor_output.lock();
+        let cell_dep = cell_dep_resolver
+            .resolve(&anchor_lock)
+            .ok_or(TxBuilderError::ResolveCellDepFailed(anchor_lock))?;
+
+        // Only the anchor output is spent here, the inputs to pay the fee and the change output
+        // are added by the capacity balancer.
+        let tx = packed::Transaction::default()
+            .as_advanced_builder()
+            .input(CellInput::new(self.request.anchor_outpoint(), 0))
+            .cell_dep(cell_dep)
+            .witness(sighash_placeholder_witness().as_bytes().pack())
+            .build();
+        Ok(tx)
+    }
+}
+
+impl FeeBumpTxBuilder<'_> {
+    fn build_with_fee_rate(&self, fee_rate: u64) -> Result<TransactionView, FundingError> {
+        // Both the anchor output and the inputs paying the fee are unlocked by our key.
+        let signer = SecpCkbRawKeySigner::new_with_secret_keys(vec![std::str::FromStr::from_str(
+            hex::encode(self.context.secret_key.as_ref()).as_ref(),
+        )
+        .expect("convert secret key between different secp256k1 versions")]);
+        let sighash_unlocker = SecpSighashUnlocker::from(Box::new(signer) as Box<_>);
+        let sighash_script_id = ScriptId::new_type(SIGHASH_TYPE_HASH.clone());
+        let mut unlockers = HashMap::default();
+        unlockers.insert(
+            sighash_script_id,
+            Box::new(sighash_unlocker) as Box<dyn ScriptUnlocker>,
+        );
+
+        let sender = self.context.funding_source_lock_script.clone();
+        let balancer =
+            CapacityBalancer::new_simple(sender.clone(), sighash_placeholder_witness(), fee_rate);
+
+        let ckb_client = CkbRpcClient::new(&self.context.rpc_url);
+        let cell_dep_resolver = ckb_client
+            .get_block_by_number(0.into())
+            .map_err(FundingError::CkbRpcError)?
+            .and_then(|genesis_block| {
+                DefaultCellDepResolver::from_genesis(&BlockView::from(genesis_block)).ok()
+            })
+            .ok_or_else(|| {
+                FundingError::CkbTxBuilderError(TxBuilderError::ResolveCellDepFailed(sender))
+            })?;
+        let header_dep_resolver = DefaultHeaderDepResolver::new(&self.context.rpc_url);
+        let mut cell_collector = DefaultCellCollector::new(&self.context.rpc_url);
+        let tx_dep_provider = DefaultTransactionDependencyProvider::new(&self.context.rpc_url, 10);
+
+        let (tx, still_locked_groups) = self.build_unlocked(
+            &mut cell_collector,
+            &cell_dep_resolver,
+            &header_dep_resolver,
+            &tx_dep_provider,
+            &balancer,
+            &unlockers,
+        )?;
+        if !still_locked_groups.is_empty() {
+            return Err(FundingError::LockedInputs);
+        }
+        Ok(tx)
+    }
+}
+
+fn sighash_placeholder_witness() -> packed::WitnessArgs {
+    packed::WitnessArgs::new_builder()
+        .lock(Some(molecule::bytes::Bytes::from(vec![0u8; 65])).pack())
+        .build()
+}
@@ -1,4 +1,6 @@
+mod fee_bump_tx;
 mod funding_tx;
 
+pub use fee_bump_tx::FeeBumpRequest;
 pub(crate) use funding_tx::FundingContext;
 pub use funding_tx::{FundingRequest, FundingTx};
@@ -8,7 +8,7 @@ pub use actor::{
 };
 pub use config::{CkbConfig, DEFAULT_CKB_BASE_DIR_NAME};
 pub use error::{CkbChainError, FundingError};
-pub use funding::{FundingRequest, FundingTx};
+pub use funding::{FeeBumpRequest, FundingRequest, FundingTx};
 
 pub mod config;
 pub mod contracts;
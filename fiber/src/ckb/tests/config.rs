@@ -1,4 +1,4 @@
-use crate::ckb::config::{UdtArgInfo, UdtCellDep, UdtCfgInfos, UdtScript};
+use crate::ckb::config::{UdtArgInfo, UdtCellDep, UdtCfgInfos, UdtMetadata, UdtScript};
 use crate::fiber::gen::fiber::UdtCfgInfos as MoleculeUdtCfgInfos;
 use ckb_types::core::{DepType, ScriptHashType};
 use ckb_types::H256;
@@ -19,6 +19,7 @@ fn test_udt_whitelist() {
             tx_hash: H256::from([0u8; 32]),
             index: 0,
         }],
+        metadata: None,
     }]);
 
     let serialized = MoleculeUdtCfgInfos::from(udt_whitelist.clone()).as_bytes();
@@ -26,3 +27,50 @@ fn test_udt_whitelist() {
         UdtCfgInfos::from(MoleculeUdtCfgInfos::from_slice(&serialized).expect("invalid mol"));
     assert_eq!(udt_whitelist, deserialized);
 }
+
+#[test]
+fn test_udt_metadata_format_amount() {
+    let metadata = UdtMetadata {
+        symbol: "RUSD".to_string(),
+        decimals: 8,
+    };
+    assert_eq!(metadata.format_amount(123456789), "1.23456789 RUSD");
+    assert_eq!(metadata.format_amount(100000000), "1 RUSD");
+    assert_eq!(metadata.format_amount(1500), "0.000015 RUSD");
+    assert_eq!(metadata.format_amount(0), "0 RUSD");
+
+    let metadata = UdtMetadata {
+        symbol: "XUDT".to_string(),
+        decimals: 0,
+    };
+    assert_eq!(metadata.format_amount(42), "42 XUDT");
+}
+
+#[test]
+fn test_udt_whitelist_bincode_serialization() {
+    let mut udt_arg_info = UdtArgInfo {
+        name: "SimpleUDT".to_string(),
+        script: UdtScript {
+            code_hash: H256::from([0u8; 32]),
+            hash_type: ScriptHashType::Data,
+            args: "0x00".to_string(),
+        },
+        auto_accept_amount: None,
+        cell_deps: vec![],
+        metadata: None,
+    };
+    // The whitelist is persisted with bincode in the node announcements.
+    for metadata in [
+        None,
+        Some(UdtMetadata {
+            symbol: "RUSD".to_string(),
+            decimals: 8,
+        }),
+    ] {
+        udt_arg_info.metadata = metadata;
+        let udt_whitelist = UdtCfgInfos(vec![udt_arg_info.clone()]);
+        let serialized = bincode::serialize(&udt_whitelist).expect("serialize");
+        let deserialized: UdtCfgInfos = bincode::deserialize(&serialized).expect("deserialize");
+        assert_eq!(udt_whitelist, deserialized);
+    }
+}
@@ -4,7 +4,7 @@ use ckb_testtool::context::Context;
 use ckb_types::{
     bytes::Bytes,
     core::{DepType, TransactionView},
-    packed::{CellDep, CellOutput, OutPoint, Script, Transaction},
+    packed::{CellDep, CellInput, CellOutput, OutPoint, Script, Transaction},
     prelude::{Builder, Entity, IntoTransactionView, Pack, PackVec, Unpack},
     H256,
 };
@@ -401,6 +401,56 @@ impl Actor for MockChainActor {
                     );
                 }
             }
+            BuildFeeBumpTx(request, reply_port) => {
+                // Pay the fee with a new cell locked by the mock secp256k1 lock script,
+                // which always succeeds, so the transaction needs no signatures.
+                const WALLET_CELL_CAPACITY: u64 = 1_000_000_000_000;
+                let mock_context = &mut MOCK_CONTEXT.write().unwrap();
+                let wallet_lock = mock_context
+                    .contracts_context
+                    .get_script(Contract::Secp256k1Lock, &[0u8; 20]);
+                let cell_deps = mock_context
+                    .contracts_context
+                    .get_cell_deps(vec![Contract::Secp256k1Lock]);
+                let wallet_cell = mock_context.context.create_cell(
+                    CellOutput::new_builder()
+                        .capacity(WALLET_CELL_CAPACITY.pack())
+                        .lock(wallet_lock.clone())
+                        .build(),
+                    Bytes::new(),
+                );
+                let anchor_capacity: u64 = request
+                    .parent_tx
+                    .output(request.anchor_index as usize)
+                    .expect("anchor output")
+                    .capacity()
+                    .unpack();
+                let build_tx = |fee: u64| {
+                    TransactionView::new_advanced_builder()
+                        .cell_deps(cell_deps.clone())
+                        .input(CellInput::new(request.anchor_outpoint(), 0))
+                        .input(CellInput::new(wallet_cell.clone(), 0))
+                        .output(
+                            CellOutput::new_builder()
+                                .capacity((anchor_capacity + WALLET_CELL_CAPACITY - fee).pack())
+                                .lock(wallet_lock.clone())
+                                .build(),
+                        )
+                        .output_data(Bytes::new().pack())
+                        .build()
+                };
+                // The size of the transaction does not depend on the fee.
+                let size = build_tx(0).data().serialized_size_in_block() as u64;
+                let tx = build_tx(request.get_child_fee(size));
+                debug!("Built fee bump transaction: {:?}", &tx);
+                if let Err(e) = reply_port.send(Ok(tx)) {
+                    error!(
+                        "[{}] send reply failed: {:?}",
+                        myself.get_name().unwrap_or_default(),
+                        e
+                    );
+                }
+            }
             SendTx(tx, reply_port) => {
                 const MAX_CYCLES: u64 = 100_000_000;
                 let mut f = || {
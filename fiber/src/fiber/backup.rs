@@ -0,0 +1,83 @@
+//! Static channel backups, which keep the minimal static data of a channel, so that the funds
+//! can be recovered by requesting the peer to force close the channel when the channel store is lost.
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use ckb_types::packed::{OutPoint, Script};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+use super::{
+    channel::ChannelBasePublicKeys,
+    key::blake2b_hash_with_salt,
+    serde_utils::{EntityHex, SliceHex},
+    types::{Hash256, Privkey, Pubkey},
+};
+use crate::{Error, Result};
+
+const CHANNEL_BACKUP_NONCE_LENGTH: usize = 12;
+
+/// The static data of a channel in a backup, it does not change over the life of the channel.
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StaticChannelBackup {
+    pub channel_id: Hash256,
+    #[serde_as(as = "EntityHex")]
+    pub funding_outpoint: OutPoint,
+    // Only the public keys of the channel are kept, the backup never contains any secret,
+    // since our funds are sent to the local shutdown script when the peer force closes.
+    pub local_channel_public_keys: ChannelBasePublicKeys,
+    pub remote_pubkey: Pubkey,
+    pub remote_channel_public_keys: ChannelBasePublicKeys,
+    #[serde_as(as = "EntityHex")]
+    pub local_shutdown_script: Script,
+    #[serde_as(as = "EntityHex")]
+    pub remote_shutdown_script: Script,
+}
+
+/// An encrypted [`StaticChannelBackup`], only the node which exports it can decrypt it.
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChannelBackup {
+    #[serde_as(as = "SliceHex")]
+    pub nonce: [u8; CHANNEL_BACKUP_NONCE_LENGTH],
+    #[serde_as(as = "SliceHex")]
+    pub encrypted_data: Vec<u8>,
+}
+
+/// Derive the key to encrypt the channel backups from the node secret key.
+pub fn derive_channel_backup_key(node_secret_key: &Privkey) -> [u8; 32] {
+    let secret_key: &[u8; 32] = node_secret_key.as_ref();
+    blake2b_hash_with_salt(secret_key, b"channel backup key")
+}
+
+impl ChannelBackup {
+    pub fn encrypt(backup: &StaticChannelBackup, key: &[u8; 32]) -> Self {
+        let data = bincode::serialize(backup).expect("serialize static channel backup");
+        let mut nonce = [0u8; CHANNEL_BACKUP_NONCE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let encrypted_data = ChaCha20Poly1305::new(Key::from_slice(key))
+            .encrypt(Nonce::from_slice(&nonce), data.as_slice())
+            .expect("encrypt static channel backup");
+        Self {
+            nonce,
+            encrypted_data,
+        }
+    }
+
+    pub fn decrypt(&self, key: &[u8; 32]) -> Result<StaticChannelBackup> {
+        let data = ChaCha20Poly1305::new(Key::from_slice(key))
+            .decrypt(Nonce::from_slice(&self.nonce), self.encrypted_data.as_slice())
+            .map_err(|_| {
+                Error::InvalidParameter(
+                    "Failed to decrypt channel backup, it's not exported by this node".to_string(),
+                )
+            })?;
+        bincode::deserialize(&data).map_err(|err| {
+            Error::InvalidParameter(format!("Invalid channel backup data: {}", err))
+        })
+    }
+}
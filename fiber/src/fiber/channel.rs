@@ -1,19 +1,24 @@
 #[cfg(debug_assertions)]
 use crate::fiber::network::DebugEvent;
-use crate::fiber::serde_utils::U64Hex;
+use crate::fiber::serde_utils::{SliceHex, U64Hex};
 use bitflags::bitflags;
 use ckb_jsonrpc_types::BlockNumber;
-use futures::future::OptionFuture;
+use futures::future::{join_all, OptionFuture};
 use secp256k1::XOnlyPublicKey;
 use tracing::{debug, error, info, trace, warn};
 
 use crate::{
     ckb::{
-        contracts::{get_cell_deps, get_script_by_contract, Contract},
-        FundingRequest,
+        config::UdtMetadata,
+        contracts::{get_cell_deps, get_script_by_contract, get_udt_metadata, Contract},
+        FeeBumpRequest, FundingRequest,
     },
     fiber::{
-        config::{DEFAULT_MIN_SHUTDOWN_FEE, MAX_PAYMENT_TLC_EXPIRY_LIMIT, MIN_TLC_EXPIRY_DELTA},
+        backup::{ChannelBackup, StaticChannelBackup},
+        config::{
+            ChannelVisibilityPolicy, NoInvoicePolicy, DEFAULT_MIN_SHUTDOWN_FEE,
+            MAX_PAYMENT_TLC_EXPIRY_LIMIT, MIN_TLC_EXPIRY_DELTA,
+        },
         fee::{
             calculate_commitment_tx_fee, calculate_shutdown_tx_fee, calculate_tlc_forward_fee,
             shutdown_tx_size,
@@ -30,8 +35,8 @@ use crate::{
             ClosingSigned, CommitmentSigned, EcdsaSignature, FiberChannelMessage, FiberMessage,
             Hash256, OpenChannel, PaymentOnionPacket, PeeledPaymentOnionPacket, Privkey, Pubkey,
             ReestablishChannel, RemoveTlc, RemoveTlcFulfill, RemoveTlcReason, RevokeAndAck,
-            Shutdown, TlcErr, TlcErrPacket, TlcErrorCode, TxCollaborationMsg, TxComplete, TxUpdate,
-            NO_SHARED_SECRET,
+            Shutdown, TlcErr, TlcErrPacket, TlcErrorCode, TxAbort, TxCollaborationMsg, TxComplete,
+            TxUpdate, UpdateFee, NO_SHARED_SECRET,
         },
         NetworkActorCommand, NetworkActorEvent, NetworkActorMessage, ASSUME_NETWORK_ACTOR_ALIVE,
     },
@@ -39,7 +44,7 @@ use crate::{
     now_timestamp_as_millis_u64, NetworkServiceEvent,
 };
 use ckb_hash::{blake2b_256, new_blake2b};
-use ckb_sdk::{Since, SinceType};
+use ckb_sdk::{Address, AddressPayload, NetworkType, Since, SinceType};
 use ckb_types::{
     core::{
         Capacity, CapacityError, EpochNumberWithFraction, FeeRate, TransactionBuilder,
@@ -67,9 +72,12 @@ use thiserror::Error;
 use tokio::sync::oneshot;
 
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     fmt::Debug,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
     time::{SystemTime, UNIX_EPOCH},
     u128,
 };
@@ -102,7 +110,14 @@ pub const MESSAGE_OF_NODE2_FLAG: u32 = 1;
 // The channel is disabled, and no more tlcs can be added to the channel.
 pub const CHANNEL_DISABLED_FLAG: u32 = 1;
 
+// The commitment transaction of a channel with anchor outputs has the commitment cell at index 0,
+// followed by the anchor output of the counterparty and the anchor output of the holder.
+pub const REMOTE_ANCHOR_OUTPUT_INDEX: u32 = 1;
+pub const LOCAL_ANCHOR_OUTPUT_INDEX: u32 = 2;
+
 const AUTO_SETDOWN_TLC_INTERVAL: Duration = Duration::from_secs(2);
+// The interval to check whether a held TLC is about to expire.
+const HELD_TLC_CHECK_INTERVAL: Duration = Duration::from_secs(60);
 
 #[derive(Debug)]
 pub enum ChannelActorMessage {
@@ -115,16 +130,35 @@ pub enum ChannelActorMessage {
     PeerMessage(FiberChannelMessage),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct AddTlcResponse {
     pub tlc_id: u64,
+    // The commitment numbers when the tlc is created, to correlate it with later commitment updates.
+    pub created_at: CommitmentNumbers,
+    // The expiry of the tlc in milliseconds, the tlc times out after then.
+    pub expiry: u64,
 }
 
 #[derive(Clone)]
 pub struct TlcNotification {
     pub channel_id: Hash256,
-    pub tlc: AddTlcInfo,
+    // Shared by all the subscribers, the onion packet is stripped as it's only used to forward
+    // the tlc.
+    pub tlc: Arc<AddTlcInfo>,
     pub script: Script,
+    // Display metadata of the UDT, `None` if it is not configured for this node.
+    pub udt_metadata: Option<UdtMetadata>,
+}
+
+impl TlcNotification {
+    /// Returns the tlc amount formatted with the UDT decimals and symbol,
+    /// or the raw amount if no UDT metadata is available.
+    pub fn formatted_amount(&self) -> String {
+        match self.udt_metadata {
+            Some(ref metadata) => metadata.format_amount(self.tlc.amount),
+            None => self.tlc.amount.to_string(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -134,17 +168,179 @@ pub enum ChannelCommand {
     // tx_complete event.
     CommitmentSigned(),
     AddTlc(AddTlcCommand, RpcReplyPort<Result<AddTlcResponse, TlcErr>>),
-    RemoveTlc(RemoveTlcCommand, RpcReplyPort<Result<(), String>>),
-    Shutdown(ShutdownCommand, RpcReplyPort<Result<(), String>>),
-    Update(UpdateCommand, RpcReplyPort<Result<(), String>>),
+    AddTlcBatch(
+        Vec<AddTlcCommand>,
+        RpcReplyPort<Result<Vec<AddTlcResponse>, TlcErr>>,
+    ),
+    RemoveTlc(
+        RemoveTlcCommand,
+        RpcReplyPort<Result<(), ChannelCommandError>>,
+    ),
+    FailTlc(
+        u64,
+        TlcErrorCode,
+        RpcReplyPort<Result<(), ChannelCommandError>>,
+    ),
+    Shutdown(
+        ShutdownCommand,
+        RpcReplyPort<Result<(), ChannelCommandError>>,
+    ),
+    Update(UpdateCommand, RpcReplyPort<Result<(), ChannelCommandError>>),
+    GetBalanceHistory(RpcReplyPort<Vec<BalanceSnapshot>>),
+    SettleHeldTlc(Hash256, RpcReplyPort<Result<(), ChannelCommandError>>),
+    ApproveTlc(Hash256, RpcReplyPort<Result<(), ChannelCommandError>>),
+    GetPaymentProof(Hash256, RpcReplyPort<Option<Hash256>>),
+    GetFundingAddress(NetworkType, RpcReplyPort<String>),
+    GetTlcCapacityStatus(RpcReplyPort<TlcCapacityStatus>),
+    GetMaxTlcAmounts(RpcReplyPort<MaxTlcAmounts>),
+    GetCapacityBreakdown(RpcReplyPort<CapacityBreakdown>),
+    GetMusig2Info(RpcReplyPort<Result<Musig2Info, ChannelCommandError>>),
+    GetCommitmentPointInfo(RpcReplyPort<CommitmentPointInfo>),
+    GetChannelSummary(RpcReplyPort<ChannelSummary>),
+    GetForwardingPolicy(RpcReplyPort<Result<ForwardingPolicy, ChannelCommandError>>),
+    ExportBackup(RpcReplyPort<Result<ChannelBackup, ChannelCommandError>>),
+    DrainAndDisable(RpcReplyPort<DrainStatus>),
+    UpdateShutdownScript(Script, RpcReplyPort<Result<(), ChannelCommandError>>),
+    ResyncCommitment(RpcReplyPort<Result<(), ProcessingChannelError>>),
+    BumpForceCloseFee(FeeRate, RpcReplyPort<Result<(), ChannelCommandError>>),
+    GetLatestCommitmentTx(RpcReplyPort<Option<CommitmentTxInfo>>),
+    GetMetrics(RpcReplyPort<ChannelMetrics>),
+    GetRoutingStats(RpcReplyPort<RoutingStats>),
+    GetLifetimeFees(RpcReplyPort<u128>),
+    InspectRetryableRemoves(RpcReplyPort<Vec<RetryableRemoveTlc>>),
+    DumpTlcState(RpcReplyPort<TlcStateDump>),
+    ClearRetryableRemove(RetryableRemoveTlc),
+    IsReadyToForward(RpcReplyPort<bool>),
+    GetForwardingBlocker(RpcReplyPort<Option<ForwardBlocker>>),
+    UpdateFeeRate(FeeRate, RpcReplyPort<Result<(), ChannelCommandError>>),
+    GetSettlementDataAt(u64, bool, RpcReplyPort<Option<SettlementData>>),
+    GetRemoteSettlementData(RpcReplyPort<Result<SettlementData, ChannelCommandError>>),
+    SetForwardingPaused(bool),
     #[cfg(test)]
     ReloadState(),
 }
 
+impl ChannelCommand {
+    // Whether the command only reads the channel state, the state is not persisted after
+    // handling such commands as nothing changed. The match is exhaustive on purpose, so that
+    // every new command has to decide whether it changes the persisted state, a command which
+    // changes only the runtime fields (e.g. `SetForwardingPaused`) is also read only here.
+    pub fn is_read_only(&self) -> bool {
+        match self {
+            ChannelCommand::GetBalanceHistory(_)
+            | ChannelCommand::GetFundingAddress(_, _)
+            | ChannelCommand::GetTlcCapacityStatus(_)
+            | ChannelCommand::GetMaxTlcAmounts(_)
+            | ChannelCommand::GetCapacityBreakdown(_)
+            | ChannelCommand::GetMusig2Info(_)
+            | ChannelCommand::GetCommitmentPointInfo(_)
+            | ChannelCommand::GetChannelSummary(_)
+            | ChannelCommand::GetPaymentProof(_, _)
+            | ChannelCommand::GetForwardingPolicy(_)
+            | ChannelCommand::ExportBackup(_)
+            | ChannelCommand::GetLatestCommitmentTx(_)
+            | ChannelCommand::GetMetrics(_)
+            | ChannelCommand::GetRoutingStats(_)
+            | ChannelCommand::GetLifetimeFees(_)
+            | ChannelCommand::InspectRetryableRemoves(_)
+            | ChannelCommand::DumpTlcState(_)
+            | ChannelCommand::IsReadyToForward(_)
+            | ChannelCommand::GetForwardingBlocker(_)
+            | ChannelCommand::GetSettlementDataAt(_, _, _)
+            | ChannelCommand::GetRemoteSettlementData(_)
+            | ChannelCommand::SetForwardingPaused(_) => true,
+            ChannelCommand::TxCollaborationCommand(_)
+            | ChannelCommand::CommitmentSigned()
+            | ChannelCommand::AddTlc(_, _)
+            | ChannelCommand::AddTlcBatch(_, _)
+            | ChannelCommand::RemoveTlc(_, _)
+            | ChannelCommand::FailTlc(_, _, _)
+            | ChannelCommand::Shutdown(_, _)
+            | ChannelCommand::Update(_, _)
+            | ChannelCommand::SettleHeldTlc(_, _)
+            | ChannelCommand::ApproveTlc(_, _)
+            | ChannelCommand::DrainAndDisable(_)
+            | ChannelCommand::UpdateShutdownScript(_, _)
+            | ChannelCommand::ResyncCommitment(_)
+            | ChannelCommand::BumpForceCloseFee(_, _)
+            | ChannelCommand::ClearRetryableRemove(_)
+            | ChannelCommand::UpdateFeeRate(_, _) => false,
+            #[cfg(test)]
+            ChannelCommand::ReloadState() => false,
+        }
+    }
+}
+
+/// The latest commitment transaction which would be broadcasted to force close the channel.
+#[derive(Clone, Debug)]
+pub struct CommitmentTxInfo {
+    /// The commitment number in the commitment lock args of the transaction.
+    pub commitment_number: u64,
+    pub transaction: TransactionView,
+    /// The settlement data received in the last RevokeAndAck message.
+    pub settlement_data: Option<SettlementData>,
+}
+
+/// The tlc forwarding outcomes of the channel since the channel actor is started.
+///
+/// Successful forwards are counted on the outgoing channel when the downstream fulfills
+/// the tlc, while failures to forward are counted on the incoming channel.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChannelMetrics {
+    /// The number of forwarded tlcs fulfilled by the downstream.
+    pub forwarded_count: u64,
+    /// The number of tlcs failed to be forwarded, grouped by the error code.
+    pub failed_count: HashMap<TlcErrorCode, u64>,
+    /// The number of forwarded tlcs failed by the downstream. The error is encrypted for
+    /// the payment sender, so the error code is unknown to us.
+    pub downstream_failed_count: u64,
+    /// The sum of `received_amount - forward_amount` of the fulfilled forwards.
+    pub fees_earned: u128,
+    /// The timestamp in milliseconds of the last forwarded tlc fulfilled by the downstream.
+    pub last_forwarded_at: Option<u64>,
+}
+
+/// The inputs of the routing score of the channel, used by the local pathfinder to estimate
+/// the success probability of the payments routed through the channel.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoutingStats {
+    /// The number of forwarded tlcs fulfilled by the downstream.
+    pub success_count: u64,
+    /// The number of tlcs failed to be forwarded, including the ones failed by the downstream.
+    pub failure_count: u64,
+    /// The liquid capacity of the channel, excluding the reserved ckb amount.
+    pub liquid_capacity: u128,
+    /// Our forwarding fee rate, only set for public channels.
+    pub tlc_fee_proportional_millionths: Option<u128>,
+    /// Our tlc expiry delta in milliseconds, only set for public channels.
+    pub tlc_expiry_delta: Option<u64>,
+    /// The milliseconds since the last successful forward, None if nothing is forwarded yet.
+    pub millis_since_last_forward: Option<u64>,
+}
+
+/// The reason why the channel can't forward tlcs now, see `ChannelActorState::forwarding_blocker`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ForwardBlocker {
+    /// The channel is not in the `ChannelReady` state.
+    NotChannelReady,
+    /// The channel is not announced, so it's not used to forward tlcs.
+    NotPublic,
+    /// The channel is disabled by us.
+    Disabled,
+    /// The channel is reestablishing with the peer after reconnection.
+    Reestablishing,
+    /// The channel is waiting for the ack of the peer to update tlcs.
+    WaitingAck,
+    /// No tlc can be offered now because of our balance, the reserve or the in-flight limits.
+    InsufficientLiquidity,
+}
+
 #[derive(Debug)]
 pub enum TxCollaborationCommand {
     TxUpdate(TxUpdateCommand),
     TxComplete(),
+    /// Abort the funding collaboration with the given reason.
+    TxAbort(String),
 }
 
 #[derive(Debug, Clone)]
@@ -161,6 +357,14 @@ pub struct AddTlcCommand {
     /// Use all zeros when no shared secrets are available.
     pub shared_secret: [u8; 32],
     pub previous_tlc: Option<(Hash256, u64)>,
+    /// The preimage already known by the sender, e.g. for spontaneous payments.
+    ///
+    /// It's only stored locally and never sent to the peer in the AddTlc message.
+    pub payment_preimage: Option<Hash256>,
+    /// The key chosen by the client to retry the command safely.
+    ///
+    /// A retry with the same key returns the response of the tlc added by the first attempt.
+    pub idempotency_key: Option<String>,
 }
 
 #[derive(Debug)]
@@ -174,6 +378,16 @@ pub struct ShutdownCommand {
     pub close_script: Script,
     pub fee_rate: FeeRate,
     pub force: bool,
+    // Skip the validation of the close script against the allowed lock script code hashes.
+    pub allow_unknown_close_script: bool,
+    // The number of blocks within which the shutdown transaction is expected to be confirmed,
+    // the fee rate is estimated from it instead of using `fee_rate` if it's set.
+    // It only applies to the cooperative close, the fee of the force close transaction is
+    // fixed when it's signed, and can be bumped by `BumpForceCloseFee`.
+    pub target_blocks: Option<u64>,
+    // The minimal CKB amount we must get back after paying the fee and the occupied capacity,
+    // the cooperative close is rejected if it's not worthwhile. It doesn't apply to force close.
+    pub min_net_close_balance: Option<u64>,
 }
 
 #[derive(Debug)]
@@ -182,6 +396,8 @@ pub struct UpdateCommand {
     pub tlc_expiry_delta: Option<u64>,
     pub tlc_minimum_value: Option<u128>,
     pub tlc_fee_proportional_millionths: Option<u128>,
+    pub min_tlc_expiry_delta: Option<u64>,
+    pub max_tlc_expiry_delta: Option<u64>,
 }
 
 #[derive(Debug)]
@@ -201,8 +417,23 @@ pub const MAX_COMMITMENT_DELAY_EPOCHS: u64 = 84;
 pub const DEFAULT_MAX_TLC_VALUE_IN_FLIGHT: u128 = u128::MAX;
 pub const DEFAULT_MAX_TLC_NUMBER_IN_FLIGHT: u64 = 30;
 pub const DEFAULT_MIN_TLC_VALUE: u128 = 0;
+pub const DEFAULT_CHANNEL_RESERVE_AMOUNT: u128 = 0;
 pub const SYS_MAX_TLC_NUMBER_IN_FLIGHT: u64 = 253;
 pub const MAX_TLC_NUMBER_IN_FLIGHT: u64 = 125;
+// The maximum number of balance snapshots kept in the channel state,
+// the oldest snapshot will be dropped when the limit is reached.
+pub const MAX_BALANCE_HISTORY_LENGTH: usize = 256;
+// The maximum number of settlement data of past commitments kept in the channel state,
+// the settlement data of the oldest commitment will be dropped when the limit is reached.
+pub const MAX_SETTLEMENT_DATA_HISTORY_LENGTH: usize = 256;
+// The channel is force closed after this number of consecutive failures of signing or verifying
+// the commitments exchanged with the peer, instead of retrying with a broken peer forever.
+pub const MAX_CONSECUTIVE_SIGNING_FAILURES: u64 = 5;
+// The maximal number of idempotency keys of the AddTlc commands remembered by each channel.
+pub const MAX_ADD_TLC_IDEMPOTENCY_KEYS: usize = 128;
+// The operators are notified once reestablishing a channel timed out this number of consecutive
+// attempts, which indicates an unstable peer.
+pub const REESTABLISH_TIMEOUT_NOTIFY_ATTEMPTS: u64 = 3;
 
 #[derive(Debug)]
 pub struct TxUpdateCommand {
@@ -211,6 +442,8 @@ pub struct TxUpdateCommand {
 
 pub struct OpenChannelParameter {
     pub funding_amount: u128,
+    // The amount pushed to the remote party from our funding amount at channel opening.
+    pub push_amount: u128,
     pub seed: [u8; 32],
     pub public_channel_info: Option<PublicChannelInfo>,
     pub funding_udt_type_script: Option<Script>,
@@ -221,6 +454,14 @@ pub struct OpenChannelParameter {
     pub funding_fee_rate: Option<u64>,
     pub max_tlc_value_in_flight: u128,
     pub max_tlc_number_in_flight: u64,
+    // The maximum value in flight as the percentage of the balance, `None` if not limited.
+    pub max_tlc_value_in_flight_percentage: Option<u8>,
+    pub channel_reserve_amount: u128,
+    pub anchor_outputs: bool,
+    pub sorted_settlement_outputs: bool,
+    pub tlc_dust_limit: u128,
+    // The hash algorithms we allow the tlcs of the channel to use.
+    pub allowed_hash_algorithms: Vec<HashAlgorithm>,
 }
 
 pub struct AcceptChannelParameter {
@@ -233,6 +474,22 @@ pub struct AcceptChannelParameter {
     pub channel_id_sender: Option<oneshot::Sender<Hash256>>,
     pub max_tlc_value_in_flight: u128,
     pub max_tlc_number_in_flight: u64,
+    // The maximum value in flight as the percentage of the balance, `None` if not limited.
+    pub max_tlc_value_in_flight_percentage: Option<u8>,
+    // The commitment delay epoch we want to use instead of the one proposed by the opener.
+    pub commitment_delay_epoch: Option<EpochNumberWithFraction>,
+    // The minimal liquid capacity (to_local_amount + to_remote_amount) of the channel to accept.
+    pub min_accept_funding_amount: u128,
+    pub channel_reserve_amount: u128,
+    // The hash algorithms we allow the tlcs of the channel to use.
+    pub allowed_hash_algorithms: Vec<HashAlgorithm>,
+    // The udt type scripts allowed to fund the channel, `None` if all udts are allowed,
+    // and empty if only native ckb channels are allowed.
+    pub allowed_udt_scripts: Option<Arc<Vec<Script>>>,
+    // Whether the public or private channels are accepted.
+    pub accept_channel_visibility: ChannelVisibilityPolicy,
+    // The minimal reserved ckb amount the opener must post, 0 if not required.
+    pub accept_min_remote_reserve: u64,
 }
 
 pub enum ChannelInitializationParameter {
@@ -253,6 +510,8 @@ pub enum ChannelInitializationParameter {
 pub struct ChannelSubscribers {
     pub pending_received_tlcs_subscribers: Arc<OutputPort<TlcNotification>>,
     pub settled_tlcs_subscribers: Arc<OutputPort<TlcNotification>>,
+    // Notified with (channel_id, old_state, new_state) whenever a channel changes its state.
+    pub state_transition_subscribers: Arc<OutputPort<(Hash256, ChannelState, ChannelState)>>,
 }
 
 impl Default for ChannelSubscribers {
@@ -260,16 +519,252 @@ impl Default for ChannelSubscribers {
         Self {
             pending_received_tlcs_subscribers: Arc::new(OutputPort::default()),
             settled_tlcs_subscribers: Arc::new(OutputPort::default()),
+            state_transition_subscribers: Arc::new(OutputPort::default()),
+        }
+    }
+}
+
+/// The payment hashes of the forwarded tlcs which were already settled, shared by all
+/// the channel actors of the node. The oldest hash is evicted when the capacity is reached.
+#[derive(Debug, Default)]
+pub struct SeenPaymentHashes {
+    capacity: usize,
+    hashes: HashSet<Hash256>,
+    order: VecDeque<Hash256>,
+}
+
+impl SeenPaymentHashes {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            hashes: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn contains(&self, payment_hash: &Hash256) -> bool {
+        self.hashes.contains(payment_hash)
+    }
+
+    pub fn insert(&mut self, payment_hash: Hash256) {
+        if self.capacity == 0 || !self.hashes.insert(payment_hash) {
+            return;
+        }
+        self.order.push_back(payment_hash);
+        if self.order.len() > self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.hashes.remove(&evicted);
+            }
+        }
+    }
+}
+
+/// The responses of the AddTlc commands by their idempotency keys, so that the retried commands
+/// get the tlcs added by the first attempts. The oldest key is evicted when the capacity is reached.
+#[derive(Debug, Default)]
+pub struct AddTlcIdempotencyKeys {
+    capacity: usize,
+    responses: HashMap<String, (Hash256, AddTlcResponse)>,
+    order: VecDeque<String>,
+}
+
+impl AddTlcIdempotencyKeys {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            responses: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    // Returns the payment hash and the response of the tlc added with the key.
+    pub fn get(&self, key: &str) -> Option<&(Hash256, AddTlcResponse)> {
+        self.responses.get(key)
+    }
+
+    pub fn insert(&mut self, key: String, payment_hash: Hash256, response: AddTlcResponse) {
+        if self.capacity == 0 || self.responses.contains_key(&key) {
+            return;
+        }
+        self.responses.insert(key.clone(), (payment_hash, response));
+        self.order.push_back(key);
+        if self.order.len() > self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.responses.remove(&evicted);
+            }
+        }
+    }
+}
+
+/// Token buckets limiting the rate of the AddTlc messages from each peer, shared by all the
+/// channel actors of the node, so that a peer can't bypass the limit by opening more channels.
+#[derive(Debug)]
+pub struct AddTlcRateLimiter {
+    // The tokens refilled per second, i.e. the sustained rate of tlcs.
+    rate: u64,
+    // The maximal tokens in a bucket, i.e. the burst of tlcs allowed.
+    burst: u64,
+    // The remaining tokens and the last refilled time in milliseconds of each peer.
+    buckets: HashMap<Pubkey, (f64, u64)>,
+}
+
+impl AddTlcRateLimiter {
+    pub fn new(rate: u64, burst: u64) -> Self {
+        Self {
+            rate,
+            burst: burst.max(1),
+            buckets: HashMap::new(),
+        }
+    }
+
+    // Take a token from the bucket of the peer, returns false if the bucket is empty.
+    pub fn try_acquire(&mut self, peer: &Pubkey, now_millis: u64) -> bool {
+        let burst = self.burst as f64;
+        let (tokens, refilled_at) = self.buckets.entry(*peer).or_insert((burst, now_millis));
+        let elapsed_millis = now_millis.saturating_sub(*refilled_at);
+        *tokens = (*tokens + elapsed_millis as f64 * self.rate as f64 / 1000.0).min(burst);
+        *refilled_at = now_millis.max(*refilled_at);
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// The exponential backoff of reestablishing a channel whose previous attempts are not completed,
+/// so that a flapping peer doesn't cause a storm of reestablish messages.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReestablishBackoff {
+    // The delay before the second attempt, doubled on each consecutive attempt.
+    pub base: Duration,
+    // The maximal delay before an attempt.
+    pub cap: Duration,
+    // An attempt is retried if not completed within the timeout, `None` to never time out.
+    pub timeout: Option<Duration>,
+}
+
+impl ReestablishBackoff {
+    // The delay before the attempt following the given number of uncompleted attempts.
+    pub fn delay(&self, attempts: u64) -> Duration {
+        if attempts == 0 {
+            return Duration::ZERO;
         }
+        let exponent = (attempts - 1).min(u32::BITS as u64 - 1) as u32;
+        self.base.saturating_mul(1 << exponent).min(self.cap)
     }
 }
 
+/// The timeout of the cooperative close started by us, so that a non-cooperative peer can't
+/// keep the channel in the shutting down state forever.
+#[derive(Clone, Copy, Debug)]
+pub struct ShutdownTimeout {
+    // The duration after sending Shutdown for the peer to complete the cooperative close.
+    pub timeout: Duration,
+    // Whether to force close the channel on the timeout, otherwise only an alert is emitted.
+    pub force_close: bool,
+}
+
+/// The bounds of the forwarding fee policy updated by `UpdateCommand`, so that a buggy fee
+/// manager can't make the channels unroutable or unprofitable.
+#[derive(Clone, Copy, Debug)]
+pub struct FeePolicyBounds {
+    // The allowed range of `tlc_fee_proportional_millionths`.
+    pub min_fee_proportional_millionths: u128,
+    pub max_fee_proportional_millionths: u128,
+    // The maximal allowed `tlc_minimum_value`, `None` if not bounded.
+    pub max_tlc_minimum_value: Option<u128>,
+}
+
+/// The decision of a `ForwardPolicy` on a received tlc which is to be forwarded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ForwardDecision {
+    Forward,
+    // Fail the received tlc back with the error code instead of forwarding it.
+    Deny(TlcErrorCode),
+}
+
+/// A hook to veto forwarding the received tlcs, e.g. implemented by a plugin to enforce custom
+/// routing policies. It's consulted after all the built-in checks of the channel are passed.
+pub trait ForwardPolicy: Send + Sync {
+    fn should_forward(
+        &self,
+        incoming: &AddTlcInfo,
+        next_hop: &PeeledPaymentOnionPacket,
+    ) -> ForwardDecision;
+}
+
+/// The configurations of the channel actors, which are shared by all the channels of the node.
+#[derive(Clone)]
+pub struct ChannelActorConfig {
+    pub accept_keysend: bool,
+    // `None` if re-forwarding settled payment hashes is allowed.
+    pub seen_payment_hashes: Option<Arc<Mutex<SeenPaymentHashes>>>,
+    // The multi-part payments received from all the channels of the node.
+    pub received_mpp_payments: Arc<Mutex<ReceivedMppPayments>>,
+    // The channel funding is aborted if not completed within this duration, `None` to wait forever.
+    pub funding_timeout: Option<Duration>,
+    // The actor is kept alive within this duration after the peer disconnected, waiting for the
+    // peer to reconnect, `None` to stop immediately.
+    pub peer_disconnect_grace: Option<Duration>,
+    // The code hashes of the lock scripts allowed to close the channel to, `None` if any lock script is allowed.
+    pub allowed_close_script_code_hashes: Option<Arc<HashSet<Hash256>>>,
+    // The key to encrypt the exported channel backups.
+    pub channel_backup_key: [u8; 32],
+    // The maximal attempts to retry removing a tlc before abandoning it, `None` if retry forever.
+    pub max_retryable_remove_attempts: Option<u64>,
+    // `None` if the AddTlc messages from the peer are not rate limited.
+    pub add_tlc_rate_limiter: Option<Arc<Mutex<AddTlcRateLimiter>>>,
+    // The maximal number of commitment points from the peer retained in the channel state,
+    // `None` if bounded by the max tlc number in flight of the channel.
+    pub max_remote_commitment_points: Option<usize>,
+    // How the received tlcs without invoices are handled when we are the final hop.
+    pub no_invoice_policy: NoInvoicePolicy,
+    pub reestablish_backoff: ReestablishBackoff,
+    // `None` if the cooperative close started by us never times out.
+    pub shutdown_timeout: Option<ShutdownTimeout>,
+    // `None` if the ResyncCommitment commands wait for the RevokeAndAck message forever.
+    pub commitment_resync_timeout: Option<Duration>,
+    pub fee_policy_bounds: FeePolicyBounds,
+    // `None` if all the received tlcs passing the built-in checks are forwarded.
+    pub forward_policy: Option<Arc<dyn ForwardPolicy>>,
+    // `None` if the funding keys are kept in memory and persisted with the channel states.
+    pub commitment_signer_provider: Option<Arc<dyn CommitmentSignerProvider>>,
+    // The estimator of the shutdown fee rates, shared by all the channels.
+    pub fee_estimator: Arc<dyn FeeEstimator>,
+}
+
+type CommitmentResyncReply = RpcReplyPort<Result<(), ProcessingChannelError>>;
+
 pub struct ChannelActor<S> {
     local_pubkey: Pubkey,
     remote_pubkey: Pubkey,
     network: ActorRef<NetworkActorMessage>,
     store: S,
     subscribers: ChannelSubscribers,
+    config: ChannelActorConfig,
+    metrics: Mutex<ChannelMetrics>,
+    // The received tlcs are failed instead of forwarded while paused, it's not persisted and
+    // resets to false when the actor restarts.
+    forwarding_paused: AtomicBool,
+    // The number of consecutive CommitmentSigned and RevokeAndAck messages from the peer which
+    // failed the signing or verification, reset once such a message is handled successfully.
+    consecutive_signing_failures: AtomicU64,
+    // The responses of the AddTlc commands with idempotency keys, it's not persisted since
+    // the retries are expected shortly after the first attempts.
+    add_tlc_idempotency_keys: Mutex<AddTlcIdempotencyKeys>,
+    // The remote commitment number resynced by the ResyncCommitment command and its reply port
+    // waiting for the RevokeAndAck message from the peer, it's not persisted since the reply port
+    // can't outlive the actor.
+    pending_commitment_resync: Mutex<Option<(u64, CommitmentResyncReply)>>,
+    // The number of consecutive attempts to reestablish the channel which are not completed,
+    // used to back off reestablishing with a flapping peer. It's not persisted, the actor is kept
+    // alive across the reconnections within the peer disconnect grace period.
+    reestablish_attempts: AtomicU64,
+    // The sequence number of the latest reestablish attempt, the delayed messages and the
+    // timeouts of the previous attempts are ignored.
+    reestablish_sequence: AtomicU64,
 }
 
 impl<S> ChannelActor<S>
@@ -282,6 +777,7 @@ where
         network: ActorRef<NetworkActorMessage>,
         store: S,
         subscribers: ChannelSubscribers,
+        config: ChannelActorConfig,
     ) -> Self {
         Self {
             local_pubkey,
@@ -289,6 +785,16 @@ where
             network,
             store,
             subscribers,
+            config,
+            metrics: Mutex::new(ChannelMetrics::default()),
+            forwarding_paused: AtomicBool::new(false),
+            consecutive_signing_failures: AtomicU64::new(0),
+            add_tlc_idempotency_keys: Mutex::new(AddTlcIdempotencyKeys::new(
+                MAX_ADD_TLC_IDEMPOTENCY_KEYS,
+            )),
+            pending_commitment_resync: Mutex::new(None),
+            reestablish_attempts: AtomicU64::new(0),
+            reestablish_sequence: AtomicU64::new(0),
         }
     }
 
@@ -296,6 +802,28 @@ where
         self.local_pubkey
     }
 
+    // Hand over the funding key of a new channel to the configured signer provider, so that
+    // it's never persisted, or get the signer back from the provider for a restored channel.
+    fn install_commitment_signer(&self, state: &mut ChannelActorState) -> ProcessingChannelResult {
+        let Some(provider) = self.config.commitment_signer_provider.as_ref() else {
+            return Ok(());
+        };
+        let signer = if state.signer.funding_key.is_some() {
+            let in_memory_signer = state.signer.clone();
+            state.signer.funding_key = None;
+            provider.import_signer(in_memory_signer)
+        } else {
+            let funding_pubkey = state.get_local_channel_public_keys().funding_pubkey;
+            provider.get_signer(&funding_pubkey).ok_or_else(|| {
+                ProcessingChannelError::InvalidState(format!(
+                    "The commitment signer of funding pubkey {:?} is not found",
+                    funding_pubkey
+                ))
+            })?
+        };
+        state.set_commitment_signer(signer)
+    }
+
     pub fn get_remote_pubkey(&self) -> Pubkey {
         self.remote_pubkey
     }
@@ -304,12 +832,41 @@ where
         self.remote_pubkey.tentacle_peer_id()
     }
 
+    /// Check that the close script is a lock script we know how to spend, i.e. its code hash
+    /// is in the allowed list configured for this node, to guard against mistyped close addresses.
+    fn validate_close_script(
+        &self,
+        close_script: &Script,
+        allow_unknown_close_script: bool,
+    ) -> ProcessingChannelResult {
+        let Some(allowed_code_hashes) = &self.config.allowed_close_script_code_hashes else {
+            return Ok(());
+        };
+        let code_hash: Hash256 = close_script.code_hash().into();
+        if allow_unknown_close_script || allowed_code_hashes.contains(&code_hash) {
+            return Ok(());
+        }
+        Err(ProcessingChannelError::InvalidParameter(format!(
+            "Close script with unknown code hash {:?} is not allowed, set allow_unknown_close_script to close to it anyway",
+            code_hash
+        )))
+    }
+
     pub async fn handle_peer_message(
         &self,
         myself: &ActorRef<ChannelActorMessage>,
         state: &mut ChannelActorState,
         message: FiberChannelMessage,
     ) -> Result<(), ProcessingChannelError> {
+        state.check_chain_hash()?;
+
+        if let FiberChannelMessage::ReestablishChannel(ref reestablish_channel) = message {
+            if reestablish_channel.force_close_request {
+                return self.handle_force_close_request(state);
+            }
+            self.reestablish_attempts.store(0, Ordering::SeqCst);
+        }
+
         if state.reestablishing {
             match message {
                 FiberChannelMessage::ReestablishChannel(ref reestablish_channel) => {
@@ -342,12 +899,15 @@ where
                     }
                 }
 
-                // TODO: check announcement_signatures validity here.
                 let AnnouncementSignatures {
                     node_signature,
                     partial_signature,
                     ..
                 } = announcement_signatures;
+                state.verify_remote_channel_announcement_signature(
+                    &node_signature,
+                    partial_signature,
+                )?;
                 state.update_remote_channel_announcement_signature(
                     node_signature,
                     partial_signature,
@@ -563,9 +1123,33 @@ where
                 state.handle_reestablish_channel_message(reestablish_channel, &self.network)?;
                 Ok(())
             }
-            FiberChannelMessage::TxAbort(_)
-            | FiberChannelMessage::TxInitRBF(_)
-            | FiberChannelMessage::TxAckRBF(_) => {
+            FiberChannelMessage::TxAbort(tx_abort) => {
+                state.check_funding_abortable()?;
+                let reason = String::from_utf8_lossy(&tx_abort.message).to_string();
+                warn!(
+                    "Funding of channel {:?} aborted by peer: {}",
+                    state.get_id(),
+                    &reason
+                );
+                self.abort_funding(myself, state, reason);
+                Ok(())
+            }
+            FiberChannelMessage::UpdateFee(update_fee) => {
+                let fee_rate_updated = if update_fee.ack {
+                    state.handle_update_fee_ack(update_fee.fee_rate)
+                } else {
+                    state.handle_update_fee_proposal(update_fee.fee_rate, &self.network)
+                };
+                // The signed commitment txs still pay the old fee rate, both parties re-sign the
+                // commitment tx of the peer once the new fee rate is applied, so that a force
+                // close after the update broadcasts a commitment tx paying the new fee rate.
+                if fee_rate_updated {
+                    self.handle_commitment_signed_command(state)?;
+                    state.set_waiting_ack(&self.network, true);
+                }
+                Ok(())
+            }
+            FiberChannelMessage::TxInitRBF(_) | FiberChannelMessage::TxAckRBF(_) => {
                 warn!("Received unsupported message: {:?}", &message);
                 Ok(())
             }
@@ -578,7 +1162,10 @@ where
         error: &ProcessingChannelError,
     ) -> TlcErr {
         let error_code = match error {
-            ProcessingChannelError::PeelingOnionPacketError(_) => TlcErrorCode::InvalidOnionPayload,
+            ProcessingChannelError::PeelingOnionPacketError(_)
+            | ProcessingChannelError::TlcHashAlgorithmNotAllowed(_) => {
+                TlcErrorCode::InvalidOnionPayload
+            }
             ProcessingChannelError::TlcForwardFeeIsTooLow => TlcErrorCode::FeeInsufficient,
             ProcessingChannelError::TlcExpirySoon => TlcErrorCode::ExpiryTooSoon,
             ProcessingChannelError::TlcExpiryTooFar => TlcErrorCode::ExpiryTooFar,
@@ -588,7 +1175,9 @@ where
                 _ => TlcErrorCode::IncorrectOrUnknownPaymentDetails,
             },
             ProcessingChannelError::FinalIncorrectPreimage
-            | ProcessingChannelError::FinalIncorrectPaymentHash => {
+            | ProcessingChannelError::FinalIncorrectPaymentHash
+            | ProcessingChannelError::FinalProbeTlc
+            | ProcessingChannelError::ForwardingSettledPaymentHash => {
                 TlcErrorCode::IncorrectOrUnknownPaymentDetails
             }
             ProcessingChannelError::FinalIncorrectHTLCAmount => {
@@ -602,14 +1191,19 @@ where
             ProcessingChannelError::TlcNumberExceedLimit
             | ProcessingChannelError::TlcAmountExceedLimit
             | ProcessingChannelError::TlcValueInflightExceedLimit
-            | ProcessingChannelError::WaitingTlcAck => TlcErrorCode::TemporaryChannelFailure,
+            | ProcessingChannelError::WaitingTlcAck
+            | ProcessingChannelError::TlcRateLimited
+            | ProcessingChannelError::ForwardingPaused => TlcErrorCode::TemporaryChannelFailure,
+            ProcessingChannelError::ForwardingDenied(error_code) => *error_code,
             ProcessingChannelError::InvalidState(error) => match state.state {
                 // we can not revert back up `ChannelReady` after `ShuttingDown`
                 ChannelState::Closed(_) | ChannelState::ShuttingDown(_) => {
                     TlcErrorCode::PermanentChannelFailure
                 }
                 ChannelState::ChannelReady() => {
-                    if error.contains("channel is not public or disabled") {
+                    if !state.is_ready_to_forward()
+                        || error.contains("channel is not public or disabled")
+                    {
                         TlcErrorCode::TemporaryChannelFailure
                     } else {
                         // we expect `ChannelReady` will be both OK for tlc forwarding,
@@ -626,6 +1220,7 @@ where
             ProcessingChannelError::SpawnErr(_)
             | ProcessingChannelError::Musig2SigningError(_)
             | ProcessingChannelError::Musig2VerifyError(_)
+            | ProcessingChannelError::InvalidCommitmentSecret(_)
             | ProcessingChannelError::CapacityError(_) => TlcErrorCode::TemporaryNodeFailure,
             ProcessingChannelError::InvalidParameter(_) => {
                 TlcErrorCode::IncorrectOrUnknownPaymentDetails
@@ -635,7 +1230,14 @@ where
             }
         };
 
-        let channel_update = if error_code.is_update() {
+        let channel_update = if matches!(error, ProcessingChannelError::ForwardingPaused) {
+            // Pausing forwarding is a local decision which should not be gossiped, so only
+            // attach the existing channel update instead of generating a new one.
+            state
+                .public_channel_info
+                .as_ref()
+                .and_then(|info| info.channel_update.clone())
+        } else if error_code.is_update() {
             state.try_create_channel_update_message(&self.network).await
         } else {
             None
@@ -666,11 +1268,16 @@ where
         state: &mut ChannelActorState,
     ) {
         let pending_apply_tlcs = state.tlc_state.commit_remote_tlcs();
+        let mut peeled_onion_packets = self.peel_onion_packets(state, &pending_apply_tlcs).await;
         for tlc_info in pending_apply_tlcs {
             match tlc_info {
                 TlcKind::AddTlc(add_tlc) => {
                     assert!(add_tlc.is_received());
-                    if let Err(e) = self.apply_add_tlc_operation(myself, state, &add_tlc).await {
+                    let peeled_onion_packet = peeled_onion_packets.remove(&add_tlc.tlc_id);
+                    if let Err(e) = self
+                        .apply_add_tlc_operation(myself, state, &add_tlc, peeled_onion_packet)
+                        .await
+                    {
                         let tlc_err = match e.source {
                             // If we already have TlcErr, we can directly use it to send back to the peer.
                             ProcessingChannelError::TlcForwardingError(tlc_err) => tlc_err,
@@ -762,7 +1369,104 @@ where
             payment_preimage: preimage,
         });
         let tlc = tlc_info.clone();
-        if let Some(invoice) = self.store.get_invoice(&tlc.payment_hash) {
+        if state
+            .mpp_payments
+            .get(&tlc.payment_hash)
+            .is_some_and(|mpp_payment| mpp_payment.parts.contains_key(&tlc_id))
+        {
+            let now = state.clock.now_millis();
+            self.sync_received_mpp_payments(myself, state);
+            let status = self
+                .config
+                .received_mpp_payments
+                .lock()
+                .expect("received mpp payments lock poisoned")
+                .poll(&tlc.payment_hash, now);
+            match status {
+                MppPaymentStatus::Pending(expiry) => {
+                    // Wait for the other parts, and check again before the earliest part expires.
+                    let timeout = expiry - now - MIN_TLC_EXPIRY_DELTA;
+                    let delay = Duration::from_millis(timeout).min(HELD_TLC_CHECK_INTERVAL);
+                    myself.send_after(delay, move || {
+                        ChannelActorMessage::Event(ChannelEvent::CheckHeldTlc(tlc_id))
+                    });
+                    return;
+                }
+                MppPaymentStatus::Completed => {
+                    // Persist the completion, so that the parts are still settled after a restart.
+                    if let Some(mpp_payment) = state.mpp_payments.get_mut(&tlc.payment_hash) {
+                        mpp_payment.completed = true;
+                    }
+                }
+                MppPaymentStatus::TimedOut => {}
+            }
+            if status == MppPaymentStatus::TimedOut {
+                debug!(
+                    "Fail tlc {:?} because the multi-part payment is not completed in time",
+                    &tlc.tlc_id
+                );
+                remove_reason = RemoveTlcReason::RemoveTlcFail(TlcErrPacket::new(
+                    TlcErr::new(TlcErrorCode::MppTimeout),
+                    &tlc.shared_secret,
+                ));
+                self.register_retryable_tlc_remove(myself, state, tlc.tlc_id, remove_reason)
+                    .await;
+                return;
+            }
+        }
+        let invoice_status = self.store.get_invoice_status(&tlc.payment_hash);
+        if state.tlcs_pending_approval.contains(&tlc_id) {
+            // Keep the tlc without an invoice pending until it's approved by ApproveTlc,
+            // fail it if the tlc is going to expire before the approval.
+            let now = state.clock.now_millis();
+            if tlc.expiry >= now + MIN_TLC_EXPIRY_DELTA {
+                let delay = Duration::from_millis(tlc.expiry - MIN_TLC_EXPIRY_DELTA - now)
+                    .min(HELD_TLC_CHECK_INTERVAL);
+                myself.send_after(delay, move || {
+                    ChannelActorMessage::Event(ChannelEvent::CheckHeldTlc(tlc_id))
+                });
+                return;
+            }
+            debug!(
+                "Fail tlc {:?} pending approval because it is going to expire",
+                &tlc.tlc_id
+            );
+            state.tlcs_pending_approval.remove(&tlc_id);
+            remove_reason = RemoveTlcReason::RemoveTlcFail(TlcErrPacket::new(
+                TlcErr::new(TlcErrorCode::IncorrectOrUnknownPaymentDetails),
+                &tlc.shared_secret,
+            ));
+        } else if self.store.is_invoice_held(&tlc.payment_hash)
+            && invoice_status != Some(CkbInvoiceStatus::Cancelled)
+        {
+            // The invoice is held, keep the tlc pending until it is released by SettleHeldTlc,
+            // cancel it if the tlc is going to expire before the release.
+            let now = state.clock.now_millis();
+            if tlc.expiry > now + MIN_TLC_EXPIRY_DELTA {
+                if invoice_status == Some(CkbInvoiceStatus::Open) {
+                    self.store
+                        .update_invoice_status(&tlc.payment_hash, CkbInvoiceStatus::Received)
+                        .expect("update invoice status error");
+                }
+                // Check again when the tlc is going to expire, it's a no-op if released by then.
+                let delay = Duration::from_millis(tlc.expiry - MIN_TLC_EXPIRY_DELTA - now);
+                myself.send_after(delay, move || {
+                    ChannelActorMessage::Event(ChannelEvent::CheckHeldTlc(tlc_id))
+                });
+                return;
+            }
+            debug!(
+                "Cancel held tlc {:?} because it is going to expire",
+                &tlc.tlc_id
+            );
+            self.store
+                .update_invoice_status(&tlc.payment_hash, CkbInvoiceStatus::Cancelled)
+                .expect("update invoice status error");
+            remove_reason = RemoveTlcReason::RemoveTlcFail(TlcErrPacket::new(
+                TlcErr::new(TlcErrorCode::InvoiceCancelled),
+                &tlc.shared_secret,
+            ));
+        } else if let Some(invoice) = self.store.get_invoice(&tlc.payment_hash) {
             let status = self.get_invoice_status(&invoice);
             match status {
                 CkbInvoiceStatus::Expired => {
@@ -778,9 +1482,12 @@ where
                     ));
                 }
                 CkbInvoiceStatus::Paid => {
-                    // we have already checked invoice status in apply_add_tlc_operation_with_peeled_onion_packet
-                    // this maybe happened when process is killed and restart
-                    error!("invoice already paid, ignore");
+                    // the invoice is paid by the other parts of a completed multi-part payment
+                    if !state.mpp_payments.contains_key(&tlc.payment_hash) {
+                        // we have already checked invoice status in apply_add_tlc_operation_with_peeled_onion_packet
+                        // this maybe happened when process is killed and restart
+                        error!("invoice already paid, ignore");
+                    }
                 }
                 _ => {
                     self.store
@@ -799,12 +1506,13 @@ where
         myself: &ActorRef<ChannelActorMessage>,
         state: &mut ChannelActorState,
         add_tlc: &AddTlcInfo,
+        peeled_onion_packet: Option<Result<PeeledPaymentOnionPacket, ProcessingChannelError>>,
     ) -> Result<(), ProcessingChannelErrorWithSharedSecret> {
         // If needed, shared secret also get be extracted from the encrypted onion packet:
         // - Extract public key from onion_packet[1..34]
         // - Obtain share secret using DH Key Exchange from the public key and the network private key stored in the network actor state.
         if let Some(peeled_onion_packet) = self
-            .try_add_tlc_peel_onion_packet(state, add_tlc)
+            .try_add_tlc_peel_onion_packet(state, add_tlc, peeled_onion_packet)
             .await
             .map_err(ProcessingChannelError::without_shared_secret)?
         {
@@ -819,12 +1527,19 @@ where
         }
 
         if let Some(ref udt_type_script) = state.funding_udt_type_script {
+            // Notify with the tlc in the state, which carries the preimage and the custom records
+            // set by the final hop.
+            let tlc = state
+                .get_received_tlc(add_tlc.tlc_id.into())
+                .unwrap_or(add_tlc)
+                .without_onion_packet();
             self.subscribers
                 .pending_received_tlcs_subscribers
                 .send(TlcNotification {
-                    tlc: add_tlc.clone(),
+                    tlc: Arc::new(tlc),
                     channel_id: state.get_id(),
                     script: udt_type_script.clone(),
+                    udt_metadata: get_udt_metadata(udt_type_script),
                 });
         }
 
@@ -838,15 +1553,32 @@ where
         Ok(())
     }
 
+    // The onion packet may be peeled in advance by `peel_onion_packets`, in which case the
+    // peeled result is used after the checks instead of peeling it again.
     async fn try_add_tlc_peel_onion_packet(
         &self,
         state: &mut ChannelActorState,
         add_tlc: &AddTlcInfo,
+        peeled_onion_packet: Option<Result<PeeledPaymentOnionPacket, ProcessingChannelError>>,
     ) -> Result<Option<PeeledPaymentOnionPacket>, ProcessingChannelError> {
+        if state.rate_limited_tlcs.remove(&u64::from(add_tlc.tlc_id)) {
+            return Err(ProcessingChannelError::TlcRateLimited);
+        }
         state.check_tlc_expiry(add_tlc.expiry)?;
+        state.check_tlc_hash_algorithm(add_tlc.hash_algorithm)?;
 
         assert!(state.get_received_tlc(add_tlc.tlc_id.into()).is_some());
 
+        // Reject malformed packets early to avoid a round-trip to the network actor.
+        if let Some(onion_packet) = add_tlc.onion_packet.as_ref() {
+            onion_packet
+                .check_format()
+                .map_err(|err| ProcessingChannelError::PeelingOnionPacketError(err.to_string()))?;
+        }
+
+        if let Some(peeled_onion_packet) = peeled_onion_packet {
+            return peeled_onion_packet.map(Some);
+        }
         Ok(
             OptionFuture::from(add_tlc.onion_packet.clone().map(|onion_packet| {
                 self.peel_onion_packet(onion_packet, add_tlc.payment_hash.clone())
@@ -856,6 +1588,39 @@ where
         )
     }
 
+    // Peel the onion packets of the received tlcs to be applied concurrently, the concurrency
+    // is limited by the network actor. The tlcs are still applied in order with the results.
+    // The rate limited tlcs and the malformed packets are skipped, they fail without peeling.
+    async fn peel_onion_packets(
+        &self,
+        state: &ChannelActorState,
+        tlcs: &[TlcKind],
+    ) -> HashMap<TLCId, Result<PeeledPaymentOnionPacket, ProcessingChannelError>> {
+        let onion_packets: Vec<_> = tlcs
+            .iter()
+            .filter_map(|tlc| match tlc {
+                TlcKind::AddTlc(add_tlc)
+                    if !state.rate_limited_tlcs.contains(&u64::from(add_tlc.tlc_id)) =>
+                {
+                    let onion_packet = add_tlc.onion_packet.clone()?;
+                    onion_packet.check_format().ok()?;
+                    Some((add_tlc.tlc_id, add_tlc.payment_hash, onion_packet))
+                }
+                _ => None,
+            })
+            .collect();
+        join_all(
+            onion_packets
+                .into_iter()
+                .map(|(tlc_id, payment_hash, onion_packet)| async move {
+                    (tlc_id, self.peel_onion_packet(onion_packet, payment_hash).await)
+                }),
+        )
+        .await
+        .into_iter()
+        .collect()
+    }
+
     async fn apply_add_tlc_operation_with_peeled_onion_packet(
         &self,
         state: &mut ChannelActorState,
@@ -867,6 +1632,23 @@ where
         let forward_amount = peeled_onion_packet.current.amount;
 
         if peeled_onion_packet.is_last() {
+            // The probes are failed before any other checks, they never touch the invoices.
+            if peeled_onion_packet.current.is_probe() {
+                debug!(
+                    "Failing probe tlc {:?} with payment hash {:?}",
+                    add_tlc.tlc_id, payment_hash
+                );
+                self.network
+                    .send_message(NetworkActorMessage::new_notification(
+                        NetworkServiceEvent::ProbeTlcReceived(
+                            state.get_id(),
+                            payment_hash,
+                            received_amount,
+                        ),
+                    ))
+                    .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+                return Err(ProcessingChannelError::FinalProbeTlc);
+            }
             if forward_amount != add_tlc.amount {
                 return Err(ProcessingChannelError::FinalIncorrectHTLCAmount);
             }
@@ -874,38 +1656,125 @@ where
             if add_tlc.expiry < peeled_onion_packet.current.expiry {
                 return Err(ProcessingChannelError::IncorrectFinalTlcExpiry);
             }
-            if add_tlc.expiry < now_timestamp_as_millis_u64() + MIN_TLC_EXPIRY_DELTA {
+            if add_tlc.expiry < state.clock.now_millis() + state.get_min_tlc_expiry_delta() {
                 return Err(ProcessingChannelError::TlcExpirySoon);
             }
 
-            if let Some(invoice) = self.store.get_invoice(&payment_hash) {
-                let invoice_status = self.get_invoice_status(&invoice);
-                if invoice_status != CkbInvoiceStatus::Open {
+            // The received tlcs sharing the payment hash must be the parts of a multi-part payment.
+            let total_amount = peeled_onion_packet.current.total_amount;
+            if total_amount.is_some() {
+                state.check_feature_negotiated(FeatureBits::MULTI_PART_PAYMENT)?;
+            }
+            if total_amount.is_none() && state.has_other_received_tlcs(add_tlc) {
+                return Err(ProcessingChannelError::RepeatedProcessing(format!(
+                    "Received tlc {:?} with duplicate payment hash {:?} without the MPP marker",
+                    add_tlc.tlc_id, payment_hash
+                )));
+            }
+            // The invoice is already marked as received by the previous parts of the payment,
+            // which may be received from the other channels.
+            let is_later_part = total_amount.is_some()
+                && self
+                    .config
+                    .received_mpp_payments
+                    .lock()
+                    .expect("received mpp payments lock poisoned")
+                    .is_pending(&payment_hash);
+
+            let invoice = self.store.get_invoice(&payment_hash);
+            if let Some(ref invoice) = invoice {
+                let invoice_status = self.get_invoice_status(invoice);
+                if invoice_status != CkbInvoiceStatus::Open
+                    && !(is_later_part && invoice_status == CkbInvoiceStatus::Received)
+                {
                     return Err(ProcessingChannelError::FinalInvoiceInvalid(invoice_status));
                 }
+                if let (Some(total_amount), Some(invoice_amount)) = (total_amount, invoice.amount())
+                {
+                    if total_amount != invoice_amount {
+                        return Err(ProcessingChannelError::FinalIncorrectHTLCAmount);
+                    }
+                }
                 self.store
                     .update_invoice_status(&payment_hash, CkbInvoiceStatus::Received)
                     .expect("update invoice status failed");
             }
 
+            // A keysend payment carries the preimage in the onion packet without an invoice.
+            let keysend_preimage = peeled_onion_packet.current.payment_preimage;
+            if keysend_preimage.is_some() {
+                state.check_feature_negotiated(FeatureBits::KEYSEND)?;
+            }
+            if keysend_preimage.is_some() && invoice.is_none() && !self.config.accept_keysend {
+                return Err(ProcessingChannelError::FinalIncorrectPaymentHash);
+            }
+            if invoice.is_none() && self.config.no_invoice_policy == NoInvoicePolicy::Reject {
+                return Err(ProcessingChannelError::FinalIncorrectPaymentHash);
+            }
+
             // if this is the last hop, store the preimage.
             // though we will RemoveTlcFulfill the TLC in try_to_settle_down_tlc function,
             // here we can do error check early here for better error handling.
-            let preimage = peeled_onion_packet
-                .current
-                .payment_preimage
-                .or_else(|| self.store.get_invoice_preimage(&add_tlc.payment_hash));
+            let preimage =
+                keysend_preimage.or_else(|| self.store.get_invoice_preimage(&add_tlc.payment_hash));
 
             if let Some(preimage) = preimage {
                 let filled_payment_hash: Hash256 = add_tlc.hash_algorithm.hash(preimage).into();
                 if add_tlc.payment_hash != filled_payment_hash {
                     return Err(ProcessingChannelError::FinalIncorrectPreimage);
                 }
+                if let Some(total_amount) = total_amount {
+                    if !self
+                        .config
+                        .received_mpp_payments
+                        .lock()
+                        .expect("received mpp payments lock poisoned")
+                        .can_add_part(&payment_hash, total_amount)
+                    {
+                        return Err(ProcessingChannelError::FinalIncorrectHTLCAmount);
+                    }
+                    state.add_mpp_part(add_tlc, total_amount)?;
+                }
                 state.set_received_tlc_preimage(add_tlc.tlc_id.into(), Some(preimage));
+                state.set_received_tlc_custom_records(
+                    add_tlc.tlc_id.into(),
+                    peeled_onion_packet.current.custom_records.clone(),
+                );
+                if invoice.is_none()
+                    && self.config.no_invoice_policy == NoInvoicePolicy::HoldForApproval
+                {
+                    state.tlcs_pending_approval.insert(add_tlc.tlc_id.into());
+                    self.network
+                        .send_message(NetworkActorMessage::new_notification(
+                            NetworkServiceEvent::TlcPendingApproval(
+                                state.get_id(),
+                                payment_hash,
+                                add_tlc.tlc_id.into(),
+                            ),
+                        ))
+                        .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+                }
             } else {
                 return Err(ProcessingChannelError::FinalIncorrectPaymentHash);
             }
         } else {
+            if state.draining {
+                return Err(ProcessingChannelError::InvalidState(
+                    "Channel is draining, forwarding tlc is not allowed".to_string(),
+                ));
+            }
+            if self.forwarding_paused.load(Ordering::SeqCst) {
+                return Err(ProcessingChannelError::ForwardingPaused);
+            }
+            if self.is_payment_hash_settled_by_forwarding(&payment_hash) {
+                return Err(ProcessingChannelError::ForwardingSettledPaymentHash);
+            }
+            if state.has_other_received_tlcs(add_tlc) {
+                return Err(ProcessingChannelError::RepeatedProcessing(format!(
+                    "Forwarding tlc {:?} with duplicate payment hash {:?}",
+                    add_tlc.tlc_id, payment_hash
+                )));
+            }
             match state.public_channel_info.as_ref() {
                 Some(public_channel_info) if public_channel_info.enabled => {
                     let min_tlc_value = public_channel_info.tlc_min_value;
@@ -931,6 +1800,18 @@ where
                         );
                         return Err(ProcessingChannelError::TlcForwardFeeIsTooLow);
                     }
+                    if let Some(ForwardDecision::Deny(error_code)) = self
+                        .config
+                        .forward_policy
+                        .as_ref()
+                        .map(|policy| policy.should_forward(add_tlc, &peeled_onion_packet))
+                    {
+                        debug!(
+                            "Forwarding tlc {:?} is denied by the forward policy: {:?}",
+                            add_tlc.tlc_id, error_code
+                        );
+                        return Err(ProcessingChannelError::ForwardingDenied(error_code));
+                    }
                     // if this is not the last hop, forward TLC to next hop
                     self.handle_forward_onion_packet(
                         state,
@@ -964,10 +1845,30 @@ where
         state.check_for_tlc_update(Some(add_tlc.amount), false, false)?;
         let tlc_info = state.create_inbounding_tlc(add_tlc.clone())?;
         state.check_insert_tlc(&tlc_info)?;
+
+        // The token is only taken once the tlc passes the checks, so that the rejected tlcs
+        // don't count against the rate limit. The tlc exceeding the rate limit is still added
+        // to keep the state in sync with the peer, but it's failed once committed, without
+        // peeling the onion packet.
+        let rate_limited = self.config.add_tlc_rate_limiter.as_ref().is_some_and(|limiter| {
+            !limiter
+                .lock()
+                .expect("add tlc rate limiter lock poisoned")
+                .try_acquire(&self.get_remote_pubkey(), state.clock.now_millis())
+        });
         state
             .tlc_state
             .add_remote_tlc(TlcKind::AddTlc(tlc_info.clone()));
         state.increment_next_received_tlc_id();
+        if rate_limited {
+            warn!(
+                "AddTlc {} of channel {:?} exceeds the rate limit of peer {:?}",
+                add_tlc.tlc_id,
+                state.get_id(),
+                self.get_remote_peer_id()
+            );
+            state.rate_limited_tlcs.insert(add_tlc.tlc_id);
+        }
         Ok(())
     }
 
@@ -998,22 +1899,32 @@ where
     ) -> Result<(), ProcessingChannelError> {
         let channel_id = state.get_id();
         let remove_reason = remove_tlc.reason.clone();
-        let tlc_info = state
-            .remove_tlc_with_reason(remove_tlc.tlc_id, &remove_reason)
-            .expect("expect remove tlc successfully");
+        let tlc_info = match state.remove_tlc_with_reason(remove_tlc.tlc_id, &remove_reason) {
+            Ok(tlc_info) => tlc_info,
+            // The balances are already updated by the same removal, nothing to do.
+            Err(ProcessingChannelError::RepeatedProcessing(_)) => {
+                debug!(
+                    "Skipping applying the repeated removal of tlc {:?}",
+                    remove_tlc.tlc_id
+                );
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
         if let (
             Some(ref udt_type_script),
             RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill { payment_preimage }),
         ) = (state.funding_udt_type_script.clone(), &remove_reason)
         {
-            let mut tlc = tlc_info.clone();
+            let mut tlc = tlc_info.without_onion_packet();
             tlc.payment_preimage = Some(*payment_preimage);
             self.subscribers
                 .settled_tlcs_subscribers
                 .send(TlcNotification {
-                    tlc,
+                    tlc: Arc::new(tlc),
                     channel_id,
                     script: udt_type_script.clone(),
+                    udt_metadata: get_udt_metadata(udt_type_script),
                 });
         }
         if tlc_info.previous_tlc.is_none() {
@@ -1028,6 +1939,10 @@ where
                 ))
                 .expect("myself alive");
         } else {
+            if matches!(remove_reason, RemoveTlcReason::RemoveTlcFulfill(_)) {
+                self.remember_payment_hash_settled_by_forwarding(tlc_info.payment_hash);
+            }
+            self.record_forward_result(state, &tlc_info, &remove_reason);
             // relay RemoveTlc to previous channel if needed
             self.try_to_relay_remove_tlc(myself, state, &tlc_info, remove_reason)
                 .await;
@@ -1035,22 +1950,112 @@ where
         Ok(())
     }
 
-    async fn handle_forward_onion_packet(
+    fn is_payment_hash_settled_by_forwarding(&self, payment_hash: &Hash256) -> bool {
+        self.config.seen_payment_hashes.as_ref().is_some_and(|seen| {
+            seen.lock()
+                .expect("seen payment hashes lock poisoned")
+                .contains(payment_hash)
+        })
+    }
+
+    fn remember_payment_hash_settled_by_forwarding(&self, payment_hash: Hash256) {
+        if let Some(seen) = self.config.seen_payment_hashes.as_ref() {
+            seen.lock()
+                .expect("seen payment hashes lock poisoned")
+                .insert(payment_hash);
+        }
+    }
+
+    // Only the metrics lock is taken besides reading the state, so it's cheap enough for the
+    // pathfinder to query many channels during route computation.
+    fn routing_stats(&self, state: &ChannelActorState) -> RoutingStats {
+        let metrics = self.metrics.lock().expect("channel metrics lock poisoned");
+        RoutingStats {
+            success_count: metrics.forwarded_count,
+            failure_count: metrics.failed_count.values().sum::<u64>()
+                + metrics.downstream_failed_count,
+            liquid_capacity: state.get_liquid_capacity(),
+            tlc_fee_proportional_millionths: state.get_our_tlc_fee_proportional_millionths(),
+            tlc_expiry_delta: state.get_our_tlc_expiry_delta(),
+            millis_since_last_forward: metrics
+                .last_forwarded_at
+                .map(|timestamp| state.clock.now_millis().saturating_sub(timestamp)),
+        }
+    }
+
+    fn record_forward_result(
         &self,
         state: &mut ChannelActorState,
-        payment_hash: Hash256,
-        peeled_onion_packet: PeeledPaymentOnionPacket,
-        added_tlc_id: u64,
-    ) -> Result<(), ProcessingChannelError> {
-        let (send, recv) = oneshot::channel::<Result<u64, TlcErr>>();
-        let rpc_reply = RpcReplyPort::from(send);
-        self.network
-            .send_message(NetworkActorMessage::Command(
-                NetworkActorCommand::SendPaymentOnionPacket(
-                    SendOnionPacketCommand {
-                        peeled_onion_packet,
-                        previous_tlc: Some((state.get_id(), added_tlc_id)),
-                        payment_hash,
+        tlc_info: &AddTlcInfo,
+        remove_reason: &RemoveTlcReason,
+    ) {
+        let (previous_channel_id, previous_tlc) =
+            tlc_info.previous_tlc.expect("expect previous tlc");
+        let received_tlc = self
+            .store
+            .get_channel_actor_state(&previous_channel_id)
+            .and_then(|state| state.get_received_tlc(previous_tlc.into()).cloned());
+        let mut metrics = self.metrics.lock().expect("channel metrics lock poisoned");
+        match remove_reason {
+            RemoveTlcReason::RemoveTlcFulfill(_) => {
+                metrics.forwarded_count += 1;
+                metrics.last_forwarded_at = Some(state.clock.now_millis());
+                match received_tlc {
+                    Some(received_tlc) => {
+                        let fee = received_tlc.amount.saturating_sub(tlc_info.amount);
+                        metrics.fees_earned += fee;
+                        state.total_fees_earned = state.total_fees_earned.saturating_add(fee);
+                        self.network
+                            .send_message(NetworkActorMessage::new_notification(
+                                NetworkServiceEvent::ForwardSettled {
+                                    incoming_channel: previous_channel_id,
+                                    outgoing_channel: tlc_info.channel_id,
+                                    payment_hash: tlc_info.payment_hash,
+                                    fee,
+                                    next_hop: received_tlc.next_hop,
+                                },
+                            ))
+                            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+                    }
+                    None => warn!(
+                        "Previous tlc {:?} of channel {:?} not found, fee is not counted",
+                        previous_tlc, previous_channel_id
+                    ),
+                }
+            }
+            RemoveTlcReason::RemoveTlcFail(_) => {
+                metrics.downstream_failed_count += 1;
+                self.network
+                    .send_message(NetworkActorMessage::new_notification(
+                        NetworkServiceEvent::ForwardFailed {
+                            incoming_channel: previous_channel_id,
+                            outgoing_channel: tlc_info.channel_id,
+                            payment_hash: tlc_info.payment_hash,
+                            next_hop: received_tlc.and_then(|tlc| tlc.next_hop),
+                        },
+                    ))
+                    .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+            }
+        }
+    }
+
+    async fn handle_forward_onion_packet(
+        &self,
+        state: &mut ChannelActorState,
+        payment_hash: Hash256,
+        peeled_onion_packet: PeeledPaymentOnionPacket,
+        added_tlc_id: u64,
+    ) -> Result<(), ProcessingChannelError> {
+        state.set_received_tlc_next_hop(added_tlc_id, peeled_onion_packet.current.next_hop);
+        let (send, recv) = oneshot::channel::<Result<u64, TlcErr>>();
+        let rpc_reply = RpcReplyPort::from(send);
+        self.network
+            .send_message(NetworkActorMessage::Command(
+                NetworkActorCommand::SendPaymentOnionPacket(
+                    SendOnionPacketCommand {
+                        peeled_onion_packet,
+                        previous_tlc: Some((state.get_id(), added_tlc_id)),
+                        payment_hash,
                     },
                     rpc_reply,
                 ),
@@ -1059,6 +2064,13 @@ where
 
         // If we failed to forward the onion packet, we should remove the tlc.
         if let Err(res) = recv.await.expect("expect command replied") {
+            *self
+                .metrics
+                .lock()
+                .expect("channel metrics lock poisoned")
+                .failed_count
+                .entry(res.error_code)
+                .or_default() += 1;
             return Err(ProcessingChannelError::TlcForwardingError(res));
         }
         Ok(())
@@ -1109,6 +2121,20 @@ where
                 )));
             }
         };
+        if let Some(violation) = state.balance_invariant_violation.clone() {
+            self.network
+                .send_message(NetworkActorMessage::new_notification(
+                    NetworkServiceEvent::ChannelBalanceInvariantViolated(
+                        state.get_id(),
+                        violation.clone(),
+                    ),
+                ))
+                .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+            return Err(ProcessingChannelError::InvalidState(format!(
+                "Refusing to sign the commitment as the balance invariant is violated: {}",
+                violation
+            )));
+        }
         let (funding_tx_partial_signature, commitment_tx_partial_signature) =
             state.build_and_sign_commitment_tx()?;
         let commitment_signed = CommitmentSigned {
@@ -1145,10 +2171,103 @@ where
         &self,
         state: &mut ChannelActorState,
         command: AddTlcCommand,
-    ) -> Result<u64, ProcessingChannelError> {
+    ) -> Result<AddTlcResponse, ProcessingChannelError> {
+        let payment_hash = command.payment_hash;
+        let idempotency_key = command.idempotency_key.clone();
+        if let Some(key) = idempotency_key.as_deref() {
+            let idempotency_keys = self
+                .add_tlc_idempotency_keys
+                .lock()
+                .expect("add tlc idempotency keys lock poisoned");
+            if let Some((added_payment_hash, response)) = idempotency_keys.get(key) {
+                if *added_payment_hash != payment_hash {
+                    return Err(ProcessingChannelError::InvalidParameter(format!(
+                        "Idempotency key {} is already used by the tlc with payment hash {:?}",
+                        key, added_payment_hash
+                    )));
+                }
+                debug!("Returning the tlc added with idempotency key {}", key);
+                return Ok(response.clone());
+            }
+        }
+
+        let (add_tlc, response) = self.stage_add_tlc(state, command)?;
+        if let Some(key) = idempotency_key {
+            self.add_tlc_idempotency_keys
+                .lock()
+                .expect("add tlc idempotency keys lock poisoned")
+                .insert(key, payment_hash, response.clone());
+        }
+        self.send_add_tlc(state, add_tlc);
+
+        self.handle_commitment_signed_command(state)?;
+        state.set_waiting_ack(&self.network, true);
+        Ok(response)
+    }
+
+    // Add all the tlcs in the batch and sign them with a single commitment transaction.
+    // The limits are checked cumulatively, and none of the tlcs is added if any of them fails.
+    pub fn handle_add_tlc_batch_command(
+        &self,
+        state: &mut ChannelActorState,
+        commands: Vec<AddTlcCommand>,
+    ) -> Result<Vec<AddTlcResponse>, ProcessingChannelError> {
+        if commands.is_empty() {
+            return Err(ProcessingChannelError::InvalidParameter(
+                "Empty AddTlc batch".to_string(),
+            ));
+        }
+        let tlc_state = state.tlc_state.clone();
+        let mut add_tlcs = Vec::with_capacity(commands.len());
+        let mut responses = Vec::with_capacity(commands.len());
+        for command in commands {
+            match self.stage_add_tlc(state, command) {
+                Ok((add_tlc, response)) => {
+                    add_tlcs.push(add_tlc);
+                    responses.push(response);
+                }
+                Err(err) => {
+                    debug!(
+                        "Rolling back {} staged tlcs in batch: {:?}",
+                        add_tlcs.len(),
+                        &err
+                    );
+                    state.tlc_state = tlc_state;
+                    return Err(err);
+                }
+            }
+        }
+
+        for add_tlc in add_tlcs {
+            self.send_add_tlc(state, add_tlc);
+        }
+
+        self.handle_commitment_signed_command(state)?;
+        state.set_waiting_ack(&self.network, true);
+        Ok(responses)
+    }
+
+    // Check the tlc and add it to the local pending tlcs, the returned message is not sent yet.
+    fn stage_add_tlc(
+        &self,
+        state: &mut ChannelActorState,
+        command: AddTlcCommand,
+    ) -> Result<(AddTlc, AddTlcResponse), ProcessingChannelError> {
         state.check_for_tlc_update(Some(command.amount), true, true)?;
+        if state.draining && command.previous_tlc.is_some() {
+            return Err(ProcessingChannelError::InvalidState(
+                "Channel is draining, forwarding tlc is not allowed".to_string(),
+            ));
+        }
+        // The channel may be disabled before the peers of the incoming channels learn it.
+        if state.get_our_enabled() == Some(false) && command.previous_tlc.is_some() {
+            return Err(ProcessingChannelError::InvalidState(
+                "Channel is disabled, forwarding tlc is not allowed".to_string(),
+            ));
+        }
         state.check_tlc_expiry(command.expiry)?;
-        let tlc = state.create_outbounding_tlc(command.clone());
+        state.check_tlc_hash_algorithm(command.hash_algorithm)?;
+        let tlc = state.create_outbounding_tlc(command.clone())?;
         state.check_insert_tlc(&tlc)?;
         state.tlc_state.add_local_tlc(TlcKind::AddTlc(tlc.clone()));
         state.increment_next_offered_tlc_id();
@@ -1162,7 +2281,15 @@ where
             hash_algorithm: command.hash_algorithm,
             onion_packet: command.onion_packet,
         };
+        let response = AddTlcResponse {
+            tlc_id: tlc.tlc_id.into(),
+            created_at: tlc.created_at,
+            expiry: tlc.expiry,
+        };
+        Ok((add_tlc, response))
+    }
 
+    fn send_add_tlc(&self, state: &ChannelActorState, add_tlc: AddTlc) {
         // Send tlc update message to peer.
         let msg =
             FiberMessageWithPeerId::new(state.get_remote_peer_id(), FiberMessage::add_tlc(add_tlc));
@@ -1172,10 +2299,6 @@ where
                 NetworkActorCommand::SendFiberMessage(msg),
             ))
             .expect(ASSUME_NETWORK_ACTOR_ALIVE);
-
-        self.handle_commitment_signed_command(state)?;
-        state.tlc_state.set_waiting_ack(true);
-        Ok(tlc.tlc_id.into())
     }
 
     pub fn handle_remove_tlc_command(
@@ -1207,10 +2330,142 @@ where
 
         state.maybe_transition_to_shutdown(&self.network)?;
         self.handle_commitment_signed_command(state)?;
-        state.tlc_state.set_waiting_ack(true);
+        state.set_waiting_ack(&self.network, true);
         Ok(())
     }
 
+    /// Force close the channel once the peer keeps failing the signing of the commitments, e.g.
+    /// because of a bug of the peer, so that the channel is not wedged with the commitments which
+    /// can never be signed. The actor is kept alive to wait for the commitment transaction to be
+    /// confirmed, but it no longer handles the commitments from the peer in the closing state.
+    fn handle_signing_failure(
+        &self,
+        state: &mut ChannelActorState,
+        error: &ProcessingChannelError,
+    ) {
+        let failures = self.consecutive_signing_failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if failures < MAX_CONSECUTIVE_SIGNING_FAILURES {
+            return;
+        }
+        self.consecutive_signing_failures.store(0, Ordering::SeqCst);
+
+        let reason = format!(
+            "{} consecutive signing failures with the peer, the last error: {}",
+            failures, error
+        );
+        error!("Force closing channel {:?}: {}", state.get_id(), &reason);
+        if let Err(err) = self.handle_shutdown_command(
+            state,
+            ShutdownCommand {
+                close_script: state.get_local_shutdown_script(),
+                fee_rate: FeeRate::from_u64(state.commitment_fee_rate),
+                force: true,
+                allow_unknown_close_script: true,
+                target_blocks: None,
+                min_net_close_balance: None,
+            },
+        ) {
+            error!(
+                "Failed to force close channel {:?} after signing failures: {:?}",
+                state.get_id(),
+                err
+            );
+            return;
+        }
+        self.network
+            .send_message(NetworkActorMessage::new_notification(
+                NetworkServiceEvent::ChannelForceClosedOnSigningFailures(state.get_id(), reason),
+            ))
+            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+    }
+
+    // The cooperative close is not completed if the channel is still shutting down without
+    // a commitment transaction submitted.
+    fn handle_shutdown_timeout(&self, state: &mut ChannelActorState) {
+        let ChannelState::ShuttingDown(flags) = state.state else {
+            return;
+        };
+        if flags.contains(ShuttingDownFlags::WAITING_COMMITMENT_CONFIRMATION) {
+            return;
+        }
+        let Some(shutdown_timeout) = self.config.shutdown_timeout else {
+            return;
+        };
+
+        warn!(
+            "Cooperative close of channel {:?} timed out in state {:?}",
+            state.get_id(),
+            flags
+        );
+        let mut force_closed = false;
+        if shutdown_timeout.force_close {
+            match self.handle_shutdown_command(
+                state,
+                ShutdownCommand {
+                    close_script: state.get_local_shutdown_script(),
+                    fee_rate: FeeRate::from_u64(state.commitment_fee_rate),
+                    force: true,
+                    allow_unknown_close_script: true,
+                    target_blocks: None,
+                    min_net_close_balance: None,
+                },
+            ) {
+                Ok(_) => force_closed = true,
+                Err(err) => error!(
+                    "Failed to force close channel {:?} after shutdown timeout: {:?}",
+                    state.get_id(),
+                    err
+                ),
+            }
+        }
+        self.network
+            .send_message(NetworkActorMessage::new_notification(
+                NetworkServiceEvent::ChannelShutdownTimedOut(
+                    state.get_id(),
+                    state.get_remote_peer_id(),
+                    force_closed,
+                ),
+            ))
+            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+    }
+
+    /// The peer has lost the channel state and restored the channel from a backup,
+    /// broadcast our latest commitment transaction so that both parties can get their funds back.
+    fn handle_force_close_request(&self, state: &mut ChannelActorState) -> ProcessingChannelResult {
+        // The request is resent on every reconnection, ignore it once the channel is closing.
+        let can_force_close = match state.state {
+            ChannelState::ChannelReady() => true,
+            ChannelState::ShuttingDown(flags) => {
+                !flags.contains(ShuttingDownFlags::WAITING_COMMITMENT_CONFIRMATION)
+            }
+            _ => false,
+        };
+        if !can_force_close {
+            warn!(
+                "Ignoring force close request for channel {:?} in state {:?}",
+                state.get_id(),
+                state.state
+            );
+            return Ok(());
+        }
+        warn!(
+            "Peer {:?} requested to force close channel {:?}",
+            state.get_remote_peer_id(),
+            state.get_id()
+        );
+        self.handle_shutdown_command(
+            state,
+            ShutdownCommand {
+                close_script: state.get_local_shutdown_script(),
+                fee_rate: FeeRate::from_u64(state.commitment_fee_rate),
+                force: true,
+                allow_unknown_close_script: true,
+                target_blocks: None,
+                min_net_close_balance: None,
+            },
+        )
+    }
+
     pub fn handle_shutdown_command(
         &self,
         state: &mut ChannelActorState,
@@ -1240,6 +2495,7 @@ where
                 .latest_commitment_transaction
                 .clone()
                 .expect("latest_commitment_transaction should exist when channel is in ChannelReady of ShuttingDown state");
+            state.track_force_close_transaction(&transaction);
             self.network
                 .send_message(NetworkActorMessage::new_event(
                     NetworkActorEvent::CommitmentTransactionPending(transaction, state.get_id()),
@@ -1266,7 +2522,12 @@ where
             }
         };
 
-        state.check_shutdown_fee_rate(command.fee_rate, &command.close_script)?;
+        self.validate_close_script(&command.close_script, command.allow_unknown_close_script)?;
+        let fee_rate = state.get_shutdown_fee_rate(&command)?;
+        state.check_shutdown_fee_rate(fee_rate, &command.close_script)?;
+        if let Some(min_net_close_balance) = command.min_net_close_balance {
+            state.check_net_close_balance(fee_rate, &command.close_script, min_net_close_balance)?;
+        }
         self.network
             .send_message(NetworkActorMessage::new_command(
                 NetworkActorCommand::SendFiberMessage(FiberMessageWithPeerId::new(
@@ -1274,7 +2535,7 @@ where
                     FiberMessage::shutdown(Shutdown {
                         channel_id: state.get_id(),
                         close_script: command.close_script.clone(),
-                        fee_rate: command.fee_rate,
+                        fee_rate,
                     }),
                 )),
             ))
@@ -1282,7 +2543,7 @@ where
 
         let shutdown_info = ShutdownInfo {
             close_script: command.close_script,
-            fee_rate: command.fee_rate.as_u64(),
+            fee_rate: fee_rate.as_u64(),
             signature: None,
         };
         state.local_shutdown_info = Some(shutdown_info);
@@ -1313,21 +2574,96 @@ where
             tlc_expiry_delta,
             tlc_minimum_value,
             tlc_fee_proportional_millionths,
+            min_tlc_expiry_delta,
+            max_tlc_expiry_delta,
         } = command;
 
-        let mut updated = false;
+        // Reject the updates out of the bounds before changing anything.
+        let bounds = &self.config.fee_policy_bounds;
+        if let Some(fee) = tlc_fee_proportional_millionths {
+            if fee < bounds.min_fee_proportional_millionths
+                || fee > bounds.max_fee_proportional_millionths
+            {
+                return Err(ProcessingChannelError::InvalidParameter(format!(
+                    "TLC fee proportional millionths {} is out of the allowed range [{}, {}]",
+                    fee,
+                    bounds.min_fee_proportional_millionths,
+                    bounds.max_fee_proportional_millionths
+                )));
+            }
+        }
+        if let (Some(value), Some(max_value)) = (tlc_minimum_value, bounds.max_tlc_minimum_value) {
+            if value > max_value {
+                return Err(ProcessingChannelError::InvalidParameter(format!(
+                    "TLC minimum value {} is too large, expect at most {}",
+                    value, max_value
+                )));
+            }
+        }
 
-        if let Some(enabled) = enabled {
-            updated |= state.update_our_enabled(enabled);
+        // The expiry delta bounds are local policy and not announced in ChannelUpdate.
+        let update_expiry_delta_bounds =
+            min_tlc_expiry_delta.is_some() || max_tlc_expiry_delta.is_some();
+        let min = min_tlc_expiry_delta.or(state.get_our_min_tlc_expiry_delta());
+        let max = max_tlc_expiry_delta.or(state.get_our_max_tlc_expiry_delta());
+        if update_expiry_delta_bounds {
+            if let Some(min) = min.filter(|&min| min < MIN_TLC_EXPIRY_DELTA) {
+                return Err(ProcessingChannelError::InvalidParameter(format!(
+                    "Minimal TLC expiry delta {} is too small, expect larger than {}",
+                    min, MIN_TLC_EXPIRY_DELTA
+                )));
+            }
+            if let Some(max) = max.filter(|&max| max > MAX_PAYMENT_TLC_EXPIRY_LIMIT) {
+                return Err(ProcessingChannelError::InvalidParameter(format!(
+                    "Maximal TLC expiry delta {} is too large, expect smaller than {}",
+                    max, MAX_PAYMENT_TLC_EXPIRY_LIMIT
+                )));
+            }
+            if let (Some(min), Some(max)) = (min, max) {
+                if min > max {
+                    return Err(ProcessingChannelError::InvalidParameter(format!(
+                        "Minimal TLC expiry delta {} is larger than the maximal one {}",
+                        min, max
+                    )));
+                }
+            }
         }
 
+        // The expiry delta is checked against the bounds updated by the same command.
         if let Some(delta) = tlc_expiry_delta {
-            if delta < MIN_TLC_EXPIRY_DELTA {
+            let min_delta = min.unwrap_or(MIN_TLC_EXPIRY_DELTA);
+            if delta < min_delta {
                 return Err(ProcessingChannelError::InvalidParameter(format!(
                     "TLC expiry delta is too small, expect larger than {}",
-                    MIN_TLC_EXPIRY_DELTA
+                    min_delta
                 )));
             }
+            let max_delta = max.unwrap_or(MAX_PAYMENT_TLC_EXPIRY_LIMIT);
+            if delta > max_delta {
+                return Err(ProcessingChannelError::InvalidParameter(format!(
+                    "TLC expiry delta is too large, expect smaller than {}",
+                    max_delta
+                )));
+            }
+        }
+
+        // All the updates are valid, apply them now.
+        if update_expiry_delta_bounds {
+            let public_channel_info = state.public_channel_state_mut();
+            public_channel_info.min_tlc_expiry_delta = min;
+            public_channel_info.max_tlc_expiry_delta = max;
+        }
+
+        let mut updated = false;
+
+        if let Some(enabled) = enabled {
+            updated |= state.update_our_enabled(enabled);
+            if enabled {
+                state.draining = false;
+            }
+        }
+
+        if let Some(delta) = tlc_expiry_delta {
             updated |= state.update_our_tlc_expiry_delta(delta);
         }
 
@@ -1348,6 +2684,223 @@ where
         Ok(())
     }
 
+    // Propose to update the commitment fee rate, which is only applied after the peer acks it,
+    // we don't offer new tlcs before that so that both parties sign the same commitment txs.
+    pub fn handle_update_fee_rate_command(
+        &self,
+        state: &mut ChannelActorState,
+        fee_rate: FeeRate,
+    ) -> ProcessingChannelResult {
+        let fee_rate = fee_rate.as_u64();
+        if fee_rate == state.commitment_fee_rate {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "Commitment fee rate is already {}",
+                fee_rate
+            )));
+        }
+        state.check_commitment_fee_rate_update(fee_rate)?;
+        state.pending_commitment_fee_rate = Some(fee_rate);
+        state.send_update_fee_message(fee_rate, false, &self.network);
+        Ok(())
+    }
+
+    // Re-sign the current commitment transaction of the peer without any tlc changes, the peer
+    // replies with a RevokeAndAck message once our signatures are verified.
+    pub fn handle_resync_commitment_command(
+        &self,
+        state: &mut ChannelActorState,
+    ) -> ProcessingChannelResult {
+        if !matches!(state.state, ChannelState::ChannelReady()) {
+            return Err(ProcessingChannelError::InvalidState(format!(
+                "Resyncing commitment while in invalid state {:?}",
+                &state.state
+            )));
+        }
+        if state.tlc_state.waiting_ack || state.tlc_state.all_tlcs().next().is_some() {
+            return Err(ProcessingChannelError::InvalidState(
+                "Resyncing commitment while there are tlcs in flight".to_string(),
+            ));
+        }
+        self.handle_commitment_signed_command(state)?;
+        state.set_waiting_ack(&self.network, true);
+        Ok(())
+    }
+
+    // Report the parts of the multi-part payments received from this channel to the payments
+    // shared by all the channels of the node.
+    fn sync_received_mpp_payments(
+        &self,
+        myself: &ActorRef<ChannelActorMessage>,
+        state: &ChannelActorState,
+    ) {
+        self.config
+            .received_mpp_payments
+            .lock()
+            .expect("received mpp payments lock poisoned")
+            .sync_channel(state.get_id(), myself, &state.mpp_payments);
+    }
+
+    // Reply to the pending ResyncCommitment command with the result of handling the RevokeAndAck
+    // message from the peer.
+    fn complete_commitment_resync(&self, result: &ProcessingChannelResult) {
+        let reply = self
+            .pending_commitment_resync
+            .lock()
+            .expect("pending commitment resync lock poisoned")
+            .take();
+        if let Some((_, reply)) = reply {
+            let _ = reply.send(result.as_ref().map(|_| ()).map_err(|err| {
+                ProcessingChannelError::InvalidState(format!(
+                    "Failed to resync commitment: {}",
+                    err
+                ))
+            }));
+        }
+    }
+
+    // Fail the pending ResyncCommitment command if the peer hasn't replied the resynced remote
+    // commitment with the given number yet.
+    fn handle_commitment_resync_timeout(&self, commitment_number: u64) {
+        let mut pending_commitment_resync = self
+            .pending_commitment_resync
+            .lock()
+            .expect("pending commitment resync lock poisoned");
+        if !matches!(
+            pending_commitment_resync.as_ref(),
+            Some((number, _)) if *number == commitment_number
+        ) {
+            return;
+        }
+        if let Some((_, reply)) = pending_commitment_resync.take() {
+            let _ = reply.send(Err(ProcessingChannelError::InvalidState(format!(
+                "Timed out waiting for the peer to revoke the resynced commitment {}",
+                commitment_number
+            ))));
+        }
+    }
+
+    pub fn handle_bump_force_close_fee_command(
+        &self,
+        state: &mut ChannelActorState,
+        fee_rate: FeeRate,
+    ) -> ProcessingChannelResult {
+        match state.state {
+            ChannelState::ShuttingDown(flags)
+                if flags.contains(ShuttingDownFlags::WAITING_COMMITMENT_CONFIRMATION) => {}
+            _ => {
+                return Err(ProcessingChannelError::InvalidState(format!(
+                    "Bumping force close fee while in invalid state {:?}",
+                    &state.state
+                )));
+            }
+        }
+
+        // The commitment transaction is signed by both parties at the commitment fee rate,
+        // and its commitment output is time locked by the commitment delay, so we can neither
+        // re-sign it with a higher fee nor spend that output with a child transaction.
+        // The only thing we can do is resubmitting it if it already pays enough fee,
+        // or spending our anchor output with a child transaction if the channel has one.
+        if fee_rate.as_u64() > state.commitment_fee_rate {
+            let Some(anchor_outpoint) = state.get_local_anchor_outpoint() else {
+                return Err(ProcessingChannelError::InvalidParameter(format!(
+                    "Fee rate {} is larger than the commitment fee rate {}, which can not be bumped for the commitment transaction",
+                    fee_rate.as_u64(),
+                    state.commitment_fee_rate
+                )));
+            };
+            let transaction = state.latest_commitment_transaction.clone().expect(
+                "latest_commitment_transaction should exist when channel is in ShuttingDown state",
+            );
+            let parent_fee = state.get_commitment_transaction_fee(&transaction)?;
+            let request = FeeBumpRequest {
+                parent_tx: transaction.into_view(),
+                parent_fee,
+                anchor_index: anchor_outpoint.index().unpack(),
+                fee_rate: fee_rate.as_u64(),
+            };
+            debug!(
+                "Bumping the fee of force close transaction {:?} of channel {:?} to {}",
+                anchor_outpoint.tx_hash(),
+                state.get_id(),
+                fee_rate.as_u64()
+            );
+            self.network
+                .send_message(NetworkActorMessage::new_command(
+                    NetworkActorCommand::BumpFeeWithAnchor(state.get_id(), request),
+                ))
+                .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+            return Ok(());
+        }
+
+        let transaction = state.latest_commitment_transaction.clone().expect(
+            "latest_commitment_transaction should exist when channel is in ShuttingDown state",
+        );
+        state.track_force_close_transaction(&transaction);
+        debug!(
+            "Resubmitting force close transactions {:?} of channel {:?}",
+            &state.pending_force_close_tx_hashes,
+            state.get_id()
+        );
+        self.network
+            .send_message(NetworkActorMessage::new_event(
+                NetworkActorEvent::CommitmentTransactionPending(transaction, state.get_id()),
+            ))
+            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+        Ok(())
+    }
+
+    pub fn handle_update_shutdown_script_command(
+        &self,
+        state: &mut ChannelActorState,
+        shutdown_script: Script,
+    ) -> ProcessingChannelResult {
+        if !matches!(state.state, ChannelState::ChannelReady()) {
+            return Err(ProcessingChannelError::InvalidState(format!(
+                "Updating shutdown script while in invalid state {:?}",
+                &state.state
+            )));
+        }
+
+        // The reserved ckb amount is fixed at channel open, make sure it still covers the
+        // cell occupied by the new shutdown script plus the minimal shutdown fee.
+        let udt_type_script = &state.funding_udt_type_script;
+        let occupied_capacity = occupied_capacity(&shutdown_script, udt_type_script)?.as_u64();
+        let reserved_capacity =
+            reserved_capacity(&shutdown_script, udt_type_script, state.anchor_outputs)?.as_u64();
+        if state.local_reserved_ckb_amount < reserved_capacity {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "Reserved CKB amount {} is less than {} (occupied capacity {} of the new shutdown script plus shutdown fee)",
+                state.local_reserved_ckb_amount, reserved_capacity, occupied_capacity,
+            )));
+        }
+
+        debug!(
+            "Updating local shutdown script of channel {:?} to {:?}",
+            state.get_id(),
+            &shutdown_script
+        );
+        state.local_shutdown_script = shutdown_script;
+        Ok(())
+    }
+
+    pub async fn handle_drain_and_disable_command(
+        &self,
+        state: &mut ChannelActorState,
+    ) -> DrainStatus {
+        state.draining = true;
+        if state.is_public() && state.update_our_enabled(false) {
+            let update = state.generate_disabled_channel_update(&self.network).await;
+            self.network
+                .send_message(NetworkActorMessage::new_command(
+                    NetworkActorCommand::BroadcastMessages(vec![BroadcastMessage::ChannelUpdate(
+                        update,
+                    )]),
+                ))
+                .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+        }
+        state.get_drain_status()
+    }
+
     pub async fn register_retryable_tlc_remove(
         &self,
         myself: &ActorRef<ChannelActorMessage>,
@@ -1409,7 +2962,7 @@ where
                     }
                 }
                 RetryableRemoveTlc::RelayRemoveTlc(channel_id, tlc_id, ref reason) => {
-                    let (send, recv) = oneshot::channel::<Result<(), String>>();
+                    let (send, recv) = oneshot::channel::<Result<(), ChannelCommandError>>();
                     let port = RpcReplyPort::from(send);
                     self.network
                         .send_message(NetworkActorMessage::new_command(
@@ -1430,7 +2983,9 @@ where
                             Ok(_) => {
                                 state.tlc_state.remove_pending_remove_tlc(&retryable_remove);
                             }
-                            Err(err) if err.contains("WaitingTlcAck") => {
+                            Err(err)
+                                if err.code == ProcessingChannelError::WaitingTlcAck.code() =>
+                            {
                                 error!(
                                 "Failed to relay remove tlc: {:?} because of WaitingTlcAck, retry it later",
                                 &retryable_remove
@@ -1448,6 +3003,19 @@ where
                 }
             }
         }
+        // Abandon the pending removes which have been retried too many times, e.g. the
+        // downstream channel of a relayed remove is gone, to avoid retrying forever.
+        if let Some(max_attempts) = self.config.max_retryable_remove_attempts {
+            for retryable_remove in state
+                .tlc_state
+                .abandon_exhausted_retryable_removes(max_attempts)
+            {
+                error!(
+                    "Abandon retryable remove tlc: {:?} after {} attempts",
+                    &retryable_remove, max_attempts
+                );
+            }
+        }
         // If there are more pending removes, we will retry it later
         if !state.tlc_state.get_pending_remove().is_empty() {
             myself.send_after(AUTO_SETDOWN_TLC_INTERVAL, || {
@@ -1456,14 +3024,101 @@ where
         }
     }
 
+    pub async fn handle_settle_held_tlc_command(
+        &self,
+        myself: &ActorRef<ChannelActorMessage>,
+        state: &mut ChannelActorState,
+        payment_hash: Hash256,
+    ) -> ProcessingChannelResult {
+        if !self.store.is_invoice_held(&payment_hash) {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "Invoice {:?} is not held",
+                payment_hash
+            )));
+        }
+        let tlc_ids: Vec<u64> = state
+            .get_all_received_tlcs()
+            .filter(|tlc| tlc.payment_hash == payment_hash && tlc.removed_at.is_none())
+            .map(|tlc| tlc.tlc_id.into())
+            .collect();
+        if tlc_ids.is_empty() {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "No held tlc found for payment hash {:?}",
+                payment_hash
+            )));
+        }
+        self.store
+            .set_invoice_hold(&payment_hash, false)
+            .map_err(|err| ProcessingChannelError::InvalidParameter(err.to_string()))?;
+        for tlc_id in tlc_ids {
+            self.try_to_settle_down_tlc(myself, state, tlc_id).await;
+        }
+        Ok(())
+    }
+
+    pub async fn handle_approve_tlc_command(
+        &self,
+        myself: &ActorRef<ChannelActorMessage>,
+        state: &mut ChannelActorState,
+        payment_hash: Hash256,
+    ) -> ProcessingChannelResult {
+        let tlc_ids: Vec<u64> = state
+            .get_all_received_tlcs()
+            .filter(|tlc| tlc.payment_hash == payment_hash && tlc.removed_at.is_none())
+            .map(|tlc| tlc.tlc_id.into())
+            .filter(|tlc_id| state.tlcs_pending_approval.contains(tlc_id))
+            .collect();
+        if tlc_ids.is_empty() {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "No tlc pending approval found for payment hash {:?}",
+                payment_hash
+            )));
+        }
+        for tlc_id in tlc_ids {
+            state.tlcs_pending_approval.remove(&tlc_id);
+            self.try_to_settle_down_tlc(myself, state, tlc_id).await;
+        }
+        Ok(())
+    }
+
+    // The preimage of the tlcs fulfilled in this channel with the payment hash, or of the paid
+    // invoice if the removed tlcs are already pruned from the channel state.
+    pub fn get_payment_proof(
+        &self,
+        state: &ChannelActorState,
+        payment_hash: Hash256,
+    ) -> Option<Hash256> {
+        state
+            .tlc_state
+            .all_tlcs()
+            .filter(|tlc| tlc.payment_hash == payment_hash)
+            .find_map(|tlc| match tlc.removed_at {
+                Some((_, RemoveTlcReason::RemoveTlcFulfill(ref fulfill))) => {
+                    Some(fulfill.payment_preimage)
+                }
+                _ => None,
+            })
+            .or_else(|| match self.store.get_invoice_status(&payment_hash) {
+                Some(CkbInvoiceStatus::Paid) => self.store.get_invoice_preimage(&payment_hash),
+                _ => None,
+            })
+    }
+
     // This is the dual of `handle_tx_collaboration_msg`. Any logic error here is likely
     // to present in the other function as well.
     pub fn handle_tx_collaboration_command(
         &self,
+        myself: &ActorRef<ChannelActorMessage>,
         state: &mut ChannelActorState,
         command: TxCollaborationCommand,
     ) -> Result<(), ProcessingChannelError> {
         debug!("Handling tx collaboration command: {:?}", &command);
+        if let TxCollaborationCommand::TxAbort(reason) = command {
+            state.check_funding_abortable()?;
+            self.send_tx_abort(state, &reason);
+            self.abort_funding(myself, state, reason);
+            return Ok(());
+        }
         let is_complete_command = matches!(command, TxCollaborationCommand::TxComplete());
         let is_waiting_for_remote = match state.state {
             ChannelState::CollaboratingFundingTx(flags) => {
@@ -1515,6 +3170,8 @@ where
         // as in that case both us and the remote are waiting for each other to send the message.
         match command {
             TxCollaborationCommand::TxUpdate(tx_update) => {
+                // Make sure we don't contribute more than our negotiated amount.
+                state.is_tx_final(&tx_update.transaction, state.remote_funding_contribution)?;
                 let fiber_message = FiberMessage::tx_update(TxUpdate {
                     channel_id: state.get_id(),
                     tx: tx_update.transaction.clone(),
@@ -1554,25 +3211,63 @@ where
                     flags | CollaboratingFundingTxFlags::OUR_TX_COMPLETE_SENT,
                 ));
             }
+            TxCollaborationCommand::TxAbort(_) => unreachable!("TxAbort is handled above"),
         }
 
         Ok(())
     }
 
+    fn send_tx_abort(&self, state: &ChannelActorState, reason: &str) {
+        self.network
+            .send_message(NetworkActorMessage::new_command(
+                NetworkActorCommand::SendFiberMessage(FiberMessageWithPeerId::new(
+                    state.get_remote_peer_id(),
+                    FiberMessage::tx_abort(TxAbort {
+                        channel_id: state.get_id(),
+                        message: reason.as_bytes().to_vec(),
+                    }),
+                )),
+            ))
+            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+    }
+
+    // The funding transaction is not broadcast yet, so it's safe to just discard it
+    // and close the channel without any on-chain transaction.
+    fn abort_funding(
+        &self,
+        myself: &ActorRef<ChannelActorMessage>,
+        state: &mut ChannelActorState,
+        reason: String,
+    ) {
+        state.funding_tx = None;
+        state.update_state(ChannelState::Closed(CloseFlags::ABANDONED));
+        self.network
+            .send_message(NetworkActorMessage::new_event(
+                NetworkActorEvent::FundingAborted(
+                    state.get_id(),
+                    state.get_remote_peer_id(),
+                    reason,
+                ),
+            ))
+            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+        myself.stop(Some("FundingAborted".to_string()));
+    }
+
     pub async fn handle_command(
         &self,
+        myself: &ActorRef<ChannelActorMessage>,
         state: &mut ChannelActorState,
         command: ChannelCommand,
     ) -> Result<(), ProcessingChannelError> {
         match command {
             ChannelCommand::TxCollaborationCommand(tx_collaboration_command) => {
-                self.handle_tx_collaboration_command(state, tx_collaboration_command)
+                self.handle_tx_collaboration_command(myself, state, tx_collaboration_command)
             }
             ChannelCommand::CommitmentSigned() => self.handle_commitment_signed_command(state),
             ChannelCommand::AddTlc(command, reply) => {
                 match self.handle_add_tlc_command(state, command) {
-                    Ok(tlc_id) => {
-                        let _ = reply.send(Ok(AddTlcResponse { tlc_id }));
+                    Ok(response) => {
+                        let _ = reply.send(Ok(response));
                         Ok(())
                     }
                     Err(err) => {
@@ -1583,6 +3278,20 @@ where
                     }
                 }
             }
+            ChannelCommand::AddTlcBatch(commands, reply) => {
+                match self.handle_add_tlc_batch_command(state, commands) {
+                    Ok(responses) => {
+                        let _ = reply.send(Ok(responses));
+                        Ok(())
+                    }
+                    Err(err) => {
+                        debug!("Error processing AddTlcBatch command: {:?}", &err);
+                        let tlc_err = self.get_tlc_error(state, &err).await;
+                        let _ = reply.send(Err(tlc_err));
+                        Err(err)
+                    }
+                }
+            }
             ChannelCommand::RemoveTlc(command, reply) => {
                 match self.handle_remove_tlc_command(state, command) {
                     Ok(_) => {
@@ -1590,21 +3299,49 @@ where
                         Ok(())
                     }
                     Err(err) => {
-                        let _ = reply.send(Err(err.to_string()));
+                        let _ = reply.send(Err((&err).into()));
+                        Err(err)
+                    }
+                }
+            }
+            ChannelCommand::FailTlc(tlc_id, error_code, reply) => {
+                let result = state
+                    .build_fail_reason(tlc_id, error_code)
+                    .and_then(|reason| {
+                        self.handle_remove_tlc_command(
+                            state,
+                            RemoveTlcCommand { id: tlc_id, reason },
+                        )
+                    });
+                match result {
+                    Ok(_) => {
+                        let _ = reply.send(Ok(()));
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let _ = reply.send(Err((&err).into()));
                         Err(err)
                     }
                 }
             }
             ChannelCommand::Shutdown(command, reply) => {
+                let force = command.force;
                 match self.handle_shutdown_command(state, command) {
                     Ok(_) => {
                         debug!("Shutdown command processed successfully");
+                        if let (false, Some(shutdown_timeout)) =
+                            (force, self.config.shutdown_timeout)
+                        {
+                            myself.send_after(shutdown_timeout.timeout, || {
+                                ChannelActorMessage::Event(ChannelEvent::CheckShutdownTimeout)
+                            });
+                        }
                         let _ = reply.send(Ok(()));
                         Ok(())
                     }
                     Err(err) => {
                         debug!("Error processing shutdown command: {:?}", &err);
-                        let _ = reply.send(Err(err.to_string()));
+                        let _ = reply.send(Err((&err).into()));
                         Err(err)
                     }
                 }
@@ -1612,23 +3349,251 @@ where
             ChannelCommand::Update(command, reply) => {
                 match self.handle_update_command(state, command).await {
                     Ok(_) => {
-                        debug!("Update command processed successfully");
+                        debug!("Update command processed successfully");
+                        let _ = reply.send(Ok(()));
+                        Ok(())
+                    }
+                    Err(err) => {
+                        debug!("Error processing update command: {:?}", &err);
+                        let _ = reply.send(Err((&err).into()));
+                        Err(err)
+                    }
+                }
+            }
+            ChannelCommand::GetBalanceHistory(reply) => {
+                let _ = reply.send(state.get_balance_history());
+                Ok(())
+            }
+            ChannelCommand::GetSettlementDataAt(commitment_number, local, reply) => {
+                let _ = reply.send(self.store.get_settlement_data(
+                    &state.get_id(),
+                    local,
+                    commitment_number,
+                ));
+                Ok(())
+            }
+            ChannelCommand::GetRemoteSettlementData(reply) => {
+                let _ = reply.send(state.remote_settlement_data().map_err(|err| (&err).into()));
+                Ok(())
+            }
+            ChannelCommand::GetFundingAddress(network, reply) => {
+                let _ = reply.send(state.get_funding_address(network));
+                Ok(())
+            }
+            ChannelCommand::GetTlcCapacityStatus(reply) => {
+                let _ = reply.send(state.tlc_capacity_status());
+                Ok(())
+            }
+            ChannelCommand::GetMaxTlcAmounts(reply) => {
+                let _ = reply.send(MaxTlcAmounts {
+                    max_sendable_amount: state.max_sendable_amount(),
+                    max_receivable_amount: state.max_receivable_amount(),
+                });
+                Ok(())
+            }
+            ChannelCommand::GetCapacityBreakdown(reply) => {
+                let _ = reply.send(state.capacity_breakdown());
+                Ok(())
+            }
+            ChannelCommand::GetMusig2Info(reply) => {
+                let _ = reply.send(state.musig2_info().map_err(|err| (&err).into()));
+                Ok(())
+            }
+            ChannelCommand::GetCommitmentPointInfo(reply) => {
+                let _ = reply.send(state.commitment_point_info());
+                Ok(())
+            }
+            ChannelCommand::GetChannelSummary(reply) => {
+                let _ = reply.send(state.channel_summary());
+                Ok(())
+            }
+            ChannelCommand::GetPaymentProof(payment_hash, reply) => {
+                let _ = reply.send(self.get_payment_proof(state, payment_hash));
+                Ok(())
+            }
+            ChannelCommand::GetForwardingPolicy(reply) => {
+                let _ = reply.send(state.get_forwarding_policy().map_err(|err| (&err).into()));
+                Ok(())
+            }
+            ChannelCommand::ExportBackup(reply) => {
+                let _ = reply.send(
+                    state
+                        .get_static_channel_backup()
+                        .map(|backup| {
+                            ChannelBackup::encrypt(&backup, &self.config.channel_backup_key)
+                        })
+                        .map_err(|err| (&err).into()),
+                );
+                Ok(())
+            }
+            ChannelCommand::GetLatestCommitmentTx(reply) => {
+                let _ = reply.send(state.get_latest_commitment_tx_info());
+                Ok(())
+            }
+            ChannelCommand::GetMetrics(reply) => {
+                let metrics = self
+                    .metrics
+                    .lock()
+                    .expect("channel metrics lock poisoned")
+                    .clone();
+                let _ = reply.send(metrics);
+                Ok(())
+            }
+            ChannelCommand::GetRoutingStats(reply) => {
+                let _ = reply.send(self.routing_stats(state));
+                Ok(())
+            }
+            ChannelCommand::GetLifetimeFees(reply) => {
+                let _ = reply.send(state.total_fees_earned);
+                Ok(())
+            }
+            ChannelCommand::InspectRetryableRemoves(reply) => {
+                let _ = reply.send(state.tlc_state.get_pending_remove());
+                Ok(())
+            }
+            ChannelCommand::DumpTlcState(reply) => {
+                let _ = reply.send(state.tlc_state.dump());
+                Ok(())
+            }
+            ChannelCommand::ClearRetryableRemove(retryable_remove) => {
+                warn!(
+                    "Clearing retryable remove tlc manually: {:?}",
+                    &retryable_remove
+                );
+                state.tlc_state.remove_pending_remove_tlc(&retryable_remove);
+                Ok(())
+            }
+            ChannelCommand::SetForwardingPaused(paused) => {
+                info!(
+                    "Setting tlc forwarding paused to {} for channel {:?}",
+                    paused,
+                    state.get_id()
+                );
+                self.forwarding_paused.store(paused, Ordering::SeqCst);
+                Ok(())
+            }
+            ChannelCommand::IsReadyToForward(reply) => {
+                let _ = reply.send(state.is_ready_to_forward());
+                Ok(())
+            }
+            ChannelCommand::GetForwardingBlocker(reply) => {
+                let _ = reply.send(state.forwarding_blocker());
+                Ok(())
+            }
+            ChannelCommand::DrainAndDisable(reply) => {
+                let status = self.handle_drain_and_disable_command(state).await;
+                let _ = reply.send(status);
+                Ok(())
+            }
+            ChannelCommand::UpdateShutdownScript(shutdown_script, reply) => {
+                match self.handle_update_shutdown_script_command(state, shutdown_script) {
+                    Ok(_) => {
+                        let _ = reply.send(Ok(()));
+                        Ok(())
+                    }
+                    Err(err) => {
+                        debug!("Error processing update shutdown script command: {:?}", &err);
+                        let _ = reply.send(Err((&err).into()));
+                        Err(err)
+                    }
+                }
+            }
+            ChannelCommand::ResyncCommitment(reply) => {
+                match self.handle_resync_commitment_command(state) {
+                    Ok(_) => {
+                        let commitment_number = state.get_remote_commitment_number();
+                        *self
+                            .pending_commitment_resync
+                            .lock()
+                            .expect("pending commitment resync lock poisoned") =
+                            Some((commitment_number, reply));
+                        if let Some(timeout) = self.config.commitment_resync_timeout {
+                            myself.send_after(timeout, move || {
+                                ChannelActorMessage::Event(
+                                    ChannelEvent::CheckCommitmentResyncTimeout(commitment_number),
+                                )
+                            });
+                        }
+                    }
+                    Err(err) => {
+                        debug!("Error processing resync commitment command: {:?}", &err);
+                        let _ = reply.send(Err(err));
+                    }
+                }
+                Ok(())
+            }
+            ChannelCommand::UpdateFeeRate(fee_rate, reply) => {
+                match self.handle_update_fee_rate_command(state, fee_rate) {
+                    Ok(_) => {
+                        let _ = reply.send(Ok(()));
+                        Ok(())
+                    }
+                    Err(err) => {
+                        debug!("Error processing update fee rate command: {:?}", &err);
+                        let _ = reply.send(Err((&err).into()));
+                        Err(err)
+                    }
+                }
+            }
+            ChannelCommand::BumpForceCloseFee(fee_rate, reply) => {
+                match self.handle_bump_force_close_fee_command(state, fee_rate) {
+                    Ok(_) => {
+                        let _ = reply.send(Ok(()));
+                        Ok(())
+                    }
+                    Err(err) => {
+                        debug!("Error processing bump force close fee command: {:?}", &err);
+                        let _ = reply.send(Err((&err).into()));
+                        Err(err)
+                    }
+                }
+            }
+            ChannelCommand::SettleHeldTlc(payment_hash, reply) => {
+                match self
+                    .handle_settle_held_tlc_command(myself, state, payment_hash)
+                    .await
+                {
+                    Ok(_) => {
+                        let _ = reply.send(Ok(()));
+                        Ok(())
+                    }
+                    Err(err) => {
+                        debug!("Error processing settle held tlc command: {:?}", &err);
+                        let _ = reply.send(Err((&err).into()));
+                        Err(err)
+                    }
+                }
+            }
+            ChannelCommand::ApproveTlc(payment_hash, reply) => {
+                match self
+                    .handle_approve_tlc_command(myself, state, payment_hash)
+                    .await
+                {
+                    Ok(_) => {
                         let _ = reply.send(Ok(()));
                         Ok(())
                     }
                     Err(err) => {
-                        debug!("Error processing update command: {:?}", &err);
-                        let _ = reply.send(Err(err.to_string()));
+                        debug!("Error processing approve tlc command: {:?}", &err);
+                        let _ = reply.send(Err((&err).into()));
                         Err(err)
                     }
                 }
             }
             #[cfg(test)]
             ChannelCommand::ReloadState() => {
+                let clock = state.clock.clone();
+                let state_transition_subscribers = state.state_transition_subscribers.take();
+                let commitment_signer = std::mem::take(&mut state.commitment_signer);
                 *state = self
                     .store
                     .get_channel_actor_state(&state.get_id())
                     .expect("load channel state failed");
+                state.clock = clock;
+                state.state_transition_subscribers = state_transition_subscribers;
+                state.commitment_signer = commitment_signer;
+                state.max_remote_commitment_points = self.config.max_remote_commitment_points;
+                state.fee_estimator = self.config.fee_estimator.clone();
                 Ok(())
             }
         }
@@ -1670,7 +3635,7 @@ where
                 state.update_state(ChannelState::AwaitingChannelReady(flags));
                 state.maybe_channel_is_ready(&self.network).await;
             }
-            ChannelEvent::CommitmentTransactionConfirmed => {
+            ChannelEvent::CommitmentTransactionConfirmed(tx_hash) => {
                 match state.state {
                     ChannelState::ShuttingDown(flags)
                         if flags.contains(ShuttingDownFlags::WAITING_COMMITMENT_CONFIRMATION) => {}
@@ -1680,17 +3645,136 @@ where
                         ));
                     }
                 };
+                state.pending_force_close_tx_hashes.clear();
                 state.update_state(ChannelState::Closed(CloseFlags::UNCOOPERATIVE));
+                self.store
+                    .insert_closed_channel_record(state.closed_channel_record(tx_hash));
                 debug!("Channel closed with uncooperative close");
             }
+            ChannelEvent::ForceCloseFeeBumped(transaction) => {
+                if let ChannelState::ShuttingDown(flags) = state.state {
+                    if flags.contains(ShuttingDownFlags::WAITING_COMMITMENT_CONFIRMATION) {
+                        state.track_force_close_transaction(&transaction);
+                    }
+                }
+            }
             ChannelEvent::CheckTlcSetdown => {
                 self.check_and_apply_retryable_remove_tlcs(myself, state)
                     .await;
             }
-            ChannelEvent::PeerDisconnected => {
-                myself.stop(Some("PeerDisconnected".to_string()));
+            ChannelEvent::CheckHeldTlc(tlc_id) => {
+                if state
+                    .get_received_tlc(tlc_id)
+                    .is_some_and(|tlc| tlc.removed_at.is_none())
+                {
+                    self.try_to_settle_down_tlc(myself, state, tlc_id).await;
+                }
+            }
+            ChannelEvent::CheckMppPayment(payment_hash) => {
+                let pending_removes = state.tlc_state.get_pending_remove();
+                let part_ids = state
+                    .mpp_payments
+                    .get(&payment_hash)
+                    .map(|mpp_payment| mpp_payment.parts.keys().copied().collect::<Vec<_>>())
+                    .unwrap_or_default();
+                for tlc_id in part_ids {
+                    // The part which decided the payment status is already being removed.
+                    let is_removing = pending_removes.iter().any(|remove| {
+                        matches!(
+                            remove,
+                            RetryableRemoveTlc::RemoveTlc(id, _) if u64::from(*id) == tlc_id
+                        )
+                    });
+                    if !is_removing {
+                        myself
+                            .send_message(ChannelActorMessage::Event(ChannelEvent::CheckHeldTlc(
+                                tlc_id,
+                            )))
+                            .expect("myself alive");
+                    }
+                }
+            }
+            ChannelEvent::PeerDisconnected => match self.config.peer_disconnect_grace {
+                Some(grace) => {
+                    let disconnected_at = state.clock.now_millis();
+                    state.peer_disconnected_at = Some(disconnected_at);
+                    myself.send_after(grace, move || {
+                        ChannelActorMessage::Event(ChannelEvent::PeerDisconnectGraceElapsed(
+                            disconnected_at,
+                        ))
+                    });
+                }
+                None => {
+                    myself.stop(Some("PeerDisconnected".to_string()));
+                }
+            },
+            ChannelEvent::PeerReconnected => {
+                // Cancel the pending stop, and resync with the peer as messages may be lost
+                // while disconnected.
+                if state.peer_disconnected_at.take().is_some() {
+                    self.start_reestablishing(myself, state);
+                }
+            }
+            ChannelEvent::PeerDisconnectGraceElapsed(disconnected_at) => {
+                // The peer may have reconnected and disconnected again, only the latest
+                // disconnection is effective.
+                if state.peer_disconnected_at == Some(disconnected_at) {
+                    myself.stop(Some("PeerDisconnected".to_string()));
+                }
+            }
+            ChannelEvent::SendReestablishChannel(sequence) => {
+                // Our message is still needed by the peer even if we have handled its message,
+                // unless a newer attempt has been started.
+                if self.reestablish_sequence.load(Ordering::SeqCst) == sequence {
+                    self.send_reestablish_channel(state);
+                }
+            }
+            ChannelEvent::CheckReestablishTimeout(sequence) => {
+                if state.reestablishing
+                    && self.reestablish_sequence.load(Ordering::SeqCst) == sequence
+                    && state.peer_disconnected_at.is_none()
+                {
+                    let attempts = self.reestablish_attempts.load(Ordering::SeqCst);
+                    warn!(
+                        "Reestablishing channel {:?} timed out after {} attempts",
+                        state.get_id(),
+                        attempts
+                    );
+                    if attempts >= REESTABLISH_TIMEOUT_NOTIFY_ATTEMPTS {
+                        self.network
+                            .send_message(NetworkActorMessage::new_notification(
+                                NetworkServiceEvent::ChannelReestablishTimedOut(
+                                    state.get_id(),
+                                    state.get_remote_peer_id(),
+                                    attempts,
+                                ),
+                            ))
+                            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+                    }
+                    self.start_reestablishing(myself, state);
+                }
+            }
+            ChannelEvent::CheckShutdownTimeout => {
+                self.handle_shutdown_timeout(state);
+            }
+            ChannelEvent::CheckCommitmentResyncTimeout(commitment_number) => {
+                self.handle_commitment_resync_timeout(commitment_number);
+            }
+            ChannelEvent::CheckFundingTimeout => {
+                if state.check_funding_abortable().is_ok() {
+                    let reason = "Funding collaboration timed out".to_string();
+                    warn!(
+                        "Aborting funding of channel {:?}: {}",
+                        state.get_id(),
+                        &reason
+                    );
+                    self.send_tx_abort(state, &reason);
+                    self.abort_funding(myself, state, reason);
+                }
             }
-            ChannelEvent::ClosingTransactionConfirmed => {
+            ChannelEvent::ClosingTransactionConfirmed(tx_hash) => {
+                self.store
+                    .insert_closed_channel_record(state.closed_channel_record(tx_hash));
                 // Broadcast the channel update message which disables the channel.
                 let update = state.generate_disabled_channel_update(&self.network).await;
 
@@ -1708,6 +3792,71 @@ where
         Ok(())
     }
 
+    // Send the ReestablishChannel message to resync the channel state with the peer.
+    fn start_reestablishing(
+        &self,
+        myself: &ActorRef<ChannelActorMessage>,
+        state: &mut ChannelActorState,
+    ) {
+        state.reestablishing = true;
+
+        let attempts = self.reestablish_attempts.fetch_add(1, Ordering::SeqCst);
+        let sequence = self.reestablish_sequence.fetch_add(1, Ordering::SeqCst) + 1;
+        let delay = self.config.reestablish_backoff.delay(attempts);
+        if delay.is_zero() {
+            self.send_reestablish_channel(state);
+        } else {
+            debug!(
+                "Reestablishing channel {:?} in {:?} after {} uncompleted attempts",
+                state.get_id(),
+                delay,
+                attempts
+            );
+            myself.send_after(delay, move || {
+                ChannelActorMessage::Event(ChannelEvent::SendReestablishChannel(sequence))
+            });
+        }
+        if let Some(timeout) = self.config.reestablish_backoff.timeout {
+            myself.send_after(delay + timeout, move || {
+                ChannelActorMessage::Event(ChannelEvent::CheckReestablishTimeout(sequence))
+            });
+        }
+
+        // If the channel is already ready, we should notify the network actor.
+        // so that we update the network.outpoint_channel_map
+        if matches!(state.state, ChannelState::ChannelReady()) {
+            self.network
+                .send_message(NetworkActorMessage::new_event(
+                    NetworkActorEvent::ChannelReady(
+                        state.get_id(),
+                        state.get_remote_peer_id(),
+                        state.must_get_funding_transaction_outpoint(),
+                    ),
+                ))
+                .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+        }
+    }
+
+    fn send_reestablish_channel(&self, state: &ChannelActorState) {
+        let reestablish_channel = ReestablishChannel {
+            channel_id: state.get_id(),
+            local_commitment_number: state.get_current_commitment_number(true),
+            remote_commitment_number: state.get_current_commitment_number(false),
+            force_close_request: false,
+        };
+
+        let command = FiberMessageWithPeerId::new(
+            self.get_remote_peer_id(),
+            FiberMessage::reestablish_channel(reestablish_channel),
+        );
+
+        self.network
+            .send_message(NetworkActorMessage::new_command(
+                NetworkActorCommand::SendFiberMessage(command),
+            ))
+            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+    }
+
     fn get_invoice_status(&self, invoice: &CkbInvoice) -> CkbInvoiceStatus {
         match self
             .store
@@ -1743,9 +3892,16 @@ where
 
     async fn pre_start(
         &self,
-        _myself: ActorRef<Self::Msg>,
+        myself: ActorRef<Self::Msg>,
         args: Self::Arguments,
     ) -> Result<Self::State, ActorProcessingErr> {
+        // Channels that are not funded by then will be aborted, this is a no-op for other channels.
+        if let Some(funding_timeout) = self.config.funding_timeout {
+            myself.send_after(funding_timeout, || {
+                ChannelActorMessage::Event(ChannelEvent::CheckFundingTimeout)
+            });
+        }
+
         // startup the event processing
         match args {
             ChannelInitializationParameter::AcceptChannel(AcceptChannelParameter {
@@ -1758,6 +3914,14 @@ where
                 channel_id_sender,
                 max_tlc_number_in_flight,
                 max_tlc_value_in_flight,
+                max_tlc_value_in_flight_percentage,
+                commitment_delay_epoch: local_commitment_delay_epoch,
+                min_accept_funding_amount,
+                channel_reserve_amount,
+                allowed_hash_algorithms,
+                allowed_udt_scripts,
+                accept_channel_visibility,
+                accept_min_remote_reserve,
             }) => {
                 let peer_id = self.get_remote_peer_id();
                 debug!(
@@ -1783,6 +3947,7 @@ where
                     max_tlc_value_in_flight: remote_max_tlc_value_in_flight,
                     max_tlc_number_in_flight: remote_max_tlc_number_in_flight,
                     channel_announcement_nonce,
+                    push_amount,
                     ..
                 } = &open_channel;
 
@@ -1793,8 +3958,33 @@ where
                     ))));
                 }
 
-                // TODO: we may reject the channel opening request here
-                // if the peer want to open a public channel, but we don't want to.
+                if push_amount > funding_amount {
+                    return Err(Box::new(ProcessingChannelError::InvalidParameter(format!(
+                        "Push amount {} is greater than the funding amount {}",
+                        push_amount, funding_amount
+                    ))));
+                }
+
+                check_funding_udt_type_script(
+                    allowed_udt_scripts.as_deref().map(Vec::as_slice),
+                    funding_udt_type_script,
+                )?;
+
+                if !accept_channel_visibility.accepts(public) {
+                    return Err(Box::new(ProcessingChannelError::InvalidParameter(format!(
+                        "{} channel is not accepted by the channel visibility policy {:?}",
+                        if public { "Public" } else { "Private" },
+                        accept_channel_visibility
+                    ))));
+                }
+
+                if *reserved_ckb_amount < accept_min_remote_reserve {
+                    return Err(Box::new(ProcessingChannelError::InvalidParameter(format!(
+                        "Opener reserved CKB amount {} is below the minimal remote reserve {}",
+                        reserved_ckb_amount, accept_min_remote_reserve
+                    ))));
+                }
+
                 if public && (channel_announcement_nonce.is_none() || public_channel_info.is_none())
                 {
                     return Err(Box::new(ProcessingChannelError::InvalidParameter(
@@ -1802,13 +3992,27 @@ where
                     )));
                 }
 
+                // The delay in `OpenChannel` is applied to our commitment transactions, while the
+                // acceptor may propose a different delay for the commitment transactions of the
+                // opener, otherwise both commitment transactions use the same delay.
+                check_commitment_delay_epoch(*commitment_delay_epoch)?;
+                let remote_delay_epoch = match local_commitment_delay_epoch {
+                    Some(epoch) => {
+                        check_commitment_delay_epoch(epoch.full_value())?;
+                        epoch.full_value()
+                    }
+                    None => *commitment_delay_epoch,
+                };
+
                 let mut state = ChannelActorState::new_inbound_channel(
                     *channel_id,
+                    *chain_hash,
                     public_channel_info,
                     local_funding_amount,
                     local_reserved_ckb_amount,
                     *commitment_fee_rate,
                     *commitment_delay_epoch,
+                    remote_delay_epoch,
                     *funding_fee_rate,
                     funding_udt_type_script.clone(),
                     &seed,
@@ -1828,8 +4032,46 @@ where
                     max_tlc_number_in_flight,
                     max_tlc_value_in_flight,
                 );
+                state.state_transition_subscribers =
+                    Some(self.subscribers.state_transition_subscribers.clone());
+                state.max_remote_commitment_points = self.config.max_remote_commitment_points;
+                state.fee_estimator = self.config.fee_estimator.clone();
+                self.install_commitment_signer(&mut state)?;
+                state.local_constraints.channel_reserve_amount = channel_reserve_amount;
+                state.set_max_tlc_value_in_flight_percentage(max_tlc_value_in_flight_percentage);
+                state.anchor_outputs = open_channel.has_anchor_outputs();
+                state.sorted_settlement_outputs = open_channel.has_sorted_settlement_outputs();
+                state.tlc_dust_limit = open_channel.tlc_dust_limit;
+                state.allowed_hash_algorithms = negotiate_hash_algorithms(
+                    &allowed_hash_algorithms,
+                    &open_channel.allowed_hash_algorithms,
+                )?;
+                state.features = FeatureBits::all() & open_channel.features;
+                if open_channel.has_anchor_outputs() {
+                    state.check_feature_negotiated(FeatureBits::ANCHOR_OUTPUTS)?;
+                }
+                if open_channel.has_sorted_settlement_outputs() {
+                    state.check_feature_negotiated(FeatureBits::SORTED_SETTLEMENT_OUTPUTS)?;
+                }
+                // A peer without asymmetric delays adopts the delay in `AcceptChannel` for both
+                // commitment transactions.
+                if !state.features.contains(FeatureBits::ASYMMETRIC_DELAY_EPOCHS) {
+                    state.local_delay_epoch = state.remote_delay_epoch;
+                }
+                // The opener pushes part of its funding amount to us.
+                state.push_amount = *push_amount;
+                state.to_local_amount += *push_amount;
+                state.to_remote_amount -= *push_amount;
                 state.check_accept_channel_parameters()?;
 
+                let liquid_capacity = state.to_local_amount + state.to_remote_amount;
+                if liquid_capacity < min_accept_funding_amount {
+                    return Err(Box::new(ProcessingChannelError::InvalidParameter(format!(
+                        "The channel capacity {} is less than the minimal accept funding amount {}",
+                        liquid_capacity, min_accept_funding_amount
+                    ))));
+                }
+
                 let commitment_number = INITIAL_COMMITMENT_NUMBER;
 
                 let channel_announcement_nonce = if public {
@@ -1844,7 +4086,7 @@ where
                     reserved_ckb_amount: local_reserved_ckb_amount,
                     max_tlc_value_in_flight,
                     max_tlc_number_in_flight,
-                    funding_pubkey: state.signer.funding_key.pubkey(),
+                    funding_pubkey: state.get_local_channel_public_keys().funding_pubkey,
                     tlc_basepoint: state.signer.tlc_base_key.pubkey(),
                     first_per_commitment_point: state
                         .signer
@@ -1854,6 +4096,9 @@ where
                         .get_commitment_point(commitment_number + 1),
                     channel_announcement_nonce,
                     next_local_nonce: state.get_local_musig2_pubnonce(),
+                    commitment_delay_epoch: state.remote_delay_epoch,
+                    allowed_hash_algorithms: state.allowed_hash_algorithms.clone(),
+                    features: state.features,
                 };
 
                 let command = FiberMessageWithPeerId::new(
@@ -1877,6 +4122,7 @@ where
             }
             ChannelInitializationParameter::OpenChannel(OpenChannelParameter {
                 funding_amount,
+                push_amount,
                 seed,
                 public_channel_info,
                 funding_udt_type_script,
@@ -1887,6 +4133,12 @@ where
                 funding_fee_rate,
                 max_tlc_number_in_flight,
                 max_tlc_value_in_flight,
+                max_tlc_value_in_flight_percentage,
+                channel_reserve_amount,
+                anchor_outputs,
+                sorted_settlement_outputs,
+                tlc_dust_limit,
+                allowed_hash_algorithms,
             }) => {
                 let public = public_channel_info.is_some();
                 let peer_id = self.get_remote_peer_id();
@@ -1900,7 +4152,16 @@ where
                     funding_amount,
                     &shutdown_script,
                     &funding_udt_type_script,
+                    anchor_outputs,
                 )?;
+                // The pushed amount is taken from our liquid funding amount, the reserved CKB amount
+                // is left untouched, so that our outputs in the commitment transactions are still valid.
+                if push_amount > to_local_amount.saturating_sub(channel_reserve_amount) {
+                    return Err(Box::new(ProcessingChannelError::InvalidParameter(format!(
+                        "Push amount {} is greater than the funding amount {} minus the channel reserve amount {}",
+                        push_amount, to_local_amount, channel_reserve_amount
+                    ))));
+                }
 
                 let mut channel = ChannelActorState::new_outbound_channel(
                     public_channel_info,
@@ -1908,6 +4169,7 @@ where
                     self.get_local_pubkey(),
                     self.get_remote_pubkey(),
                     to_local_amount,
+                    push_amount,
                     reserved_ckb_amount,
                     commitment_fee_rate,
                     commitment_delay_epoch
@@ -1923,14 +4185,31 @@ where
                     max_tlc_value_in_flight,
                     max_tlc_number_in_flight,
                 );
+                channel.state_transition_subscribers =
+                    Some(self.subscribers.state_transition_subscribers.clone());
+                channel.max_remote_commitment_points = self.config.max_remote_commitment_points;
+                channel.fee_estimator = self.config.fee_estimator.clone();
+                self.install_commitment_signer(&mut channel)?;
+                channel.local_constraints.channel_reserve_amount = channel_reserve_amount;
+                channel.set_max_tlc_value_in_flight_percentage(max_tlc_value_in_flight_percentage);
+                channel.anchor_outputs = anchor_outputs;
+                channel.sorted_settlement_outputs = sorted_settlement_outputs;
+                channel.tlc_dust_limit = tlc_dust_limit;
+                channel.allowed_hash_algorithms = allowed_hash_algorithms;
 
                 channel.check_open_channel_parameters()?;
 
-                let channel_flags = if public {
+                let mut channel_flags = if public {
                     ChannelFlags::PUBLIC
                 } else {
                     ChannelFlags::empty()
                 };
+                if anchor_outputs {
+                    channel_flags |= ChannelFlags::ANCHOR_OUTPUTS;
+                }
+                if sorted_settlement_outputs {
+                    channel_flags |= ChannelFlags::SORTED_SETTLEMENT_OUTPUTS;
+                }
                 let channel_announcement_nonce = if public {
                     Some(channel.get_channel_announcement_musig2_pubnonce())
                 } else {
@@ -1938,15 +4217,18 @@ where
                 };
                 let commitment_number = INITIAL_COMMITMENT_NUMBER;
                 let message = FiberMessage::ChannelInitialization(OpenChannel {
-                    chain_hash: get_chain_hash(),
+                    chain_hash: channel.chain_hash.unwrap_or_else(get_chain_hash),
                     channel_id: channel.get_id(),
                     funding_udt_type_script,
-                    funding_amount: channel.to_local_amount,
+                    funding_amount: channel.to_local_amount + channel.push_amount,
+                    tlc_dust_limit: channel.tlc_dust_limit,
+                    allowed_hash_algorithms: channel.allowed_hash_algorithms.clone(),
+                    features: channel.features,
                     shutdown_script,
                     reserved_ckb_amount: channel.local_reserved_ckb_amount,
                     funding_fee_rate,
                     commitment_fee_rate,
-                    commitment_delay_epoch: channel.commitment_delay_epoch,
+                    commitment_delay_epoch: channel.remote_delay_epoch,
                     max_tlc_value_in_flight: channel.local_constraints.max_tlc_value_in_flight,
                     max_tlc_number_in_flight: channel.local_constraints.max_tlc_number_in_flight,
                     channel_flags,
@@ -1996,37 +4278,49 @@ where
                     .store
                     .get_channel_actor_state(&channel_id)
                     .expect("channel should exist");
-                channel.reestablishing = true;
-
-                let reestablish_channel = ReestablishChannel {
-                    channel_id,
-                    local_commitment_number: channel.get_current_commitment_number(true),
-                    remote_commitment_number: channel.get_current_commitment_number(false),
-                };
-
-                let command = FiberMessageWithPeerId::new(
-                    self.get_remote_peer_id(),
-                    FiberMessage::reestablish_channel(reestablish_channel),
-                );
+                channel.state_transition_subscribers =
+                    Some(self.subscribers.state_transition_subscribers.clone());
+                channel.max_remote_commitment_points = self.config.max_remote_commitment_points;
+                channel.fee_estimator = self.config.fee_estimator.clone();
+                self.install_commitment_signer(&mut channel)?;
+                self.start_reestablishing(&myself, &mut channel);
+
+                // The shutdown timeout is a timer which is lost after a restart, start it again
+                // if the cooperative close is still not completed.
+                if let (ChannelState::ShuttingDown(flags), Some(shutdown_timeout)) =
+                    (channel.state, self.config.shutdown_timeout)
+                {
+                    if !flags.contains(ShuttingDownFlags::WAITING_COMMITMENT_CONFIRMATION) {
+                        myself.send_after(shutdown_timeout.timeout, || {
+                            ChannelActorMessage::Event(ChannelEvent::CheckShutdownTimeout)
+                        });
+                    }
+                }
 
-                self.network
-                    .send_message(NetworkActorMessage::new_command(
-                        NetworkActorCommand::SendFiberMessage(command),
-                    ))
-                    .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+                // Retryable removes are persisted with the channel state, but nothing else
+                // would retry them after a restart, so schedule a check here.
+                if !channel.tlc_state.get_pending_remove().is_empty() {
+                    myself.send_after(AUTO_SETDOWN_TLC_INTERVAL, || {
+                        ChannelActorMessage::Event(ChannelEvent::CheckTlcSetdown)
+                    });
+                }
 
-                // If the channel is already ready, we should notify the network actor.
-                // so that we update the network.outpoint_channel_map
-                if matches!(channel.state, ChannelState::ChannelReady()) {
-                    self.network
-                        .send_message(NetworkActorMessage::new_event(
-                            NetworkActorEvent::ChannelReady(
-                                channel.get_id(),
-                                channel.get_remote_peer_id(),
-                                channel.must_get_funding_transaction_outpoint(),
-                            ),
-                        ))
-                        .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+                // The checks of the held tlcs, the tlcs pending approval and the parts of the
+                // multi-part payments are timers which are lost after a restart, check them again
+                // so that they are cancelled before expiry.
+                self.sync_received_mpp_payments(&myself, &channel);
+                for tlc in channel.get_all_received_tlcs().filter(|tlc| {
+                    tlc.removed_at.is_none()
+                        && (self.store.is_invoice_held(&tlc.payment_hash)
+                            || channel.tlcs_pending_approval.contains(&u64::from(tlc.tlc_id))
+                            || channel.mpp_payments.contains_key(&tlc.payment_hash))
+                }) {
+                    let tlc_id = tlc.tlc_id.into();
+                    myself
+                        .send_message(ChannelActorMessage::Event(ChannelEvent::CheckHeldTlc(
+                            tlc_id,
+                        )))
+                        .expect("myself alive");
                 }
                 Ok(channel)
             }
@@ -2046,24 +4340,50 @@ where
             message,
         );
 
+        let drained_before = state.draining && state.get_drain_status().is_drained();
+        // Skip persisting the whole state again if the message can't change it,
+        // which is common on busy nodes polling the channels.
+        let state_dirty = !matches!(
+            &message,
+            ChannelActorMessage::Command(command) if command.is_read_only()
+        );
         match message {
             ChannelActorMessage::PeerMessage(message) => {
-                if let Err(error) = self.handle_peer_message(&myself, state, message).await {
-                    error!("Error while processing channel message: {:?}", error);
-                    #[cfg(debug_assertions)]
-                    self.network
-                        .clone()
-                        .send_message(NetworkActorMessage::new_notification(
-                            NetworkServiceEvent::DebugEvent(DebugEvent::Common(format!(
-                                "{:?}",
-                                error
-                            ))),
-                        ))
-                        .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+                let is_commitment_message = matches!(
+                    message,
+                    FiberChannelMessage::CommitmentSigned(_)
+                        | FiberChannelMessage::RevokeAndAck(_)
+                );
+                let is_revoke_and_ack = matches!(message, FiberChannelMessage::RevokeAndAck(_));
+                let result = self.handle_peer_message(&myself, state, message).await;
+                if is_revoke_and_ack {
+                    self.complete_commitment_resync(&result);
+                }
+                match result {
+                    Ok(()) if is_commitment_message => {
+                        self.consecutive_signing_failures.store(0, Ordering::SeqCst);
+                    }
+                    Ok(()) => {}
+                    Err(error) => {
+                        error!("Error while processing channel message: {:?}", error);
+                        #[cfg(debug_assertions)]
+                        self.network
+                            .clone()
+                            .send_message(NetworkActorMessage::new_notification(
+                                NetworkServiceEvent::DebugEvent(DebugEvent::Common(format!(
+                                    "{:?}",
+                                    error
+                                ))),
+                            ))
+                            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+                        if is_commitment_message && error.is_signing_failure() {
+                            self.handle_signing_failure(state, &error);
+                        }
+                    }
                 }
             }
             ChannelActorMessage::Command(command) => {
-                if let Err(err) = self.handle_command(state, command).await {
+                if let Err(err) = self.handle_command(&myself, state, command).await {
                     error!("Error while processing channel command: {:?}", err);
                 }
             }
@@ -2074,7 +4394,25 @@ where
             }
         }
 
-        self.store.insert_channel_actor_state(state.clone());
+        if state.draining && !drained_before && state.get_drain_status().is_drained() {
+            self.network
+                .send_message(NetworkActorMessage::new_notification(
+                    NetworkServiceEvent::ChannelDrained(state.get_id()),
+                ))
+                .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+        }
+
+        let channel_id = state.get_id();
+        for (local, commitment_number, settlement_data) in
+            std::mem::take(&mut state.pending_settlement_data)
+        {
+            self.store
+                .insert_settlement_data(channel_id, local, commitment_number, settlement_data);
+        }
+        if state_dirty {
+            self.sync_received_mpp_payments(&myself, state);
+            self.store.insert_channel_actor_state(state.clone());
+        }
         Ok(())
     }
 }
@@ -2178,6 +4516,154 @@ impl TlcKind {
     }
 }
 
+/// The parts of a multi-part payment received from a channel, which are settled together with the
+/// parts from the other channels once the total amount is received, or failed together if not
+/// completed before the earliest part expires.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct MppPayment {
+    // The total amount of the payment specified in the onion packets of all the parts.
+    pub total_amount: u128,
+    // The amounts of the received parts which are not removed yet, keyed by the tlc ids.
+    pub parts: HashMap<u64, u128>,
+    // The earliest expiry of the received parts.
+    pub expiry: u64,
+    // Whether all the parts from all the channels are received, the parts are settled once
+    // completed.
+    pub completed: bool,
+}
+
+impl MppPayment {
+    pub fn new(total_amount: u128) -> Self {
+        Self {
+            total_amount,
+            parts: HashMap::new(),
+            expiry: u64::MAX,
+            completed: false,
+        }
+    }
+
+    pub fn received_amount(&self) -> u128 {
+        self.parts.values().sum()
+    }
+
+    // Whether the parts should be failed as the payment can't be completed before the earliest
+    // part expires.
+    pub fn is_timed_out(&self, now: u64) -> bool {
+        !self.completed && now + MIN_TLC_EXPIRY_DELTA > self.expiry
+    }
+}
+
+/// Whether the received parts of a multi-part payment should be settled or failed.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MppPaymentStatus {
+    // Waiting for the other parts until the earliest part with the given expiry is going to expire.
+    Pending(u64),
+    Completed,
+    TimedOut,
+}
+
+/// The multi-part payments received from all the channels of the node, so that the parts received
+/// from different channels are accumulated together. Each channel actor keeps its parts in the
+/// persisted channel state and reports them here, and it's notified to settle or fail its parts
+/// once the payment is completed or timed out by the parts in another channel.
+#[derive(Default)]
+pub struct ReceivedMppPayments {
+    payments: HashMap<Hash256, ReceivedMppPayment>,
+}
+
+#[derive(Default)]
+struct ReceivedMppPayment {
+    // The parts received from each channel and the actor of the channel, keyed by the channel ids.
+    channels: HashMap<Hash256, (ActorRef<ChannelActorMessage>, MppPayment)>,
+    // Both flags are kept until all the parts are removed, so that a late part can't be added to
+    // a payment which is already settled or failed.
+    completed: bool,
+    timed_out: bool,
+}
+
+impl ReceivedMppPayments {
+    // Whether a part with the total amount can be added to the payment, which is either a new
+    // payment, or a pending payment with the same total amount.
+    pub fn can_add_part(&self, payment_hash: &Hash256, total_amount: u128) -> bool {
+        self.payments.get(payment_hash).map_or(true, |payment| {
+            !payment.completed
+                && !payment.timed_out
+                && payment
+                    .channels
+                    .values()
+                    .all(|(_, parts)| parts.total_amount == total_amount)
+        })
+    }
+
+    // Whether some parts of the payment are received and the payment is not settled or failed yet.
+    pub fn is_pending(&self, payment_hash: &Hash256) -> bool {
+        self.payments
+            .get(payment_hash)
+            .is_some_and(|payment| !payment.completed && !payment.timed_out)
+    }
+
+    // Replace the parts received from the channel with the parts in its state.
+    pub fn sync_channel(
+        &mut self,
+        channel_id: Hash256,
+        channel: &ActorRef<ChannelActorMessage>,
+        mpp_payments: &HashMap<Hash256, MppPayment>,
+    ) {
+        self.payments.retain(|payment_hash, payment| {
+            if !mpp_payments.contains_key(payment_hash) {
+                payment.channels.remove(&channel_id);
+            }
+            !payment.channels.is_empty()
+        });
+        for (payment_hash, parts) in mpp_payments {
+            let payment = self.payments.entry(*payment_hash).or_default();
+            payment.completed |= parts.completed;
+            payment
+                .channels
+                .insert(channel_id, (channel.clone(), parts.clone()));
+        }
+    }
+
+    // Check whether the payment is completed or timed out by all the parts received so far. The
+    // actors of all the channels holding the parts are notified to settle or fail their parts
+    // together once the status is decided.
+    pub fn poll(&mut self, payment_hash: &Hash256, now: u64) -> MppPaymentStatus {
+        let Some(payment) = self.payments.get_mut(payment_hash) else {
+            return MppPaymentStatus::TimedOut;
+        };
+        if !payment.completed && !payment.timed_out {
+            let (received_amount, total_amount, expiry) = payment.channels.values().fold(
+                (0u128, 0u128, u64::MAX),
+                |(received_amount, _, expiry), (_, parts)| {
+                    (
+                        received_amount.saturating_add(parts.received_amount()),
+                        parts.total_amount,
+                        expiry.min(parts.expiry),
+                    )
+                },
+            );
+            if received_amount >= total_amount {
+                payment.completed = true;
+            } else if now + MIN_TLC_EXPIRY_DELTA > expiry {
+                payment.timed_out = true;
+            } else {
+                return MppPaymentStatus::Pending(expiry);
+            }
+            for (channel, _) in payment.channels.values() {
+                // The channel actor may be stopped, its parts are checked once it's restarted.
+                let _ = channel.send_message(ChannelActorMessage::Event(
+                    ChannelEvent::CheckMppPayment(*payment_hash),
+                ));
+            }
+        }
+        if payment.completed {
+            MppPaymentStatus::Completed
+        } else {
+            MppPaymentStatus::TimedOut
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct AddTlcInfo {
     pub channel_id: Hash256,
@@ -2204,6 +4690,15 @@ pub struct AddTlcInfo {
     ///                ^^^^                 ^^^^
     ///
     pub previous_tlc: Option<(Hash256, TLCId)>,
+
+    /// The custom records attached by the payment sender, only set for the tlcs received by the final hop.
+    #[serde(default)]
+    pub custom_records: Vec<(u64, Vec<u8>)>,
+
+    /// The next hop peeled from the onion packet, only set for the received tlcs forwarded by us,
+    /// so that the forwarding results can be attributed to the neighbors.
+    #[serde(default)]
+    pub next_hop: Option<Pubkey>,
 }
 
 impl AddTlcInfo {
@@ -2211,6 +4706,14 @@ impl AddTlcInfo {
         self.tlc_id.is_offered()
     }
 
+    // Copy the tlc without the onion packet, which is only needed to forward the tlc.
+    pub fn without_onion_packet(&self) -> Self {
+        AddTlcInfo {
+            onion_packet: None,
+            ..self.clone()
+        }
+    }
+
     pub fn is_received(&self) -> bool {
         !self.is_offered()
     }
@@ -2273,9 +4776,9 @@ impl TlcKind {
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct PendingTlcs {
-    tlcs: Vec<TlcKind>,
-    committed_index: usize,
-    next_tlc_id: u64,
+    pub(crate) tlcs: Vec<TlcKind>,
+    pub(crate) committed_index: usize,
+    pub(crate) next_tlc_id: u64,
 }
 
 impl PendingTlcs {
@@ -2358,38 +4861,181 @@ impl PendingTlcs {
         self.committed_index = self.tlcs.len();
     }
 
-    pub fn shrink_removed_tlc(&mut self) {
-        assert_eq!(self.committed_index, self.tlcs.len());
-        let new_committed_index = self
-            .get_committed_tlcs()
-            .iter()
-            .filter(|tlc| match tlc {
-                TlcKind::AddTlc(info) => info.removed_at.is_none(),
-                _ => true,
-            })
-            .count();
-        self.tlcs.retain(|tlc| match tlc {
-            TlcKind::AddTlc(info) => info.removed_at.is_none(),
-            _ => true,
-        });
-        self.committed_index = new_committed_index;
+    pub fn shrink_removed_tlc(&mut self) {
+        assert_eq!(self.committed_index, self.tlcs.len());
+        let new_committed_index = self
+            .get_committed_tlcs()
+            .iter()
+            .filter(|tlc| match tlc {
+                TlcKind::AddTlc(info) => info.removed_at.is_none(),
+                _ => true,
+            })
+            .count();
+        self.tlcs.retain(|tlc| match tlc {
+            TlcKind::AddTlc(info) => info.removed_at.is_none(),
+            _ => true,
+        });
+        self.committed_index = new_committed_index;
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub enum RetryableRemoveTlc {
+    RemoveTlc(TLCId, RemoveTlcReason),
+    RelayRemoveTlc(Hash256, u64, RemoveTlcReason),
+}
+
+impl RetryableRemoveTlc {
+    pub fn is_fulfill(&self) -> bool {
+        matches!(
+            self,
+            RetryableRemoveTlc::RemoveTlc(_, RemoveTlcReason::RemoveTlcFulfill(_))
+                | RetryableRemoveTlc::RelayRemoveTlc(_, _, RemoveTlcReason::RemoveTlcFulfill(_))
+        )
+    }
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct TlcState {
+    pub(crate) local_pending_tlcs: PendingTlcs,
+    pub(crate) remote_pending_tlcs: PendingTlcs,
+    // if the tlc is pending to be removed, the reason will be stored here
+    // this will only used for retrying remove TLC
+    pub(crate) retryable_remove_tlcs: Vec<RetryableRemoveTlc>,
+    // the number of failed attempts of the retryable removes above
+    #[serde(default)]
+    pub(crate) retryable_remove_attempts: Vec<(RetryableRemoveTlc, u64)>,
+    pub(crate) waiting_ack: bool,
+}
+
+/// The reason of a removed tlc in [`TlcStateDump`], the preimage of a fulfilled tlc is redacted.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RemoveTlcReasonDump {
+    RemoveTlcFulfill,
+    RemoveTlcFail(TlcErrPacket),
+}
+
+impl From<&RemoveTlcReason> for RemoveTlcReasonDump {
+    fn from(reason: &RemoveTlcReason) -> Self {
+        match reason {
+            RemoveTlcReason::RemoveTlcFulfill(_) => RemoveTlcReasonDump::RemoveTlcFulfill,
+            RemoveTlcReason::RemoveTlcFail(packet) => {
+                RemoveTlcReasonDump::RemoveTlcFail(packet.clone())
+            }
+        }
+    }
+}
+
+/// A pending tlc in [`TlcStateDump`], the onion packet, the shared secret, the custom records
+/// and the preimage are left out.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TlcDump {
+    AddTlc {
+        tlc_id: TLCId,
+        amount: u128,
+        payment_hash: Hash256,
+        expiry: u64,
+        created_at: CommitmentNumbers,
+        removed_at: Option<(CommitmentNumbers, RemoveTlcReasonDump)>,
+        has_payment_preimage: bool,
+        previous_tlc: Option<(Hash256, TLCId)>,
+    },
+    RemoveTlc {
+        tlc_id: TLCId,
+        reason: RemoveTlcReasonDump,
+    },
+}
+
+impl From<&TlcKind> for TlcDump {
+    fn from(tlc: &TlcKind) -> Self {
+        match tlc {
+            TlcKind::AddTlc(info) => TlcDump::AddTlc {
+                tlc_id: info.tlc_id,
+                amount: info.amount,
+                payment_hash: info.payment_hash,
+                expiry: info.expiry,
+                created_at: info.created_at,
+                removed_at: info
+                    .removed_at
+                    .as_ref()
+                    .map(|(commitment_numbers, reason)| (*commitment_numbers, reason.into())),
+                has_payment_preimage: info.payment_preimage.is_some(),
+                previous_tlc: info.previous_tlc,
+            },
+            TlcKind::RemoveTlc(info) => TlcDump::RemoveTlc {
+                tlc_id: info.tlc_id,
+                reason: (&info.reason).into(),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PendingTlcsDump {
+    pub tlcs: Vec<TlcDump>,
+    // The tlcs before this index are committed.
+    pub committed_index: usize,
+    pub next_tlc_id: u64,
+}
+
+impl From<&PendingTlcs> for PendingTlcsDump {
+    fn from(pending_tlcs: &PendingTlcs) -> Self {
+        Self {
+            tlcs: pending_tlcs.tlcs.iter().map(Into::into).collect(),
+            committed_index: pending_tlcs.committed_index,
+            next_tlc_id: pending_tlcs.next_tlc_id,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RetryableRemoveTlcDump {
+    RemoveTlc(TLCId, RemoveTlcReasonDump),
+    RelayRemoveTlc(Hash256, u64, RemoveTlcReasonDump),
+}
+
+impl From<&RetryableRemoveTlc> for RetryableRemoveTlcDump {
+    fn from(retryable_remove: &RetryableRemoveTlc) -> Self {
+        match retryable_remove {
+            RetryableRemoveTlc::RemoveTlc(tlc_id, reason) => {
+                RetryableRemoveTlcDump::RemoveTlc(*tlc_id, reason.into())
+            }
+            RetryableRemoveTlc::RelayRemoveTlc(channel_id, tlc_id, reason) => {
+                RetryableRemoveTlcDump::RelayRemoveTlc(*channel_id, *tlc_id, reason.into())
+            }
+        }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
-pub enum RetryableRemoveTlc {
-    RemoveTlc(TLCId, RemoveTlcReason),
-    RelayRemoveTlc(Hash256, u64, RemoveTlcReason),
+/// A dump of the [`TlcState`] of a channel for diagnostics, it's safe to share since the
+/// preimages and the secrets of the tlcs are redacted.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TlcStateDump {
+    pub local_pending_tlcs: PendingTlcsDump,
+    pub remote_pending_tlcs: PendingTlcsDump,
+    // The retryable removes with the number of failed attempts.
+    pub retryable_remove_tlcs: Vec<(RetryableRemoveTlcDump, u64)>,
+    pub waiting_ack: bool,
 }
 
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
-pub struct TlcState {
-    local_pending_tlcs: PendingTlcs,
-    remote_pending_tlcs: PendingTlcs,
-    // if the tlc is pending to be removed, the reason will be stored here
-    // this will only used for retrying remove TLC
-    retryable_remove_tlcs: Vec<RetryableRemoveTlc>,
-    waiting_ack: bool,
+impl TlcState {
+    pub fn dump(&self) -> TlcStateDump {
+        TlcStateDump {
+            local_pending_tlcs: (&self.local_pending_tlcs).into(),
+            remote_pending_tlcs: (&self.remote_pending_tlcs).into(),
+            retryable_remove_tlcs: self
+                .retryable_remove_tlcs
+                .iter()
+                .map(|retryable_remove| {
+                    (
+                        retryable_remove.into(),
+                        self.get_retryable_remove_attempts(retryable_remove),
+                    )
+                })
+                .collect(),
+            waiting_ack: self.waiting_ack,
+        }
+    }
 }
 
 impl TlcState {
@@ -2437,6 +5083,57 @@ impl TlcState {
     pub fn remove_pending_remove_tlc(&mut self, retryable_remove: &RetryableRemoveTlc) {
         self.retryable_remove_tlcs
             .retain(|remove| remove != retryable_remove);
+        self.retryable_remove_attempts
+            .retain(|(remove, _)| remove != retryable_remove);
+    }
+
+    pub fn get_retryable_remove_attempts(&self, retryable_remove: &RetryableRemoveTlc) -> u64 {
+        self.retryable_remove_attempts
+            .iter()
+            .find_map(|(remove, attempts)| (remove == retryable_remove).then_some(*attempts))
+            .unwrap_or_default()
+    }
+
+    // Increment the attempts of all the pending removes, and abandon the ones which have been
+    // retried `max_attempts` times. The fulfills are never abandoned, because dropping a relayed
+    // fulfill loses the preimage the upstream channel needs to claim the funds we already paid.
+    pub fn abandon_exhausted_retryable_removes(
+        &mut self,
+        max_attempts: u64,
+    ) -> Vec<RetryableRemoveTlc> {
+        let mut abandoned = vec![];
+        for retryable_remove in self.get_pending_remove() {
+            if retryable_remove.is_fulfill() {
+                continue;
+            }
+            let attempts = self.increment_retryable_remove_attempts(&retryable_remove);
+            if attempts >= max_attempts {
+                self.remove_pending_remove_tlc(&retryable_remove);
+                abandoned.push(retryable_remove);
+            }
+        }
+        abandoned
+    }
+
+    pub fn increment_retryable_remove_attempts(
+        &mut self,
+        retryable_remove: &RetryableRemoveTlc,
+    ) -> u64 {
+        match self
+            .retryable_remove_attempts
+            .iter_mut()
+            .find(|(remove, _)| remove == retryable_remove)
+        {
+            Some((_, attempts)) => {
+                *attempts += 1;
+                *attempts
+            }
+            None => {
+                self.retryable_remove_attempts
+                    .push((retryable_remove.clone(), 1));
+                1
+            }
+        }
     }
 
     pub fn get(&self, id: &TLCId) -> Option<&AddTlcInfo> {
@@ -2493,6 +5190,15 @@ impl TlcState {
         self.remote_pending_tlcs.push(tlc_info);
     }
 
+    // Whether a RemoveTlc of the tlc is already added, it may be not applied yet.
+    pub fn has_remove_tlc(&self, tlc_id: &TLCId) -> bool {
+        self.local_pending_tlcs
+            .tlcs()
+            .iter()
+            .chain(self.remote_pending_tlcs.tlcs())
+            .any(|tlc| matches!(tlc, TlcKind::RemoveTlc(info) if info.tlc_id == *tlc_id))
+    }
+
     pub fn next_local_tlc_id(&self) -> u64 {
         self.local_pending_tlcs.next_tlc_id()
     }
@@ -2660,12 +5366,32 @@ impl TlcState {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct BalanceSnapshot {
+    // The commitment numbers at the time the balances changed.
+    pub commitment_numbers: CommitmentNumbers,
+    pub to_local_amount: u128,
+    pub to_remote_amount: u128,
+    // The timestamp in milliseconds when the snapshot was taken.
+    pub timestamp: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Default)]
 pub struct ChannelConstraints {
     // The maximum value can be in pending
     pub max_tlc_value_in_flight: u128,
     // The maximum number of tlcs that we can accept.
     pub max_tlc_number_in_flight: u64,
+    // The minimal balance to keep in the channel, offered tlcs can't make the balance lower than this.
+    // It's only set in our local constraints as it's not negotiated with the peer, so the received
+    // tlcs are never checked against a reserve of the peer.
+    #[serde(default)]
+    pub channel_reserve_amount: u128,
+    // The maximum value in pending as the percentage of our balance, it's our local policy only
+    // set in our local constraints, and the more restrictive one of it and the absolute value is
+    // applied to our offered tlcs.
+    #[serde(default)]
+    pub max_tlc_value_in_flight_percentage: Option<u8>,
 }
 
 impl ChannelConstraints {
@@ -2673,6 +5399,19 @@ impl ChannelConstraints {
         Self {
             max_tlc_value_in_flight,
             max_tlc_number_in_flight,
+            channel_reserve_amount: DEFAULT_CHANNEL_RESERVE_AMOUNT,
+            max_tlc_value_in_flight_percentage: None,
+        }
+    }
+
+    // The effective maximum value in pending given the current balance of the tlc offerer,
+    // so that the limit scales with the balance as it shifts by payments.
+    pub fn get_max_tlc_value_in_flight(&self, balance: u128) -> u128 {
+        match self.max_tlc_value_in_flight_percentage {
+            Some(percentage) => self
+                .max_tlc_value_in_flight
+                .min(balance.saturating_mul(percentage as u128) / 100),
+            None => self.max_tlc_value_in_flight,
         }
     }
 
@@ -2684,6 +5423,119 @@ impl ChannelConstraints {
     }
 }
 
+// The number of tlcs still in flight while draining the channel.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+pub struct DrainStatus {
+    pub offered_tlcs_in_flight: u64,
+    pub received_tlcs_in_flight: u64,
+}
+
+impl DrainStatus {
+    pub fn is_drained(&self) -> bool {
+        self.offered_tlcs_in_flight == 0 && self.received_tlcs_in_flight == 0
+    }
+}
+
+// The usage of tlc limits in both directions, offered tlcs are limited by the local constraints,
+// received tlcs are limited by the remote constraints.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+pub struct TlcCapacityStatus {
+    pub offered_tlc_count: u64,
+    pub offered_remaining_slots: u64,
+    pub offered_remaining_value_in_flight: u128,
+    pub received_tlc_count: u64,
+    pub received_remaining_slots: u64,
+    pub received_remaining_value_in_flight: u128,
+}
+
+// The largest amounts of a single tlc which can be offered or received right now.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+pub struct MaxTlcAmounts {
+    pub max_sendable_amount: u128,
+    pub max_receivable_amount: u128,
+}
+
+// Where the capacity locked in the channel goes, the reserved ckb amounts are not routable,
+// the commitment transaction fee is paid from them when the channel is force closed.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+pub struct CapacityBreakdown {
+    // The total ckb capacity locked in the funding cell.
+    pub total: u64,
+    // The capacity used for routing, which is the udt amount for udt channels.
+    pub liquid: u128,
+    pub local_reserved: u64,
+    pub remote_reserved: u64,
+    // The fee of the commitment transaction at the current commitment fee rate.
+    pub commitment_fee: u64,
+    // The udt amount locked in the funding cell, `None` for ckb channels. The ckb capacity of
+    // an udt channel is the reserved amounts only.
+    pub udt_amount: Option<u128>,
+}
+
+// The musig2 setup of the 2-of-2 funding lock, to verify it against the on-chain funding output.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct Musig2Info {
+    // The x-only aggregated pubkey of the funding pubkeys of both parties.
+    pub x_only_aggregated_pubkey: [u8; 32],
+    #[serde_as(as = "EntityHex")]
+    pub funding_lock_script: Script,
+    // Whether our funding pubkey goes first in the key aggregation.
+    pub local_goes_first: bool,
+}
+
+// The commitment numbers of both parties and the commitment point we send next, to compare with
+// the ones expected by the peer when reestablishing the channel fails.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct CommitmentPointInfo {
+    pub local_commitment_number: u64,
+    pub remote_commitment_number: u64,
+    pub next_commitment_point: Pubkey,
+}
+
+// A standardized summary of the channel, the summaries of the channels with the same peer
+// can be collected into a per-peer view, e.g. to sum up the balances.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ChannelSummary {
+    pub channel_id: Hash256,
+    pub remote_pubkey: Pubkey,
+    pub state: ChannelState,
+    pub to_local_amount: u128,
+    pub to_remote_amount: u128,
+    pub is_public: bool,
+    // Whether the channel is enabled for forwarding, private channels are always enabled.
+    pub enabled: bool,
+    #[serde_as(as = "Option<EntityHex>")]
+    pub funding_outpoint: Option<OutPoint>,
+    #[serde_as(as = "Option<EntityHex>")]
+    pub funding_udt_type_script: Option<Script>,
+}
+
+// The final balances of a closed channel persisted for accounting, which are kept even if
+// the channel state is pruned.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ClosedChannelRecord {
+    pub channel_id: Hash256,
+    pub remote_pubkey: Pubkey,
+    pub to_local_amount: u128,
+    pub to_remote_amount: u128,
+    // Whether the channel is closed cooperatively or uncooperatively.
+    pub close_flags: CloseFlags,
+    pub closing_tx_hash: Hash256,
+    // The timestamp in milliseconds when the closing transaction is confirmed.
+    pub closed_at: u64,
+}
+
+// The forwarding policy of our side currently in effect for a public channel,
+// it should match the one broadcasted in our ChannelUpdate.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ForwardingPolicy {
+    pub tlc_min_value: u128,
+    pub tlc_expiry_delta: u64,
+    pub tlc_fee_proportional_millionths: u128,
+}
+
 #[serde_as]
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct RevocationData {
@@ -2713,6 +5565,96 @@ pub struct SettlementData {
     pub to_remote_output_data: Bytes,
 }
 
+/// The source of the current time used by the channel, e.g. for checking tlc expiry.
+/// A fixed clock can be injected so that the tlc expiry checks are deterministic in tests.
+pub trait Clock: Send + Sync {
+    /// The milliseconds since unix epoch.
+    fn now_millis(&self) -> u64;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        now_timestamp_as_millis_u64()
+    }
+}
+
+/// A clock which always returns the time set by `set`, useful for reproducible tests and fuzzing.
+#[derive(Debug, Default)]
+pub struct FixedClock(AtomicU64);
+
+impl FixedClock {
+    pub fn new(now_millis: u64) -> Self {
+        Self(AtomicU64::new(now_millis))
+    }
+
+    pub fn set(&self, now_millis: u64) {
+        self.0.store(now_millis, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, millis: u64) {
+        self.0.fetch_add(millis, Ordering::SeqCst);
+    }
+}
+
+impl Clock for FixedClock {
+    fn now_millis(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+pub(crate) fn default_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+/// The estimator of the fee rate to get a transaction confirmed within the target blocks,
+/// e.g. for the shutdown transaction closing the channel cooperatively.
+pub trait FeeEstimator: Send + Sync {
+    /// The estimated fee rate in shannons per kilo-bytes, `None` if it can't be estimated.
+    fn estimate_fee_rate(&self, target_blocks: u64) -> Option<FeeRate>;
+}
+
+/// A fee estimator multiplying the base fee rate by a static factor for the urgent targets.
+/// It never looks at the chain, so it's only a fallback used when no estimator is passed in
+/// `NetworkActorStartArguments`.
+#[derive(Clone, Copy, Debug)]
+pub struct StaticFeeEstimator {
+    pub base_fee_rate: u64,
+}
+
+impl Default for StaticFeeEstimator {
+    fn default() -> Self {
+        Self {
+            base_fee_rate: DEFAULT_FEE_RATE,
+        }
+    }
+}
+
+impl FeeEstimator for StaticFeeEstimator {
+    fn estimate_fee_rate(&self, target_blocks: u64) -> Option<FeeRate> {
+        let factor = match target_blocks {
+            0 => return None,
+            1..=2 => 3,
+            3..=6 => 2,
+            _ => 1,
+        };
+        Some(FeeRate::from_u64(self.base_fee_rate * factor))
+    }
+}
+
+pub(crate) fn default_fee_estimator() -> Arc<dyn FeeEstimator> {
+    Arc::new(StaticFeeEstimator::default())
+}
+
+// The capacity and the UDT amount (zero for CKB channels) contributed to the funding cell.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FundingContribution {
+    pub capacity: u64,
+    pub udt_amount: u128,
+}
+
 #[serde_as]
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ChannelActorState {
@@ -2753,6 +5695,10 @@ pub struct ChannelActorState {
     // The amount of CKB/UDT that the remote owns in the channel.
     // This value will only change after we have resolved a tlc.
     pub to_remote_amount: u128,
+    // The amount pushed from the opener to the acceptor at channel opening,
+    // it's funded by the opener but owned by the acceptor from the beginning.
+    #[serde(default)]
+    pub push_amount: u128,
 
     // these two amounts used to keep the minimal ckb amount for the two parties
     // TLC operations will not affect these two amounts, only used to keep the commitment transactions
@@ -2765,9 +5711,12 @@ pub struct ChannelActorState {
     // The side who want to submit the commitment transaction will pay fee
     pub commitment_fee_rate: u64,
 
-    // The delay time for the commitment transaction, this value is set by the initiator of the channel.
-    // It must be a relative EpochNumberWithFraction in u64 format.
-    pub commitment_delay_epoch: u64,
+    // The delay time for our commitment transaction, this value is set by the peer in the
+    // `OpenChannel`/`AcceptChannel` message, as the delay protects the peer from our revoked
+    // commitment transactions. It must be a relative EpochNumberWithFraction in u64 format.
+    pub local_delay_epoch: u64,
+    // The delay time for the commitment transaction of the peer, this value is set by us.
+    pub remote_delay_epoch: u64,
 
     // The fee rate used for funding transaction, the initiator may set it as `funding_fee_rate` option,
     // if it's not set, DEFAULT_FEE_RATE will be used as default value, two sides will use the same fee rate
@@ -2818,6 +5767,10 @@ pub struct ChannelActorState {
     // We need to save all these points to derive the keys for the commitment transactions.
     // The length of this vector is at most the maximum number of flighting tlcs.
     pub remote_commitment_points: Vec<(u64, Pubkey)>,
+    // The secrets of the revoked commitments revealed by the counterparty in RevokeAndAck,
+    // they are pruned together with the corresponding points in `remote_commitment_points`.
+    #[serde_as(as = "Vec<(U64Hex, SliceHex)>")]
+    pub remote_commitment_secrets: Vec<(u64, [u8; 32])>,
     pub remote_channel_public_keys: Option<ChannelBasePublicKeys>,
 
     // The shutdown info for both local and remote, they are setup by the shutdown command or message.
@@ -2828,6 +5781,123 @@ pub struct ChannelActorState {
     pub reestablishing: bool,
 
     pub created_at: SystemTime,
+
+    // The history of balance changes, at most MAX_BALANCE_HISTORY_LENGTH entries are kept.
+    pub balance_history: VecDeque<BalanceSnapshot>,
+
+    // Whether the channel is draining for maintenance, forwarding tlcs are rejected while draining.
+    pub draining: bool,
+
+    // The hashes of the broadcasted force close (commitment) transactions which are not confirmed
+    // yet, including the child transactions spending the anchor outputs to bump their fee.
+    pub pending_force_close_tx_hashes: Vec<Hash256>,
+
+    // The settlement data received in the last RevokeAndAck message, only used for inspection.
+    pub latest_settlement_data: Option<SettlementData>,
+
+    // Whether the commitment transactions have an anchor output for each party to bump the fee,
+    // it's negotiated by the ANCHOR_OUTPUTS channel flag while opening the channel.
+    pub anchor_outputs: bool,
+
+    // The tlcs with amount below this limit are trimmed from the commitment transactions,
+    // it's proposed by the opener so that both parties trim the same tlcs.
+    pub tlc_dust_limit: u128,
+
+    // The hash algorithms allowed by both parties for the tlcs of the channel,
+    // empty for the channels opened before the negotiation, which allow all algorithms.
+    #[serde(default)]
+    pub allowed_hash_algorithms: Vec<HashAlgorithm>,
+
+    // The partial signatures of the last sent CommitmentSigned message, to avoid re-signing
+    // the same commitment transaction when resending it.
+    #[serde(default)]
+    pub commitment_signed_cache: Option<CommitmentSignedCache>,
+
+    // The commitment fee rate we proposed in an UpdateFee message, which is applied after the
+    // peer acks it.
+    #[serde(default)]
+    pub pending_commitment_fee_rate: Option<u64>,
+
+    // The hash of the chain in the OpenChannel message creating the channel, which is checked
+    // against our chain by the acceptor, as AcceptChannel doesn't carry the chain hash. The
+    // messages of the channel are rejected if the node is running on another chain. `None` for
+    // the channels created before the chain hash is recorded.
+    #[serde(default)]
+    pub chain_hash: Option<Hash256>,
+
+    // The fees earned by forwarding payments through this channel as the outgoing channel
+    // since the channel is opened, unlike the channel metrics, it survives restarts.
+    #[serde(default)]
+    pub total_fees_earned: u128,
+
+    // The multi-part payments whose parts are being received, keyed by the payment hashes.
+    #[serde(default)]
+    pub mpp_payments: HashMap<Hash256, MppPayment>,
+
+    // Whether the outputs of the settlement transactions are sorted by the lock scripts and the
+    // amounts, it's negotiated by the SORTED_SETTLEMENT_OUTPUTS channel flag while opening the
+    // channel. Both parties must sort them identically, otherwise the signatures are invalid.
+    #[serde(default)]
+    pub sorted_settlement_outputs: bool,
+
+    // The violation of the invariant that the total of the balances equals the funded amount,
+    // no more commitments are signed once it's set.
+    #[serde(default)]
+    pub balance_invariant_violation: Option<String>,
+
+    // The optional features supported by both parties, negotiated while opening the channel.
+    // The channels opened before the negotiation only support the features they already had.
+    #[serde(default = "FeatureBits::before_negotiation")]
+    pub features: FeatureBits,
+
+    // The ids of the received tlcs exceeding the rate limit of the peer, which are failed without
+    // peeling the onion packets once committed. They are persisted so that a restart before the
+    // tlcs are committed doesn't let them bypass the rate limit.
+    pub rate_limited_tlcs: HashSet<u64>,
+
+    // The ids of the received tlcs without invoices which are held until approved by ApproveTlc.
+    pub tlcs_pending_approval: HashSet<u64>,
+
+    // The clock used to get the current time, it's not persisted and defaults to the system clock.
+    #[serde(skip, default = "default_clock")]
+    pub clock: Arc<dyn Clock>,
+
+    // The estimator of the shutdown fee rate from the target blocks, it's not persisted and
+    // set from the channel actor config.
+    #[serde(skip, default = "default_fee_estimator")]
+    pub fee_estimator: Arc<dyn FeeEstimator>,
+
+    // The subscribers to notify on state transitions, it's not persisted and set by the channel actor.
+    #[serde(skip)]
+    pub state_transition_subscribers:
+        Option<Arc<OutputPort<(Hash256, ChannelState, ChannelState)>>>,
+
+    // The signer of the musig2 partial signatures, it's not persisted and
+    // defaults to the in-memory `signer` above, which is only created once.
+    #[serde(skip)]
+    pub commitment_signer: OnceLock<Arc<dyn CommitmentSigner>>,
+
+    // The time the peer disconnected, it's set while waiting for the peer to reconnect
+    // within the disconnect grace period, and it's not persisted.
+    #[serde(skip)]
+    pub peer_disconnected_at: Option<u64>,
+
+    // The maximal number of commitment points from the peer retained in `remote_commitment_points`,
+    // it's not persisted and set by the channel actor, `None` if bounded by the max tlc number in flight.
+    #[serde(skip)]
+    pub max_remote_commitment_points: Option<usize>,
+
+    // The settlement data of the commitments signed while handling the current message, as
+    // `(local, commitment_number, settlement_data)`. It's not persisted with the state, the
+    // channel actor moves them to the store, which keeps the history under its own key prefix.
+    #[serde(skip)]
+    pub pending_settlement_data: Vec<(bool, u64, SettlementData)>,
+
+    // The contribution of the peer to the funding cell, attributed from the increases of the
+    // funding cell in its TxUpdate messages. It's not persisted since the funding transaction
+    // collaboration is not resumed after a restart.
+    #[serde(skip)]
+    pub remote_funding_contribution: FundingContribution,
 }
 
 #[serde_as]
@@ -2839,6 +5909,18 @@ pub struct ShutdownInfo {
     pub signature: Option<PartialSignature>,
 }
 
+// The partial signatures sent in the last CommitmentSigned message, they are reused when
+// the message is resent for the same remote commitment transaction, e.g. while reestablishing.
+#[derive(Clone, Serialize, Deserialize, Eq, PartialEq, Debug)]
+pub struct CommitmentSignedCache {
+    pub commitment_number: u64,
+    // The hash of the signed commitment and settlement transactions and the remote nonce,
+    // which changes whenever the tlcs or the balances of the commitment transaction change.
+    pub fingerprint: [u8; 32],
+    pub funding_tx_partial_signature: PartialSignature,
+    pub commitment_tx_partial_signature: PartialSignature,
+}
+
 // This struct holds the channel information that are only relevant when the channel
 // is public. The information includes signatures to the channel announcement message,
 // our config for the channel that will be published to the network (via ChannelUpdate).
@@ -2862,6 +5944,13 @@ pub struct PublicChannelInfo {
     /// The minimal tcl value we can receive in relay tlc
     pub tlc_min_value: u128,
 
+    // The bounds of the tlc expiry delta for this channel, in milliseconds.
+    // The global `MIN_TLC_EXPIRY_DELTA` and `MAX_PAYMENT_TLC_EXPIRY_LIMIT` are used if not set.
+    // The received tlcs expiring beyond the maximal delta are failed with `ExpiryTooFar`, so that
+    // the liquidity of the channel is not locked for too long.
+    pub min_tlc_expiry_delta: Option<u64>,
+    pub max_tlc_expiry_delta: Option<u64>,
+
     // Channel announcement signatures, may be empty for private channel.
     pub local_channel_announcement_signature: Option<(EcdsaSignature, PartialSignature)>,
     pub remote_channel_announcement_signature: Option<(EcdsaSignature, PartialSignature)>,
@@ -2895,10 +5984,30 @@ pub struct ClosedChannel {}
 #[derive(Debug)]
 pub enum ChannelEvent {
     PeerDisconnected,
+    PeerReconnected,
+    // The peer disconnect grace period started at the given time elapsed.
+    PeerDisconnectGraceElapsed(u64),
     FundingTransactionConfirmed(BlockNumber, u32),
-    CommitmentTransactionConfirmed,
-    ClosingTransactionConfirmed,
+    CommitmentTransactionConfirmed(Hash256),
+    // The child transaction spending our anchor output to bump the force close fee is built.
+    ForceCloseFeeBumped(Transaction),
+    ClosingTransactionConfirmed(Hash256),
     CheckTlcSetdown,
+    CheckHeldTlc(u64),
+    CheckFundingTimeout,
+    // Send the ReestablishChannel message of the delayed attempt with the given sequence number.
+    SendReestablishChannel(u64),
+    // The reestablish attempt with the given sequence number timed out if the channel is still
+    // reestablishing.
+    CheckReestablishTimeout(u64),
+    // The cooperative close started by us timed out if the channel is still shutting down.
+    CheckShutdownTimeout,
+    // The multi-part payment with the given payment hash is completed or timed out, settle or
+    // fail the parts received from this channel.
+    CheckMppPayment(Hash256),
+    // The ResyncCommitment command resyncing the remote commitment with the given number timed
+    // out if the peer still hasn't revoked it.
+    CheckCommitmentResyncTimeout(u64),
 }
 
 pub type ProcessingChannelResult = Result<(), ProcessingChannelError>;
@@ -2951,6 +6060,20 @@ pub enum ProcessingChannelError {
     TlcExpiryTooFar,
     #[error("Tlc forwarding error")]
     TlcForwardingError(TlcErr),
+    #[error("The payment_hash was already settled by a forwarded tlc")]
+    ForwardingSettledPaymentHash,
+    #[error("The revealed secret does not match the commitment point of commitment number {0}")]
+    InvalidCommitmentSecret(u64),
+    #[error("The tlc hash algorithm {0:?} is not allowed in this channel")]
+    TlcHashAlgorithmNotAllowed(HashAlgorithm),
+    #[error("The tlc exceeds the rate limit of the peer")]
+    TlcRateLimited,
+    #[error("Forwarding tlcs is paused in this channel")]
+    ForwardingPaused,
+    #[error("Forwarding the tlc is denied by the forward policy with {0:?}")]
+    ForwardingDenied(TlcErrorCode),
+    #[error("The tlc is a probe which is always failed by the final hop")]
+    FinalProbeTlc,
 }
 
 /// ProcessingChannelError which brings the shared secret used in forwarding onion packet.
@@ -2979,6 +6102,77 @@ impl ProcessingChannelError {
     pub fn without_shared_secret(self) -> ProcessingChannelErrorWithSharedSecret {
         self.with_shared_secret(NO_SHARED_SECRET.clone())
     }
+
+    // Whether the error is caused by the signatures or the commitment secrets from the peer.
+    pub fn is_signing_failure(&self) -> bool {
+        matches!(
+            self,
+            ProcessingChannelError::Musig2VerifyError(_)
+                | ProcessingChannelError::Musig2SigningError(_)
+                | ProcessingChannelError::InvalidCommitmentSecret(_)
+        )
+    }
+
+    // A stable numeric code for each kind of error, so that the callers can
+    // distinguish the errors without matching the error messages.
+    pub fn code(&self) -> u32 {
+        match self {
+            ProcessingChannelError::InvalidState(_) => 1,
+            ProcessingChannelError::RepeatedProcessing(_) => 2,
+            ProcessingChannelError::InvalidParameter(_) => 3,
+            ProcessingChannelError::CapacityError(_) => 4,
+            ProcessingChannelError::SpawnErr(_) => 5,
+            ProcessingChannelError::Musig2VerifyError(_) => 6,
+            ProcessingChannelError::Musig2SigningError(_) => 7,
+            ProcessingChannelError::WaitingTlcAck => 8,
+            ProcessingChannelError::PeelingOnionPacketError(_) => 9,
+            ProcessingChannelError::IncorrectTlcExpiry => 10,
+            ProcessingChannelError::IncorrectFinalTlcExpiry => 11,
+            ProcessingChannelError::FinalIncorrectHTLCAmount => 12,
+            ProcessingChannelError::FinalIncorrectPaymentHash => 13,
+            ProcessingChannelError::FinalIncorrectPreimage => 14,
+            ProcessingChannelError::TlcForwardFeeIsTooLow => 15,
+            ProcessingChannelError::FinalInvoiceInvalid(_) => 16,
+            ProcessingChannelError::TlcNumberExceedLimit => 17,
+            ProcessingChannelError::TlcValueInflightExceedLimit => 18,
+            ProcessingChannelError::TlcAmountIsTooLow => 19,
+            ProcessingChannelError::TlcAmountExceedLimit => 20,
+            ProcessingChannelError::TlcExpirySoon => 21,
+            ProcessingChannelError::TlcExpiryTooFar => 22,
+            ProcessingChannelError::TlcForwardingError(_) => 23,
+            ProcessingChannelError::ForwardingSettledPaymentHash => 24,
+            ProcessingChannelError::InvalidCommitmentSecret(_) => 25,
+            ProcessingChannelError::TlcHashAlgorithmNotAllowed(_) => 26,
+            ProcessingChannelError::TlcRateLimited => 27,
+            ProcessingChannelError::ForwardingPaused => 28,
+            ProcessingChannelError::ForwardingDenied(_) => 29,
+            ProcessingChannelError::FinalProbeTlc => 30,
+        }
+    }
+}
+
+/// The error replied to the callers of channel commands, which carries both
+/// the error code from `ProcessingChannelError::code` and the error message.
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[error("{message}")]
+pub struct ChannelCommandError {
+    pub code: u32,
+    pub message: String,
+}
+
+impl From<&ProcessingChannelError> for ChannelCommandError {
+    fn from(err: &ProcessingChannelError) -> Self {
+        Self {
+            code: err.code(),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<ProcessingChannelError> for ChannelCommandError {
+    fn from(err: ProcessingChannelError) -> Self {
+        (&err).into()
+    }
 }
 
 bitflags! {
@@ -2986,6 +6180,19 @@ bitflags! {
     #[serde(transparent)]
     pub struct ChannelFlags: u8 {
         const PUBLIC = 1;
+        const ANCHOR_OUTPUTS = 1 << 1;
+        const SORTED_SETTLEMENT_OUTPUTS = 1 << 2;
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(transparent)]
+    pub struct FeatureBits: u64 {
+        const ANCHOR_OUTPUTS = 1;
+        const SORTED_SETTLEMENT_OUTPUTS = 1 << 1;
+        const MULTI_PART_PAYMENT = 1 << 2;
+        const KEYSEND = 1 << 3;
+        // The bit 1 << 4 is reserved for splicing.
+        const ASYMMETRIC_DELAY_EPOCHS = 1 << 5;
     }
 
     #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -3053,6 +6260,16 @@ bitflags! {
         const COOPERATIVE = 1;
         /// Indicates that channel is closed uncooperatively, initiated by one party forcely.
         const UNCOOPERATIVE = 1 << 1;
+        /// Indicates that channel funding is aborted before the funding transaction is broadcast.
+        const ABANDONED = 1 << 2;
+    }
+}
+
+impl FeatureBits {
+    // The features of the channels opened before the negotiation, which accepted the keysend
+    // payments but had none of the other features.
+    pub fn before_negotiation() -> Self {
+        Self::KEYSEND
     }
 }
 
@@ -3133,8 +6350,10 @@ pub(crate) fn get_funding_and_reserved_amount(
     total_amount: u128,
     shutdown_script: &Script,
     udt_type_script: &Option<Script>,
+    anchor_outputs: bool,
 ) -> Result<(u128, u64), ProcessingChannelError> {
-    let reserved_capacity = reserved_capacity(shutdown_script, udt_type_script)?.as_u64();
+    let reserved_capacity =
+        reserved_capacity(shutdown_script, udt_type_script, anchor_outputs)?.as_u64();
     if udt_type_script.is_none() {
         if total_amount < reserved_capacity as u128 {
             return Err(ProcessingChannelError::InvalidParameter(format!(
@@ -3158,11 +6377,143 @@ pub(crate) fn get_funding_and_reserved_amount(
 pub(crate) fn reserved_capacity(
     shutdown_script: &Script,
     udt_type_script: &Option<Script>,
+    anchor_outputs: bool,
 ) -> Result<Capacity, CapacityError> {
-    occupied_capacity(shutdown_script, udt_type_script)?
-        .safe_add(Capacity::shannons(DEFAULT_MIN_SHUTDOWN_FEE))
+    let capacity = occupied_capacity(shutdown_script, udt_type_script)?
+        .safe_add(Capacity::shannons(DEFAULT_MIN_SHUTDOWN_FEE))?;
+    if anchor_outputs {
+        capacity.safe_add(anchor_capacity(shutdown_script)?)
+    } else {
+        Ok(capacity)
+    }
+}
+
+// The anchor output is a plain cell locked by the shutdown script of its owner, the owner
+// can spend it in a child transaction to bump the fee of a stuck commitment transaction.
+pub(crate) fn anchor_capacity(shutdown_script: &Script) -> Result<Capacity, CapacityError> {
+    occupied_capacity(shutdown_script, &None)
+}
+
+// The key to sort the settlement outputs deterministically, the lock script goes first, then the
+// CKB amount and the UDT amount in the output data.
+pub(crate) fn settlement_output_sort_key(
+    output: &CellOutput,
+    output_data: &Bytes,
+) -> (Vec<u8>, u64, Vec<u8>) {
+    let capacity: u64 = output.capacity().unpack();
+    (
+        output.lock().as_slice().to_vec(),
+        capacity,
+        output_data.raw_data().to_vec(),
+    )
+}
+
+pub(crate) fn check_commitment_delay_epoch(
+    commitment_delay_epoch: u64,
+) -> Result<(), ProcessingChannelError> {
+    let epoch = EpochNumberWithFraction::from_full_value_unchecked(commitment_delay_epoch);
+    if !epoch.is_well_formed() {
+        return Err(ProcessingChannelError::InvalidParameter(format!(
+            "Commitment delay epoch {} is not a valid value",
+            commitment_delay_epoch,
+        )));
+    }
+
+    let min = EpochNumberWithFraction::new(MIN_COMMITMENT_DELAY_EPOCHS, 0, 1);
+    if epoch < min {
+        return Err(ProcessingChannelError::InvalidParameter(format!(
+            "Commitment delay epoch {} is less than the minimal value {}",
+            epoch, min
+        )));
+    }
+
+    let max = EpochNumberWithFraction::new(MAX_COMMITMENT_DELAY_EPOCHS, 0, 1);
+    if epoch > max {
+        return Err(ProcessingChannelError::InvalidParameter(format!(
+            "Commitment delay epoch {} is greater than the maximal value {}",
+            epoch, max
+        )));
+    }
+    Ok(())
+}
+
+// The hash algorithms allowed by both parties, an empty remote list means the peer doesn't
+// restrict the hash algorithms, e.g. the peer doesn't support the negotiation yet.
+pub(crate) fn negotiate_hash_algorithms(
+    local: &[HashAlgorithm],
+    remote: &[HashAlgorithm],
+) -> Result<Vec<HashAlgorithm>, ProcessingChannelError> {
+    if remote.is_empty() {
+        return Ok(local.to_vec());
+    }
+    let hash_algorithms: Vec<_> = local
+        .iter()
+        .filter(|algorithm| remote.contains(algorithm))
+        .copied()
+        .collect();
+    if hash_algorithms.is_empty() {
+        return Err(ProcessingChannelError::InvalidParameter(format!(
+            "No hash algorithm is allowed by both parties, local: {:?}, remote: {:?}",
+            local, remote
+        )));
+    }
+    Ok(hash_algorithms)
+}
+
+// Check the udt funding the channel against the allowed udt type scripts, `None` means all udts
+// are allowed, and an empty list means only native ckb channels are allowed.
+pub(crate) fn check_funding_udt_type_script(
+    allowed_udt_scripts: Option<&[Script]>,
+    funding_udt_type_script: &Option<Script>,
+) -> Result<(), ProcessingChannelError> {
+    match (allowed_udt_scripts, funding_udt_type_script) {
+        (Some(allowed_udt_scripts), Some(udt_type_script))
+            if !allowed_udt_scripts.contains(udt_type_script) =>
+        {
+            Err(ProcessingChannelError::InvalidParameter(format!(
+                "Funding udt type script {:?} is not allowed",
+                udt_type_script
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Emit the preimage of a message signed for the commitments as hex, so that the construction
+// of the messages can be compared with other implementations byte by byte. The message must be
+// the hash of the parts, which is checked by running the tests with the feature enabled.
+#[cfg(feature = "signing-test-vector")]
+fn trace_signing_message(name: &str, parts: &[(&str, &[u8])], message: &[u8; 32]) {
+    let preimage = parts
+        .iter()
+        .flat_map(|(_, bytes)| bytes.iter().copied())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        &blake2b_256(preimage),
+        message,
+        "the traced parts of {} are not the preimage of the signed message",
+        name
+    );
+    for (part, bytes) in parts {
+        trace!(
+            target: "fnn::fiber::channel::signing_test_vector",
+            "{} {}: {}",
+            name,
+            part,
+            hex::encode(bytes)
+        );
+    }
+    trace!(
+        target: "fnn::fiber::channel::signing_test_vector",
+        "{} message: {}",
+        name,
+        hex::encode(message)
+    );
 }
 
+#[cfg(not(feature = "signing-test-vector"))]
+fn trace_signing_message(_name: &str, _parts: &[(&str, &[u8])], _message: &[u8; 32]) {}
+
 pub(crate) fn occupied_capacity(
     shutdown_script: &Script,
     udt_type_script: &Option<Script>,
@@ -3185,7 +6536,7 @@ impl From<&ChannelActorState> for Musig2SignContext {
         Musig2SignContext {
             key_agg_ctx: value.get_musig2_agg_context(),
             agg_nonce: value.get_musig2_agg_pubnonce(),
-            seckey: value.signer.funding_key.clone(),
+            signer: value.get_commitment_signer(),
             secnonce: value.get_local_musig2_secnonce(),
         }
     }
@@ -3226,7 +6577,7 @@ impl From<(&ChannelActorState, bool)> for Musig2SignContext {
         Musig2SignContext {
             key_agg_ctx,
             agg_nonce,
-            seckey: channel.signer.funding_key.clone(),
+            signer: channel.get_commitment_signer(),
             secnonce: channel.get_local_musig2_secnonce(),
         }
     }
@@ -3269,6 +6620,38 @@ impl ChannelActorState {
         self.public_channel_info.is_some()
     }
 
+    // Whether the channel can forward tlcs now, i.e. it's a ready and enabled public channel,
+    // and it's neither reestablishing nor waiting for the ack of the peer.
+    pub fn is_ready_to_forward(&self) -> bool {
+        matches!(self.state, ChannelState::ChannelReady())
+            && self.get_our_enabled() == Some(true)
+            && !self.reestablishing
+            && !self.tlc_state.waiting_ack
+    }
+
+    // The first reason why the channel can't forward tlcs now, `None` if nothing blocks it.
+    // The checks are in the same order as `is_ready_to_forward`, plus the sendable liquidity.
+    pub fn forwarding_blocker(&self) -> Option<ForwardBlocker> {
+        if !matches!(self.state, ChannelState::ChannelReady()) {
+            return Some(ForwardBlocker::NotChannelReady);
+        }
+        match self.get_our_enabled() {
+            None => return Some(ForwardBlocker::NotPublic),
+            Some(false) => return Some(ForwardBlocker::Disabled),
+            Some(true) => {}
+        }
+        if self.reestablishing {
+            return Some(ForwardBlocker::Reestablishing);
+        }
+        if self.tlc_state.waiting_ack {
+            return Some(ForwardBlocker::WaitingAck);
+        }
+        if self.max_sendable_amount() == 0 {
+            return Some(ForwardBlocker::InsufficientLiquidity);
+        }
+        None
+    }
+
     pub async fn try_create_channel_messages(
         &mut self,
         network: &ActorRef<NetworkActorMessage>,
@@ -3280,45 +6663,81 @@ impl ChannelActorState {
         Some((channel_announcement, channel_update))
     }
 
-    pub async fn try_create_channel_announcement_message(
-        &mut self,
-        network: &ActorRef<NetworkActorMessage>,
-    ) -> Option<ChannelAnnouncement> {
-        if !self.is_public() {
-            debug!("Ignoring non-public channel announcement");
-            return None;
-        }
-
-        let mut channel_announcement = match self
+    // Get the channel announcement we have created, which may not be signed by the other party yet,
+    // or create a new unsigned one if we have not created it yet.
+    fn get_or_create_channel_announcement(&self) -> ChannelAnnouncement {
+        if let Some(channel_announcement) = self
             .public_channel_info
             .as_ref()
             .and_then(|state| state.channel_announcement.clone())
         {
-            // Skipping creating new signed channel announcement if it exists
-            Some(x) if x.is_signed() => return Some(x),
-            // We have created a channel announcement, but it's not signed by the other
-            // party yet. We should try to complete the signatures next.
-            Some(x) => x,
-            // We have not created a channel announcement yet.
-            None => {
-                let channel_outpoint = self.must_get_funding_transaction_outpoint();
-                let capacity = self.get_liquid_capacity();
-                let (node1_id, node2_id) = if self.local_is_node1() {
-                    (self.local_pubkey, self.remote_pubkey)
-                } else {
-                    (self.remote_pubkey, self.local_pubkey)
-                };
-                let channel_announcement = ChannelAnnouncement::new_unsigned(
-                    &node1_id,
-                    &node2_id,
-                    channel_outpoint,
-                    &self.get_funding_lock_script_xonly_key(),
-                    capacity,
-                    self.funding_udt_type_script.clone(),
-                );
-                channel_announcement
-            }
+            return channel_announcement;
+        }
+        let channel_outpoint = self.must_get_funding_transaction_outpoint();
+        let capacity = self.get_liquid_capacity();
+        let (node1_id, node2_id) = if self.local_is_node1() {
+            (self.local_pubkey, self.remote_pubkey)
+        } else {
+            (self.remote_pubkey, self.local_pubkey)
+        };
+        ChannelAnnouncement::new_unsigned(
+            &node1_id,
+            &node2_id,
+            channel_outpoint,
+            &self.get_funding_lock_script_xonly_key(),
+            capacity,
+            self.funding_udt_type_script.clone(),
+        )
+    }
+
+    /// Verify the signatures of the channel announcement from the peer before saving them, so that
+    /// invalid signatures are rejected early instead of failing the aggregation later.
+    fn verify_remote_channel_announcement_signature(
+        &self,
+        node_signature: &EcdsaSignature,
+        partial_signature: PartialSignature,
+    ) -> ProcessingChannelResult {
+        let message = self.get_or_create_channel_announcement().message_to_sign();
+        if !node_signature.verify(&self.remote_pubkey, &message) {
+            return Err(ProcessingChannelError::Musig2VerifyError(
+                VerifyError::BadSignature,
+            ));
+        }
+
+        let remote_nonce = self.get_remote_channel_announcement_nonce().ok_or_else(|| {
+            ProcessingChannelError::InvalidState(
+                "Remote channel announcement nonce is missing".to_string(),
+            )
+        })?;
+        let local_nonce = self
+            .get_channel_announcement_musig2_secnonce()
+            .public_nonce();
+        let agg_nonce =
+            AggNonce::sum(self.order_things_for_musig2(local_nonce, remote_nonce.clone()));
+        let verify_ctx = Musig2VerifyContext {
+            key_agg_ctx: self.get_musig2_agg_context(),
+            agg_nonce,
+            pubkey: *self.get_remote_funding_pubkey(),
+            pubnonce: remote_nonce,
         };
+        verify_ctx.verify(partial_signature, &message)?;
+        Ok(())
+    }
+
+    pub async fn try_create_channel_announcement_message(
+        &mut self,
+        network: &ActorRef<NetworkActorMessage>,
+    ) -> Option<ChannelAnnouncement> {
+        if !self.is_public() {
+            debug!("Ignoring non-public channel announcement");
+            return None;
+        }
+
+        let mut channel_announcement = self.get_or_create_channel_announcement();
+        // Skipping creating new signed channel announcement if it exists
+        if channel_announcement.is_signed() {
+            return Some(channel_announcement);
+        }
 
         let local_nonce = self
             .get_channel_announcement_musig2_secnonce()
@@ -3460,11 +6879,13 @@ impl ChannelActorState {
 
     pub fn new_inbound_channel<'a>(
         temp_channel_id: Hash256,
+        chain_hash: Hash256,
         public_channel_info: Option<PublicChannelInfo>,
         local_value: u128,
         local_reserved_ckb_amount: u64,
         commitment_fee_rate: u64,
-        commitment_delay_epoch: u64,
+        local_delay_epoch: u64,
+        remote_delay_epoch: u64,
         funding_fee_rate: u64,
         funding_udt_type_script: Option<Script>,
         seed: &[u8],
@@ -3508,8 +6929,10 @@ impl ChannelActorState {
             funding_udt_type_script,
             to_local_amount: local_value,
             to_remote_amount: remote_value,
+            push_amount: 0,
             commitment_fee_rate,
-            commitment_delay_epoch,
+            local_delay_epoch,
+            remote_delay_epoch,
             funding_fee_rate,
             id: channel_id,
             tlc_state: Default::default(),
@@ -3525,6 +6948,7 @@ impl ChannelActorState {
                 (0, first_commitment_point),
                 (1, second_commitment_point),
             ],
+            remote_commitment_secrets: vec![],
             local_shutdown_info: None,
             remote_shutdown_info: None,
             local_reserved_ckb_amount,
@@ -3540,6 +6964,31 @@ impl ChannelActorState {
             latest_commitment_transaction: None,
             reestablishing: false,
             created_at: SystemTime::now(),
+            balance_history: VecDeque::new(),
+            draining: false,
+            pending_force_close_tx_hashes: vec![],
+            latest_settlement_data: None,
+            anchor_outputs: false,
+            tlc_dust_limit: 0,
+            allowed_hash_algorithms: vec![],
+            commitment_signed_cache: None,
+            pending_commitment_fee_rate: None,
+            tlcs_pending_approval: HashSet::new(),
+            chain_hash: Some(chain_hash),
+            total_fees_earned: 0,
+            mpp_payments: HashMap::new(),
+            sorted_settlement_outputs: false,
+            balance_invariant_violation: None,
+            features: FeatureBits::all(),
+            rate_limited_tlcs: HashSet::new(),
+            clock: default_clock(),
+            fee_estimator: default_fee_estimator(),
+            state_transition_subscribers: None,
+            commitment_signer: OnceLock::new(),
+            peer_disconnected_at: None,
+            max_remote_commitment_points: None,
+            pending_settlement_data: vec![],
+            remote_funding_contribution: Default::default(),
         };
         if let Some(nonce) = remote_channel_announcement_nonce {
             state.update_remote_channel_announcement_nonce(&nonce);
@@ -3553,7 +7002,8 @@ impl ChannelActorState {
         seed: &[u8],
         local_pubkey: Pubkey,
         remote_pubkey: Pubkey,
-        to_local_amount: u128,
+        funding_amount: u128,
+        push_amount: u128,
         local_reserved_ckb_amount: u64,
         commitment_fee_rate: u64,
         commitment_delay_epoch: u64,
@@ -3575,10 +7025,12 @@ impl ChannelActorState {
             funding_tx_confirmed_at: None,
             funding_udt_type_script,
             is_acceptor: false,
-            to_local_amount,
-            to_remote_amount: 0,
+            to_local_amount: funding_amount - push_amount,
+            to_remote_amount: push_amount,
+            push_amount,
             commitment_fee_rate,
-            commitment_delay_epoch,
+            local_delay_epoch: commitment_delay_epoch,
+            remote_delay_epoch: commitment_delay_epoch,
             funding_fee_rate,
             id: temp_channel_id,
             tlc_state: Default::default(),
@@ -3595,6 +7047,7 @@ impl ChannelActorState {
             remote_nonces: vec![],
             commitment_numbers: Default::default(),
             remote_commitment_points: vec![],
+            remote_commitment_secrets: vec![],
             local_shutdown_script: shutdown_script,
             remote_shutdown_script: None,
             local_shutdown_info: None,
@@ -3604,6 +7057,31 @@ impl ChannelActorState {
             latest_commitment_transaction: None,
             reestablishing: false,
             created_at: SystemTime::now(),
+            balance_history: VecDeque::new(),
+            draining: false,
+            pending_force_close_tx_hashes: vec![],
+            latest_settlement_data: None,
+            anchor_outputs: false,
+            tlc_dust_limit: 0,
+            allowed_hash_algorithms: vec![],
+            commitment_signed_cache: None,
+            pending_commitment_fee_rate: None,
+            tlcs_pending_approval: HashSet::new(),
+            chain_hash: Some(get_chain_hash()),
+            total_fees_earned: 0,
+            mpp_payments: HashMap::new(),
+            sorted_settlement_outputs: false,
+            balance_invariant_violation: None,
+            features: FeatureBits::all(),
+            rate_limited_tlcs: HashSet::new(),
+            clock: default_clock(),
+            fee_estimator: default_fee_estimator(),
+            state_transition_subscribers: None,
+            commitment_signer: OnceLock::new(),
+            peer_disconnected_at: None,
+            max_remote_commitment_points: None,
+            pending_settlement_data: vec![],
+            remote_funding_contribution: Default::default(),
         }
     }
 
@@ -3611,15 +7089,17 @@ impl ChannelActorState {
     fn check_open_channel_parameters(&self) -> ProcessingChannelResult {
         let udt_type_script = &self.funding_udt_type_script;
 
-        // reserved_ckb_amount
-        let occupied_capacity =
-            occupied_capacity(&self.local_shutdown_script, udt_type_script)?.as_u64();
+        // reserved_ckb_amount, the anchor output is also paid by the reserved amount
+        let occupied_capacity = occupied_capacity(&self.local_shutdown_script, udt_type_script)?
+            .as_u64()
+            + self.get_anchor_capacity(true);
         if self.local_reserved_ckb_amount < occupied_capacity {
             return Err(ProcessingChannelError::InvalidParameter(format!(
                 "Reserved CKB amount {} is less than {}",
                 self.local_reserved_ckb_amount, occupied_capacity,
             )));
         }
+        self.check_total_amount_bounds()?;
 
         // funding_fee_rate
         if self.funding_fee_rate < DEFAULT_FEE_RATE {
@@ -3636,39 +7116,10 @@ impl ChannelActorState {
                 DEFAULT_COMMITMENT_FEE_RATE,
             )));
         }
-        let commitment_fee = calculate_commitment_tx_fee(self.commitment_fee_rate, udt_type_script);
-        let reserved_fee = self.local_reserved_ckb_amount - occupied_capacity;
-        if commitment_fee * 2 > reserved_fee {
-            return Err(ProcessingChannelError::InvalidParameter(format!(
-                "Commitment fee {} which caculated by commitment fee rate {} is larger than half of reserved fee {}",
-                commitment_fee, self.commitment_fee_rate, reserved_fee
-            )));
-        }
+        self.check_commitment_fee_within_reserve(true, self.commitment_fee_rate)?;
 
         // commitment_delay_epoch
-        let epoch = EpochNumberWithFraction::from_full_value_unchecked(self.commitment_delay_epoch);
-        if !epoch.is_well_formed() {
-            return Err(ProcessingChannelError::InvalidParameter(format!(
-                "Commitment delay epoch {} is not a valid value",
-                self.commitment_delay_epoch,
-            )));
-        }
-
-        let min = EpochNumberWithFraction::new(MIN_COMMITMENT_DELAY_EPOCHS, 0, 1);
-        if epoch < min {
-            return Err(ProcessingChannelError::InvalidParameter(format!(
-                "Commitment delay epoch {} is less than the minimal value {}",
-                epoch, min
-            )));
-        }
-
-        let max = EpochNumberWithFraction::new(MAX_COMMITMENT_DELAY_EPOCHS, 0, 1);
-        if epoch > max {
-            return Err(ProcessingChannelError::InvalidParameter(format!(
-                "Commitment delay epoch {} is greater than the maximal value {}",
-                epoch, max
-            )));
-        }
+        check_commitment_delay_epoch(self.remote_delay_epoch)?;
 
         // max_tlc_number_in_flight
         if self.local_constraints.max_tlc_number_in_flight > SYS_MAX_TLC_NUMBER_IN_FLIGHT {
@@ -3691,29 +7142,220 @@ impl ChannelActorState {
 
         let udt_type_script = &self.funding_udt_type_script;
 
-        // reserved_ckb_amount
+        // reserved_ckb_amount, the anchor output is also paid by the reserved amount
         let occupied_capacity =
-            occupied_capacity(&self.get_remote_shutdown_script(), udt_type_script)?.as_u64();
+            occupied_capacity(&self.get_remote_shutdown_script(), udt_type_script)?.as_u64()
+                + self.get_anchor_capacity(false);
         if self.remote_reserved_ckb_amount < occupied_capacity {
             return Err(ProcessingChannelError::InvalidParameter(format!(
                 "Reserved CKB amount {} is less than {}",
                 self.remote_reserved_ckb_amount, occupied_capacity,
             )));
         }
+        self.check_total_amount_bounds()?;
 
         // commitment_fee_rate
-        let commitment_fee = calculate_commitment_tx_fee(self.commitment_fee_rate, udt_type_script);
-        let reserved_fee = self.remote_reserved_ckb_amount - occupied_capacity;
+        self.check_commitment_fee_within_reserve(false, self.commitment_fee_rate)?;
+
+        Ok(())
+    }
+
+    // The commitment fee is paid by the reserved amount of each party, which should be able to
+    // pay it twice besides the occupied capacity of its outputs.
+    fn check_commitment_fee_within_reserve(
+        &self,
+        local: bool,
+        commitment_fee_rate: u64,
+    ) -> ProcessingChannelResult {
+        let (shutdown_script, reserved_ckb_amount) = if local {
+            (self.get_local_shutdown_script(), self.local_reserved_ckb_amount)
+        } else {
+            (
+                self.get_remote_shutdown_script(),
+                self.remote_reserved_ckb_amount,
+            )
+        };
+        let occupied_capacity =
+            occupied_capacity(&shutdown_script, &self.funding_udt_type_script)?.as_u64()
+                + self.get_anchor_capacity(local);
+        let commitment_fee = calculate_commitment_tx_fee(
+            commitment_fee_rate,
+            &self.funding_udt_type_script,
+            self.anchor_outputs,
+        );
+        let reserved_fee = reserved_ckb_amount.saturating_sub(occupied_capacity);
         if commitment_fee * 2 > reserved_fee {
             return Err(ProcessingChannelError::InvalidParameter(format!(
                 "Commitment fee {} which caculated by commitment fee rate {} is larger than half of reserved fee {}",
-                commitment_fee, self.commitment_fee_rate, reserved_fee
+                commitment_fee, commitment_fee_rate, reserved_fee
+            )));
+        }
+        Ok(())
+    }
+
+    // Check whether the commitment fee rate can be updated to `fee_rate` now, the update is only
+    // allowed when there is no tlc in flight, so that no commitment tx is being signed.
+    fn check_commitment_fee_rate_update(&self, fee_rate: u64) -> ProcessingChannelResult {
+        if self.state != ChannelState::ChannelReady() {
+            return Err(ProcessingChannelError::InvalidState(format!(
+                "Updating commitment fee rate in invalid state {:?}",
+                &self.state
+            )));
+        }
+        if self.pending_commitment_fee_rate.is_some() {
+            return Err(ProcessingChannelError::InvalidState(
+                "Another commitment fee rate update is in progress".to_string(),
+            ));
+        }
+        if self.tlc_state.waiting_ack || self.tlc_state.all_tlcs().next().is_some() {
+            return Err(ProcessingChannelError::InvalidState(
+                "Updating commitment fee rate while there are tlcs in flight".to_string(),
+            ));
+        }
+        if fee_rate < DEFAULT_COMMITMENT_FEE_RATE {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "Commitment fee rate is less than {}",
+                DEFAULT_COMMITMENT_FEE_RATE,
             )));
         }
+        self.check_commitment_fee_within_reserve(true, fee_rate)?;
+        self.check_commitment_fee_within_reserve(false, fee_rate)
+    }
+
+    fn send_update_fee_message(
+        &self,
+        fee_rate: u64,
+        ack: bool,
+        network: &ActorRef<NetworkActorMessage>,
+    ) {
+        network
+            .send_message(NetworkActorMessage::new_command(
+                NetworkActorCommand::SendFiberMessage(FiberMessageWithPeerId::new(
+                    self.get_remote_peer_id(),
+                    FiberMessage::update_fee(UpdateFee {
+                        channel_id: self.get_id(),
+                        fee_rate,
+                        ack,
+                    }),
+                )),
+            ))
+            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+    }
+
+    // Apply the commitment fee rate proposed by the peer if it's valid, and reply with the
+    // commitment fee rate we use, which is unchanged if the proposal is rejected.
+    // Returns whether the commitment fee rate is updated.
+    fn handle_update_fee_proposal(
+        &mut self,
+        fee_rate: u64,
+        network: &ActorRef<NetworkActorMessage>,
+    ) -> bool {
+        let mut updated = false;
+        if fee_rate != self.commitment_fee_rate {
+            match self.check_commitment_fee_rate_update(fee_rate) {
+                Ok(()) => {
+                    debug!(
+                        "Updating commitment fee rate of channel {:?} from {} to {}",
+                        self.get_id(),
+                        self.commitment_fee_rate,
+                        fee_rate
+                    );
+                    self.commitment_fee_rate = fee_rate;
+                    updated = true;
+                }
+                Err(err) => {
+                    warn!(
+                        "Rejecting commitment fee rate {} proposed by peer of channel {:?}: {}",
+                        fee_rate,
+                        self.get_id(),
+                        err
+                    );
+                }
+            }
+        }
+        self.send_update_fee_message(self.commitment_fee_rate, true, network);
+        updated
+    }
+
+    // Returns whether the commitment fee rate is updated.
+    fn handle_update_fee_ack(&mut self, fee_rate: u64) -> bool {
+        let Some(pending_fee_rate) = self.pending_commitment_fee_rate.take() else {
+            warn!(
+                "Received unexpected UpdateFee ack of channel {:?}, fee rate {}",
+                self.get_id(),
+                fee_rate
+            );
+            return false;
+        };
+        if fee_rate == pending_fee_rate {
+            debug!(
+                "Updating commitment fee rate of channel {:?} from {} to {}",
+                self.get_id(),
+                self.commitment_fee_rate,
+                fee_rate
+            );
+            self.commitment_fee_rate = fee_rate;
+            true
+        } else {
+            warn!(
+                "Commitment fee rate {} of channel {:?} is rejected by peer",
+                pending_fee_rate,
+                self.get_id()
+            );
+            false
+        }
+    }
 
+    // The amounts are later added up as u64 capacities, e.g. in `get_total_ckb_amount`,
+    // so the total CKB amount of the channel must fit in u64 to avoid truncation.
+    fn check_total_amount_bounds(&self) -> ProcessingChannelResult {
+        let total_amount = self
+            .to_local_amount
+            .checked_add(self.to_remote_amount)
+            .ok_or_else(|| {
+                ProcessingChannelError::InvalidParameter(format!(
+                    "The total funding amount overflows, local amount {}, remote amount {}",
+                    self.to_local_amount, self.to_remote_amount
+                ))
+            })?;
+        let total_reserved_ckb_amount =
+            self.local_reserved_ckb_amount as u128 + self.remote_reserved_ckb_amount as u128;
+        let total_ckb_amount = if self.funding_udt_type_script.is_none() {
+            total_amount.saturating_add(total_reserved_ckb_amount)
+        } else {
+            total_reserved_ckb_amount
+        };
+        if total_ckb_amount > u64::MAX as u128 {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "The total CKB amount {} of the channel should not be greater than {}",
+                total_ckb_amount,
+                u64::MAX
+            )));
+        }
         Ok(())
     }
 
+    /// Get the fee rate of the shutdown transaction for the shutdown command, it's estimated
+    /// from the target blocks if they are set, and it's at least the commitment fee rate.
+    pub fn get_shutdown_fee_rate(
+        &self,
+        command: &ShutdownCommand,
+    ) -> Result<FeeRate, ProcessingChannelError> {
+        let Some(target_blocks) = command.target_blocks else {
+            return Ok(command.fee_rate);
+        };
+        let fee_rate = self
+            .fee_estimator
+            .estimate_fee_rate(target_blocks)
+            .ok_or_else(|| {
+                ProcessingChannelError::InvalidParameter(format!(
+                    "Failed to estimate the fee rate for target blocks {}",
+                    target_blocks
+                ))
+            })?;
+        Ok(FeeRate::from_u64(fee_rate.as_u64().max(self.commitment_fee_rate)))
+    }
+
     fn check_shutdown_fee_rate(
         &self,
         fee_rate: FeeRate,
@@ -3726,6 +7368,24 @@ impl ChannelActorState {
             )));
         }
 
+        let (fee, available_max_fee) =
+            self.get_shutdown_fee_and_available_max_fee(fee_rate, close_script)?;
+        if fee > available_max_fee {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "Local balance is not enough to pay the fee, expect fee {} <= available_max_fee {}",
+                fee, available_max_fee
+            )));
+        }
+        Ok(())
+    }
+
+    // The fee of the shutdown transaction, and the CKB amount we get back after deducting
+    // the occupied capacity of our output, which is the max fee we can afford.
+    fn get_shutdown_fee_and_available_max_fee(
+        &self,
+        fee_rate: FeeRate,
+        close_script: &Script,
+    ) -> Result<(u64, u64), ProcessingChannelError> {
         let fee = calculate_shutdown_tx_fee(
             fee_rate.as_u64(),
             &self.funding_udt_type_script,
@@ -3741,11 +7401,23 @@ impl ChannelActorState {
             self.local_reserved_ckb_amount
                 .saturating_sub(occupied_capacity)
         };
+        Ok((fee, available_max_fee))
+    }
 
-        if fee > available_max_fee {
+    // Closing the channel is not worthwhile if the fee consumes most of our balance.
+    fn check_net_close_balance(
+        &self,
+        fee_rate: FeeRate,
+        close_script: &Script,
+        min_net_close_balance: u64,
+    ) -> ProcessingChannelResult {
+        let (fee, available_max_fee) =
+            self.get_shutdown_fee_and_available_max_fee(fee_rate, close_script)?;
+        let net_close_balance = available_max_fee.saturating_sub(fee);
+        if net_close_balance < min_net_close_balance {
             return Err(ProcessingChannelError::InvalidParameter(format!(
-                "Local balance is not enough to pay the fee, expect fee {} <= available_max_fee {}",
-                fee, available_max_fee
+                "Net close balance {} is below the minimum {}, closing the channel is not economical",
+                net_close_balance, min_net_close_balance
             )));
         }
         Ok(())
@@ -3787,7 +7459,10 @@ impl ChannelActorState {
             "Updating channel state from {:?} to {:?}",
             &self.state, &new_state
         );
-        self.state = new_state;
+        let old_state = std::mem::replace(&mut self.state, new_state);
+        if let Some(subscribers) = self.state_transition_subscribers.as_ref() {
+            subscribers.send((self.id, old_state, new_state));
+        }
     }
 
     fn local_is_node1(&self) -> bool {
@@ -3816,14 +7491,10 @@ impl ChannelActorState {
         let peer_id = self.get_remote_peer_id();
         let channel_outpoint = self.must_get_funding_transaction_outpoint();
 
-        let partial_signature: PartialSignature = sign_partial(
-            &key_agg_ctx,
-            &self.signer.funding_key,
-            local_secnonce,
-            &agg_nonce,
-            message,
-        )
-        .expect("Partial sign channel announcement");
+        let partial_signature = self
+            .get_commitment_signer()
+            .sign_partial(&key_agg_ctx, local_secnonce, &agg_nonce, message)
+            .expect("Partial sign channel announcement");
 
         let node_signature = sign_network_message(network.clone(), message)
             .await
@@ -3941,6 +7612,28 @@ impl ChannelActorState {
             .map(|info| info.tlc_expiry_delta)
     }
 
+    fn get_our_min_tlc_expiry_delta(&self) -> Option<u64> {
+        self.public_channel_info
+            .as_ref()
+            .and_then(|info| info.min_tlc_expiry_delta)
+    }
+
+    fn get_our_max_tlc_expiry_delta(&self) -> Option<u64> {
+        self.public_channel_info
+            .as_ref()
+            .and_then(|info| info.max_tlc_expiry_delta)
+    }
+
+    pub fn get_min_tlc_expiry_delta(&self) -> u64 {
+        self.get_our_min_tlc_expiry_delta()
+            .unwrap_or(MIN_TLC_EXPIRY_DELTA)
+    }
+
+    pub fn get_max_tlc_expiry_delta(&self) -> u64 {
+        self.get_our_max_tlc_expiry_delta()
+            .unwrap_or(MAX_PAYMENT_TLC_EXPIRY_LIMIT)
+    }
+
     fn update_our_tlc_expiry_delta(&mut self, value: u64) -> bool {
         let old_value = self.get_our_tlc_expiry_delta();
         match old_value {
@@ -3962,6 +7655,112 @@ impl ChannelActorState {
             + self.get_total_reserved_ckb_amount()
     }
 
+    fn get_commitment_tx_fee(&self) -> u64 {
+        calculate_commitment_tx_fee(
+            self.commitment_fee_rate,
+            &self.funding_udt_type_script,
+            self.anchor_outputs,
+        )
+    }
+
+    // The capacity of the anchor output of our side or the remote side, it's 0 if the
+    // channel has no anchor outputs.
+    fn get_anchor_capacity(&self, local: bool) -> u64 {
+        if !self.anchor_outputs {
+            return 0;
+        }
+        let shutdown_script = if local {
+            self.get_local_shutdown_script()
+        } else {
+            self.get_remote_shutdown_script()
+        };
+        anchor_capacity(&shutdown_script)
+            .expect("valid anchor capacity")
+            .as_u64()
+    }
+
+    fn get_total_anchor_capacity(&self) -> u64 {
+        self.get_anchor_capacity(true) + self.get_anchor_capacity(false)
+    }
+
+    // The capacity of the commitment cell, which is also the capacity of the output
+    // spending it with the revocation signature.
+    // The commitment fee and the anchor outputs are paid from the reserved amounts, it fails
+    // instead of underflowing if the reserved amounts can't afford them.
+    fn get_commitment_output_capacity(&self) -> Result<u64, CapacityError> {
+        let total_capacity = if self.funding_udt_type_script.is_some() {
+            self.get_total_reserved_ckb_amount()
+        } else {
+            self.get_total_ckb_amount()
+        };
+        total_capacity
+            .checked_sub(self.get_commitment_tx_fee())
+            .and_then(|capacity| capacity.checked_sub(self.get_total_anchor_capacity()))
+            .ok_or(CapacityError::Overflow)
+    }
+
+    // The reserved amount of a party left in its settlement output, after paying for its anchor
+    // output and the commitment fee.
+    fn get_settlement_reserved_ckb_amount(&self, local: bool) -> Result<u64, CapacityError> {
+        let reserved_ckb_amount = if local {
+            self.local_reserved_ckb_amount
+        } else {
+            self.remote_reserved_ckb_amount
+        };
+        reserved_ckb_amount
+            .checked_sub(self.get_anchor_capacity(local))
+            .and_then(|amount| amount.checked_sub(self.get_commitment_tx_fee()))
+            .ok_or(CapacityError::Overflow)
+    }
+
+    // The reserved amounts are checked while opening the channel, but the commitment fee rate
+    // may change later, so check the capacities before building the commitment and settlement
+    // transactions, which fails with `CapacityError` rather than panicking on the boundary.
+    pub(crate) fn check_commitment_capacity(&self) -> ProcessingChannelResult {
+        self.get_commitment_output_capacity()?;
+        self.get_settlement_reserved_ckb_amount(true)?;
+        self.get_settlement_reserved_ckb_amount(false)?;
+        Ok(())
+    }
+
+    // The fee paid by the commitment transaction, which spends the funding cell.
+    fn get_commitment_transaction_fee(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<u64, ProcessingChannelError> {
+        let funding_capacity: u64 = self
+            .funding_tx
+            .as_ref()
+            .and_then(|tx| tx.raw().outputs().get(0))
+            .ok_or_else(|| {
+                ProcessingChannelError::InvalidState("Funding transaction is absent".to_string())
+            })?
+            .capacity()
+            .unpack();
+        let outputs_capacity: u64 = transaction
+            .raw()
+            .outputs()
+            .into_iter()
+            .map(|output| -> u64 { output.capacity().unpack() })
+            .sum();
+        funding_capacity.checked_sub(outputs_capacity).ok_or_else(|| {
+            ProcessingChannelError::InvalidState(
+                "Commitment transaction outputs exceed the funding capacity".to_string(),
+            )
+        })
+    }
+
+    /// The out point of our anchor output in the latest commitment transaction we're holding,
+    /// which can be spent by a child transaction to bump the fee of the force close.
+    pub fn get_local_anchor_outpoint(&self) -> Option<OutPoint> {
+        if !self.anchor_outputs {
+            return None;
+        }
+        self.latest_commitment_transaction
+            .as_ref()
+            .map(|tx| OutPoint::new(tx.calc_tx_hash(), LOCAL_ANCHOR_OUTPUT_INDEX))
+    }
+
     fn get_total_udt_amount(&self) -> u128 {
         self.to_local_amount + self.to_remote_amount
     }
@@ -3977,12 +7776,82 @@ impl ChannelActorState {
         capacity
     }
 
+    pub fn musig2_info(&self) -> Result<Musig2Info, ProcessingChannelError> {
+        if self.remote_channel_public_keys.is_none() {
+            return Err(ProcessingChannelError::InvalidState(
+                "The funding pubkey of the peer is not known yet".to_string(),
+            ));
+        }
+        Ok(Musig2Info {
+            x_only_aggregated_pubkey: self.get_funding_lock_script_xonly(),
+            funding_lock_script: self.get_funding_lock_script(),
+            local_goes_first: self.should_local_go_first_in_musig2(),
+        })
+    }
+
+    pub fn commitment_point_info(&self) -> CommitmentPointInfo {
+        CommitmentPointInfo {
+            local_commitment_number: self.get_local_commitment_number(),
+            remote_commitment_number: self.get_remote_commitment_number(),
+            next_commitment_point: self.next_commitment_point_to_send(),
+        }
+    }
+
+    pub fn closed_channel_record(&self, closing_tx_hash: Hash256) -> ClosedChannelRecord {
+        ClosedChannelRecord {
+            channel_id: self.get_id(),
+            remote_pubkey: self.remote_pubkey,
+            to_local_amount: self.to_local_amount,
+            to_remote_amount: self.to_remote_amount,
+            close_flags: match self.state {
+                ChannelState::Closed(flags) => flags,
+                _ => CloseFlags::empty(),
+            },
+            closing_tx_hash,
+            closed_at: self.clock.now_millis(),
+        }
+    }
+
+    pub fn channel_summary(&self) -> ChannelSummary {
+        ChannelSummary {
+            channel_id: self.get_id(),
+            remote_pubkey: self.remote_pubkey,
+            state: self.state,
+            to_local_amount: self.to_local_amount,
+            to_remote_amount: self.to_remote_amount,
+            is_public: self.is_public(),
+            enabled: self.get_our_enabled().unwrap_or(true),
+            funding_outpoint: self.get_funding_transaction_outpoint(),
+            funding_udt_type_script: self.funding_udt_type_script.clone(),
+        }
+    }
+
+    pub fn capacity_breakdown(&self) -> CapacityBreakdown {
+        let (total, udt_amount) = if self.funding_udt_type_script.is_some() {
+            (
+                self.get_total_reserved_ckb_amount(),
+                Some(self.get_total_udt_amount()),
+            )
+        } else {
+            (self.get_total_ckb_amount(), None)
+        };
+        CapacityBreakdown {
+            total,
+            liquid: self.get_liquid_capacity(),
+            local_reserved: self.local_reserved_ckb_amount,
+            remote_reserved: self.remote_reserved_ckb_amount,
+            commitment_fee: self.get_commitment_tx_fee(),
+            udt_amount,
+        }
+    }
+
     // Send RevokeAndAck message to the counterparty, and update the
     // channel state accordingly.
     fn send_revoke_and_ack_message(
         &mut self,
         network: &ActorRef<NetworkActorMessage>,
     ) -> ProcessingChannelResult {
+        self.check_commitment_capacity()?;
         let key_agg_ctx = {
             let local_pubkey = self.get_local_channel_public_keys().funding_pubkey;
             let remote_pubkey = self.get_remote_channel_public_keys().funding_pubkey;
@@ -3997,20 +7866,16 @@ impl ChannelActorState {
             Musig2SignContext {
                 key_agg_ctx,
                 agg_nonce,
-                seckey: self.signer.funding_key.clone(),
+                signer: self.get_commitment_signer(),
                 secnonce: self.get_local_musig2_secnonce(),
             }
         };
 
         let revocation_partial_signature = {
-            let commitment_tx_fee = calculate_commitment_tx_fee(
-                self.commitment_fee_rate,
-                &self.funding_udt_type_script,
-            );
             let lock_script = self.get_remote_shutdown_script();
             let (output, output_data) = if let Some(udt_type_script) = &self.funding_udt_type_script
             {
-                let capacity = self.get_total_reserved_ckb_amount() - commitment_tx_fee;
+                let capacity = self.get_commitment_output_capacity()?;
                 let output = CellOutput::new_builder()
                     .lock(lock_script)
                     .type_(Some(udt_type_script.clone()).pack())
@@ -4020,7 +7885,7 @@ impl ChannelActorState {
                 let output_data = self.get_total_udt_amount().to_le_bytes().pack();
                 (output, output_data)
             } else {
-                let capacity = self.get_total_ckb_amount() - commitment_tx_fee;
+                let capacity = self.get_commitment_output_capacity()?;
                 let output = CellOutput::new_builder()
                     .lock(lock_script)
                     .capacity(capacity.pack())
@@ -4032,7 +7897,7 @@ impl ChannelActorState {
             let commitment_number = self.get_remote_commitment_number() - 1;
             let commitment_lock_script_args = [
                 &blake2b_256(x_only_aggregated_pubkey)[0..20],
-                self.get_delay_epoch_as_lock_args_bytes().as_slice(),
+                self.get_delay_epoch_as_lock_args_bytes(false).as_slice(),
                 commitment_number.to_be_bytes().as_slice(),
             ]
             .concat();
@@ -4045,6 +7910,15 @@ impl ChannelActorState {
                 ]
                 .concat(),
             );
+            trace_signing_message(
+                "revocation",
+                &[
+                    ("output", output.as_slice()),
+                    ("output_data", output_data.as_slice()),
+                    ("lock_script_args", commitment_lock_script_args.as_slice()),
+                ],
+                &message,
+            );
             sign_ctx
                 .clone()
                 .sign(message.as_slice())
@@ -4058,7 +7932,7 @@ impl ChannelActorState {
             ) = self.build_settlement_transaction_outputs(false);
             let commitment_lock_script_args = [
                 &blake2b_256(x_only_aggregated_pubkey)[0..20],
-                self.get_delay_epoch_as_lock_args_bytes().as_slice(),
+                self.get_delay_epoch_as_lock_args_bytes(false).as_slice(),
                 self.get_remote_commitment_number().to_be_bytes().as_slice(),
             ]
             .concat();
@@ -4072,10 +7946,27 @@ impl ChannelActorState {
                 ]
                 .concat(),
             );
+            trace_signing_message(
+                "revoke_and_ack_settlement",
+                &[
+                    ("to_local_output", to_local_output.as_slice()),
+                    ("to_local_output_data", to_local_output_data.as_slice()),
+                    ("to_remote_output", to_remote_output.as_slice()),
+                    ("to_remote_output_data", to_remote_output_data.as_slice()),
+                    ("lock_script_args", commitment_lock_script_args.as_slice()),
+                ],
+                &message,
+            );
 
             sign_ctx.sign(message.as_slice())?
         };
 
+        // Reveal the secret of the commitment we are revoking, so that the counterparty
+        // can check it against the commitment point we have sent before.
+        let revoked_secret = self
+            .signer
+            .get_commitment_secret(self.get_remote_commitment_number() - 1);
+
         // Note that we must update channel state here to update commitment number,
         // so that next step will obtain the correct commitment point.
         self.increment_remote_commitment_number();
@@ -4090,6 +7981,7 @@ impl ChannelActorState {
                         revocation_partial_signature,
                         commitment_tx_partial_signature,
                         next_per_commitment_point: point,
+                        last_per_commitment_secret: Some(revoked_secret),
                     }),
                 )),
             ))
@@ -4110,7 +8002,7 @@ impl ChannelActorState {
     }
 
     pub fn get_local_secnonce(&self) -> SecNonce {
-        self.signer
+        self.get_commitment_signer()
             .derive_musig2_nonce(self.get_local_commitment_number())
     }
 
@@ -4119,7 +8011,7 @@ impl ChannelActorState {
     }
 
     pub fn get_next_local_secnonce(&self) -> SecNonce {
-        self.signer
+        self.get_commitment_signer()
             .derive_musig2_nonce(self.get_next_commitment_number(true))
     }
 
@@ -4160,9 +8052,9 @@ impl ChannelActorState {
         self.last_used_nonce_in_commitment_signed = Some(nonce);
     }
 
-    fn take_remote_nonce_for_raa(&mut self) -> PubNonce {
+    fn get_remote_nonce_for_raa(&self) -> PubNonce {
         self.last_used_nonce_in_commitment_signed
-            .take()
+            .clone()
             .expect("set last_used_nonce_in_commitment_signed in commitment signed")
     }
 
@@ -4210,38 +8102,125 @@ impl ChannelActorState {
         self.tlc_state.get_next_received()
     }
 
-    pub fn increment_next_offered_tlc_id(&mut self) {
-        self.tlc_state.increment_offering();
+    pub fn increment_next_offered_tlc_id(&mut self) {
+        self.tlc_state.increment_offering();
+    }
+
+    pub fn increment_next_received_tlc_id(&mut self) {
+        self.tlc_state.increment_received();
+    }
+
+    pub fn get_offered_tlc(&self, tlc_id: u64) -> Option<&AddTlcInfo> {
+        self.tlc_state.get(&TLCId::Offered(tlc_id))
+    }
+
+    pub fn get_received_tlc(&self, tlc_id: u64) -> Option<&AddTlcInfo> {
+        self.tlc_state.get(&TLCId::Received(tlc_id))
+    }
+
+    /// Build the reason to fail the received tlc with the error code. The error is encrypted
+    /// with the shared secret of the tlc so that only the payment sender can decode it.
+    pub fn build_fail_reason(
+        &self,
+        tlc_id: u64,
+        code: TlcErrorCode,
+    ) -> Result<RemoveTlcReason, ProcessingChannelError> {
+        let tlc = self.get_received_tlc(tlc_id).ok_or_else(|| {
+            ProcessingChannelError::InvalidParameter(format!("Received tlc {} not found", tlc_id))
+        })?;
+        let channel_update = if code.is_update() {
+            self.public_channel_info
+                .as_ref()
+                .and_then(|info| info.channel_update.clone())
+        } else {
+            None
+        };
+        let tlc_err = TlcErr::new_channel_fail(
+            code,
+            self.local_pubkey,
+            self.must_get_funding_transaction_outpoint(),
+            channel_update,
+        );
+        Ok(RemoveTlcReason::RemoveTlcFail(TlcErrPacket::new(
+            tlc_err,
+            &tlc.shared_secret,
+        )))
+    }
+
+    pub(crate) fn set_received_tlc_preimage(&mut self, tlc_id: u64, preimage: Option<Hash256>) {
+        if let Some(tlc) = self.tlc_state.get_mut(&TLCId::Received(tlc_id)) {
+            tlc.payment_preimage = preimage;
+        }
+    }
+
+    fn set_received_tlc_custom_records(
+        &mut self,
+        tlc_id: u64,
+        custom_records: Vec<(u64, Vec<u8>)>,
+    ) {
+        if let Some(tlc) = self.tlc_state.get_mut(&TLCId::Received(tlc_id)) {
+            tlc.custom_records = custom_records;
+        }
     }
 
-    pub fn increment_next_received_tlc_id(&mut self) {
-        self.tlc_state.increment_received();
+    fn set_received_tlc_next_hop(&mut self, tlc_id: u64, next_hop: Option<Pubkey>) {
+        if let Some(tlc) = self.tlc_state.get_mut(&TLCId::Received(tlc_id)) {
+            tlc.next_hop = next_hop;
+        }
     }
 
-    pub fn get_offered_tlc(&self, tlc_id: u64) -> Option<&AddTlcInfo> {
-        self.tlc_state.get(&TLCId::Offered(tlc_id))
+    // Whether there are other received tlcs with the same payment hash which are not removed.
+    fn has_other_received_tlcs(&self, tlc: &AddTlcInfo) -> bool {
+        self.get_all_received_tlcs().any(|other| {
+            other.payment_hash == tlc.payment_hash
+                && other.tlc_id != tlc.tlc_id
+                && other.removed_at.is_none()
+        })
     }
 
-    pub fn get_received_tlc(&self, tlc_id: u64) -> Option<&AddTlcInfo> {
-        self.tlc_state.get(&TLCId::Received(tlc_id))
+    fn add_mpp_part(
+        &mut self,
+        tlc: &AddTlcInfo,
+        total_amount: u128,
+    ) -> Result<(), ProcessingChannelError> {
+        let mpp_payment = self
+            .mpp_payments
+            .entry(tlc.payment_hash)
+            .or_insert_with(|| MppPayment::new(total_amount));
+        if mpp_payment.total_amount != total_amount || mpp_payment.completed {
+            return Err(ProcessingChannelError::FinalIncorrectHTLCAmount);
+        }
+        mpp_payment.parts.insert(tlc.tlc_id.into(), tlc.amount);
+        mpp_payment.expiry = mpp_payment.expiry.min(tlc.expiry);
+        Ok(())
     }
 
-    pub(crate) fn set_received_tlc_preimage(&mut self, tlc_id: u64, preimage: Option<Hash256>) {
-        if let Some(tlc) = self.tlc_state.get_mut(&TLCId::Received(tlc_id)) {
-            tlc.payment_preimage = preimage;
+    fn remove_mpp_part(&mut self, payment_hash: &Hash256, tlc_id: u64) {
+        if let Some(mpp_payment) = self.mpp_payments.get_mut(payment_hash) {
+            mpp_payment.parts.remove(&tlc_id);
+            if mpp_payment.parts.is_empty() {
+                self.mpp_payments.remove(payment_hash);
+            }
         }
     }
 
     pub fn check_insert_tlc(&mut self, tlc: &AddTlcInfo) -> Result<(), ProcessingChannelError> {
         let payment_hash = tlc.payment_hash;
-        if let Some(tlc) = self
-            .tlc_state
-            .all_tlcs()
-            .find(|tlc| tlc.payment_hash == payment_hash)
-        {
+        // The parts of a multi-part payment share the payment hash, which are the received tlcs,
+        // whose MPP markers are checked after peeling the onion packets, or the offered tlcs
+        // sent by ourselves as the payer.
+        let is_payment_part = |part: &AddTlcInfo| {
+            part.is_received() || (part.previous_tlc.is_none() && part.onion_packet.is_some())
+        };
+        if let Some(other) = self.tlc_state.all_tlcs().find(|other| {
+            other.payment_hash == payment_hash
+                && !(other.is_offered() == tlc.is_offered()
+                    && is_payment_part(other)
+                    && is_payment_part(tlc))
+        }) {
             return Err(ProcessingChannelError::RepeatedProcessing(format!(
                 "Trying to insert tlc with duplicate payment hash {:?} with tlc {:?}",
-                payment_hash, tlc
+                payment_hash, other
             )));
         }
         if tlc.is_offered() {
@@ -4250,6 +8229,14 @@ impl ChannelActorState {
             if sent_tlc_value + tlc.amount > self.to_local_amount {
                 return Err(ProcessingChannelError::TlcAmountExceedLimit);
             }
+            let channel_reserve_amount = self.local_constraints.channel_reserve_amount;
+            if self.to_local_amount - sent_tlc_value - tlc.amount < channel_reserve_amount {
+                debug!(
+                    "Adding tlc {:?} with amount {} breaks channel reserve {}",
+                    tlc.tlc_id, tlc.amount, channel_reserve_amount
+                );
+                return Err(ProcessingChannelError::TlcAmountExceedLimit);
+            }
         } else {
             let received_tlc_value = self.get_received_tlc_balance();
             debug_assert!(self.to_remote_amount >= received_tlc_value);
@@ -4300,33 +8287,182 @@ impl ChannelActorState {
                     }
 
                     // update balance according to the tlc
-                    let (mut to_local_amount, mut to_remote_amount) =
-                        (self.to_local_amount, self.to_remote_amount);
-                    if current.is_offered() {
-                        to_local_amount -= current.amount;
-                        to_remote_amount += current.amount;
+                    let balances = if current.is_offered() {
+                        self.to_local_amount
+                            .checked_sub(current.amount)
+                            .zip(self.to_remote_amount.checked_add(current.amount))
                     } else {
-                        to_local_amount += current.amount;
-                        to_remote_amount -= current.amount;
+                        self.to_local_amount
+                            .checked_add(current.amount)
+                            .zip(self.to_remote_amount.checked_sub(current.amount))
+                    };
+                    match balances {
+                        Some((to_local_amount, to_remote_amount)) => {
+                            self.to_local_amount = to_local_amount;
+                            self.to_remote_amount = to_remote_amount;
+                            self.check_balance_invariant();
+                            self.record_balance_snapshot();
+
+                            debug!("Updated local balance to {} and remote balance to {} by removing tlc {:?} with reason {:?}",
+                                    to_local_amount, to_remote_amount, tlc_id, reason);
+                        }
+                        None => {
+                            self.record_balance_invariant_violation(format!(
+                                "Settling tlc {:?} of amount {} overflows the balances (local {}, remote {})",
+                                tlc_id, current.amount, self.to_local_amount, self.to_remote_amount
+                            ));
+                        }
                     }
-                    self.to_local_amount = to_local_amount;
-                    self.to_remote_amount = to_remote_amount;
-
-                    debug!("Updated local balance to {} and remote balance to {} by removing tlc {:?} with reason {:?}",
-                            to_local_amount, to_remote_amount, tlc_id, reason);
                 }
                 self.tlc_state
                     .apply_remove_tlc(tlc_id, removed_at, reason.clone());
+                if current.is_received() {
+                    self.remove_mpp_part(&current.payment_hash, tlc_id.into());
+                }
             }
         }
 
         Ok(current.clone())
     }
 
+    // Settling tlcs only moves amounts between the balances, and the pending tlcs stay in the
+    // balances until settled, so the total of the balances always equals the amount funded in
+    // the funding cell, excluding the reserved ckb.
+    pub(crate) fn check_balance_invariant(&mut self) {
+        let Some(funding_tx) = self.funding_tx.as_ref() else {
+            return;
+        };
+        let funded_amount = match self.get_funding_cell_contribution(funding_tx) {
+            Ok(funded) if self.funding_udt_type_script.is_some() => Some(funded.udt_amount),
+            Ok(funded) => funded
+                .capacity
+                .checked_sub(self.get_total_reserved_ckb_amount())
+                .map(u128::from),
+            Err(_) => None,
+        };
+        let total_amount = self.to_local_amount.checked_add(self.to_remote_amount);
+        if total_amount.is_none() || total_amount != funded_amount {
+            self.record_balance_invariant_violation(format!(
+                "Total balance {:?} (local {}, remote {}) doesn't match the funded amount {:?}",
+                total_amount, self.to_local_amount, self.to_remote_amount, funded_amount
+            ));
+        }
+    }
+
+    fn record_balance_invariant_violation(&mut self, violation: String) {
+        error!(
+            "Balance invariant of channel {:?} violated: {}",
+            self.id, &violation
+        );
+        // Keep the first violation, which is the root cause of the later ones.
+        self.balance_invariant_violation.get_or_insert(violation);
+    }
+
+    pub fn record_balance_snapshot(&mut self) {
+        if self.balance_history.len() >= MAX_BALANCE_HISTORY_LENGTH {
+            self.balance_history.pop_front();
+        }
+        self.balance_history.push_back(BalanceSnapshot {
+            commitment_numbers: self.get_current_commitment_numbers(),
+            to_local_amount: self.to_local_amount,
+            to_remote_amount: self.to_remote_amount,
+            timestamp: now_timestamp_as_millis_u64(),
+        });
+    }
+
+    // Notify outside observers when the waiting ack state is toggled,
+    // so that they can pause submitting tlc commands while waiting for RevokeAndAck.
+    fn set_waiting_ack(&mut self, network: &ActorRef<NetworkActorMessage>, waiting_ack: bool) {
+        if self.tlc_state.waiting_ack == waiting_ack {
+            return;
+        }
+        self.tlc_state.set_waiting_ack(waiting_ack);
+        network
+            .send_message(NetworkActorMessage::new_notification(
+                NetworkServiceEvent::ChannelAckState(self.get_id(), waiting_ack),
+            ))
+            .expect(ASSUME_NETWORK_ACTOR_ALIVE);
+    }
+
+    pub fn get_balance_history(&self) -> Vec<BalanceSnapshot> {
+        self.balance_history.iter().cloned().collect()
+    }
+
+    // The settlement data are moved to the store by the channel actor after handling the message,
+    // see `ChannelActorStateStore::get_settlement_data`.
+    fn record_settlement_data(
+        &mut self,
+        local: bool,
+        commitment_number: u64,
+        settlement_data: SettlementData,
+    ) {
+        self.pending_settlement_data
+            .push((local, commitment_number, settlement_data));
+    }
+
+    /// Get the settlement data of the remote's current commitment, i.e. how the peer could settle
+    /// the commitment transaction it may broadcast, which is what the watchtowers need to monitor.
+    ///
+    /// The aggregated signature requires the partial signature of the peer, which is sent in the
+    /// RevokeAndAck message, so the settlement data signed by the peer is returned only if it
+    /// still matches the settlement outputs of the current remote commitment. We never sign the
+    /// settlement again here, as signing another message with a used musig2 nonce would leak the
+    /// funding private key.
+    ///
+    /// The remote's commitment is built with `for_remote` set on our side, which is exactly what
+    /// the peer builds with `for_remote` unset for its own local commitment. The settlement data
+    /// in RevokeAndAck is verified in the same way, so they are compared on the same outputs.
+    pub fn remote_settlement_data(&self) -> Result<SettlementData, ProcessingChannelError> {
+        self.check_commitment_capacity()?;
+        let x_only_aggregated_pubkey = self.get_commitment_lock_script_xonly(true);
+        let ([to_local_output, to_remote_output], [to_local_output_data, to_remote_output_data]) =
+            self.build_settlement_transaction_outputs(true);
+        self.latest_settlement_data
+            .as_ref()
+            .filter(|settlement_data| {
+                settlement_data.x_only_aggregated_pubkey == x_only_aggregated_pubkey
+                    && settlement_data.to_local_output == to_local_output
+                    && settlement_data.to_local_output_data == to_local_output_data
+                    && settlement_data.to_remote_output == to_remote_output
+                    && settlement_data.to_remote_output_data == to_remote_output_data
+            })
+            .cloned()
+            .ok_or_else(|| {
+                ProcessingChannelError::InvalidState(format!(
+                    "The settlement of the remote commitment {} is not signed by the peer yet",
+                    self.get_remote_commitment_number()
+                ))
+            })
+    }
+
     pub fn get_local_channel_public_keys(&self) -> &ChannelBasePublicKeys {
         &self.local_channel_public_keys
     }
 
+    /// Install the signer of the musig2 partial signatures, e.g. a hardware signer,
+    /// its funding pubkey must be the one negotiated with the peer.
+    pub fn set_commitment_signer(
+        &mut self,
+        signer: Arc<dyn CommitmentSigner>,
+    ) -> ProcessingChannelResult {
+        let funding_pubkey = self.get_local_channel_public_keys().funding_pubkey;
+        if signer.funding_pubkey() != funding_pubkey {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "The funding pubkey of the signer {:?} doesn't match the channel funding pubkey {:?}",
+                signer.funding_pubkey(),
+                funding_pubkey
+            )));
+        }
+        self.commitment_signer = OnceLock::from(signer);
+        Ok(())
+    }
+
+    pub fn get_commitment_signer(&self) -> Arc<dyn CommitmentSigner> {
+        self.commitment_signer
+            .get_or_init(|| Arc::new(self.signer.clone()))
+            .clone()
+    }
+
     pub fn get_remote_channel_public_keys(&self) -> &ChannelBasePublicKeys {
         self.remote_channel_public_keys
             .as_ref()
@@ -4363,6 +8499,26 @@ impl ChannelActorState {
             .1
     }
 
+    pub fn get_latest_commitment_tx_info(&self) -> Option<CommitmentTxInfo> {
+        let transaction = self.latest_commitment_transaction.clone()?.into_view();
+        // The commitment lock args are composed of the pubkey hash (20 bytes),
+        // the delay epoch (8 bytes) and the big endian commitment number (8 bytes).
+        let args = transaction.output(0)?.lock().args().raw_data();
+        let commitment_number = u64::from_be_bytes(args.get(28..36)?.try_into().ok()?);
+        Some(CommitmentTxInfo {
+            commitment_number,
+            transaction,
+            settlement_data: self.latest_settlement_data.clone(),
+        })
+    }
+
+    pub fn track_force_close_transaction(&mut self, transaction: &Transaction) {
+        let tx_hash: Hash256 = transaction.calc_tx_hash().into();
+        if !self.pending_force_close_tx_hashes.contains(&tx_hash) {
+            self.pending_force_close_tx_hashes.push(tx_hash);
+        }
+    }
+
     pub fn get_local_shutdown_script(&self) -> Script {
         self.local_shutdown_script.clone()
     }
@@ -4396,6 +8552,11 @@ impl ChannelActorState {
         self.get_local_commitment_point(self.get_remote_commitment_number())
     }
 
+    /// Get the commitment point we send to the counterparty next.
+    pub fn next_commitment_point_to_send(&self) -> Pubkey {
+        self.get_local_commitment_point(self.get_remote_commitment_number())
+    }
+
     pub fn get_funding_lock_script_xonly_key(&self) -> XOnlyPublicKey {
         let pubkey: secp256k1::PublicKey = self.get_musig2_agg_context().aggregated_pubkey();
         pubkey.into()
@@ -4413,13 +8574,38 @@ impl ChannelActorState {
         get_script_by_contract(Contract::FundingLock, &pubkey_hash[0..20])
     }
 
+    // The CKB address of the funding lock script, useful to look up the funding cell in explorers.
+    pub fn get_funding_address(&self, network: NetworkType) -> String {
+        let payload = AddressPayload::from(self.get_funding_lock_script());
+        Address::new(network, payload, true).to_string()
+    }
+
+    // The amounts to fund by both parties, the pushed amount is funded by the opener
+    // until the funding transaction is confirmed.
+    fn get_funding_amounts(&self) -> (u128, u128) {
+        if self.funding_tx_confirmed_at.is_some() || self.push_amount == 0 {
+            (self.to_local_amount, self.to_remote_amount)
+        } else if self.is_acceptor {
+            (
+                self.to_local_amount - self.push_amount,
+                self.to_remote_amount + self.push_amount,
+            )
+        } else {
+            (
+                self.to_local_amount + self.push_amount,
+                self.to_remote_amount - self.push_amount,
+            )
+        }
+    }
+
     pub fn get_funding_request(&self) -> FundingRequest {
+        let (local_amount, remote_amount) = self.get_funding_amounts();
         FundingRequest {
             script: self.get_funding_lock_script(),
             udt_type_script: self.funding_udt_type_script.clone(),
-            local_amount: self.to_local_amount,
+            local_amount,
             funding_fee_rate: self.funding_fee_rate,
-            remote_amount: self.to_remote_amount,
+            remote_amount,
             local_reserved_ckb_amount: self.local_reserved_ckb_amount,
             remote_reserved_ckb_amount: self.remote_reserved_ckb_amount,
         }
@@ -4450,7 +8636,7 @@ impl ChannelActorState {
     }
 
     pub fn get_local_musig2_secnonce(&self) -> SecNonce {
-        self.signer
+        self.get_commitment_signer()
             .derive_musig2_nonce(self.get_local_commitment_number())
     }
 
@@ -4595,13 +8781,56 @@ impl ChannelActorState {
             .collect()
     }
 
-    fn get_active_htlcs(&self, local: bool) -> Vec<u8> {
+    // Whether the tlc is too small to be enforced on chain, the trimmed tlcs are not included
+    // in the commitment transactions, and their amounts stay in the offerer's balance of
+    // the settlement outputs until they are resolved off chain.
+    pub fn is_trimmed_tlc(&self, tlc: &AddTlcInfo) -> bool {
+        tlc.amount < self.tlc_dust_limit
+    }
+
+    // Only our offered tlcs are limited by the percentage of our balance, the peer isn't aware of
+    // our local policy, so limiting the received tlcs would reject the peer's valid AddTlc.
+    pub fn set_max_tlc_value_in_flight_percentage(&mut self, percentage: Option<u8>) {
+        self.local_constraints.max_tlc_value_in_flight_percentage = percentage;
+    }
+
+    pub fn is_hash_algorithm_allowed(&self, hash_algorithm: HashAlgorithm) -> bool {
+        self.allowed_hash_algorithms.is_empty()
+            || self.allowed_hash_algorithms.contains(&hash_algorithm)
+    }
+
+    pub fn check_feature_negotiated(&self, feature: FeatureBits) -> ProcessingChannelResult {
+        if !self.features.contains(feature) {
+            return Err(ProcessingChannelError::InvalidState(format!(
+                "Feature {:?} is not negotiated for channel {:?}",
+                feature,
+                self.get_id()
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_tlc_hash_algorithm(
+        &self,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<(), ProcessingChannelError> {
+        if !self.is_hash_algorithm_allowed(hash_algorithm) {
+            return Err(ProcessingChannelError::TlcHashAlgorithmNotAllowed(
+                hash_algorithm,
+            ));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn get_active_htlcs(&self, local: bool) -> Vec<u8> {
         // Build a sorted array of TLC so that both party can generate the same commitment transaction.
         let tlcs = {
             let (mut received_tlcs, mut offered_tlcs) = (
                 self.get_active_received_tlc_with_pubkeys(local),
                 self.get_active_offered_tlc_with_pubkeys(local),
             );
+            received_tlcs.retain(|(tlc, _, _)| !self.is_trimmed_tlc(tlc));
+            offered_tlcs.retain(|(tlc, _, _)| !self.is_trimmed_tlc(tlc));
             let (mut a, mut b) = if local {
                 (received_tlcs, offered_tlcs)
             } else {
@@ -4635,6 +8864,22 @@ impl ChannelActorState {
         }
     }
 
+    // Check the channel is created on the chain the node is running on, as the messages of the
+    // channel only carry the channel id.
+    fn check_chain_hash(&self) -> ProcessingChannelResult {
+        match self.chain_hash {
+            Some(chain_hash) if chain_hash != get_chain_hash() => {
+                Err(ProcessingChannelError::InvalidParameter(format!(
+                    "Channel {:?} is created on chain {:?}, but the node is running on chain {:?}",
+                    self.get_id(),
+                    chain_hash,
+                    get_chain_hash()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn any_tlc_pending(&self) -> bool {
         self.tlc_state
             .all_tlcs()
@@ -4681,8 +8926,8 @@ impl ChannelActorState {
         return fee <= remote_available_max_fee;
     }
 
-    fn check_tlc_expiry(&self, expiry: u64) -> ProcessingChannelResult {
-        let current_time = now_timestamp_as_millis_u64();
+    pub(crate) fn check_tlc_expiry(&self, expiry: u64) -> ProcessingChannelResult {
+        let current_time = self.clock.now_millis();
         if current_time >= expiry {
             debug!(
                 "TLC expiry {} is already passed, current time: {}",
@@ -4690,7 +8935,16 @@ impl ChannelActorState {
             );
             return Err(ProcessingChannelError::TlcExpirySoon);
         }
-        if expiry >= current_time + MAX_PAYMENT_TLC_EXPIRY_LIMIT {
+        if let Some(min_delta) = self.get_our_min_tlc_expiry_delta() {
+            if expiry < current_time + min_delta {
+                debug!(
+                    "TLC expiry {} is less than the minimal expiry delta {} of the channel, current time: {}",
+                    expiry, min_delta, current_time
+                );
+                return Err(ProcessingChannelError::TlcExpirySoon);
+            }
+        }
+        if expiry >= current_time + self.get_max_tlc_expiry_delta() {
             debug!(
                 "TLC expiry {} is too far in the future, current time: {}",
                 expiry, current_time
@@ -4707,7 +8961,9 @@ impl ChannelActorState {
         reason: &RemoveTlcReason,
     ) -> ProcessingChannelResult {
         if let Some(tlc) = self.tlc_state.get(&tlc_id) {
-            if tlc.removed_at.is_some() {
+            // A replayed RemoveTlc is rejected before the removal is applied as well, adding it
+            // again would settle the tlc twice.
+            if tlc.removed_at.is_some() || self.tlc_state.has_remove_tlc(&tlc_id) {
                 return Err(ProcessingChannelError::RepeatedProcessing(
                     "TLC is already removed".to_string(),
                 ));
@@ -4737,8 +8993,21 @@ impl ChannelActorState {
         if is_tlc_command_message && self.tlc_state.waiting_ack {
             return Err(ProcessingChannelError::WaitingTlcAck);
         }
+        if is_sent && add_tlc_amount.is_some() && self.pending_commitment_fee_rate.is_some() {
+            return Err(ProcessingChannelError::WaitingTlcAck);
+        }
         match self.state {
             ChannelState::ChannelReady() => {}
+            // All tlcs are already removed once the channel is dropping pending, and the shutdown
+            // transaction is built from the settled balances, so no tlc can be added or removed.
+            ChannelState::ShuttingDown(flags)
+                if flags.contains(ShuttingDownFlags::DROPPING_PENDING) =>
+            {
+                return Err(ProcessingChannelError::InvalidState(format!(
+                    "Channel is dropping pending in state {:?}, no tlc updates are allowed",
+                    self.state
+                )))
+            }
             ChannelState::ShuttingDown(_) if add_tlc_amount.is_none() => {}
             _ => {
                 return Err(ProcessingChannelError::InvalidState(format!(
@@ -4759,6 +9028,109 @@ impl ChannelActorState {
         Ok(())
     }
 
+    pub fn get_drain_status(&self) -> DrainStatus {
+        DrainStatus {
+            offered_tlcs_in_flight: self.get_all_offer_tlcs().count() as u64,
+            received_tlcs_in_flight: self.get_all_received_tlcs().count() as u64,
+        }
+    }
+
+    pub fn get_forwarding_policy(&self) -> Result<ForwardingPolicy, ProcessingChannelError> {
+        let info = self.public_channel_info.as_ref().ok_or_else(|| {
+            ProcessingChannelError::InvalidState(
+                "Forwarding policy is only available for public channels".to_string(),
+            )
+        })?;
+        Ok(ForwardingPolicy {
+            tlc_min_value: info.tlc_min_value,
+            tlc_expiry_delta: info.tlc_expiry_delta,
+            tlc_fee_proportional_millionths: info.tlc_fee_proportional_millionths,
+        })
+    }
+
+    pub fn get_static_channel_backup(&self) -> Result<StaticChannelBackup, ProcessingChannelError> {
+        let funding_outpoint = self.get_funding_transaction_outpoint().ok_or_else(|| {
+            ProcessingChannelError::InvalidState(
+                "Channel backup is only available after the funding transaction is built"
+                    .to_string(),
+            )
+        })?;
+        Ok(StaticChannelBackup {
+            channel_id: self.get_id(),
+            funding_outpoint,
+            local_channel_public_keys: self.get_local_channel_public_keys().clone(),
+            remote_pubkey: self.remote_pubkey,
+            remote_channel_public_keys: self.get_remote_channel_public_keys().clone(),
+            local_shutdown_script: self.get_local_shutdown_script(),
+            remote_shutdown_script: self.get_remote_shutdown_script(),
+        })
+    }
+
+    pub fn tlc_capacity_status(&self) -> TlcCapacityStatus {
+        let offered_tlc_count = self.get_all_offer_tlcs().count() as u64;
+        let offered_amount = self
+            .get_all_offer_tlcs()
+            .fold(0_u128, |sum, tlc| sum + tlc.amount);
+        let received_tlc_count = self.get_all_received_tlcs().count() as u64;
+        let received_amount = self
+            .get_all_received_tlcs()
+            .fold(0_u128, |sum, tlc| sum + tlc.amount);
+        TlcCapacityStatus {
+            offered_tlc_count,
+            offered_remaining_slots: self
+                .local_constraints
+                .max_tlc_number_in_flight
+                .saturating_sub(offered_tlc_count),
+            offered_remaining_value_in_flight: self
+                .local_constraints
+                .get_max_tlc_value_in_flight(self.to_local_amount)
+                .saturating_sub(offered_amount),
+            received_tlc_count,
+            received_remaining_slots: self
+                .remote_constraints
+                .max_tlc_number_in_flight
+                .saturating_sub(received_tlc_count),
+            received_remaining_value_in_flight: self
+                .remote_constraints
+                .max_tlc_value_in_flight
+                .saturating_sub(received_amount),
+        }
+    }
+
+    /// The largest amount of a single tlc we can offer right now, which is limited by our balance
+    /// excluding the offered tlcs and the channel reserve, the remaining value in flight and the
+    /// remaining tlc slots. The commitment fee is paid from the reserved ckb amount, so it doesn't
+    /// reduce the sendable amount. Returns 0 if no tlc can be offered.
+    pub fn max_sendable_amount(&self) -> u128 {
+        if !matches!(self.state, ChannelState::ChannelReady()) {
+            return 0;
+        }
+        let status = self.tlc_capacity_status();
+        if status.offered_remaining_slots == 0 {
+            return 0;
+        }
+        self.to_local_amount
+            .saturating_sub(self.get_offered_tlc_balance())
+            .saturating_sub(self.local_constraints.channel_reserve_amount)
+            .min(status.offered_remaining_value_in_flight)
+    }
+
+    /// The largest amount of a single tlc the peer can offer to us right now, which is limited by
+    /// the balance of the peer excluding the received tlcs, the remaining value in flight and the
+    /// remaining tlc slots. Returns 0 if no tlc can be received.
+    pub fn max_receivable_amount(&self) -> u128 {
+        if !matches!(self.state, ChannelState::ChannelReady()) {
+            return 0;
+        }
+        let status = self.tlc_capacity_status();
+        if status.received_remaining_slots == 0 {
+            return 0;
+        }
+        self.to_remote_amount
+            .saturating_sub(self.get_received_tlc_balance())
+            .min(status.received_remaining_value_in_flight)
+    }
+
     fn check_tlc_limits(
         &self,
         add_amount: u128,
@@ -4777,7 +9149,11 @@ impl ChannelActorState {
                 .get_all_offer_tlcs()
                 .fold(0_u128, |sum, tlc| sum + tlc.amount)
                 + add_amount;
-            if active_offered_amount > self.local_constraints.max_tlc_value_in_flight {
+            if active_offered_amount
+                > self
+                    .local_constraints
+                    .get_max_tlc_value_in_flight(self.to_local_amount)
+            {
                 return Err(ProcessingChannelError::TlcValueInflightExceedLimit);
             }
         } else {
@@ -4798,14 +9174,24 @@ impl ChannelActorState {
         Ok(())
     }
 
-    fn create_outbounding_tlc(&self, command: AddTlcCommand) -> AddTlcInfo {
+    fn create_outbounding_tlc(
+        &self,
+        command: AddTlcCommand,
+    ) -> Result<AddTlcInfo, ProcessingChannelError> {
+        if let Some(preimage) = command.payment_preimage {
+            let filled_payment_hash: Hash256 = command.hash_algorithm.hash(preimage).into();
+            if command.payment_hash != filled_payment_hash {
+                return Err(ProcessingChannelError::FinalIncorrectPreimage);
+            }
+        }
+
         let id = self.get_next_offering_tlc_id();
         assert!(
             self.get_offered_tlc(id).is_none(),
             "Must not have the same id in pending offered tlcs"
         );
 
-        AddTlcInfo {
+        Ok(AddTlcInfo {
             channel_id: self.get_id(),
             tlc_id: TLCId::Offered(id),
             amount: command.amount,
@@ -4813,14 +9199,16 @@ impl ChannelActorState {
             expiry: command.expiry,
             hash_algorithm: command.hash_algorithm,
             created_at: self.get_current_commitment_numbers(),
-            payment_preimage: None,
+            payment_preimage: command.payment_preimage,
             removed_at: None,
             onion_packet: command.onion_packet,
             shared_secret: command.shared_secret,
             previous_tlc: command
                 .previous_tlc
                 .map(|(channel_id, tlc_id)| (channel_id, TLCId::Received(tlc_id))),
-        }
+            custom_records: vec![],
+            next_hop: None,
+        })
     }
 
     fn create_inbounding_tlc(&self, message: AddTlc) -> Result<AddTlcInfo, ProcessingChannelError> {
@@ -4839,6 +9227,8 @@ impl ChannelActorState {
             payment_preimage: None,
             removed_at: None,
             previous_tlc: None,
+            custom_records: vec![],
+            next_hop: None,
         };
         Ok(tlc_info)
     }
@@ -4865,9 +9255,14 @@ impl ChannelActorState {
         psct: &PartiallySignedCommitmentTransaction,
     ) -> Result<(TransactionView, SettlementData), ProcessingChannelError> {
         let completed_commitment_tx = {
+            let message: [u8; 32] = psct.commitment_tx.hash().unpack();
+            trace_signing_message(
+                "commitment_funding",
+                &[("commitment_tx_raw", psct.commitment_tx.data().raw().as_slice())],
+                &message,
+            );
             let sign_ctx = Musig2SignContext::from(self);
-            let our_funding_tx_partial_signature =
-                sign_ctx.sign(psct.commitment_tx.hash().as_slice())?;
+            let our_funding_tx_partial_signature = sign_ctx.sign(message.as_slice())?;
             self.aggregate_partial_signatures_to_consume_funding_cell(
                 [
                     psct.funding_tx_partial_signature,
@@ -4913,6 +9308,17 @@ impl ChannelActorState {
                 ]
                 .concat(),
             );
+            trace_signing_message(
+                "commitment_settlement",
+                &[
+                    ("to_local_output", to_local_output.as_slice()),
+                    ("to_local_output_data", to_local_output_data.as_slice()),
+                    ("to_remote_output", to_remote_output.as_slice()),
+                    ("to_remote_output_data", to_remote_output_data.as_slice()),
+                    ("lock_script_args", &args[0..36]),
+                ],
+                &message,
+            );
             let sign_ctx = Musig2SignContext::from((self, false));
             let our_commitment_tx_partial_signature = sign_ctx.sign(message.as_slice())?;
 
@@ -4952,21 +9358,35 @@ impl ChannelActorState {
             }
         };
 
-        if !flags.contains(ShuttingDownFlags::AWAITING_PENDING_TLCS) || self.any_tlc_pending() {
-            debug!(
-                "Will not shutdown the channel because we require all tlcs resolved and both parties sent the Shutdown message, current state: {:?}, pending tlcs: {:?}",
-                &self.state,
-                &self.tlc_state.all_commited_tlcs().collect::<Vec<_>>()
-            );
-            return Ok(());
-        }
+        // Once dropping pending, tlc updates are rejected by `check_for_tlc_update`, so there is
+        // no need to check the pending tlcs again, e.g. when the ClosingSigned message arrives.
+        if !flags.contains(ShuttingDownFlags::DROPPING_PENDING) {
+            if !flags.contains(ShuttingDownFlags::AWAITING_PENDING_TLCS) || self.any_tlc_pending()
+            {
+                debug!(
+                    "Will not shutdown the channel because we require all tlcs resolved and both parties sent the Shutdown message, current state: {:?}, pending tlcs: {:?}",
+                    &self.state,
+                    &self.tlc_state.all_commited_tlcs().collect::<Vec<_>>()
+                );
+                return Ok(());
+            }
 
-        debug!("All pending tlcs are resolved, transitioning to Shutdown state");
-        self.update_state(ChannelState::ShuttingDown(
-            flags | ShuttingDownFlags::DROPPING_PENDING,
-        ));
+            debug!("All pending tlcs are resolved, transitioning to Shutdown state");
+            self.update_state(ChannelState::ShuttingDown(
+                flags | ShuttingDownFlags::DROPPING_PENDING,
+            ));
+        }
 
         if self.local_shutdown_info.is_some() && self.remote_shutdown_info.is_some() {
+            // The shutdown transaction must not change after we signed it, otherwise our
+            // signature can't be aggregated with the signature of the peer.
+            if self
+                .local_shutdown_info
+                .as_ref()
+                .is_some_and(|info| info.signature.is_none())
+            {
+                self.align_shutdown_fee_rates();
+            }
             let shutdown_tx = self.build_shutdown_tx()?;
             let sign_ctx = Musig2SignContext::from(&*self);
 
@@ -5009,9 +9429,10 @@ impl ChannelActorState {
                         [local_shutdown_signature, remote_shutdown_signature],
                         &shutdown_tx,
                     )?;
-                assert_eq!(
-                    tx.data().serialized_size_in_block(),
-                    shutdown_tx_size(&self.funding_udt_type_script, shutdown_scripts)
+                // The fee is calculated with both outputs, omitting a dust output shrinks the tx.
+                assert!(
+                    tx.data().serialized_size_in_block()
+                        <= shutdown_tx_size(&self.funding_udt_type_script, shutdown_scripts)
                 );
 
                 self.update_state(ChannelState::Closed(CloseFlags::COOPERATIVE));
@@ -5035,6 +9456,78 @@ impl ChannelActorState {
         Ok(())
     }
 
+    // Both parties may send the Shutdown message with their own fee rates at the same time,
+    // then none of them accepts the fee rate of the other. To converge on the same shutdown
+    // transaction, node1 pays its part of the fee at the higher of the two fee rates if it can
+    // afford it. Both parties apply the same rule with the settled balances before signing.
+    fn align_shutdown_fee_rates(&mut self) {
+        let local_is_node1 = self.local_is_node1();
+        let (Some(local), Some(remote)) = (
+            self.local_shutdown_info.as_ref(),
+            self.remote_shutdown_info.as_ref(),
+        ) else {
+            return;
+        };
+        // A zero fee rate means the shutdown is auto accepted, there is nothing to converge.
+        if local.fee_rate == 0 || remote.fee_rate == 0 {
+            return;
+        }
+        let fee_rate = local.fee_rate.max(remote.fee_rate);
+        let (node1_info, node2_info, node1_amount, node1_reserved_ckb_amount) = if local_is_node1
+        {
+            (local, remote, self.to_local_amount, self.local_reserved_ckb_amount)
+        } else {
+            (remote, local, self.to_remote_amount, self.remote_reserved_ckb_amount)
+        };
+        if node1_info.fee_rate == fee_rate {
+            return;
+        }
+
+        let fee = calculate_shutdown_tx_fee(
+            fee_rate,
+            &self.funding_udt_type_script,
+            (
+                node2_info.close_script.clone(),
+                node1_info.close_script.clone(),
+            ),
+        );
+        let Ok(occupied_capacity) =
+            occupied_capacity(&node1_info.close_script, &self.funding_udt_type_script)
+        else {
+            return;
+        };
+        let available_max_fee = if self.funding_udt_type_script.is_none() {
+            (node1_amount as u64 + node1_reserved_ckb_amount)
+                .saturating_sub(occupied_capacity.as_u64())
+        } else {
+            node1_reserved_ckb_amount.saturating_sub(occupied_capacity.as_u64())
+        };
+        if fee > available_max_fee {
+            debug!(
+                "Node1 can't afford the shutdown fee {} at fee rate {}, keep the fee rates",
+                fee, fee_rate
+            );
+            return;
+        }
+
+        debug!(
+            "Both parties sent Shutdown with different fee rates, node1 pays at fee rate {}",
+            fee_rate
+        );
+        let node1_info = if local_is_node1 {
+            self.local_shutdown_info.as_mut()
+        } else {
+            self.remote_shutdown_info.as_mut()
+        }
+        .expect("shutdown info exists");
+        node1_info.fee_rate = fee_rate;
+        // Our signature for the old fee rate is invalid now, sign and send the ClosingSigned
+        // message again. The signature of the remote node1 is always made after aligning.
+        if local_is_node1 {
+            node1_info.signature = None;
+        }
+    }
+
     fn handle_accept_channel_message(
         &mut self,
         accept_channel: AcceptChannel,
@@ -5050,7 +9543,7 @@ impl ChannelActorState {
             NegotiatingFundingFlags::INIT_SENT,
         ));
 
-        self.to_remote_amount = accept_channel.funding_amount;
+        self.to_remote_amount = accept_channel.funding_amount + self.push_amount;
         self.remote_reserved_ckb_amount = accept_channel.reserved_ckb_amount;
 
         self.save_remote_nonce(accept_channel.next_local_nonce.clone());
@@ -5069,6 +9562,32 @@ impl ChannelActorState {
 
         self.check_accept_channel_parameters()?;
 
+        self.allowed_hash_algorithms = negotiate_hash_algorithms(
+            &self.allowed_hash_algorithms,
+            &accept_channel.allowed_hash_algorithms,
+        )?;
+        self.features &= accept_channel.features;
+        if self.anchor_outputs {
+            self.check_feature_negotiated(FeatureBits::ANCHOR_OUTPUTS)?;
+        }
+        if self.sorted_settlement_outputs {
+            self.check_feature_negotiated(FeatureBits::SORTED_SETTLEMENT_OUTPUTS)?;
+        }
+
+        // The delay in `AcceptChannel` is applied to our commitment transactions, and also to the
+        // commitment transactions of the peer if it doesn't support asymmetric delays.
+        if accept_channel.commitment_delay_epoch != self.local_delay_epoch {
+            check_commitment_delay_epoch(accept_channel.commitment_delay_epoch)?;
+            debug!(
+                "Peer proposed local commitment delay epoch {}, previous value {}",
+                accept_channel.commitment_delay_epoch, self.local_delay_epoch
+            );
+            self.local_delay_epoch = accept_channel.commitment_delay_epoch;
+        }
+        if !self.features.contains(FeatureBits::ASYMMETRIC_DELAY_EPOCHS) {
+            self.remote_delay_epoch = self.local_delay_epoch;
+        }
+
         match accept_channel.channel_announcement_nonce {
             Some(ref nonce) if self.is_public() => {
                 debug!("Updating remote channel announcement nonce: {:?}", nonce);
@@ -5149,9 +9668,7 @@ impl ChannelActorState {
         };
         match msg {
             TxCollaborationMsg::TxUpdate(msg) => {
-                // TODO check if the tx is valid.
-                self.funding_tx = Some(msg.tx.clone());
-                if self.is_tx_final(&msg.tx)? {
+                if self.apply_remote_funding_tx_update(&msg.tx)? {
                     self.maybe_complete_tx_collaboration(msg.tx, network)?;
                 } else {
                     network
@@ -5173,6 +9690,7 @@ impl ChannelActorState {
                 let settlement_data = self.check_init_commitment_tx_signature(
                     tx_complete.commitment_tx_partial_signature,
                 )?;
+                self.record_settlement_data(false, 0, settlement_data.clone());
                 network
                     .send_message(NetworkActorMessage::new_notification(
                         NetworkServiceEvent::RemoteTxComplete(
@@ -5253,13 +9771,16 @@ impl ChannelActorState {
                     self.get_remote_peer_id(),
                     self.get_id(),
                     commitment_tx.clone(),
-                    settlement_data,
+                    settlement_data.clone(),
                 ),
             ))
             .expect(ASSUME_NETWORK_ACTOR_ALIVE);
 
         self.save_remote_nonce(commitment_signed.next_local_nonce);
         self.latest_commitment_transaction = Some(commitment_tx.data());
+        if let Some(info) = self.get_latest_commitment_tx_info() {
+            self.record_settlement_data(true, info.commitment_number, settlement_data);
+        }
         match flags {
             CommitmentSignedFlags::SigningCommitment(flags) => {
                 let flags = flags | SigningCommitmentFlags::THEIR_COMMITMENT_SIGNED_SENT;
@@ -5478,25 +9999,79 @@ impl ChannelActorState {
             .expect(ASSUME_NETWORK_ACTOR_ALIVE);
     }
 
+    fn get_max_remote_commitment_points(&self) -> usize {
+        self.max_remote_commitment_points
+            .unwrap_or((self.local_constraints.max_tlc_number_in_flight + 1) as usize)
+    }
+
+    // The points are looked up by `get_remote_commitment_point` with the local commitment number
+    // at which a tlc is created, so a point can only be pruned when no live tlc was created at its
+    // commitment number. The latest two points are always retained for the upcoming commitments.
     fn append_remote_commitment_point(&mut self, commitment_point: Pubkey) {
         self.remote_commitment_points
             .push((self.get_local_commitment_number(), commitment_point));
 
         let len = self.remote_commitment_points.len();
-        if len > (self.local_constraints.max_tlc_number_in_flight + 1) as usize {
-            let min_remote_commitment = self
+        if len > self.get_max_remote_commitment_points() {
+            let needed_commitments: HashSet<u64> = self
                 .tlc_state
                 .all_tlcs()
-                .map(|x| x.created_at.remote)
-                .min()
-                .unwrap_or_default();
-            self.remote_commitment_points
-                .retain(|(num, _)| *num >= min_remote_commitment);
+                .map(|tlc| tlc.created_at.local)
+                .collect();
+            let min_latest_commitment = self.remote_commitment_points[len - 2].0;
+            self.remote_commitment_points.retain(|(num, _)| {
+                *num >= min_latest_commitment || needed_commitments.contains(num)
+            });
+        }
+        assert!(
+            self.tlc_state.all_tlcs().all(|tlc| self
+                .remote_commitment_points
+                .iter()
+                .any(|(num, _)| *num == tlc.created_at.local)),
+            "remote commitment points needed by the live tlcs should be retained"
+        );
+    }
+
+    /// Check the secret revealed by the counterparty against the commitment point it has
+    /// committed for the same commitment number. The secret is not checked if the point is
+    /// already pruned.
+    pub(crate) fn verify_remote_commitment_secret(
+        &self,
+        commitment_number: u64,
+        secret: &[u8; 32],
+    ) -> ProcessingChannelResult {
+        let committed_point = self
+            .remote_commitment_points
+            .iter()
+            .find(|(number, _)| *number == commitment_number);
+        if let Some((_, point)) = committed_point {
+            if Privkey::from(secret).pubkey() != *point {
+                return Err(ProcessingChannelError::InvalidCommitmentSecret(
+                    commitment_number,
+                ));
+            }
         }
-        assert!(
-            self.remote_commitment_points.len()
-                <= (self.local_constraints.max_tlc_number_in_flight + 1) as usize
-        );
+        Ok(())
+    }
+
+    fn append_remote_commitment_secret(&mut self, commitment_number: u64, secret: [u8; 32]) {
+        self.remote_commitment_secrets.push((commitment_number, secret));
+        let min_remote_commitment = self
+            .remote_commitment_points
+            .iter()
+            .map(|(num, _)| *num)
+            .min()
+            .unwrap_or_default();
+        self.remote_commitment_secrets
+            .retain(|(num, _)| *num >= min_remote_commitment);
+    }
+
+    /// Get the secret revealed by the counterparty for the given revoked commitment number.
+    pub fn get_remote_commitment_secret(&self, commitment_number: u64) -> Option<[u8; 32]> {
+        self.remote_commitment_secrets
+            .iter()
+            .find(|(number, _)| *number == commitment_number)
+            .map(|(_, secret)| *secret)
     }
 
     fn handle_revoke_and_ack_peer_message(
@@ -5509,7 +10084,40 @@ impl ChannelActorState {
             revocation_partial_signature,
             commitment_tx_partial_signature,
             next_per_commitment_point,
+            last_per_commitment_secret,
         } = revoke_and_ack;
+        // A RevokeAndAck answers our last CommitmentSigned, a stale or duplicate one must not
+        // move the commitment numbers forward. The nonce is cleared once the RevokeAndAck is
+        // consumed, and set again by the next CommitmentSigned.
+        if self.last_used_nonce_in_commitment_signed.is_none() {
+            return Err(ProcessingChannelError::InvalidState(format!(
+                "Received unexpected RevokeAndAck message while no CommitmentSigned is awaiting revocation, our commitment_numbers {:?}",
+                self.commitment_numbers
+            )));
+        }
+        // The next commitment point is recorded for the commitment number after the revocation,
+        // which is never seen before.
+        let next_commitment_number = self.get_local_commitment_number() + 1;
+        if let Some((number, _)) = self
+            .remote_commitment_points
+            .iter()
+            .find(|(number, point)| {
+                *number >= next_commitment_number || *point == next_per_commitment_point
+            })
+        {
+            return Err(ProcessingChannelError::InvalidState(format!(
+                "Received RevokeAndAck message for commitment number {}, which is already revoked, expected commitment number {}",
+                number, next_commitment_number
+            )));
+        }
+        // Check before taking the nonces, so that no nonce is consumed on failures.
+        self.check_commitment_capacity()?;
+
+        // The commitment revoked by this message is the one before our current local commitment.
+        let revoked_commitment_number = self.get_local_commitment_number() - 1;
+        if let Some(secret) = &last_per_commitment_secret {
+            self.verify_remote_commitment_secret(revoked_commitment_number, secret)?;
+        }
 
         let key_agg_ctx = {
             let local_pubkey = self.get_local_channel_public_keys().funding_pubkey;
@@ -5519,7 +10127,7 @@ impl ChannelActorState {
         let x_only_aggregated_pubkey = key_agg_ctx.aggregated_pubkey::<Point>().serialize_xonly();
         let (verify_ctx, sign_ctx) = {
             let local_nonce = self.get_local_nonce();
-            let remote_nonce = self.take_remote_nonce_for_raa();
+            let remote_nonce = self.get_remote_nonce_for_raa();
             let nonces = [remote_nonce.clone(), local_nonce];
             let agg_nonce = AggNonce::sum(nonces);
 
@@ -5533,21 +10141,17 @@ impl ChannelActorState {
                 Musig2SignContext {
                     key_agg_ctx,
                     agg_nonce,
-                    seckey: self.signer.funding_key.clone(),
+                    signer: self.get_commitment_signer(),
                     secnonce: self.get_local_musig2_secnonce(),
                 },
             )
         };
 
         let revocation_data = {
-            let commitment_tx_fee = calculate_commitment_tx_fee(
-                self.commitment_fee_rate,
-                &self.funding_udt_type_script,
-            );
             let lock_script = self.get_local_shutdown_script();
             let (output, output_data) = if let Some(udt_type_script) = &self.funding_udt_type_script
             {
-                let capacity = self.get_total_reserved_ckb_amount() - commitment_tx_fee;
+                let capacity = self.get_commitment_output_capacity()?;
                 let output = CellOutput::new_builder()
                     .lock(lock_script)
                     .type_(Some(udt_type_script.clone()).pack())
@@ -5557,7 +10161,7 @@ impl ChannelActorState {
                 let output_data = self.get_total_udt_amount().to_le_bytes().pack();
                 (output, output_data)
             } else {
-                let capacity = self.get_total_ckb_amount() - commitment_tx_fee;
+                let capacity = self.get_commitment_output_capacity()?;
                 let output = CellOutput::new_builder()
                     .lock(lock_script)
                     .capacity(capacity.pack())
@@ -5570,7 +10174,7 @@ impl ChannelActorState {
 
             let commitment_lock_script_args = [
                 &blake2b_256(x_only_aggregated_pubkey)[0..20],
-                self.get_delay_epoch_as_lock_args_bytes().as_slice(),
+                self.get_delay_epoch_as_lock_args_bytes(true).as_slice(),
                 commitment_number.to_be_bytes().as_slice(),
             ]
             .concat();
@@ -5598,6 +10202,7 @@ impl ChannelActorState {
             }
         };
 
+        let settled_commitment_number = self.get_local_commitment_number();
         let settlement_data = {
             let (
                 [to_local_output, to_remote_output],
@@ -5605,8 +10210,8 @@ impl ChannelActorState {
             ) = self.build_settlement_transaction_outputs(true);
             let commitment_lock_script_args = [
                 &blake2b_256(x_only_aggregated_pubkey)[0..20],
-                self.get_delay_epoch_as_lock_args_bytes().as_slice(),
-                self.get_local_commitment_number().to_be_bytes().as_slice(),
+                self.get_delay_epoch_as_lock_args_bytes(true).as_slice(),
+                settled_commitment_number.to_be_bytes().as_slice(),
             ]
             .concat();
             let message = blake2b_256(
@@ -5636,8 +10241,14 @@ impl ChannelActorState {
             }
         };
 
+        // The nonce is kept until all the signatures are verified, so that an invalid
+        // RevokeAndAck does not prevent the valid one from being handled.
+        self.last_used_nonce_in_commitment_signed = None;
         self.increment_local_commitment_number();
         self.append_remote_commitment_point(next_per_commitment_point);
+        if let Some(secret) = last_per_commitment_secret {
+            self.append_remote_commitment_secret(revoked_commitment_number, secret);
+        }
 
         let staging_tlcs = self.tlc_state.commit_local_tlcs();
         for tlc in staging_tlcs {
@@ -5646,7 +10257,9 @@ impl ChannelActorState {
                     .expect("expect remove tlc successfully");
             }
         }
-        self.tlc_state.set_waiting_ack(false);
+        self.set_waiting_ack(network, false);
+        self.record_settlement_data(false, settled_commitment_number, settlement_data.clone());
+        self.latest_settlement_data = Some(settlement_data.clone());
 
         network
             .send_message(NetworkActorMessage::new_notification(
@@ -5766,6 +10379,12 @@ impl ChannelActorState {
                         expected_remote_commitment_number, acutal_remote_commitment_number
                     );
                 }
+
+                // The peer may not have received our UpdateFee message or we may not have
+                // received its ack, the peer acks again if it has already applied the fee rate.
+                if let Some(fee_rate) = self.pending_commitment_fee_rate {
+                    self.send_update_fee_message(fee_rate, false, network);
+                }
             }
             _ => {
                 // TODO: @quake we need to handle other states.
@@ -5778,16 +10397,17 @@ impl ChannelActorState {
         Ok(())
     }
 
-    fn is_tx_final(&self, tx: &Transaction) -> Result<bool, ProcessingChannelError> {
-        // TODO: check if the tx is valid
+    fn get_funding_cell_contribution(
+        &self,
+        tx: &Transaction,
+    ) -> Result<FundingContribution, ProcessingChannelError> {
         let tx = tx.clone().into_view();
 
-        let first_output = tx
-            .outputs()
-            .get(0)
-            .ok_or(ProcessingChannelError::InvalidParameter(
+        let (first_output, data) = tx.output_with_data(0).ok_or(
+            ProcessingChannelError::InvalidParameter(
                 "Funding transaction should have at least one output".to_string(),
-            ))?;
+            ),
+        )?;
 
         if first_output.lock() != self.get_funding_lock_script() {
             return Err(ProcessingChannelError::InvalidState(
@@ -5795,37 +10415,162 @@ impl ChannelActorState {
             ));
         }
 
-        let current_capacity: u64 = first_output.capacity().unpack();
+        let udt_amount = if self.funding_udt_type_script.is_some() {
+            let amount_bytes: [u8; 16] = data
+                .as_ref()
+                .get(0..16)
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or(ProcessingChannelError::InvalidParameter(
+                    "Funding cell data should contain the udt amount".to_string(),
+                ))?;
+            u128::from_le_bytes(amount_bytes)
+        } else {
+            0
+        };
+        Ok(FundingContribution {
+            capacity: first_output.capacity().unpack(),
+            udt_amount,
+        })
+    }
+
+    // The negotiated contributions of both parties to the funding cell, each party funds its
+    // amount and its reserved ckb.
+    fn get_negotiated_funding_contributions(&self) -> (FundingContribution, FundingContribution) {
+        let (local_amount, remote_amount) = self.get_funding_amounts();
+        if self.funding_udt_type_script.is_some() {
+            (
+                FundingContribution {
+                    capacity: self.local_reserved_ckb_amount,
+                    udt_amount: local_amount,
+                },
+                FundingContribution {
+                    capacity: self.remote_reserved_ckb_amount,
+                    udt_amount: remote_amount,
+                },
+            )
+        } else {
+            (
+                FundingContribution {
+                    capacity: local_amount as u64 + self.local_reserved_ckb_amount,
+                    udt_amount: 0,
+                },
+                FundingContribution {
+                    capacity: remote_amount as u64 + self.remote_reserved_ckb_amount,
+                    udt_amount: 0,
+                },
+            )
+        }
+    }
+
+    // The contribution of the peer after its funding transaction update, the increase of the
+    // funding cell from our last version of the funding transaction is funded by the peer.
+    fn get_remote_funding_contribution_after_update(
+        &self,
+        tx: &Transaction,
+    ) -> Result<FundingContribution, ProcessingChannelError> {
+        let new = self.get_funding_cell_contribution(tx)?;
+        let old = match self.funding_tx.as_ref() {
+            Some(old_tx) => self.get_funding_cell_contribution(old_tx)?,
+            None => FundingContribution::default(),
+        };
+        if new.capacity < old.capacity || new.udt_amount < old.udt_amount {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "Funding transaction update decreased the funding cell from {:?} to {:?}",
+                old, new
+            )));
+        }
+        Ok(FundingContribution {
+            capacity: self.remote_funding_contribution.capacity + (new.capacity - old.capacity),
+            udt_amount: self.remote_funding_contribution.udt_amount
+                + (new.udt_amount - old.udt_amount),
+        })
+    }
+
+    // The funding transaction is final when both parties have contributed exactly their
+    // negotiated amounts to the funding cell, and it's invalid if any of them contributed more.
+    fn is_tx_final(
+        &self,
+        tx: &Transaction,
+        remote_contribution: FundingContribution,
+    ) -> Result<bool, ProcessingChannelError> {
+        // TODO: check if the tx is valid
+        let total = self.get_funding_cell_contribution(tx)?;
+        let (local_negotiated, remote_negotiated) = self.get_negotiated_funding_contributions();
+        // The part of the funding cell not funded by the peer is funded by us.
+        let local_contribution = FundingContribution {
+            capacity: total.capacity.saturating_sub(remote_contribution.capacity),
+            udt_amount: total.udt_amount.saturating_sub(remote_contribution.udt_amount),
+        };
+        debug!(
+            "Funding contributions local: {:?} (negotiated {:?}), remote: {:?} (negotiated {:?})",
+            local_contribution, local_negotiated, remote_contribution, remote_negotiated
+        );
 
-        // make sure both parties have paid the reserved ckb amount
-        if current_capacity <= self.local_reserved_ckb_amount
-            || current_capacity <= self.remote_reserved_ckb_amount
+        if remote_contribution.capacity > remote_negotiated.capacity
+            || remote_contribution.udt_amount > remote_negotiated.udt_amount
         {
-            return Ok(false);
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "Remote funding contribution {:?} exceeds the negotiated amount {:?}",
+                remote_contribution, remote_negotiated
+            )));
+        }
+        if local_contribution.capacity > local_negotiated.capacity
+            || local_contribution.udt_amount > local_negotiated.udt_amount
+        {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "Local funding contribution {:?} exceeds the negotiated amount {:?}",
+                local_contribution, local_negotiated
+            )));
         }
+        Ok(local_contribution == local_negotiated && remote_contribution == remote_negotiated)
+    }
 
-        if self.funding_udt_type_script.is_some() {
-            let (_output, data) =
-                tx.output_with_data(0)
-                    .ok_or(ProcessingChannelError::InvalidParameter(
-                        "Funding transaction should have at least one output".to_string(),
-                    ))?;
-            assert!(data.as_ref().len() >= 16);
-            let mut amount_bytes = [0u8; 16];
-            amount_bytes.copy_from_slice(&data.as_ref()[0..16]);
-            let udt_amount = u128::from_le_bytes(amount_bytes);
-            debug!(
-                "udt_amount: {}, to_remote_amount: {}, to_local_amount: {}",
-                udt_amount, self.to_remote_amount, self.to_local_amount
-            );
-            debug!("current_capacity: {}, remote_reserved_ckb_amount: {}, local_reserved_ckb_amount: {}",
-                current_capacity, self.remote_reserved_ckb_amount, self.local_reserved_ckb_amount);
-            let is_udt_amount_ok = udt_amount == self.get_total_udt_amount();
-            return Ok(is_udt_amount_ok);
-        } else {
-            let is_complete = current_capacity == self.get_total_ckb_amount();
-            Ok(is_complete)
+    // Both parties contribute their inputs and outputs to the funding transaction in turn, the
+    // peer may only add its own ones, so all the inputs and the outputs (except the funding
+    // output) of the previous version must be kept intact.
+    pub(crate) fn check_funding_tx_update(&self, tx: &Transaction) -> ProcessingChannelResult {
+        let Some(old_tx) = self.funding_tx.as_ref() else {
+            return Ok(());
+        };
+        let old_tx = old_tx.clone().into_view();
+        let new_tx = tx.clone().into_view();
+
+        if let Some(input) = old_tx
+            .inputs()
+            .into_iter()
+            .find(|input| !new_tx.inputs().into_iter().any(|new_input| &new_input == input))
+        {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "Funding transaction update removed the input {:?}",
+                input.previous_output()
+            )));
+        }
+        let new_outputs = new_tx.outputs_with_data_iter().skip(1).collect::<Vec<_>>();
+        if let Some((output, _data)) = old_tx
+            .outputs_with_data_iter()
+            .skip(1)
+            .find(|output| !new_outputs.contains(output))
+        {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "Funding transaction update removed the output {:?}",
+                output
+            )));
         }
+        Ok(())
+    }
+
+    // Take the funding transaction updated by the peer after checking the contributions of both
+    // parties, returns whether the funding transaction is final.
+    pub(crate) fn apply_remote_funding_tx_update(
+        &mut self,
+        tx: &Transaction,
+    ) -> Result<bool, ProcessingChannelError> {
+        self.check_funding_tx_update(tx)?;
+        let remote_contribution = self.get_remote_funding_contribution_after_update(tx)?;
+        let is_final = self.is_tx_final(tx, remote_contribution)?;
+        self.funding_tx = Some(tx.clone());
+        self.remote_funding_contribution = remote_contribution;
+        Ok(is_final)
     }
 
     fn maybe_complete_tx_collaboration(
@@ -5833,7 +10578,7 @@ impl ChannelActorState {
         tx: Transaction,
         network: &ActorRef<NetworkActorMessage>,
     ) -> ProcessingChannelResult {
-        let is_complete = self.is_tx_final(&tx)?;
+        let is_complete = self.is_tx_final(&tx, self.remote_funding_contribution)?;
 
         debug!(
             "Checking if funding transaction {:?} is complete: {}",
@@ -5873,7 +10618,10 @@ impl ChannelActorState {
         Ok(())
     }
 
-    fn build_init_commitment_tx_signature(&self) -> Result<PartialSignature, SigningError> {
+    fn build_init_commitment_tx_signature(
+        &self,
+    ) -> Result<PartialSignature, ProcessingChannelError> {
+        self.check_commitment_capacity()?;
         let key_agg_ctx = {
             let local_pubkey = self.get_local_channel_public_keys().funding_pubkey;
             let remote_pubkey = self.get_remote_channel_public_keys().funding_pubkey;
@@ -5888,7 +10636,7 @@ impl ChannelActorState {
             Musig2SignContext {
                 key_agg_ctx,
                 agg_nonce,
-                seckey: self.signer.funding_key.clone(),
+                signer: self.get_commitment_signer(),
                 secnonce: self.get_local_musig2_secnonce(),
             }
         };
@@ -5897,7 +10645,7 @@ impl ChannelActorState {
         let version = 0u64;
         let commitment_lock_script_args = [
             &blake2b_256(x_only_aggregated_pubkey)[0..20],
-            self.get_delay_epoch_as_lock_args_bytes().as_slice(),
+            self.get_delay_epoch_as_lock_args_bytes(false).as_slice(),
             version.to_be_bytes().as_slice(),
         ]
         .concat();
@@ -5911,14 +10659,26 @@ impl ChannelActorState {
             ]
             .concat(),
         );
+        trace_signing_message(
+            "init_commitment_settlement",
+            &[
+                ("to_local_output", to_local_output.as_slice()),
+                ("to_local_output_data", to_local_output_data.as_slice()),
+                ("to_remote_output", to_remote_output.as_slice()),
+                ("to_remote_output_data", to_remote_output_data.as_slice()),
+                ("lock_script_args", commitment_lock_script_args.as_slice()),
+            ],
+            &message,
+        );
 
-        sign_ctx.sign(message.as_slice())
+        Ok(sign_ctx.sign(message.as_slice())?)
     }
 
     fn check_init_commitment_tx_signature(
         &self,
         signature: PartialSignature,
     ) -> Result<SettlementData, ProcessingChannelError> {
+        self.check_commitment_capacity()?;
         let key_agg_ctx = {
             let local_pubkey = self.get_local_channel_public_keys().funding_pubkey;
             let remote_pubkey = self.get_remote_channel_public_keys().funding_pubkey;
@@ -5940,7 +10700,7 @@ impl ChannelActorState {
         let version = 0u64;
         let commitment_lock_script_args = [
             &blake2b_256(x_only_aggregated_pubkey)[0..20],
-            self.get_delay_epoch_as_lock_args_bytes().as_slice(),
+            self.get_delay_epoch_as_lock_args_bytes(true).as_slice(),
             version.to_be_bytes().as_slice(),
         ]
         .concat();
@@ -5961,7 +10721,7 @@ impl ChannelActorState {
             let sign_ctx = Musig2SignContext {
                 key_agg_ctx,
                 agg_nonce,
-                seckey: self.signer.funding_key.clone(),
+                signer: self.get_commitment_signer(),
                 secnonce: self.get_local_musig2_secnonce(),
             };
 
@@ -5983,6 +10743,25 @@ impl ChannelActorState {
         Ok(settlement_data)
     }
 
+    // The funding can only be aborted before we send out our signatures of the funding
+    // transaction, after that the peer may broadcast it at any time.
+    pub fn check_funding_abortable(&self) -> ProcessingChannelResult {
+        match self.state {
+            ChannelState::NegotiatingFunding(_)
+            | ChannelState::CollaboratingFundingTx(_)
+            | ChannelState::SigningCommitment(_) => Ok(()),
+            ChannelState::AwaitingTxSignatures(flags)
+                if !flags.contains(AwaitingTxSignaturesFlags::OUR_TX_SIGNATURES_SENT) =>
+            {
+                Ok(())
+            }
+            _ => Err(ProcessingChannelError::InvalidState(format!(
+                "Unable to abort funding in state {:?}",
+                &self.state
+            ))),
+        }
+    }
+
     // TODO: More checks to the funding tx.
     fn check_tx_complete_preconditions(&mut self) -> ProcessingChannelResult {
         match self.funding_tx.as_ref() {
@@ -5996,7 +10775,7 @@ impl ChannelActorState {
                     "Received TxComplete message, funding tx is present {:?}",
                     tx
                 );
-                let check = self.is_tx_final(tx);
+                let check = self.is_tx_final(tx, self.remote_funding_contribution);
                 if !check.is_ok_and(|ok| ok) {
                     return Err(ProcessingChannelError::InvalidState(
                         "Received TxComplete message, but funding tx is not final".to_string(),
@@ -6046,7 +10825,7 @@ impl ChannelActorState {
                 && self.should_local_go_first_in_musig2()
     }
 
-    fn build_shutdown_tx(&self) -> Result<TransactionView, ProcessingChannelError> {
+    pub(crate) fn build_shutdown_tx(&self) -> Result<TransactionView, ProcessingChannelError> {
         let local_shutdown_info = self
             .local_shutdown_info
             .as_ref()
@@ -6087,73 +10866,129 @@ impl ChannelActorState {
                 .build(),
         );
 
-        if let Some(type_script) = &self.funding_udt_type_script {
-            debug!(
-                "shutdown UDT local_amount: {}, remote_amount: {}",
-                self.to_local_amount, self.to_remote_amount
-            );
+        let (to_local_output, to_local_output_data, to_remote_output, to_remote_output_data) =
+            if let Some(type_script) = &self.funding_udt_type_script {
+                debug!(
+                    "shutdown UDT local_amount: {}, remote_amount: {}",
+                    self.to_local_amount, self.to_remote_amount
+                );
 
-            let local_capacity: u64 = self.local_reserved_ckb_amount - local_shutdown_fee;
-            debug!(
-                "shutdown_tx local_capacity: {} - {} = {}",
-                self.local_reserved_ckb_amount, local_shutdown_fee, local_capacity
-            );
-            let to_local_output = CellOutput::new_builder()
-                .lock(local_shutdown_script)
-                .type_(Some(type_script.clone()).pack())
-                .capacity(local_capacity.pack())
-                .build();
-            let to_local_output_data = self.to_local_amount.to_le_bytes().pack();
+                let local_capacity: u64 =
+                    self.local_reserved_ckb_amount.saturating_sub(local_shutdown_fee);
+                debug!(
+                    "shutdown_tx local_capacity: {} - {} = {}",
+                    self.local_reserved_ckb_amount, local_shutdown_fee, local_capacity
+                );
+                let to_local_output = CellOutput::new_builder()
+                    .lock(local_shutdown_script)
+                    .type_(Some(type_script.clone()).pack())
+                    .capacity(local_capacity.pack())
+                    .build();
+                let to_local_output_data = self.to_local_amount.to_le_bytes().pack();
 
-            let remote_capacity: u64 = self.remote_reserved_ckb_amount - remote_shutdown_fee;
-            debug!(
-                "shutdown_tx remote_capacity: {} - {} = {}",
-                self.remote_reserved_ckb_amount, remote_shutdown_fee, remote_capacity
-            );
-            let to_remote_output = CellOutput::new_builder()
-                .lock(remote_shutdown_script)
-                .type_(Some(type_script.clone()).pack())
-                .capacity(remote_capacity.pack())
-                .build();
-            let to_remote_output_data = self.to_remote_amount.to_le_bytes().pack();
+                let remote_capacity: u64 =
+                    self.remote_reserved_ckb_amount.saturating_sub(remote_shutdown_fee);
+                debug!(
+                    "shutdown_tx remote_capacity: {} - {} = {}",
+                    self.remote_reserved_ckb_amount, remote_shutdown_fee, remote_capacity
+                );
+                let to_remote_output = CellOutput::new_builder()
+                    .lock(remote_shutdown_script)
+                    .type_(Some(type_script.clone()).pack())
+                    .capacity(remote_capacity.pack())
+                    .build();
+                let to_remote_output_data = self.to_remote_amount.to_le_bytes().pack();
+                (
+                    to_local_output,
+                    to_local_output_data,
+                    to_remote_output,
+                    to_remote_output_data,
+                )
+            } else {
+                debug!(
+                    "Final balance partition before shutting down: local {} (fee {}), remote {} (fee {})",
+                    self.to_local_amount, local_shutdown_fee,
+                    self.to_remote_amount, remote_shutdown_fee
+                );
+                let local_value = (self.to_local_amount as u64 + self.local_reserved_ckb_amount)
+                    .saturating_sub(local_shutdown_fee);
+                let remote_value = (self.to_remote_amount as u64 + self.remote_reserved_ckb_amount)
+                    .saturating_sub(remote_shutdown_fee);
+                debug!(
+                    "Building shutdown transaction with values: local {}, remote {}",
+                    local_value, remote_value
+                );
+                let to_local_output = CellOutput::new_builder()
+                    .capacity(local_value.pack())
+                    .lock(local_shutdown_script)
+                    .build();
+                let to_remote_output = CellOutput::new_builder()
+                    .capacity(remote_value.pack())
+                    .lock(remote_shutdown_script)
+                    .build();
+                (
+                    to_local_output,
+                    Default::default(),
+                    to_remote_output,
+                    Default::default(),
+                )
+            };
 
-            let outputs = self.order_things_for_musig2(to_local_output, to_remote_output);
-            let outputs_data =
-                self.order_things_for_musig2(to_local_output_data, to_remote_output_data);
-            let tx = tx_builder
-                .set_outputs(outputs.to_vec())
-                .set_outputs_data(outputs_data.to_vec())
-                .build();
-            Ok(tx)
-        } else {
-            debug!(
-                "Final balance partition before shutting down: local {} (fee {}), remote {} (fee {})",
-                self.to_local_amount, local_shutdown_fee,
-                self.to_remote_amount, remote_shutdown_fee
-            );
-            let local_value =
-                self.to_local_amount as u64 + self.local_reserved_ckb_amount - local_shutdown_fee;
-            let remote_value = self.to_remote_amount as u64 + self.remote_reserved_ckb_amount
-                - remote_shutdown_fee;
-            debug!(
-                "Building shutdown transaction with values: local {}, remote {}",
-                local_value, remote_value
-            );
-            let to_local_output = CellOutput::new_builder()
-                .capacity(local_value.pack())
-                .lock(local_shutdown_script)
-                .build();
-            let to_remote_output = CellOutput::new_builder()
-                .capacity(remote_value.pack())
-                .lock(remote_shutdown_script)
-                .build();
-            let outputs = self.order_things_for_musig2(to_local_output, to_remote_output);
-            let tx = tx_builder
-                .set_outputs(outputs.to_vec())
-                .set_outputs_data(vec![Default::default(), Default::default()])
-                .build();
-            Ok(tx)
+        // A party with negligible balance may not afford the cell of its output after paying
+        // the fee, such an output is omitted and its capacity is left to the fee.
+        let is_local_output_dust =
+            self.is_dust_shutdown_output(&to_local_output, self.to_local_amount)?;
+        let is_remote_output_dust =
+            self.is_dust_shutdown_output(&to_remote_output, self.to_remote_amount)?;
+        let (outputs, outputs_data) = match (is_local_output_dust, is_remote_output_dust) {
+            (false, false) => (
+                self.order_things_for_musig2(to_local_output, to_remote_output)
+                    .to_vec(),
+                self.order_things_for_musig2(to_local_output_data, to_remote_output_data)
+                    .to_vec(),
+            ),
+            (true, false) => {
+                debug!("Omitting the dust local output from the shutdown transaction");
+                (vec![to_remote_output], vec![to_remote_output_data])
+            }
+            (false, true) => {
+                debug!("Omitting the dust remote output from the shutdown transaction");
+                (vec![to_local_output], vec![to_local_output_data])
+            }
+            (true, true) => {
+                return Err(ProcessingChannelError::InvalidState(
+                    "Both outputs of the shutdown transaction are below their occupied capacities"
+                        .to_string(),
+                ));
+            }
+        };
+        let tx = tx_builder
+            .set_outputs(outputs)
+            .set_outputs_data(outputs_data)
+            .build();
+        Ok(tx)
+    }
+
+    // Whether the shutdown output can't cover the occupied capacity of its cell, only the
+    // outputs without UDT amount can be omitted, otherwise the UDT would be burnt.
+    fn is_dust_shutdown_output(
+        &self,
+        output: &CellOutput,
+        amount: u128,
+    ) -> Result<bool, ProcessingChannelError> {
+        let capacity: u64 = output.capacity().unpack();
+        let occupied_capacity =
+            occupied_capacity(&output.lock(), &self.funding_udt_type_script)?.as_u64();
+        if capacity >= occupied_capacity {
+            return Ok(false);
+        }
+        if self.funding_udt_type_script.is_some() && amount > 0 {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "Shutdown output capacity {} is below the occupied capacity {} while holding UDT amount {}",
+                capacity, occupied_capacity, amount
+            )));
         }
+        Ok(true)
     }
 
     // The parameter `for_remote` here specifies whether we are building the commitment transaction
@@ -6175,6 +11010,7 @@ impl ChannelActorState {
             let cell_deps =
                 get_cell_deps(vec![Contract::FundingLock], &self.funding_udt_type_script);
             let (output, output_data) = self.build_commitment_transaction_output(for_remote);
+            let anchor_outputs = self.build_anchor_outputs(for_remote);
 
             TransactionBuilder::default()
                 .cell_deps(cell_deps)
@@ -6185,6 +11021,8 @@ impl ChannelActorState {
                 )
                 .output(output)
                 .output_data(output_data)
+                .outputs(anchor_outputs.clone())
+                .outputs_data(vec![Bytes::default(); anchor_outputs.len()])
                 .build()
         };
 
@@ -6218,7 +11056,7 @@ impl ChannelActorState {
 
         let mut commitment_lock_script_args = [
             &blake2b_256(x_only_aggregated_pubkey)[0..20],
-            self.get_delay_epoch_as_lock_args_bytes().as_slice(),
+            self.get_delay_epoch_as_lock_args_bytes(for_remote).as_slice(),
             version.to_be_bytes().as_slice(),
         ]
         .concat();
@@ -6229,12 +11067,10 @@ impl ChannelActorState {
         let commitment_lock_script =
             get_script_by_contract(Contract::CommitmentLock, &commitment_lock_script_args);
 
-        let commitment_tx_fee =
-            calculate_commitment_tx_fee(self.commitment_fee_rate, &self.funding_udt_type_script);
-
         if let Some(udt_type_script) = &self.funding_udt_type_script {
-            let capacity = self.local_reserved_ckb_amount + self.remote_reserved_ckb_amount
-                - commitment_tx_fee;
+            let capacity = self
+                .get_commitment_output_capacity()
+                .expect("commitment capacity checked");
             let output = CellOutput::new_builder()
                 .lock(commitment_lock_script)
                 .type_(Some(udt_type_script.clone()).pack())
@@ -6244,7 +11080,9 @@ impl ChannelActorState {
             let output_data = self.get_total_udt_amount().to_le_bytes().pack();
             (output, output_data)
         } else {
-            let capacity = self.get_total_ckb_amount() - commitment_tx_fee;
+            let capacity = self
+                .get_commitment_output_capacity()
+                .expect("commitment capacity checked");
             let output = CellOutput::new_builder()
                 .lock(commitment_lock_script)
                 .capacity(capacity.pack())
@@ -6254,6 +11092,27 @@ impl ChannelActorState {
         }
     }
 
+    // The anchor outputs of the commitment transaction, the anchor of the counterparty goes first,
+    // followed by the anchor of the holder of the commitment transaction.
+    fn build_anchor_outputs(&self, for_remote: bool) -> Vec<CellOutput> {
+        if !self.anchor_outputs {
+            return vec![];
+        }
+        let local_anchor_output = CellOutput::new_builder()
+            .lock(self.get_local_shutdown_script())
+            .capacity(self.get_anchor_capacity(true).pack())
+            .build();
+        let remote_anchor_output = CellOutput::new_builder()
+            .lock(self.get_remote_shutdown_script())
+            .capacity(self.get_anchor_capacity(false).pack())
+            .build();
+        if for_remote {
+            vec![local_anchor_output, remote_anchor_output]
+        } else {
+            vec![remote_anchor_output, local_anchor_output]
+        }
+    }
+
     fn get_commitment_lock_script_xonly(&self, for_remote: bool) -> [u8; 32] {
         let local_pubkey = self.get_local_channel_public_keys().funding_pubkey;
         let remote_pubkey = self.get_remote_channel_public_keys().funding_pubkey;
@@ -6271,14 +11130,41 @@ impl ChannelActorState {
     fn build_settlement_transaction_outputs(
         &self,
         for_remote: bool,
+    ) -> ([CellOutput; 2], [Bytes; 2]) {
+        let ([first_output, second_output], [first_output_data, second_output_data]) =
+            self.build_unsorted_settlement_transaction_outputs(for_remote);
+        if self.sorted_settlement_outputs
+            && settlement_output_sort_key(&second_output, &second_output_data)
+                < settlement_output_sort_key(&first_output, &first_output_data)
+        {
+            (
+                [second_output, first_output],
+                [second_output_data, first_output_data],
+            )
+        } else {
+            (
+                [first_output, second_output],
+                [first_output_data, second_output_data],
+            )
+        }
+    }
+
+    fn build_unsorted_settlement_transaction_outputs(
+        &self,
+        for_remote: bool,
     ) -> ([CellOutput; 2], [Bytes; 2]) {
         let offered_fulfilled = self.get_pending_fulfilled_tlcs_amount(for_remote, true);
         let received_fulfilled = self.get_pending_fulfilled_tlcs_amount(for_remote, false);
 
         let to_local_value = self.to_local_amount - offered_fulfilled + received_fulfilled;
         let to_remote_value = self.to_remote_amount - received_fulfilled + offered_fulfilled;
-        let commitment_tx_fee =
-            calculate_commitment_tx_fee(self.commitment_fee_rate, &self.funding_udt_type_script);
+        // each party pays for its own anchor output and the commitment fee from the reserved amount
+        let local_reserved_ckb_amount = self
+            .get_settlement_reserved_ckb_amount(true)
+            .expect("commitment capacity checked");
+        let remote_reserved_ckb_amount = self
+            .get_settlement_reserved_ckb_amount(false)
+            .expect("commitment capacity checked");
 
         let to_local_output_script = self.get_local_shutdown_script();
         let to_remote_output_script = self.get_remote_shutdown_script();
@@ -6288,14 +11174,14 @@ impl ChannelActorState {
             let to_local_output = CellOutput::new_builder()
                 .lock(to_local_output_script)
                 .type_(Some(udt_type_script.clone()).pack())
-                .capacity((self.local_reserved_ckb_amount - commitment_tx_fee).pack())
+                .capacity(local_reserved_ckb_amount.pack())
                 .build();
             let to_local_output_data = to_local_value.to_le_bytes().pack();
 
             let to_remote_output = CellOutput::new_builder()
                 .lock(to_remote_output_script)
                 .type_(Some(udt_type_script.clone()).pack())
-                .capacity((self.remote_reserved_ckb_amount - commitment_tx_fee).pack())
+                .capacity(remote_reserved_ckb_amount.pack())
                 .build();
             let to_remote_output_data = to_remote_value.to_le_bytes().pack();
             if for_remote {
@@ -6312,19 +11198,13 @@ impl ChannelActorState {
         } else {
             let to_local_output = CellOutput::new_builder()
                 .lock(to_local_output_script)
-                .capacity(
-                    (to_local_value as u64 + self.local_reserved_ckb_amount - commitment_tx_fee)
-                        .pack(),
-                )
+                .capacity((to_local_value as u64 + local_reserved_ckb_amount).pack())
                 .build();
             let to_local_output_data = Bytes::default();
 
             let to_remote_output = CellOutput::new_builder()
                 .lock(to_remote_output_script)
-                .capacity(
-                    (to_remote_value as u64 + self.remote_reserved_ckb_amount - commitment_tx_fee)
-                        .pack(),
-                )
+                .capacity((to_remote_value as u64 + remote_reserved_ckb_amount).pack())
                 .build();
             let to_remote_output_data = Bytes::default();
             if for_remote {
@@ -6341,18 +11221,31 @@ impl ChannelActorState {
         }
     }
 
+    // Both parties must order the tlcs in the commitment lock identically, otherwise the
+    // signatures from the peer are invalid. Log the tlcs in debug builds to diagnose the mismatch.
+    fn log_active_htlcs_on_verify_failure(&self) {
+        if cfg!(debug_assertions) {
+            error!(
+                "Failed to verify the commitment signatures of channel {:?}, active htlcs of the local commitment: {}, of the remote commitment: {}",
+                self.get_id(),
+                hex::encode(self.get_active_htlcs(true)),
+                hex::encode(self.get_active_htlcs(false)),
+            );
+        }
+    }
+
     pub fn build_and_verify_commitment_tx(
         &self,
         funding_tx_partial_signature: PartialSignature,
         commitment_tx_partial_signature: PartialSignature,
     ) -> Result<PartiallySignedCommitmentTransaction, ProcessingChannelError> {
+        self.check_commitment_capacity()?;
         let (commitment_tx, settlement_tx) = self.build_commitment_and_settlement_tx(false);
 
         let verify_ctx = Musig2VerifyContext::from(self);
-        verify_ctx.verify(
-            funding_tx_partial_signature,
-            commitment_tx.hash().as_slice(),
-        )?;
+        verify_ctx
+            .verify(funding_tx_partial_signature, commitment_tx.hash().as_slice())
+            .inspect_err(|_| self.log_active_htlcs_on_verify_failure())?;
 
         let verify_ctx = Musig2VerifyContext::from((self, false));
         let to_local_output = settlement_tx
@@ -6388,7 +11281,9 @@ impl ChannelActorState {
             ]
             .concat(),
         );
-        verify_ctx.verify(commitment_tx_partial_signature, message.as_slice())?;
+        verify_ctx
+            .verify(commitment_tx_partial_signature, message.as_slice())
+            .inspect_err(|_| self.log_active_htlcs_on_verify_failure())?;
 
         Ok(PartiallySignedCommitmentTransaction {
             version: self.get_current_commitment_number(false),
@@ -6399,15 +11294,49 @@ impl ChannelActorState {
         })
     }
 
-    fn build_and_sign_commitment_tx(
-        &self,
+    pub(crate) fn build_and_sign_commitment_tx(
+        &mut self,
     ) -> Result<(PartialSignature, PartialSignature), ProcessingChannelError> {
+        self.check_commitment_capacity()?;
         let (commitment_tx, settlement_tx) = self.build_commitment_and_settlement_tx(true);
 
-        let sign_ctx = Musig2SignContext::from(self);
-        let funding_tx_partial_signature = sign_ctx.sign(commitment_tx.hash().as_slice())?;
+        // Reuse the partial signatures if we have already signed exactly the same transactions
+        // with the same nonce for this commitment number, e.g. when resending CommitmentSigned
+        // while reestablishing the channel. Any change of the tlcs or balances changes the
+        // fingerprint and invalidates the cache.
+        let commitment_number = self.get_remote_commitment_number();
+        let fingerprint = blake2b_256(
+            [
+                commitment_tx.hash().as_slice(),
+                settlement_tx.hash().as_slice(),
+                self.get_remote_nonce().serialize().as_slice(),
+            ]
+            .concat(),
+        );
+        if let Some(cache) = &self.commitment_signed_cache {
+            if cache.commitment_number == commitment_number && cache.fingerprint == fingerprint {
+                debug!(
+                    "Reusing cached commitment signatures for channel {:?} commitment number {}",
+                    self.get_id(),
+                    commitment_number
+                );
+                return Ok((
+                    cache.funding_tx_partial_signature,
+                    cache.commitment_tx_partial_signature,
+                ));
+            }
+        }
+
+        let message: [u8; 32] = commitment_tx.hash().unpack();
+        trace_signing_message(
+            "commitment_signed_funding",
+            &[("commitment_tx_raw", commitment_tx.data().raw().as_slice())],
+            &message,
+        );
+        let sign_ctx = Musig2SignContext::from(&*self);
+        let funding_tx_partial_signature = sign_ctx.sign(message.as_slice())?;
 
-        let sign_ctx = Musig2SignContext::from((self, true));
+        let sign_ctx = Musig2SignContext::from((&*self, true));
         let to_local_output = settlement_tx
             .outputs()
             .get(0)
@@ -6441,9 +11370,27 @@ impl ChannelActorState {
             ]
             .concat(),
         );
+        trace_signing_message(
+            "commitment_signed_settlement",
+            &[
+                ("to_local_output", to_local_output.as_slice()),
+                ("to_local_output_data", to_local_output_data.as_slice()),
+                ("to_remote_output", to_remote_output.as_slice()),
+                ("to_remote_output_data", to_remote_output_data.as_slice()),
+                ("lock_script_args", &args[0..36]),
+            ],
+            &message,
+        );
 
         let commitment_tx_partial_signature = sign_ctx.sign(message.as_slice())?;
 
+        self.commitment_signed_cache = Some(CommitmentSignedCache {
+            commitment_number,
+            fingerprint,
+            funding_tx_partial_signature,
+            commitment_tx_partial_signature,
+        });
+
         Ok((
             funding_tx_partial_signature,
             commitment_tx_partial_signature,
@@ -6464,12 +11411,15 @@ impl ChannelActorState {
         self.complete_partially_signed_tx(&tx)
     }
 
-    fn get_delay_epoch_as_lock_args_bytes(&self) -> [u8; 8] {
-        let since = Since::new(
-            SinceType::EpochNumberWithFraction,
-            self.commitment_delay_epoch,
-            true,
-        );
+    // The delay of the commitment transaction held by the remote peer if `for_remote` is true,
+    // otherwise the delay of our commitment transaction.
+    fn get_delay_epoch_as_lock_args_bytes(&self, for_remote: bool) -> [u8; 8] {
+        let delay_epoch = if for_remote {
+            self.remote_delay_epoch
+        } else {
+            self.local_delay_epoch
+        };
+        let since = Since::new(SinceType::EpochNumberWithFraction, delay_epoch, true);
         since.value().to_le_bytes()
     }
 }
@@ -6497,7 +11447,39 @@ pub trait ChannelActorStateStore {
             .filter(|(_, _, state)| !state.is_closed())
             .collect()
     }
+    fn get_channel_summaries_by_peer(&self, peer_id: &PeerId) -> Vec<ChannelSummary> {
+        self.get_channel_ids_by_peer(peer_id)
+            .into_iter()
+            .filter_map(|id| self.get_channel_actor_state(&id))
+            .map(|state| state.channel_summary())
+            .collect()
+    }
     fn get_channel_state_by_outpoint(&self, id: &OutPoint) -> Option<ChannelActorState>;
+    fn insert_closed_channel_record(&self, record: ClosedChannelRecord);
+    fn get_closed_channel_records(&self) -> Vec<ClosedChannelRecord>;
+    fn insert_restored_channel_backup(&self, backup: StaticChannelBackup);
+    fn get_restored_channel_backups(&self) -> Vec<StaticChannelBackup>;
+    fn insert_settlement_data(
+        &self,
+        channel_id: Hash256,
+        local: bool,
+        commitment_number: u64,
+        settlement_data: SettlementData,
+    );
+    /// Get the settlement data of our (`local`) or the peer's commitment with the given
+    /// commitment number, which can be used to settle the commitment transaction broadcasted
+    /// on chain.
+    ///
+    /// The aggregated signatures of the settlement outputs can't be rebuilt once the musig2
+    /// nonces are consumed, so the settlement data are retained while the commitments are
+    /// signed, at most MAX_SETTLEMENT_DATA_HISTORY_LENGTH commitments for each party. `None` is
+    /// returned for the pruned old commitments.
+    fn get_settlement_data(
+        &self,
+        channel_id: &Hash256,
+        local: bool,
+        commitment_number: u64,
+    ) -> Option<SettlementData>;
 }
 
 /// A wrapper on CommitmentTransaction that has a partial signature along with
@@ -6587,19 +11569,14 @@ impl Musig2VerifyContext {
 pub struct Musig2SignContext {
     key_agg_ctx: KeyAggContext,
     agg_nonce: AggNonce,
-    seckey: Privkey,
+    signer: Arc<dyn CommitmentSigner>,
     secnonce: SecNonce,
 }
 
 impl Musig2SignContext {
     pub fn sign(self, message: &[u8]) -> Result<PartialSignature, SigningError> {
-        sign_partial(
-            &self.key_agg_ctx,
-            self.seckey,
-            self.secnonce.clone(),
-            &self.agg_nonce,
-            message,
-        )
+        self.signer
+            .sign_partial(&self.key_agg_ctx, self.secnonce, &self.agg_nonce, message)
     }
 }
 
@@ -6669,8 +11646,9 @@ pub fn derive_tlc_pubkey(base_key: &Pubkey, commitment_point: &Pubkey) -> Pubkey
 #[derive(Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct InMemorySigner {
     /// Holder secret key in the 2-of-2 multisig script of a channel. This key also backs the
-    /// holder's anchor output in a commitment transaction, if one is present.
-    pub funding_key: Privkey,
+    /// holder's anchor output in a commitment transaction, if one is present. It's `None` once
+    /// handed over to a [`CommitmentSignerProvider`], so that it's never persisted.
+    pub funding_key: Option<Privkey>,
     /// Holder HTLC secret key used in commitment transaction HTLC outputs.
     pub tlc_base_key: Privkey,
     /// SecNonce used to generate valid signature in musig.
@@ -6703,7 +11681,7 @@ impl InMemorySigner {
         let musig2_base_nonce = key_derive(tlc_base_key.as_ref(), b"musig nocne");
 
         Self {
-            funding_key,
+            funding_key: Some(funding_key),
             tlc_base_key,
             musig2_base_nonce,
             commitment_seed,
@@ -6712,11 +11690,17 @@ impl InMemorySigner {
 
     fn get_base_public_keys(&self) -> ChannelBasePublicKeys {
         ChannelBasePublicKeys {
-            funding_pubkey: self.funding_key.pubkey(),
+            funding_pubkey: self.must_get_funding_key().pubkey(),
             tlc_base_key: self.tlc_base_key.pubkey(),
         }
     }
 
+    fn must_get_funding_key(&self) -> &Privkey {
+        self.funding_key
+            .as_ref()
+            .expect("funding key is kept in memory without a commitment signer provider")
+    }
+
     pub fn get_commitment_point(&self, commitment_number: u64) -> Pubkey {
         get_commitment_point(&self.commitment_seed, commitment_number)
     }
@@ -6737,3 +11721,70 @@ impl InMemorySigner {
         SecNonce::build(seckey.as_ref()).build()
     }
 }
+
+/// Signs the musig2 partial signatures of a channel with its funding key, which are used in the
+/// commitment, revocation, settlement, shutdown and channel announcement transactions.
+///
+/// [`InMemorySigner`] is the default implementation, which keeps the funding key in memory.
+/// To keep the funding key in a hardware or remote signer instead, implement this trait by
+/// forwarding the requests to the signer, and provide the signers of all the channels with a
+/// [`CommitmentSignerProvider`] in [`ChannelActorConfig`]. The signer must use the funding key
+/// which is derived from the channel seed, since its public key has been exchanged with the
+/// peer while opening the channel.
+pub trait CommitmentSigner: Send + Sync {
+    /// The public key of the funding key, used to check that the signer matches the channel.
+    fn funding_pubkey(&self) -> Pubkey;
+
+    /// Derive the secret nonce for the commitment number, the nonce must be deterministic
+    /// as the public nonce has already been sent to the peer before signing.
+    fn derive_musig2_nonce(&self, commitment_number: u64) -> SecNonce;
+
+    /// Create the musig2 partial signature of the message with the funding key.
+    fn sign_partial(
+        &self,
+        key_agg_ctx: &KeyAggContext,
+        secnonce: SecNonce,
+        agg_nonce: &AggNonce,
+        message: &[u8],
+    ) -> Result<PartialSignature, SigningError>;
+}
+
+/// Provides the [`CommitmentSigner`]s of the channels, e.g. backed by a hardware signer.
+///
+/// The keys of a new channel are handed over to the provider once they're derived, and the
+/// funding key is erased from the channel state before the state is persisted. The signers are
+/// not persisted, so the provider is asked for the signer again whenever the channel is loaded
+/// from the store.
+pub trait CommitmentSignerProvider: Send + Sync {
+    /// Take the custody of the keys of a new channel, and return its signer.
+    fn import_signer(&self, signer: InMemorySigner) -> Arc<dyn CommitmentSigner>;
+
+    /// Get the signer of the funding key imported before, `None` if it's unknown.
+    fn get_signer(&self, funding_pubkey: &Pubkey) -> Option<Arc<dyn CommitmentSigner>>;
+}
+
+impl CommitmentSigner for InMemorySigner {
+    fn funding_pubkey(&self) -> Pubkey {
+        self.must_get_funding_key().pubkey()
+    }
+
+    fn derive_musig2_nonce(&self, commitment_number: u64) -> SecNonce {
+        InMemorySigner::derive_musig2_nonce(self, commitment_number)
+    }
+
+    fn sign_partial(
+        &self,
+        key_agg_ctx: &KeyAggContext,
+        secnonce: SecNonce,
+        agg_nonce: &AggNonce,
+        message: &[u8],
+    ) -> Result<PartialSignature, SigningError> {
+        sign_partial(
+            key_agg_ctx,
+            self.must_get_funding_key(),
+            secnonce,
+            agg_nonce,
+            message,
+        )
+    }
+}
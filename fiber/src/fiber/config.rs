@@ -1,4 +1,8 @@
-use crate::{ckb::contracts::Contract, Result};
+use crate::{
+    ckb::contracts::Contract,
+    fiber::{channel::FeePolicyBounds, hash_algorithm::HashAlgorithm, types::Hash256},
+    Error, Result,
+};
 use ckb_jsonrpc_types::{CellDep, Script};
 use clap_serde_derive::{
     clap::{self},
@@ -64,6 +68,81 @@ pub const DEFAULT_GOSSIP_STORE_MAINTENANCE_INTERVAL_MS: u64 = 20 * 1000;
 /// Whether to sync the network graph from the network. true means syncing.
 pub const DEFAULT_SYNC_NETWORK_GRAPH: bool = true;
 
+/// Whether to accept keysend payments which carry the preimage in the onion packet.
+pub const DEFAULT_ACCEPT_KEYSEND: bool = true;
+
+/// The number of settled payment hashes to remember for rejecting re-forwarding. 0 means disabled.
+pub const DEFAULT_SEEN_PAYMENT_HASHES_CAPACITY: usize = 0;
+
+/// The minimal liquid capacity of a native ckb channel to accept. 0 means no minimal value.
+pub const DEFAULT_MIN_ACCEPT_CKB_FUNDING_AMOUNT: u64 = 0;
+
+/// The minimal liquid capacity of a udt channel to accept. 0 means no minimal value.
+pub const DEFAULT_MIN_ACCEPT_UDT_FUNDING_AMOUNT: u128 = 0;
+
+/// The timeout to abort a channel whose funding is not completed. 0 means never abort.
+pub const DEFAULT_FUNDING_TIMEOUT_SECONDS: u64 = 60 * 60;
+
+/// The grace period to keep the channels alive waiting for the disconnected peer. 0 means no grace period.
+pub const DEFAULT_PEER_DISCONNECT_GRACE_SECONDS: u64 = 0;
+
+/// The base delay of the exponential backoff to reestablish a channel. 0 means no backoff.
+pub const DEFAULT_REESTABLISH_BACKOFF_BASE_SECONDS: u64 = 1;
+
+/// The maximal delay of the exponential backoff to reestablish a channel.
+pub const DEFAULT_REESTABLISH_BACKOFF_CAP_SECONDS: u64 = 5 * 60;
+
+/// The timeout to complete reestablishing a channel before retrying. 0 means never time out.
+pub const DEFAULT_REESTABLISH_TIMEOUT_SECONDS: u64 = 60;
+
+/// The timeout for the peer to complete the cooperative close after we sent Shutdown.
+/// 0 means never time out.
+pub const DEFAULT_SHUTDOWN_TIMEOUT_SECONDS: u64 = 24 * 60 * 60;
+
+/// Whether to force close the channel when the cooperative close times out, otherwise only
+/// an alert is emitted.
+pub const DEFAULT_FORCE_CLOSE_ON_SHUTDOWN_TIMEOUT: bool = false;
+
+/// The timeout for the peer to reply the resynced commitment with RevokeAndAck.
+/// 0 means never time out.
+pub const DEFAULT_COMMITMENT_RESYNC_TIMEOUT_SECONDS: u64 = 60;
+
+/// The maximum value of the tlcs in flight as the percentage of the balance. 0 means no limit.
+pub const DEFAULT_MAX_TLC_VALUE_IN_FLIGHT_PERCENTAGE: u8 = 0;
+
+/// The minimal fee rate in millionths allowed to be set by updating channels.
+pub const DEFAULT_MIN_ALLOWED_FEE_PPM: u128 = 0;
+
+/// The maximal fee rate in millionths allowed to be set by updating channels, which charges
+/// the whole forwarded amount as the fee.
+pub const DEFAULT_MAX_ALLOWED_FEE_PPM: u128 = 1_000_000;
+
+/// The maximal tlc minimum value allowed to be set by updating channels. 0 means no limit.
+pub const DEFAULT_MAX_ALLOWED_TLC_MINIMUM_VALUE: u128 = 0;
+
+/// The maximal tlc dust limit proposed by the peers opening channels to us, 1 CKB by default.
+pub const DEFAULT_MAX_ACCEPTED_TLC_DUST_LIMIT: u128 = 100_000_000;
+
+/// The maximal number of hops (excluding the sender) in the routes of payments sent by this node.
+pub const DEFAULT_MAX_PAYMENT_HOPS: usize = 20;
+
+/// The maximal number of onion packets of the received tlcs peeled concurrently.
+pub const DEFAULT_ONION_PEELING_CONCURRENCY: usize = 4;
+
+/// The maximal attempts to retry removing a tlc before abandoning it. 0 means retry forever.
+/// The fulfills are always retried forever.
+pub const DEFAULT_MAX_RETRYABLE_REMOVE_ATTEMPTS: u64 = 0;
+
+/// The rate of AddTlc messages accepted from each peer, per second. 0 means no limit.
+pub const DEFAULT_ADD_TLC_RATE_LIMIT: u64 = 0;
+
+/// The maximal burst of AddTlc messages accepted from each peer when the rate is limited.
+pub const DEFAULT_ADD_TLC_BURST_LIMIT: u64 = 100;
+
+/// The maximal number of commitment points from the peer retained in the channel state.
+/// 0 means the retention is bounded by the max tlc number in flight of the channel.
+pub const DEFAULT_MAX_REMOTE_COMMITMENT_POINTS: u64 = 0;
+
 // See comment in `LdkConfig` for why do we need to specify both name and long,
 // and prefix them with `ckb-`/`CKB_`.
 #[derive(ClapSerde, Debug, Clone)]
@@ -243,6 +322,124 @@ pub struct FiberConfig {
     )]
     pub(crate) sync_network_graph: Option<bool>,
 
+    /// Whether to accept keysend payments without an invoice. [default: true]
+    #[arg(
+        name = "FIBER_ACCEPT_KEYSEND",
+        long = "fiber-accept-keysend",
+        env,
+        help = "Whether to accept keysend payments without an invoice. [default: true]"
+    )]
+    pub(crate) accept_keysend: Option<bool>,
+
+    /// The number of payment hashes settled by forwarded tlcs to remember, forwarding tlcs with
+    /// these payment hashes again will be rejected. 0 means disabled. [default: 0]
+    #[arg(
+        name = "FIBER_SEEN_PAYMENT_HASHES_CAPACITY",
+        long = "fiber-seen-payment-hashes-capacity",
+        env,
+        help = "The number of payment hashes settled by forwarded tlcs to remember, forwarding tlcs with these payment hashes again will be rejected. 0 means disabled. [default: 0]"
+    )]
+    pub(crate) seen_payment_hashes_capacity: Option<usize>,
+
+    /// The minimal liquid capacity (funding amounts of both parties excluding the reserved ckb)
+    /// of a native ckb channel to accept, unit: shannons [default: 0 (no minimal value)]
+    #[arg(
+        name = "FIBER_MIN_ACCEPT_CKB_FUNDING_AMOUNT",
+        long = "fiber-min-accept-ckb-funding-amount",
+        env,
+        help = "The minimal liquid capacity of a native ckb channel to accept, unit: shannons [default: 0 (no minimal value)]"
+    )]
+    pub(crate) min_accept_ckb_funding_amount: Option<u64>,
+
+    /// The minimal liquid capacity (funding amounts of both parties) of a udt channel to accept.
+    /// [default: 0 (no minimal value)]
+    #[arg(
+        name = "FIBER_MIN_ACCEPT_UDT_FUNDING_AMOUNT",
+        long = "fiber-min-accept-udt-funding-amount",
+        env,
+        help = "The minimal liquid capacity of a udt channel to accept. [default: 0 (no minimal value)]"
+    )]
+    pub(crate) min_accept_udt_funding_amount: Option<u128>,
+
+    /// The timeout in seconds to abort a channel whose funding collaboration is not completed.
+    /// 0 means never abort. [default: 3600 (1 hour)]
+    #[arg(
+        name = "FIBER_FUNDING_TIMEOUT_SECONDS",
+        long = "fiber-funding-timeout-seconds",
+        env,
+        help = "The timeout in seconds to abort a channel whose funding is not completed. 0 means never abort. [default: 3600 (1 hour)]"
+    )]
+    pub(crate) funding_timeout_seconds: Option<u64>,
+
+    /// The grace period in seconds to keep the channels of a disconnected peer alive, the channels
+    /// are resumed without being reloaded if the peer reconnects within it. 0 means stopping the
+    /// channels immediately. [default: 0]
+    #[arg(
+        name = "FIBER_PEER_DISCONNECT_GRACE_SECONDS",
+        long = "fiber-peer-disconnect-grace-seconds",
+        env,
+        help = "The grace period in seconds to keep the channels of a disconnected peer alive. 0 means stopping the channels immediately. [default: 0]"
+    )]
+    pub(crate) peer_disconnect_grace_seconds: Option<u64>,
+
+    /// The maximal number of hops in the routes of payments sent by this node, the onion packets
+    /// received from others are not limited by it. [default: 20]
+    #[arg(
+        name = "FIBER_MAX_PAYMENT_HOPS",
+        long = "fiber-max-payment-hops",
+        env,
+        help = "The maximal number of hops in the routes of payments sent by this node, the onion packets received from others are not limited by it. [default: 20]"
+    )]
+    pub(crate) max_payment_hops: Option<usize>,
+
+    /// The maximal number of onion packets of the received tlcs peeled concurrently. [default: 4]
+    #[arg(
+        name = "FIBER_ONION_PEELING_CONCURRENCY",
+        long = "fiber-onion-peeling-concurrency",
+        env
+    )]
+    pub(crate) onion_peeling_concurrency: Option<usize>,
+
+    /// The maximal attempts to retry removing a tlc before abandoning it, the retries are
+    /// scheduled every 2 seconds. 0 means retry forever. The fulfills are never abandoned.
+    /// [default: 0]
+    #[arg(
+        name = "FIBER_MAX_RETRYABLE_REMOVE_ATTEMPTS",
+        long = "fiber-max-retryable-remove-attempts",
+        env
+    )]
+    pub(crate) max_retryable_remove_attempts: Option<u64>,
+
+    /// The sustained rate of AddTlc messages accepted from each peer, per second, the excess tlcs
+    /// are failed without peeling their onion packets. 0 means no limit. [default: 0]
+    #[arg(
+        name = "FIBER_ADD_TLC_RATE_LIMIT",
+        long = "fiber-add-tlc-rate-limit",
+        env,
+        help = "The rate of AddTlc messages accepted from each peer, per second. 0 means no limit. [default: 0]"
+    )]
+    pub(crate) add_tlc_rate_limit: Option<u64>,
+
+    /// The maximal burst of AddTlc messages accepted from each peer when the rate is limited. [default: 100]
+    #[arg(
+        name = "FIBER_ADD_TLC_BURST_LIMIT",
+        long = "fiber-add-tlc-burst-limit",
+        env,
+        help = "The maximal burst of AddTlc messages accepted from each peer when the rate is limited. [default: 100]"
+    )]
+    pub(crate) add_tlc_burst_limit: Option<u64>,
+
+    /// The maximal number of commitment points from the peer retained in the channel state,
+    /// the points needed by the live tlcs are always retained. 0 means the retention is bounded
+    /// by the max tlc number in flight of the channel. [default: 0]
+    #[arg(
+        name = "FIBER_MAX_REMOTE_COMMITMENT_POINTS",
+        long = "fiber-max-remote-commitment-points",
+        env,
+        help = "The maximal number of commitment points from the peer retained in the channel state. 0 means bounded by the max tlc number in flight of the channel. [default: 0]"
+    )]
+    pub(crate) max_remote_commitment_points: Option<u64>,
+
     /// The interval to check watchtower, in seconds. 0 means never check. [default: 60 (1 minute)]
     #[arg(
         name = "FIBER_WATCHTOWER_CHECK_INTERVAL_SECONDS",
@@ -251,6 +448,196 @@ pub struct FiberConfig {
         help = "The interval to check watchtower, in seconds. 0 means never check. [default: 60 (1 minute)]"
     )]
     pub watchtower_check_interval_seconds: Option<u64>,
+
+    /// code hashes of the lock scripts allowed to be used as the close script of channels (separated by `,`),
+    /// closing to other lock scripts is rejected unless explicitly overridden. Empty means no validation [default: empty]
+    #[arg(name = "FIBER_ALLOWED_CLOSE_SCRIPT_CODE_HASHES", long = "fiber-allowed-close-script-code-hashes", env, value_parser, num_args = 0.., value_delimiter = ',')]
+    pub(crate) allowed_close_script_code_hashes: Vec<Hash256>,
+
+    /// hash algorithms allowed to be used by the tlcs of channels (separated by `,`), e.g. `ckb_hash,sha256`,
+    /// the channels only allow the algorithms supported by both parties. Empty means all supported algorithms [default: empty]
+    #[arg(name = "FIBER_ALLOWED_HASH_ALGORITHMS", long = "fiber-allowed-hash-algorithms", env, value_parser, num_args = 0.., value_delimiter = ',')]
+    pub(crate) allowed_hash_algorithms: Vec<HashAlgorithm>,
+
+    /// udt type scripts allowed to fund the channels opened by peers, each in json format, e.g.
+    /// `{"code_hash":"0x...","hash_type":"type","args":"0x..."}`. Not set means all udts are allowed,
+    /// and an empty list means only native ckb channels are accepted [default: not set]
+    #[arg(name = "FIBER_ALLOWED_UDT_SCRIPTS", long = "fiber-allowed-udt-scripts", env, value_parser = parse_json_script, num_args = 0..)]
+    pub(crate) allowed_udt_scripts: Option<Vec<Script>>,
+
+    /// which channels opened by peers are accepted by whether they are public, one of `any`,
+    /// `public_only` and `private_only` [default: any]
+    #[arg(name = "FIBER_ACCEPT_CHANNEL_VISIBILITY", long = "fiber-accept-channel-visibility", env, value_parser)]
+    pub(crate) accept_channel_visibility: Option<ChannelVisibilityPolicy>,
+
+    /// minimal reserved ckb amount the opener of a channel must post to be accepted, in shannons,
+    /// requiring a larger security margin than the occupied capacity. 0 means no requirement [default: 0]
+    #[arg(name = "FIBER_ACCEPT_MIN_REMOTE_RESERVE", long = "fiber-accept-min-remote-reserve", env)]
+    pub(crate) accept_min_remote_reserve: Option<u64>,
+
+    /// how the received tlcs without invoices are handled by the final hop, one of `reject`,
+    /// `accept_if_preimage_known` and `hold_for_approval` [default: accept_if_preimage_known]
+    #[arg(name = "FIBER_NO_INVOICE_POLICY", long = "fiber-no-invoice-policy", env, value_parser)]
+    pub(crate) no_invoice_policy: Option<NoInvoicePolicy>,
+
+    /// base delay in seconds before reestablishing a channel again after the previous attempts
+    /// are not completed, doubled on each consecutive attempt. 0 means no backoff [default: 1]
+    #[arg(
+        name = "FIBER_REESTABLISH_BACKOFF_BASE_SECONDS",
+        long = "fiber-reestablish-backoff-base-seconds",
+        env
+    )]
+    pub(crate) reestablish_backoff_base_seconds: Option<u64>,
+
+    /// maximal delay in seconds before reestablishing a channel again [default: 300]
+    #[arg(
+        name = "FIBER_REESTABLISH_BACKOFF_CAP_SECONDS",
+        long = "fiber-reestablish-backoff-cap-seconds",
+        env
+    )]
+    pub(crate) reestablish_backoff_cap_seconds: Option<u64>,
+
+    /// timeout in seconds to complete reestablishing a channel, the channel is reestablished
+    /// again after the timeout. 0 means never time out [default: 60]
+    #[arg(
+        name = "FIBER_REESTABLISH_TIMEOUT_SECONDS",
+        long = "fiber-reestablish-timeout-seconds",
+        env
+    )]
+    pub(crate) reestablish_timeout_seconds: Option<u64>,
+
+    /// timeout in seconds for the peer to complete the cooperative close after we sent Shutdown.
+    /// 0 means never time out [default: 86400 (1 day)]
+    #[arg(
+        name = "FIBER_SHUTDOWN_TIMEOUT_SECONDS",
+        long = "fiber-shutdown-timeout-seconds",
+        env
+    )]
+    pub(crate) shutdown_timeout_seconds: Option<u64>,
+
+    /// whether to force close the channel when the cooperative close times out, otherwise only
+    /// an alert is emitted [default: false]
+    #[arg(
+        name = "FIBER_FORCE_CLOSE_ON_SHUTDOWN_TIMEOUT",
+        long = "fiber-force-close-on-shutdown-timeout",
+        env
+    )]
+    pub(crate) force_close_on_shutdown_timeout: Option<bool>,
+
+    /// timeout in seconds for the peer to reply the resynced commitment with RevokeAndAck, the
+    /// resync commitment command fails once timed out. 0 means never time out [default: 60]
+    #[arg(
+        name = "FIBER_COMMITMENT_RESYNC_TIMEOUT_SECONDS",
+        long = "fiber-commitment-resync-timeout-seconds",
+        env
+    )]
+    pub(crate) commitment_resync_timeout_seconds: Option<u64>,
+
+    /// maximum value of the tlcs offered by us in flight as the percentage (1 to 100) of our
+    /// balance, the more restrictive one of it and the absolute limit of the channel is applied.
+    /// 0 means no limit [default: 0]
+    #[arg(
+        name = "FIBER_MAX_TLC_VALUE_IN_FLIGHT_PERCENTAGE",
+        long = "fiber-max-tlc-value-in-flight-percentage",
+        env,
+        value_parser = clap::value_parser!(u8).range(0..=100)
+    )]
+    pub(crate) max_tlc_value_in_flight_percentage: Option<u8>,
+
+    /// minimal tlc fee proportional millionths allowed to be set by updating channels
+    /// [default: 0]
+    #[arg(
+        name = "FIBER_MIN_ALLOWED_FEE_PPM",
+        long = "fiber-min-allowed-fee-ppm",
+        env
+    )]
+    pub(crate) min_allowed_fee_ppm: Option<u128>,
+
+    /// maximal tlc fee proportional millionths allowed to be set by updating channels
+    /// [default: 1000000]
+    #[arg(
+        name = "FIBER_MAX_ALLOWED_FEE_PPM",
+        long = "fiber-max-allowed-fee-ppm",
+        env
+    )]
+    pub(crate) max_allowed_fee_ppm: Option<u128>,
+
+    /// maximal tlc minimum value allowed to be set by updating channels, 0 means no limit
+    /// [default: 0]
+    #[arg(
+        name = "FIBER_MAX_ALLOWED_TLC_MINIMUM_VALUE",
+        long = "fiber-max-allowed-tlc-minimum-value",
+        env
+    )]
+    pub(crate) max_allowed_tlc_minimum_value: Option<u128>,
+
+    /// maximal tlc dust limit accepted from the peers opening channels to us, 0 means the peers
+    /// can't trim dust tlcs from the commitment transactions [default: 100000000]
+    #[arg(
+        name = "FIBER_MAX_ACCEPTED_TLC_DUST_LIMIT",
+        long = "fiber-max-accepted-tlc-dust-limit",
+        env
+    )]
+    pub(crate) max_accepted_tlc_dust_limit: Option<u128>,
+}
+
+/// The policy of accepting the channels opened by peers by whether the channels are public.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelVisibilityPolicy {
+    #[default]
+    Any,
+    PublicOnly,
+    PrivateOnly,
+}
+
+impl ChannelVisibilityPolicy {
+    pub fn accepts(&self, public: bool) -> bool {
+        match self {
+            ChannelVisibilityPolicy::Any => true,
+            ChannelVisibilityPolicy::PublicOnly => public,
+            ChannelVisibilityPolicy::PrivateOnly => !public,
+        }
+    }
+}
+
+impl FromStr for ChannelVisibilityPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(ChannelVisibilityPolicy::Any),
+            "public_only" => Ok(ChannelVisibilityPolicy::PublicOnly),
+            "private_only" => Ok(ChannelVisibilityPolicy::PrivateOnly),
+            _ => Err(format!("Unknown channel visibility policy: {}", s)),
+        }
+    }
+}
+
+/// The policy of the final hop handling the received tlcs without invoices.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoInvoicePolicy {
+    /// Fail the tlcs without invoices, including the keysend payments.
+    Reject,
+    /// Fulfill the tlcs whose preimages are known, e.g. the keysend payments.
+    #[default]
+    AcceptIfPreimageKnown,
+    /// Hold the tlcs whose preimages are known until they are approved by `ApproveTlc`.
+    HoldForApproval,
+}
+
+impl FromStr for NoInvoicePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(NoInvoicePolicy::Reject),
+            "accept_if_preimage_known" => Ok(NoInvoicePolicy::AcceptIfPreimageKnown),
+            "hold_for_approval" => Ok(NoInvoicePolicy::HoldForApproval),
+            _ => Err(format!("Unknown no invoice policy: {}", s)),
+        }
+    }
 }
 
 /// Must be a valid utf-8 string of length maximal length 32 bytes.
@@ -445,6 +832,168 @@ impl FiberConfig {
         self.sync_network_graph
             .unwrap_or(DEFAULT_SYNC_NETWORK_GRAPH)
     }
+
+    pub fn accept_keysend(&self) -> bool {
+        self.accept_keysend.unwrap_or(DEFAULT_ACCEPT_KEYSEND)
+    }
+
+    pub fn seen_payment_hashes_capacity(&self) -> usize {
+        self.seen_payment_hashes_capacity
+            .unwrap_or(DEFAULT_SEEN_PAYMENT_HASHES_CAPACITY)
+    }
+
+    pub fn min_accept_ckb_funding_amount(&self) -> u64 {
+        self.min_accept_ckb_funding_amount
+            .unwrap_or(DEFAULT_MIN_ACCEPT_CKB_FUNDING_AMOUNT)
+    }
+
+    pub fn min_accept_udt_funding_amount(&self) -> u128 {
+        self.min_accept_udt_funding_amount
+            .unwrap_or(DEFAULT_MIN_ACCEPT_UDT_FUNDING_AMOUNT)
+    }
+
+    pub fn funding_timeout_seconds(&self) -> u64 {
+        self.funding_timeout_seconds
+            .unwrap_or(DEFAULT_FUNDING_TIMEOUT_SECONDS)
+    }
+
+    pub fn peer_disconnect_grace_seconds(&self) -> u64 {
+        self.peer_disconnect_grace_seconds
+            .unwrap_or(DEFAULT_PEER_DISCONNECT_GRACE_SECONDS)
+    }
+
+    pub fn max_payment_hops(&self) -> usize {
+        self.max_payment_hops.unwrap_or(DEFAULT_MAX_PAYMENT_HOPS)
+    }
+
+    pub fn onion_peeling_concurrency(&self) -> usize {
+        self.onion_peeling_concurrency
+            .unwrap_or(DEFAULT_ONION_PEELING_CONCURRENCY)
+    }
+
+    pub fn max_retryable_remove_attempts(&self) -> u64 {
+        self.max_retryable_remove_attempts
+            .unwrap_or(DEFAULT_MAX_RETRYABLE_REMOVE_ATTEMPTS)
+    }
+
+    pub fn add_tlc_rate_limit(&self) -> u64 {
+        self.add_tlc_rate_limit.unwrap_or(DEFAULT_ADD_TLC_RATE_LIMIT)
+    }
+
+    pub fn add_tlc_burst_limit(&self) -> u64 {
+        self.add_tlc_burst_limit.unwrap_or(DEFAULT_ADD_TLC_BURST_LIMIT)
+    }
+
+    pub fn max_remote_commitment_points(&self) -> u64 {
+        self.max_remote_commitment_points
+            .unwrap_or(DEFAULT_MAX_REMOTE_COMMITMENT_POINTS)
+    }
+
+    pub fn allowed_close_script_code_hashes(&self) -> &[Hash256] {
+        &self.allowed_close_script_code_hashes
+    }
+
+    pub fn allowed_hash_algorithms(&self) -> Vec<HashAlgorithm> {
+        if self.allowed_hash_algorithms.is_empty() {
+            HashAlgorithm::supported_algorithms()
+        } else {
+            self.allowed_hash_algorithms.clone()
+        }
+    }
+
+    pub fn allowed_udt_scripts(&self) -> Option<&[Script]> {
+        self.allowed_udt_scripts.as_deref()
+    }
+
+    pub fn accept_channel_visibility(&self) -> ChannelVisibilityPolicy {
+        self.accept_channel_visibility.unwrap_or_default()
+    }
+
+    pub fn accept_min_remote_reserve(&self) -> u64 {
+        self.accept_min_remote_reserve.unwrap_or_default()
+    }
+
+    pub fn no_invoice_policy(&self) -> NoInvoicePolicy {
+        self.no_invoice_policy.unwrap_or_default()
+    }
+
+    pub fn reestablish_backoff_base_seconds(&self) -> u64 {
+        self.reestablish_backoff_base_seconds
+            .unwrap_or(DEFAULT_REESTABLISH_BACKOFF_BASE_SECONDS)
+    }
+
+    pub fn reestablish_backoff_cap_seconds(&self) -> u64 {
+        self.reestablish_backoff_cap_seconds
+            .unwrap_or(DEFAULT_REESTABLISH_BACKOFF_CAP_SECONDS)
+    }
+
+    pub fn reestablish_timeout_seconds(&self) -> u64 {
+        self.reestablish_timeout_seconds
+            .unwrap_or(DEFAULT_REESTABLISH_TIMEOUT_SECONDS)
+    }
+
+    pub fn shutdown_timeout_seconds(&self) -> u64 {
+        self.shutdown_timeout_seconds
+            .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT_SECONDS)
+    }
+
+    pub fn force_close_on_shutdown_timeout(&self) -> bool {
+        self.force_close_on_shutdown_timeout
+            .unwrap_or(DEFAULT_FORCE_CLOSE_ON_SHUTDOWN_TIMEOUT)
+    }
+
+    pub fn commitment_resync_timeout_seconds(&self) -> u64 {
+        self.commitment_resync_timeout_seconds
+            .unwrap_or(DEFAULT_COMMITMENT_RESYNC_TIMEOUT_SECONDS)
+    }
+
+    pub fn max_tlc_value_in_flight_percentage(&self) -> u8 {
+        self.max_tlc_value_in_flight_percentage
+            .unwrap_or(DEFAULT_MAX_TLC_VALUE_IN_FLIGHT_PERCENTAGE)
+    }
+
+    pub fn min_allowed_fee_ppm(&self) -> u128 {
+        self.min_allowed_fee_ppm
+            .unwrap_or(DEFAULT_MIN_ALLOWED_FEE_PPM)
+    }
+
+    pub fn max_allowed_fee_ppm(&self) -> u128 {
+        self.max_allowed_fee_ppm
+            .unwrap_or(DEFAULT_MAX_ALLOWED_FEE_PPM)
+    }
+
+    pub fn max_allowed_tlc_minimum_value(&self) -> u128 {
+        self.max_allowed_tlc_minimum_value
+            .unwrap_or(DEFAULT_MAX_ALLOWED_TLC_MINIMUM_VALUE)
+    }
+
+    pub fn fee_policy_bounds(&self) -> Result<FeePolicyBounds> {
+        let min_fee_proportional_millionths = self.min_allowed_fee_ppm();
+        let max_fee_proportional_millionths = self.max_allowed_fee_ppm();
+        if min_fee_proportional_millionths > max_fee_proportional_millionths {
+            return Err(Error::InvalidParameter(format!(
+                "min_allowed_fee_ppm {} is greater than max_allowed_fee_ppm {}",
+                min_fee_proportional_millionths, max_fee_proportional_millionths
+            )));
+        }
+        Ok(FeePolicyBounds {
+            min_fee_proportional_millionths,
+            max_fee_proportional_millionths,
+            max_tlc_minimum_value: match self.max_allowed_tlc_minimum_value() {
+                0 => None,
+                value => Some(value),
+            },
+        })
+    }
+
+    pub fn max_accepted_tlc_dust_limit(&self) -> u128 {
+        self.max_accepted_tlc_dust_limit
+            .unwrap_or(DEFAULT_MAX_ACCEPTED_TLC_DUST_LIMIT)
+    }
+}
+
+fn parse_json_script(s: &str) -> std::result::Result<Script, serde_json::Error> {
+    serde_json::from_str(s)
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
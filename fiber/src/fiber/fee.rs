@@ -10,7 +10,7 @@ use ckb_types::{
 };
 use molecule::prelude::Entity;
 
-fn commitment_tx_size(udt_type_script: &Option<Script>) -> usize {
+fn commitment_tx_size(udt_type_script: &Option<Script>, anchor_outputs: bool) -> usize {
     // when there is pending htlcs, the commitment lock args will be 56 bytes, otherwise 46 bytes.
     // to simplify the calculation, we use hardcoded 56 bytes here.
     let commitment_lock_script = get_script_by_contract(Contract::CommitmentLock, &[0u8; 56]);
@@ -32,11 +32,21 @@ fn commitment_tx_size(udt_type_script: &Option<Script>) -> usize {
         (output, Bytes::default())
     };
 
+    // The anchor outputs are locked by the shutdown scripts of both parties, to simplify
+    // the calculation, we assume the lock args are at most 32 bytes.
+    let anchor_count = if anchor_outputs { 2 } else { 0 };
+    let anchor_output = CellOutput::new_builder()
+        .capacity(0.pack())
+        .lock(get_script_by_contract(Contract::FundingLock, &[0u8; 32]))
+        .build();
+
     let mock_commitment_tx = TransactionBuilder::default()
         .cell_deps(cell_deps)
         .input(CellInput::default())
         .output(output)
         .output_data(output_data)
+        .outputs(vec![anchor_output; anchor_count])
+        .outputs_data(vec![Bytes::default(); anchor_count])
         .set_witnesses(vec![[0; FUNDING_CELL_WITNESS_LEN].pack()])
         .build();
     mock_commitment_tx.data().serialized_size_in_block()
@@ -88,9 +98,13 @@ pub(crate) fn shutdown_tx_size(
     mock_shutdown_tx.data().serialized_size_in_block()
 }
 
-pub(crate) fn calculate_commitment_tx_fee(fee_rate: u64, udt_type_script: &Option<Script>) -> u64 {
+pub(crate) fn calculate_commitment_tx_fee(
+    fee_rate: u64,
+    udt_type_script: &Option<Script>,
+    anchor_outputs: bool,
+) -> u64 {
     let fee_rate: FeeRate = FeeRate::from_u64(fee_rate);
-    let tx_size = commitment_tx_size(udt_type_script) as u64;
+    let tx_size = commitment_tx_size(udt_type_script, anchor_outputs) as u64;
     fee_rate.fee(tx_size).as_u64()
 }
 
@@ -3502,6 +3502,15 @@ impl ::core::fmt::Display for OpenChannel {
         )?;
         write!(f, ", {}: {}", "next_local_nonce", self.next_local_nonce())?;
         write!(f, ", {}: {}", "channel_flags", self.channel_flags())?;
+        write!(f, ", {}: {}", "push_amount", self.push_amount())?;
+        write!(f, ", {}: {}", "tlc_dust_limit", self.tlc_dust_limit())?;
+        write!(
+            f,
+            ", {}: {}",
+            "allowed_hash_algorithms",
+            self.allowed_hash_algorithms()
+        )?;
+        write!(f, ", {}: {}", "features", self.features())?;
         let extra_count = self.count_extra_fields();
         if extra_count != 0 {
             write!(f, ", .. ({} fields)", extra_count)?;
@@ -3516,13 +3525,15 @@ impl ::core::default::Default for OpenChannel {
     }
 }
 impl OpenChannel {
-    const DEFAULT_VALUE: [u8; 464] = [
-        208, 1, 0, 0, 76, 0, 0, 0, 108, 0, 0, 0, 140, 0, 0, 0, 140, 0, 0, 0, 156, 0, 0, 0, 209, 0,
-        0, 0, 217, 0, 0, 0, 225, 0, 0, 0, 233, 0, 0, 0, 249, 0, 0, 0, 1, 1, 0, 0, 9, 1, 0, 0, 42,
-        1, 0, 0, 75, 1, 0, 0, 108, 1, 0, 0, 141, 1, 0, 0, 141, 1, 0, 0, 207, 1, 0, 0, 0, 0, 0, 0,
+    const DEFAULT_VALUE: [u8; 524] = [
+        12, 2, 0, 0, 92, 0, 0, 0, 124, 0, 0, 0, 156, 0, 0, 0, 156, 0, 0, 0, 172, 0, 0, 0, 225, 0,
+        0, 0, 233, 0, 0, 0, 241, 0, 0, 0, 249, 0, 0, 0, 9, 1, 0, 0, 17, 1, 0, 0, 25, 1, 0, 0, 58,
+        1, 0, 0, 91, 1, 0, 0, 124, 1, 0, 0, 157, 1, 0, 0, 157, 1, 0, 0, 223, 1, 0, 0, 224, 1, 0, 0,
+        240, 1, 0, 0, 0, 2, 0, 0, 4, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 53, 0, 0, 0, 16, 0, 0, 0, 48, 0, 0, 0, 49,
+        0, 0, 53, 0, 0, 0, 16, 0, 0, 0, 48, 0, 0, 0, 49, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
@@ -3534,7 +3545,7 @@ impl OpenChannel {
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
     ];
-    pub const FIELD_COUNT: usize = 18;
+    pub const FIELD_COUNT: usize = 22;
     pub fn total_size(&self) -> usize {
         molecule::unpack_number(self.as_slice()) as usize
     }
@@ -3656,11 +3667,35 @@ impl OpenChannel {
     pub fn channel_flags(&self) -> Byte {
         let slice = self.as_slice();
         let start = molecule::unpack_number(&slice[72..]) as usize;
+        let end = molecule::unpack_number(&slice[76..]) as usize;
+        Byte::new_unchecked(self.0.slice(start..end))
+    }
+    pub fn push_amount(&self) -> Uint128 {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[76..]) as usize;
+        let end = molecule::unpack_number(&slice[80..]) as usize;
+        Uint128::new_unchecked(self.0.slice(start..end))
+    }
+    pub fn tlc_dust_limit(&self) -> Uint128 {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[80..]) as usize;
+        let end = molecule::unpack_number(&slice[84..]) as usize;
+        Uint128::new_unchecked(self.0.slice(start..end))
+    }
+    pub fn allowed_hash_algorithms(&self) -> Bytes {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[84..]) as usize;
+        let end = molecule::unpack_number(&slice[88..]) as usize;
+        Bytes::new_unchecked(self.0.slice(start..end))
+    }
+    pub fn features(&self) -> Uint64 {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[88..]) as usize;
         if self.has_extra_fields() {
-            let end = molecule::unpack_number(&slice[76..]) as usize;
-            Byte::new_unchecked(self.0.slice(start..end))
+            let end = molecule::unpack_number(&slice[92..]) as usize;
+            Uint64::new_unchecked(self.0.slice(start..end))
         } else {
-            Byte::new_unchecked(self.0.slice(start..))
+            Uint64::new_unchecked(self.0.slice(start..))
         }
     }
     pub fn as_reader<'r>(&'r self) -> OpenChannelReader<'r> {
@@ -3708,6 +3743,10 @@ impl molecule::prelude::Entity for OpenChannel {
             .channel_annoucement_nonce(self.channel_annoucement_nonce())
             .next_local_nonce(self.next_local_nonce())
             .channel_flags(self.channel_flags())
+            .push_amount(self.push_amount())
+            .tlc_dust_limit(self.tlc_dust_limit())
+            .allowed_hash_algorithms(self.allowed_hash_algorithms())
+            .features(self.features())
     }
 }
 #[derive(Clone, Copy)]
@@ -3792,6 +3831,15 @@ impl<'r> ::core::fmt::Display for OpenChannelReader<'r> {
         )?;
         write!(f, ", {}: {}", "next_local_nonce", self.next_local_nonce())?;
         write!(f, ", {}: {}", "channel_flags", self.channel_flags())?;
+        write!(f, ", {}: {}", "push_amount", self.push_amount())?;
+        write!(f, ", {}: {}", "tlc_dust_limit", self.tlc_dust_limit())?;
+        write!(
+            f,
+            ", {}: {}",
+            "allowed_hash_algorithms",
+            self.allowed_hash_algorithms()
+        )?;
+        write!(f, ", {}: {}", "features", self.features())?;
         let extra_count = self.count_extra_fields();
         if extra_count != 0 {
             write!(f, ", .. ({} fields)", extra_count)?;
@@ -3800,7 +3848,7 @@ impl<'r> ::core::fmt::Display for OpenChannelReader<'r> {
     }
 }
 impl<'r> OpenChannelReader<'r> {
-    pub const FIELD_COUNT: usize = 18;
+    pub const FIELD_COUNT: usize = 22;
     pub fn total_size(&self) -> usize {
         molecule::unpack_number(self.as_slice()) as usize
     }
@@ -3922,11 +3970,35 @@ impl<'r> OpenChannelReader<'r> {
     pub fn channel_flags(&self) -> ByteReader<'r> {
         let slice = self.as_slice();
         let start = molecule::unpack_number(&slice[72..]) as usize;
+        let end = molecule::unpack_number(&slice[76..]) as usize;
+        ByteReader::new_unchecked(&self.as_slice()[start..end])
+    }
+    pub fn push_amount(&self) -> Uint128Reader<'r> {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[76..]) as usize;
+        let end = molecule::unpack_number(&slice[80..]) as usize;
+        Uint128Reader::new_unchecked(&self.as_slice()[start..end])
+    }
+    pub fn tlc_dust_limit(&self) -> Uint128Reader<'r> {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[80..]) as usize;
+        let end = molecule::unpack_number(&slice[84..]) as usize;
+        Uint128Reader::new_unchecked(&self.as_slice()[start..end])
+    }
+    pub fn allowed_hash_algorithms(&self) -> BytesReader<'r> {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[84..]) as usize;
+        let end = molecule::unpack_number(&slice[88..]) as usize;
+        BytesReader::new_unchecked(&self.as_slice()[start..end])
+    }
+    pub fn features(&self) -> Uint64Reader<'r> {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[88..]) as usize;
         if self.has_extra_fields() {
-            let end = molecule::unpack_number(&slice[76..]) as usize;
-            ByteReader::new_unchecked(&self.as_slice()[start..end])
+            let end = molecule::unpack_number(&slice[92..]) as usize;
+            Uint64Reader::new_unchecked(&self.as_slice()[start..end])
         } else {
-            ByteReader::new_unchecked(&self.as_slice()[start..])
+            Uint64Reader::new_unchecked(&self.as_slice()[start..])
         }
     }
 }
@@ -3994,6 +4066,10 @@ impl<'r> molecule::prelude::Reader<'r> for OpenChannelReader<'r> {
         PubNonceOptReader::verify(&slice[offsets[15]..offsets[16]], compatible)?;
         PubNonceReader::verify(&slice[offsets[16]..offsets[17]], compatible)?;
         ByteReader::verify(&slice[offsets[17]..offsets[18]], compatible)?;
+        Uint128Reader::verify(&slice[offsets[18]..offsets[19]], compatible)?;
+        Uint128Reader::verify(&slice[offsets[19]..offsets[20]], compatible)?;
+        BytesReader::verify(&slice[offsets[20]..offsets[21]], compatible)?;
+        Uint64Reader::verify(&slice[offsets[21]..offsets[22]], compatible)?;
         Ok(())
     }
 }
@@ -4017,9 +4093,13 @@ pub struct OpenChannelBuilder {
     pub(crate) channel_annoucement_nonce: PubNonceOpt,
     pub(crate) next_local_nonce: PubNonce,
     pub(crate) channel_flags: Byte,
+    pub(crate) push_amount: Uint128,
+    pub(crate) tlc_dust_limit: Uint128,
+    pub(crate) allowed_hash_algorithms: Bytes,
+    pub(crate) features: Uint64,
 }
 impl OpenChannelBuilder {
-    pub const FIELD_COUNT: usize = 18;
+    pub const FIELD_COUNT: usize = 22;
     pub fn chain_hash(mut self, v: Byte32) -> Self {
         self.chain_hash = v;
         self
@@ -4092,6 +4172,22 @@ impl OpenChannelBuilder {
         self.channel_flags = v;
         self
     }
+    pub fn push_amount(mut self, v: Uint128) -> Self {
+        self.push_amount = v;
+        self
+    }
+    pub fn tlc_dust_limit(mut self, v: Uint128) -> Self {
+        self.tlc_dust_limit = v;
+        self
+    }
+    pub fn allowed_hash_algorithms(mut self, v: Bytes) -> Self {
+        self.allowed_hash_algorithms = v;
+        self
+    }
+    pub fn features(mut self, v: Uint64) -> Self {
+        self.features = v;
+        self
+    }
 }
 impl molecule::prelude::Builder for OpenChannelBuilder {
     type Entity = OpenChannel;
@@ -4116,6 +4212,10 @@ impl molecule::prelude::Builder for OpenChannelBuilder {
             + self.channel_annoucement_nonce.as_slice().len()
             + self.next_local_nonce.as_slice().len()
             + self.channel_flags.as_slice().len()
+            + self.push_amount.as_slice().len()
+            + self.tlc_dust_limit.as_slice().len()
+            + self.allowed_hash_algorithms.as_slice().len()
+            + self.features.as_slice().len()
     }
     fn write<W: molecule::io::Write>(&self, writer: &mut W) -> molecule::io::Result<()> {
         let mut total_size = molecule::NUMBER_SIZE * (Self::FIELD_COUNT + 1);
@@ -4156,6 +4256,14 @@ impl molecule::prelude::Builder for OpenChannelBuilder {
         total_size += self.next_local_nonce.as_slice().len();
         offsets.push(total_size);
         total_size += self.channel_flags.as_slice().len();
+        offsets.push(total_size);
+        total_size += self.push_amount.as_slice().len();
+        offsets.push(total_size);
+        total_size += self.tlc_dust_limit.as_slice().len();
+        offsets.push(total_size);
+        total_size += self.allowed_hash_algorithms.as_slice().len();
+        offsets.push(total_size);
+        total_size += self.features.as_slice().len();
         writer.write_all(&molecule::pack_number(total_size as molecule::Number))?;
         for offset in offsets.into_iter() {
             writer.write_all(&molecule::pack_number(offset as molecule::Number))?;
@@ -4178,6 +4286,10 @@ impl molecule::prelude::Builder for OpenChannelBuilder {
         writer.write_all(self.channel_annoucement_nonce.as_slice())?;
         writer.write_all(self.next_local_nonce.as_slice())?;
         writer.write_all(self.channel_flags.as_slice())?;
+        writer.write_all(self.push_amount.as_slice())?;
+        writer.write_all(self.tlc_dust_limit.as_slice())?;
+        writer.write_all(self.allowed_hash_algorithms.as_slice())?;
+        writer.write_all(self.features.as_slice())?;
         Ok(())
     }
     fn build(&self) -> Self::Entity {
@@ -4248,6 +4360,19 @@ impl ::core::fmt::Display for AcceptChannel {
             self.channel_annoucement_nonce()
         )?;
         write!(f, ", {}: {}", "next_local_nonce", self.next_local_nonce())?;
+        write!(
+            f,
+            ", {}: {}",
+            "commitment_delay_epoch",
+            self.commitment_delay_epoch()
+        )?;
+        write!(
+            f,
+            ", {}: {}",
+            "allowed_hash_algorithms",
+            self.allowed_hash_algorithms()
+        )?;
+        write!(f, ", {}: {}", "features", self.features())?;
         let extra_count = self.count_extra_fields();
         if extra_count != 0 {
             write!(f, ", .. ({} fields)", extra_count)?;
@@ -4262,12 +4387,13 @@ impl ::core::default::Default for AcceptChannel {
     }
 }
 impl AcceptChannel {
-    const DEFAULT_VALUE: [u8; 383] = [
-        127, 1, 0, 0, 52, 0, 0, 0, 84, 0, 0, 0, 100, 0, 0, 0, 153, 0, 0, 0, 161, 0, 0, 0, 177, 0,
-        0, 0, 185, 0, 0, 0, 218, 0, 0, 0, 251, 0, 0, 0, 28, 1, 0, 0, 61, 1, 0, 0, 61, 1, 0, 0, 0,
+    const DEFAULT_VALUE: [u8; 415] = [
+        159, 1, 0, 0, 64, 0, 0, 0, 96, 0, 0, 0, 112, 0, 0, 0, 165, 0, 0, 0, 173, 0, 0, 0, 189, 0,
+        0, 0, 197, 0, 0, 0, 230, 0, 0, 0, 7, 1, 0, 0, 40, 1, 0, 0, 73, 1, 0, 0, 73, 1, 0, 0, 139,
+        1, 0, 0, 147, 1, 0, 0, 151, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        53, 0, 0, 0, 16, 0, 0, 0, 48, 0, 0, 0, 49, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 53, 0, 0, 0, 16, 0, 0, 0, 48, 0, 0, 0,
-        49, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
@@ -4276,9 +4402,9 @@ impl AcceptChannel {
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        0,
+        0, 0, 0, 0,
     ];
-    pub const FIELD_COUNT: usize = 12;
+    pub const FIELD_COUNT: usize = 15;
     pub fn total_size(&self) -> usize {
         molecule::unpack_number(self.as_slice()) as usize
     }
@@ -4364,11 +4490,29 @@ impl AcceptChannel {
     pub fn next_local_nonce(&self) -> PubNonce {
         let slice = self.as_slice();
         let start = molecule::unpack_number(&slice[48..]) as usize;
+        let end = molecule::unpack_number(&slice[52..]) as usize;
+        PubNonce::new_unchecked(self.0.slice(start..end))
+    }
+    pub fn commitment_delay_epoch(&self) -> Uint64 {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[52..]) as usize;
+        let end = molecule::unpack_number(&slice[56..]) as usize;
+        Uint64::new_unchecked(self.0.slice(start..end))
+    }
+    pub fn allowed_hash_algorithms(&self) -> Bytes {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[56..]) as usize;
+        let end = molecule::unpack_number(&slice[60..]) as usize;
+        Bytes::new_unchecked(self.0.slice(start..end))
+    }
+    pub fn features(&self) -> Uint64 {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[60..]) as usize;
         if self.has_extra_fields() {
-            let end = molecule::unpack_number(&slice[52..]) as usize;
-            PubNonce::new_unchecked(self.0.slice(start..end))
+            let end = molecule::unpack_number(&slice[64..]) as usize;
+            Uint64::new_unchecked(self.0.slice(start..end))
         } else {
-            PubNonce::new_unchecked(self.0.slice(start..))
+            Uint64::new_unchecked(self.0.slice(start..))
         }
     }
     pub fn as_reader<'r>(&'r self) -> AcceptChannelReader<'r> {
@@ -4410,6 +4554,9 @@ impl molecule::prelude::Entity for AcceptChannel {
             .second_per_commitment_point(self.second_per_commitment_point())
             .channel_annoucement_nonce(self.channel_annoucement_nonce())
             .next_local_nonce(self.next_local_nonce())
+            .commitment_delay_epoch(self.commitment_delay_epoch())
+            .allowed_hash_algorithms(self.allowed_hash_algorithms())
+            .features(self.features())
     }
 }
 #[derive(Clone, Copy)]
@@ -4473,6 +4620,19 @@ impl<'r> ::core::fmt::Display for AcceptChannelReader<'r> {
             self.channel_annoucement_nonce()
         )?;
         write!(f, ", {}: {}", "next_local_nonce", self.next_local_nonce())?;
+        write!(
+            f,
+            ", {}: {}",
+            "commitment_delay_epoch",
+            self.commitment_delay_epoch()
+        )?;
+        write!(
+            f,
+            ", {}: {}",
+            "allowed_hash_algorithms",
+            self.allowed_hash_algorithms()
+        )?;
+        write!(f, ", {}: {}", "features", self.features())?;
         let extra_count = self.count_extra_fields();
         if extra_count != 0 {
             write!(f, ", .. ({} fields)", extra_count)?;
@@ -4481,7 +4641,7 @@ impl<'r> ::core::fmt::Display for AcceptChannelReader<'r> {
     }
 }
 impl<'r> AcceptChannelReader<'r> {
-    pub const FIELD_COUNT: usize = 12;
+    pub const FIELD_COUNT: usize = 15;
     pub fn total_size(&self) -> usize {
         molecule::unpack_number(self.as_slice()) as usize
     }
@@ -4567,11 +4727,29 @@ impl<'r> AcceptChannelReader<'r> {
     pub fn next_local_nonce(&self) -> PubNonceReader<'r> {
         let slice = self.as_slice();
         let start = molecule::unpack_number(&slice[48..]) as usize;
+        let end = molecule::unpack_number(&slice[52..]) as usize;
+        PubNonceReader::new_unchecked(&self.as_slice()[start..end])
+    }
+    pub fn commitment_delay_epoch(&self) -> Uint64Reader<'r> {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[52..]) as usize;
+        let end = molecule::unpack_number(&slice[56..]) as usize;
+        Uint64Reader::new_unchecked(&self.as_slice()[start..end])
+    }
+    pub fn allowed_hash_algorithms(&self) -> BytesReader<'r> {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[56..]) as usize;
+        let end = molecule::unpack_number(&slice[60..]) as usize;
+        BytesReader::new_unchecked(&self.as_slice()[start..end])
+    }
+    pub fn features(&self) -> Uint64Reader<'r> {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[60..]) as usize;
         if self.has_extra_fields() {
-            let end = molecule::unpack_number(&slice[52..]) as usize;
-            PubNonceReader::new_unchecked(&self.as_slice()[start..end])
+            let end = molecule::unpack_number(&slice[64..]) as usize;
+            Uint64Reader::new_unchecked(&self.as_slice()[start..end])
         } else {
-            PubNonceReader::new_unchecked(&self.as_slice()[start..])
+            Uint64Reader::new_unchecked(&self.as_slice()[start..])
         }
     }
 }
@@ -4633,6 +4811,9 @@ impl<'r> molecule::prelude::Reader<'r> for AcceptChannelReader<'r> {
         PubkeyReader::verify(&slice[offsets[9]..offsets[10]], compatible)?;
         PubNonceOptReader::verify(&slice[offsets[10]..offsets[11]], compatible)?;
         PubNonceReader::verify(&slice[offsets[11]..offsets[12]], compatible)?;
+        Uint64Reader::verify(&slice[offsets[12]..offsets[13]], compatible)?;
+        BytesReader::verify(&slice[offsets[13]..offsets[14]], compatible)?;
+        Uint64Reader::verify(&slice[offsets[14]..offsets[15]], compatible)?;
         Ok(())
     }
 }
@@ -4650,9 +4831,12 @@ pub struct AcceptChannelBuilder {
     pub(crate) second_per_commitment_point: Pubkey,
     pub(crate) channel_annoucement_nonce: PubNonceOpt,
     pub(crate) next_local_nonce: PubNonce,
+    pub(crate) commitment_delay_epoch: Uint64,
+    pub(crate) allowed_hash_algorithms: Bytes,
+    pub(crate) features: Uint64,
 }
 impl AcceptChannelBuilder {
-    pub const FIELD_COUNT: usize = 12;
+    pub const FIELD_COUNT: usize = 15;
     pub fn channel_id(mut self, v: Byte32) -> Self {
         self.channel_id = v;
         self
@@ -4701,6 +4885,18 @@ impl AcceptChannelBuilder {
         self.next_local_nonce = v;
         self
     }
+    pub fn commitment_delay_epoch(mut self, v: Uint64) -> Self {
+        self.commitment_delay_epoch = v;
+        self
+    }
+    pub fn allowed_hash_algorithms(mut self, v: Bytes) -> Self {
+        self.allowed_hash_algorithms = v;
+        self
+    }
+    pub fn features(mut self, v: Uint64) -> Self {
+        self.features = v;
+        self
+    }
 }
 impl molecule::prelude::Builder for AcceptChannelBuilder {
     type Entity = AcceptChannel;
@@ -4719,6 +4915,9 @@ impl molecule::prelude::Builder for AcceptChannelBuilder {
             + self.second_per_commitment_point.as_slice().len()
             + self.channel_annoucement_nonce.as_slice().len()
             + self.next_local_nonce.as_slice().len()
+            + self.commitment_delay_epoch.as_slice().len()
+            + self.allowed_hash_algorithms.as_slice().len()
+            + self.features.as_slice().len()
     }
     fn write<W: molecule::io::Write>(&self, writer: &mut W) -> molecule::io::Result<()> {
         let mut total_size = molecule::NUMBER_SIZE * (Self::FIELD_COUNT + 1);
@@ -4747,6 +4946,12 @@ impl molecule::prelude::Builder for AcceptChannelBuilder {
         total_size += self.channel_annoucement_nonce.as_slice().len();
         offsets.push(total_size);
         total_size += self.next_local_nonce.as_slice().len();
+        offsets.push(total_size);
+        total_size += self.commitment_delay_epoch.as_slice().len();
+        offsets.push(total_size);
+        total_size += self.allowed_hash_algorithms.as_slice().len();
+        offsets.push(total_size);
+        total_size += self.features.as_slice().len();
         writer.write_all(&molecule::pack_number(total_size as molecule::Number))?;
         for offset in offsets.into_iter() {
             writer.write_all(&molecule::pack_number(offset as molecule::Number))?;
@@ -4763,6 +4968,9 @@ impl molecule::prelude::Builder for AcceptChannelBuilder {
         writer.write_all(self.second_per_commitment_point.as_slice())?;
         writer.write_all(self.channel_annoucement_nonce.as_slice())?;
         writer.write_all(self.next_local_nonce.as_slice())?;
+        writer.write_all(self.commitment_delay_epoch.as_slice())?;
+        writer.write_all(self.allowed_hash_algorithms.as_slice())?;
+        writer.write_all(self.features.as_slice())?;
         Ok(())
     }
     fn build(&self) -> Self::Entity {
@@ -7499,6 +7707,12 @@ impl ::core::fmt::Display for RevokeAndAck {
             "next_per_commitment_point",
             self.next_per_commitment_point()
         )?;
+        write!(
+            f,
+            ", {}: {}",
+            "last_per_commitment_secret",
+            self.last_per_commitment_secret()
+        )?;
         write!(f, " }}")
     }
 }
@@ -7509,16 +7723,17 @@ impl ::core::default::Default for RevokeAndAck {
     }
 }
 impl RevokeAndAck {
-    const DEFAULT_VALUE: [u8; 129] = [
+    const DEFAULT_VALUE: [u8; 161] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
     ];
-    pub const TOTAL_SIZE: usize = 129;
-    pub const FIELD_SIZES: [usize; 4] = [32, 32, 32, 33];
-    pub const FIELD_COUNT: usize = 4;
+    pub const TOTAL_SIZE: usize = 161;
+    pub const FIELD_SIZES: [usize; 5] = [32, 32, 32, 33, 32];
+    pub const FIELD_COUNT: usize = 5;
     pub fn channel_id(&self) -> Byte32 {
         Byte32::new_unchecked(self.0.slice(0..32))
     }
@@ -7531,6 +7746,9 @@ impl RevokeAndAck {
     pub fn next_per_commitment_point(&self) -> Pubkey {
         Pubkey::new_unchecked(self.0.slice(96..129))
     }
+    pub fn last_per_commitment_secret(&self) -> Byte32 {
+        Byte32::new_unchecked(self.0.slice(129..161))
+    }
     pub fn as_reader<'r>(&'r self) -> RevokeAndAckReader<'r> {
         RevokeAndAckReader::new_unchecked(self.as_slice())
     }
@@ -7562,6 +7780,7 @@ impl molecule::prelude::Entity for RevokeAndAck {
             .revocation_partial_signature(self.revocation_partial_signature())
             .commitment_tx_partial_signature(self.commitment_tx_partial_signature())
             .next_per_commitment_point(self.next_per_commitment_point())
+            .last_per_commitment_secret(self.last_per_commitment_secret())
     }
 }
 #[derive(Clone, Copy)]
@@ -7602,13 +7821,19 @@ impl<'r> ::core::fmt::Display for RevokeAndAckReader<'r> {
             "next_per_commitment_point",
             self.next_per_commitment_point()
         )?;
+        write!(
+            f,
+            ", {}: {}",
+            "last_per_commitment_secret",
+            self.last_per_commitment_secret()
+        )?;
         write!(f, " }}")
     }
 }
 impl<'r> RevokeAndAckReader<'r> {
-    pub const TOTAL_SIZE: usize = 129;
-    pub const FIELD_SIZES: [usize; 4] = [32, 32, 32, 33];
-    pub const FIELD_COUNT: usize = 4;
+    pub const TOTAL_SIZE: usize = 161;
+    pub const FIELD_SIZES: [usize; 5] = [32, 32, 32, 33, 32];
+    pub const FIELD_COUNT: usize = 5;
     pub fn channel_id(&self) -> Byte32Reader<'r> {
         Byte32Reader::new_unchecked(&self.as_slice()[0..32])
     }
@@ -7621,6 +7846,9 @@ impl<'r> RevokeAndAckReader<'r> {
     pub fn next_per_commitment_point(&self) -> PubkeyReader<'r> {
         PubkeyReader::new_unchecked(&self.as_slice()[96..129])
     }
+    pub fn last_per_commitment_secret(&self) -> Byte32Reader<'r> {
+        Byte32Reader::new_unchecked(&self.as_slice()[129..161])
+    }
 }
 impl<'r> molecule::prelude::Reader<'r> for RevokeAndAckReader<'r> {
     type Entity = RevokeAndAck;
@@ -7649,11 +7877,12 @@ pub struct RevokeAndAckBuilder {
     pub(crate) revocation_partial_signature: Byte32,
     pub(crate) commitment_tx_partial_signature: Byte32,
     pub(crate) next_per_commitment_point: Pubkey,
+    pub(crate) last_per_commitment_secret: Byte32,
 }
 impl RevokeAndAckBuilder {
-    pub const TOTAL_SIZE: usize = 129;
-    pub const FIELD_SIZES: [usize; 4] = [32, 32, 32, 33];
-    pub const FIELD_COUNT: usize = 4;
+    pub const TOTAL_SIZE: usize = 161;
+    pub const FIELD_SIZES: [usize; 5] = [32, 32, 32, 33, 32];
+    pub const FIELD_COUNT: usize = 5;
     pub fn channel_id(mut self, v: Byte32) -> Self {
         self.channel_id = v;
         self
@@ -7670,6 +7899,10 @@ impl RevokeAndAckBuilder {
         self.next_per_commitment_point = v;
         self
     }
+    pub fn last_per_commitment_secret(mut self, v: Byte32) -> Self {
+        self.last_per_commitment_secret = v;
+        self
+    }
 }
 impl molecule::prelude::Builder for RevokeAndAckBuilder {
     type Entity = RevokeAndAck;
@@ -7682,6 +7915,7 @@ impl molecule::prelude::Builder for RevokeAndAckBuilder {
         writer.write_all(self.revocation_partial_signature.as_slice())?;
         writer.write_all(self.commitment_tx_partial_signature.as_slice())?;
         writer.write_all(self.next_per_commitment_point.as_slice())?;
+        writer.write_all(self.last_per_commitment_secret.as_slice())?;
         Ok(())
     }
     fn build(&self) -> Self::Entity {
@@ -8705,6 +8939,12 @@ impl ::core::fmt::Display for ReestablishChannel {
             "remote_commitment_number",
             self.remote_commitment_number()
         )?;
+        write!(
+            f,
+            ", {}: {}",
+            "force_close_request",
+            self.force_close_request()
+        )?;
         let extra_count = self.count_extra_fields();
         if extra_count != 0 {
             write!(f, ", .. ({} fields)", extra_count)?;
@@ -8719,12 +8959,12 @@ impl ::core::default::Default for ReestablishChannel {
     }
 }
 impl ReestablishChannel {
-    const DEFAULT_VALUE: [u8; 64] = [
-        64, 0, 0, 0, 16, 0, 0, 0, 48, 0, 0, 0, 56, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    const DEFAULT_VALUE: [u8; 69] = [
+        69, 0, 0, 0, 20, 0, 0, 0, 52, 0, 0, 0, 60, 0, 0, 0, 68, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-        0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
     ];
-    pub const FIELD_COUNT: usize = 3;
+    pub const FIELD_COUNT: usize = 4;
     pub fn total_size(&self) -> usize {
         molecule::unpack_number(self.as_slice()) as usize
     }
@@ -8756,11 +8996,17 @@ impl ReestablishChannel {
     pub fn remote_commitment_number(&self) -> Uint64 {
         let slice = self.as_slice();
         let start = molecule::unpack_number(&slice[12..]) as usize;
+        let end = molecule::unpack_number(&slice[16..]) as usize;
+        Uint64::new_unchecked(self.0.slice(start..end))
+    }
+    pub fn force_close_request(&self) -> Byte {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[16..]) as usize;
         if self.has_extra_fields() {
-            let end = molecule::unpack_number(&slice[16..]) as usize;
-            Uint64::new_unchecked(self.0.slice(start..end))
+            let end = molecule::unpack_number(&slice[20..]) as usize;
+            Byte::new_unchecked(self.0.slice(start..end))
         } else {
-            Uint64::new_unchecked(self.0.slice(start..))
+            Byte::new_unchecked(self.0.slice(start..))
         }
     }
     pub fn as_reader<'r>(&'r self) -> ReestablishChannelReader<'r> {
@@ -8793,6 +9039,7 @@ impl molecule::prelude::Entity for ReestablishChannel {
             .channel_id(self.channel_id())
             .local_commitment_number(self.local_commitment_number())
             .remote_commitment_number(self.remote_commitment_number())
+            .force_close_request(self.force_close_request())
     }
 }
 #[derive(Clone, Copy)]
@@ -8827,6 +9074,12 @@ impl<'r> ::core::fmt::Display for ReestablishChannelReader<'r> {
             "remote_commitment_number",
             self.remote_commitment_number()
         )?;
+        write!(
+            f,
+            ", {}: {}",
+            "force_close_request",
+            self.force_close_request()
+        )?;
         let extra_count = self.count_extra_fields();
         if extra_count != 0 {
             write!(f, ", .. ({} fields)", extra_count)?;
@@ -8835,7 +9088,7 @@ impl<'r> ::core::fmt::Display for ReestablishChannelReader<'r> {
     }
 }
 impl<'r> ReestablishChannelReader<'r> {
-    pub const FIELD_COUNT: usize = 3;
+    pub const FIELD_COUNT: usize = 4;
     pub fn total_size(&self) -> usize {
         molecule::unpack_number(self.as_slice()) as usize
     }
@@ -8867,11 +9120,17 @@ impl<'r> ReestablishChannelReader<'r> {
     pub fn remote_commitment_number(&self) -> Uint64Reader<'r> {
         let slice = self.as_slice();
         let start = molecule::unpack_number(&slice[12..]) as usize;
+        let end = molecule::unpack_number(&slice[16..]) as usize;
+        Uint64Reader::new_unchecked(&self.as_slice()[start..end])
+    }
+    pub fn force_close_request(&self) -> ByteReader<'r> {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[16..]) as usize;
         if self.has_extra_fields() {
-            let end = molecule::unpack_number(&slice[16..]) as usize;
-            Uint64Reader::new_unchecked(&self.as_slice()[start..end])
+            let end = molecule::unpack_number(&slice[20..]) as usize;
+            ByteReader::new_unchecked(&self.as_slice()[start..end])
         } else {
-            Uint64Reader::new_unchecked(&self.as_slice()[start..])
+            ByteReader::new_unchecked(&self.as_slice()[start..])
         }
     }
 }
@@ -8924,6 +9183,7 @@ impl<'r> molecule::prelude::Reader<'r> for ReestablishChannelReader<'r> {
         Byte32Reader::verify(&slice[offsets[0]..offsets[1]], compatible)?;
         Uint64Reader::verify(&slice[offsets[1]..offsets[2]], compatible)?;
         Uint64Reader::verify(&slice[offsets[2]..offsets[3]], compatible)?;
+        ByteReader::verify(&slice[offsets[3]..offsets[4]], compatible)?;
         Ok(())
     }
 }
@@ -8932,9 +9192,10 @@ pub struct ReestablishChannelBuilder {
     pub(crate) channel_id: Byte32,
     pub(crate) local_commitment_number: Uint64,
     pub(crate) remote_commitment_number: Uint64,
+    pub(crate) force_close_request: Byte,
 }
 impl ReestablishChannelBuilder {
-    pub const FIELD_COUNT: usize = 3;
+    pub const FIELD_COUNT: usize = 4;
     pub fn channel_id(mut self, v: Byte32) -> Self {
         self.channel_id = v;
         self
@@ -8947,6 +9208,10 @@ impl ReestablishChannelBuilder {
         self.remote_commitment_number = v;
         self
     }
+    pub fn force_close_request(mut self, v: Byte) -> Self {
+        self.force_close_request = v;
+        self
+    }
 }
 impl molecule::prelude::Builder for ReestablishChannelBuilder {
     type Entity = ReestablishChannel;
@@ -8956,6 +9221,7 @@ impl molecule::prelude::Builder for ReestablishChannelBuilder {
             + self.channel_id.as_slice().len()
             + self.local_commitment_number.as_slice().len()
             + self.remote_commitment_number.as_slice().len()
+            + self.force_close_request.as_slice().len()
     }
     fn write<W: molecule::io::Write>(&self, writer: &mut W) -> molecule::io::Result<()> {
         let mut total_size = molecule::NUMBER_SIZE * (Self::FIELD_COUNT + 1);
@@ -8966,6 +9232,8 @@ impl molecule::prelude::Builder for ReestablishChannelBuilder {
         total_size += self.local_commitment_number.as_slice().len();
         offsets.push(total_size);
         total_size += self.remote_commitment_number.as_slice().len();
+        offsets.push(total_size);
+        total_size += self.force_close_request.as_slice().len();
         writer.write_all(&molecule::pack_number(total_size as molecule::Number))?;
         for offset in offsets.into_iter() {
             writer.write_all(&molecule::pack_number(offset as molecule::Number))?;
@@ -8973,6 +9241,7 @@ impl molecule::prelude::Builder for ReestablishChannelBuilder {
         writer.write_all(self.channel_id.as_slice())?;
         writer.write_all(self.local_commitment_number.as_slice())?;
         writer.write_all(self.remote_commitment_number.as_slice())?;
+        writer.write_all(self.force_close_request.as_slice())?;
         Ok(())
     }
     fn build(&self) -> Self::Entity {
@@ -9301,6 +9570,294 @@ impl molecule::prelude::Builder for AnnouncementSignaturesBuilder {
     }
 }
 #[derive(Clone)]
+pub struct UpdateFee(molecule::bytes::Bytes);
+impl ::core::fmt::LowerHex for UpdateFee {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        use molecule::hex_string;
+        if f.alternate() {
+            write!(f, "0x")?;
+        }
+        write!(f, "{}", hex_string(self.as_slice()))
+    }
+}
+impl ::core::fmt::Debug for UpdateFee {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{}({:#x})", Self::NAME, self)
+    }
+}
+impl ::core::fmt::Display for UpdateFee {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{} {{ ", Self::NAME)?;
+        write!(f, "{}: {}", "channel_id", self.channel_id())?;
+        write!(f, ", {}: {}", "fee_rate", self.fee_rate())?;
+        write!(f, ", {}: {}", "ack", self.ack())?;
+        let extra_count = self.count_extra_fields();
+        if extra_count != 0 {
+            write!(f, ", .. ({} fields)", extra_count)?;
+        }
+        write!(f, " }}")
+    }
+}
+impl ::core::default::Default for UpdateFee {
+    fn default() -> Self {
+        let v = molecule::bytes::Bytes::from_static(&Self::DEFAULT_VALUE);
+        UpdateFee::new_unchecked(v)
+    }
+}
+impl UpdateFee {
+    const DEFAULT_VALUE: [u8; 57] = [
+        57, 0, 0, 0, 16, 0, 0, 0, 48, 0, 0, 0, 56, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    pub const FIELD_COUNT: usize = 3;
+    pub fn total_size(&self) -> usize {
+        molecule::unpack_number(self.as_slice()) as usize
+    }
+    pub fn field_count(&self) -> usize {
+        if self.total_size() == molecule::NUMBER_SIZE {
+            0
+        } else {
+            (molecule::unpack_number(&self.as_slice()[molecule::NUMBER_SIZE..]) as usize / 4) - 1
+        }
+    }
+    pub fn count_extra_fields(&self) -> usize {
+        self.field_count() - Self::FIELD_COUNT
+    }
+    pub fn has_extra_fields(&self) -> bool {
+        Self::FIELD_COUNT != self.field_count()
+    }
+    pub fn channel_id(&self) -> Byte32 {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[4..]) as usize;
+        let end = molecule::unpack_number(&slice[8..]) as usize;
+        Byte32::new_unchecked(self.0.slice(start..end))
+    }
+    pub fn fee_rate(&self) -> Uint64 {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[8..]) as usize;
+        let end = molecule::unpack_number(&slice[12..]) as usize;
+        Uint64::new_unchecked(self.0.slice(start..end))
+    }
+    pub fn ack(&self) -> Byte {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[12..]) as usize;
+        if self.has_extra_fields() {
+            let end = molecule::unpack_number(&slice[16..]) as usize;
+            Byte::new_unchecked(self.0.slice(start..end))
+        } else {
+            Byte::new_unchecked(self.0.slice(start..))
+        }
+    }
+    pub fn as_reader<'r>(&'r self) -> UpdateFeeReader<'r> {
+        UpdateFeeReader::new_unchecked(self.as_slice())
+    }
+}
+impl molecule::prelude::Entity for UpdateFee {
+    type Builder = UpdateFeeBuilder;
+    const NAME: &'static str = "UpdateFee";
+    fn new_unchecked(data: molecule::bytes::Bytes) -> Self {
+        UpdateFee(data)
+    }
+    fn as_bytes(&self) -> molecule::bytes::Bytes {
+        self.0.clone()
+    }
+    fn as_slice(&self) -> &[u8] {
+        &self.0[..]
+    }
+    fn from_slice(slice: &[u8]) -> molecule::error::VerificationResult<Self> {
+        UpdateFeeReader::from_slice(slice).map(|reader| reader.to_entity())
+    }
+    fn from_compatible_slice(slice: &[u8]) -> molecule::error::VerificationResult<Self> {
+        UpdateFeeReader::from_compatible_slice(slice).map(|reader| reader.to_entity())
+    }
+    fn new_builder() -> Self::Builder {
+        ::core::default::Default::default()
+    }
+    fn as_builder(self) -> Self::Builder {
+        Self::new_builder()
+            .channel_id(self.channel_id())
+            .fee_rate(self.fee_rate())
+            .ack(self.ack())
+    }
+}
+#[derive(Clone, Copy)]
+pub struct UpdateFeeReader<'r>(&'r [u8]);
+impl<'r> ::core::fmt::LowerHex for UpdateFeeReader<'r> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        use molecule::hex_string;
+        if f.alternate() {
+            write!(f, "0x")?;
+        }
+        write!(f, "{}", hex_string(self.as_slice()))
+    }
+}
+impl<'r> ::core::fmt::Debug for UpdateFeeReader<'r> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{}({:#x})", Self::NAME, self)
+    }
+}
+impl<'r> ::core::fmt::Display for UpdateFeeReader<'r> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "{} {{ ", Self::NAME)?;
+        write!(f, "{}: {}", "channel_id", self.channel_id())?;
+        write!(f, ", {}: {}", "fee_rate", self.fee_rate())?;
+        write!(f, ", {}: {}", "ack", self.ack())?;
+        let extra_count = self.count_extra_fields();
+        if extra_count != 0 {
+            write!(f, ", .. ({} fields)", extra_count)?;
+        }
+        write!(f, " }}")
+    }
+}
+impl<'r> UpdateFeeReader<'r> {
+    pub const FIELD_COUNT: usize = 3;
+    pub fn total_size(&self) -> usize {
+        molecule::unpack_number(self.as_slice()) as usize
+    }
+    pub fn field_count(&self) -> usize {
+        if self.total_size() == molecule::NUMBER_SIZE {
+            0
+        } else {
+            (molecule::unpack_number(&self.as_slice()[molecule::NUMBER_SIZE..]) as usize / 4) - 1
+        }
+    }
+    pub fn count_extra_fields(&self) -> usize {
+        self.field_count() - Self::FIELD_COUNT
+    }
+    pub fn has_extra_fields(&self) -> bool {
+        Self::FIELD_COUNT != self.field_count()
+    }
+    pub fn channel_id(&self) -> Byte32Reader<'r> {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[4..]) as usize;
+        let end = molecule::unpack_number(&slice[8..]) as usize;
+        Byte32Reader::new_unchecked(&self.as_slice()[start..end])
+    }
+    pub fn fee_rate(&self) -> Uint64Reader<'r> {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[8..]) as usize;
+        let end = molecule::unpack_number(&slice[12..]) as usize;
+        Uint64Reader::new_unchecked(&self.as_slice()[start..end])
+    }
+    pub fn ack(&self) -> ByteReader<'r> {
+        let slice = self.as_slice();
+        let start = molecule::unpack_number(&slice[12..]) as usize;
+        if self.has_extra_fields() {
+            let end = molecule::unpack_number(&slice[16..]) as usize;
+            ByteReader::new_unchecked(&self.as_slice()[start..end])
+        } else {
+            ByteReader::new_unchecked(&self.as_slice()[start..])
+        }
+    }
+}
+impl<'r> molecule::prelude::Reader<'r> for UpdateFeeReader<'r> {
+    type Entity = UpdateFee;
+    const NAME: &'static str = "UpdateFeeReader";
+    fn to_entity(&self) -> Self::Entity {
+        Self::Entity::new_unchecked(self.as_slice().to_owned().into())
+    }
+    fn new_unchecked(slice: &'r [u8]) -> Self {
+        UpdateFeeReader(slice)
+    }
+    fn as_slice(&self) -> &'r [u8] {
+        self.0
+    }
+    fn verify(slice: &[u8], compatible: bool) -> molecule::error::VerificationResult<()> {
+        use molecule::verification_error as ve;
+        let slice_len = slice.len();
+        if slice_len < molecule::NUMBER_SIZE {
+            return ve!(Self, HeaderIsBroken, molecule::NUMBER_SIZE, slice_len);
+        }
+        let total_size = molecule::unpack_number(slice) as usize;
+        if slice_len != total_size {
+            return ve!(Self, TotalSizeNotMatch, total_size, slice_len);
+        }
+        if slice_len < molecule::NUMBER_SIZE * 2 {
+            return ve!(Self, HeaderIsBroken, molecule::NUMBER_SIZE * 2, slice_len);
+        }
+        let offset_first = molecule::unpack_number(&slice[molecule::NUMBER_SIZE..]) as usize;
+        if offset_first % molecule::NUMBER_SIZE != 0 || offset_first < molecule::NUMBER_SIZE * 2 {
+            return ve!(Self, OffsetsNotMatch);
+        }
+        if slice_len < offset_first {
+            return ve!(Self, HeaderIsBroken, offset_first, slice_len);
+        }
+        let field_count = offset_first / molecule::NUMBER_SIZE - 1;
+        if field_count < Self::FIELD_COUNT {
+            return ve!(Self, FieldCountNotMatch, Self::FIELD_COUNT, field_count);
+        } else if !compatible && field_count > Self::FIELD_COUNT {
+            return ve!(Self, FieldCountNotMatch, Self::FIELD_COUNT, field_count);
+        };
+        let mut offsets: Vec<usize> = slice[molecule::NUMBER_SIZE..offset_first]
+            .chunks_exact(molecule::NUMBER_SIZE)
+            .map(|x| molecule::unpack_number(x) as usize)
+            .collect();
+        offsets.push(total_size);
+        if offsets.windows(2).any(|i| i[0] > i[1]) {
+            return ve!(Self, OffsetsNotMatch);
+        }
+        Byte32Reader::verify(&slice[offsets[0]..offsets[1]], compatible)?;
+        Uint64Reader::verify(&slice[offsets[1]..offsets[2]], compatible)?;
+        ByteReader::verify(&slice[offsets[2]..offsets[3]], compatible)?;
+        Ok(())
+    }
+}
+#[derive(Clone, Debug, Default)]
+pub struct UpdateFeeBuilder {
+    pub(crate) channel_id: Byte32,
+    pub(crate) fee_rate: Uint64,
+    pub(crate) ack: Byte,
+}
+impl UpdateFeeBuilder {
+    pub const FIELD_COUNT: usize = 3;
+    pub fn channel_id(mut self, v: Byte32) -> Self {
+        self.channel_id = v;
+        self
+    }
+    pub fn fee_rate(mut self, v: Uint64) -> Self {
+        self.fee_rate = v;
+        self
+    }
+    pub fn ack(mut self, v: Byte) -> Self {
+        self.ack = v;
+        self
+    }
+}
+impl molecule::prelude::Builder for UpdateFeeBuilder {
+    type Entity = UpdateFee;
+    const NAME: &'static str = "UpdateFeeBuilder";
+    fn expected_length(&self) -> usize {
+        molecule::NUMBER_SIZE * (Self::FIELD_COUNT + 1)
+            + self.channel_id.as_slice().len()
+            + self.fee_rate.as_slice().len()
+            + self.ack.as_slice().len()
+    }
+    fn write<W: molecule::io::Write>(&self, writer: &mut W) -> molecule::io::Result<()> {
+        let mut total_size = molecule::NUMBER_SIZE * (Self::FIELD_COUNT + 1);
+        let mut offsets = Vec::with_capacity(Self::FIELD_COUNT);
+        offsets.push(total_size);
+        total_size += self.channel_id.as_slice().len();
+        offsets.push(total_size);
+        total_size += self.fee_rate.as_slice().len();
+        offsets.push(total_size);
+        total_size += self.ack.as_slice().len();
+        writer.write_all(&molecule::pack_number(total_size as molecule::Number))?;
+        for offset in offsets.into_iter() {
+            writer.write_all(&molecule::pack_number(offset as molecule::Number))?;
+        }
+        writer.write_all(self.channel_id.as_slice())?;
+        writer.write_all(self.fee_rate.as_slice())?;
+        writer.write_all(self.ack.as_slice())?;
+        Ok(())
+    }
+    fn build(&self) -> Self::Entity {
+        let mut inner = Vec::with_capacity(self.expected_length());
+        self.write(&mut inner)
+            .unwrap_or_else(|_| panic!("{} build should be ok", Self::NAME));
+        UpdateFee::new_unchecked(inner.into())
+    }
+}
+#[derive(Clone)]
 pub struct UdtCellDep(molecule::bytes::Bytes);
 impl ::core::fmt::LowerHex for UdtCellDep {
     fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
@@ -10938,7 +11495,7 @@ impl FiberMessage {
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
     ];
-    pub const ITEMS_COUNT: usize = 17;
+    pub const ITEMS_COUNT: usize = 18;
     pub fn item_id(&self) -> molecule::Number {
         molecule::unpack_number(self.as_slice())
     }
@@ -10962,6 +11519,7 @@ impl FiberMessage {
             14 => ClosingSigned::new_unchecked(inner).into(),
             15 => ReestablishChannel::new_unchecked(inner).into(),
             16 => AnnouncementSignatures::new_unchecked(inner).into(),
+            17 => UpdateFee::new_unchecked(inner).into(),
             _ => panic!("{}: invalid data", Self::NAME),
         }
     }
@@ -11018,7 +11576,7 @@ impl<'r> ::core::fmt::Display for FiberMessageReader<'r> {
     }
 }
 impl<'r> FiberMessageReader<'r> {
-    pub const ITEMS_COUNT: usize = 17;
+    pub const ITEMS_COUNT: usize = 18;
     pub fn item_id(&self) -> molecule::Number {
         molecule::unpack_number(self.as_slice())
     }
@@ -11042,6 +11600,7 @@ impl<'r> FiberMessageReader<'r> {
             14 => ClosingSignedReader::new_unchecked(inner).into(),
             15 => ReestablishChannelReader::new_unchecked(inner).into(),
             16 => AnnouncementSignaturesReader::new_unchecked(inner).into(),
+            17 => UpdateFeeReader::new_unchecked(inner).into(),
             _ => panic!("{}: invalid data", Self::NAME),
         }
     }
@@ -11084,6 +11643,7 @@ impl<'r> molecule::prelude::Reader<'r> for FiberMessageReader<'r> {
             14 => ClosingSignedReader::verify(inner_slice, compatible),
             15 => ReestablishChannelReader::verify(inner_slice, compatible),
             16 => AnnouncementSignaturesReader::verify(inner_slice, compatible),
+            17 => UpdateFeeReader::verify(inner_slice, compatible),
             _ => ve!(Self, UnknownItem, Self::ITEMS_COUNT, item_id),
         }?;
         Ok(())
@@ -11092,7 +11652,7 @@ impl<'r> molecule::prelude::Reader<'r> for FiberMessageReader<'r> {
 #[derive(Clone, Debug, Default)]
 pub struct FiberMessageBuilder(pub(crate) FiberMessageUnion);
 impl FiberMessageBuilder {
-    pub const ITEMS_COUNT: usize = 17;
+    pub const ITEMS_COUNT: usize = 18;
     pub fn set<I>(mut self, v: I) -> Self
     where
         I: ::core::convert::Into<FiberMessageUnion>,
@@ -11137,6 +11697,7 @@ pub enum FiberMessageUnion {
     ClosingSigned(ClosingSigned),
     ReestablishChannel(ReestablishChannel),
     AnnouncementSignatures(AnnouncementSignatures),
+    UpdateFee(UpdateFee),
 }
 #[derive(Debug, Clone, Copy)]
 pub enum FiberMessageUnionReader<'r> {
@@ -11157,6 +11718,7 @@ pub enum FiberMessageUnionReader<'r> {
     ClosingSigned(ClosingSignedReader<'r>),
     ReestablishChannel(ReestablishChannelReader<'r>),
     AnnouncementSignatures(AnnouncementSignaturesReader<'r>),
+    UpdateFee(UpdateFeeReader<'r>),
 }
 impl ::core::default::Default for FiberMessageUnion {
     fn default() -> Self {
@@ -11223,6 +11785,9 @@ impl ::core::fmt::Display for FiberMessageUnion {
                     item
                 )
             }
+            FiberMessageUnion::UpdateFee(ref item) => {
+                write!(f, "{}::{}({})", Self::NAME, UpdateFee::NAME, item)
+            }
         }
     }
 }
@@ -11286,6 +11851,9 @@ impl<'r> ::core::fmt::Display for FiberMessageUnionReader<'r> {
                     item
                 )
             }
+            FiberMessageUnionReader::UpdateFee(ref item) => {
+                write!(f, "{}::{}({})", Self::NAME, UpdateFee::NAME, item)
+            }
         }
     }
 }
@@ -11309,6 +11877,7 @@ impl FiberMessageUnion {
             FiberMessageUnion::ClosingSigned(ref item) => write!(f, "{}", item),
             FiberMessageUnion::ReestablishChannel(ref item) => write!(f, "{}", item),
             FiberMessageUnion::AnnouncementSignatures(ref item) => write!(f, "{}", item),
+            FiberMessageUnion::UpdateFee(ref item) => write!(f, "{}", item),
         }
     }
 }
@@ -11332,6 +11901,7 @@ impl<'r> FiberMessageUnionReader<'r> {
             FiberMessageUnionReader::ClosingSigned(ref item) => write!(f, "{}", item),
             FiberMessageUnionReader::ReestablishChannel(ref item) => write!(f, "{}", item),
             FiberMessageUnionReader::AnnouncementSignatures(ref item) => write!(f, "{}", item),
+            FiberMessageUnionReader::UpdateFee(ref item) => write!(f, "{}", item),
         }
     }
 }
@@ -11420,6 +11990,11 @@ impl ::core::convert::From<AnnouncementSignatures> for FiberMessageUnion {
         FiberMessageUnion::AnnouncementSignatures(item)
     }
 }
+impl ::core::convert::From<UpdateFee> for FiberMessageUnion {
+    fn from(item: UpdateFee) -> Self {
+        FiberMessageUnion::UpdateFee(item)
+    }
+}
 impl<'r> ::core::convert::From<OpenChannelReader<'r>> for FiberMessageUnionReader<'r> {
     fn from(item: OpenChannelReader<'r>) -> Self {
         FiberMessageUnionReader::OpenChannel(item)
@@ -11505,6 +12080,11 @@ impl<'r> ::core::convert::From<AnnouncementSignaturesReader<'r>> for FiberMessag
         FiberMessageUnionReader::AnnouncementSignatures(item)
     }
 }
+impl<'r> ::core::convert::From<UpdateFeeReader<'r>> for FiberMessageUnionReader<'r> {
+    fn from(item: UpdateFeeReader<'r>) -> Self {
+        FiberMessageUnionReader::UpdateFee(item)
+    }
+}
 impl FiberMessageUnion {
     pub const NAME: &'static str = "FiberMessageUnion";
     pub fn as_bytes(&self) -> molecule::bytes::Bytes {
@@ -11526,6 +12106,7 @@ impl FiberMessageUnion {
             FiberMessageUnion::ClosingSigned(item) => item.as_bytes(),
             FiberMessageUnion::ReestablishChannel(item) => item.as_bytes(),
             FiberMessageUnion::AnnouncementSignatures(item) => item.as_bytes(),
+            FiberMessageUnion::UpdateFee(item) => item.as_bytes(),
         }
     }
     pub fn as_slice(&self) -> &[u8] {
@@ -11547,6 +12128,7 @@ impl FiberMessageUnion {
             FiberMessageUnion::ClosingSigned(item) => item.as_slice(),
             FiberMessageUnion::ReestablishChannel(item) => item.as_slice(),
             FiberMessageUnion::AnnouncementSignatures(item) => item.as_slice(),
+            FiberMessageUnion::UpdateFee(item) => item.as_slice(),
         }
     }
     pub fn item_id(&self) -> molecule::Number {
@@ -11568,6 +12150,7 @@ impl FiberMessageUnion {
             FiberMessageUnion::ClosingSigned(_) => 14,
             FiberMessageUnion::ReestablishChannel(_) => 15,
             FiberMessageUnion::AnnouncementSignatures(_) => 16,
+            FiberMessageUnion::UpdateFee(_) => 17,
         }
     }
     pub fn item_name(&self) -> &str {
@@ -11589,6 +12172,7 @@ impl FiberMessageUnion {
             FiberMessageUnion::ClosingSigned(_) => "ClosingSigned",
             FiberMessageUnion::ReestablishChannel(_) => "ReestablishChannel",
             FiberMessageUnion::AnnouncementSignatures(_) => "AnnouncementSignatures",
+            FiberMessageUnion::UpdateFee(_) => "UpdateFee",
         }
     }
     pub fn as_reader<'r>(&'r self) -> FiberMessageUnionReader<'r> {
@@ -11610,6 +12194,7 @@ impl FiberMessageUnion {
             FiberMessageUnion::ClosingSigned(item) => item.as_reader().into(),
             FiberMessageUnion::ReestablishChannel(item) => item.as_reader().into(),
             FiberMessageUnion::AnnouncementSignatures(item) => item.as_reader().into(),
+            FiberMessageUnion::UpdateFee(item) => item.as_reader().into(),
         }
     }
 }
@@ -11634,6 +12219,7 @@ impl<'r> FiberMessageUnionReader<'r> {
             FiberMessageUnionReader::ClosingSigned(item) => item.as_slice(),
             FiberMessageUnionReader::ReestablishChannel(item) => item.as_slice(),
             FiberMessageUnionReader::AnnouncementSignatures(item) => item.as_slice(),
+            FiberMessageUnionReader::UpdateFee(item) => item.as_slice(),
         }
     }
     pub fn item_id(&self) -> molecule::Number {
@@ -11655,6 +12241,7 @@ impl<'r> FiberMessageUnionReader<'r> {
             FiberMessageUnionReader::ClosingSigned(_) => 14,
             FiberMessageUnionReader::ReestablishChannel(_) => 15,
             FiberMessageUnionReader::AnnouncementSignatures(_) => 16,
+            FiberMessageUnionReader::UpdateFee(_) => 17,
         }
     }
     pub fn item_name(&self) -> &str {
@@ -11676,6 +12263,7 @@ impl<'r> FiberMessageUnionReader<'r> {
             FiberMessageUnionReader::ClosingSigned(_) => "ClosingSigned",
             FiberMessageUnionReader::ReestablishChannel(_) => "ReestablishChannel",
             FiberMessageUnionReader::AnnouncementSignatures(_) => "AnnouncementSignatures",
+            FiberMessageUnionReader::UpdateFee(_) => "UpdateFee",
         }
     }
 }
@@ -11764,3 +12352,8 @@ impl From<AnnouncementSignatures> for FiberMessage {
         Self::new_builder().set(value).build()
     }
 }
+impl From<UpdateFee> for FiberMessage {
+    fn from(value: UpdateFee) -> Self {
+        Self::new_builder().set(value).build()
+    }
+}
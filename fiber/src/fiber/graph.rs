@@ -617,9 +617,11 @@ where
     }
 
     pub(crate) fn record_payment_success(&mut self, mut payment_session: PaymentSession) {
-        let session_route = &payment_session.route.nodes;
         let mut result = InternalResult::default();
-        result.succeed_range_pairs(session_route, 0, session_route.len() - 1);
+        for route in payment_session.routes() {
+            let session_route = &route.nodes;
+            result.succeed_range_pairs(session_route, 0, session_route.len() - 1);
+        }
         self.history.apply_internal_result(result);
         payment_session.set_success_status();
         self.store.insert_payment_session(payment_session);
@@ -630,11 +632,15 @@ where
         payment_session: &PaymentSession,
         tlc_err: TlcErr,
     ) -> bool {
+        let need_to_retry = self.record_route_fail(&payment_session.route, tlc_err);
+        return need_to_retry && payment_session.can_retry();
+    }
+
+    pub(crate) fn record_route_fail(&mut self, route: &SessionRoute, tlc_err: TlcErr) -> bool {
         let mut internal_result = InternalResult::default();
-        let nodes = &payment_session.route.nodes;
-        let need_to_retry = internal_result.record_payment_fail(nodes, tlc_err);
+        let need_to_retry = internal_result.record_payment_fail(&route.nodes, tlc_err);
         self.history.apply_internal_result(internal_result);
-        return need_to_retry && payment_session.can_retry();
+        need_to_retry
     }
 
     #[cfg(test)]
@@ -655,14 +661,44 @@ where
     pub fn build_route(
         &self,
         payment_data: SendPaymentData,
+    ) -> Result<Vec<PaymentHopData>, PathFindError> {
+        self.build_route_with_part(payment_data, None, &HashSet::new())
+    }
+
+    /// Returns the route of a part of a multi-part payment, which pays `part_amount` of the
+    /// payment amount through a first hop channel not in `excluded_first_hops`.
+    pub fn build_part_route(
+        &self,
+        payment_data: SendPaymentData,
+        part_amount: u128,
+        excluded_first_hops: &HashSet<OutPoint>,
+    ) -> Result<Vec<PaymentHopData>, PathFindError> {
+        self.build_route_with_part(payment_data, Some(part_amount), excluded_first_hops)
+    }
+
+    fn build_route_with_part(
+        &self,
+        payment_data: SendPaymentData,
+        part_amount: Option<u128>,
+        excluded_first_hops: &HashSet<OutPoint>,
     ) -> Result<Vec<PaymentHopData>, PathFindError> {
         let source = self.get_source_pubkey();
         let target = payment_data.target_pubkey;
-        let amount = payment_data.amount;
+        let amount = part_amount.unwrap_or(payment_data.amount);
+        // The final hop of each part carries the payment amount as the multi-part payment marker,
+        // and the max fee amount is shared by the parts in proportion to their amounts.
+        let total_amount = part_amount.map(|_| payment_data.amount);
+        let max_fee_amount = match part_amount {
+            Some(part_amount) => payment_data
+                .max_fee_amount
+                .map(|fee| fee.saturating_mul(part_amount) / payment_data.amount),
+            None => payment_data.max_fee_amount,
+        };
         let preimage = payment_data.preimage;
         let payment_hash = payment_data.payment_hash;
         let udt_type_script = payment_data.udt_type_script;
         let final_tlc_expiry_delta = payment_data.final_tlc_expiry_delta;
+        let custom_records = payment_data.custom_records;
         let invoice = payment_data
             .invoice
             .map(|x| x.parse::<CkbInvoice>().expect("parse CKB invoice"));
@@ -683,15 +719,16 @@ where
             ));
         }
 
-        let route = self.find_path(
+        let route = self.find_path_with_excluded_first_hops(
             source,
             target,
             amount,
-            payment_data.max_fee_amount,
+            max_fee_amount,
             udt_type_script,
             final_tlc_expiry_delta,
             payment_data.tlc_expiry_limit,
             allow_self_payment,
+            excluded_first_hops,
         )?;
         assert!(!route.is_empty());
 
@@ -741,6 +778,12 @@ where
                 expiry: current_expiry,
                 funding_tx_hash,
                 payment_preimage: if is_last { preimage } else { None },
+                custom_records: if is_last {
+                    custom_records.clone()
+                } else {
+                    vec![]
+                },
+                total_amount: if is_last { total_amount } else { None },
             });
             current_expiry += expiry_delta;
             current_amount += fee;
@@ -753,6 +796,8 @@ where
             expiry: current_expiry,
             funding_tx_hash: route[0].channel_outpoint.tx_hash().into(),
             payment_preimage: None,
+            custom_records: vec![],
+            total_amount: None,
         });
         hops_data.reverse();
         assert_eq!(hops_data.len(), route.len() + 1);
@@ -781,6 +826,34 @@ where
         final_tlc_expiry_delta: u64,
         tlc_expiry_limit: u64,
         allow_self: bool,
+    ) -> Result<Vec<PathEdge>, PathFindError> {
+        self.find_path_with_excluded_first_hops(
+            source,
+            target,
+            amount,
+            max_fee_amount,
+            udt_type_script,
+            final_tlc_expiry_delta,
+            tlc_expiry_limit,
+            allow_self,
+            &HashSet::new(),
+        )
+    }
+
+    // the same as `find_path`, but the channels in `excluded_first_hops` are not used
+    // as the first hop, so that the parts of a multi-part payment go through different channels
+    #[allow(clippy::too_many_arguments)]
+    pub fn find_path_with_excluded_first_hops(
+        &self,
+        source: Pubkey,
+        target: Pubkey,
+        amount: u128,
+        max_fee_amount: Option<u128>,
+        udt_type_script: Option<Script>,
+        final_tlc_expiry_delta: u64,
+        tlc_expiry_limit: u64,
+        allow_self: bool,
+        excluded_first_hops: &HashSet<OutPoint>,
     ) -> Result<Vec<PathEdge>, PathFindError> {
         let started_time = std::time::Instant::now();
         let nodes_len = self.nodes.len();
@@ -846,6 +919,9 @@ where
                 if &udt_type_script != channel_info.udt_type_script() {
                     continue;
                 }
+                if from == source && excluded_first_hops.contains(channel_info.out_point()) {
+                    continue;
+                }
 
                 // if the channel is already visited in the last hop, skip it
                 if last_hop_channels
@@ -1100,6 +1176,20 @@ impl SessionRoute {
         assert!(first_amount >= last_amount);
         first_amount - last_amount
     }
+
+    pub fn hops_public_keys(&self) -> Vec<Pubkey> {
+        // Skip the first node, which is the sender.
+        self.nodes.iter().skip(1).map(|x| x.pubkey).collect()
+    }
+}
+
+// A part of a multi-part payment, which is sent through its own first hop channel.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PaymentSessionPart {
+    pub first_hop_tlc_id: Option<u64>,
+    pub route: SessionRoute,
+    // Session key for onion packet of the part. Save it for decoding the error packet.
+    pub session_key: [u8; 32],
 }
 
 #[serde_as]
@@ -1119,6 +1209,8 @@ pub struct PaymentSession {
     pub route: SessionRoute,
     // Session key for onion packet. Save it for decoding the error packet.
     pub session_key: [u8; 32],
+    // The parts of a multi-part payment, empty if the payment is sent in a single part.
+    pub parts: Vec<PaymentSessionPart>,
 }
 
 impl PaymentSession {
@@ -1136,6 +1228,7 @@ impl PaymentSession {
             first_hop_tlc_id: None,
             route: SessionRoute::default(),
             session_key: Default::default(),
+            parts: vec![],
         }
     }
 
@@ -1169,12 +1262,19 @@ impl PaymentSession {
     }
 
     pub fn fee(&self) -> u128 {
-        self.route.fee()
+        self.routes().map(|route| route.fee()).sum()
     }
 
     pub fn hops_public_keys(&self) -> Vec<Pubkey> {
-        // Skip the first node, which is the sender.
-        self.route.nodes.iter().skip(1).map(|x| x.pubkey).collect()
+        self.route.hops_public_keys()
+    }
+
+    // The routes of all the parts of the payment.
+    pub fn routes(&self) -> impl Iterator<Item = &SessionRoute> {
+        let single_part = self.parts.is_empty().then_some(&self.route);
+        single_part
+            .into_iter()
+            .chain(self.parts.iter().map(|part| &part.route))
     }
 }
 
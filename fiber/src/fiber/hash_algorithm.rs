@@ -2,6 +2,7 @@ use bitcoin::hashes::{sha256::Hash as Sha256, Hash as _};
 use ckb_hash::blake2b_256;
 use ckb_types::packed;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use thiserror::Error;
 
 #[repr(u8)]
@@ -31,6 +32,10 @@ impl HashAlgorithm {
 #[error("Unknown Hash Algorithm: {0}")]
 pub struct UnknownHashAlgorithmError(pub u8);
 
+#[derive(Error, Debug)]
+#[error("Unknown Hash Algorithm: {0}")]
+pub struct UnknownHashAlgorithmNameError(pub String);
+
 impl TryFrom<u8> for HashAlgorithm {
     type Error = UnknownHashAlgorithmError;
 
@@ -43,6 +48,18 @@ impl TryFrom<u8> for HashAlgorithm {
     }
 }
 
+impl FromStr for HashAlgorithm {
+    type Err = UnknownHashAlgorithmNameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ckb_hash" => Ok(HashAlgorithm::CkbHash),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            _ => Err(UnknownHashAlgorithmNameError(s.to_string())),
+        }
+    }
+}
+
 impl TryFrom<packed::Byte> for HashAlgorithm {
     type Error = UnknownHashAlgorithmError;
 
@@ -77,4 +94,18 @@ mod tests {
             serde_json::from_str(&serialized).expect("hash algorithm from json");
         assert_eq!(deserialized, algorithm);
     }
+
+    #[test]
+    fn test_hash_algorithm_from_str() {
+        for algorithm in super::HashAlgorithm::supported_algorithms() {
+            let name = serde_json::to_value(algorithm).expect("hash algorithm to json");
+            let parsed: super::HashAlgorithm = name
+                .as_str()
+                .expect("hash algorithm name")
+                .parse()
+                .expect("parse hash algorithm");
+            assert_eq!(parsed, algorithm);
+        }
+        assert!("md5".parse::<super::HashAlgorithm>().is_err());
+    }
 }
@@ -225,6 +225,10 @@ impl InternalResult {
                 TlcErrorCode::ExpiryTooSoon | TlcErrorCode::ExpiryTooFar => {
                     need_to_retry = false;
                 }
+                TlcErrorCode::MppTimeout => {
+                    // the route is fine, the other parts of the payment are missing
+                    self.succeed_range_pairs(nodes, 0, len - 1);
+                }
                 _ => {
                     self.fail_node(nodes, len - 1);
                     if len > 1 {
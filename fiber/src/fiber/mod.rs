@@ -14,6 +14,7 @@ mod key;
 mod path;
 
 pub use key::KeyPair;
+pub mod backup;
 pub mod channel;
 pub mod gen;
 pub mod hash_algorithm;
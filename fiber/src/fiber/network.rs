@@ -18,7 +18,7 @@ use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 use std::{u128, u64};
 use tentacle::multiaddr::{MultiAddr, Protocol};
@@ -40,23 +40,30 @@ use tentacle::{
     traits::{ServiceHandle, ServiceProtocol},
     ProtocolId, SessionId,
 };
-use tokio::sync::{mpsc, oneshot, RwLock};
+use tokio::sync::{mpsc, oneshot, RwLock, Semaphore};
 use tokio_util::task::TaskTracker;
 use tracing::{debug, error, info, trace, warn};
 
+use super::backup::{derive_channel_backup_key, ChannelBackup, StaticChannelBackup};
 use super::channel::{
-    get_funding_and_reserved_amount, occupied_capacity, AcceptChannelParameter, ChannelActor,
-    ChannelActorMessage, ChannelActorStateStore, ChannelCommand, ChannelCommandWithId,
-    ChannelEvent, ChannelInitializationParameter, ChannelState, ChannelSubscribers,
+    anchor_capacity, check_commitment_delay_epoch, default_fee_estimator,
+    get_funding_and_reserved_amount, occupied_capacity, AcceptChannelParameter, AddTlcRateLimiter,
+    ChannelActor, ChannelActorConfig, ChannelActorMessage, ChannelActorStateStore, ChannelCommand,
+    ChannelCommandWithId, ChannelEvent, ChannelInitializationParameter, ChannelState,
+    ChannelSubscribers, CommitmentSignerProvider, FeeEstimator, FeePolicyBounds, ForwardPolicy,
     OpenChannelParameter, ProcessingChannelError, ProcessingChannelResult, PublicChannelInfo,
-    RevocationData, SettlementData, ShuttingDownFlags, DEFAULT_COMMITMENT_FEE_RATE,
-    DEFAULT_FEE_RATE, DEFAULT_MAX_TLC_VALUE_IN_FLIGHT, MAX_COMMITMENT_DELAY_EPOCHS,
-    MAX_TLC_NUMBER_IN_FLIGHT, MIN_COMMITMENT_DELAY_EPOCHS, SYS_MAX_TLC_NUMBER_IN_FLIGHT,
+    ReceivedMppPayments, ReestablishBackoff, RevocationData, SeenPaymentHashes, SettlementData,
+    ShutdownTimeout, ShuttingDownFlags, DEFAULT_CHANNEL_RESERVE_AMOUNT, DEFAULT_COMMITMENT_FEE_RATE,
+    DEFAULT_FEE_RATE, DEFAULT_MAX_TLC_VALUE_IN_FLIGHT, MAX_TLC_NUMBER_IN_FLIGHT,
+    SYS_MAX_TLC_NUMBER_IN_FLIGHT,
+};
+use super::config::{
+    AnnouncedNodeName, ChannelVisibilityPolicy, NoInvoicePolicy, MIN_TLC_EXPIRY_DELTA,
 };
-use super::config::{AnnouncedNodeName, MIN_TLC_EXPIRY_DELTA};
 use super::fee::calculate_commitment_tx_fee;
 use super::gossip::{GossipActorMessage, GossipMessageStore, GossipMessageUpdates};
-use super::graph::{NetworkGraph, NetworkGraphStateStore, SessionRoute};
+use super::graph::{NetworkGraph, NetworkGraphStateStore, PaymentSessionPart, SessionRoute};
+use super::hash_algorithm::HashAlgorithm;
 use super::key::blake2b_hash_with_salt;
 use super::types::{
     BroadcastMessage, BroadcastMessageQuery, EcdsaSignature, FiberMessage, GossipMessage, Hash256,
@@ -67,7 +74,9 @@ use super::{FiberConfig, ASSUME_NETWORK_ACTOR_ALIVE};
 
 use crate::ckb::config::UdtCfgInfos;
 use crate::ckb::contracts::{check_udt_script, get_udt_whitelist, is_udt_type_auto_accept};
-use crate::ckb::{CkbChainMessage, FundingRequest, FundingTx, TraceTxRequest, TraceTxResponse};
+use crate::ckb::{
+    CkbChainMessage, FeeBumpRequest, FundingRequest, FundingTx, TraceTxRequest, TraceTxResponse,
+};
 use crate::fiber::channel::{
     AddTlcCommand, AddTlcResponse, TxCollaborationCommand, TxUpdateCommand,
 };
@@ -76,7 +85,8 @@ use crate::fiber::gossip::{GossipProtocolHandle, SubscribableGossipMessageStore}
 use crate::fiber::graph::{PaymentSession, PaymentSessionStatus};
 use crate::fiber::serde_utils::EntityHex;
 use crate::fiber::types::{
-    FiberChannelMessage, PaymentOnionPacket, PeeledPaymentOnionPacket, TxSignatures,
+    FiberChannelMessage, PaymentOnionPacket, PeeledPaymentOnionPacket, ReestablishChannel,
+    TxSignatures,
 };
 use crate::fiber::KeyPair;
 use crate::invoice::{CkbInvoice, InvoiceStore};
@@ -86,8 +96,26 @@ pub const FIBER_PROTOCOL_ID: ProtocolId = ProtocolId::new(42);
 
 pub const GOSSIP_PROTOCOL_ID: ProtocolId = ProtocolId::new(43);
 
+// The version of the fiber protocol, which is bumped whenever the messages change incompatibly,
+// so that the peers running an incompatible version fail to open the protocol instead of failing
+// to decode the messages. Version 0.2.0 changes the messages of the initial version by:
+// - appending `push_amount`, `tlc_dust_limit`, `allowed_hash_algorithms` and `features` to
+//   OpenChannel;
+// - appending `commitment_delay_epoch`, `allowed_hash_algorithms` and `features` to AcceptChannel;
+// - appending `last_per_commitment_secret` to RevokeAndAck;
+// - appending `force_close_request` to ReestablishChannel;
+// - adding the UpdateFee message to FiberMessage.
+pub const FIBER_PROTOCOL_VERSION: &str = "0.2.0";
+
 pub const DEFAULT_CHAIN_ACTOR_TIMEOUT: u64 = 300000;
 
+// The maximal size the custom records of a payment add to the serialized final hop data,
+// so that the onion packet still has room for the hop data of a long route.
+pub const MAX_PAYMENT_CUSTOM_RECORDS_SIZE: usize = 1024;
+
+// The maximal number of parts a multi-part payment is split into.
+pub const MAX_PAYMENT_PARTS: u64 = 16;
+
 // tx index is not returned on older ckb version, using dummy tx index instead.
 // Waiting for https://github.com/nervosnetwork/ckb/pull/4583/ to be released.
 const DUMMY_FUNDING_TX_INDEX: u32 = 0;
@@ -212,6 +240,9 @@ pub enum NetworkActorCommand {
     ),
     // Send a command to a channel.
     ControlFiberChannel(ChannelCommandWithId),
+    // Restore a channel from the backup exported by `ChannelCommand::ExportBackup`,
+    // the peer of the channel is requested to force close it, now or once connected.
+    ImportChannelBackup(ChannelBackup, RpcReplyPort<Result<Hash256, String>>),
     // The first parameter is the peeled onion in binary via `PeeledOnionPacket::serialize`. `PeeledOnionPacket::current`
     // is for the current node.
     SendPaymentOnionPacket(SendOnionPacketCommand, RpcReplyPort<Result<u64, TlcErr>>),
@@ -221,6 +252,9 @@ pub enum NetworkActorCommand {
         RpcReplyPort<Result<PeeledPaymentOnionPacket, String>>,
     ),
     UpdateChannelFunding(Hash256, Transaction, FundingRequest),
+    // Build and broadcast a child transaction spending the anchor output of the force close
+    // transaction of the channel to bump its fee.
+    BumpFeeWithAnchor(Hash256, FeeBumpRequest),
     SignTx(PeerId, Hash256, Transaction, Option<Vec<Vec<u8>>>),
     // Process a broadcast message from the network.
     ProcessBroadcastMessage(BroadcastMessage),
@@ -261,6 +295,8 @@ pub async fn sign_network_message(
 pub struct OpenChannelCommand {
     pub peer_id: PeerId,
     pub funding_amount: u128,
+    // The amount to push to the remote party from our funding amount.
+    pub push_amount: Option<u128>,
     pub public: bool,
     pub shutdown_script: Option<Script>,
     pub funding_udt_type_script: Option<Script>,
@@ -272,6 +308,16 @@ pub struct OpenChannelCommand {
     pub tlc_fee_proportional_millionths: Option<u128>,
     pub max_tlc_value_in_flight: Option<u128>,
     pub max_tlc_number_in_flight: Option<u64>,
+    // The minimal balance we must keep in the channel after offering tlcs.
+    pub channel_reserve_amount: Option<u128>,
+    // Whether to add anchor outputs to the commitment transactions, so that the fee of
+    // the force close can be bumped by a child transaction.
+    pub anchor_outputs: bool,
+    // Whether to sort the outputs of the settlement transactions, so that they don't reveal
+    // which party they belong to.
+    pub sorted_settlement_outputs: bool,
+    // The tlcs with amount below the dust limit are trimmed from the commitment transactions.
+    pub tlc_dust_limit: Option<u128>,
 }
 
 #[serde_as]
@@ -304,6 +350,9 @@ pub struct SendPaymentCommand {
     pub allow_self_payment: bool,
     // dry_run only used for checking, default is false
     pub dry_run: bool,
+    // the custom records (type, value) attached to the final hop's onion payload
+    #[serde(default)]
+    pub custom_records: Vec<(u64, Vec<u8>)>,
 }
 
 #[serde_as]
@@ -324,6 +373,8 @@ pub struct SendPaymentData {
     pub preimage: Option<Hash256>,
     pub allow_self_payment: bool,
     pub dry_run: bool,
+    #[serde(default)]
+    pub custom_records: Vec<(u64, Vec<u8>)>,
 }
 
 impl SendPaymentData {
@@ -442,6 +493,38 @@ impl SendPaymentData {
             (payment_hash, Some(preimage))
         };
 
+        let mut record_types = HashSet::new();
+        if !command
+            .custom_records
+            .iter()
+            .all(|(record_type, _)| record_types.insert(*record_type))
+        {
+            return Err("custom_records should not have duplicated types".to_string());
+        }
+        let custom_records_size =
+            PaymentHopData::serialized_custom_records_size(&command.custom_records);
+        if custom_records_size > MAX_PAYMENT_CUSTOM_RECORDS_SIZE {
+            return Err(format!(
+                "custom_records are too large, expect the serialized size ({}) to be at most {}",
+                custom_records_size, MAX_PAYMENT_CUSTOM_RECORDS_SIZE
+            ));
+        }
+
+        if let Some(max_parts) = command.max_parts {
+            if max_parts == 0 || max_parts > MAX_PAYMENT_PARTS {
+                return Err(format!(
+                    "invalid max_parts, expect between 1 and {}",
+                    MAX_PAYMENT_PARTS
+                ));
+            }
+            if amount < max_parts as u128 {
+                return Err(format!(
+                    "The payment amount ({}) is too small to be split into {} parts",
+                    amount, max_parts
+                ));
+            }
+        }
+
         if udt_type_script.is_none() && amount >= u64::MAX as u128 {
             return Err(format!(
                 "The payment amount ({}) should be less than {}",
@@ -465,8 +548,14 @@ impl SendPaymentData {
             preimage,
             allow_self_payment: command.allow_self_payment,
             dry_run: command.dry_run,
+            custom_records: command.custom_records,
         })
     }
+
+    // Whether the payment is split into multiple parts sent through different first hop channels.
+    pub fn is_multi_part(&self) -> bool {
+        self.max_parts.is_some_and(|max_parts| max_parts > 1)
+    }
 }
 
 #[derive(Debug)]
@@ -479,6 +568,10 @@ pub struct AcceptChannelCommand {
     pub min_tlc_value: Option<u128>,
     pub tlc_fee_proportional_millionths: Option<u128>,
     pub tlc_expiry_delta: Option<u64>,
+    // Counter-propose a commitment delay epoch instead of the one in the OpenChannel message.
+    pub commitment_delay_epoch: Option<EpochNumberWithFraction>,
+    // The minimal balance we must keep in the channel after offering tlcs.
+    pub channel_reserve_amount: Option<u128>,
 }
 
 #[derive(Debug)]
@@ -527,6 +620,9 @@ pub enum NetworkServiceEvent {
     // and both parties sent ChannelReady messages).
     ChannelReady(PeerId, Hash256, OutPoint),
     ChannelClosed(PeerId, Hash256, Byte32),
+    // The channel funding is aborted by either party (or timed out) before the funding
+    // transaction is broadcast, the string is the reason of abortion.
+    ChannelFundingAborted(PeerId, Hash256, String),
     // A RevokeAndAck is received from the peer. Other data relevant to this
     // RevokeAndAck message are also assembled here. The watch tower may use this.
     RevokeAndAckReceived(
@@ -539,6 +635,46 @@ pub enum NetworkServiceEvent {
     // and we successfully assemble the partial signature from other party
     // to create a complete commitment transaction and a settlement transaction.
     RemoteCommitmentSigned(PeerId, Hash256, TransactionView, SettlementData),
+    // The channel starts or stops waiting for the RevokeAndAck of the last tlc update,
+    // tlc commands will fail with WaitingTlcAck while waiting.
+    ChannelAckState(Hash256, bool /* waiting */),
+    // A draining channel has no tlcs in flight, it's safe to restart the node now.
+    ChannelDrained(Hash256),
+    // The channel is force closed by us as the signing of the commitments with the peer
+    // failed repeatedly, the string is the reason.
+    ChannelForceClosedOnSigningFailures(Hash256, String),
+    // A received tlc without an invoice is held until it's approved by `ApproveTlc`,
+    // with the channel id, the payment hash and the tlc id.
+    TlcPendingApproval(Hash256, Hash256, u64),
+    // Reestablishing the channel with the peer timed out for the given number of consecutive
+    // attempts, the peer may be unstable.
+    ChannelReestablishTimedOut(Hash256, PeerId, u64),
+    // The cooperative close started by us is not completed by the peer within the timeout,
+    // the bool indicates whether the channel is force closed.
+    ChannelShutdownTimedOut(Hash256, PeerId, bool),
+    // The total of the balances of the channel is changed by settling tlcs, which may leak or
+    // create funds. It's critical and the channel refuses to sign new commitments.
+    ChannelBalanceInvariantViolated(Hash256, String),
+    // A probe tlc is received as the final hop and failed immediately, with the channel id,
+    // the payment hash and the amount.
+    ProbeTlcReceived(Hash256, Hash256, u128),
+    // A payment forwarded by us is fulfilled, the fee is the difference between the amounts
+    // of the incoming tlc and the outgoing tlc. The next hop is peeled from the onion packet.
+    ForwardSettled {
+        incoming_channel: Hash256,
+        outgoing_channel: Hash256,
+        payment_hash: Hash256,
+        fee: u128,
+        next_hop: Option<Pubkey>,
+    },
+    // A payment forwarded by us is failed by the downstream hops, the next hop is the neighbor
+    // which the payment is forwarded to.
+    ForwardFailed {
+        incoming_channel: Hash256,
+        outgoing_channel: Hash256,
+        payment_hash: Hash256,
+        next_hop: Option<Pubkey>,
+    },
     // Some other debug event for assertion.
     #[cfg(debug_assertions)]
     DebugEvent(DebugEvent),
@@ -593,6 +729,9 @@ pub enum NetworkActorEvent {
     /// A funding transaction has failed.
     FundingTransactionFailed(OutPoint),
 
+    /// The channel funding is aborted before the funding transaction is broadcast.
+    FundingAborted(Hash256, PeerId, String),
+
     /// Channel is going to be closed forcely, and the closing transaction is ready to be broadcasted.
     CommitmentTransactionPending(Transaction, Hash256),
 
@@ -710,6 +849,14 @@ where
                                 temp_channel_id,
                                 funding_amount: if open_channel.funding_udt_type_script.is_some() {
                                     0
+                                } else if open_channel.has_anchor_outputs() {
+                                    // Our anchor output is paid by our reserved amount.
+                                    let anchor_capacity =
+                                        anchor_capacity(&state.default_shutdown_script)
+                                            .expect("valid anchor capacity")
+                                            .as_u64();
+                                    (state.auto_accept_channel_ckb_funding_amount + anchor_capacity)
+                                        as u128
                                 } else {
                                     state.auto_accept_channel_ckb_funding_amount as u128
                                 },
@@ -719,6 +866,8 @@ where
                                 min_tlc_value: None,
                                 tlc_fee_proportional_millionths: None,
                                 tlc_expiry_delta: None,
+                                commitment_delay_epoch: None,
+                                channel_reserve_amount: None,
                             };
                             state.create_inbound_channel(accept_channel).await?;
                         }
@@ -730,6 +879,19 @@ where
             }
             FiberMessage::ChannelNormalOperation(m) => {
                 let channel_id = m.get_channel_id();
+                if let FiberChannelMessage::ReestablishChannel(reestablish_channel) = &m {
+                    let is_channel_peer = self
+                        .store
+                        .get_channel_actor_state(&channel_id)
+                        .is_some_and(|channel| channel.get_remote_peer_id() == peer_id);
+                    if reestablish_channel.force_close_request && !is_channel_peer {
+                        warn!(
+                            "Ignoring force close request for channel {:?} from {:?}, which is not the peer of the channel",
+                            &channel_id, &peer_id
+                        );
+                        return Ok(());
+                    }
+                }
                 state
                     .send_message_to_channel_actor(
                         channel_id,
@@ -870,6 +1032,9 @@ where
             NetworkActorEvent::FundingTransactionFailed(outpoint) => {
                 error!("Funding transaction failed: {:?}", outpoint);
             }
+            NetworkActorEvent::FundingAborted(channel_id, peer_id, reason) => {
+                state.on_funding_aborted(channel_id, peer_id, reason);
+            }
             NetworkActorEvent::ClosingTransactionPending(channel_id, peer_id, tx) => {
                 state
                     .on_closing_transaction_pending(channel_id, peer_id.clone(), tx.clone())
@@ -1093,15 +1258,30 @@ where
                     .await;
             }
             NetworkActorCommand::PeelPaymentOnionPacket(onion_packet, payment_hash, reply) => {
-                let response = onion_packet
-                    .peel(
-                        &state.private_key,
-                        Some(payment_hash.as_ref()),
-                        &Secp256k1::new(),
-                    )
-                    .map_err(|err| err.to_string());
+                // The DH operations are independent of each other, peel the packets in blocking
+                // tasks so the onion packets of multiple tlcs can be peeled in parallel without
+                // blocking the actor loop.
+                let private_key = state.private_key.clone();
+                let semaphore = state.onion_peeling_semaphore.clone();
+                ractor::concurrency::tokio_primatives::spawn(async move {
+                    let _permit = semaphore
+                        .acquire_owned()
+                        .await
+                        .expect("onion peeling semaphore is never closed");
+                    let response = tokio::task::spawn_blocking(move || {
+                        onion_packet
+                            .peel(
+                                &private_key,
+                                Some(payment_hash.as_ref()),
+                                &Secp256k1::new(),
+                            )
+                            .map_err(|err| err.to_string())
+                    })
+                    .await
+                    .unwrap_or_else(|err| Err(format!("Failed to peel onion packet: {}", err)));
 
-                let _ = reply.send(response);
+                    let _ = reply.send(response);
+                });
             }
 
             NetworkActorCommand::UpdateChannelFunding(channel_id, transaction, request) => {
@@ -1144,6 +1324,53 @@ where
                     )
                     .await?
             }
+            NetworkActorCommand::BumpFeeWithAnchor(channel_id, request) => {
+                let tx = match call_t!(
+                    self.chain_actor.clone(),
+                    CkbChainMessage::BuildFeeBumpTx,
+                    DEFAULT_CHAIN_ACTOR_TIMEOUT,
+                    request
+                ) {
+                    Ok(Ok(tx)) => tx,
+                    Ok(Err(err)) => {
+                        error!("Failed to build fee bump tx for channel {:?}: {}", channel_id, err);
+                        return Ok(());
+                    }
+                    Err(err) => {
+                        error!("Failed to call chain actor: {}", err);
+                        return Ok(());
+                    }
+                };
+                debug!("Fee bump transaction built for channel {:?}: {:?}", channel_id, tx);
+                state
+                    .send_message_to_channel_actor(
+                        channel_id,
+                        None,
+                        ChannelActorMessage::Event(ChannelEvent::ForceCloseFeeBumped(tx.data())),
+                    )
+                    .await;
+                let tx_hash = tx.hash();
+                state
+                    .broadcast_tx_with_callback(tx, move |result| match result {
+                        Ok(TraceTxResponse {
+                            status:
+                                TxStatus {
+                                    status: Status::Committed,
+                                    ..
+                                },
+                            ..
+                        }) => {
+                            info!("Fee bump transaction {:?} confirmed", tx_hash);
+                        }
+                        result => {
+                            error!(
+                                "Fee bump transaction {:?} failed to be confirmed: {:?}",
+                                tx_hash, result
+                            );
+                        }
+                    })
+                    .await;
+            }
             NetworkActorCommand::SignTx(
                 ref peer_id,
                 ref channel_id,
@@ -1278,6 +1505,13 @@ where
                     }
                 }
             }
+            NetworkActorCommand::ImportChannelBackup(backup, reply) => {
+                let result = self
+                    .import_channel_backup(state, backup)
+                    .await
+                    .map_err(|err| err.to_string());
+                let _ = reply.send(result);
+            }
             NetworkActorCommand::GetPayment(payment_hash, reply) => {
                 match self.on_get_payment(&payment_hash) {
                     Ok(payment) => {
@@ -1373,6 +1607,8 @@ where
                 onion_packet: peeled_onion_packet.next.clone(),
                 shared_secret: shared_secret.clone(),
                 previous_tlc,
+                payment_preimage: None,
+                idempotency_key: None,
             },
             rpc_reply,
         );
@@ -1439,6 +1675,31 @@ where
                             .await
                             .record_payment_success(payment_session);
                     }
+                    RemoveTlcReason::RemoveTlcFail(reason) if !payment_session.parts.is_empty() => {
+                        // The parts of a multi-part payment are not retried, the error is decoded
+                        // with the session key of the part it comes from.
+                        let (error_detail, route) = payment_session
+                            .parts
+                            .iter()
+                            .find_map(|part| {
+                                reason
+                                    .decode(&part.session_key, part.route.hops_public_keys())
+                                    .map(|error| (error, Some(part.route.clone())))
+                            })
+                            .unwrap_or((TlcErr::new(TlcErrorCode::InvalidOnionError), None));
+                        self.update_graph_with_tlc_fail(&state.network, &error_detail)
+                            .await;
+                        if let Some(route) = route {
+                            self.network_graph
+                                .write()
+                                .await
+                                .record_route_fail(&route, error_detail.clone());
+                        }
+                        self.set_payment_fail_with_error(
+                            &mut payment_session,
+                            error_detail.error_code.as_ref(),
+                        );
+                    }
                     RemoveTlcReason::RemoveTlcFail(reason) => {
                         let error_detail = reason
                             .decode(
@@ -1513,6 +1774,34 @@ where
         }
     }
 
+    async fn import_channel_backup(
+        &self,
+        state: &mut NetworkActorState<S>,
+        backup: ChannelBackup,
+    ) -> crate::Result<Hash256> {
+        let backup = backup.decrypt(&derive_channel_backup_key(&state.private_key))?;
+        let channel_id = backup.channel_id;
+        if self.store.get_channel_actor_state(&channel_id).is_some() {
+            return Err(Error::InvalidParameter(format!(
+                "Channel {:?} already exists, no need to restore it from backup",
+                channel_id
+            )));
+        }
+        // The backup is kept so that the force close request is resent once the peer reconnects.
+        self.store.insert_restored_channel_backup(backup.clone());
+        let peer_id = backup.remote_pubkey.tentacle_peer_id();
+        if state.is_connected(&peer_id) {
+            state.request_force_close_from_backup(&backup).await?;
+        } else {
+            info!(
+                "Peer {:?} of channel {:?} restored from backup is not connected, \
+                 requesting to force close once connected",
+                &peer_id, &channel_id
+            );
+        }
+        Ok(channel_id)
+    }
+
     fn on_get_payment(&self, payment_hash: &Hash256) -> Result<SendPaymentResponse, Error> {
         match self.store.get_payment_session(*payment_hash) {
             Some(payment_session) => Ok(payment_session.into()),
@@ -1546,6 +1835,69 @@ where
         };
     }
 
+    // Builds the routes of the parts of a multi-part payment. The payment amount is split evenly,
+    // the last part also carries the remainder, and each part goes through a different first hop
+    // channel so that the parts don't compete for the same local balance.
+    async fn build_multi_part_payment_routes(
+        &self,
+        payment_session: &mut PaymentSession,
+        payment_data: &SendPaymentData,
+    ) -> Result<Vec<Vec<PaymentHopData>>, Error> {
+        let max_parts = payment_data.max_parts.unwrap_or(1) as u128;
+        let part_amount = payment_data.amount / max_parts;
+        let mut excluded_first_hops = HashSet::new();
+        let mut routes = vec![];
+        let graph = self.network_graph.read().await;
+        for i in 0..max_parts {
+            let amount = if i == max_parts - 1 {
+                payment_data.amount - part_amount * (max_parts - 1)
+            } else {
+                part_amount
+            };
+            match graph.build_part_route(payment_data.clone(), amount, &excluded_first_hops) {
+                Err(e) => {
+                    let error = format!("Failed to build route of part {}, {}", i, e);
+                    self.set_payment_fail_with_error(payment_session, &error);
+                    return Err(Error::SendPaymentError(error));
+                }
+                Ok(hops) => {
+                    assert_ne!(hops[0].funding_tx_hash, Hash256::default());
+                    excluded_first_hops.insert(OutPoint::new(hops[0].funding_tx_hash.into(), 0));
+                    routes.push(hops);
+                }
+            }
+        }
+        Ok(routes)
+    }
+
+    // Sends all the parts of a multi-part payment. The parts are not retried, the payment fails
+    // if any of them fails, and the receiver fails the parts already sent once they time out.
+    async fn send_multi_part_payment(
+        &self,
+        state: &mut NetworkActorState<S>,
+        payment_session: &mut PaymentSession,
+        payment_data: &SendPaymentData,
+    ) -> Result<PaymentSession, Error> {
+        let routes = self
+            .build_multi_part_payment_routes(payment_session, payment_data)
+            .await?;
+        for hops in routes {
+            self.send_payment_onion_packet(state, payment_session, payment_data, hops)
+                .await
+                .map_err(|e| match e {
+                    Error::SendPaymentFirstHopError(err, _) => Error::SendPaymentError(err),
+                    e => e,
+                })?;
+            payment_session.parts.push(PaymentSessionPart {
+                first_hop_tlc_id: payment_session.first_hop_tlc_id,
+                route: payment_session.route.clone(),
+                session_key: payment_session.session_key,
+            });
+        }
+        self.store.insert_payment_session(payment_session.clone());
+        Ok(payment_session.clone())
+    }
+
     async fn send_payment_onion_packet(
         &self,
         state: &mut NetworkActorState<S>,
@@ -1553,6 +1905,18 @@ where
         payment_data: &SendPaymentData,
         hops: Vec<PaymentHopData>,
     ) -> Result<PaymentSession, Error> {
+        // The first hop data is for ourself.
+        if hops.len() - 1 > state.max_payment_hops {
+            let err = format!(
+                "Route of payment {:?} has {} hops, exceeds the max payment hops {}",
+                payment_data.payment_hash,
+                hops.len() - 1,
+                state.max_payment_hops
+            );
+            self.set_payment_fail_with_error(payment_session, &err);
+            return Err(Error::SendPaymentError(err));
+        }
+
         let session_key = Privkey::from_slice(KeyPair::generate_random_key().as_ref());
         assert_ne!(hops[0].funding_tx_hash, Hash256::default());
         let first_channel_outpoint = OutPoint::new(hops[0].funding_tx_hash.into(), 0);
@@ -1632,6 +1996,11 @@ where
         let payment_data = payment_session.request.clone();
         if payment_session.can_retry() {
             payment_session.retried_times += 1;
+            if payment_data.is_multi_part() {
+                return self
+                    .send_multi_part_payment(state, &mut payment_session, &payment_data)
+                    .await;
+            }
             let hops_info = self
                 .build_payment_route(&mut payment_session, &payment_data)
                 .await?;
@@ -1695,6 +2064,24 @@ where
         // will not store the payment session and send the onion packet
         if payment_data.dry_run {
             let mut payment_session = PaymentSession::new(payment_data.clone(), 0);
+            if payment_data.is_multi_part() {
+                let routes = self
+                    .build_multi_part_payment_routes(&mut payment_session, &payment_data)
+                    .await?;
+                payment_session.parts = routes
+                    .iter()
+                    .map(|hops| PaymentSessionPart {
+                        first_hop_tlc_id: None,
+                        route: SessionRoute::new(
+                            state.get_public_key(),
+                            payment_data.target_pubkey,
+                            hops,
+                        ),
+                        session_key: Default::default(),
+                    })
+                    .collect();
+                return Ok(payment_session.into());
+            }
             let hops = self
                 .build_payment_route(&mut payment_session, &payment_data)
                 .await?;
@@ -1748,6 +2135,9 @@ pub struct NetworkActorState<S> {
     peer_session_map: HashMap<PeerId, (SessionId, SessionType)>,
     session_channels_map: HashMap<SessionId, HashSet<Hash256>>,
     channels: HashMap<Hash256, ActorRef<ChannelActorMessage>>,
+    // The channels of disconnected peers which are kept alive within the peer disconnect grace
+    // period, they are resumed if the peer reconnects in time.
+    disconnected_channels: HashMap<Hash256, ActorRef<ChannelActorMessage>>,
     // Outpoint to channel id mapping, only contains channels with state of Ready.
     // We need to remove the channel from this map when the channel is closed or peer disconnected.
     outpoint_channel_map: HashMap<OutPoint, Hash256>,
@@ -1762,6 +2152,9 @@ pub struct NetworkActorState<S> {
     open_channel_auto_accept_min_ckb_funding_amount: u64,
     // Tha default amount of CKB to be funded when auto accepting a channel.
     auto_accept_channel_ckb_funding_amount: u64,
+    // The minimal liquid capacity of native ckb and udt channels to accept.
+    min_accept_ckb_funding_amount: u64,
+    min_accept_udt_funding_amount: u128,
     // The default expiry delta to forward tlcs.
     tlc_expiry_delta: u64,
     // The default tlc min and max value of tlcs to be accepted.
@@ -1774,6 +2167,58 @@ pub struct NetworkActorState<S> {
     channel_subscribers: ChannelSubscribers,
     max_inbound_peers: usize,
     min_outbound_peers: usize,
+    // Whether to accept keysend payments at the final hop.
+    accept_keysend: bool,
+    // The payment hashes settled by forwarded tlcs, `None` if re-forwarding them is allowed.
+    seen_payment_hashes: Option<Arc<Mutex<SeenPaymentHashes>>>,
+    // The multi-part payments received from all the channels, shared by the channel actors.
+    received_mpp_payments: Arc<Mutex<ReceivedMppPayments>>,
+    // The timeout to abort channels whose funding is not completed, `None` if never abort.
+    funding_timeout: Option<Duration>,
+    // The grace period to keep the channels of disconnected peers alive, `None` if stop them immediately.
+    peer_disconnect_grace: Option<Duration>,
+    // The code hashes of the lock scripts allowed to close channels to, `None` if any lock script is allowed.
+    allowed_close_script_code_hashes: Option<Arc<HashSet<Hash256>>>,
+    // The maximal number of hops in the routes of payments sent by us.
+    max_payment_hops: usize,
+    // Limits the number of onion packets peeled concurrently off the actor loop.
+    onion_peeling_semaphore: Arc<Semaphore>,
+    // The maximal attempts to retry removing a tlc before abandoning it, `None` if retry forever.
+    max_retryable_remove_attempts: Option<u64>,
+    // The hash algorithms allowed to be used by the tlcs of our channels.
+    allowed_hash_algorithms: Vec<HashAlgorithm>,
+    // The udt type scripts allowed to fund the channels opened by peers, `None` if all udts are allowed.
+    allowed_udt_scripts: Option<Arc<Vec<Script>>>,
+    // The rate limiter of the AddTlc messages from each peer, `None` if not limited.
+    add_tlc_rate_limiter: Option<Arc<Mutex<AddTlcRateLimiter>>>,
+    // The maximal number of commitment points from the peer retained in the channel states,
+    // `None` if bounded by the max tlc number in flight of each channel.
+    max_remote_commitment_points: Option<usize>,
+    // The channels opened by peers are accepted only if their visibility matches this policy.
+    accept_channel_visibility: ChannelVisibilityPolicy,
+    // The minimal reserved ckb amount of the opener to accept a channel.
+    accept_min_remote_reserve: u64,
+    // How the final hop handles the received tlcs without invoices.
+    no_invoice_policy: NoInvoicePolicy,
+    // The backoff of reestablishing the channels whose previous attempts are not completed.
+    reestablish_backoff: ReestablishBackoff,
+    // The timeout of the cooperative close started by us, `None` to wait forever.
+    shutdown_timeout: Option<ShutdownTimeout>,
+    // The timeout of the peer replying the resynced commitments, `None` to wait forever.
+    commitment_resync_timeout: Option<Duration>,
+    // The maximum value in flight of the channels as the percentage of the balances.
+    max_tlc_value_in_flight_percentage: Option<u8>,
+    // The bounds of the forwarding fee policies updated by the Update commands.
+    fee_policy_bounds: FeePolicyBounds,
+    // The maximal tlc dust limit accepted from the peers opening channels to us.
+    max_accepted_tlc_dust_limit: u128,
+    // The hook to veto forwarding the received tlcs, shared by all the channels.
+    forward_policy: Option<Arc<dyn ForwardPolicy>>,
+    // The provider of the signers keeping the funding keys of the channels, `None` to keep them
+    // in memory with the channel states.
+    commitment_signer_provider: Option<Arc<dyn CommitmentSignerProvider>>,
+    // The estimator of the shutdown fee rates from the target blocks, shared by all the channels.
+    fee_estimator: Arc<dyn FeeEstimator>,
 }
 
 #[serde_as]
@@ -1913,6 +2358,29 @@ where
         self.private_key.pubkey()
     }
 
+    fn channel_actor_config(&self) -> ChannelActorConfig {
+        ChannelActorConfig {
+            accept_keysend: self.accept_keysend,
+            seen_payment_hashes: self.seen_payment_hashes.clone(),
+            received_mpp_payments: self.received_mpp_payments.clone(),
+            funding_timeout: self.funding_timeout,
+            peer_disconnect_grace: self.peer_disconnect_grace,
+            allowed_close_script_code_hashes: self.allowed_close_script_code_hashes.clone(),
+            channel_backup_key: derive_channel_backup_key(&self.private_key),
+            max_retryable_remove_attempts: self.max_retryable_remove_attempts,
+            add_tlc_rate_limiter: self.add_tlc_rate_limiter.clone(),
+            max_remote_commitment_points: self.max_remote_commitment_points,
+            no_invoice_policy: self.no_invoice_policy,
+            reestablish_backoff: self.reestablish_backoff,
+            shutdown_timeout: self.shutdown_timeout,
+            commitment_resync_timeout: self.commitment_resync_timeout,
+            fee_policy_bounds: self.fee_policy_bounds,
+            forward_policy: self.forward_policy.clone(),
+            commitment_signer_provider: self.commitment_signer_provider.clone(),
+            fee_estimator: self.fee_estimator.clone(),
+        }
+    }
+
     pub fn generate_channel_seed(&mut self) -> [u8; 32] {
         let channel_user_id = self.channels.len();
         let seed = channel_user_id
@@ -1934,6 +2402,7 @@ where
         let OpenChannelCommand {
             peer_id,
             funding_amount,
+            push_amount,
             public,
             shutdown_script,
             funding_udt_type_script,
@@ -1945,6 +2414,10 @@ where
             tlc_fee_proportional_millionths,
             max_tlc_value_in_flight,
             max_tlc_number_in_flight,
+            channel_reserve_amount,
+            anchor_outputs,
+            sorted_settlement_outputs,
+            tlc_dust_limit,
         } = open_channel;
         let remote_pubkey =
             self.get_peer_pubkey(&peer_id)
@@ -1980,9 +2453,11 @@ where
                 network.clone(),
                 store,
                 self.channel_subscribers.clone(),
+                self.channel_actor_config(),
             ),
             ChannelInitializationParameter::OpenChannel(OpenChannelParameter {
                 funding_amount,
+                push_amount: push_amount.unwrap_or_default(),
                 seed,
                 public_channel_info: public.then_some(PublicChannelInfo::new(
                     tlc_min_value.unwrap_or(self.tlc_min_value),
@@ -1999,6 +2474,13 @@ where
                     .unwrap_or(DEFAULT_MAX_TLC_VALUE_IN_FLIGHT),
                 max_tlc_number_in_flight: max_tlc_number_in_flight
                     .unwrap_or(MAX_TLC_NUMBER_IN_FLIGHT),
+                max_tlc_value_in_flight_percentage: self.max_tlc_value_in_flight_percentage,
+                channel_reserve_amount: channel_reserve_amount
+                    .unwrap_or(DEFAULT_CHANNEL_RESERVE_AMOUNT),
+                anchor_outputs,
+                sorted_settlement_outputs,
+                tlc_dust_limit: tlc_dust_limit.unwrap_or_default(),
+                allowed_hash_algorithms: self.allowed_hash_algorithms.clone(),
             }),
             network.clone().get_cell(),
         )
@@ -2023,6 +2505,8 @@ where
             min_tlc_value,
             tlc_fee_proportional_millionths,
             tlc_expiry_delta,
+            commitment_delay_epoch,
+            channel_reserve_amount,
         } = accept_channel;
 
         let (peer_id, open_channel) = self
@@ -2046,6 +2530,7 @@ where
             funding_amount,
             &shutdown_script,
             &open_channel.funding_udt_type_script,
+            open_channel.has_anchor_outputs(),
         )?;
 
         let network = self.network.clone();
@@ -2055,6 +2540,12 @@ where
             return Ok((channel.clone(), temp_channel_id, id));
         }
 
+        let min_accept_funding_amount = if open_channel.funding_udt_type_script.is_some() {
+            self.min_accept_udt_funding_amount
+        } else {
+            self.min_accept_ckb_funding_amount as u128
+        };
+
         let seed = self.generate_channel_seed();
         let (tx, rx) = oneshot::channel::<Hash256>();
         let channel = Actor::spawn_linked(
@@ -2065,6 +2556,7 @@ where
                 network.clone(),
                 store,
                 self.channel_subscribers.clone(),
+                self.channel_actor_config(),
             ),
             ChannelInitializationParameter::AcceptChannel(AcceptChannelParameter {
                 funding_amount,
@@ -2081,6 +2573,15 @@ where
                 max_tlc_number_in_flight: max_tlc_number_in_flight
                     .unwrap_or(MAX_TLC_NUMBER_IN_FLIGHT),
                 max_tlc_value_in_flight: max_tlc_value_in_flight.unwrap_or(u128::MAX),
+                max_tlc_value_in_flight_percentage: self.max_tlc_value_in_flight_percentage,
+                commitment_delay_epoch,
+                min_accept_funding_amount,
+                channel_reserve_amount: channel_reserve_amount
+                    .unwrap_or(DEFAULT_CHANNEL_RESERVE_AMOUNT),
+                allowed_hash_algorithms: self.allowed_hash_algorithms.clone(),
+                allowed_udt_scripts: self.allowed_udt_scripts.clone(),
+                accept_channel_visibility: self.accept_channel_visibility,
+                accept_min_remote_reserve: self.accept_min_remote_reserve,
             }),
             network.clone().get_cell(),
         )
@@ -2200,9 +2701,12 @@ where
     ) -> Result<(), ProcessingChannelError> {
         let udt_type_script = &open_channel.funding_udt_type_script;
 
-        // reserved_ckb_amount
-        let occupied_capacity =
+        // reserved_ckb_amount, the anchor output is also paid by the reserved amount
+        let mut occupied_capacity =
             occupied_capacity(&open_channel.shutdown_script, udt_type_script)?.as_u64();
+        if open_channel.has_anchor_outputs() {
+            occupied_capacity += anchor_capacity(&open_channel.shutdown_script)?.as_u64();
+        }
         if open_channel.reserved_ckb_amount < occupied_capacity {
             return Err(ProcessingChannelError::InvalidParameter(format!(
                 "Reserved CKB amount {} is less than {}",
@@ -2225,8 +2729,11 @@ where
                 DEFAULT_COMMITMENT_FEE_RATE,
             )));
         }
-        let commitment_fee =
-            calculate_commitment_tx_fee(open_channel.commitment_fee_rate, udt_type_script);
+        let commitment_fee = calculate_commitment_tx_fee(
+            open_channel.commitment_fee_rate,
+            udt_type_script,
+            open_channel.has_anchor_outputs(),
+        );
         let reserved_fee = open_channel.reserved_ckb_amount - occupied_capacity;
         if commitment_fee * 2 > reserved_fee {
             return Err(ProcessingChannelError::InvalidParameter(format!(
@@ -2236,30 +2743,7 @@ where
         }
 
         // commitment_delay_epoch
-        let epoch =
-            EpochNumberWithFraction::from_full_value_unchecked(open_channel.commitment_delay_epoch);
-        if !epoch.is_well_formed() {
-            return Err(ProcessingChannelError::InvalidParameter(format!(
-                "Commitment delay epoch {} is not a valid value",
-                open_channel.commitment_delay_epoch,
-            )));
-        }
-
-        let min = EpochNumberWithFraction::new(MIN_COMMITMENT_DELAY_EPOCHS, 0, 1);
-        if epoch < min {
-            return Err(ProcessingChannelError::InvalidParameter(format!(
-                "Commitment delay epoch {} is less than the minimal value {}",
-                epoch, min
-            )));
-        }
-
-        let max = EpochNumberWithFraction::new(MAX_COMMITMENT_DELAY_EPOCHS, 0, 1);
-        if epoch > max {
-            return Err(ProcessingChannelError::InvalidParameter(format!(
-                "Commitment delay epoch {} is greater than the maximal value {}",
-                epoch, max
-            )));
-        }
+        check_commitment_delay_epoch(open_channel.commitment_delay_epoch)?;
 
         // max_tlc_number_in_flight
         if open_channel.max_tlc_number_in_flight > SYS_MAX_TLC_NUMBER_IN_FLIGHT {
@@ -2269,6 +2753,15 @@ where
             )));
         }
 
+        // tlc_dust_limit, the trimmed tlcs are paid to the miners when the commitment transaction
+        // is committed, so the opener can't burn our funds with an arbitrary large limit
+        if open_channel.tlc_dust_limit > self.max_accepted_tlc_dust_limit {
+            return Err(ProcessingChannelError::InvalidParameter(format!(
+                "TLC dust limit {} is greater than the maximal accepted value {}",
+                open_channel.tlc_dust_limit, self.max_accepted_tlc_dust_limit
+            )));
+        }
+
         Ok(())
     }
 
@@ -2318,15 +2811,13 @@ where
                                     debug!("Handling force shutdown command in ShuttingDown state, flags: {:?}", &flags);
                                 }
                                 _ => {
-                                    let error = Error::ChannelError(
-                                        ProcessingChannelError::InvalidState(format!(
-                                            "Handling force shutdown command invalid state {:?}",
-                                            &state.state
-                                        )),
-                                    );
-
-                                    let _ = rpc_reply.send(Err(error.to_string()));
-                                    return Err(error);
+                                    let error = ProcessingChannelError::InvalidState(format!(
+                                        "Handling force shutdown command invalid state {:?}",
+                                        &state.state
+                                    ));
+
+                                    let _ = rpc_reply.send(Err((&error).into()));
+                                    return Err(Error::ChannelError(error));
                                 }
                             };
 
@@ -2334,6 +2825,7 @@ where
                                 .latest_commitment_transaction
                                 .clone()
                                 .expect("latest_commitment_transaction should exist when channel is in ChannelReady of ShuttingDown state");
+                            state.track_force_close_transaction(&transaction);
                             self.network
                                 .send_message(NetworkActorMessage::new_event(
                                     NetworkActorEvent::CommitmentTransactionPending(
@@ -2377,6 +2869,18 @@ where
             );
             return Ok(actor.clone());
         }
+        // The channel is still alive if the peer reconnects within the disconnect grace period,
+        // resume it instead of reloading it from the store.
+        if let Some(actor) = self.disconnected_channels.remove(&channel_id) {
+            if actor
+                .send_message(ChannelActorMessage::Event(ChannelEvent::PeerReconnected))
+                .is_ok()
+            {
+                debug!("Resuming channel {:x} after peer reconnected", &channel_id);
+                self.on_channel_created(channel_id, peer_id, actor.clone());
+                return Ok(actor);
+            }
+        }
         let remote_pubkey =
             self.get_peer_pubkey(peer_id)
                 .ok_or(ProcessingChannelError::InvalidState(format!(
@@ -2393,6 +2897,7 @@ where
                 self.network.clone(),
                 self.store.clone(),
                 self.channel_subscribers.clone(),
+                self.channel_actor_config(),
             ),
             ChannelInitializationParameter::ReestablishChannel(channel_id),
             self.network.get_cell(),
@@ -2442,6 +2947,42 @@ where
                 error!("Failed to reestablish channel {:x}: {:?}", &channel_id, &e);
             }
         }
+
+        for backup in store.get_restored_channel_backups() {
+            if &backup.remote_pubkey.tentacle_peer_id() != remote_peer_id
+                || store.get_channel_actor_state(&backup.channel_id).is_some()
+            {
+                continue;
+            }
+            if let Err(e) = self.request_force_close_from_backup(&backup).await {
+                error!(
+                    "Failed to request force close of channel {:x} restored from backup: {:?}",
+                    &backup.channel_id, &e
+                );
+            }
+        }
+    }
+
+    // The peer ignores the request once the channel is closing, so it's safe to resend it.
+    async fn request_force_close_from_backup(
+        &self,
+        backup: &StaticChannelBackup,
+    ) -> crate::Result<()> {
+        let peer_id = backup.remote_pubkey.tentacle_peer_id();
+        info!(
+            "Requesting peer {:?} to force close channel {:?} restored from backup",
+            &peer_id, &backup.channel_id
+        );
+        self.send_fiber_message_to_peer(
+            &peer_id,
+            FiberMessage::reestablish_channel(ReestablishChannel {
+                channel_id: backup.channel_id,
+                local_commitment_number: 0,
+                remote_commitment_number: 0,
+                force_close_request: true,
+            }),
+        )
+        .await
     }
 
     fn remove_channel(&mut self, channel_id: &Hash256) -> Option<ActorRef<ChannelActorMessage>> {
@@ -2458,6 +2999,9 @@ where
                         let _ = channel.send_message(ChannelActorMessage::Event(
                             ChannelEvent::PeerDisconnected,
                         ));
+                        if self.peer_disconnect_grace.is_some() {
+                            self.disconnected_channels.insert(channel_id, channel);
+                        }
                     }
                 }
             }
@@ -2566,7 +3110,9 @@ where
         self.send_message_to_channel_actor(
             *channel_id,
             None,
-            ChannelActorMessage::Event(ChannelEvent::ClosingTransactionConfirmed),
+            ChannelActorMessage::Event(ChannelEvent::ClosingTransactionConfirmed(
+                (&tx_hash).into(),
+            )),
         )
         .await;
         self.remove_channel(channel_id);
@@ -2583,6 +3129,25 @@ where
             .expect(ASSUME_NETWORK_MYSELF_ALIVE);
     }
 
+    fn on_funding_aborted(&mut self, channel_id: Hash256, peer_id: PeerId, reason: String) {
+        info!(
+            "Funding of channel {:?} with peer {:?} is aborted: {}",
+            &channel_id, &peer_id, &reason
+        );
+        self.remove_channel(&channel_id);
+        if let Some(session) = self.get_peer_session(&peer_id) {
+            if let Some(set) = self.session_channels_map.get_mut(&session) {
+                set.remove(&channel_id);
+            }
+        }
+        // Notify outside observers.
+        self.network
+            .send_message(NetworkActorMessage::new_notification(
+                NetworkServiceEvent::ChannelFundingAborted(peer_id, channel_id, reason),
+            ))
+            .expect(ASSUME_NETWORK_MYSELF_ALIVE);
+    }
+
     pub async fn on_open_channel_msg(
         &mut self,
         peer_id: PeerId,
@@ -2767,7 +3332,7 @@ where
         self.send_message_to_channel_actor(
             channel_id,
             None,
-            ChannelActorMessage::Event(ChannelEvent::CommitmentTransactionConfirmed),
+            ChannelActorMessage::Event(ChannelEvent::CommitmentTransactionConfirmed(tx_hash)),
         )
         .await;
     }
@@ -2823,6 +3388,10 @@ pub struct NetworkActorStartArguments {
     pub tracker: TaskTracker,
     pub channel_subscribers: ChannelSubscribers,
     pub default_shutdown_script: Script,
+    pub forward_policy: Option<Arc<dyn ForwardPolicy>>,
+    pub commitment_signer_provider: Option<Arc<dyn CommitmentSignerProvider>>,
+    // `None` to fall back to the static fee estimator, which doesn't look at the chain.
+    pub fee_estimator: Option<Arc<dyn FeeEstimator>>,
 }
 
 #[rasync_trait]
@@ -2852,6 +3421,9 @@ where
             tracker,
             channel_subscribers,
             default_shutdown_script,
+            forward_policy,
+            commitment_signer_provider,
+            fee_estimator,
         } = args;
         let now = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
@@ -2996,6 +3568,7 @@ where
             peer_session_map: Default::default(),
             session_channels_map: Default::default(),
             channels: Default::default(),
+            disconnected_channels: Default::default(),
             outpoint_channel_map: Default::default(),
             to_be_accepted_channels: Default::default(),
             pending_channels: Default::default(),
@@ -3003,6 +3576,8 @@ where
             open_channel_auto_accept_min_ckb_funding_amount: config
                 .open_channel_auto_accept_min_ckb_funding_amount(),
             auto_accept_channel_ckb_funding_amount: config.auto_accept_channel_ckb_funding_amount(),
+            min_accept_ckb_funding_amount: config.min_accept_ckb_funding_amount(),
+            min_accept_udt_funding_amount: config.min_accept_udt_funding_amount(),
             tlc_expiry_delta: config.tlc_expiry_delta(),
             tlc_min_value: config.tlc_min_value(),
             tlc_max_value: config.tlc_max_value(),
@@ -3011,6 +3586,80 @@ where
             channel_subscribers,
             max_inbound_peers: config.max_inbound_peers(),
             min_outbound_peers: config.min_outbound_peers(),
+            accept_keysend: config.accept_keysend(),
+            seen_payment_hashes: match config.seen_payment_hashes_capacity() {
+                0 => None,
+                capacity => Some(Arc::new(Mutex::new(SeenPaymentHashes::new(capacity)))),
+            },
+            received_mpp_payments: Default::default(),
+            funding_timeout: match config.funding_timeout_seconds() {
+                0 => None,
+                seconds => Some(Duration::from_secs(seconds)),
+            },
+            peer_disconnect_grace: match config.peer_disconnect_grace_seconds() {
+                0 => None,
+                seconds => Some(Duration::from_secs(seconds)),
+            },
+            allowed_close_script_code_hashes: match config.allowed_close_script_code_hashes() {
+                code_hashes if code_hashes.is_empty() => None,
+                code_hashes => Some(Arc::new(code_hashes.iter().cloned().collect())),
+            },
+            max_payment_hops: config.max_payment_hops(),
+            onion_peeling_semaphore: Arc::new(Semaphore::new(
+                config.onion_peeling_concurrency().max(1),
+            )),
+            max_retryable_remove_attempts: match config.max_retryable_remove_attempts() {
+                0 => None,
+                attempts => Some(attempts),
+            },
+            allowed_hash_algorithms: config.allowed_hash_algorithms(),
+            allowed_udt_scripts: config
+                .allowed_udt_scripts()
+                .map(|scripts| Arc::new(scripts.iter().cloned().map(Into::into).collect())),
+            add_tlc_rate_limiter: match config.add_tlc_rate_limit() {
+                0 => None,
+                rate => Some(Arc::new(Mutex::new(AddTlcRateLimiter::new(
+                    rate,
+                    config.add_tlc_burst_limit(),
+                )))),
+            },
+            max_remote_commitment_points: match config.max_remote_commitment_points() {
+                0 => None,
+                points => Some(points as usize),
+            },
+            accept_channel_visibility: config.accept_channel_visibility(),
+            accept_min_remote_reserve: config.accept_min_remote_reserve(),
+            no_invoice_policy: config.no_invoice_policy(),
+            reestablish_backoff: ReestablishBackoff {
+                base: Duration::from_secs(config.reestablish_backoff_base_seconds()),
+                cap: Duration::from_secs(config.reestablish_backoff_cap_seconds()),
+                timeout: match config.reestablish_timeout_seconds() {
+                    0 => None,
+                    seconds => Some(Duration::from_secs(seconds)),
+                },
+            },
+            shutdown_timeout: match config.shutdown_timeout_seconds() {
+                0 => None,
+                seconds => Some(ShutdownTimeout {
+                    timeout: Duration::from_secs(seconds),
+                    force_close: config.force_close_on_shutdown_timeout(),
+                }),
+            },
+            commitment_resync_timeout: match config.commitment_resync_timeout_seconds() {
+                0 => None,
+                seconds => Some(Duration::from_secs(seconds)),
+            },
+            max_tlc_value_in_flight_percentage: match config.max_tlc_value_in_flight_percentage() {
+                0 => None,
+                percentage => Some(percentage),
+            },
+            fee_policy_bounds: config
+                .fee_policy_bounds()
+                .map_err(|err| err.to_string())?,
+            max_accepted_tlc_dust_limit: config.max_accepted_tlc_dust_limit(),
+            forward_policy,
+            commitment_signer_provider,
+            fee_estimator: fee_estimator.unwrap_or_else(default_fee_estimator),
         };
 
         // Save our own NodeInfo to the network graph.
@@ -3119,11 +3768,15 @@ where
         &self,
         _myself: ActorRef<Self::Msg>,
         message: SupervisionEvent,
-        _state: &mut Self::State,
+        state: &mut Self::State,
     ) -> Result<(), ActorProcessingErr> {
         match message {
             SupervisionEvent::ActorTerminated(who, _, _) => {
                 debug!("Actor {:?} terminated", who);
+                // The channel is stopped as the peer didn't reconnect within the grace period.
+                state
+                    .disconnected_channels
+                    .retain(|_, actor| actor.get_id() != who.get_id());
             }
             SupervisionEvent::ActorPanicked(who, _) => {
                 error!("Actor {:?} panicked", who);
@@ -3143,6 +3796,7 @@ impl FiberProtocolHandle {
     fn create_meta(self) -> ProtocolMeta {
         MetaBuilder::new()
             .id(FIBER_PROTOCOL_ID)
+            .support_versions(vec![FIBER_PROTOCOL_VERSION.to_string()])
             .service_handle(move || {
                 let handle = Box::new(self);
                 ProtocolHandle::Callback(handle)
@@ -3269,6 +3923,9 @@ pub async fn start_network<
     channel_subscribers: ChannelSubscribers,
     network_graph: Arc<RwLock<NetworkGraph<S>>>,
     default_shutdown_script: Script,
+    forward_policy: Option<Arc<dyn ForwardPolicy>>,
+    commitment_signer_provider: Option<Arc<dyn CommitmentSignerProvider>>,
+    fee_estimator: Option<Arc<dyn FeeEstimator>>,
 ) -> ActorRef<NetworkActorMessage> {
     let my_pubkey = config.public_key();
     let my_peer_id = PeerId::from_public_key(&my_pubkey);
@@ -3281,6 +3938,9 @@ pub async fn start_network<
             tracker,
             channel_subscribers,
             default_shutdown_script,
+            forward_policy,
+            commitment_signer_provider,
+            fee_estimator,
         },
         root_actor,
     )
@@ -1,41 +1,62 @@
 use crate::fiber::channel::{
-    AddTlcInfo, CommitmentNumbers, RemoveTlcInfo, TLCId, TlcKind, TlcState, UpdateCommand,
+    AddTlcInfo, AddTlcRateLimiter, ChannelState, CloseFlags, CommitmentNumbers, FeatureBits,
+    FeeEstimator, FixedClock, ForwardBlocker, ForwardDecision, ForwardPolicy, MppPayment,
+    ReestablishBackoff, RemoveTlcInfo, RetryableRemoveTlc, SeenPaymentHashes, ShutdownInfo,
+    ShuttingDownFlags, StaticFeeEstimator, TLCId, TlcDump, TlcKind, TlcState, UpdateCommand,
 };
-use crate::fiber::config::MAX_PAYMENT_TLC_EXPIRY_LIMIT;
+use crate::fiber::config::{
+    ChannelVisibilityPolicy, FiberConfig, NoInvoicePolicy, MAX_PAYMENT_TLC_EXPIRY_LIMIT,
+    MIN_TLC_EXPIRY_DELTA,
+};
+use crate::fiber::fee::{calculate_commitment_tx_fee, calculate_shutdown_tx_fee};
 use crate::fiber::graph::PaymentSessionStatus;
-use crate::fiber::network::{DebugEvent, SendPaymentCommand};
+use crate::fiber::network::{get_chain_hash, DebugEvent, FiberMessageWithPeerId, SendPaymentCommand};
 use crate::fiber::tests::test_utils::*;
 use crate::fiber::types::{
-    Hash256, PaymentHopData, PeeledOnionPacket, TlcErrorCode, NO_SHARED_SECRET,
+    AnnouncementSignatures, CommitmentSigned, FiberMessage, Hash256, PaymentHopData,
+    PaymentOnionPacket, PeeledOnionPacket, PeeledPaymentOnionPacket, RevokeAndAck, TlcErr,
+    TlcErrPacket, TlcErrorCode, NO_SHARED_SECRET, PROBE_CUSTOM_RECORD_TYPE,
 };
-use crate::invoice::{CkbInvoiceStatus, Currency, InvoiceBuilder};
+use crate::invoice::{CkbInvoiceStatus, Currency, InvoiceBuilder, InvoiceStore};
 use crate::{
-    ckb::contracts::{get_cell_deps, Contract},
+    ckb::{
+        contracts::{get_cell_deps, get_script_by_contract, Contract},
+        tests::test_utils::MOCK_CONTEXT,
+    },
     fiber::{
         channel::{
-            derive_private_key, derive_tlc_pubkey, AddTlcCommand, ChannelActorStateStore,
-            ChannelCommand, ChannelCommandWithId, InMemorySigner, RemoveTlcCommand,
-            ShutdownCommand, DEFAULT_COMMITMENT_FEE_RATE,
+            anchor_capacity, check_funding_udt_type_script, derive_private_key, derive_tlc_pubkey,
+            negotiate_hash_algorithms, occupied_capacity, reserved_capacity,
+            settlement_output_sort_key, AddTlcCommand, ChannelActorStateStore, ChannelCommand,
+            ChannelCommandError, ChannelCommandWithId, CommitmentSigner, CommitmentSignerProvider,
+            InMemorySigner, Musig2SignContext, ProcessingChannelError, RemoveTlcCommand,
+            ShutdownCommand, DEFAULT_COMMITMENT_DELAY_EPOCHS, DEFAULT_COMMITMENT_FEE_RATE,
+            DEFAULT_FEE_RATE, INITIAL_COMMITMENT_NUMBER, LOCAL_ANCHOR_OUTPUT_INDEX,
+            MAX_COMMITMENT_DELAY_EPOCHS, MAX_CONSECUTIVE_SIGNING_FAILURES,
         },
         config::DEFAULT_AUTO_ACCEPT_CHANNEL_CKB_FUNDING_AMOUNT,
         hash_algorithm::HashAlgorithm,
         network::{AcceptChannelCommand, OpenChannelCommand},
         tests::test_utils::establish_channel_between_nodes,
-        types::{Privkey, RemoveTlcFulfill, RemoveTlcReason},
+        types::{Privkey, Pubkey, RemoveTlc, RemoveTlcFulfill, RemoveTlcReason},
         NetworkActorCommand, NetworkActorMessage,
     },
     gen_rand_fiber_private_key, gen_rand_fiber_public_key, gen_rand_sha256_hash,
     now_timestamp_as_millis_u64, NetworkServiceEvent,
 };
 use ckb_jsonrpc_types::Status;
+use ckb_sdk::{Address, NetworkType};
 use ckb_types::{
-    core::FeeRate,
-    packed::{CellInput, Script, Transaction},
+    core::{EpochNumberWithFraction, FeeRate},
+    packed::{CellInput, CellOutput, OutPoint, Script, Transaction},
     prelude::{AsTransactionBuilder, Builder, Entity, IntoTransactionView, Pack, Unpack},
 };
-use ractor::call;
+use musig2::{errors::SigningError, AggNonce, KeyAggContext, PartialSignature, SecNonce};
+use ractor::{call, Actor, ActorProcessingErr, ActorRef};
 use secp256k1::Secp256k1;
 use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use super::test_utils::{init_tracing, NetworkNode};
@@ -62,6 +83,119 @@ fn test_derive_private_and_public_tlc_keys() {
     assert_eq!(derived_privkey.pubkey(), derived_pubkey);
 }
 
+#[test]
+fn test_seen_payment_hashes_evict_oldest() {
+    let mut seen = SeenPaymentHashes::new(2);
+    let hashes: Vec<Hash256> = (0..3).map(|_| gen_rand_sha256_hash()).collect();
+    seen.insert(hashes[0]);
+    seen.insert(hashes[1]);
+    // inserting a duplicated hash should not evict anything
+    seen.insert(hashes[0]);
+    assert!(seen.contains(&hashes[0]));
+    assert!(seen.contains(&hashes[1]));
+
+    seen.insert(hashes[2]);
+    assert!(!seen.contains(&hashes[0]));
+    assert!(seen.contains(&hashes[1]));
+    assert!(seen.contains(&hashes[2]));
+
+    let mut disabled = SeenPaymentHashes::new(0);
+    disabled.insert(hashes[0]);
+    assert!(!disabled.contains(&hashes[0]));
+}
+
+#[test]
+fn test_add_tlc_rate_limiter() {
+    let mut limiter = AddTlcRateLimiter::new(2, 3);
+    let peer_a = gen_rand_fiber_public_key();
+    let peer_b = gen_rand_fiber_public_key();
+    let now = 1_000_000;
+
+    // the burst is consumed first
+    for _ in 0..3 {
+        assert!(limiter.try_acquire(&peer_a, now));
+    }
+    assert!(!limiter.try_acquire(&peer_a, now));
+    // the buckets of peers are independent
+    assert!(limiter.try_acquire(&peer_b, now));
+
+    // 2 tokens per second are refilled
+    assert!(!limiter.try_acquire(&peer_a, now + 400));
+    assert!(limiter.try_acquire(&peer_a, now + 500));
+    assert!(!limiter.try_acquire(&peer_a, now + 500));
+
+    // the refilled tokens never exceed the burst
+    let later = now + 60_000;
+    for _ in 0..3 {
+        assert!(limiter.try_acquire(&peer_a, later));
+    }
+    assert!(!limiter.try_acquire(&peer_a, later));
+}
+
+#[test]
+fn test_retryable_remove_attempts() {
+    let mut tlc_state = TlcState::default();
+    let reason = RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
+        payment_preimage: gen_rand_sha256_hash(),
+    });
+    let channel_id = gen_rand_sha256_hash();
+    tlc_state.set_tlc_pending_remove(TLCId::Received(0), reason.clone());
+    tlc_state.insert_relay_tlc_remove(channel_id, 1, reason.clone());
+
+    let local_remove = RetryableRemoveTlc::RemoveTlc(TLCId::Received(0), reason.clone());
+    let relay_remove = RetryableRemoveTlc::RelayRemoveTlc(channel_id, 1, reason);
+    assert_eq!(
+        tlc_state.get_pending_remove(),
+        vec![local_remove.clone(), relay_remove.clone()]
+    );
+
+    assert_eq!(tlc_state.get_retryable_remove_attempts(&relay_remove), 0);
+    assert_eq!(tlc_state.increment_retryable_remove_attempts(&relay_remove), 1);
+    assert_eq!(tlc_state.increment_retryable_remove_attempts(&relay_remove), 2);
+    assert_eq!(tlc_state.get_retryable_remove_attempts(&relay_remove), 2);
+    assert_eq!(tlc_state.get_retryable_remove_attempts(&local_remove), 0);
+
+    tlc_state.remove_pending_remove_tlc(&relay_remove);
+    assert_eq!(tlc_state.get_pending_remove(), vec![local_remove]);
+    assert_eq!(tlc_state.get_retryable_remove_attempts(&relay_remove), 0);
+}
+
+#[test]
+fn test_abandon_exhausted_retryable_removes_keeps_fulfills() {
+    let mut tlc_state = TlcState::default();
+    let fulfill = RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
+        payment_preimage: gen_rand_sha256_hash(),
+    });
+    let fail = RemoveTlcReason::RemoveTlcFail(TlcErrPacket::new(
+        TlcErr::new(TlcErrorCode::TemporaryChannelFailure),
+        &NO_SHARED_SECRET,
+    ));
+    let channel_id = gen_rand_sha256_hash();
+    tlc_state.insert_relay_tlc_remove(channel_id, 0, fulfill.clone());
+    tlc_state.insert_relay_tlc_remove(channel_id, 1, fail.clone());
+
+    let relay_fulfill = RetryableRemoveTlc::RelayRemoveTlc(channel_id, 0, fulfill);
+    let relay_fail = RetryableRemoveTlc::RelayRemoveTlc(channel_id, 1, fail);
+    let max_attempts = 3;
+    for _ in 1..max_attempts {
+        assert!(tlc_state
+            .abandon_exhausted_retryable_removes(max_attempts)
+            .is_empty());
+    }
+    assert_eq!(
+        tlc_state.abandon_exhausted_retryable_removes(max_attempts),
+        vec![relay_fail]
+    );
+
+    // The fulfill survives past the limit.
+    for _ in 0..max_attempts {
+        assert!(tlc_state
+            .abandon_exhausted_retryable_removes(max_attempts)
+            .is_empty());
+    }
+    assert_eq!(tlc_state.get_pending_remove(), vec![relay_fulfill]);
+}
+
 #[test]
 fn test_pending_tlcs() {
     let mut tlc_state = TlcState::default();
@@ -78,6 +212,8 @@ fn test_pending_tlcs() {
         removed_at: None,
         payment_preimage: None,
         previous_tlc: None,
+        custom_records: vec![],
+        next_hop: None,
     };
     let add_tlc2 = AddTlcInfo {
         amount: 20000,
@@ -92,6 +228,8 @@ fn test_pending_tlcs() {
         removed_at: None,
         payment_preimage: None,
         previous_tlc: None,
+        custom_records: vec![],
+        next_hop: None,
     };
     tlc_state.add_local_tlc(TlcKind::AddTlc(add_tlc1.clone()));
     tlc_state.add_local_tlc(TlcKind::AddTlc(add_tlc2.clone()));
@@ -159,6 +297,8 @@ fn test_pending_tlcs_duplicated_tlcs() {
         removed_at: None,
         payment_preimage: None,
         previous_tlc: None,
+        custom_records: vec![],
+        next_hop: None,
     };
     tlc_state.add_local_tlc(TlcKind::AddTlc(add_tlc1.clone()));
 
@@ -197,6 +337,8 @@ fn test_pending_tlcs_duplicated_tlcs() {
         removed_at: None,
         payment_preimage: None,
         previous_tlc: None,
+        custom_records: vec![],
+        next_hop: None,
     };
 
     tlc_state_2.add_local_tlc(TlcKind::AddTlc(add_tlc2.clone()));
@@ -239,6 +381,8 @@ fn test_pending_tlcs_with_remove_tlc() {
         removed_at: None,
         payment_preimage: None,
         previous_tlc: None,
+        custom_records: vec![],
+        next_hop: None,
     };
     let add_tlc2 = AddTlcInfo {
         amount: 20000,
@@ -253,6 +397,8 @@ fn test_pending_tlcs_with_remove_tlc() {
         removed_at: None,
         payment_preimage: None,
         previous_tlc: None,
+        custom_records: vec![],
+        next_hop: None,
     };
     let remote_tlc = RemoveTlcInfo {
         channel_id: gen_rand_sha256_hash(),
@@ -298,6 +444,11 @@ async fn test_open_channel_to_peer() {
                 tlc_fee_proportional_millionths: None,
                 max_tlc_number_in_flight: None,
                 max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
             },
             rpc_reply,
         ))
@@ -338,6 +489,11 @@ async fn test_open_and_accept_channel() {
                 tlc_fee_proportional_millionths: None,
                 max_tlc_number_in_flight: None,
                 max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
             },
             rpc_reply,
         ))
@@ -368,6 +524,8 @@ async fn test_open_and_accept_channel() {
                 min_tlc_value: None,
                 tlc_fee_proportional_millionths: None,
                 tlc_expiry_delta: None,
+                commitment_delay_epoch: None,
+                channel_reserve_amount: None,
             },
             rpc_reply,
         ))
@@ -379,100 +537,417 @@ async fn test_open_and_accept_channel() {
 }
 
 #[tokio::test]
-async fn test_create_private_channel() {
+async fn test_abort_funding_after_funding_timeout() {
     init_tracing();
 
-    let node_a_funding_amount = 100000000000;
-    let node_b_funding_amount = 6200000000;
-
-    let (_node_a, _node_b, _new_channel_id, _) = NetworkNode::new_2_nodes_with_established_channel(
-        node_a_funding_amount,
-        node_b_funding_amount,
-        false,
-    )
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(|config| {
+                config.funding_timeout_seconds = Some(1);
+            })
+            .build()
+    })
     .await;
+    let node_b = nodes.pop().unwrap();
+    let mut node_a = nodes.pop().unwrap();
+
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 100000000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let open_channel_result = call!(node_a.network_actor, message)
+        .expect("node_a alive")
+        .expect("open channel success");
+
+    // Node b never accepts the channel, so node a should abort it after the timeout.
+    node_a
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelFundingAborted(peer_id, channel_id, _reason) => {
+                assert_eq!(peer_id, &node_b.peer_id);
+                assert_eq!(channel_id, &open_channel_result.channel_id);
+                true
+            }
+            _ => false,
+        })
+        .await;
+    // Wait for the channel actor to persist its state.
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    let state = node_a.get_channel_actor_state(open_channel_result.channel_id);
+    assert_eq!(state.state, ChannelState::Closed(CloseFlags::ABANDONED));
+    assert!(state.funding_tx.is_none());
 }
 
 #[tokio::test]
-async fn test_create_public_channel() {
+async fn test_accept_channel_below_min_accept_funding_amount_should_fail() {
     init_tracing();
 
-    let node_a_funding_amount = 100000000000;
-    let node_b_funding_amount = 6200000000;
-
-    let (_node_a, _node_b, _new_channel_id, _) = NetworkNode::new_2_nodes_with_established_channel(
-        node_a_funding_amount,
-        node_b_funding_amount,
-        true,
-    )
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(|config| {
+                config.min_accept_ckb_funding_amount = Some(200000000000);
+            })
+            .build()
+    })
     .await;
+    let mut node_b = nodes.pop().unwrap();
+    let node_a = nodes.pop().unwrap();
+
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 100000000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let open_channel_result = call!(node_a.network_actor, message)
+        .expect("node_a alive")
+        .expect("open channel success");
+
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelPendingToBeAccepted(peer_id, _channel_id) => {
+                assert_eq!(peer_id, &node_a.peer_id);
+                true
+            }
+            _ => false,
+        })
+        .await;
+
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id: open_channel_result.channel_id,
+                funding_amount: DEFAULT_AUTO_ACCEPT_CHANNEL_CKB_FUNDING_AMOUNT as u128,
+                shutdown_script: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                min_tlc_value: None,
+                tlc_fee_proportional_millionths: None,
+                tlc_expiry_delta: None,
+                commitment_delay_epoch: None,
+                channel_reserve_amount: None,
+            },
+            rpc_reply,
+        ))
+    };
+
+    let result = call!(node_b.network_actor, message).expect("node_b alive");
+    assert!(result.is_err());
 }
 
-#[tokio::test]
-async fn test_public_channel_saved_to_the_owner_graph() {
-    init_tracing();
+async fn open_channel_and_accept_with_commitment_delay_epoch(
+    commitment_delay_epoch: EpochNumberWithFraction,
+) -> (NetworkNode, NetworkNode, Result<Hash256, String>) {
+    let [node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
 
-    let node1_funding_amount = 100000000000;
-    let node2_funding_amount = 6200000000;
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 100000000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
+            },
+            rpc_reply,
+        ))
+    };
+    let open_channel_result = call!(node_a.network_actor, message)
+        .expect("node_a alive")
+        .expect("open channel success");
 
-    let (mut node1, mut node2, _new_channel_id, _) =
-        NetworkNode::new_2_nodes_with_established_channel(
-            node1_funding_amount,
-            node2_funding_amount,
-            true,
-        )
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelPendingToBeAccepted(peer_id, _channel_id) => {
+                assert_eq!(peer_id, &node_a.peer_id);
+                true
+            }
+            _ => false,
+        })
         .await;
 
-    // Wait for the channel announcement to be broadcasted
-    tokio::time::sleep(tokio::time::Duration::from_millis(2000)).await;
+    let message = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id: open_channel_result.channel_id,
+                funding_amount: DEFAULT_AUTO_ACCEPT_CHANNEL_CKB_FUNDING_AMOUNT as u128,
+                shutdown_script: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                min_tlc_value: None,
+                tlc_fee_proportional_millionths: None,
+                tlc_expiry_delta: None,
+                commitment_delay_epoch: Some(commitment_delay_epoch),
+                channel_reserve_amount: None,
+            },
+            rpc_reply,
+        ))
+    };
 
-    let node1_id = node1.peer_id.clone();
-    node1.stop().await;
-    let node2_id = node2.peer_id.clone();
-    node2.stop().await;
+    let result = call!(node_b.network_actor, message)
+        .expect("node_b alive")
+        .map(|res| res.new_channel_id);
+    (node_a, node_b, result)
+}
 
-    let node1_channels = node1.get_network_graph_channels().await;
-    assert_eq!(node1_channels.len(), 1);
-    let node1_channel = &node1_channels[0];
-    assert_eq!(
-        HashSet::from([node1_channel.node1_peerid(), node1_channel.node2_peerid()]),
-        HashSet::from([node1_id.clone(), node2_id.clone()])
-    );
-    let node1_nodes = node1.get_network_graph_nodes().await;
-    assert_eq!(node1_nodes.len(), 2);
-    for node in node1_nodes {
-        assert!(node.node_id == node1_channel.node1() || node.node_id == node1_channel.node2());
-    }
+#[tokio::test]
+async fn test_accept_channel_with_counter_proposed_commitment_delay_epoch() {
+    init_tracing();
 
-    let node2_channels = node2.get_network_graph_channels().await;
-    assert_eq!(node2_channels.len(), 1);
-    let node2_channel = &node2_channels[0];
-    assert_eq!(
-        HashSet::from([node2_channel.node1_peerid(), node2_channel.node2_peerid()]),
-        HashSet::from([node1_id, node2_id])
-    );
-    let node2_nodes = node2.get_network_graph_nodes().await;
-    assert_eq!(node2_nodes.len(), 2);
-    for node in node2_nodes {
-        assert!(node.node_id == node2_channel.node1() || node.node_id == node2_channel.node2());
-    }
+    let commitment_delay_epoch = EpochNumberWithFraction::new(2, 0, 1);
+    let (node_a, node_b, result) =
+        open_channel_and_accept_with_commitment_delay_epoch(commitment_delay_epoch).await;
+    let channel_id = result.expect("accept channel success");
+
+    // Wait for the AcceptChannel message to be processed by node_a.
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    let state_a = node_a.get_channel_actor_state(channel_id);
+    let state_b = node_b.get_channel_actor_state(channel_id);
+    let default_delay_epoch =
+        EpochNumberWithFraction::new(DEFAULT_COMMITMENT_DELAY_EPOCHS, 0, 1).full_value();
+    assert_eq!(state_a.local_delay_epoch, commitment_delay_epoch.full_value());
+    assert_eq!(state_a.remote_delay_epoch, default_delay_epoch);
+    assert_eq!(state_b.local_delay_epoch, default_delay_epoch);
+    assert_eq!(state_b.remote_delay_epoch, commitment_delay_epoch.full_value());
 }
 
 #[tokio::test]
-async fn test_public_channel_saved_to_the_other_nodes_graph() {
+async fn test_add_tlc_with_differing_commitment_delay_epochs() {
     init_tracing();
 
-    let node1_funding_amount = 100000000000;
-    let node2_funding_amount = 6200000000;
+    let commitment_delay_epoch = EpochNumberWithFraction::new(2, 0, 1);
+    let (node_a, mut node_b, result) =
+        open_channel_and_accept_with_commitment_delay_epoch(commitment_delay_epoch).await;
+    let channel_id = result.expect("accept channel success");
 
-    let [mut node1, mut node2, mut node3] = NetworkNode::new_n_interconnected_nodes().await;
-    let (_channel_id, funding_tx) = establish_channel_between_nodes(
-        &mut node1,
-        &mut node2,
-        true,
-        node1_funding_amount,
-        node2_funding_amount,
-        None,
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelReady(peer_id, id, _funding_tx_hash) => {
+                assert_eq!(peer_id, &node_a.peer_id);
+                assert_eq!(id, &channel_id);
+                true
+            }
+            _ => false,
+        })
+        .await;
+
+    let preimage = [1; 32];
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: HashAlgorithm::CkbHash.hash(&preimage).into(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully added tlc");
+
+    // Both commitment transactions are signed with the delay of their holders, the tlc can only
+    // be committed if the two peers agree on the delay of each commitment transaction.
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::RemoteCommitmentSigned(peer_id, id, _, _) => {
+                assert_eq!(peer_id, &node_a.peer_id);
+                assert_eq!(id, &channel_id);
+                true
+            }
+            _ => false,
+        })
+        .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    let state_a = node_a.get_channel_actor_state(channel_id);
+    let state_b = node_b.get_channel_actor_state(channel_id);
+    assert_ne!(state_a.local_delay_epoch, state_a.remote_delay_epoch);
+    assert_eq!(
+        state_a.get_local_commitment_number(),
+        state_b.get_remote_commitment_number()
+    );
+    assert_eq!(
+        state_a.get_remote_commitment_number(),
+        state_b.get_local_commitment_number()
+    );
+    assert!(state_a.get_local_commitment_number() > INITIAL_COMMITMENT_NUMBER);
+}
+
+#[tokio::test]
+async fn test_accept_channel_with_invalid_commitment_delay_epoch() {
+    init_tracing();
+
+    let commitment_delay_epoch =
+        EpochNumberWithFraction::new(MAX_COMMITMENT_DELAY_EPOCHS + 1, 0, 1);
+    let (_node_a, _node_b, result) =
+        open_channel_and_accept_with_commitment_delay_epoch(commitment_delay_epoch).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_create_private_channel() {
+    init_tracing();
+
+    let node_a_funding_amount = 100000000000;
+    let node_b_funding_amount = 6200000000;
+
+    let (_node_a, _node_b, _new_channel_id, _) = NetworkNode::new_2_nodes_with_established_channel(
+        node_a_funding_amount,
+        node_b_funding_amount,
+        false,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_create_public_channel() {
+    init_tracing();
+
+    let node_a_funding_amount = 100000000000;
+    let node_b_funding_amount = 6200000000;
+
+    let (_node_a, _node_b, _new_channel_id, _) = NetworkNode::new_2_nodes_with_established_channel(
+        node_a_funding_amount,
+        node_b_funding_amount,
+        true,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_public_channel_saved_to_the_owner_graph() {
+    init_tracing();
+
+    let node1_funding_amount = 100000000000;
+    let node2_funding_amount = 6200000000;
+
+    let (mut node1, mut node2, _new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(
+            node1_funding_amount,
+            node2_funding_amount,
+            true,
+        )
+        .await;
+
+    // Wait for the channel announcement to be broadcasted
+    tokio::time::sleep(tokio::time::Duration::from_millis(2000)).await;
+
+    let node1_id = node1.peer_id.clone();
+    node1.stop().await;
+    let node2_id = node2.peer_id.clone();
+    node2.stop().await;
+
+    let node1_channels = node1.get_network_graph_channels().await;
+    assert_eq!(node1_channels.len(), 1);
+    let node1_channel = &node1_channels[0];
+    assert_eq!(
+        HashSet::from([node1_channel.node1_peerid(), node1_channel.node2_peerid()]),
+        HashSet::from([node1_id.clone(), node2_id.clone()])
+    );
+    let node1_nodes = node1.get_network_graph_nodes().await;
+    assert_eq!(node1_nodes.len(), 2);
+    for node in node1_nodes {
+        assert!(node.node_id == node1_channel.node1() || node.node_id == node1_channel.node2());
+    }
+
+    let node2_channels = node2.get_network_graph_channels().await;
+    assert_eq!(node2_channels.len(), 1);
+    let node2_channel = &node2_channels[0];
+    assert_eq!(
+        HashSet::from([node2_channel.node1_peerid(), node2_channel.node2_peerid()]),
+        HashSet::from([node1_id, node2_id])
+    );
+    let node2_nodes = node2.get_network_graph_nodes().await;
+    assert_eq!(node2_nodes.len(), 2);
+    for node in node2_nodes {
+        assert!(node.node_id == node2_channel.node1() || node.node_id == node2_channel.node2());
+    }
+}
+
+#[tokio::test]
+async fn test_public_channel_saved_to_the_other_nodes_graph() {
+    init_tracing();
+
+    let node1_funding_amount = 100000000000;
+    let node2_funding_amount = 6200000000;
+
+    let [mut node1, mut node2, mut node3] = NetworkNode::new_n_interconnected_nodes().await;
+    let (_channel_id, funding_tx) = establish_channel_between_nodes(
+        &mut node1,
+        &mut node2,
+        true,
+        node1_funding_amount,
+        node2_funding_amount,
+        None,
         None,
         None,
         None,
@@ -583,6 +1058,7 @@ async fn test_network_send_payment_normal_keysend_workflow() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -631,6 +1107,7 @@ async fn test_network_send_payment_normal_keysend_workflow() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -686,6 +1163,7 @@ async fn test_network_send_payment_send_each_other() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -714,6 +1192,7 @@ async fn test_network_send_payment_send_each_other() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -779,6 +1258,7 @@ async fn test_network_send_payment_send_with_ack() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -810,6 +1290,7 @@ async fn test_network_send_payment_send_with_ack() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -867,6 +1348,8 @@ async fn test_network_send_previous_tlc_error() {
             funding_tx_hash: Hash256::default(),
             hash_algorithm: HashAlgorithm::Sha256,
             payment_preimage: None,
+            custom_records: vec![],
+            total_amount: None,
         },
         PaymentHopData {
             amount: 8,
@@ -875,6 +1358,8 @@ async fn test_network_send_previous_tlc_error() {
             funding_tx_hash: Hash256::default(),
             hash_algorithm: HashAlgorithm::Sha256,
             payment_preimage: None,
+            custom_records: vec![],
+            total_amount: None,
         },
     ];
     let generated_payment_hash = gen_rand_sha256_hash();
@@ -903,6 +1388,8 @@ async fn test_network_send_previous_tlc_error() {
                         onion_packet: packet.next.clone(),
                         shared_secret: packet.shared_secret.clone(),
                         previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
                     },
                     rpc_reply,
                 ),
@@ -950,6 +1437,7 @@ async fn test_network_send_previous_tlc_error() {
                 allow_self_payment: false,
                 dry_run: false,
                 tlc_expiry_limit: None,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1003,6 +1491,7 @@ async fn test_network_send_payment_keysend_with_payment_hash() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1014,6 +1503,77 @@ async fn test_network_send_payment_keysend_with_payment_hash() {
         .contains("keysend payment should not have payment_hash"));
 }
 
+#[tokio::test]
+async fn test_network_send_payment_keysend_rejected_by_receiver() {
+    init_tracing();
+
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(move |config| {
+                if i == 1 {
+                    config.accept_keysend = Some(false);
+                }
+            })
+            .build()
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let mut node_a = nodes.pop().unwrap();
+    let (new_channel_id, _funding_tx) = establish_channel_between_nodes(
+        &mut node_a,
+        &mut node_b,
+        true,
+        100000000000,
+        6200000000,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await;
+    // Wait for the channel announcement to be broadcasted
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+    let node_b_local_balance = node_b.get_local_balance_from_channel(new_channel_id);
+
+    let res = node_a
+        .send_payment(SendPaymentCommand {
+            target_pubkey: Some(node_b.pubkey.clone()),
+            amount: Some(10000),
+            payment_hash: None,
+            final_tlc_expiry_delta: None,
+            tlc_expiry_limit: None,
+            invoice: None,
+            timeout: None,
+            max_fee_amount: None,
+            max_parts: None,
+            keysend: Some(true),
+            udt_type_script: None,
+            allow_self_payment: false,
+            dry_run: false,
+            custom_records: vec![],
+        })
+        .await
+        .expect("send payment");
+    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+
+    // The receiver fails the keysend tlc instead of claiming it with the preimage.
+    node_a
+        .assert_payment_status(res.payment_hash, PaymentSessionStatus::Failed, None)
+        .await;
+    assert_eq!(
+        node_b.get_local_balance_from_channel(new_channel_id),
+        node_b_local_balance
+    );
+}
+
 #[tokio::test]
 async fn test_network_send_payment_final_incorrect_hash() {
     init_tracing();
@@ -1051,6 +1611,7 @@ async fn test_network_send_payment_final_incorrect_hash() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1116,6 +1677,7 @@ async fn test_network_send_payment_target_not_found() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1156,6 +1718,7 @@ async fn test_network_send_payment_amount_is_too_large() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1200,6 +1763,7 @@ async fn test_network_send_payment_with_dry_run() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: true,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1227,6 +1791,7 @@ async fn test_network_send_payment_with_dry_run() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: true,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1271,6 +1836,7 @@ async fn test_send_payment_with_3_nodes() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1349,6 +1915,7 @@ async fn test_send_payment_with_rev_3_nodes() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1419,6 +1986,7 @@ async fn test_send_payment_with_max_nodes() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: true,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1442,6 +2010,7 @@ async fn test_send_payment_with_max_nodes() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1506,6 +2075,7 @@ async fn test_send_payment_with_3_nodes_overflow() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1554,6 +2124,7 @@ async fn test_send_payment_fail_with_3_nodes_invalid_hash() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1625,6 +2196,7 @@ async fn test_send_payment_fail_with_3_nodes_final_tlc_expiry_delta() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1651,6 +2223,7 @@ async fn test_send_payment_fail_with_3_nodes_final_tlc_expiry_delta() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1676,6 +2249,7 @@ async fn test_send_payment_fail_with_3_nodes_final_tlc_expiry_delta() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1718,6 +2292,7 @@ async fn test_send_payment_fail_with_3_nodes_dry_run_fee() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: true,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1743,6 +2318,7 @@ async fn test_send_payment_fail_with_3_nodes_dry_run_fee() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: true,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1770,6 +2346,7 @@ async fn test_send_payment_fail_with_3_nodes_dry_run_fee() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1796,6 +2373,7 @@ async fn test_send_payment_fail_with_3_nodes_dry_run_fee() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1836,6 +2414,7 @@ async fn test_network_send_payment_dry_run_can_still_query() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1861,6 +2440,7 @@ async fn test_network_send_payment_dry_run_can_still_query() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: true,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1901,6 +2481,7 @@ async fn test_network_send_payment_dry_run_will_not_create_payment_session() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: true,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1927,6 +2508,7 @@ async fn test_network_send_payment_dry_run_will_not_create_payment_session() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -1977,6 +2559,11 @@ async fn do_test_channel_commitment_tx_after_add_tlc(algorithm: HashAlgorithm) {
                 tlc_fee_proportional_millionths: None,
                 max_tlc_number_in_flight: None,
                 max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
             },
             rpc_reply,
         ))
@@ -2006,6 +2593,8 @@ async fn do_test_channel_commitment_tx_after_add_tlc(algorithm: HashAlgorithm) {
                 min_tlc_value: None,
                 tlc_fee_proportional_millionths: None,
                 tlc_expiry_delta: None,
+                commitment_delay_epoch: None,
+                channel_reserve_amount: None,
             },
             rpc_reply,
         ))
@@ -2062,6 +2651,8 @@ async fn do_test_channel_commitment_tx_after_add_tlc(algorithm: HashAlgorithm) {
                         onion_packet: None,
                         shared_secret: NO_SHARED_SECRET.clone(),
                         previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
                     },
                     rpc_reply,
                 ),
@@ -2180,6 +2771,8 @@ async fn do_test_remove_tlc_with_wrong_hash_algorithm(
                         onion_packet: None,
                         shared_secret: NO_SHARED_SECRET.clone(),
                         previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
                     },
                     rpc_reply,
                 ),
@@ -2232,6 +2825,8 @@ async fn do_test_remove_tlc_with_wrong_hash_algorithm(
                         onion_packet: None,
                         shared_secret: NO_SHARED_SECRET.clone(),
                         previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
                     },
                     rpc_reply,
                 ),
@@ -2290,6 +2885,8 @@ async fn test_remove_tlc_with_expiry_error() {
         onion_packet: None,
         shared_secret: NO_SHARED_SECRET.clone(),
         previous_tlc: None,
+        payment_preimage: None,
+        idempotency_key: None,
     };
 
     std::thread::sleep(std::time::Duration::from_millis(400));
@@ -2313,6 +2910,8 @@ async fn test_remove_tlc_with_expiry_error() {
         onion_packet: None,
         shared_secret: NO_SHARED_SECRET.clone(),
         previous_tlc: None,
+        payment_preimage: None,
+        idempotency_key: None,
     };
 
     let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
@@ -2352,6 +2951,8 @@ async fn do_test_add_tlc_duplicated() {
             onion_packet: None,
             shared_secret: NO_SHARED_SECRET.clone(),
             previous_tlc: None,
+            payment_preimage: None,
+            idempotency_key: None,
         };
         let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
             NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
@@ -2391,6 +2992,8 @@ async fn do_test_add_tlc_waiting_ack() {
             onion_packet: None,
             shared_secret: NO_SHARED_SECRET.clone(),
             previous_tlc: None,
+            payment_preimage: None,
+            idempotency_key: None,
         };
         let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
             NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
@@ -2421,6 +3024,8 @@ async fn do_test_add_tlc_waiting_ack() {
             onion_packet: None,
             previous_tlc: None,
             shared_secret: NO_SHARED_SECRET.clone(),
+            payment_preimage: None,
+            idempotency_key: None,
         };
         let add_tlc_result = call!(node_b.network_actor, |rpc_reply| {
             NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
@@ -2481,6 +3086,8 @@ async fn do_test_add_tlc_number_limit() {
             onion_packet: None,
             shared_secret: NO_SHARED_SECRET.clone(),
             previous_tlc: None,
+            payment_preimage: None,
+            idempotency_key: None,
         };
         let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
             NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
@@ -2512,6 +3119,8 @@ async fn do_test_add_tlc_number_limit() {
             onion_packet: None,
             shared_secret: NO_SHARED_SECRET.clone(),
             previous_tlc: None,
+            payment_preimage: None,
+            idempotency_key: None,
         };
         let add_tlc_result = call!(node_b.network_actor, |rpc_reply| {
             NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
@@ -2566,6 +3175,8 @@ async fn do_test_add_tlc_number_limit_reverse() {
             onion_packet: None,
             shared_secret: NO_SHARED_SECRET.clone(),
             previous_tlc: None,
+            payment_preimage: None,
+            idempotency_key: None,
         };
         let add_tlc_result = call!(node_b.network_actor, |rpc_reply| {
             NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
@@ -2597,6 +3208,8 @@ async fn do_test_add_tlc_number_limit_reverse() {
             onion_packet: None,
             shared_secret: NO_SHARED_SECRET.clone(),
             previous_tlc: None,
+            payment_preimage: None,
+            idempotency_key: None,
         };
         let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
             NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
@@ -2652,6 +3265,8 @@ async fn do_test_add_tlc_value_limit() {
             onion_packet: None,
             shared_secret: NO_SHARED_SECRET.clone(),
             previous_tlc: None,
+            payment_preimage: None,
+            idempotency_key: None,
         };
         let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
             NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
@@ -2684,6 +3299,8 @@ async fn do_test_add_tlc_value_limit() {
             onion_packet: None,
             shared_secret: NO_SHARED_SECRET.clone(),
             previous_tlc: None,
+            payment_preimage: None,
+            idempotency_key: None,
         };
         let add_tlc_result = call!(node_b.network_actor, |rpc_reply| {
             NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
@@ -2736,6 +3353,8 @@ async fn do_test_add_tlc_min_tlc_value_limit() {
         onion_packet: None,
         previous_tlc: None,
         shared_secret: NO_SHARED_SECRET.clone(),
+        payment_preimage: None,
+        idempotency_key: None,
     };
     let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
         NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
@@ -2760,6 +3379,8 @@ async fn do_test_add_tlc_min_tlc_value_limit() {
         onion_packet: None,
         previous_tlc: None,
         shared_secret: NO_SHARED_SECRET.clone(),
+        payment_preimage: None,
+        idempotency_key: None,
     };
     let add_tlc_result = call!(node_b.network_actor, |rpc_reply| {
         NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
@@ -2784,6 +3405,8 @@ async fn do_test_add_tlc_min_tlc_value_limit() {
         onion_packet: None,
         previous_tlc: None,
         shared_secret: NO_SHARED_SECRET.clone(),
+        payment_preimage: None,
+        idempotency_key: None,
     };
     let add_tlc_result = call!(node_b.network_actor, |rpc_reply| {
         NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
@@ -2834,6 +3457,8 @@ async fn test_channel_update_tlc_expiry() {
                         tlc_expiry_delta: Some(1000),
                         tlc_minimum_value: None,
                         tlc_fee_proportional_millionths: None,
+                        min_tlc_expiry_delta: None,
+                        max_tlc_expiry_delta: None,
                     },
                     rpc_reply,
                 ),
@@ -2858,6 +3483,8 @@ async fn test_channel_update_tlc_expiry() {
                         tlc_expiry_delta: Some(900000),
                         tlc_minimum_value: None,
                         tlc_fee_proportional_millionths: None,
+                        min_tlc_expiry_delta: None,
+                        max_tlc_expiry_delta: None,
                     },
                     rpc_reply,
                 ),
@@ -2868,6 +3495,112 @@ async fn test_channel_update_tlc_expiry() {
     assert!(update_result.is_ok());
 }
 
+#[tokio::test]
+async fn test_channel_update_tlc_expiry_with_channel_bounds() {
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, true).await;
+
+    let min_tlc_expiry_delta = 20 * 60 * 1000;
+    let max_tlc_expiry_delta = 2 * 24 * 60 * 60 * 1000;
+    for (update, expected_error) in [
+        (
+            UpdateCommand {
+                enabled: None,
+                tlc_expiry_delta: None,
+                tlc_minimum_value: None,
+                tlc_fee_proportional_millionths: None,
+                min_tlc_expiry_delta: Some(max_tlc_expiry_delta),
+                max_tlc_expiry_delta: Some(min_tlc_expiry_delta),
+            },
+            Some("is larger than the maximal one"),
+        ),
+        (
+            UpdateCommand {
+                enabled: None,
+                tlc_expiry_delta: None,
+                tlc_minimum_value: None,
+                tlc_fee_proportional_millionths: None,
+                min_tlc_expiry_delta: Some(min_tlc_expiry_delta),
+                max_tlc_expiry_delta: Some(max_tlc_expiry_delta),
+            },
+            None,
+        ),
+        (
+            UpdateCommand {
+                enabled: None,
+                tlc_expiry_delta: Some(900000),
+                tlc_minimum_value: None,
+                tlc_fee_proportional_millionths: None,
+                min_tlc_expiry_delta: None,
+                max_tlc_expiry_delta: None,
+            },
+            Some("TLC expiry delta is too small"),
+        ),
+        (
+            UpdateCommand {
+                enabled: None,
+                tlc_expiry_delta: Some(3 * 24 * 60 * 60 * 1000),
+                tlc_minimum_value: None,
+                tlc_fee_proportional_millionths: None,
+                min_tlc_expiry_delta: None,
+                max_tlc_expiry_delta: None,
+            },
+            Some("TLC expiry delta is too large"),
+        ),
+        (
+            UpdateCommand {
+                enabled: None,
+                tlc_expiry_delta: Some(24 * 60 * 60 * 1000),
+                tlc_minimum_value: None,
+                tlc_fee_proportional_millionths: None,
+                min_tlc_expiry_delta: None,
+                max_tlc_expiry_delta: None,
+            },
+            None,
+        ),
+        // Nothing is applied if any of the updates is invalid.
+        (
+            UpdateCommand {
+                enabled: None,
+                tlc_expiry_delta: Some(3 * 24 * 60 * 60 * 1000),
+                tlc_minimum_value: None,
+                tlc_fee_proportional_millionths: None,
+                min_tlc_expiry_delta: Some(MIN_TLC_EXPIRY_DELTA),
+                max_tlc_expiry_delta: None,
+            },
+            Some("TLC expiry delta is too large"),
+        ),
+    ] {
+        let update_result = call!(node_a.network_actor, |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: new_channel_id,
+                    command: ChannelCommand::Update(update, rpc_reply),
+                },
+            ))
+        })
+        .expect("node_a alive");
+        match expected_error {
+            Some(error) => assert!(update_result.unwrap_err().to_string().contains(error)),
+            None => assert!(update_result.is_ok()),
+        }
+    }
+
+    let public_channel_info = node_a
+        .get_channel_actor_state(new_channel_id)
+        .public_channel_info
+        .expect("public channel");
+    assert_eq!(
+        public_channel_info.min_tlc_expiry_delta,
+        Some(min_tlc_expiry_delta)
+    );
+    assert_eq!(
+        public_channel_info.max_tlc_expiry_delta,
+        Some(max_tlc_expiry_delta)
+    );
+    assert_eq!(public_channel_info.tlc_expiry_delta, 24 * 60 * 60 * 1000);
+}
+
 #[tokio::test]
 async fn test_channel_update_tlc_sync_up() {
     let node_a_funding_amount = 100000000000;
@@ -2914,6 +3647,7 @@ async fn test_channel_update_tlc_sync_up() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -2934,6 +3668,8 @@ async fn test_channel_update_tlc_sync_up() {
         onion_packet: None,
         previous_tlc: None,
         shared_secret: NO_SHARED_SECRET.clone(),
+        payment_preimage: None,
+        idempotency_key: None,
     };
     let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
         NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
@@ -2958,6 +3694,8 @@ async fn test_channel_update_tlc_sync_up() {
                         tlc_expiry_delta: None,
                         tlc_minimum_value: Some(100),
                         tlc_fee_proportional_millionths: None,
+                        min_tlc_expiry_delta: None,
+                        max_tlc_expiry_delta: None,
                     },
                     rpc_reply,
                 ),
@@ -2986,6 +3724,7 @@ async fn test_channel_update_tlc_sync_up() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -3008,6 +3747,8 @@ async fn test_channel_update_tlc_sync_up() {
         onion_packet: None,
         previous_tlc: None,
         shared_secret: NO_SHARED_SECRET.clone(),
+        payment_preimage: None,
+        idempotency_key: None,
     };
     let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
         NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
@@ -3040,6 +3781,7 @@ async fn test_channel_update_tlc_sync_up() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -3059,6 +3801,8 @@ async fn test_channel_update_tlc_sync_up() {
                         tlc_expiry_delta: None,
                         tlc_minimum_value: None,
                         tlc_fee_proportional_millionths: None,
+                        min_tlc_expiry_delta: None,
+                        max_tlc_expiry_delta: None,
                     },
                     rpc_reply,
                 ),
@@ -3085,6 +3829,7 @@ async fn test_channel_update_tlc_sync_up() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -3135,6 +3880,8 @@ async fn do_test_channel_with_simple_update_operation(algorithm: HashAlgorithm)
                         onion_packet: None,
                         shared_secret: NO_SHARED_SECRET.clone(),
                         previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
                     },
                     rpc_reply,
                 ),
@@ -3178,6 +3925,9 @@ async fn do_test_channel_with_simple_update_operation(algorithm: HashAlgorithm)
                         close_script: Script::default().as_builder().build(),
                         fee_rate,
                         force: false,
+                        allow_unknown_close_script: false,
+                        target_blocks: None,
+                        min_net_close_balance: None,
                     },
                     rpc_reply,
                 ),
@@ -3256,6 +4006,11 @@ async fn test_open_channel_with_invalid_ckb_amount_range() {
                 tlc_fee_proportional_millionths: None,
                 max_tlc_number_in_flight: None,
                 max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
             },
             rpc_reply,
         ))
@@ -3289,6 +4044,11 @@ async fn test_revoke_old_commitment_transaction() {
                 tlc_fee_proportional_millionths: None,
                 max_tlc_number_in_flight: None,
                 max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
             },
             rpc_reply,
         ))
@@ -3318,6 +4078,8 @@ async fn test_revoke_old_commitment_transaction() {
                 min_tlc_value: None,
                 tlc_fee_proportional_millionths: None,
                 tlc_expiry_delta: None,
+                commitment_delay_epoch: None,
+                channel_reserve_amount: None,
             },
             rpc_reply,
         ))
@@ -3463,6 +4225,11 @@ async fn test_create_channel() {
                 tlc_fee_proportional_millionths: None,
                 max_tlc_number_in_flight: None,
                 max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
             },
             rpc_reply,
         ))
@@ -3492,6 +4259,8 @@ async fn test_create_channel() {
                 min_tlc_value: None,
                 tlc_fee_proportional_millionths: None,
                 tlc_expiry_delta: None,
+                commitment_delay_epoch: None,
+                channel_reserve_amount: None,
             },
             rpc_reply,
         ))
@@ -3592,6 +4361,11 @@ async fn test_reestablish_channel() {
                 tlc_fee_proportional_millionths: None,
                 max_tlc_number_in_flight: None,
                 max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
             },
             rpc_reply,
         ))
@@ -3622,6 +4396,8 @@ async fn test_reestablish_channel() {
                 min_tlc_value: None,
                 tlc_fee_proportional_millionths: None,
                 tlc_expiry_delta: None,
+                commitment_delay_epoch: None,
+                channel_reserve_amount: None,
             },
             rpc_reply,
         ))
@@ -3708,14 +4484,302 @@ async fn test_reestablish_channel() {
 }
 
 #[tokio::test]
-async fn test_force_close_channel_when_remote_is_offline() {
-    let (mut node_a, mut node_b, channel_id, _) =
-        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
-
-    node_b.stop().await;
-    node_a
-        .expect_event(|event| matches!(event, NetworkServiceEvent::PeerDisConnected(_, _)))
-        .await;
+async fn test_resume_channel_after_peer_reconnected_within_grace_period() {
+    init_tracing();
+
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(|config| {
+                config.peer_disconnect_grace_seconds = Some(60);
+            })
+            .build()
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let mut node_a = nodes.pop().unwrap();
+    let (new_channel_id, _funding_tx) = establish_channel_between_nodes(
+        &mut node_a,
+        &mut node_b,
+        false,
+        100000000000,
+        6200000000,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await;
+
+    node_a
+        .network_actor
+        .send_message(NetworkActorMessage::new_command(
+            NetworkActorCommand::DisconnectPeer(node_b.peer_id.clone()),
+        ))
+        .expect("node_a alive");
+    node_a
+        .expect_event(|event| matches!(event, NetworkServiceEvent::PeerDisConnected(_, _)))
+        .await;
+    node_b
+        .expect_event(|event| matches!(event, NetworkServiceEvent::PeerDisConnected(_, _)))
+        .await;
+
+    // Don't use `connect_to` here as that may consume the `ChannelCreated` event.
+    node_a.connect_to_nonblocking(&node_b).await;
+    for (node, peer_id) in [
+        (&mut node_a, node_b.peer_id.clone()),
+        (&mut node_b, node_a.peer_id.clone()),
+    ] {
+        node.expect_event(|event| {
+            matches!(event, NetworkServiceEvent::ChannelCreated(id, channel_id)
+                if id == &peer_id && channel_id == &new_channel_id)
+        })
+        .await;
+    }
+    // Wait for the channel to be reestablished.
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(
+        !node_a
+            .get_channel_actor_state(new_channel_id)
+            .reestablishing
+    );
+
+    // The resumed channel is still usable.
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 10000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: gen_rand_sha256_hash(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully added tlc");
+}
+
+#[tokio::test]
+async fn test_reuse_cached_commitment_signatures() {
+    let (node_a, _node_b, channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    let mut state = node_a.get_channel_actor_state(channel_id);
+    state.commitment_signed_cache = None;
+    let signatures = state
+        .build_and_sign_commitment_tx()
+        .expect("sign commitment tx");
+    let cache = state
+        .commitment_signed_cache
+        .clone()
+        .expect("commitment signatures cached");
+    assert_eq!(cache.commitment_number, state.get_remote_commitment_number());
+
+    // Resending the same commitment transaction reuses the cached signatures,
+    // which are the same as the ones signed from scratch.
+    assert_eq!(
+        state
+            .build_and_sign_commitment_tx()
+            .expect("sign commitment tx"),
+        signatures
+    );
+    assert_eq!(state.commitment_signed_cache, Some(cache.clone()));
+
+    // Changing the balances invalidates the cache.
+    state.to_local_amount -= 1000;
+    state.to_remote_amount += 1000;
+    let new_signatures = state
+        .build_and_sign_commitment_tx()
+        .expect("sign commitment tx");
+    assert_ne!(new_signatures, signatures);
+    let new_cache = state
+        .commitment_signed_cache
+        .clone()
+        .expect("commitment signatures cached");
+    assert_ne!(new_cache.fingerprint, cache.fingerprint);
+}
+
+#[tokio::test]
+async fn test_update_commitment_fee_rate() {
+    init_tracing();
+
+    let (node_a, node_b, channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    let update_fee_rate = |fee_rate: u64| {
+        let network_actor = node_a.network_actor.clone();
+        async move {
+            call!(network_actor, |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                    ChannelCommandWithId {
+                        channel_id,
+                        command: ChannelCommand::UpdateFeeRate(
+                            FeeRate::from_u64(fee_rate),
+                            rpc_reply,
+                        ),
+                    },
+                ))
+            })
+            .expect("node_a alive")
+        }
+    };
+
+    assert!(update_fee_rate(DEFAULT_COMMITMENT_FEE_RATE - 1).await.is_err());
+
+    let new_fee_rate = DEFAULT_COMMITMENT_FEE_RATE * 2;
+    update_fee_rate(new_fee_rate)
+        .await
+        .expect("update fee rate success");
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    for node in [&node_a, &node_b] {
+        let state = node.get_channel_actor_state(channel_id);
+        assert_eq!(state.commitment_fee_rate, new_fee_rate);
+        assert_eq!(state.pending_commitment_fee_rate, None);
+    }
+
+    // Both parties sign the commitment txs with the new fee rate.
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 10000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: gen_rand_sha256_hash(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully added tlc");
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let state = node_b.get_channel_actor_state(channel_id);
+    assert_eq!(state.get_all_received_tlcs().count(), 1);
+
+    // The fee rate can't be updated while there are tlcs in flight.
+    assert!(update_fee_rate(new_fee_rate * 2).await.is_err());
+}
+
+#[tokio::test]
+async fn test_force_close_after_update_commitment_fee_rate() {
+    init_tracing();
+
+    let (mut node_a, node_b, channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    let commitment_output_capacity = |node: &NetworkNode| -> u64 {
+        node.get_channel_actor_state(channel_id)
+            .latest_commitment_transaction
+            .expect("commitment tx exists")
+            .into_view()
+            .output(0)
+            .expect("commitment output exists")
+            .capacity()
+            .unpack()
+    };
+    let capacities = [
+        commitment_output_capacity(&node_a),
+        commitment_output_capacity(&node_b),
+    ];
+
+    let new_fee_rate = DEFAULT_COMMITMENT_FEE_RATE * 2;
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id,
+                command: ChannelCommand::UpdateFeeRate(
+                    FeeRate::from_u64(new_fee_rate),
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("update fee rate success");
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // Both parties have re-signed the commitment txs of their peers with the new fee rate.
+    let fee_increment = calculate_commitment_tx_fee(new_fee_rate, &None, false)
+        - calculate_commitment_tx_fee(DEFAULT_COMMITMENT_FEE_RATE, &None, false);
+    for (node, capacity) in [&node_a, &node_b].into_iter().zip(capacities) {
+        assert_eq!(commitment_output_capacity(node), capacity - fee_increment);
+        let state = node.get_channel_actor_state(channel_id);
+        assert!(!state.tlc_state.dump().waiting_ack);
+    }
+
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id,
+                command: ChannelCommand::Shutdown(
+                    ShutdownCommand {
+                        close_script: Script::default(),
+                        fee_rate: FeeRate::from_u64(1000),
+                        force: true,
+                        allow_unknown_close_script: false,
+                        target_blocks: None,
+                        min_net_close_balance: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully force close channel");
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // The broadcasted commitment tx pays the new fee rate and is accepted by the chain.
+    let commitment_tx = node_a
+        .get_channel_actor_state(channel_id)
+        .latest_commitment_transaction
+        .expect("commitment tx exists")
+        .into_view();
+    let capacity: u64 = commitment_tx
+        .output(0)
+        .expect("commitment output exists")
+        .capacity()
+        .unpack();
+    assert_eq!(capacity, capacities[0] - fee_increment);
+    assert_eq!(node_a.trace_tx(commitment_tx).await, Status::Committed);
+}
+
+#[tokio::test]
+async fn test_force_close_channel_when_remote_is_offline() {
+    let (mut node_a, mut node_b, channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    node_b.stop().await;
+    node_a
+        .expect_event(|event| matches!(event, NetworkServiceEvent::PeerDisConnected(_, _)))
+        .await;
 
     let message = |rpc_reply| -> NetworkActorMessage {
         NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
@@ -3726,6 +4790,9 @@ async fn test_force_close_channel_when_remote_is_offline() {
                         close_script: Script::default(),
                         fee_rate: FeeRate::from_u64(1000),
                         force: true,
+                        allow_unknown_close_script: false,
+                        target_blocks: None,
+                        min_net_close_balance: None,
                     },
                     rpc_reply,
                 ),
@@ -3830,6 +4897,11 @@ async fn test_open_channel_with_large_size_shutdown_script_should_fail() {
                 tlc_fee_proportional_millionths: None,
                 max_tlc_number_in_flight: None,
                 max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
             },
             rpc_reply,
         ))
@@ -3879,6 +4951,11 @@ async fn test_accept_channel_with_large_size_shutdown_script_should_fail() {
                 tlc_fee_proportional_millionths: None,
                 max_tlc_number_in_flight: None,
                 max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
             },
             rpc_reply,
         ))
@@ -3934,6 +5011,9 @@ async fn test_shutdown_channel_with_large_size_shutdown_script_should_fail() {
                         close_script: Script::new_builder().args(vec![0u8; 21].pack()).build(),
                         fee_rate: FeeRate::from_u64(DEFAULT_COMMITMENT_FEE_RATE),
                         force: false,
+                        allow_unknown_close_script: false,
+                        target_blocks: None,
+                        min_net_close_balance: None,
                     },
                     rpc_reply,
                 ),
@@ -3942,9 +5022,13 @@ async fn test_shutdown_channel_with_large_size_shutdown_script_should_fail() {
     };
 
     let shutdown_channel_result = call!(node_b.network_actor, message).expect("node_b alive");
-    assert!(shutdown_channel_result
-        .err()
-        .unwrap()
+    let err = shutdown_channel_result.err().unwrap();
+    assert_eq!(
+        err.code,
+        ProcessingChannelError::InvalidParameter(String::new()).code()
+    );
+    assert!(err
+        .message
         .contains("Local balance is not enough to pay the fee"));
 }
 
@@ -3966,6 +5050,9 @@ async fn test_shutdown_channel_with_different_size_shutdown_script() {
                         close_script: Script::new_builder().args(vec![0u8; 19].pack()).build(),
                         fee_rate: FeeRate::from_u64(DEFAULT_COMMITMENT_FEE_RATE),
                         force: false,
+                        allow_unknown_close_script: false,
+                        target_blocks: None,
+                        min_net_close_balance: None,
                     },
                     rpc_reply,
                 ),
@@ -4077,6 +5164,9 @@ async fn test_shutdown_channel_network_graph_with_sync_up() {
                         close_script: Script::new_builder().args(vec![0u8; 19].pack()).build(),
                         fee_rate: FeeRate::from_u64(DEFAULT_COMMITMENT_FEE_RATE),
                         force: false,
+                        allow_unknown_close_script: false,
+                        target_blocks: None,
+                        min_net_close_balance: None,
                     },
                     rpc_reply,
                 ),
@@ -4134,6 +5224,7 @@ async fn test_send_payment_with_channel_balance_error() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -4171,6 +5262,7 @@ async fn test_send_payment_with_channel_balance_error() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -4233,6 +5325,7 @@ async fn test_send_payment_with_disable_channel() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -4300,6 +5393,7 @@ async fn test_send_payment_with_multiple_edges_in_middle_hops() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -4367,6 +5461,7 @@ async fn test_send_payment_with_all_failed_middle_hops() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -4434,6 +5529,7 @@ async fn test_send_payment_with_multiple_edges_can_succeed_in_retry() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -4501,6 +5597,7 @@ async fn test_send_payment_with_final_hop_multiple_edges_in_middle_hops() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -4568,6 +5665,7 @@ async fn test_send_payment_with_final_all_failed_middle_hops() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -4626,6 +5724,7 @@ async fn test_send_payment_with_final_multiple_edges_can_succeed_in_retry() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -4682,6 +5781,7 @@ async fn test_send_payment_with_first_hop_failed_with_fee() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -4734,6 +5834,7 @@ async fn test_send_payment_succeed_with_multiple_edges_in_first_hop() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -4791,6 +5892,7 @@ async fn test_send_payment_with_first_hop_all_failed() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -4851,6 +5953,7 @@ async fn test_send_payment_will_succeed_with_direct_channel_info_first_hop() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -4915,6 +6018,7 @@ async fn test_send_payment_will_succeed_with_retry_in_middle_hops() {
                 udt_type_script: None,
                 allow_self_payment: false,
                 dry_run: false,
+                custom_records: vec![],
             },
             rpc_reply,
         ))
@@ -4980,6 +6084,7 @@ async fn test_send_payment_will_fail_with_last_hop_info_in_add_tlc_peer() {
             udt_type_script: None,
             allow_self_payment: false,
             dry_run: false,
+            custom_records: vec![],
         })
         .await;
 
@@ -5051,6 +6156,7 @@ async fn test_send_payment_will_fail_with_invoice_not_generated_by_target() {
             udt_type_script: None,
             allow_self_payment: false,
             dry_run: false,
+            custom_records: vec![],
         })
         .await;
 
@@ -5115,6 +6221,7 @@ async fn test_send_payment_will_succeed_with_valid_invoice() {
             udt_type_script: None,
             allow_self_payment: false,
             dry_run: false,
+            custom_records: vec![],
         })
         .await;
 
@@ -5186,6 +6293,7 @@ async fn test_send_payment_will_fail_with_no_invoice_preimage() {
             udt_type_script: None,
             allow_self_payment: false,
             dry_run: false,
+            custom_records: vec![],
         })
         .await;
 
@@ -5260,6 +6368,7 @@ async fn test_send_payment_will_fail_with_cancelled_invoice() {
             udt_type_script: None,
             allow_self_payment: false,
             dry_run: false,
+            custom_records: vec![],
         })
         .await;
 
@@ -5319,6 +6428,7 @@ async fn test_send_payment_will_succeed_with_large_tlc_expiry_limit() {
             udt_type_script: None,
             allow_self_payment: false,
             dry_run: false,
+            custom_records: vec![],
         })
         .await;
 
@@ -5339,6 +6449,7 @@ async fn test_send_payment_will_succeed_with_large_tlc_expiry_limit() {
             udt_type_script: None,
             allow_self_payment: false,
             dry_run: false,
+            custom_records: vec![],
         })
         .await;
 
@@ -5351,3 +6462,6304 @@ async fn test_send_payment_will_succeed_with_large_tlc_expiry_limit() {
         .assert_payment_status(payment_hash, PaymentSessionStatus::Success, Some(1))
         .await;
 }
+
+#[tokio::test]
+async fn test_channel_balance_history_after_fulfill_tlc() {
+    init_tracing();
+
+    let node_a_funding_amount = 100000000000;
+    let node_b_funding_amount = 6200000000;
+
+    let (node_a, node_b, new_channel_id, _) = NetworkNode::new_2_nodes_with_established_channel(
+        node_a_funding_amount,
+        node_b_funding_amount,
+        false,
+    )
+    .await;
+
+    let initial_state = node_a.get_channel_actor_state(new_channel_id);
+    let initial_history_len = initial_state.balance_history.len();
+
+    let preimage = [1; 32];
+    let digest = HashAlgorithm::CkbHash.hash(&preimage);
+    let tlc_amount = 1000000000;
+
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: tlc_amount,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: digest.into(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("successfully added tlc");
+
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::RemoveTlc(
+                    RemoveTlcCommand {
+                        id: add_tlc_result.tlc_id,
+                        reason: RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
+                            payment_preimage: preimage.into(),
+                        }),
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("successfully removed tlc");
+
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    let history = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::GetBalanceHistory(rpc_reply),
+            },
+        ))
+    })
+    .expect("node_a alive");
+
+    assert!(history.len() > initial_history_len);
+    let last = history.last().expect("balance history is not empty");
+    assert_eq!(last.to_local_amount, initial_state.to_local_amount - tlc_amount);
+    assert_eq!(last.to_remote_amount, initial_state.to_remote_amount + tlc_amount);
+
+    let history = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::GetBalanceHistory(rpc_reply),
+            },
+        ))
+    })
+    .expect("node_b alive");
+    let last = history.last().expect("balance history is not empty");
+    assert_eq!(last.to_local_amount, initial_state.to_remote_amount + tlc_amount);
+    assert_eq!(last.to_remote_amount, initial_state.to_local_amount - tlc_amount);
+}
+
+#[test]
+fn test_channel_command_error_code() {
+    let waiting_ack: ChannelCommandError = ProcessingChannelError::WaitingTlcAck.into();
+    let invalid_state: ChannelCommandError =
+        ProcessingChannelError::InvalidState("channel is not ready".to_string()).into();
+    assert_ne!(waiting_ack.code, invalid_state.code);
+    assert_eq!(
+        waiting_ack.code,
+        ProcessingChannelError::WaitingTlcAck.code()
+    );
+    assert_eq!(
+        invalid_state.to_string(),
+        "Invalid state: channel is not ready"
+    );
+}
+
+#[tokio::test]
+async fn test_channel_funding_address() {
+    init_tracing();
+
+    let (node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    let mut addresses = vec![];
+    for node in [&node_a, &node_b] {
+        let address = call!(node.network_actor, |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: new_channel_id,
+                    command: ChannelCommand::GetFundingAddress(NetworkType::Testnet, rpc_reply),
+                },
+            ))
+        })
+        .expect("node alive");
+        addresses.push(address);
+    }
+    assert_eq!(addresses[0], addresses[1]);
+    assert!(addresses[0].starts_with("ckt"));
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    let address: Address = addresses[0].parse().expect("valid address");
+    assert_eq!(
+        Script::from(address.payload()),
+        state.get_funding_lock_script()
+    );
+}
+
+#[tokio::test]
+async fn test_channel_tlc_capacity_status() {
+    init_tracing();
+
+    let (node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    let get_tlc_capacity_status = |node: &NetworkNode| {
+        let network_actor = node.network_actor.clone();
+        async move {
+            call!(network_actor, |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                    ChannelCommandWithId {
+                        channel_id: new_channel_id,
+                        command: ChannelCommand::GetTlcCapacityStatus(rpc_reply),
+                    },
+                ))
+            })
+            .expect("node alive")
+        }
+    };
+
+    let status_before = get_tlc_capacity_status(&node_a).await;
+    assert_eq!(status_before.offered_tlc_count, 0);
+
+    let tlc_amount = 1000000000;
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: tlc_amount,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: gen_rand_sha256_hash(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("successfully added tlc");
+
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    let status_a = get_tlc_capacity_status(&node_a).await;
+    assert_eq!(status_a.offered_tlc_count, 1);
+    assert_eq!(
+        status_a.offered_remaining_slots,
+        status_before.offered_remaining_slots - 1
+    );
+    assert_eq!(
+        status_a.offered_remaining_value_in_flight,
+        status_before.offered_remaining_value_in_flight - tlc_amount
+    );
+
+    let status_b = get_tlc_capacity_status(&node_b).await;
+    assert_eq!(status_b.received_tlc_count, 1);
+    assert_eq!(status_b.offered_tlc_count, 0);
+}
+
+#[tokio::test]
+async fn test_channel_ack_state_event() {
+    init_tracing();
+
+    let (mut node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: gen_rand_sha256_hash(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("successfully added tlc");
+
+    node_a
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelAckState(channel_id, waiting) => {
+                *channel_id == new_channel_id && *waiting
+            }
+            _ => false,
+        })
+        .await;
+
+    node_a
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelAckState(channel_id, waiting) => {
+                *channel_id == new_channel_id && !*waiting
+            }
+            _ => false,
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_add_tlc_with_inline_payment_preimage() {
+    init_tracing();
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    let preimage = gen_rand_sha256_hash();
+    let payment_hash: Hash256 = HashAlgorithm::CkbHash.hash(preimage).into();
+
+    for (payment_hash, should_succeed) in [(gen_rand_sha256_hash(), false), (payment_hash, true)] {
+        let result = call!(node_a.network_actor, |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: new_channel_id,
+                    command: ChannelCommand::AddTlc(
+                        AddTlcCommand {
+                            amount: 1000000000,
+                            hash_algorithm: HashAlgorithm::CkbHash,
+                            payment_hash,
+                            expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                            onion_packet: None,
+                            shared_secret: NO_SHARED_SECRET.clone(),
+                            previous_tlc: None,
+                            payment_preimage: Some(preimage),
+                            idempotency_key: None,
+                        },
+                        rpc_reply,
+                    ),
+                },
+            ))
+        })
+        .expect("node_b alive");
+        assert_eq!(result.is_ok(), should_succeed);
+    }
+
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    let tlc = state
+        .get_all_offer_tlcs()
+        .find(|tlc| tlc.payment_hash == payment_hash)
+        .expect("tlc added");
+    assert_eq!(tlc.payment_preimage, Some(preimage));
+}
+
+#[tokio::test]
+async fn test_channel_drain_and_disable() {
+    init_tracing();
+
+    let (mut node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, true).await;
+
+    let status = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::DrainAndDisable(rpc_reply),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert!(status.is_drained());
+
+    node_a
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelDrained(channel_id) => *channel_id == new_channel_id,
+            _ => false,
+        })
+        .await;
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    assert!(state.draining);
+    assert_eq!(
+        state.public_channel_info.map(|info| info.enabled),
+        Some(false)
+    );
+}
+
+#[tokio::test]
+async fn test_retryable_relay_remove_tlc_replayed_after_restart() {
+    init_tracing();
+
+    let (node_a, mut node_b, _node_c, channel_1, channel_2) =
+        create_3_nodes_with_established_channel(
+            (100000000000, 100000000000),
+            (100000000000, 100000000000),
+            true,
+        )
+        .await;
+
+    let preimage = gen_rand_sha256_hash();
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: channel_1,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: HashAlgorithm::CkbHash.hash(preimage).into(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("tlc added");
+
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    // Queue a relayed remove on the outgoing channel while node_b is down,
+    // just like a failure that was relayed back right before a crash.
+    node_b.stop().await;
+    let mut state = node_b.get_channel_actor_state(channel_2);
+    state.tlc_state.insert_relay_tlc_remove(
+        channel_1,
+        add_tlc_result.tlc_id,
+        RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
+            payment_preimage: preimage,
+        }),
+    );
+    node_b.store.insert_channel_actor_state(state);
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    node_b.start().await;
+
+    // Wait for the reestablishment and the scheduled retry to finish.
+    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+
+    let state = node_b.get_channel_actor_state(channel_2);
+    assert!(state.tlc_state.get_pending_remove().is_empty());
+
+    let state = node_a.get_channel_actor_state(channel_1);
+    assert!(
+        state
+            .get_all_offer_tlcs()
+            .all(|tlc| tlc.tlc_id != TLCId::Offered(add_tlc_result.tlc_id)
+                || tlc.removed_at.is_some())
+    );
+}
+
+#[tokio::test]
+async fn test_update_shutdown_script() {
+    init_tracing();
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, true).await;
+
+    // the reserved ckb amount can not cover a shutdown script with such large args
+    let large_script = Script::new_builder().args(vec![0u8; 200].pack()).build();
+    let result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::UpdateShutdownScript(large_script, rpc_reply),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert_eq!(
+        result.unwrap_err().code,
+        ProcessingChannelError::InvalidParameter(String::new()).code()
+    );
+
+    let new_script = Script::new_builder().args(vec![1u8; 20].pack()).build();
+    let result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::UpdateShutdownScript(new_script.clone(), rpc_reply),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert!(result.is_ok());
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    assert_eq!(state.local_shutdown_script, new_script);
+}
+
+#[tokio::test]
+async fn test_bump_force_close_fee_in_channel_ready_state_should_fail() {
+    init_tracing();
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    let result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::BumpForceCloseFee(FeeRate::from_u64(2000), rpc_reply),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("Bumping force close fee while in invalid state"));
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    assert!(state.pending_force_close_tx_hashes.is_empty());
+}
+
+#[tokio::test]
+async fn test_bump_force_close_fee_with_anchor_output() {
+    init_tracing();
+
+    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    // The anchor output is locked by the shutdown script, which always succeeds in the mock chain.
+    let shutdown_script = get_script_by_contract(Contract::Secp256k1Lock, &[0u8; 20]);
+    let open_channel_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: Some(shutdown_script.clone()),
+                funding_amount: 16200000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: true,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_a alive")
+    .expect("open channel success");
+
+    node_b
+        .expect_event(|event| {
+            matches!(event, NetworkServiceEvent::ChannelPendingToBeAccepted(_, _))
+        })
+        .await;
+
+    let accept_channel_result = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id: open_channel_result.channel_id,
+                funding_amount: 16200000000,
+                shutdown_script: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                min_tlc_value: None,
+                tlc_fee_proportional_millionths: None,
+                tlc_expiry_delta: None,
+                commitment_delay_epoch: None,
+                channel_reserve_amount: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_b alive")
+    .expect("accept channel success");
+    let new_channel_id = accept_channel_result.new_channel_id;
+
+    node_a
+        .expect_event(|event| matches!(event, NetworkServiceEvent::ChannelReady(_, _, _)))
+        .await;
+
+    // Broadcast the commitment transaction as a force close does, and wait for its confirmation.
+    let mut state = node_a.get_channel_actor_state(new_channel_id);
+    assert_eq!(state.local_shutdown_script, shutdown_script);
+    let commitment_tx = state
+        .latest_commitment_transaction
+        .clone()
+        .expect("commitment tx exists")
+        .into_view();
+    assert_eq!(
+        node_a.submit_tx(commitment_tx.clone()).await,
+        Status::Committed
+    );
+    state.state =
+        ChannelState::ShuttingDown(ShuttingDownFlags::WAITING_COMMITMENT_CONFIRMATION);
+    node_a.update_channel_actor_state(state.clone()).await;
+
+    let fee_rate = state.commitment_fee_rate * 3;
+    let result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::BumpForceCloseFee(
+                    FeeRate::from_u64(fee_rate),
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert!(result.is_ok());
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let commitment_tx_hash: Hash256 = commitment_tx.hash().into();
+    let child_tx_hash = node_a
+        .get_channel_actor_state(new_channel_id)
+        .pending_force_close_tx_hashes
+        .into_iter()
+        .find(|tx_hash| *tx_hash != commitment_tx_hash)
+        .expect("fee bump tx tracked");
+    let child_tx = node_a
+        .get_tx_from_hash(child_tx_hash.into())
+        .await
+        .expect("fee bump tx broadcasted");
+    assert_eq!(node_a.trace_tx(child_tx.clone()).await, Status::Committed);
+    let anchor_outpoint = OutPoint::new(commitment_tx.hash(), LOCAL_ANCHOR_OUTPUT_INDEX);
+    assert!(child_tx
+        .input_pts_iter()
+        .any(|outpoint| outpoint == anchor_outpoint));
+
+    // The parent and the child as a package pay the requested fee rate.
+    let capacity_sum = |outputs: Vec<CellOutput>| -> u64 {
+        outputs
+            .into_iter()
+            .map(|output| -> u64 { output.capacity().unpack() })
+            .sum()
+    };
+    let funding_capacity: u64 = state
+        .funding_tx
+        .as_ref()
+        .and_then(|tx| tx.raw().outputs().get(0))
+        .expect("funding output exists")
+        .capacity()
+        .unpack();
+    let parent_fee =
+        funding_capacity - capacity_sum(commitment_tx.outputs().into_iter().collect());
+    let child_inputs = {
+        let mock_context = MOCK_CONTEXT.read().unwrap();
+        child_tx
+            .input_pts_iter()
+            .map(|outpoint| {
+                mock_context
+                    .context
+                    .get_cell(&outpoint)
+                    .expect("input cell exists")
+                    .0
+            })
+            .collect()
+    };
+    let child_fee =
+        capacity_sum(child_inputs) - capacity_sum(child_tx.outputs().into_iter().collect());
+    let package_size = (commitment_tx.data().serialized_size_in_block()
+        + child_tx.data().serialized_size_in_block()) as u64;
+    assert!(child_fee > 0);
+    assert!((parent_fee + child_fee) * 1000 / package_size >= fee_rate);
+}
+
+#[tokio::test]
+async fn test_reject_invalid_announcement_signatures() {
+    init_tracing();
+
+    let (node_a, mut node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    let state = node_b.get_channel_actor_state(new_channel_id);
+    let (node_signature, partial_signature) = state
+        .public_channel_info
+        .as_ref()
+        .and_then(|info| info.remote_channel_announcement_signature.clone())
+        .expect("remote announcement signatures received");
+
+    // the node signature is signed by a key other than node_a's node key
+    let forged_node_signature = gen_rand_fiber_private_key().sign([1u8; 32]);
+    node_a
+        .network_actor
+        .send_message(NetworkActorMessage::new_command(
+            NetworkActorCommand::SendFiberMessage(FiberMessageWithPeerId::new(
+                node_b.peer_id.clone(),
+                FiberMessage::announcement_signatures(AnnouncementSignatures {
+                    channel_id: new_channel_id,
+                    channel_outpoint: state.must_get_funding_transaction_outpoint(),
+                    node_signature: forged_node_signature,
+                    partial_signature,
+                }),
+            )),
+        ))
+        .expect("node_a alive");
+
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::DebugEvent(DebugEvent::Common(error)) => {
+                error.contains("Musig2VerifyError(BadSignature)")
+            }
+            _ => false,
+        })
+        .await;
+
+    // the valid signatures received before are kept
+    let state = node_b.get_channel_actor_state(new_channel_id);
+    assert_eq!(
+        state
+            .public_channel_info
+            .and_then(|info| info.remote_channel_announcement_signature),
+        Some((node_signature, partial_signature))
+    );
+}
+
+#[tokio::test]
+async fn test_get_latest_commitment_tx() {
+    init_tracing();
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    let info = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::GetLatestCommitmentTx(rpc_reply),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("latest commitment tx exists");
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    let latest_commitment_tx = state.latest_commitment_transaction.unwrap().into_view();
+    assert_eq!(info.transaction.hash(), latest_commitment_tx.hash());
+    assert_eq!(info.settlement_data, state.latest_settlement_data);
+}
+
+#[tokio::test]
+async fn test_get_settlement_data_at_commitment_number() {
+    init_tracing();
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    let preimage = [2; 32];
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: HashAlgorithm::CkbHash.hash(&preimage).into(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert!(add_tlc_result.is_ok());
+
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    // The settlement data of the remote commitments are signed in the RevokeAndAck messages,
+    // the revoked commitment number is the one before our current local commitment number.
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    let settled_commitment_number = state.get_local_commitment_number() - 1;
+    assert!(settled_commitment_number > 0);
+    let older_settlement_data = node_a
+        .store
+        .get_settlement_data(&new_channel_id, false, 0)
+        .expect("settlement data of the initial commitment recorded");
+    // Our own commitment is signed by the peer in the CommitmentSigned messages.
+    let local_commitment_number = state
+        .get_latest_commitment_tx_info()
+        .expect("commitment tx exists")
+        .commitment_number;
+    let local_settlement_data = node_a
+        .store
+        .get_settlement_data(&new_channel_id, true, local_commitment_number);
+    assert!(local_settlement_data.is_some());
+    for (commitment_number, local, expected) in [
+        (
+            settled_commitment_number,
+            false,
+            state.latest_settlement_data.clone(),
+        ),
+        (0, false, Some(older_settlement_data)),
+        (local_commitment_number, true, local_settlement_data),
+        (u64::MAX, true, None),
+    ] {
+        let settlement_data = call!(node_a.network_actor, |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: new_channel_id,
+                    command: ChannelCommand::GetSettlementDataAt(
+                        commitment_number,
+                        local,
+                        rpc_reply,
+                    ),
+                },
+            ))
+        })
+        .expect("node_a alive");
+        assert_eq!(settlement_data, expected);
+    }
+}
+
+#[tokio::test]
+async fn test_prune_remote_commitment_points_with_live_tlc() {
+    init_tracing();
+
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(|config| {
+                config.max_remote_commitment_points = Some(3);
+            })
+            .build()
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let mut node_a = nodes.pop().unwrap();
+    let (new_channel_id, _funding_tx) = establish_channel_between_nodes(
+        &mut node_a,
+        &mut node_b,
+        false,
+        100000000000,
+        6200000000,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await;
+
+    let preimages: Vec<[u8; 32]> = (0..10u8).map(|i| [i + 1; 32]).collect();
+    let mut tlc_ids = vec![];
+    for preimage in &preimages {
+        let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: new_channel_id,
+                    command: ChannelCommand::AddTlc(
+                        AddTlcCommand {
+                            amount: 1000000000,
+                            hash_algorithm: HashAlgorithm::CkbHash,
+                            payment_hash: HashAlgorithm::CkbHash.hash(preimage).into(),
+                            expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                            onion_packet: None,
+                            shared_secret: NO_SHARED_SECRET.clone(),
+                            previous_tlc: None,
+                            payment_preimage: None,
+                            idempotency_key: None,
+                        },
+                        rpc_reply,
+                    ),
+                },
+            ))
+        })
+        .expect("node_a alive")
+        .expect("successfully added tlc");
+        tlc_ids.push(add_tlc_result.tlc_id);
+        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    }
+
+    // keep the first tlc live while the others are fulfilled one by one
+    for (tlc_id, preimage) in tlc_ids.iter().zip(preimages.iter()).skip(1) {
+        call!(node_b.network_actor, |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: new_channel_id,
+                    command: ChannelCommand::RemoveTlc(
+                        RemoveTlcCommand {
+                            id: *tlc_id,
+                            reason: RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
+                                payment_preimage: (*preimage).into(),
+                            }),
+                        },
+                        rpc_reply,
+                    ),
+                },
+            ))
+        })
+        .expect("node_b alive")
+        .expect("successfully removed tlc");
+        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    }
+
+    for node in [&node_a, &node_b] {
+        let state = node.get_channel_actor_state(new_channel_id);
+        // the live tlc needs a point besides the latest two
+        assert!(state.remote_commitment_points.len() <= 3);
+        for tlc in state.tlc_state.all_tlcs() {
+            assert!(state
+                .remote_commitment_points
+                .iter()
+                .any(|(num, _)| *num == tlc.created_at.local));
+        }
+    }
+
+    // the live tlc can still be removed with the retained points
+    call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::RemoveTlc(
+                    RemoveTlcCommand {
+                        id: tlc_ids[0],
+                        reason: RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
+                            payment_preimage: preimages[0].into(),
+                        }),
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("successfully removed tlc");
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    assert!(state.remote_commitment_points.len() <= 3);
+}
+
+#[tokio::test]
+async fn test_add_tlc_below_channel_reserve_should_fail() {
+    init_tracing();
+
+    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    let open_channel_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 16200000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                channel_reserve_amount: Some(5000000000),
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_a alive")
+    .expect("open channel success");
+
+    node_b
+        .expect_event(|event| {
+            matches!(event, NetworkServiceEvent::ChannelPendingToBeAccepted(_, _))
+        })
+        .await;
+
+    let accept_channel_result = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id: open_channel_result.channel_id,
+                funding_amount: 6200000000,
+                shutdown_script: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                min_tlc_value: None,
+                tlc_fee_proportional_millionths: None,
+                tlc_expiry_delta: None,
+                commitment_delay_epoch: None,
+                channel_reserve_amount: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_b alive")
+    .expect("accept channel success");
+    let new_channel_id = accept_channel_result.new_channel_id;
+
+    node_a
+        .expect_event(|event| matches!(event, NetworkServiceEvent::ChannelReady(_, _, _)))
+        .await;
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    assert_eq!(state.local_constraints.channel_reserve_amount, 5000000000);
+    assert_eq!(state.to_local_amount, 10000000000);
+
+    // Offering this tlc would leave 4000000000 in the channel, lower than the reserve.
+    let add_tlc_command = |amount| AddTlcCommand {
+        amount,
+        hash_algorithm: HashAlgorithm::Sha256,
+        payment_hash: gen_rand_sha256_hash(),
+        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+        onion_packet: None,
+        shared_secret: NO_SHARED_SECRET.clone(),
+        previous_tlc: None,
+        payment_preimage: None,
+        idempotency_key: None,
+    };
+    let result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(add_tlc_command(6000000000), rpc_reply),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert!(result.is_err());
+
+    let result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(add_tlc_command(5000000000), rpc_reply),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+async fn test_channel_metrics_of_forwarded_payment() {
+    init_tracing();
+
+    let (node_a, node_b, node_c, channel_1, channel_2) = create_3_nodes_with_established_channel(
+        (100000000000, 100000000000),
+        (100000000000, 100000000000),
+        true,
+    )
+    .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let node_c_pubkey = node_c.pubkey.clone();
+    let res = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::SendPayment(
+            SendPaymentCommand {
+                target_pubkey: Some(node_c_pubkey),
+                amount: Some(1000000),
+                payment_hash: None,
+                final_tlc_expiry_delta: None,
+                invoice: None,
+                timeout: None,
+                max_fee_amount: None,
+                tlc_expiry_limit: None,
+                max_parts: None,
+                keysend: Some(true),
+                udt_type_script: None,
+                allow_self_payment: false,
+                dry_run: false,
+                custom_records: vec![],
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_a alive")
+    .expect("send payment success");
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    // The successful forward is counted on the outgoing channel.
+    let metrics = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: channel_2,
+                command: ChannelCommand::GetMetrics(rpc_reply),
+            },
+        ))
+    })
+    .expect("node_b alive");
+    assert_eq!(metrics.forwarded_count, 1);
+    assert_eq!(metrics.fees_earned, res.fee);
+    assert!(metrics.failed_count.is_empty());
+    assert_eq!(metrics.downstream_failed_count, 0);
+
+    let metrics = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: channel_1,
+                command: ChannelCommand::GetMetrics(rpc_reply),
+            },
+        ))
+    })
+    .expect("node_b alive");
+    assert_eq!(metrics.forwarded_count, 0);
+    assert!(metrics.failed_count.is_empty());
+}
+
+#[tokio::test]
+async fn test_forward_settled_event_of_forwarded_payment() {
+    init_tracing();
+
+    let (mut node_a, mut node_b, node_c, channel_1, channel_2) =
+        create_3_nodes_with_established_channel(
+            (100000000000, 100000000000),
+            (100000000000, 100000000000),
+            true,
+        )
+        .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let node_c_pubkey = node_c.pubkey.clone();
+    let res = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::SendPayment(
+            SendPaymentCommand {
+                target_pubkey: Some(node_c_pubkey),
+                amount: Some(1000000),
+                payment_hash: None,
+                final_tlc_expiry_delta: None,
+                invoice: None,
+                timeout: None,
+                max_fee_amount: None,
+                tlc_expiry_limit: None,
+                max_parts: None,
+                keysend: Some(true),
+                udt_type_script: None,
+                allow_self_payment: false,
+                dry_run: false,
+                custom_records: vec![],
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_a alive")
+    .expect("send payment success");
+
+    let (incoming_channel, outgoing_channel, payment_hash, fee, next_hop) = node_b
+        .expect_to_process_event(|event| match event {
+            NetworkServiceEvent::ForwardSettled {
+                incoming_channel,
+                outgoing_channel,
+                payment_hash,
+                fee,
+                next_hop,
+            } => Some((
+                *incoming_channel,
+                *outgoing_channel,
+                *payment_hash,
+                *fee,
+                *next_hop,
+            )),
+            _ => None,
+        })
+        .await;
+    assert_eq!(incoming_channel, channel_1);
+    assert_eq!(outgoing_channel, channel_2);
+    assert_eq!(payment_hash, res.payment_hash);
+    assert_eq!(fee, res.fee);
+    assert_eq!(next_hop, Some(node_c.pubkey.clone()));
+}
+
+#[tokio::test]
+async fn test_forward_failed_event_attributed_to_next_hop() {
+    init_tracing();
+
+    let (node_a, mut node_b, node_c, channel_1, channel_2) =
+        create_3_nodes_with_established_channel(
+            (100000000000, 100000000000),
+            (100000000000, 100000000000),
+            true,
+        )
+        .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    // This payment request is without an invoice, node_c will fail the forwarded tlc.
+    let node_c_pubkey = node_c.pubkey.clone();
+    let payment_hash = gen_rand_sha256_hash();
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::SendPayment(
+            SendPaymentCommand {
+                target_pubkey: Some(node_c_pubkey),
+                amount: Some(1000000),
+                payment_hash: Some(payment_hash),
+                final_tlc_expiry_delta: None,
+                invoice: None,
+                timeout: None,
+                max_fee_amount: None,
+                tlc_expiry_limit: None,
+                max_parts: None,
+                keysend: None,
+                udt_type_script: None,
+                allow_self_payment: false,
+                dry_run: false,
+                custom_records: vec![],
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_a alive")
+    .expect("send payment success");
+
+    let (incoming_channel, outgoing_channel, next_hop) = node_b
+        .expect_to_process_event(|event| match event {
+            NetworkServiceEvent::ForwardFailed {
+                incoming_channel,
+                outgoing_channel,
+                payment_hash: failed_payment_hash,
+                next_hop,
+            } if *failed_payment_hash == payment_hash => {
+                Some((*incoming_channel, *outgoing_channel, *next_hop))
+            }
+            _ => None,
+        })
+        .await;
+    assert_eq!(incoming_channel, channel_1);
+    assert_eq!(outgoing_channel, channel_2);
+    assert_eq!(next_hop, Some(node_c.pubkey.clone()));
+}
+
+#[tokio::test]
+async fn test_fail_tlc_with_error_code() {
+    init_tracing();
+
+    let (node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000,
+                        hash_algorithm: HashAlgorithm::Sha256,
+                        payment_hash: gen_rand_sha256_hash(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully added tlc");
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    let state = node_b.get_channel_actor_state(new_channel_id);
+    match state
+        .build_fail_reason(add_tlc_result.tlc_id, TlcErrorCode::TemporaryChannelFailure)
+        .expect("build fail reason")
+    {
+        RemoveTlcReason::RemoveTlcFail(error_packet) => {
+            let tlc_err = error_packet
+                .decode(&[0u8; 32], vec![])
+                .expect("decode tlc error");
+            assert_eq!(tlc_err.error_code, TlcErrorCode::TemporaryChannelFailure);
+            assert_eq!(
+                tlc_err.error_channel_outpoint(),
+                state.get_funding_transaction_outpoint()
+            );
+        }
+        reason => panic!("unexpected remove reason {:?}", reason),
+    }
+
+    let result = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::FailTlc(
+                    add_tlc_result.tlc_id + 1,
+                    TlcErrorCode::TemporaryChannelFailure,
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive");
+    assert!(result.is_err());
+
+    call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::FailTlc(
+                    add_tlc_result.tlc_id,
+                    TlcErrorCode::TemporaryChannelFailure,
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("successfully failed tlc");
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    assert!(state
+        .get_all_offer_tlcs()
+        .all(|tlc| tlc.removed_at.is_some()));
+}
+
+#[tokio::test]
+async fn test_check_tlc_expiry_with_fixed_clock() {
+    init_tracing();
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    let mut state = node_a.get_channel_actor_state(new_channel_id);
+    let now = 1_000_000_000_000;
+    let clock = Arc::new(FixedClock::new(now));
+    state.clock = clock.clone();
+
+    let expiry = now + DEFAULT_EXPIRY_DELTA;
+    assert!(state.check_tlc_expiry(expiry).is_ok());
+    assert!(state.check_tlc_expiry(now).is_err());
+    assert!(state
+        .check_tlc_expiry(now + MAX_PAYMENT_TLC_EXPIRY_LIMIT + 1)
+        .is_err());
+
+    // The result only depends on the injected clock.
+    clock.advance(DEFAULT_EXPIRY_DELTA);
+    assert!(matches!(
+        state.check_tlc_expiry(expiry),
+        Err(ProcessingChannelError::TlcExpirySoon)
+    ));
+}
+
+struct TestFeeEstimator;
+
+impl FeeEstimator for TestFeeEstimator {
+    fn estimate_fee_rate(&self, target_blocks: u64) -> Option<FeeRate> {
+        match target_blocks {
+            1 => Some(FeeRate::from_u64(5000)),
+            10 => Some(FeeRate::from_u64(1)),
+            _ => None,
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_get_shutdown_fee_rate_from_target_blocks() {
+    init_tracing();
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    let mut state = node_a.get_channel_actor_state(new_channel_id);
+    state.fee_estimator = Arc::new(TestFeeEstimator);
+    let command = |target_blocks| ShutdownCommand {
+        close_script: Script::default(),
+        fee_rate: FeeRate::from_u64(DEFAULT_COMMITMENT_FEE_RATE),
+        force: false,
+        allow_unknown_close_script: false,
+        target_blocks,
+        min_net_close_balance: None,
+    };
+
+    assert_eq!(
+        state.get_shutdown_fee_rate(&command(None)).unwrap(),
+        FeeRate::from_u64(DEFAULT_COMMITMENT_FEE_RATE)
+    );
+    assert_eq!(
+        state.get_shutdown_fee_rate(&command(Some(1))).unwrap(),
+        FeeRate::from_u64(5000)
+    );
+    // the estimated fee rate is at least the commitment fee rate
+    assert_eq!(
+        state.get_shutdown_fee_rate(&command(Some(10))).unwrap(),
+        FeeRate::from_u64(state.commitment_fee_rate)
+    );
+    assert!(matches!(
+        state.get_shutdown_fee_rate(&command(Some(100))),
+        Err(ProcessingChannelError::InvalidParameter(_))
+    ));
+
+    // the static fee estimator pays more for the urgent targets
+    let estimator = StaticFeeEstimator::default();
+    assert_eq!(estimator.estimate_fee_rate(0), None);
+    assert_eq!(
+        estimator.estimate_fee_rate(1),
+        Some(FeeRate::from_u64(3 * DEFAULT_FEE_RATE))
+    );
+    assert_eq!(
+        estimator.estimate_fee_rate(100),
+        Some(FeeRate::from_u64(DEFAULT_FEE_RATE))
+    );
+}
+
+#[tokio::test]
+async fn test_shutdown_with_target_blocks_uses_configured_fee_estimator() {
+    init_tracing();
+
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        let builder = NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i));
+        if i == 0 {
+            builder.fee_estimator(Arc::new(TestFeeEstimator)).build()
+        } else {
+            builder.build()
+        }
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let mut node_a = nodes.pop().unwrap();
+    let (new_channel_id, _funding_tx) = establish_channel_between_nodes(
+        &mut node_a,
+        &mut node_b,
+        true,
+        16200000000,
+        6200000000,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await;
+
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::Shutdown(
+                    ShutdownCommand {
+                        close_script: Script::default(),
+                        fee_rate: FeeRate::from_u64(DEFAULT_COMMITMENT_FEE_RATE),
+                        force: false,
+                        allow_unknown_close_script: false,
+                        target_blocks: Some(1),
+                        min_net_close_balance: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully shutdown channel");
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    let shutdown_info = state.local_shutdown_info.expect("local shutdown info");
+    assert_eq!(shutdown_info.fee_rate, 5000);
+}
+
+#[tokio::test]
+async fn test_add_tlc_batch() {
+    init_tracing();
+
+    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+    let (new_channel_id, _funding_tx) = establish_channel_between_nodes(
+        &mut node_a,
+        &mut node_b,
+        true,
+        16200000000,
+        6200000000,
+        Some(3),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await;
+
+    let add_tlc_command = |amount| AddTlcCommand {
+        amount,
+        hash_algorithm: HashAlgorithm::Sha256,
+        payment_hash: gen_rand_sha256_hash(),
+        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+        onion_packet: None,
+        shared_secret: NO_SHARED_SECRET.clone(),
+        previous_tlc: None,
+        payment_preimage: None,
+        idempotency_key: None,
+    };
+
+    let responses = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlcBatch(
+                    vec![add_tlc_command(1000), add_tlc_command(2000)],
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully added tlc batch");
+    let tlc_ids: Vec<_> = responses.iter().map(|response| response.tlc_id).collect();
+    assert_eq!(tlc_ids, vec![0, 1]);
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    // The responses carry the commitment numbers and expiries of the added tlcs.
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    for response in &responses {
+        let tlc = state
+            .get_offered_tlc(response.tlc_id)
+            .expect("offered tlc exists");
+        assert_eq!(response.created_at, tlc.created_at);
+        assert_eq!(response.expiry, tlc.expiry);
+    }
+
+    let state = node_b.get_channel_actor_state(new_channel_id);
+    assert_eq!(state.get_all_received_tlcs().count(), 2);
+
+    // The third tlc exceeds the max tlc number in flight, none of the batch should be added.
+    let result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlcBatch(
+                    vec![add_tlc_command(1000), add_tlc_command(1000)],
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert!(result.is_err());
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    assert_eq!(state.get_all_offer_tlcs().count(), 2);
+    assert_eq!(state.get_next_offering_tlc_id(), 2);
+    let state = node_b.get_channel_actor_state(new_channel_id);
+    assert_eq!(state.get_all_received_tlcs().count(), 2);
+}
+
+#[tokio::test]
+async fn test_is_channel_ready_to_forward() {
+    init_tracing();
+
+    let is_ready_to_forward = |node: &NetworkNode, channel_id| {
+        let network_actor = node.network_actor.clone();
+        async move {
+            call!(network_actor, |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                    ChannelCommandWithId {
+                        channel_id,
+                        command: ChannelCommand::IsReadyToForward(rpc_reply),
+                    },
+                ))
+            })
+            .expect("node alive")
+        }
+    };
+
+    let (node_a, _node_b, public_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, true).await;
+    assert!(is_ready_to_forward(&node_a, public_channel_id).await);
+
+    let mut state = node_a.get_channel_actor_state(public_channel_id);
+    state.reestablishing = true;
+    assert!(!state.is_ready_to_forward());
+
+    // Disabled channels can't forward tlcs.
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: public_channel_id,
+                command: ChannelCommand::Update(
+                    UpdateCommand {
+                        enabled: Some(false),
+                        tlc_expiry_delta: None,
+                        tlc_minimum_value: None,
+                        tlc_fee_proportional_millionths: None,
+                        min_tlc_expiry_delta: None,
+                        max_tlc_expiry_delta: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully updated channel");
+    assert!(!is_ready_to_forward(&node_a, public_channel_id).await);
+
+    // Private channels can't forward tlcs.
+    let (node_a, _node_b, private_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+    assert!(!is_ready_to_forward(&node_a, private_channel_id).await);
+}
+
+#[tokio::test]
+async fn test_get_forwarding_policy() {
+    init_tracing();
+
+    let get_forwarding_policy = |node: &NetworkNode, channel_id| {
+        let network_actor = node.network_actor.clone();
+        async move {
+            call!(network_actor, |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                    ChannelCommandWithId {
+                        channel_id,
+                        command: ChannelCommand::GetForwardingPolicy(rpc_reply),
+                    },
+                ))
+            })
+            .expect("node alive")
+        }
+    };
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, true).await;
+
+    let tlc_expiry_delta = 2 * DEFAULT_EXPIRY_DELTA;
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::Update(
+                    UpdateCommand {
+                        enabled: None,
+                        tlc_expiry_delta: Some(tlc_expiry_delta),
+                        tlc_minimum_value: Some(1000),
+                        tlc_fee_proportional_millionths: Some(2000),
+                        min_tlc_expiry_delta: None,
+                        max_tlc_expiry_delta: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully updated channel");
+
+    let policy = get_forwarding_policy(&node_a, new_channel_id)
+        .await
+        .expect("public channel has forwarding policy");
+    assert_eq!(policy.tlc_min_value, 1000);
+    assert_eq!(policy.tlc_expiry_delta, tlc_expiry_delta);
+    assert_eq!(policy.tlc_fee_proportional_millionths, 2000);
+
+    let (node_c, _node_d, private_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+    assert!(get_forwarding_policy(&node_c, private_channel_id)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_commitment_tx_with_anchor_outputs() {
+    init_tracing();
+
+    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    let open_channel_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 16200000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: true,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_a alive")
+    .expect("open channel success");
+
+    node_b
+        .expect_event(|event| {
+            matches!(event, NetworkServiceEvent::ChannelPendingToBeAccepted(_, _))
+        })
+        .await;
+
+    let accept_channel_result = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id: open_channel_result.channel_id,
+                funding_amount: 16200000000,
+                shutdown_script: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                min_tlc_value: None,
+                tlc_fee_proportional_millionths: None,
+                tlc_expiry_delta: None,
+                commitment_delay_epoch: None,
+                channel_reserve_amount: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_b alive")
+    .expect("accept channel success");
+    let new_channel_id = accept_channel_result.new_channel_id;
+
+    node_a
+        .expect_event(|event| matches!(event, NetworkServiceEvent::ChannelReady(_, _, _)))
+        .await;
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    assert!(state.anchor_outputs);
+    assert!(node_b.get_channel_actor_state(new_channel_id).anchor_outputs);
+    let local_anchor_capacity = anchor_capacity(&state.local_shutdown_script)
+        .expect("valid capacity")
+        .as_u64();
+    assert_eq!(
+        state.local_reserved_ckb_amount,
+        reserved_capacity(&state.local_shutdown_script, &None, true)
+            .expect("valid capacity")
+            .as_u64()
+    );
+
+    let commitment_tx = state
+        .latest_commitment_transaction
+        .clone()
+        .expect("commitment tx exists")
+        .into_view();
+    assert_eq!(commitment_tx.outputs().len(), 3);
+    let local_anchor = commitment_tx
+        .output(LOCAL_ANCHOR_OUTPUT_INDEX as usize)
+        .expect("local anchor output");
+    assert_eq!(local_anchor.lock(), state.local_shutdown_script);
+    let capacity: u64 = local_anchor.capacity().unpack();
+    assert_eq!(capacity, local_anchor_capacity);
+    assert_eq!(
+        state.get_local_anchor_outpoint(),
+        Some(OutPoint::new(commitment_tx.hash(), LOCAL_ANCHOR_OUTPUT_INDEX))
+    );
+
+    // The commitment signing works with the anchor outputs, the second tlc can only be added
+    // after the commitment of the first one is acked by the peer.
+    for _ in 0..2 {
+        call!(node_a.network_actor, |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: new_channel_id,
+                    command: ChannelCommand::AddTlc(
+                        AddTlcCommand {
+                            amount: 1000000000,
+                            hash_algorithm: HashAlgorithm::Sha256,
+                            payment_hash: gen_rand_sha256_hash(),
+                            expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                            onion_packet: None,
+                            shared_secret: NO_SHARED_SECRET.clone(),
+                            previous_tlc: None,
+                            payment_preimage: None,
+                            idempotency_key: None,
+                        },
+                        rpc_reply,
+                    ),
+                },
+            ))
+        })
+        .expect("node_a alive")
+        .expect("successfully added tlc");
+        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    }
+
+    let state = node_b.get_channel_actor_state(new_channel_id);
+    assert_eq!(state.get_all_received_tlcs().count(), 2);
+}
+
+#[tokio::test]
+async fn test_verify_revealed_remote_commitment_secret() {
+    init_tracing();
+
+    let (node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000,
+                        hash_algorithm: HashAlgorithm::Sha256,
+                        payment_hash: gen_rand_sha256_hash(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully added tlc");
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    // Both parties have revoked their previous commitments and revealed the secrets.
+    for node in [&node_a, &node_b] {
+        let state = node.get_channel_actor_state(new_channel_id);
+        assert!(!state.remote_commitment_secrets.is_empty());
+        for (number, secret) in &state.remote_commitment_secrets {
+            assert!(state
+                .verify_remote_commitment_secret(*number, secret)
+                .is_ok());
+        }
+    }
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    let (number, secret) = state.remote_commitment_secrets[0];
+    assert_eq!(state.get_remote_commitment_secret(number), Some(secret));
+    let mut wrong_secret = secret;
+    wrong_secret[0] ^= 1;
+    assert!(matches!(
+        state.verify_remote_commitment_secret(number, &wrong_secret),
+        Err(ProcessingChannelError::InvalidCommitmentSecret(n)) if n == number
+    ));
+}
+
+#[tokio::test]
+async fn test_shutdown_channel_with_unknown_close_script() {
+    init_tracing();
+
+    let allowed_code_hash = Hash256::from([1u8; 32]);
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(move |config| {
+                config.allowed_close_script_code_hashes = vec![allowed_code_hash];
+            })
+            .build()
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let mut node_a = nodes.pop().unwrap();
+    let (new_channel_id, _funding_tx) = establish_channel_between_nodes(
+        &mut node_a,
+        &mut node_b,
+        false,
+        100000000000,
+        6200000000,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await;
+
+    let shutdown = |allow_unknown_close_script| {
+        move |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: new_channel_id,
+                    command: ChannelCommand::Shutdown(
+                        ShutdownCommand {
+                            close_script: Script::new_builder()
+                                .args(vec![0u8; 20].pack())
+                                .build(),
+                            fee_rate: FeeRate::from_u64(DEFAULT_COMMITMENT_FEE_RATE),
+                            force: false,
+                            allow_unknown_close_script,
+                            target_blocks: None,
+                            min_net_close_balance: None,
+                        },
+                        rpc_reply,
+                    ),
+                },
+            ))
+        }
+    };
+
+    let err = call!(node_b.network_actor, shutdown(false))
+        .expect("node_b alive")
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.code,
+        ProcessingChannelError::InvalidParameter(String::new()).code()
+    );
+    assert!(err.message.contains("unknown code hash"));
+
+    call!(node_b.network_actor, shutdown(true))
+        .expect("node_b alive")
+        .expect("successfully shutdown channel with overridden validation");
+}
+
+#[tokio::test]
+async fn test_send_payment_with_disabled_outgoing_channel() {
+    init_tracing();
+    let nodes_num = 3;
+    let amounts = vec![(100000000000, 100000000000); nodes_num - 1];
+    let (nodes, channels) =
+        create_n_nodes_with_established_channel(&amounts, nodes_num, true).await;
+    let [mut node_0, mut node_1, node_2] = nodes.try_into().expect("3 nodes");
+
+    // sleep for a while
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    // The incoming channel of node_1 is still enabled, but the outgoing one is disabled without
+    // a channel update, node_1 should reject the forwarding by the outgoing channel actor before
+    // adding the tlc to the outgoing channel.
+    node_1.disable_channel(channels[1]).await;
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    let res = node_0
+        .send_payment(SendPaymentCommand {
+            target_pubkey: Some(node_2.pubkey.clone()),
+            amount: Some(3000),
+            payment_hash: None,
+            final_tlc_expiry_delta: None,
+            tlc_expiry_limit: None,
+            invoice: None,
+            timeout: None,
+            max_fee_amount: None,
+            max_parts: None,
+            keysend: Some(true),
+            udt_type_script: None,
+            allow_self_payment: false,
+            dry_run: false,
+            custom_records: vec![],
+        })
+        .await;
+    let payment_hash = res.expect("send payment").payment_hash;
+    tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
+
+    node_0
+        .assert_payment_status(payment_hash, PaymentSessionStatus::Failed, None)
+        .await;
+    assert!(node_1
+        .get_channel_actor_state(channels[1])
+        .get_all_offer_tlcs()
+        .next()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_export_and_import_channel_backup() {
+    init_tracing();
+
+    let (mut node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    let backup = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::ExportBackup(rpc_reply),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully exported channel backup");
+
+    let import_backup = |node: &NetworkNode| {
+        let network_actor = node.network_actor.clone();
+        let backup = backup.clone();
+        async move {
+            call!(network_actor, |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::ImportChannelBackup(
+                    backup, rpc_reply,
+                ))
+            })
+            .expect("node alive")
+        }
+    };
+
+    // Only the node exporting the backup can decrypt it.
+    let err = import_backup(&node_b).await.unwrap_err();
+    assert!(err.contains("Failed to decrypt channel backup"));
+
+    let err = import_backup(&node_a).await.unwrap_err();
+    assert!(err.contains("already exists"));
+
+    // Lose the channel state while the peer is disconnected, the backup is kept and the peer
+    // is requested to force close the channel once connected.
+    node_a
+        .network_actor
+        .send_message(NetworkActorMessage::new_command(
+            NetworkActorCommand::DisconnectPeer(node_b.peer_id.clone()),
+        ))
+        .expect("node_a alive");
+    node_a
+        .expect_event(|event| matches!(event, NetworkServiceEvent::PeerDisConnected(_, _)))
+        .await;
+    node_a.store.delete_channel_actor_state(&new_channel_id);
+    let channel_id = import_backup(&node_a)
+        .await
+        .expect("successfully imported channel backup");
+    assert_eq!(channel_id, new_channel_id);
+    let restored_backups = node_a.store.get_restored_channel_backups();
+    assert_eq!(restored_backups.len(), 1);
+    assert_eq!(restored_backups[0].channel_id, new_channel_id);
+
+    node_a.connect_to(&node_b).await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    let state = node_b.get_channel_actor_state(new_channel_id);
+    assert_eq!(
+        state.state,
+        ChannelState::ShuttingDown(ShuttingDownFlags::WAITING_COMMITMENT_CONFIRMATION)
+    );
+}
+
+#[tokio::test]
+async fn test_open_channel_with_push_amount() {
+    init_tracing();
+
+    let push_amount = 1000000000;
+    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    let open_channel_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 16200000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: Some(push_amount),
+                tlc_dust_limit: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_a alive")
+    .expect("open channel success");
+
+    node_b
+        .expect_event(|event| {
+            matches!(event, NetworkServiceEvent::ChannelPendingToBeAccepted(_, _))
+        })
+        .await;
+
+    let accept_channel_result = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id: open_channel_result.channel_id,
+                funding_amount: 16200000000,
+                shutdown_script: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                min_tlc_value: None,
+                tlc_fee_proportional_millionths: None,
+                tlc_expiry_delta: None,
+                commitment_delay_epoch: None,
+                channel_reserve_amount: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_b alive")
+    .expect("accept channel success");
+    let new_channel_id = accept_channel_result.new_channel_id;
+
+    node_a
+        .expect_event(|event| matches!(event, NetworkServiceEvent::ChannelReady(_, _, _)))
+        .await;
+    node_b
+        .expect_event(|event| matches!(event, NetworkServiceEvent::ChannelReady(_, _, _)))
+        .await;
+
+    let state_a = node_a.get_channel_actor_state(new_channel_id);
+    let state_b = node_b.get_channel_actor_state(new_channel_id);
+    assert_eq!(state_a.push_amount, push_amount);
+    assert_eq!(state_b.push_amount, push_amount);
+    assert_eq!(state_a.to_local_amount, state_b.to_remote_amount);
+    assert_eq!(state_a.to_remote_amount, state_b.to_local_amount);
+    assert_eq!(
+        state_a.to_remote_amount - state_a.to_local_amount,
+        2 * push_amount
+    );
+}
+
+#[tokio::test]
+async fn test_open_channel_with_push_amount_exceeding_funding_amount() {
+    init_tracing();
+
+    let [node_a, node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    let open_channel_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 16200000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: Some(16200000000),
+                tlc_dust_limit: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_a alive");
+    assert!(open_channel_result.is_err());
+}
+
+#[tokio::test]
+async fn test_read_only_channel_command_does_not_persist_state() {
+    init_tracing();
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    // Remove the persisted state, so that we can tell whether the channel actor writes it again.
+    node_a.store.delete_channel_actor_state(&new_channel_id);
+
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::GetTlcCapacityStatus(rpc_reply),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert!(node_a
+        .store
+        .get_channel_actor_state(&new_channel_id)
+        .is_none());
+
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::Update(
+                    UpdateCommand {
+                        enabled: Some(true),
+                        tlc_expiry_delta: None,
+                        tlc_minimum_value: None,
+                        tlc_fee_proportional_millionths: None,
+                        min_tlc_expiry_delta: None,
+                        max_tlc_expiry_delta: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("update channel success");
+    assert!(node_a
+        .store
+        .get_channel_actor_state(&new_channel_id)
+        .is_some());
+}
+
+// A benchmark of the read-only commands polled by a busy forwarding node, compared with
+// persisting the channel state after each of them as before. Run it with
+// `cargo test --release bench_read_only_channel_commands -- --ignored --nocapture`.
+#[tokio::test]
+#[ignore]
+async fn bench_read_only_channel_commands() {
+    const ROUNDS: u32 = 1000;
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    let started_at = std::time::Instant::now();
+    for _ in 0..ROUNDS {
+        call!(node_a.network_actor, |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: new_channel_id,
+                    command: ChannelCommand::IsReadyToForward(rpc_reply),
+                },
+            ))
+        })
+        .expect("node_a alive");
+    }
+    let commands_elapsed = started_at.elapsed();
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    let started_at = std::time::Instant::now();
+    for _ in 0..ROUNDS {
+        node_a.store.insert_channel_actor_state(state.clone());
+    }
+    let writes_elapsed = started_at.elapsed();
+
+    println!(
+        "{} read-only commands took {:?}, the skipped state writes would take another {:?}",
+        ROUNDS, commands_elapsed, writes_elapsed
+    );
+}
+
+// A signer which forwards the requests to an in-memory signer, like a remote signer would do.
+struct CountingSigner {
+    inner: InMemorySigner,
+    sign_count: AtomicUsize,
+}
+
+impl CommitmentSigner for CountingSigner {
+    fn funding_pubkey(&self) -> Pubkey {
+        CommitmentSigner::funding_pubkey(&self.inner)
+    }
+
+    fn derive_musig2_nonce(&self, commitment_number: u64) -> SecNonce {
+        self.inner.derive_musig2_nonce(commitment_number)
+    }
+
+    fn sign_partial(
+        &self,
+        key_agg_ctx: &KeyAggContext,
+        secnonce: SecNonce,
+        agg_nonce: &AggNonce,
+        message: &[u8],
+    ) -> Result<PartialSignature, SigningError> {
+        self.sign_count.fetch_add(1, Ordering::SeqCst);
+        CommitmentSigner::sign_partial(&self.inner, key_agg_ctx, secnonce, agg_nonce, message)
+    }
+}
+
+#[tokio::test]
+async fn test_set_commitment_signer() {
+    init_tracing();
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    let mut state = node_a.get_channel_actor_state(new_channel_id);
+    let default_signature = Musig2SignContext::from(&state)
+        .sign(b"message")
+        .expect("sign with the default signer");
+
+    let other_signer = InMemorySigner::generate_from_seed(&[2; 32]);
+    assert!(state.set_commitment_signer(Arc::new(other_signer)).is_err());
+
+    let signer = Arc::new(CountingSigner {
+        inner: state.signer.clone(),
+        sign_count: Default::default(),
+    });
+    state
+        .set_commitment_signer(signer.clone())
+        .expect("set commitment signer");
+    let signature = Musig2SignContext::from(&state)
+        .sign(b"message")
+        .expect("sign with the installed signer");
+    assert_eq!(signature, default_signature);
+    assert_eq!(signer.sign_count.load(Ordering::SeqCst), 1);
+}
+
+// A provider which keeps the counting signers in memory, like a hardware signer would do.
+#[derive(Default)]
+struct CountingSignerProvider {
+    signers: Mutex<Vec<Arc<CountingSigner>>>,
+}
+
+impl CommitmentSignerProvider for CountingSignerProvider {
+    fn import_signer(&self, signer: InMemorySigner) -> Arc<dyn CommitmentSigner> {
+        let signer = Arc::new(CountingSigner {
+            inner: signer,
+            sign_count: Default::default(),
+        });
+        self.signers.lock().unwrap().push(signer.clone());
+        signer
+    }
+
+    fn get_signer(&self, funding_pubkey: &Pubkey) -> Option<Arc<dyn CommitmentSigner>> {
+        self.signers
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|signer| &signer.funding_pubkey() == funding_pubkey)
+            .map(|signer| signer.clone() as Arc<dyn CommitmentSigner>)
+    }
+}
+
+#[tokio::test]
+async fn test_commitment_signer_provider_keeps_funding_key() {
+    init_tracing();
+
+    let provider = Arc::new(CountingSignerProvider::default());
+    let node_a_provider = provider.clone();
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, move |i| {
+        let builder = NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i));
+        if i == 0 {
+            builder
+                .commitment_signer_provider(node_a_provider.clone())
+                .build()
+        } else {
+            builder.build()
+        }
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let mut node_a = nodes.pop().unwrap();
+    let (new_channel_id, _funding_tx) = establish_channel_between_nodes(
+        &mut node_a,
+        &mut node_b,
+        false,
+        100000000000,
+        6200000000,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await;
+
+    // The funding key is handed over to the provider and never persisted.
+    assert_eq!(provider.signers.lock().unwrap().len(), 1);
+    let state = node_a
+        .store
+        .get_channel_actor_state(&new_channel_id)
+        .expect("channel state persisted");
+    assert!(state.signer.funding_key.is_none());
+
+    // The signer is got from the provider again when the channel is restored.
+    node_a.restart().await;
+    node_a
+        .expect_event(|event| {
+            matches!(event, NetworkServiceEvent::PeerConnected(id, _addr) if id == &node_b.peer_id)
+        })
+        .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    let close_script = node_a
+        .get_channel_actor_state(new_channel_id)
+        .local_shutdown_script;
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::Shutdown(
+                    ShutdownCommand {
+                        close_script,
+                        fee_rate: FeeRate::from_u64(DEFAULT_COMMITMENT_FEE_RATE),
+                        force: false,
+                        allow_unknown_close_script: false,
+                        target_blocks: None,
+                        min_net_close_balance: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully shutdown channel");
+    node_a
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelClosed(_, channel_id, _) => channel_id == &new_channel_id,
+            _ => false,
+        })
+        .await;
+
+    let signers = provider.signers.lock().unwrap();
+    assert_eq!(signers.len(), 1);
+    assert!(signers[0].sign_count.load(Ordering::SeqCst) > 0);
+}
+
+#[tokio::test]
+async fn test_simultaneous_shutdown_with_different_fee_rates() {
+    init_tracing();
+
+    let (mut node_a, mut node_b, new_channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    let shutdown_message = |node: &NetworkNode, fee_rate: u64| {
+        let network_actor = node.network_actor.clone();
+        let close_script = node.get_channel_actor_state(new_channel_id).local_shutdown_script;
+        async move {
+            call!(network_actor, |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                    ChannelCommandWithId {
+                        channel_id: new_channel_id,
+                        command: ChannelCommand::Shutdown(
+                            ShutdownCommand {
+                                close_script,
+                                fee_rate: FeeRate::from_u64(fee_rate),
+                                force: false,
+                                allow_unknown_close_script: false,
+                                target_blocks: None,
+                                min_net_close_balance: None,
+                            },
+                            rpc_reply,
+                        ),
+                    },
+                ))
+            })
+            .expect("node alive")
+        }
+    };
+
+    let (result_a, result_b) = tokio::join!(
+        shutdown_message(&node_a, DEFAULT_COMMITMENT_FEE_RATE),
+        shutdown_message(&node_b, DEFAULT_COMMITMENT_FEE_RATE * 2)
+    );
+    result_a.expect("node_a shutdown channel");
+    result_b.expect("node_b shutdown channel");
+
+    let node_a_shutdown_tx_hash = node_a
+        .expect_to_process_event(|event| match event {
+            NetworkServiceEvent::ChannelClosed(_, channel_id, tx_hash)
+                if channel_id == &new_channel_id =>
+            {
+                Some(tx_hash.clone())
+            }
+            _ => None,
+        })
+        .await;
+    let node_b_shutdown_tx_hash = node_b
+        .expect_to_process_event(|event| match event {
+            NetworkServiceEvent::ChannelClosed(_, channel_id, tx_hash)
+                if channel_id == &new_channel_id =>
+            {
+                Some(tx_hash.clone())
+            }
+            _ => None,
+        })
+        .await;
+    assert_eq!(node_a_shutdown_tx_hash, node_b_shutdown_tx_hash);
+    assert_eq!(
+        node_a.trace_tx_hash(node_a_shutdown_tx_hash).await,
+        Status::Committed
+    );
+}
+
+#[tokio::test]
+async fn test_trim_dust_tlcs_from_commitment_tx() {
+    init_tracing();
+
+    let tlc_dust_limit = 100000;
+    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    let open_channel_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 100000000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: Some(tlc_dust_limit),
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_a alive")
+    .expect("open channel success");
+
+    node_b
+        .expect_event(|event| {
+            matches!(event, NetworkServiceEvent::ChannelPendingToBeAccepted(_, _))
+        })
+        .await;
+
+    let accept_channel_result = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id: open_channel_result.channel_id,
+                funding_amount: 6200000000,
+                shutdown_script: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                min_tlc_value: None,
+                tlc_fee_proportional_millionths: None,
+                tlc_expiry_delta: None,
+                commitment_delay_epoch: None,
+                channel_reserve_amount: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_b alive")
+    .expect("accept channel success");
+    let new_channel_id = accept_channel_result.new_channel_id;
+
+    node_a
+        .expect_event(|event| matches!(event, NetworkServiceEvent::ChannelReady(_, _, _)))
+        .await;
+    node_b
+        .expect_event(|event| matches!(event, NetworkServiceEvent::ChannelReady(_, _, _)))
+        .await;
+    assert_eq!(
+        node_b.get_channel_actor_state(new_channel_id).tlc_dust_limit,
+        tlc_dust_limit
+    );
+
+    for amount in [tlc_dust_limit - 1, tlc_dust_limit] {
+        call!(node_a.network_actor, |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: new_channel_id,
+                    command: ChannelCommand::AddTlc(
+                        AddTlcCommand {
+                            amount,
+                            hash_algorithm: HashAlgorithm::Sha256,
+                            payment_hash: gen_rand_sha256_hash(),
+                            expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                            onion_packet: None,
+                            shared_secret: NO_SHARED_SECRET.clone(),
+                            previous_tlc: None,
+                            payment_preimage: None,
+                            idempotency_key: None,
+                        },
+                        rpc_reply,
+                    ),
+                },
+            ))
+        })
+        .expect("node_a alive")
+        .expect("successfully added tlc");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+
+    let state_a = node_a.get_channel_actor_state(new_channel_id);
+    let state_b = node_b.get_channel_actor_state(new_channel_id);
+    assert_eq!(state_a.get_all_offer_tlcs().count(), 2);
+    assert_eq!(
+        state_a
+            .get_all_offer_tlcs()
+            .filter(|tlc| state_a.is_trimmed_tlc(tlc))
+            .count(),
+        1
+    );
+
+    // Both parties trim the same tlc, only the tlc above the dust limit is in the commitment.
+    let htlcs = state_a.get_active_htlcs(true);
+    assert_eq!(htlcs.first(), Some(&1));
+    assert_eq!(htlcs, state_b.get_active_htlcs(false));
+    let htlcs = state_a.get_active_htlcs(false);
+    assert_eq!(htlcs.first(), Some(&1));
+    assert_eq!(htlcs, state_b.get_active_htlcs(true));
+}
+
+#[tokio::test]
+#[should_panic(expected = "Waiting for event timeout")]
+async fn test_open_channel_with_too_large_tlc_dust_limit_should_fail() {
+    init_tracing();
+
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(|config| {
+                config.max_accepted_tlc_dust_limit = Some(100000);
+            })
+            .build()
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let node_a = nodes.pop().unwrap();
+
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 100000000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: Some(100001),
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_a alive")
+    .expect("open channel success");
+
+    // The acceptor rejects the OpenChannel message, so the channel is never pending to be accepted.
+    node_b
+        .expect_event(|event| {
+            matches!(event, NetworkServiceEvent::ChannelPendingToBeAccepted(_, _))
+        })
+        .await;
+}
+
+#[test]
+fn test_negotiate_hash_algorithms() {
+    let all = HashAlgorithm::supported_algorithms();
+    let ckb_hash_only = vec![HashAlgorithm::CkbHash];
+
+    // Peers not supporting the negotiation send an empty list.
+    assert_eq!(negotiate_hash_algorithms(&all, &[]).unwrap(), all);
+    assert_eq!(
+        negotiate_hash_algorithms(&all, &ckb_hash_only).unwrap(),
+        ckb_hash_only
+    );
+    assert_eq!(
+        negotiate_hash_algorithms(&ckb_hash_only, &all).unwrap(),
+        ckb_hash_only
+    );
+    assert!(negotiate_hash_algorithms(&ckb_hash_only, &[HashAlgorithm::Sha256]).is_err());
+}
+
+#[test]
+fn test_check_funding_udt_type_script() {
+    let udt_script = Script::new_builder()
+        .code_hash([1u8; 32].pack())
+        .args([1u8; 20].to_vec().pack())
+        .build();
+    let other_udt_script = Script::new_builder()
+        .code_hash([2u8; 32].pack())
+        .args([2u8; 20].to_vec().pack())
+        .build();
+
+    // All udts are allowed if the list is not set.
+    assert!(check_funding_udt_type_script(None, &Some(udt_script.clone())).is_ok());
+    assert!(check_funding_udt_type_script(None, &None).is_ok());
+
+    let allowed = vec![udt_script.clone()];
+    assert!(check_funding_udt_type_script(Some(&allowed), &Some(udt_script.clone())).is_ok());
+    assert!(check_funding_udt_type_script(Some(&allowed), &Some(other_udt_script)).is_err());
+    assert!(check_funding_udt_type_script(Some(&allowed), &None).is_ok());
+
+    // Only native ckb channels are allowed with an empty list.
+    assert!(check_funding_udt_type_script(Some(&[]), &Some(udt_script)).is_err());
+    assert!(check_funding_udt_type_script(Some(&[]), &None).is_ok());
+}
+
+#[tokio::test]
+async fn test_add_tlc_with_disallowed_hash_algorithm() {
+    init_tracing();
+
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(move |config| {
+                if i == 0 {
+                    config.allowed_hash_algorithms = vec![HashAlgorithm::CkbHash];
+                }
+            })
+            .build()
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let mut node_a = nodes.pop().unwrap();
+    let (new_channel_id, _funding_tx) = establish_channel_between_nodes(
+        &mut node_a,
+        &mut node_b,
+        false,
+        100000000000,
+        6200000000,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await;
+
+    // Both parties only allow the hash algorithm allowed by node_a.
+    for node in [&node_a, &node_b] {
+        assert_eq!(
+            node.get_channel_actor_state(new_channel_id)
+                .allowed_hash_algorithms,
+            vec![HashAlgorithm::CkbHash]
+        );
+    }
+
+    let add_tlc = |hash_algorithm| {
+        move |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: new_channel_id,
+                    command: ChannelCommand::AddTlc(
+                        AddTlcCommand {
+                            amount: 10000,
+                            hash_algorithm,
+                            payment_hash: gen_rand_sha256_hash(),
+                            expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                            onion_packet: None,
+                            shared_secret: NO_SHARED_SECRET.clone(),
+                            previous_tlc: None,
+                            payment_preimage: None,
+                            idempotency_key: None,
+                        },
+                        rpc_reply,
+                    ),
+                },
+            ))
+        }
+    };
+
+    let err = call!(node_b.network_actor, add_tlc(HashAlgorithm::Sha256))
+        .expect("node_b alive")
+        .expect_err("sha256 is not allowed in the channel");
+    assert_eq!(err.error_code, TlcErrorCode::InvalidOnionPayload);
+
+    call!(node_b.network_actor, add_tlc(HashAlgorithm::CkbHash))
+        .expect("node_b alive")
+        .expect("successfully added tlc");
+}
+
+#[tokio::test]
+async fn test_accept_channel_with_total_amount_exceeding_u64() {
+    init_tracing();
+
+    let [node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    // The funding amount of the opener alone is near but below the upper bound.
+    let open_channel_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: u64::MAX as u128 - 1,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_a alive")
+    .expect("open channel success");
+
+    node_b
+        .expect_event(|event| {
+            matches!(event, NetworkServiceEvent::ChannelPendingToBeAccepted(_, _))
+        })
+        .await;
+
+    // The total amount of both parties overflows u64.
+    let accept_channel_result = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id: open_channel_result.channel_id,
+                funding_amount: 6200000000,
+                shutdown_script: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                min_tlc_value: None,
+                tlc_fee_proportional_millionths: None,
+                tlc_expiry_delta: None,
+                commitment_delay_epoch: None,
+                channel_reserve_amount: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_b alive");
+    assert!(accept_channel_result.is_err());
+}
+
+#[tokio::test]
+async fn test_pause_and_resume_tlc_forwarding() {
+    init_tracing();
+
+    let (mut node_a, node_b, node_c, channel_1, _channel_2) =
+        create_3_nodes_with_established_channel(
+            (100000000000, 100000000000),
+            (100000000000, 100000000000),
+            true,
+        )
+        .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let set_forwarding_paused = |paused: bool| {
+        node_b
+            .network_actor
+            .send_message(NetworkActorMessage::Command(
+                NetworkActorCommand::ControlFiberChannel(ChannelCommandWithId {
+                    channel_id: channel_1,
+                    command: ChannelCommand::SetForwardingPaused(paused),
+                }),
+            ))
+            .expect("node_b alive");
+    };
+    let send_payment_command = SendPaymentCommand {
+        target_pubkey: Some(node_c.pubkey.clone()),
+        amount: Some(1000000),
+        payment_hash: None,
+        final_tlc_expiry_delta: None,
+        invoice: None,
+        timeout: None,
+        max_fee_amount: None,
+        tlc_expiry_limit: None,
+        max_parts: None,
+        keysend: Some(true),
+        udt_type_script: None,
+        allow_self_payment: false,
+        dry_run: false,
+        custom_records: vec![],
+    };
+
+    set_forwarding_paused(true);
+    let channel_update = node_b
+        .get_channel_actor_state(channel_1)
+        .public_channel_info
+        .and_then(|info| info.channel_update);
+
+    let res = node_a
+        .send_payment(send_payment_command.clone())
+        .await
+        .expect("send payment");
+    tokio::time::sleep(tokio::time::Duration::from_secs(4)).await;
+    node_a
+        .assert_payment_status(res.payment_hash, PaymentSessionStatus::Failed, None)
+        .await;
+
+    // the failed tlcs do not change the channel update of the paused channel
+    assert_eq!(
+        node_b
+            .get_channel_actor_state(channel_1)
+            .public_channel_info
+            .and_then(|info| info.channel_update),
+        channel_update
+    );
+
+    set_forwarding_paused(false);
+    let res = node_a
+        .send_payment(send_payment_command)
+        .await
+        .expect("send payment");
+    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    node_a
+        .assert_payment_status(res.payment_hash, PaymentSessionStatus::Success, None)
+        .await;
+}
+
+#[tokio::test]
+async fn test_get_remote_settlement_data() {
+    init_tracing();
+
+    let (node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    let get_remote_settlement_data = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::GetRemoteSettlementData(rpc_reply),
+            },
+        ))
+    };
+
+    // no settlement of the remote commitment is signed by the peer before any RevokeAndAck
+    let result = call!(node_a.network_actor, get_remote_settlement_data).expect("node_a alive");
+    assert!(result.is_err());
+
+    let preimage = [3; 32];
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: HashAlgorithm::CkbHash.hash(&preimage).into(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert!(add_tlc_result.is_ok());
+
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    let settlement_data = call!(node_a.network_actor, get_remote_settlement_data)
+        .expect("node_a alive")
+        .expect("remote settlement data");
+    assert_eq!(Some(settlement_data.clone()), state.latest_settlement_data);
+
+    // It settles the same outputs as the peer's own view of its latest local commitment.
+    let state_b = node_b.get_channel_actor_state(new_channel_id);
+    let local_commitment_number = state_b
+        .get_latest_commitment_tx_info()
+        .expect("latest commitment tx info")
+        .commitment_number;
+    let peer_settlement_data = node_b
+        .store
+        .get_settlement_data(&new_channel_id, true, local_commitment_number)
+        .expect("peer local settlement data");
+    assert_eq!(
+        settlement_data.x_only_aggregated_pubkey,
+        peer_settlement_data.x_only_aggregated_pubkey
+    );
+    assert_eq!(settlement_data.to_local_output, peer_settlement_data.to_local_output);
+    assert_eq!(settlement_data.to_local_output_data, peer_settlement_data.to_local_output_data);
+    assert_eq!(settlement_data.to_remote_output, peer_settlement_data.to_remote_output);
+    assert_eq!(settlement_data.to_remote_output_data, peer_settlement_data.to_remote_output_data);
+}
+
+#[tokio::test]
+async fn test_reject_tlc_while_dropping_pending() {
+    init_tracing();
+
+    let (node_a, mut node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    // all tlcs are resolved and node_b is waiting for the closing signature of node_a
+    let mut state = node_b.get_channel_actor_state(new_channel_id);
+    state.state = ChannelState::ShuttingDown(
+        ShuttingDownFlags::AWAITING_PENDING_TLCS | ShuttingDownFlags::DROPPING_PENDING,
+    );
+    node_b.update_channel_actor_state(state).await;
+
+    let add_tlc_command = AddTlcCommand {
+        amount: 1000000000,
+        hash_algorithm: HashAlgorithm::CkbHash,
+        payment_hash: gen_rand_sha256_hash(),
+        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+        onion_packet: None,
+        shared_secret: NO_SHARED_SECRET.clone(),
+        previous_tlc: None,
+        payment_preimage: None,
+        idempotency_key: None,
+    };
+
+    // node_b can't add tlcs by itself
+    let add_tlc_result = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(add_tlc_command.clone(), rpc_reply),
+            },
+        ))
+    })
+    .expect("node_b alive");
+    assert_eq!(
+        add_tlc_result.unwrap_err().error_code,
+        TlcErrorCode::PermanentChannelFailure
+    );
+
+    // and the tlc arriving from node_a is rejected
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(add_tlc_command.clone(), rpc_reply),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert!(add_tlc_result.is_ok());
+
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::DebugEvent(DebugEvent::Common(error)) => {
+                error.contains("Channel is dropping pending")
+            }
+            _ => false,
+        })
+        .await;
+
+    let state = node_b.get_channel_actor_state(new_channel_id);
+    assert!(state.tlc_state.all_tlcs().next().is_none());
+    assert_eq!(
+        state.state,
+        ChannelState::ShuttingDown(
+            ShuttingDownFlags::AWAITING_PENDING_TLCS | ShuttingDownFlags::DROPPING_PENDING
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_channel_max_tlc_amounts() {
+    init_tracing();
+
+    let (node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    let get_max_tlc_amounts = |node: &NetworkNode| {
+        let network_actor = node.network_actor.clone();
+        async move {
+            call!(network_actor, |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                    ChannelCommandWithId {
+                        channel_id: new_channel_id,
+                        command: ChannelCommand::GetMaxTlcAmounts(rpc_reply),
+                    },
+                ))
+            })
+            .expect("node alive")
+        }
+    };
+
+    let amounts_a = get_max_tlc_amounts(&node_a).await;
+    let amounts_b = get_max_tlc_amounts(&node_b).await;
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    assert!(amounts_a.max_sendable_amount > 0);
+    assert!(
+        amounts_a.max_sendable_amount
+            <= state.to_local_amount - state.local_constraints.channel_reserve_amount
+    );
+    assert!(amounts_a.max_sendable_amount <= state.local_constraints.max_tlc_value_in_flight);
+    assert!(amounts_b.max_receivable_amount >= amounts_a.max_sendable_amount);
+
+    let add_tlc = |amount: u128| {
+        let network_actor = node_a.network_actor.clone();
+        async move {
+            call!(network_actor, |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                    ChannelCommandWithId {
+                        channel_id: new_channel_id,
+                        command: ChannelCommand::AddTlc(
+                            AddTlcCommand {
+                                amount,
+                                hash_algorithm: HashAlgorithm::CkbHash,
+                                payment_hash: gen_rand_sha256_hash(),
+                                expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                                onion_packet: None,
+                                shared_secret: NO_SHARED_SECRET.clone(),
+                                previous_tlc: None,
+                                payment_preimage: None,
+                                idempotency_key: None,
+                            },
+                            rpc_reply,
+                        ),
+                    },
+                ))
+            })
+            .expect("node_a alive")
+        }
+    };
+
+    // the max sendable amount is the true ceiling of a single tlc
+    assert!(add_tlc(amounts_a.max_sendable_amount + 1).await.is_err());
+    assert!(add_tlc(amounts_a.max_sendable_amount).await.is_ok());
+
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    assert_eq!(get_max_tlc_amounts(&node_a).await.max_sendable_amount, 0);
+    assert!(
+        get_max_tlc_amounts(&node_b).await.max_receivable_amount
+            < amounts_b.max_receivable_amount
+    );
+}
+
+#[tokio::test]
+async fn test_force_close_channel_on_repeated_signing_failures() {
+    init_tracing();
+
+    let (node_a, mut node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    // a partial signature of another message, which fails the verification of the commitments
+    let state = node_b.get_channel_actor_state(new_channel_id);
+    let (_, partial_signature) = state
+        .public_channel_info
+        .as_ref()
+        .and_then(|info| info.remote_channel_announcement_signature.clone())
+        .expect("remote announcement signatures received");
+    let next_local_nonce = state.get_remote_nonce();
+
+    for _ in 0..MAX_CONSECUTIVE_SIGNING_FAILURES {
+        node_a
+            .network_actor
+            .send_message(NetworkActorMessage::new_command(
+                NetworkActorCommand::SendFiberMessage(FiberMessageWithPeerId::new(
+                    node_b.peer_id.clone(),
+                    FiberMessage::commitment_signed(CommitmentSigned {
+                        channel_id: new_channel_id,
+                        funding_tx_partial_signature: partial_signature,
+                        commitment_tx_partial_signature: partial_signature,
+                        next_local_nonce: next_local_nonce.clone(),
+                    }),
+                )),
+            ))
+            .expect("node_a alive");
+    }
+
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelForceClosedOnSigningFailures(channel_id, reason) => {
+                *channel_id == new_channel_id && reason.contains("Musig2 VerifyError")
+            }
+            _ => false,
+        })
+        .await;
+
+    let state = node_b.get_channel_actor_state(new_channel_id);
+    assert!(!matches!(state.state, ChannelState::ChannelReady()));
+}
+
+#[tokio::test]
+async fn test_channel_capacity_breakdown() {
+    init_tracing();
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    let breakdown = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::GetCapacityBreakdown(rpc_reply),
+            },
+        ))
+    })
+    .expect("node_a alive");
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    assert_eq!(breakdown.liquid, state.to_local_amount + state.to_remote_amount);
+    assert_eq!(breakdown.local_reserved, state.local_reserved_ckb_amount);
+    assert_eq!(breakdown.remote_reserved, state.remote_reserved_ckb_amount);
+    assert_eq!(
+        breakdown.total as u128,
+        breakdown.liquid + (breakdown.local_reserved + breakdown.remote_reserved) as u128
+    );
+    assert!(breakdown.commitment_fee > 0);
+    assert!(breakdown.commitment_fee < breakdown.local_reserved + breakdown.remote_reserved);
+    assert_eq!(breakdown.udt_amount, None);
+}
+
+#[tokio::test]
+async fn test_reject_public_channel_by_visibility_policy() {
+    init_tracing();
+
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(|config| {
+                config.accept_channel_visibility = Some(ChannelVisibilityPolicy::PrivateOnly);
+            })
+            .build()
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let node_a = nodes.pop().unwrap();
+
+    let open_channel_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: true,
+                shutdown_script: None,
+                funding_amount: 100000000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_a alive")
+    .expect("open channel success");
+
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelPendingToBeAccepted(peer_id, _) => {
+                peer_id == &node_a.peer_id
+            }
+            _ => false,
+        })
+        .await;
+
+    let accept_channel_result = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id: open_channel_result.channel_id,
+                funding_amount: DEFAULT_AUTO_ACCEPT_CHANNEL_CKB_FUNDING_AMOUNT as u128,
+                shutdown_script: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                min_tlc_value: None,
+                tlc_fee_proportional_millionths: None,
+                tlc_expiry_delta: None,
+                commitment_delay_epoch: None,
+                channel_reserve_amount: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_b alive");
+    assert!(accept_channel_result.is_err());
+
+    assert!(ChannelVisibilityPolicy::Any.accepts(true));
+    assert!(ChannelVisibilityPolicy::PublicOnly.accepts(true));
+    assert!(!ChannelVisibilityPolicy::PublicOnly.accepts(false));
+    assert!(ChannelVisibilityPolicy::PrivateOnly.accepts(false));
+}
+
+#[tokio::test]
+async fn test_commitment_capacity_at_reserve_boundary() {
+    init_tracing();
+
+    let (mut node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, false).await;
+
+    let mut state = node_a.get_channel_actor_state(new_channel_id);
+    let commitment_fee = calculate_commitment_tx_fee(
+        state.commitment_fee_rate,
+        &state.funding_udt_type_script,
+        state.anchor_outputs,
+    );
+    let anchor_capacity = if state.anchor_outputs {
+        anchor_capacity(&state.get_local_shutdown_script())
+            .expect("valid anchor capacity")
+            .as_u64()
+    } else {
+        0
+    };
+
+    // the reserved amount pays exactly the anchor output and the commitment fee
+    state.local_reserved_ckb_amount = anchor_capacity + commitment_fee;
+    assert!(state.check_commitment_capacity().is_ok());
+
+    // one shannon less fails with a capacity error instead of underflowing
+    state.local_reserved_ckb_amount = anchor_capacity + commitment_fee - 1;
+    assert!(matches!(
+        state.check_commitment_capacity(),
+        Err(ProcessingChannelError::CapacityError(_))
+    ));
+    node_a.update_channel_actor_state(state).await;
+
+    // signing the commitment fails, but the channel actor keeps working
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: gen_rand_sha256_hash(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert_eq!(
+        add_tlc_result.unwrap_err().error_code,
+        TlcErrorCode::TemporaryNodeFailure
+    );
+
+    let breakdown = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::GetCapacityBreakdown(rpc_reply),
+            },
+        ))
+    })
+    .expect("channel actor alive");
+    assert_eq!(breakdown.local_reserved, anchor_capacity + commitment_fee - 1);
+}
+
+#[tokio::test]
+async fn test_dump_tlc_state() {
+    init_tracing();
+
+    let (node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, false).await;
+
+    let preimage = gen_rand_sha256_hash();
+    let payment_hash: Hash256 = HashAlgorithm::CkbHash.hash(preimage).into();
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash,
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: Some(preimage),
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("successfully added tlc");
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let dump_tlc_state = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::DumpTlcState(rpc_reply),
+            },
+        ))
+    };
+    let dump = call!(node_a.network_actor, dump_tlc_state).expect("node_a alive");
+    assert!(!dump.waiting_ack);
+    assert_eq!(dump.local_pending_tlcs.next_tlc_id, 1);
+    assert_eq!(
+        dump.local_pending_tlcs.committed_index,
+        dump.local_pending_tlcs.tlcs.len()
+    );
+    match dump.local_pending_tlcs.tlcs.as_slice() {
+        [TlcDump::AddTlc {
+            tlc_id,
+            amount,
+            has_payment_preimage,
+            ..
+        }] => {
+            assert_eq!(*tlc_id, TLCId::Offered(add_tlc_result.tlc_id));
+            assert_eq!(*amount, 1000000);
+            assert!(*has_payment_preimage);
+        }
+        tlcs => panic!("unexpected local pending tlcs: {:?}", tlcs),
+    }
+    // the preimage is redacted from the dump
+    assert!(!format!("{:?}", dump).contains(&format!("{:?}", preimage)));
+
+    let dump = call!(node_b.network_actor, dump_tlc_state).expect("node_b alive");
+    assert_eq!(dump.remote_pending_tlcs.next_tlc_id, 1);
+    assert!(matches!(
+        dump.remote_pending_tlcs.tlcs.as_slice(),
+        [TlcDump::AddTlc { tlc_id: TLCId::Received(id), .. }] if *id == add_tlc_result.tlc_id
+    ));
+}
+
+#[tokio::test]
+async fn test_reject_channel_below_min_remote_reserve() {
+    init_tracing();
+
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(|config| {
+                config.accept_min_remote_reserve = Some(1000 * 100000000);
+            })
+            .build()
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let node_a = nodes.pop().unwrap();
+
+    let open_channel_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 100000000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_a alive")
+    .expect("open channel success");
+
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelPendingToBeAccepted(peer_id, _) => {
+                peer_id == &node_a.peer_id
+            }
+            _ => false,
+        })
+        .await;
+
+    let accept_channel_result = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id: open_channel_result.channel_id,
+                funding_amount: DEFAULT_AUTO_ACCEPT_CHANNEL_CKB_FUNDING_AMOUNT as u128,
+                shutdown_script: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                min_tlc_value: None,
+                tlc_fee_proportional_millionths: None,
+                tlc_expiry_delta: None,
+                commitment_delay_epoch: None,
+                channel_reserve_amount: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_b alive");
+    assert!(accept_channel_result.is_err());
+}
+
+#[tokio::test]
+async fn test_active_htlcs_ordering_matches_across_peers() {
+    init_tracing();
+
+    let (node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 16200000000, false).await;
+
+    // Interleave the tlcs offered by both parties with distinct amounts, so that the tlcs are
+    // added out of the order in which they are placed in the commitment lock.
+    for (node, amount) in [
+        (&node_a, 3000000),
+        (&node_b, 2000000),
+        (&node_a, 1000000),
+        (&node_b, 4000000),
+        (&node_a, 5000000),
+    ] {
+        call!(node.network_actor, |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: new_channel_id,
+                    command: ChannelCommand::AddTlc(
+                        AddTlcCommand {
+                            amount,
+                            hash_algorithm: HashAlgorithm::CkbHash,
+                            payment_hash: gen_rand_sha256_hash(),
+                            expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                            onion_packet: None,
+                            shared_secret: NO_SHARED_SECRET.clone(),
+                            previous_tlc: None,
+                            payment_preimage: None,
+                            idempotency_key: None,
+                        },
+                        rpc_reply,
+                    ),
+                },
+            ))
+        })
+        .expect("node alive")
+        .expect("successfully added tlc");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+
+    let state_a = node_a.get_channel_actor_state(new_channel_id);
+    let state_b = node_b.get_channel_actor_state(new_channel_id);
+    assert_eq!(state_a.get_all_offer_tlcs().count(), 3);
+    assert_eq!(state_b.get_all_offer_tlcs().count(), 2);
+
+    let htlcs = state_a.get_active_htlcs(false);
+    assert_eq!(htlcs.first(), Some(&5));
+    assert_eq!(htlcs, state_b.get_active_htlcs(true));
+    let htlcs = state_a.get_active_htlcs(true);
+    assert_eq!(htlcs.first(), Some(&5));
+    assert_eq!(htlcs, state_b.get_active_htlcs(false));
+}
+
+#[tokio::test]
+async fn test_add_tlc_with_idempotency_key() {
+    init_tracing();
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, false).await;
+
+    let payment_hash = gen_rand_sha256_hash();
+    let expiry = now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA;
+    let add_tlc = |payment_hash, idempotency_key: Option<&str>| {
+        let idempotency_key = idempotency_key.map(ToString::to_string);
+        move |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: new_channel_id,
+                    command: ChannelCommand::AddTlc(
+                        AddTlcCommand {
+                            amount: 1000000,
+                            hash_algorithm: HashAlgorithm::CkbHash,
+                            payment_hash,
+                            expiry,
+                            onion_packet: None,
+                            shared_secret: NO_SHARED_SECRET.clone(),
+                            previous_tlc: None,
+                            payment_preimage: None,
+                            idempotency_key,
+                        },
+                        rpc_reply,
+                    ),
+                },
+            ))
+        }
+    };
+
+    let response = call!(node_a.network_actor, add_tlc(payment_hash, Some("retry-1")))
+        .expect("node_a alive")
+        .expect("successfully added tlc");
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // the retry with the same key returns the tlc added by the first attempt
+    let retried_response = call!(node_a.network_actor, add_tlc(payment_hash, Some("retry-1")))
+        .expect("node_a alive")
+        .expect("retry returns the added tlc");
+    assert_eq!(retried_response.tlc_id, response.tlc_id);
+    assert_eq!(retried_response.created_at, response.created_at);
+    assert_eq!(
+        node_a
+            .get_channel_actor_state(new_channel_id)
+            .get_all_offer_tlcs()
+            .count(),
+        1
+    );
+
+    // the retry without the key is still rejected as a repeated tlc
+    let result = call!(node_a.network_actor, add_tlc(payment_hash, None)).expect("node_a alive");
+    assert!(result.is_err());
+
+    // the key can't be reused by another tlc
+    let result = call!(node_a.network_actor, add_tlc(gen_rand_sha256_hash(), Some("retry-1")))
+        .expect("node_a alive");
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_reject_shutdown_below_min_net_close_balance() {
+    init_tracing();
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, false).await;
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    let net_close_balance = state.to_local_amount as u64 + state.local_reserved_ckb_amount;
+    let shutdown = |min_net_close_balance| {
+        move |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: new_channel_id,
+                    command: ChannelCommand::Shutdown(
+                        ShutdownCommand {
+                            close_script: Script::default(),
+                            fee_rate: FeeRate::from_u64(DEFAULT_COMMITMENT_FEE_RATE),
+                            force: false,
+                            allow_unknown_close_script: false,
+                            target_blocks: None,
+                            min_net_close_balance: Some(min_net_close_balance),
+                        },
+                        rpc_reply,
+                    ),
+                },
+            ))
+        }
+    };
+
+    // the fee and the occupied capacity are deducted from the balance
+    let result = call!(node_a.network_actor, shutdown(net_close_balance)).expect("node_a alive");
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("closing the channel is not economical"));
+    assert_eq!(
+        node_a.get_channel_actor_state(new_channel_id).state,
+        ChannelState::ChannelReady()
+    );
+
+    call!(node_a.network_actor, shutdown(net_close_balance / 2))
+        .expect("node_a alive")
+        .expect("successfully shutdown channel");
+    assert!(matches!(
+        node_a.get_channel_actor_state(new_channel_id).state,
+        ChannelState::ShuttingDown(_) | ChannelState::Closed(_)
+    ));
+}
+
+#[tokio::test]
+async fn test_get_musig2_info() {
+    init_tracing();
+
+    let (node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, false).await;
+
+    let get_musig2_info = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::GetMusig2Info(rpc_reply),
+            },
+        ))
+    };
+    let info_a = call!(node_a.network_actor, get_musig2_info)
+        .expect("node_a alive")
+        .expect("get musig2 info");
+    let info_b = call!(node_b.network_actor, get_musig2_info)
+        .expect("node_b alive")
+        .expect("get musig2 info");
+
+    // Both parties aggregate the same pubkey in the same order.
+    assert_eq!(info_a.x_only_aggregated_pubkey, info_b.x_only_aggregated_pubkey);
+    assert_eq!(info_a.funding_lock_script, info_b.funding_lock_script);
+    assert_ne!(info_a.local_goes_first, info_b.local_goes_first);
+
+    let funding_output = node_a
+        .get_channel_actor_state(new_channel_id)
+        .must_get_funding_transaction()
+        .raw()
+        .outputs()
+        .get(0)
+        .expect("funding output exists");
+    assert_eq!(funding_output.lock(), info_a.funding_lock_script);
+}
+
+#[tokio::test]
+async fn test_hold_tlc_without_invoice_for_approval() {
+    init_tracing();
+
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(|config| {
+                config.no_invoice_policy = Some(NoInvoicePolicy::HoldForApproval);
+            })
+            .build()
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let mut node_a = nodes.pop().unwrap();
+    let (new_channel_id, _funding_tx) = establish_channel_between_nodes(
+        &mut node_a,
+        &mut node_b,
+        true,
+        100000000000,
+        100000000000,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let res = node_a
+        .send_payment(SendPaymentCommand {
+            target_pubkey: Some(node_b.pubkey.clone()),
+            amount: Some(1000000),
+            payment_hash: None,
+            final_tlc_expiry_delta: None,
+            invoice: None,
+            timeout: None,
+            max_fee_amount: None,
+            tlc_expiry_limit: None,
+            max_parts: None,
+            keysend: Some(true),
+            udt_type_script: None,
+            allow_self_payment: false,
+            dry_run: false,
+            custom_records: vec![],
+        })
+        .await
+        .expect("send payment");
+    let payment_hash = res.payment_hash;
+
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::TlcPendingApproval(channel_id, hash, _) => {
+                channel_id == &new_channel_id && hash == &payment_hash
+            }
+            _ => false,
+        })
+        .await;
+    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    node_a
+        .assert_payment_status(payment_hash, PaymentSessionStatus::Inflight, None)
+        .await;
+
+    let approve_tlc = |payment_hash| {
+        move |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: new_channel_id,
+                    command: ChannelCommand::ApproveTlc(payment_hash, rpc_reply),
+                },
+            ))
+        }
+    };
+    let result = call!(node_b.network_actor, approve_tlc(gen_rand_sha256_hash()))
+        .expect("node_b alive");
+    assert!(result.is_err());
+
+    call!(node_b.network_actor, approve_tlc(payment_hash))
+        .expect("node_b alive")
+        .expect("approve tlc");
+    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    node_a
+        .assert_payment_status(payment_hash, PaymentSessionStatus::Success, None)
+        .await;
+    assert!(node_b
+        .get_channel_actor_state(new_channel_id)
+        .tlcs_pending_approval
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_hold_tlc_for_approval_across_restart() {
+    init_tracing();
+
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(|config| {
+                config.no_invoice_policy = Some(NoInvoicePolicy::HoldForApproval);
+            })
+            .build()
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let mut node_a = nodes.pop().unwrap();
+    let (new_channel_id, _funding_tx) = establish_channel_between_nodes(
+        &mut node_a,
+        &mut node_b,
+        true,
+        100000000000,
+        100000000000,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let res = node_a
+        .send_payment(SendPaymentCommand {
+            target_pubkey: Some(node_b.pubkey.clone()),
+            amount: Some(1000000),
+            payment_hash: None,
+            final_tlc_expiry_delta: None,
+            invoice: None,
+            timeout: None,
+            max_fee_amount: None,
+            tlc_expiry_limit: None,
+            max_parts: None,
+            keysend: Some(true),
+            udt_type_script: None,
+            allow_self_payment: false,
+            dry_run: false,
+            custom_records: vec![],
+        })
+        .await
+        .expect("send payment");
+    let payment_hash = res.payment_hash;
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::TlcPendingApproval(channel_id, hash, _) => {
+                channel_id == &new_channel_id && hash == &payment_hash
+            }
+            _ => false,
+        })
+        .await;
+
+    // The tlc is still pending approval after the restart, and the check of its expiry is
+    // scheduled again while reestablishing the channel.
+    node_b.restart().await;
+    node_b
+        .expect_event(|event| {
+            matches!(event, NetworkServiceEvent::PeerConnected(id, _addr) if id == &node_a.peer_id)
+        })
+        .await;
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    assert_eq!(
+        node_b
+            .get_channel_actor_state(new_channel_id)
+            .tlcs_pending_approval
+            .len(),
+        1
+    );
+    node_a
+        .assert_payment_status(payment_hash, PaymentSessionStatus::Inflight, None)
+        .await;
+
+    call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::ApproveTlc(payment_hash, rpc_reply),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("approve tlc");
+    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    node_a
+        .assert_payment_status(payment_hash, PaymentSessionStatus::Success, None)
+        .await;
+}
+
+#[tokio::test]
+async fn test_resync_commitment() {
+    init_tracing();
+
+    let (node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, false).await;
+
+    let resync_commitment = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::ResyncCommitment(rpc_reply),
+            },
+        ))
+    };
+    let remote_commitment_number = node_a
+        .get_channel_actor_state(new_channel_id)
+        .get_remote_commitment_number();
+    call!(node_a.network_actor, resync_commitment)
+        .expect("node_a alive")
+        .expect("resync commitment");
+
+    let state_a = node_a.get_channel_actor_state(new_channel_id);
+    let state_b = node_b.get_channel_actor_state(new_channel_id);
+    assert_eq!(
+        state_a.get_remote_commitment_number(),
+        remote_commitment_number + 1
+    );
+    assert_eq!(
+        state_a.get_remote_commitment_number(),
+        state_b.get_local_commitment_number()
+    );
+    assert!(!state_a.tlc_state.dump().waiting_ack);
+
+    let preimage = [2; 32];
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: HashAlgorithm::CkbHash.hash(&preimage).into(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully added tlc");
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    // The tlc is still in flight.
+    let result = call!(node_a.network_actor, resync_commitment).expect("node_a alive");
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_resync_commitment_timeout() {
+    init_tracing();
+
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(|config| {
+                config.peer_disconnect_grace_seconds = Some(60);
+                config.commitment_resync_timeout_seconds = Some(1);
+            })
+            .build()
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let mut node_a = nodes.pop().unwrap();
+    let (new_channel_id, _funding_tx) = establish_channel_between_nodes(
+        &mut node_a,
+        &mut node_b,
+        false,
+        16200000000,
+        6200000000,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await;
+
+    // The peer is disconnected so it never revokes the resynced commitment.
+    node_a
+        .network_actor
+        .send_message(NetworkActorMessage::new_command(
+            NetworkActorCommand::DisconnectPeer(node_b.peer_id.clone()),
+        ))
+        .expect("node_a alive");
+    node_a
+        .expect_event(|event| matches!(event, NetworkServiceEvent::PeerDisConnected(_, _)))
+        .await;
+
+    let result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::ResyncCommitment(rpc_reply),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_add_tlc_info_without_onion_packet() {
+    let add_tlc = AddTlcInfo {
+        amount: 10000,
+        channel_id: gen_rand_sha256_hash(),
+        payment_hash: gen_rand_sha256_hash(),
+        expiry: now_timestamp_as_millis_u64() + 1000,
+        hash_algorithm: HashAlgorithm::Sha256,
+        onion_packet: Some(PaymentOnionPacket::new(vec![0; 1300])),
+        shared_secret: NO_SHARED_SECRET.clone(),
+        tlc_id: TLCId::Received(0),
+        created_at: CommitmentNumbers::default(),
+        removed_at: None,
+        payment_preimage: Some(gen_rand_sha256_hash()),
+        previous_tlc: None,
+        custom_records: vec![(1, vec![1, 2, 3])],
+        next_hop: None,
+    };
+
+    let tlc = add_tlc.without_onion_packet();
+    assert_eq!(tlc.onion_packet, None);
+    assert_eq!(
+        tlc,
+        AddTlcInfo {
+            onion_packet: None,
+            ..add_tlc
+        }
+    );
+}
+
+// A benchmark of copying the tlcs without the onion packets for the tlc notifications, compared
+// with cloning the whole tlcs. Run it with
+// `cargo test --release bench_add_tlc_info_without_onion_packet -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn bench_add_tlc_info_without_onion_packet() {
+    const ROUNDS: u32 = 100000;
+
+    let add_tlc = AddTlcInfo {
+        amount: 10000,
+        channel_id: gen_rand_sha256_hash(),
+        payment_hash: gen_rand_sha256_hash(),
+        expiry: now_timestamp_as_millis_u64() + 1000,
+        hash_algorithm: HashAlgorithm::Sha256,
+        onion_packet: Some(PaymentOnionPacket::new(vec![0; 6500])),
+        shared_secret: NO_SHARED_SECRET.clone(),
+        tlc_id: TLCId::Received(0),
+        created_at: CommitmentNumbers::default(),
+        removed_at: None,
+        payment_preimage: None,
+        previous_tlc: None,
+        custom_records: vec![],
+        next_hop: None,
+    };
+
+    let started_at = std::time::Instant::now();
+    for _ in 0..ROUNDS {
+        std::hint::black_box(add_tlc.without_onion_packet());
+    }
+    let without_onion_packet_elapsed = started_at.elapsed();
+
+    let started_at = std::time::Instant::now();
+    for _ in 0..ROUNDS {
+        std::hint::black_box(add_tlc.clone());
+    }
+    let clone_elapsed = started_at.elapsed();
+
+    println!(
+        "{} tlcs copied without the onion packets took {:?}, cloned took {:?}",
+        ROUNDS, without_onion_packet_elapsed, clone_elapsed
+    );
+}
+
+#[tokio::test]
+async fn test_reject_channel_message_on_another_chain() {
+    init_tracing();
+
+    let (node_a, mut node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, false).await;
+
+    let mut state_b = node_b.get_channel_actor_state(new_channel_id);
+    state_b.chain_hash = Some(gen_rand_sha256_hash());
+    node_b.update_channel_actor_state(state_b).await;
+
+    let preimage = [3; 32];
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: HashAlgorithm::CkbHash.hash(&preimage).into(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully added tlc");
+
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::DebugEvent(DebugEvent::Common(error)) => {
+                error.contains("is created on chain")
+            }
+            _ => false,
+        })
+        .await;
+    assert!(node_b
+        .get_channel_actor_state(new_channel_id)
+        .tlc_state
+        .all_tlcs()
+        .next()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_channel_chain_hash_from_open_channel() {
+    init_tracing();
+
+    let (node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, false).await;
+
+    // The opener records the chain hash it sent in OpenChannel, and the acceptor records the
+    // chain hash received in OpenChannel.
+    assert_eq!(
+        node_a.get_channel_actor_state(new_channel_id).chain_hash,
+        Some(get_chain_hash())
+    );
+    assert_eq!(
+        node_b.get_channel_actor_state(new_channel_id).chain_hash,
+        Some(get_chain_hash())
+    );
+}
+
+#[tokio::test]
+async fn test_get_payment_proof() {
+    init_tracing();
+
+    let (mut node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    let get_payment_proof = |payment_hash| {
+        move |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: new_channel_id,
+                    command: ChannelCommand::GetPaymentProof(payment_hash, rpc_reply),
+                },
+            ))
+        }
+    };
+
+    let res = node_a
+        .send_payment(SendPaymentCommand {
+            target_pubkey: Some(node_b.pubkey.clone()),
+            amount: Some(1000000),
+            payment_hash: None,
+            final_tlc_expiry_delta: None,
+            invoice: None,
+            timeout: None,
+            max_fee_amount: None,
+            tlc_expiry_limit: None,
+            max_parts: None,
+            keysend: Some(true),
+            udt_type_script: None,
+            allow_self_payment: false,
+            dry_run: false,
+            custom_records: vec![],
+        })
+        .await
+        .expect("send payment");
+    let payment_hash = res.payment_hash;
+    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    node_a
+        .assert_payment_status(payment_hash, PaymentSessionStatus::Success, None)
+        .await;
+
+    let proof_a = call!(node_a.network_actor, get_payment_proof(payment_hash))
+        .expect("node_a alive")
+        .expect("payment proof");
+    let proof_b = call!(node_b.network_actor, get_payment_proof(payment_hash))
+        .expect("node_b alive")
+        .expect("payment proof");
+    assert_eq!(proof_a, proof_b);
+
+    // The tlc without a known preimage is failed by the final hop.
+    let res = node_a
+        .send_payment(SendPaymentCommand {
+            target_pubkey: Some(node_b.pubkey.clone()),
+            amount: Some(1000000),
+            payment_hash: Some(gen_rand_sha256_hash()),
+            final_tlc_expiry_delta: None,
+            invoice: None,
+            timeout: None,
+            max_fee_amount: None,
+            tlc_expiry_limit: None,
+            max_parts: None,
+            keysend: None,
+            udt_type_script: None,
+            allow_self_payment: false,
+            dry_run: false,
+            custom_records: vec![],
+        })
+        .await
+        .expect("send payment");
+    let failed_payment_hash = res.payment_hash;
+    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    node_a
+        .assert_payment_status(failed_payment_hash, PaymentSessionStatus::Failed, None)
+        .await;
+
+    let proof = call!(node_a.network_actor, get_payment_proof(failed_payment_hash))
+        .expect("node_a alive");
+    assert_eq!(proof, None);
+}
+
+#[test]
+fn test_reestablish_backoff_delay() {
+    let backoff = ReestablishBackoff {
+        base: Duration::from_secs(1),
+        cap: Duration::from_secs(10),
+        timeout: None,
+    };
+    assert_eq!(backoff.delay(0), Duration::ZERO);
+    assert_eq!(backoff.delay(1), Duration::from_secs(1));
+    assert_eq!(backoff.delay(2), Duration::from_secs(2));
+    assert_eq!(backoff.delay(4), Duration::from_secs(8));
+    assert_eq!(backoff.delay(5), Duration::from_secs(10));
+    assert_eq!(backoff.delay(u64::MAX), Duration::from_secs(10));
+}
+
+#[tokio::test]
+async fn test_reestablish_channel_with_flapping_peer() {
+    init_tracing();
+
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(|config| {
+                config.peer_disconnect_grace_seconds = Some(60);
+            })
+            .build()
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let mut node_a = nodes.pop().unwrap();
+    let (new_channel_id, _funding_tx) = establish_channel_between_nodes(
+        &mut node_a,
+        &mut node_b,
+        true,
+        16200000000,
+        6200000000,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await;
+
+    // The channel actors are kept alive across the reconnections, the delayed ReestablishChannel
+    // messages of the superseded attempts are not sent.
+    for _ in 0..3 {
+        node_a
+            .network_actor
+            .send_message(NetworkActorMessage::new_command(
+                NetworkActorCommand::DisconnectPeer(node_b.peer_id.clone()),
+            ))
+            .expect("node_a alive");
+        node_a
+            .expect_event(|event| matches!(event, NetworkServiceEvent::PeerDisConnected(_, _)))
+            .await;
+        node_a.connect_to(&node_b).await;
+    }
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(3000)).await;
+    assert!(!node_a.get_channel_actor_state(new_channel_id).reestablishing);
+    assert!(!node_b.get_channel_actor_state(new_channel_id).reestablishing);
+}
+
+#[tokio::test]
+async fn test_lifetime_fees_of_forwarded_payments() {
+    init_tracing();
+
+    let (mut node_a, mut node_b, node_c, channel_1, channel_2) =
+        create_3_nodes_with_established_channel(
+            (100000000000, 100000000000),
+            (100000000000, 100000000000),
+            true,
+        )
+        .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let mut total_fee = 0;
+    for amount in [1000000, 2000000] {
+        let res = node_a
+            .send_payment(SendPaymentCommand {
+                target_pubkey: Some(node_c.pubkey.clone()),
+                amount: Some(amount),
+                payment_hash: None,
+                final_tlc_expiry_delta: None,
+                invoice: None,
+                timeout: None,
+                max_fee_amount: None,
+                tlc_expiry_limit: None,
+                max_parts: None,
+                keysend: Some(true),
+                udt_type_script: None,
+                allow_self_payment: false,
+                dry_run: false,
+                custom_records: vec![],
+            })
+            .await
+            .expect("send payment success");
+        tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+        node_a
+            .assert_payment_status(res.payment_hash, PaymentSessionStatus::Success, None)
+            .await;
+        total_fee += res.fee;
+    }
+    assert!(total_fee > 0);
+
+    let get_lifetime_fees = |channel_id| {
+        move |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id,
+                    command: ChannelCommand::GetLifetimeFees(rpc_reply),
+                },
+            ))
+        }
+    };
+    // The fees are counted on the outgoing channel.
+    let fees = call!(node_b.network_actor, get_lifetime_fees(channel_2)).expect("node_b alive");
+    assert_eq!(fees, total_fee);
+    let fees = call!(node_b.network_actor, get_lifetime_fees(channel_1)).expect("node_b alive");
+    assert_eq!(fees, 0);
+
+    // The counter is persisted with the channel state.
+    node_b.restart().await;
+    assert_eq!(
+        node_b.get_channel_actor_state(channel_2).total_fees_earned,
+        total_fee
+    );
+}
+
+#[tokio::test]
+async fn test_reject_received_tlc_beyond_channel_max_expiry_delta() {
+    init_tracing();
+
+    let (node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    let max_tlc_expiry_delta = 24 * 60 * 60 * 1000;
+    call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::Update(
+                    UpdateCommand {
+                        enabled: None,
+                        tlc_expiry_delta: None,
+                        tlc_minimum_value: None,
+                        tlc_fee_proportional_millionths: None,
+                        min_tlc_expiry_delta: None,
+                        max_tlc_expiry_delta: Some(max_tlc_expiry_delta),
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("update channel");
+
+    // The expiry is within the protocol maximum, but beyond the window of node_b.
+    let preimage = [4; 32];
+    let payment_hash: Hash256 = HashAlgorithm::CkbHash.hash(&preimage).into();
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash,
+                        expiry: now_timestamp_as_millis_u64() + 2 * max_tlc_expiry_delta,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully added tlc");
+
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::DebugEvent(DebugEvent::AddTlcFailed(_, hash, err)) => {
+                assert_eq!(hash, &payment_hash);
+                assert_eq!(err.error_code, TlcErrorCode::ExpiryTooFar);
+                true
+            }
+            _ => false,
+        })
+        .await;
+}
+
+// Deny forwarding the tlcs whose forwarded amounts exceed the maximal amount.
+struct MaxAmountForwardPolicy {
+    max_amount: u128,
+    consulted: AtomicUsize,
+}
+
+impl ForwardPolicy for MaxAmountForwardPolicy {
+    fn should_forward(
+        &self,
+        _incoming: &AddTlcInfo,
+        next_hop: &PeeledPaymentOnionPacket,
+    ) -> ForwardDecision {
+        self.consulted.fetch_add(1, Ordering::SeqCst);
+        if next_hop.current.amount > self.max_amount {
+            ForwardDecision::Deny(TlcErrorCode::TemporaryNodeFailure)
+        } else {
+            ForwardDecision::Forward
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_forward_policy_denies_forwarding_tlc() {
+    init_tracing();
+
+    let forward_policy = Arc::new(MaxAmountForwardPolicy {
+        max_amount: 1000000,
+        consulted: AtomicUsize::new(0),
+    });
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(3, |i| {
+        let builder = NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i));
+        if i == 1 {
+            builder.forward_policy(forward_policy.clone()).build()
+        } else {
+            builder.build()
+        }
+    })
+    .await;
+    for (i, j) in [(0, 1), (1, 2)] {
+        let (left, right) = nodes.split_at_mut(j);
+        let (_channel_id, funding_tx) = establish_channel_between_nodes(
+            &mut left[i],
+            &mut right[0],
+            true,
+            100000000000,
+            100000000000,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await;
+        for node in nodes.iter_mut() {
+            assert_eq!(node.submit_tx(funding_tx.clone()).await, Status::Committed);
+        }
+    }
+    let [mut node_a, _node_b, node_c] = nodes.try_into().expect("3 nodes");
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let send_payment_command = |amount| SendPaymentCommand {
+        target_pubkey: Some(node_c.pubkey.clone()),
+        amount: Some(amount),
+        payment_hash: None,
+        final_tlc_expiry_delta: None,
+        invoice: None,
+        timeout: None,
+        max_fee_amount: None,
+        tlc_expiry_limit: None,
+        max_parts: None,
+        keysend: Some(true),
+        udt_type_script: None,
+        allow_self_payment: false,
+        dry_run: false,
+        custom_records: vec![],
+    };
+
+    let res = node_a
+        .send_payment(send_payment_command(2000000))
+        .await
+        .expect("send payment");
+    tokio::time::sleep(tokio::time::Duration::from_secs(4)).await;
+    node_a
+        .assert_payment_status(res.payment_hash, PaymentSessionStatus::Failed, None)
+        .await;
+    assert!(forward_policy.consulted.load(Ordering::SeqCst) >= 1);
+
+    let res = node_a
+        .send_payment(send_payment_command(1000000))
+        .await
+        .expect("send payment");
+    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    node_a
+        .assert_payment_status(res.payment_hash, PaymentSessionStatus::Success, None)
+        .await;
+}
+
+#[test]
+fn test_mpp_payment_timed_out() {
+    let now = now_timestamp_as_millis_u64();
+    let mut mpp_payment = MppPayment::new(3000);
+    mpp_payment.parts.insert(0, 1000);
+    mpp_payment.expiry = now + MIN_TLC_EXPIRY_DELTA + 1000;
+    assert_eq!(mpp_payment.received_amount(), 1000);
+    assert!(!mpp_payment.is_timed_out(now));
+    assert!(mpp_payment.is_timed_out(now + 1001));
+
+    // a completed payment is settled instead of timed out
+    mpp_payment.completed = true;
+    assert!(!mpp_payment.is_timed_out(now + 1001));
+}
+
+#[tokio::test]
+async fn test_receive_multi_part_payment() {
+    init_tracing();
+
+    let (node_a, node_b, channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 100000000000, false)
+            .await;
+
+    let preimage = gen_rand_sha256_hash();
+    let payment_hash: Hash256 = HashAlgorithm::CkbHash.hash(preimage).into();
+    let (part_amount, total_amount) = (1000000000, 2000000000);
+    let expiry = now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA;
+    let add_part_command = || {
+        let hops_infos = vec![
+            PaymentHopData {
+                amount: part_amount,
+                expiry,
+                next_hop: Some(node_b.pubkey.clone()),
+                funding_tx_hash: Hash256::default(),
+                hash_algorithm: HashAlgorithm::CkbHash,
+                payment_preimage: None,
+                custom_records: vec![],
+                total_amount: None,
+            },
+            PaymentHopData {
+                amount: part_amount,
+                expiry,
+                next_hop: None,
+                funding_tx_hash: Hash256::default(),
+                hash_algorithm: HashAlgorithm::CkbHash,
+                payment_preimage: Some(preimage),
+                custom_records: vec![],
+                total_amount: Some(total_amount),
+            },
+        ];
+        let packet = PeeledOnionPacket::create(
+            gen_rand_fiber_private_key(),
+            hops_infos,
+            Some(payment_hash.as_ref().to_vec()),
+            &Secp256k1::new(),
+        )
+        .expect("create peeled packet");
+        AddTlcCommand {
+            amount: part_amount,
+            payment_hash,
+            expiry,
+            hash_algorithm: HashAlgorithm::CkbHash,
+            onion_packet: packet.next.clone(),
+            shared_secret: packet.shared_secret.clone(),
+            previous_tlc: None,
+            payment_preimage: None,
+            idempotency_key: None,
+        }
+    };
+    let to_local_amount = node_b.get_channel_actor_state(channel_id).to_local_amount;
+
+    for i in 1..=2 {
+        let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id,
+                    command: ChannelCommand::AddTlc(add_part_command(), rpc_reply),
+                },
+            ))
+        })
+        .expect("node_a alive");
+        assert!(add_tlc_result.is_ok());
+        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+
+        let state = node_b.get_channel_actor_state(channel_id);
+        if i == 1 {
+            // the first part is held until the other parts are received
+            let mpp_payment = state.mpp_payments.get(&payment_hash).expect("mpp payment");
+            assert_eq!(mpp_payment.received_amount(), part_amount);
+            assert!(!mpp_payment.completed);
+            assert_eq!(state.to_local_amount, to_local_amount);
+        } else {
+            // both parts are fulfilled once the total amount is received
+            assert_eq!(state.to_local_amount, to_local_amount + total_amount);
+            assert!(state.mpp_payments.is_empty());
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_get_commitment_point_info() {
+    init_tracing();
+
+    let (node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, false).await;
+
+    let get_commitment_point_info = |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::GetCommitmentPointInfo(rpc_reply),
+            },
+        ))
+    };
+    let info_a = call!(node_a.network_actor, get_commitment_point_info).expect("node_a alive");
+    let info_b = call!(node_b.network_actor, get_commitment_point_info).expect("node_b alive");
+
+    let state_a = node_a.get_channel_actor_state(new_channel_id);
+    assert_eq!(info_a.local_commitment_number, state_a.get_local_commitment_number());
+    assert_eq!(info_a.remote_commitment_number, state_a.get_remote_commitment_number());
+    // The commitment numbers of both parties are in sync after the channel is ready.
+    assert_eq!(info_a.local_commitment_number, info_b.remote_commitment_number);
+    assert_eq!(info_a.remote_commitment_number, info_b.local_commitment_number);
+
+    // The peer already knows the commitment point we send next.
+    let state_b = node_b.get_channel_actor_state(new_channel_id);
+    assert!(state_b
+        .remote_commitment_points
+        .iter()
+        .any(|(_, point)| point == &info_a.next_commitment_point));
+}
+
+#[tokio::test]
+async fn test_sorted_settlement_outputs() {
+    init_tracing();
+
+    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+
+    let open_channel_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::OpenChannel(
+            OpenChannelCommand {
+                peer_id: node_b.peer_id.clone(),
+                public: false,
+                shutdown_script: None,
+                funding_amount: 16200000000,
+                funding_udt_type_script: None,
+                commitment_fee_rate: None,
+                commitment_delay_epoch: None,
+                funding_fee_rate: None,
+                tlc_expiry_delta: None,
+                tlc_min_value: None,
+                tlc_fee_proportional_millionths: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: true,
+                push_amount: None,
+                tlc_dust_limit: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_a alive")
+    .expect("open channel success");
+
+    node_b
+        .expect_event(|event| {
+            matches!(event, NetworkServiceEvent::ChannelPendingToBeAccepted(_, _))
+        })
+        .await;
+
+    let accept_channel_result = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::AcceptChannel(
+            AcceptChannelCommand {
+                temp_channel_id: open_channel_result.channel_id,
+                funding_amount: 6200000000,
+                shutdown_script: None,
+                max_tlc_number_in_flight: None,
+                max_tlc_value_in_flight: None,
+                min_tlc_value: None,
+                tlc_fee_proportional_millionths: None,
+                tlc_expiry_delta: None,
+                commitment_delay_epoch: None,
+                channel_reserve_amount: None,
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_b alive")
+    .expect("accept channel success");
+    let new_channel_id = accept_channel_result.new_channel_id;
+
+    node_a
+        .expect_event(|event| matches!(event, NetworkServiceEvent::ChannelReady(_, _, _)))
+        .await;
+
+    assert!(node_a.get_channel_actor_state(new_channel_id).sorted_settlement_outputs);
+    assert!(node_b.get_channel_actor_state(new_channel_id).sorted_settlement_outputs);
+
+    let preimage = [2; 32];
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: HashAlgorithm::CkbHash.hash(&preimage).into(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert!(add_tlc_result.is_ok());
+
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    // both parties order the settlement outputs the same way, so the signatures still aggregate
+    for node in [&node_a, &node_b] {
+        let state = node.get_channel_actor_state(new_channel_id);
+        assert_eq!(state.state, ChannelState::ChannelReady());
+        let settlement_data = state
+            .latest_settlement_data
+            .expect("settlement data recorded");
+        assert!(
+            settlement_output_sort_key(
+                &settlement_data.to_local_output,
+                &settlement_data.to_local_output_data
+            ) <= settlement_output_sort_key(
+                &settlement_data.to_remote_output,
+                &settlement_data.to_remote_output_data
+            )
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_reject_duplicate_revoke_and_ack() {
+    init_tracing();
+
+    let (mut node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, true).await;
+
+    let preimage = [3; 32];
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 1000000000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: HashAlgorithm::CkbHash.hash(&preimage).into(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert!(add_tlc_result.is_ok());
+
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    // replay the last RevokeAndAck received by node_a
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    // the nonce of the last CommitmentSigned is cleared once its RevokeAndAck is consumed
+    assert!(state.last_used_nonce_in_commitment_signed.is_none());
+    let (_, next_per_commitment_point) = *state
+        .remote_commitment_points
+        .last()
+        .expect("remote commitment points recorded");
+    let (_, last_per_commitment_secret) = *state
+        .remote_commitment_secrets
+        .last()
+        .expect("remote commitment secrets recorded");
+    let (_, partial_signature) = state
+        .public_channel_info
+        .as_ref()
+        .and_then(|info| info.remote_channel_announcement_signature.clone())
+        .expect("remote announcement signatures received");
+    node_b
+        .network_actor
+        .send_message(NetworkActorMessage::new_command(
+            NetworkActorCommand::SendFiberMessage(FiberMessageWithPeerId::new(
+                node_a.peer_id.clone(),
+                FiberMessage::revoke_and_ack(RevokeAndAck {
+                    channel_id: new_channel_id,
+                    revocation_partial_signature: partial_signature,
+                    commitment_tx_partial_signature: partial_signature,
+                    next_per_commitment_point,
+                    last_per_commitment_secret: Some(last_per_commitment_secret),
+                }),
+            )),
+        ))
+        .expect("node_b alive");
+
+    node_a
+        .expect_event(|event| match event {
+            NetworkServiceEvent::DebugEvent(DebugEvent::Common(error)) => {
+                error.contains("unexpected RevokeAndAck")
+            }
+            _ => false,
+        })
+        .await;
+
+    let new_state = node_a.get_channel_actor_state(new_channel_id);
+    assert_eq!(new_state.commitment_numbers, state.commitment_numbers);
+    assert_eq!(new_state.remote_commitment_points, state.remote_commitment_points);
+    assert_eq!(new_state.state, ChannelState::ChannelReady());
+}
+
+#[tokio::test]
+async fn test_build_shutdown_tx_omits_dust_output() {
+    init_tracing();
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, false).await;
+
+    let mut state = node_a.get_channel_actor_state(new_channel_id);
+    let local_shutdown_script = state.get_local_shutdown_script();
+    let remote_shutdown_script = state.get_remote_shutdown_script();
+    let shutdown_info = |close_script| ShutdownInfo {
+        close_script,
+        fee_rate: DEFAULT_FEE_RATE,
+        signature: None,
+    };
+    state.local_shutdown_info = Some(shutdown_info(local_shutdown_script.clone()));
+    state.remote_shutdown_info = Some(shutdown_info(remote_shutdown_script.clone()));
+
+    // the reserved amount of the remote party with zero balance still covers its output
+    state.to_remote_amount = 0;
+    let shutdown_tx = state.build_shutdown_tx().expect("build shutdown tx");
+    assert_eq!(shutdown_tx.outputs().len(), 2);
+
+    // the remote party can't afford its output after paying the fee
+    state.remote_reserved_ckb_amount = occupied_capacity(&remote_shutdown_script, &None)
+        .expect("valid capacity")
+        .as_u64();
+    let shutdown_tx = state.build_shutdown_tx().expect("build shutdown tx");
+    assert_eq!(shutdown_tx.outputs().len(), 1);
+    assert_eq!(shutdown_tx.outputs_data().len(), 1);
+    let output = shutdown_tx.output(0).expect("local output");
+    assert_eq!(output.lock(), local_shutdown_script);
+    let capacity: u64 = output.capacity().unpack();
+    let local_shutdown_fee = calculate_shutdown_tx_fee(
+        DEFAULT_FEE_RATE,
+        &None,
+        (remote_shutdown_script, local_shutdown_script.clone()),
+    );
+    assert_eq!(
+        capacity,
+        state.to_local_amount as u64 + state.local_reserved_ckb_amount - local_shutdown_fee
+    );
+    let local_occupied_capacity = occupied_capacity(&local_shutdown_script, &None)
+        .expect("valid capacity")
+        .as_u64();
+    assert!(capacity >= local_occupied_capacity);
+
+    // no output is left if neither party can afford its output
+    state.to_local_amount = 0;
+    state.local_reserved_ckb_amount = local_occupied_capacity;
+    assert!(state.build_shutdown_tx().is_err());
+}
+
+#[tokio::test]
+async fn test_get_channel_summaries_with_peer() {
+    init_tracing();
+
+    let [mut node_a, mut node_b] = NetworkNode::new_n_interconnected_nodes().await;
+    let mut channel_ids = vec![];
+    for public in [true, false] {
+        let (channel_id, _funding_tx) = establish_channel_between_nodes(
+            &mut node_a,
+            &mut node_b,
+            public,
+            16200000000,
+            6200000000,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await;
+        channel_ids.push(channel_id);
+    }
+
+    let summary = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: channel_ids[0],
+                command: ChannelCommand::GetChannelSummary(rpc_reply),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    let state = node_a.get_channel_actor_state(channel_ids[0]);
+    assert_eq!(summary, state.channel_summary());
+    assert_eq!(summary.channel_id, channel_ids[0]);
+    assert_eq!(summary.remote_pubkey, node_b.pubkey);
+    assert_eq!(summary.state, ChannelState::ChannelReady());
+    assert!(summary.is_public);
+    assert!(summary.enabled);
+    assert!(summary.funding_outpoint.is_some());
+    assert!(summary.funding_udt_type_script.is_none());
+
+    // the balances of all channels with the peer can be summed up from the summaries
+    let summaries = node_a.store.get_channel_summaries_by_peer(&node_b.peer_id);
+    assert_eq!(summaries.len(), 2);
+    assert!(summaries.iter().any(|summary| !summary.is_public));
+    let total_local_amount: u128 = summaries.iter().map(|s| s.to_local_amount).sum();
+    let expected_total_local_amount: u128 = channel_ids
+        .iter()
+        .map(|id| node_a.get_channel_actor_state(*id).to_local_amount)
+        .sum();
+    assert_eq!(total_local_amount, expected_total_local_amount);
+    let total_remote_amount: u128 = summaries.iter().map(|s| s.to_remote_amount).sum();
+    assert_eq!(
+        total_remote_amount,
+        node_b
+            .store
+            .get_channel_summaries_by_peer(&node_a.peer_id)
+            .iter()
+            .map(|s| s.to_local_amount)
+            .sum::<u128>()
+    );
+}
+
+#[tokio::test]
+async fn test_negotiate_channel_features() {
+    init_tracing();
+
+    let (mut node_a, mut node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, true).await;
+
+    for node in [&node_a, &node_b] {
+        assert_eq!(
+            node.get_channel_actor_state(new_channel_id).features,
+            FeatureBits::all()
+        );
+    }
+
+    // The keysend payment is rejected once the feature is not negotiated by the payee.
+    let mut state = node_b.get_channel_actor_state(new_channel_id);
+    state.features.remove(FeatureBits::KEYSEND);
+    node_b.update_channel_actor_state(state).await;
+    let node_b_local_balance = node_b.get_local_balance_from_channel(new_channel_id);
+    // Wait for the channel announcement to be broadcasted
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let res = node_a
+        .send_payment(SendPaymentCommand {
+            target_pubkey: Some(node_b.pubkey.clone()),
+            amount: Some(10000),
+            payment_hash: None,
+            final_tlc_expiry_delta: None,
+            tlc_expiry_limit: None,
+            invoice: None,
+            timeout: None,
+            max_fee_amount: None,
+            max_parts: None,
+            keysend: Some(true),
+            udt_type_script: None,
+            allow_self_payment: false,
+            dry_run: false,
+            custom_records: vec![],
+        })
+        .await
+        .expect("send payment");
+    tokio::time::sleep(tokio::time::Duration::from_millis(2000)).await;
+    node_a
+        .assert_payment_status(res.payment_hash, PaymentSessionStatus::Failed, None)
+        .await;
+    assert_eq!(
+        node_b.get_local_balance_from_channel(new_channel_id),
+        node_b_local_balance
+    );
+}
+
+#[tokio::test]
+async fn test_routing_stats_of_forwarded_payment() {
+    init_tracing();
+
+    let (node_a, node_b, node_c, _channel_1, channel_2) = create_3_nodes_with_established_channel(
+        (100000000000, 100000000000),
+        (100000000000, 100000000000),
+        true,
+    )
+    .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let stats = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: channel_2,
+                command: ChannelCommand::GetRoutingStats(rpc_reply),
+            },
+        ))
+    })
+    .expect("node_b alive");
+    assert_eq!(stats.success_count, 0);
+    assert_eq!(stats.failure_count, 0);
+    assert_eq!(stats.millis_since_last_forward, None);
+    assert!(stats.tlc_fee_proportional_millionths.is_some());
+    assert!(stats.tlc_expiry_delta.is_some());
+
+    let node_c_pubkey = node_c.pubkey.clone();
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::SendPayment(
+            SendPaymentCommand {
+                target_pubkey: Some(node_c_pubkey),
+                amount: Some(1000000),
+                payment_hash: None,
+                final_tlc_expiry_delta: None,
+                invoice: None,
+                timeout: None,
+                max_fee_amount: None,
+                tlc_expiry_limit: None,
+                max_parts: None,
+                keysend: Some(true),
+                udt_type_script: None,
+                allow_self_payment: false,
+                dry_run: false,
+                custom_records: vec![],
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_a alive")
+    .expect("send payment success");
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let stats = call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: channel_2,
+                command: ChannelCommand::GetRoutingStats(rpc_reply),
+            },
+        ))
+    })
+    .expect("node_b alive");
+    assert_eq!(stats.success_count, 1);
+    assert_eq!(stats.failure_count, 0);
+    assert!(stats.millis_since_last_forward.is_some());
+    let state = node_b.get_channel_actor_state(channel_2);
+    assert_eq!(
+        stats.liquid_capacity,
+        state.to_local_amount + state.to_remote_amount
+    );
+}
+
+#[tokio::test]
+async fn test_force_close_channel_on_shutdown_timeout() {
+    init_tracing();
+
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(|config| {
+                config.peer_disconnect_grace_seconds = Some(60);
+                config.shutdown_timeout_seconds = Some(1);
+                config.force_close_on_shutdown_timeout = Some(true);
+            })
+            .build()
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let mut node_a = nodes.pop().unwrap();
+    let (new_channel_id, _funding_tx) = establish_channel_between_nodes(
+        &mut node_a,
+        &mut node_b,
+        false,
+        100000000000,
+        6200000000,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await;
+
+    // The peer is disconnected so it never responds to our Shutdown message.
+    node_a
+        .network_actor
+        .send_message(NetworkActorMessage::new_command(
+            NetworkActorCommand::DisconnectPeer(node_b.peer_id.clone()),
+        ))
+        .expect("node_a alive");
+    node_a
+        .expect_event(|event| matches!(event, NetworkServiceEvent::PeerDisConnected(_, _)))
+        .await;
+
+    let close_script = node_a
+        .get_channel_actor_state(new_channel_id)
+        .local_shutdown_script;
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::Shutdown(
+                    ShutdownCommand {
+                        close_script,
+                        fee_rate: FeeRate::from_u64(DEFAULT_COMMITMENT_FEE_RATE),
+                        force: false,
+                        allow_unknown_close_script: false,
+                        target_blocks: None,
+                        min_net_close_balance: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("shutdown channel");
+
+    node_a
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelShutdownTimedOut(channel_id, peer_id, force_closed) => {
+                assert_eq!(channel_id, &new_channel_id);
+                assert_eq!(peer_id, &node_b.peer_id);
+                assert!(force_closed);
+                true
+            }
+            _ => false,
+        })
+        .await;
+    assert_eq!(
+        node_a.get_channel_actor_state(new_channel_id).state,
+        ChannelState::ShuttingDown(ShuttingDownFlags::WAITING_COMMITMENT_CONFIRMATION)
+    );
+}
+
+#[tokio::test]
+async fn test_shutdown_timeout_after_restart() {
+    init_tracing();
+
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(|config| {
+                config.peer_disconnect_grace_seconds = Some(60);
+                config.shutdown_timeout_seconds = Some(3);
+                config.force_close_on_shutdown_timeout = Some(true);
+            })
+            .build()
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let mut node_a = nodes.pop().unwrap();
+    let (new_channel_id, _funding_tx) = establish_channel_between_nodes(
+        &mut node_a,
+        &mut node_b,
+        false,
+        100000000000,
+        6200000000,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await;
+
+    // The peer is disconnected so it never receives our Shutdown message.
+    node_a
+        .network_actor
+        .send_message(NetworkActorMessage::new_command(
+            NetworkActorCommand::DisconnectPeer(node_b.peer_id.clone()),
+        ))
+        .expect("node_a alive");
+    node_a
+        .expect_event(|event| matches!(event, NetworkServiceEvent::PeerDisConnected(_, _)))
+        .await;
+
+    let close_script = node_a
+        .get_channel_actor_state(new_channel_id)
+        .local_shutdown_script;
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::Shutdown(
+                    ShutdownCommand {
+                        close_script,
+                        fee_rate: FeeRate::from_u64(DEFAULT_COMMITMENT_FEE_RATE),
+                        force: false,
+                        allow_unknown_close_script: false,
+                        target_blocks: None,
+                        min_net_close_balance: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("shutdown channel");
+
+    // The timer started by the Shutdown command is lost, the channel is still timed out once
+    // reestablished after the restart.
+    node_a.restart().await;
+    node_a
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelShutdownTimedOut(channel_id, peer_id, force_closed) => {
+                assert_eq!(channel_id, &new_channel_id);
+                assert_eq!(peer_id, &node_b.peer_id);
+                assert!(force_closed);
+                true
+            }
+            _ => false,
+        })
+        .await;
+    assert_eq!(
+        node_a.get_channel_actor_state(new_channel_id).state,
+        ChannelState::ShuttingDown(ShuttingDownFlags::WAITING_COMMITMENT_CONFIRMATION)
+    );
+}
+
+#[tokio::test]
+async fn test_check_funding_tx_update_keeps_previous_contributions() {
+    init_tracing();
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    let funding_tx = state
+        .funding_tx
+        .clone()
+        .expect("funding tx exists")
+        .into_view();
+    // Both parties have contributed their inputs.
+    assert!(funding_tx.inputs().len() >= 2);
+
+    // Adding more inputs and outputs is allowed.
+    let extra_input = CellInput::new_builder()
+        .previous_output(OutPoint::new(gen_rand_sha256_hash().into(), 0))
+        .build();
+    let tx = funding_tx
+        .as_advanced_builder()
+        .input(extra_input)
+        .output(CellOutput::new_builder().build())
+        .output_data(Default::default())
+        .build();
+    assert!(state.check_funding_tx_update(&tx.data()).is_ok());
+
+    // Removing the previous inputs is rejected.
+    let inputs = funding_tx.inputs().into_iter().skip(1).collect::<Vec<_>>();
+    let tx = funding_tx.as_advanced_builder().set_inputs(inputs).build();
+    assert!(state.check_funding_tx_update(&tx.data()).is_err());
+}
+
+#[tokio::test]
+async fn test_remote_funding_tx_update_exceeding_its_contribution() {
+    init_tracing();
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    let mut state = node_a.get_channel_actor_state(new_channel_id);
+    let funding_tx = state
+        .funding_tx
+        .clone()
+        .expect("funding tx exists")
+        .into_view();
+    let with_funding_capacity = |capacity: u64| {
+        let mut outputs = funding_tx.outputs().into_iter().collect::<Vec<_>>();
+        outputs[0] = outputs[0]
+            .clone()
+            .as_builder()
+            .capacity(capacity.pack())
+            .build();
+        funding_tx.as_advanced_builder().set_outputs(outputs).build().data()
+    };
+
+    // Only we have funded the channel.
+    let local_capacity = state.to_local_amount as u64 + state.local_reserved_ckb_amount;
+    let local_only_tx = with_funding_capacity(local_capacity);
+    state.funding_tx = Some(local_only_tx.clone());
+    state.remote_funding_contribution = Default::default();
+
+    // The peer funds more than its negotiated amount, the update is rejected.
+    let total_capacity: u64 = funding_tx
+        .output(0)
+        .expect("funding output")
+        .capacity()
+        .unpack();
+    let over_funded_tx = with_funding_capacity(total_capacity + 1);
+    assert!(state.apply_remote_funding_tx_update(&over_funded_tx).is_err());
+    assert_eq!(state.funding_tx, Some(local_only_tx));
+
+    // The peer funds exactly its negotiated amount.
+    assert!(state
+        .apply_remote_funding_tx_update(&funding_tx.data())
+        .expect("valid funding tx update"));
+    assert_eq!(state.funding_tx, Some(funding_tx.data()));
+    assert_eq!(
+        state.remote_funding_contribution.capacity,
+        total_capacity - local_capacity
+    );
+}
+
+#[tokio::test]
+async fn test_balance_invariant_against_funded_amount() {
+    init_tracing();
+
+    let (node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    let mut state = node_a.get_channel_actor_state(new_channel_id);
+    state.check_balance_invariant();
+    assert_eq!(state.balance_invariant_violation, None);
+
+    // The balances don't match the funding cell anymore.
+    state.to_local_amount += 1;
+    state.check_balance_invariant();
+    assert!(state.balance_invariant_violation.is_some());
+}
+
+#[tokio::test]
+async fn test_refuse_signing_commitment_on_balance_invariant_violation() {
+    init_tracing();
+
+    let (mut node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    let mut state = node_a.get_channel_actor_state(new_channel_id);
+    state.balance_invariant_violation = Some("Total balance changed".to_string());
+    node_a.update_channel_actor_state(state).await;
+
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: 10000,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: gen_rand_sha256_hash(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully added tlc");
+
+    // The commitment of the added tlc is not signed.
+    node_a
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelBalanceInvariantViolated(channel_id, violation) => {
+                *channel_id == new_channel_id && violation == "Total balance changed"
+            }
+            _ => false,
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_closed_channel_record_after_cooperative_close() {
+    init_tracing();
+
+    let (mut node_a, node_b, new_channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::Shutdown(
+                    ShutdownCommand {
+                        close_script: state.local_shutdown_script.clone(),
+                        fee_rate: FeeRate::from_u64(DEFAULT_COMMITMENT_FEE_RATE),
+                        force: false,
+                        allow_unknown_close_script: false,
+                        target_blocks: None,
+                        min_net_close_balance: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully shutdown channel");
+
+    let shutdown_tx_hash = node_a
+        .expect_to_process_event(|event| match event {
+            NetworkServiceEvent::ChannelClosed(peer_id, channel_id, tx_hash)
+                if peer_id == &node_b.peer_id && channel_id == &new_channel_id =>
+            {
+                Some(tx_hash.clone())
+            }
+            _ => None,
+        })
+        .await;
+    // Wait for the channel actor to persist the record.
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    let records = node_a.store.get_closed_channel_records();
+    assert_eq!(records.len(), 1);
+    let record = &records[0];
+    assert_eq!(record.channel_id, new_channel_id);
+    assert_eq!(record.remote_pubkey, node_b.pubkey);
+    assert_eq!(record.close_flags, CloseFlags::COOPERATIVE);
+    assert_eq!(record.closing_tx_hash, Hash256::from(&shutdown_tx_hash));
+    assert_eq!(
+        record.to_local_amount + record.to_remote_amount,
+        state.to_local_amount + state.to_remote_amount
+    );
+}
+
+type StateTransition = (Hash256, ChannelState, ChannelState);
+
+struct StateTransitionCollector {
+    transitions: Arc<Mutex<Vec<StateTransition>>>,
+}
+
+#[ractor::async_trait]
+impl Actor for StateTransitionCollector {
+    type Msg = StateTransition;
+    type State = ();
+    type Arguments = ();
+
+    async fn pre_start(
+        &self,
+        _myself: ActorRef<Self::Msg>,
+        _args: Self::Arguments,
+    ) -> Result<Self::State, ActorProcessingErr> {
+        Ok(())
+    }
+
+    async fn handle(
+        &self,
+        _myself: ActorRef<Self::Msg>,
+        message: Self::Msg,
+        _state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        self.transitions.lock().unwrap().push(message);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_state_transition_subscribers_notified_on_cooperative_close() {
+    init_tracing();
+
+    let (mut node_a, node_b, new_channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    let transitions = Arc::new(Mutex::new(Vec::new()));
+    let (collector, _) = Actor::spawn(
+        None,
+        StateTransitionCollector {
+            transitions: transitions.clone(),
+        },
+        (),
+    )
+    .await
+    .expect("start state transition collector");
+    node_a
+        .channel_subscribers
+        .state_transition_subscribers
+        .subscribe(collector, Some);
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::Shutdown(
+                    ShutdownCommand {
+                        close_script: state.local_shutdown_script.clone(),
+                        fee_rate: FeeRate::from_u64(DEFAULT_COMMITMENT_FEE_RATE),
+                        force: false,
+                        allow_unknown_close_script: false,
+                        target_blocks: None,
+                        min_net_close_balance: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully shutdown channel");
+
+    node_a
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ChannelClosed(peer_id, channel_id, _tx_hash) => {
+                peer_id == &node_b.peer_id && channel_id == &new_channel_id
+            }
+            _ => false,
+        })
+        .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    // The transitions are notified in order, from the ready state to the closed state.
+    let transitions = transitions.lock().unwrap().clone();
+    assert!(transitions
+        .iter()
+        .all(|(channel_id, _, _)| channel_id == &new_channel_id));
+    assert!(transitions.windows(2).all(|pair| pair[0].2 == pair[1].1));
+    let (_, first_old_state, _) = transitions.first().expect("notified transitions");
+    assert_eq!(first_old_state, &ChannelState::ChannelReady());
+    assert!(transitions
+        .iter()
+        .any(|(_, _, new_state)| matches!(new_state, ChannelState::ShuttingDown(_))));
+    let (_, _, last_new_state) = transitions.last().expect("notified transitions");
+    assert_eq!(last_new_state, &ChannelState::Closed(CloseFlags::COOPERATIVE));
+}
+
+#[tokio::test]
+async fn test_max_tlc_value_in_flight_percentage_of_balance() {
+    init_tracing();
+
+    let (mut node_a, _node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, false).await;
+
+    let mut state = node_a.get_channel_actor_state(new_channel_id);
+    let to_local_amount = state.to_local_amount;
+    state.set_max_tlc_value_in_flight_percentage(Some(10));
+    assert_eq!(
+        state
+            .local_constraints
+            .get_max_tlc_value_in_flight(to_local_amount),
+        to_local_amount / 10
+    );
+    node_a.update_channel_actor_state(state).await;
+
+    // the tlc exceeding the percentage of the balance is rejected though the absolute limit is
+    // not reached
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: to_local_amount / 10 + 1,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: gen_rand_sha256_hash(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert_eq!(
+        add_tlc_result.unwrap_err().error_code,
+        TlcErrorCode::TemporaryChannelFailure
+    );
+
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: to_local_amount / 10,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: gen_rand_sha256_hash(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive");
+    assert!(add_tlc_result.is_ok());
+}
+
+#[tokio::test]
+async fn test_max_tlc_value_in_flight_percentage_not_applied_to_received_tlcs() {
+    init_tracing();
+
+    let (mut node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(16200000000, 6200000000, false).await;
+
+    let mut state = node_a.get_channel_actor_state(new_channel_id);
+    let to_remote_amount = state.to_remote_amount;
+    state.set_max_tlc_value_in_flight_percentage(Some(10));
+    node_a.update_channel_actor_state(state).await;
+
+    // The percentage is our local policy, the tlc from the peer exceeding the percentage of its
+    // balance is still accepted.
+    call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: to_remote_amount / 10 + 1,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: gen_rand_sha256_hash(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("successfully added tlc");
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    assert_eq!(state.get_all_received_tlcs().count(), 1);
+}
+
+#[tokio::test]
+async fn test_get_forwarding_blocker() {
+    init_tracing();
+
+    let get_forwarding_blocker = |node: &NetworkNode, channel_id| {
+        let network_actor = node.network_actor.clone();
+        async move {
+            call!(network_actor, |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                    ChannelCommandWithId {
+                        channel_id,
+                        command: ChannelCommand::GetForwardingBlocker(rpc_reply),
+                    },
+                ))
+            })
+            .expect("node alive")
+        }
+    };
+
+    let (node_a, _node_b, public_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, true).await;
+    assert_eq!(get_forwarding_blocker(&node_a, public_channel_id).await, None);
+
+    let mut state = node_a.get_channel_actor_state(public_channel_id);
+    state.tlc_state.waiting_ack = true;
+    assert_eq!(state.forwarding_blocker(), Some(ForwardBlocker::WaitingAck));
+    // the first failing reason is returned
+    state.reestablishing = true;
+    assert_eq!(state.forwarding_blocker(), Some(ForwardBlocker::Reestablishing));
+
+    let mut state = node_a.get_channel_actor_state(public_channel_id);
+    state.to_local_amount = state.local_constraints.channel_reserve_amount;
+    assert_eq!(state.forwarding_blocker(), Some(ForwardBlocker::InsufficientLiquidity));
+
+    let (node_a, _node_b, private_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+    assert_eq!(
+        get_forwarding_blocker(&node_a, private_channel_id).await,
+        Some(ForwardBlocker::NotPublic)
+    );
+}
+
+#[tokio::test]
+async fn test_keysend_payments_with_limited_onion_peeling_concurrency() {
+    init_tracing();
+
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(|config| {
+                config.onion_peeling_concurrency = Some(1);
+            })
+            .build()
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let mut node_a = nodes.pop().unwrap();
+    let (channel_id, _funding_tx) = establish_channel_between_nodes(
+        &mut node_a,
+        &mut node_b,
+        true,
+        100000000000,
+        6200000000,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await;
+    let node_a_local_balance = node_a.get_local_balance_from_channel(channel_id);
+
+    // Wait for the channel announcement to be broadcasted
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    // the onion packets are peeled one at a time, while all the payments still succeed
+    let mut payment_hashes = vec![];
+    for _ in 0..3 {
+        let res = node_a
+            .send_payment(SendPaymentCommand {
+                target_pubkey: Some(node_b.pubkey.clone()),
+                amount: Some(10000),
+                payment_hash: None,
+                final_tlc_expiry_delta: None,
+                tlc_expiry_limit: None,
+                invoice: None,
+                timeout: None,
+                max_fee_amount: None,
+                max_parts: None,
+                keysend: Some(true),
+                udt_type_script: None,
+                allow_self_payment: false,
+                dry_run: false,
+                custom_records: vec![],
+            })
+            .await
+            .expect("send payment success");
+        payment_hashes.push(res.payment_hash);
+    }
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(3000)).await;
+    for payment_hash in payment_hashes {
+        node_a
+            .assert_payment_status(payment_hash, PaymentSessionStatus::Success, None)
+            .await;
+    }
+    assert_eq!(
+        node_a_local_balance - node_a.get_local_balance_from_channel(channel_id),
+        30000
+    );
+}
+
+// A benchmark of peeling the onion packets of concurrent tlcs in the blocking tasks spawned by
+// the network actor, compared with peeling them one by one in place as before. Run it with
+// `cargo test --release bench_peel_payment_onion_packets -- --ignored --nocapture`.
+#[tokio::test]
+#[ignore]
+async fn bench_peel_payment_onion_packets() {
+    const PACKETS: usize = 1000;
+
+    let (node_a, _node_b, _new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+
+    let payment_hash = gen_rand_sha256_hash();
+    let hops_infos = vec![
+        PaymentHopData {
+            amount: 10000,
+            expiry: 3,
+            next_hop: Some(node_a.pubkey.clone()),
+            funding_tx_hash: Hash256::default(),
+            hash_algorithm: HashAlgorithm::CkbHash,
+            payment_preimage: None,
+            custom_records: vec![],
+            total_amount: None,
+        },
+        PaymentHopData {
+            amount: 10000,
+            expiry: 3,
+            next_hop: None,
+            funding_tx_hash: Hash256::default(),
+            hash_algorithm: HashAlgorithm::CkbHash,
+            payment_preimage: None,
+            custom_records: vec![],
+            total_amount: None,
+        },
+    ];
+    let onion_packet = PeeledOnionPacket::create(
+        gen_rand_fiber_private_key(),
+        hops_infos,
+        Some(payment_hash.as_ref().to_vec()),
+        &Secp256k1::new(),
+    )
+    .expect("create peeled packet")
+    .next
+    .expect("onion packet for node_a");
+
+    let started_at = std::time::Instant::now();
+    futures::future::join_all((0..PACKETS).map(|_| {
+        let network_actor = node_a.network_actor.clone();
+        let onion_packet = onion_packet.clone();
+        async move {
+            call!(network_actor, |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::PeelPaymentOnionPacket(
+                    onion_packet,
+                    payment_hash,
+                    rpc_reply,
+                ))
+            })
+            .expect("node_a alive")
+            .expect("peel onion packet")
+        }
+    }))
+    .await;
+    let network_actor_elapsed = started_at.elapsed();
+
+    let secp = Secp256k1::new();
+    let started_at = std::time::Instant::now();
+    for _ in 0..PACKETS {
+        std::hint::black_box(
+            onion_packet
+                .clone()
+                .peel(
+                    node_a.get_private_key(),
+                    Some(payment_hash.as_ref()),
+                    &secp,
+                )
+                .expect("peel onion packet"),
+        );
+    }
+    let in_place_elapsed = started_at.elapsed();
+
+    println!(
+        "{} onion packets peeled by the network actor took {:?}, peeled in place took {:?}",
+        PACKETS, network_actor_elapsed, in_place_elapsed
+    );
+}
+
+#[tokio::test]
+async fn test_fail_probe_tlc_at_final_hop() {
+    init_tracing();
+
+    let (node_a, mut node_b, channel_id) =
+        create_nodes_with_established_channel(100000000000, 6200000000, true).await;
+    let node_a_local_balance = node_a.get_local_balance_from_channel(channel_id);
+
+    // Wait for the channel announcement to be broadcasted
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let node_b_pubkey = node_b.pubkey.clone();
+    let res = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::SendPayment(
+            SendPaymentCommand {
+                target_pubkey: Some(node_b_pubkey),
+                amount: Some(10000),
+                payment_hash: None,
+                final_tlc_expiry_delta: None,
+                tlc_expiry_limit: None,
+                invoice: None,
+                timeout: None,
+                max_fee_amount: None,
+                max_parts: None,
+                keysend: Some(true),
+                udt_type_script: None,
+                allow_self_payment: false,
+                dry_run: false,
+                custom_records: vec![(PROBE_CUSTOM_RECORD_TYPE, vec![])],
+            },
+            rpc_reply,
+        ))
+    })
+    .expect("node_a alive")
+    .unwrap();
+    let payment_hash = res.payment_hash;
+
+    node_b
+        .expect_event(|event| match event {
+            NetworkServiceEvent::ProbeTlcReceived(id, hash, amount) => {
+                assert_eq!(id, &channel_id);
+                assert_eq!(hash, &payment_hash);
+                assert_eq!(*amount, 10000);
+                true
+            }
+            _ => false,
+        })
+        .await;
+
+    // the probe is failed even though the preimage is carried by the keysend payment
+    tokio::time::sleep(tokio::time::Duration::from_millis(2000)).await;
+    node_a
+        .assert_payment_status(payment_hash, PaymentSessionStatus::Failed, None)
+        .await;
+    assert_eq!(
+        node_a.get_local_balance_from_channel(channel_id),
+        node_a_local_balance
+    );
+}
+
+#[tokio::test]
+async fn test_replayed_remove_tlc_fulfill_settles_once() {
+    init_tracing();
+
+    let (node_a, node_b, new_channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, false).await;
+    let node_a_local_balance = node_a.get_local_balance_from_channel(new_channel_id);
+    let node_b_local_balance = node_b.get_local_balance_from_channel(new_channel_id);
+
+    let preimage = [1; 32];
+    let tlc_amount = 1000000000;
+    let add_tlc_result = call!(node_a.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::AddTlc(
+                    AddTlcCommand {
+                        amount: tlc_amount,
+                        hash_algorithm: HashAlgorithm::CkbHash,
+                        payment_hash: HashAlgorithm::CkbHash.hash(&preimage).into(),
+                        expiry: now_timestamp_as_millis_u64() + DEFAULT_EXPIRY_DELTA,
+                        onion_packet: None,
+                        shared_secret: NO_SHARED_SECRET.clone(),
+                        previous_tlc: None,
+                        payment_preimage: None,
+                        idempotency_key: None,
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_a alive")
+    .expect("successfully added tlc");
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    let reason = RemoveTlcReason::RemoveTlcFulfill(RemoveTlcFulfill {
+        payment_preimage: preimage.into(),
+    });
+    call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id: new_channel_id,
+                command: ChannelCommand::RemoveTlc(
+                    RemoveTlcCommand {
+                        id: add_tlc_result.tlc_id,
+                        reason: reason.clone(),
+                    },
+                    rpc_reply,
+                ),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("successfully removed tlc");
+
+    // replay the same RemoveTlc both before and after the removal is applied
+    for delay in [0, 1000] {
+        tokio::time::sleep(tokio::time::Duration::from_millis(delay)).await;
+        node_b
+            .network_actor
+            .send_message(NetworkActorMessage::new_command(
+                NetworkActorCommand::SendFiberMessage(FiberMessageWithPeerId::new(
+                    node_a.peer_id.clone(),
+                    FiberMessage::remove_tlc(RemoveTlc {
+                        channel_id: new_channel_id,
+                        tlc_id: add_tlc_result.tlc_id,
+                        reason: reason.clone(),
+                    }),
+                )),
+            ))
+            .expect("node_b alive");
+    }
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    assert_eq!(state.state, ChannelState::ChannelReady());
+    assert_eq!(state.to_local_amount, node_a_local_balance - tlc_amount);
+    assert_eq!(
+        node_b.get_local_balance_from_channel(new_channel_id),
+        node_b_local_balance + tlc_amount
+    );
+}
+
+#[test]
+fn test_reject_min_allowed_fee_ppm_greater_than_max() {
+    let config = FiberConfig {
+        min_allowed_fee_ppm: Some(5000),
+        max_allowed_fee_ppm: Some(100),
+        ..Default::default()
+    };
+    assert!(config.fee_policy_bounds().is_err());
+
+    let config = FiberConfig {
+        min_allowed_fee_ppm: Some(100),
+        max_allowed_fee_ppm: Some(5000),
+        ..Default::default()
+    };
+    assert!(config.fee_policy_bounds().is_ok());
+}
+
+#[tokio::test]
+async fn test_update_channel_within_fee_policy_bounds() {
+    init_tracing();
+
+    let mut nodes = NetworkNode::new_n_interconnected_nodes_with_config(2, |i| {
+        NetworkNodeConfigBuilder::new()
+            .node_name(Some(format!("node-{}", i)))
+            .base_dir_prefix(&format!("test-fnn-node-{}-", i))
+            .fiber_config_updater(|config| {
+                config.min_allowed_fee_ppm = Some(100);
+                config.max_allowed_fee_ppm = Some(5000);
+                config.max_allowed_tlc_minimum_value = Some(10000);
+            })
+            .build()
+    })
+    .await;
+    let mut node_b = nodes.pop().unwrap();
+    let mut node_a = nodes.pop().unwrap();
+    let (new_channel_id, _funding_tx) = establish_channel_between_nodes(
+        &mut node_a,
+        &mut node_b,
+        true,
+        100000000000,
+        6200000000,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await;
+
+    let update_channel = |tlc_minimum_value, tlc_fee_proportional_millionths| {
+        let network_actor = node_a.network_actor.clone();
+        async move {
+            call!(network_actor, |rpc_reply| {
+                NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                    ChannelCommandWithId {
+                        channel_id: new_channel_id,
+                        command: ChannelCommand::Update(
+                            UpdateCommand {
+                                enabled: None,
+                                tlc_expiry_delta: None,
+                                tlc_minimum_value,
+                                tlc_fee_proportional_millionths,
+                                min_tlc_expiry_delta: None,
+                                max_tlc_expiry_delta: None,
+                            },
+                            rpc_reply,
+                        ),
+                    },
+                ))
+            })
+            .expect("node_a alive")
+        }
+    };
+
+    for (tlc_minimum_value, fee) in [(None, Some(50)), (None, Some(6000)), (Some(20000), None)] {
+        let err = update_channel(tlc_minimum_value, fee)
+            .await
+            .expect_err("update out of the bounds");
+        assert_eq!(err.code, ProcessingChannelError::InvalidParameter(String::new()).code());
+    }
+    // nothing is changed by the rejected updates
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    let public_channel_info = state.public_channel_info.as_ref().unwrap();
+    assert_ne!(public_channel_info.tlc_fee_proportional_millionths, 6000);
+    assert_ne!(public_channel_info.tlc_min_value, 20000);
+
+    update_channel(Some(10000), Some(5000))
+        .await
+        .expect("update within the bounds");
+    let state = node_a.get_channel_actor_state(new_channel_id);
+    let public_channel_info = state.public_channel_info.as_ref().unwrap();
+    assert_eq!(public_channel_info.tlc_fee_proportional_millionths, 5000);
+    assert_eq!(public_channel_info.tlc_min_value, 10000);
+}
+
+async fn send_held_invoice_payment(
+    node_a: &mut NetworkNode,
+    node_b: &mut NetworkNode,
+    amount: u128,
+) -> Hash256 {
+    let preimage = gen_rand_sha256_hash();
+    let invoice = InvoiceBuilder::new(Currency::Fibd)
+        .amount(Some(amount))
+        .payment_preimage(preimage)
+        .payee_pub_key(node_b.pubkey.into())
+        .expiry_time(Duration::from_secs(100))
+        .build()
+        .expect("build invoice success");
+    let payment_hash = *invoice.payment_hash();
+    node_b.insert_invoice(invoice.clone(), Some(preimage));
+    node_b
+        .store
+        .set_invoice_hold(&payment_hash, true)
+        .expect("hold invoice");
+
+    let res = node_a
+        .send_payment(SendPaymentCommand {
+            target_pubkey: Some(node_b.pubkey.clone()),
+            amount: Some(amount),
+            payment_hash: None,
+            final_tlc_expiry_delta: None,
+            tlc_expiry_limit: None,
+            invoice: Some(invoice.to_string()),
+            timeout: None,
+            max_fee_amount: None,
+            max_parts: None,
+            keysend: None,
+            udt_type_script: None,
+            allow_self_payment: false,
+            dry_run: false,
+            custom_records: vec![],
+        })
+        .await
+        .expect("send payment");
+    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    res.payment_hash
+}
+
+#[tokio::test]
+async fn test_held_tlc_settled_after_release() {
+    init_tracing();
+
+    let (mut node_a, mut node_b, channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, true).await;
+    // Wait for the channel announcement to be broadcasted
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+    let node_b_local_balance = node_b.get_local_balance_from_channel(channel_id);
+
+    let payment_hash = send_held_invoice_payment(&mut node_a, &mut node_b, 10000).await;
+
+    // The tlc is accepted but not fulfilled until the invoice is released.
+    node_a
+        .assert_payment_status(payment_hash, PaymentSessionStatus::Inflight, None)
+        .await;
+    assert_eq!(
+        node_b.get_invoice_status(&payment_hash),
+        Some(CkbInvoiceStatus::Received)
+    );
+    assert_eq!(
+        node_b.get_local_balance_from_channel(channel_id),
+        node_b_local_balance
+    );
+    assert!(node_b
+        .get_channel_actor_state(channel_id)
+        .get_all_received_tlcs()
+        .any(|tlc| tlc.payment_hash == payment_hash && tlc.removed_at.is_none()));
+
+    call!(node_b.network_actor, |rpc_reply| {
+        NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+            ChannelCommandWithId {
+                channel_id,
+                command: ChannelCommand::SettleHeldTlc(payment_hash, rpc_reply),
+            },
+        ))
+    })
+    .expect("node_b alive")
+    .expect("settle held tlc");
+    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+
+    node_a
+        .assert_payment_status(payment_hash, PaymentSessionStatus::Success, None)
+        .await;
+    assert_eq!(
+        node_b.get_local_balance_from_channel(channel_id),
+        node_b_local_balance + 10000
+    );
+    assert_eq!(
+        node_b.get_invoice_status(&payment_hash),
+        Some(CkbInvoiceStatus::Paid)
+    );
+}
+
+#[tokio::test]
+async fn test_held_tlc_cancelled_before_expiry_after_restart() {
+    init_tracing();
+
+    let (mut node_a, mut node_b, channel_id, _) =
+        NetworkNode::new_2_nodes_with_established_channel(100000000000, 6200000000, true).await;
+    // Wait for the channel announcement to be broadcasted
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let payment_hash = send_held_invoice_payment(&mut node_a, &mut node_b, 10000).await;
+    node_a
+        .assert_payment_status(payment_hash, PaymentSessionStatus::Inflight, None)
+        .await;
+
+    // Make the held tlc going to expire soon while the node is down, the check must be
+    // scheduled again after the restart to cancel it.
+    node_b.stop().await;
+    let mut state = node_b.store.get_channel_actor_state(&channel_id).unwrap();
+    let tlc_id = state
+        .get_all_received_tlcs()
+        .find(|tlc| tlc.payment_hash == payment_hash)
+        .expect("held tlc")
+        .tlc_id;
+    state.tlc_state.get_mut(&tlc_id).expect("held tlc").expiry =
+        now_timestamp_as_millis_u64() + MIN_TLC_EXPIRY_DELTA + 3000;
+    node_b.store.insert_channel_actor_state(state);
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    node_b.start().await;
+
+    tokio::time::sleep(tokio::time::Duration::from_secs(8)).await;
+    node_a
+        .assert_payment_status(payment_hash, PaymentSessionStatus::Failed, None)
+        .await;
+    assert_eq!(
+        node_b.get_invoice_status(&payment_hash),
+        Some(CkbInvoiceStatus::Cancelled)
+    );
+}
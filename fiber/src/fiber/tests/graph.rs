@@ -516,6 +516,7 @@ fn test_graph_build_router_is_ok_with_fee_rate() {
         preimage: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     });
     assert!(route.is_ok());
     let route = route.unwrap();
@@ -523,6 +524,43 @@ fn test_graph_build_router_is_ok_with_fee_rate() {
     assert_eq!(amounts, vec![1000, 1000]);
 }
 
+#[test]
+fn test_graph_build_route_with_custom_records() {
+    let mut network = MockNetworkGraph::new(6);
+    network.add_edge(1, 2, Some(2000), Some(10000));
+    network.add_edge(2, 3, Some(2000), Some(10000));
+
+    let source = network.keys[1];
+    network.set_source(source);
+    let node3 = network.keys[3];
+    let custom_records = vec![(1, b"memo".to_vec())];
+    let route = network
+        .graph
+        .build_route(SendPaymentData {
+            target_pubkey: node3.into(),
+            amount: 1000,
+            payment_hash: Hash256::default(),
+            invoice: None,
+            final_tlc_expiry_delta: DEFAULT_TLC_EXPIRY_DELTA,
+            tlc_expiry_limit: MAX_PAYMENT_TLC_EXPIRY_LIMIT,
+            timeout: None,
+            max_fee_amount: Some(1000),
+            max_parts: None,
+            keysend: false,
+            udt_type_script: None,
+            preimage: None,
+            allow_self_payment: false,
+            dry_run: false,
+            custom_records: custom_records.clone(),
+        })
+        .expect("build route");
+    assert_eq!(route.len(), 3);
+    // only the final hop carries the custom records
+    assert!(route[0].custom_records.is_empty());
+    assert!(route[1].custom_records.is_empty());
+    assert_eq!(route[2].custom_records, custom_records);
+}
+
 #[test]
 fn test_graph_build_router_fee_rate_optimize() {
     let mut network = MockNetworkGraph::new(10);
@@ -556,6 +594,7 @@ fn test_graph_build_router_fee_rate_optimize() {
         preimage: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     });
     assert!(route.is_ok());
     let route = route.unwrap();
@@ -588,6 +627,7 @@ fn test_graph_build_router_no_fee_with_direct_pay() {
         preimage: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     });
     assert!(route.is_ok());
     let route = route.unwrap();
@@ -713,6 +753,7 @@ fn test_graph_build_route_three_nodes() {
         preimage: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     });
     assert!(route.is_ok());
     let route = route.unwrap();
@@ -759,6 +800,7 @@ fn test_graph_build_route_below_min_tlc_value() {
         preimage: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     });
     assert!(route.is_err());
 }
@@ -788,6 +830,7 @@ fn test_graph_build_route_select_edge_with_latest_timestamp() {
         preimage: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     });
     assert!(route.is_ok());
     eprintln!("got route {:?}", route);
@@ -825,6 +868,7 @@ fn test_graph_build_route_select_edge_with_large_capacity() {
         preimage: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     });
     assert!(route.is_ok());
     let route = route.unwrap();
@@ -879,6 +923,7 @@ fn test_graph_mark_failed_channel() {
         preimage: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     });
     assert!(route.is_err());
 
@@ -901,6 +946,7 @@ fn test_graph_mark_failed_channel() {
         preimage: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     });
     assert!(route.is_ok());
 }
@@ -933,6 +979,7 @@ fn test_graph_session_router() {
         preimage: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     });
     assert!(route.is_ok());
 
@@ -973,6 +1020,7 @@ fn test_graph_mark_failed_node() {
         preimage: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     });
     assert!(route.is_ok());
 
@@ -993,6 +1041,7 @@ fn test_graph_mark_failed_node() {
         preimage: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     });
     assert!(route.is_ok());
 
@@ -1015,6 +1064,7 @@ fn test_graph_mark_failed_node() {
         preimage: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     });
     assert!(route.is_err());
 
@@ -1034,6 +1084,7 @@ fn test_graph_mark_failed_node() {
         preimage: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     });
     assert!(route.is_err());
 }
@@ -1062,6 +1113,7 @@ fn test_graph_payment_self_default_is_false() {
         udt_type_script: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     };
     let payment_data = SendPaymentData::new(command);
     assert!(payment_data.is_ok());
@@ -1096,6 +1148,7 @@ fn test_graph_payment_pay_single_path() {
         udt_type_script: None,
         allow_self_payment: true,
         dry_run: false,
+        custom_records: vec![],
     };
     let payment_data = SendPaymentData::new(command);
     assert!(payment_data.is_ok());
@@ -1128,6 +1181,7 @@ fn test_graph_payment_pay_self_with_one_node() {
         udt_type_script: None,
         allow_self_payment: true,
         dry_run: false,
+        custom_records: vec![],
     };
     let payment_data = SendPaymentData::new(command);
     assert!(payment_data.is_ok());
@@ -1163,6 +1217,7 @@ fn test_graph_build_route_with_double_edge_node() {
         udt_type_script: None,
         allow_self_payment: true,
         dry_run: false,
+        custom_records: vec![],
     };
     let payment_data = SendPaymentData::new(command).unwrap();
     let route = network.graph.build_route(payment_data);
@@ -1198,6 +1253,7 @@ fn test_graph_build_route_with_other_node_maybe_better() {
         udt_type_script: None,
         allow_self_payment: true,
         dry_run: false,
+        custom_records: vec![],
     };
     let payment_data = SendPaymentData::new(command).unwrap();
     let route = network.graph.build_route(payment_data);
@@ -1231,6 +1287,7 @@ fn test_graph_payment_pay_self_will_ok() {
         udt_type_script: None,
         allow_self_payment: true,
         dry_run: false,
+        custom_records: vec![],
     };
     let payment_data = SendPaymentData::new(command);
     assert!(payment_data.is_ok());
@@ -1288,6 +1345,7 @@ fn test_graph_build_route_with_path_limits() {
         udt_type_script: None,
         allow_self_payment: true,
         dry_run: false,
+        custom_records: vec![],
     };
     let payment_data = SendPaymentData::new(command).unwrap();
     let route = network.graph.build_route(payment_data);
@@ -1337,6 +1395,7 @@ fn test_graph_build_route_with_path_limit_fail_with_fee_not_enough() {
         udt_type_script: None,
         allow_self_payment: true,
         dry_run: false,
+        custom_records: vec![],
     };
     let payment_data = SendPaymentData::new(command).unwrap();
     let route = network.graph.build_route(payment_data);
@@ -1366,6 +1425,7 @@ fn test_graph_payment_expiry_is_in_right_order() {
         udt_type_script: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     };
     let payment_data = SendPaymentData::new(command);
     assert!(payment_data.is_ok());
@@ -1397,6 +1457,7 @@ fn test_graph_payment_expiry_is_in_right_order() {
         udt_type_script: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     };
     let payment_data = SendPaymentData::new(command);
     assert!(payment_data.is_ok());
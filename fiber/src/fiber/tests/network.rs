@@ -5,7 +5,10 @@ use crate::{
         config::DEFAULT_TLC_EXPIRY_DELTA,
         gossip::GossipMessageStore,
         graph::ChannelUpdateInfo,
-        network::{NetworkActorStateStore, SendPaymentCommand, SendPaymentData},
+        network::{
+            NetworkActorStateStore, SendPaymentCommand, SendPaymentData,
+            MAX_PAYMENT_CUSTOM_RECORDS_SIZE,
+        },
         tests::test_utils::NetworkNodeConfigBuilder,
         types::{
             BroadcastMessage, ChannelAnnouncement, ChannelUpdate, NodeAnnouncement, Privkey, Pubkey,
@@ -752,6 +755,7 @@ fn test_send_payment_validate_payment_hash() {
         udt_type_script: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     };
 
     let result = SendPaymentData::new(send_command);
@@ -776,6 +780,7 @@ fn test_send_payment_validate_amount() {
         udt_type_script: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     };
 
     let result = SendPaymentData::new(send_command);
@@ -821,6 +826,7 @@ fn test_send_payment_validate_invoice() {
         udt_type_script: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     };
 
     let result = SendPaymentData::new(send_command);
@@ -843,6 +849,7 @@ fn test_send_payment_validate_invoice() {
         udt_type_script: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     };
 
     // keysend is set with invoice, should be error
@@ -866,6 +873,7 @@ fn test_send_payment_validate_invoice() {
         udt_type_script: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     };
 
     let result = SendPaymentData::new(send_command);
@@ -886,6 +894,7 @@ fn test_send_payment_validate_invoice() {
         udt_type_script: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     };
 
     let result = SendPaymentData::new(send_command);
@@ -906,6 +915,7 @@ fn test_send_payment_validate_invoice() {
         udt_type_script: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     };
 
     let result = SendPaymentData::new(send_command);
@@ -926,6 +936,7 @@ fn test_send_payment_validate_invoice() {
         udt_type_script: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     };
 
     let result = SendPaymentData::new(send_command);
@@ -961,6 +972,7 @@ fn test_send_payment_validate_invoice() {
         udt_type_script: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     };
 
     let result = SendPaymentData::new(send_command);
@@ -986,6 +998,7 @@ fn test_send_payment_validate_htlc_expiry_delta() {
         udt_type_script: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     };
 
     let result = SendPaymentData::new(send_command);
@@ -994,3 +1007,37 @@ fn test_send_payment_validate_htlc_expiry_delta() {
         .unwrap_err()
         .contains("invalid final_tlc_expiry_delta"));
 }
+
+#[test]
+fn test_send_payment_validate_custom_records() {
+    let send_command = |custom_records| SendPaymentCommand {
+        target_pubkey: Some(gen_rand_fiber_public_key()),
+        amount: Some(1000),
+        payment_hash: Some(gen_rand_sha256_hash()),
+        final_tlc_expiry_delta: None,
+        tlc_expiry_limit: None,
+        invoice: None,
+        timeout: None,
+        max_fee_amount: None,
+        max_parts: None,
+        keysend: None,
+        udt_type_script: None,
+        allow_self_payment: false,
+        dry_run: false,
+        custom_records,
+    };
+
+    let custom_records = vec![(1, b"memo".to_vec()), (2, vec![0; 16])];
+    let result = SendPaymentData::new(send_command(custom_records.clone()));
+    assert_eq!(result.expect("valid custom records").custom_records, custom_records);
+
+    let result = SendPaymentData::new(send_command(vec![(1, vec![1]), (1, vec![2])]));
+    assert!(result.unwrap_err().contains("duplicated types"));
+
+    // The values are hex encoded in the hop data, so they take twice the space there.
+    let result = SendPaymentData::new(send_command(vec![(
+        1,
+        vec![0; MAX_PAYMENT_CUSTOM_RECORDS_SIZE / 2],
+    )]));
+    assert!(result.unwrap_err().contains("custom_records are too large"));
+}
@@ -1,7 +1,10 @@
 use super::test_utils::init_tracing;
 use crate::fiber::graph::PaymentSessionStatus;
-use crate::fiber::network::SendPaymentCommand;
+use crate::fiber::network::{SendPaymentCommand, MAX_PAYMENT_CUSTOM_RECORDS_SIZE, MAX_PAYMENT_PARTS};
 use crate::fiber::tests::test_utils::*;
+use crate::fiber::types::PaymentHopData;
+use crate::invoice::{Currency, InvoiceBuilder};
+use std::time::Duration;
 
 #[tokio::test]
 async fn test_send_payment_for_direct_channel_and_dry_run() {
@@ -40,6 +43,7 @@ async fn test_send_payment_for_direct_channel_and_dry_run() {
             udt_type_script: None,
             allow_self_payment: false,
             dry_run: true,
+            custom_records: vec![],
         })
         .await;
 
@@ -61,6 +65,7 @@ async fn test_send_payment_for_direct_channel_and_dry_run() {
             udt_type_script: None,
             allow_self_payment: false,
             dry_run: false,
+            custom_records: vec![],
         })
         .await;
 
@@ -88,6 +93,7 @@ async fn test_send_payment_for_direct_channel_and_dry_run() {
             udt_type_script: None,
             allow_self_payment: false,
             dry_run: false,
+            custom_records: vec![],
         })
         .await;
 
@@ -158,6 +164,7 @@ async fn test_send_payment_for_pay_self() {
             udt_type_script: None,
             allow_self_payment: false,
             dry_run: true,
+            custom_records: vec![],
         })
         .await;
 
@@ -179,6 +186,7 @@ async fn test_send_payment_for_pay_self() {
             udt_type_script: None,
             allow_self_payment: true,
             dry_run: false,
+            custom_records: vec![],
         })
         .await;
 
@@ -236,9 +244,162 @@ async fn test_send_payment_for_pay_self() {
             udt_type_script: None,
             allow_self_payment: false,
             dry_run: true,
+            custom_records: vec![],
         })
         .await;
 
     eprintln!("res: {:?}", res);
     assert_eq!(res.unwrap().fee, 0);
 }
+
+#[tokio::test]
+async fn test_send_payment_with_custom_records_at_size_limit() {
+    init_tracing();
+    let _span = tracing::info_span!("node", node = "test").entered();
+
+    let (nodes, _channels) = create_n_nodes_with_index_and_amounts_with_established_channel(
+        &[
+            ((0, 1), (MIN_RESERVED_CKB + 10000000000, MIN_RESERVED_CKB)),
+            ((1, 2), (MIN_RESERVED_CKB + 10000000000, MIN_RESERVED_CKB)),
+        ],
+        3,
+        true,
+    )
+    .await;
+    let [mut node_0, _node_1, node_2] = nodes.try_into().expect("3 nodes");
+
+    // The largest record whose serialized size does not exceed the limit.
+    let mut value_len = 0;
+    while PaymentHopData::serialized_custom_records_size(&[(1, vec![0; value_len + 1])])
+        <= MAX_PAYMENT_CUSTOM_RECORDS_SIZE
+    {
+        value_len += 1;
+    }
+    let custom_records = vec![(1, vec![0; value_len])];
+    // Each byte of the value takes two hex characters.
+    assert!(
+        PaymentHopData::serialized_custom_records_size(&custom_records)
+            >= MAX_PAYMENT_CUSTOM_RECORDS_SIZE - 1
+    );
+
+    // sleep for a while
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    let res = node_0
+        .send_payment(SendPaymentCommand {
+            target_pubkey: Some(node_2.pubkey.clone()),
+            amount: Some(60000000),
+            payment_hash: None,
+            final_tlc_expiry_delta: None,
+            tlc_expiry_limit: None,
+            invoice: None,
+            timeout: None,
+            max_fee_amount: None,
+            max_parts: None,
+            keysend: Some(true),
+            udt_type_script: None,
+            allow_self_payment: false,
+            dry_run: false,
+            custom_records,
+        })
+        .await;
+
+    eprintln!("res: {:?}", res);
+    assert!(res.is_ok());
+    // sleep for a while
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    let payment_hash = res.unwrap().payment_hash;
+    node_0
+        .assert_payment_status(payment_hash, PaymentSessionStatus::Success, Some(1))
+        .await;
+}
+
+#[tokio::test]
+async fn test_send_multi_part_payment_through_parallel_channels() {
+    init_tracing();
+    let _span = tracing::info_span!("node", node = "test").entered();
+
+    let (nodes, channels) = create_n_nodes_with_index_and_amounts_with_established_channel(
+        &[
+            ((0, 1), (MIN_RESERVED_CKB + 10000000000, MIN_RESERVED_CKB)),
+            ((0, 1), (MIN_RESERVED_CKB + 10000000000, MIN_RESERVED_CKB)),
+        ],
+        2,
+        true,
+    )
+    .await;
+    let [mut node_0, mut node_1] = nodes.try_into().expect("2 nodes");
+
+    // sleep for a while
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+    // Neither channel alone can carry the whole amount.
+    let amount = 15000000000;
+    let preimage = gen_rand_sha256_hash();
+    let invoice = InvoiceBuilder::new(Currency::Fibd)
+        .amount(Some(amount))
+        .payment_preimage(preimage)
+        .payee_pub_key(node_1.pubkey.into())
+        .expiry_time(Duration::from_secs(100))
+        .build()
+        .expect("build invoice success");
+    node_1.insert_invoice(invoice.clone(), Some(preimage));
+
+    let send_payment_command = |max_parts, dry_run| SendPaymentCommand {
+        target_pubkey: Some(node_1.pubkey.clone()),
+        amount: Some(amount),
+        payment_hash: None,
+        final_tlc_expiry_delta: None,
+        tlc_expiry_limit: None,
+        invoice: Some(invoice.to_string()),
+        timeout: None,
+        max_fee_amount: None,
+        max_parts,
+        keysend: None,
+        udt_type_script: None,
+        allow_self_payment: false,
+        dry_run,
+        custom_records: vec![],
+    };
+
+    let res = node_0
+        .send_payment(send_payment_command(Some(0), true))
+        .await;
+    assert!(res.is_err());
+    let res = node_0
+        .send_payment(send_payment_command(Some(MAX_PAYMENT_PARTS + 1), true))
+        .await;
+    assert!(res.is_err());
+    let res = node_0.send_payment(send_payment_command(None, true)).await;
+    assert!(res.is_err());
+    let res = node_0
+        .send_payment(send_payment_command(Some(2), true))
+        .await;
+    assert!(res.is_ok());
+
+    let res = node_0
+        .send_payment(send_payment_command(Some(2), false))
+        .await
+        .expect("send payment");
+    // sleep for a while
+    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    node_0
+        .assert_payment_status(res.payment_hash, PaymentSessionStatus::Success, Some(1))
+        .await;
+    let payment_session = node_0
+        .get_payment_session(res.payment_hash)
+        .expect("payment session");
+    assert_eq!(payment_session.parts.len(), 2);
+
+    // Each channel carries a half of the payment.
+    for channel_id in channels {
+        assert_eq!(
+            node_0.get_local_balance_from_channel(channel_id),
+            10000000000 - amount / 2
+        );
+        assert_eq!(
+            node_1.get_local_balance_from_channel(channel_id),
+            amount / 2
+        );
+    }
+}
@@ -2,6 +2,10 @@ use crate::fiber::channel::ChannelActorState;
 use crate::fiber::channel::ChannelActorStateStore;
 use crate::fiber::channel::ChannelCommand;
 use crate::fiber::channel::ChannelCommandWithId;
+use crate::fiber::channel::ChannelSubscribers;
+use crate::fiber::channel::CommitmentSignerProvider;
+use crate::fiber::channel::FeeEstimator;
+use crate::fiber::channel::ForwardPolicy;
 use crate::fiber::graph::NetworkGraphStateStore;
 use crate::fiber::graph::PaymentSession;
 use crate::fiber::graph::PaymentSessionStatus;
@@ -166,7 +170,6 @@ pub fn generate_store() -> Store {
     store.expect("create store")
 }
 
-#[derive(Debug)]
 pub struct NetworkNode {
     /// The base directory of the node, will be deleted after this struct dropped.
     pub base_dir: Arc<TempDir>,
@@ -181,6 +184,23 @@ pub struct NetworkNode {
     pub peer_id: PeerId,
     pub event_emitter: mpsc::Receiver<NetworkServiceEvent>,
     pub pubkey: Pubkey,
+    pub forward_policy: Option<Arc<dyn ForwardPolicy>>,
+    pub commitment_signer_provider: Option<Arc<dyn CommitmentSignerProvider>>,
+    pub fee_estimator: Option<Arc<dyn FeeEstimator>>,
+    pub channel_subscribers: ChannelSubscribers,
+}
+
+// The forward policy and the signer provider are trait objects without `Debug`,
+// so only the identity of the node is shown.
+impl std::fmt::Debug for NetworkNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NetworkNode")
+            .field("base_dir", &self.base_dir)
+            .field("node_name", &self.node_name)
+            .field("peer_id", &self.peer_id)
+            .field("pubkey", &self.pubkey)
+            .finish_non_exhaustive()
+    }
 }
 
 pub struct NetworkNodeConfig {
@@ -188,6 +208,9 @@ pub struct NetworkNodeConfig {
     node_name: Option<String>,
     store: Store,
     fiber_config: FiberConfig,
+    forward_policy: Option<Arc<dyn ForwardPolicy>>,
+    commitment_signer_provider: Option<Arc<dyn CommitmentSignerProvider>>,
+    fee_estimator: Option<Arc<dyn FeeEstimator>>,
 }
 
 impl NetworkNodeConfig {
@@ -202,6 +225,9 @@ pub struct NetworkNodeConfigBuilder {
     // We may generate a FiberConfig based on the base_dir and node_name,
     // but allow user to override it.
     fiber_config_updater: Option<Box<dyn FnOnce(&mut FiberConfig) + 'static>>,
+    forward_policy: Option<Arc<dyn ForwardPolicy>>,
+    commitment_signer_provider: Option<Arc<dyn CommitmentSignerProvider>>,
+    fee_estimator: Option<Arc<dyn FeeEstimator>>,
 }
 
 impl NetworkNodeConfigBuilder {
@@ -210,6 +236,9 @@ impl NetworkNodeConfigBuilder {
             base_dir: None,
             node_name: None,
             fiber_config_updater: None,
+            forward_policy: None,
+            commitment_signer_provider: None,
+            fee_estimator: None,
         }
     }
 
@@ -235,6 +264,24 @@ impl NetworkNodeConfigBuilder {
         self
     }
 
+    pub fn forward_policy(mut self, forward_policy: Arc<dyn ForwardPolicy>) -> Self {
+        self.forward_policy = Some(forward_policy);
+        self
+    }
+
+    pub fn commitment_signer_provider(
+        mut self,
+        commitment_signer_provider: Arc<dyn CommitmentSignerProvider>,
+    ) -> Self {
+        self.commitment_signer_provider = Some(commitment_signer_provider);
+        self
+    }
+
+    pub fn fee_estimator(mut self, fee_estimator: Arc<dyn FeeEstimator>) -> Self {
+        self.fee_estimator = Some(fee_estimator);
+        self
+    }
+
     pub fn build(self) -> NetworkNodeConfig {
         let base_dir = self
             .base_dir
@@ -248,6 +295,9 @@ impl NetworkNodeConfigBuilder {
             node_name,
             store,
             fiber_config,
+            forward_policy: self.forward_policy,
+            commitment_signer_provider: self.commitment_signer_provider,
+            fee_estimator: self.fee_estimator,
         };
         if let Some(updater) = self.fiber_config_updater {
             updater(&mut config.fiber_config);
@@ -289,6 +339,11 @@ pub(crate) async fn establish_channel_between_nodes(
                 tlc_fee_proportional_millionths: a_tlc_fee_proportional_millionths,
                 max_tlc_number_in_flight: a_max_tlc_number_in_flight,
                 max_tlc_value_in_flight: a_max_tlc_value_in_flight,
+                channel_reserve_amount: None,
+                anchor_outputs: false,
+                sorted_settlement_outputs: false,
+                push_amount: None,
+                tlc_dust_limit: None,
             },
             rpc_reply,
         ))
@@ -318,6 +373,8 @@ pub(crate) async fn establish_channel_between_nodes(
                 min_tlc_value: b_tlc_min_value,
                 tlc_fee_proportional_millionths: b_tlc_fee_proportional_millionths,
                 tlc_expiry_delta: b_tlc_expiry_delta,
+                commitment_delay_epoch: None,
+                channel_reserve_amount: None,
             },
             rpc_reply,
         ))
@@ -633,6 +690,9 @@ impl NetworkNode {
             node_name,
             store,
             fiber_config,
+            forward_policy,
+            commitment_signer_provider,
+            fee_estimator,
         } = config;
 
         let _span = tracing::info_span!("NetworkNode", node_name = &node_name).entered();
@@ -656,6 +716,7 @@ impl NetworkNode {
             public_key.clone(),
         )));
 
+        let channel_subscribers = ChannelSubscribers::default();
         let network_actor = Actor::spawn_linked(
             Some(format!("network actor at {}", base_dir.to_str())),
             NetworkActor::new(
@@ -667,8 +728,11 @@ impl NetworkNode {
             NetworkActorStartArguments {
                 config: fiber_config.clone(),
                 tracker: new_tokio_task_tracker(),
-                channel_subscribers: Default::default(),
+                channel_subscribers: channel_subscribers.clone(),
                 default_shutdown_script: Default::default(),
+                forward_policy: forward_policy.clone(),
+                commitment_signer_provider: commitment_signer_provider.clone(),
+                fee_estimator: fee_estimator.clone(),
             },
             root.get_cell(),
         )
@@ -707,6 +771,10 @@ impl NetworkNode {
             peer_id,
             event_emitter: event_receiver,
             pubkey: public_key.into(),
+            forward_policy,
+            commitment_signer_provider,
+            fee_estimator,
+            channel_subscribers,
         }
     }
 
@@ -716,6 +784,9 @@ impl NetworkNode {
             node_name: self.node_name.clone(),
             store: self.store.clone(),
             fiber_config: self.fiber_config.clone(),
+            forward_policy: self.forward_policy.clone(),
+            commitment_signer_provider: self.commitment_signer_provider.clone(),
+            fee_estimator: self.fee_estimator.clone(),
         }
     }
 
@@ -4,8 +4,8 @@ use crate::{
         hash_algorithm::HashAlgorithm,
         types::{
             secp256k1_instance, AddTlc, BroadcastMessageID, Cursor, Hash256, PaymentHopData,
-            PeeledOnionPacket, Privkey, Pubkey, TlcErr, TlcErrPacket, TlcErrorCode,
-            NO_SHARED_SECRET,
+            PaymentOnionPacket, PeeledOnionPacket, Privkey, Pubkey, TlcErr, TlcErrPacket,
+            TlcErrorCode, NO_SHARED_SECRET,
         },
     },
     gen_rand_channel_outpoint, gen_rand_fiber_private_key, gen_rand_fiber_public_key,
@@ -127,6 +127,8 @@ fn test_peeled_onion_packet() {
             funding_tx_hash: Hash256::default(),
             hash_algorithm: HashAlgorithm::Sha256,
             payment_preimage: None,
+            custom_records: vec![],
+            total_amount: None,
         },
         PaymentHopData {
             amount: 5,
@@ -135,6 +137,8 @@ fn test_peeled_onion_packet() {
             funding_tx_hash: Hash256::default(),
             hash_algorithm: HashAlgorithm::Sha256,
             payment_preimage: None,
+            custom_records: vec![],
+            total_amount: None,
         },
         PaymentHopData {
             amount: 8,
@@ -143,6 +147,8 @@ fn test_peeled_onion_packet() {
             funding_tx_hash: Hash256::default(),
             hash_algorithm: HashAlgorithm::Sha256,
             payment_preimage: None,
+            custom_records: vec![(1, b"memo".to_vec()), (65537, vec![0xab; 32])],
+            total_amount: Some(8),
         },
     ];
     let packet = PeeledOnionPacket::create(
@@ -170,6 +176,50 @@ fn test_peeled_onion_packet() {
     assert!(packet.is_last());
 }
 
+#[test]
+fn test_onion_packet_check_format() {
+    let secp = Secp256k1::new();
+    let keys: Vec<Privkey> = std::iter::repeat_with(|| gen_rand_fiber_private_key())
+        .take(2)
+        .collect();
+    let hops_infos = vec![
+        PaymentHopData {
+            amount: 2,
+            expiry: 3,
+            next_hop: Some(keys[1].pubkey().into()),
+            funding_tx_hash: Hash256::default(),
+            hash_algorithm: HashAlgorithm::Sha256,
+            payment_preimage: None,
+            custom_records: vec![],
+            total_amount: None,
+        },
+        PaymentHopData {
+            amount: 2,
+            expiry: 3,
+            next_hop: None,
+            funding_tx_hash: Hash256::default(),
+            hash_algorithm: HashAlgorithm::Sha256,
+            payment_preimage: None,
+            custom_records: vec![],
+            total_amount: None,
+        },
+    ];
+    let packet = PeeledOnionPacket::create(gen_rand_fiber_private_key(), hops_infos, None, &secp)
+        .expect("create peeled packet");
+    let onion_packet = packet.next.expect("next packet");
+    assert!(onion_packet.check_format().is_ok());
+
+    let mut data = onion_packet.into_bytes();
+    data[0] = 1;
+    assert!(PaymentOnionPacket::new(data.clone())
+        .check_format()
+        .is_err());
+
+    data[0] = 0;
+    data.push(0);
+    assert!(PaymentOnionPacket::new(data).check_format().is_err());
+}
+
 #[test]
 fn test_tlc_fail_error() {
     let tlc_fail_detail = TlcErr::new(TlcErrorCode::InvalidOnionVersion);
@@ -221,6 +271,10 @@ fn test_tlc_err_packet_encryption() {
             .decode(session_key.as_ref(), hops_path.clone())
             .expect("decrypted");
         assert_eq!(decrypted_tlc_fail_detail, tlc_fail_detail);
+        assert_eq!(
+            tlc_fail.decode_with_shared_secrets(&hops_ss),
+            Some(tlc_fail_detail.clone())
+        );
     }
 
     {
@@ -232,5 +286,11 @@ fn test_tlc_err_packet_encryption() {
             .decode(session_key.as_ref(), hops_path.clone())
             .expect("decrypted");
         assert_eq!(decrypted_tlc_fail_detail, tlc_fail_detail);
+        assert_eq!(
+            tlc_fail.decode_with_shared_secrets(&hops_ss),
+            Some(tlc_fail_detail.clone())
+        );
+        // the error can't be decoded without the shared secret of the erring hop
+        assert_eq!(tlc_fail.decode_with_shared_secrets(&hops_ss[0..2]), None);
     }
 }
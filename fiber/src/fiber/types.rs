@@ -1,18 +1,18 @@
-use super::channel::{ChannelFlags, CHANNEL_DISABLED_FLAG, MESSAGE_OF_NODE2_FLAG};
+use super::channel::{ChannelFlags, FeatureBits, CHANNEL_DISABLED_FLAG, MESSAGE_OF_NODE2_FLAG};
 use super::config::AnnouncedNodeName;
 use super::gen::fiber::{self as molecule_fiber, PubNonce as Byte66, UdtCellDeps, Uint128Opt};
 use super::gen::gossip::{self as molecule_gossip};
 use super::hash_algorithm::{HashAlgorithm, UnknownHashAlgorithmError};
 use super::network::get_chain_hash;
 use super::r#gen::fiber::PubNonceOpt;
-use super::serde_utils::{EntityHex, SliceHex};
+use super::serde_utils::{EntityHex, SliceHex, U64Hex};
 use crate::ckb::config::{UdtArgInfo, UdtCellDep, UdtCfgInfos, UdtScript};
 use crate::ckb::contracts::get_udt_whitelist;
 
 use anyhow::anyhow;
 use ckb_types::{
     core::FeeRate,
-    packed::{Byte32 as MByte32, BytesVec, OutPoint, Script, Transaction},
+    packed::{Byte32 as MByte32, Bytes, BytesVec, OutPoint, Script, Transaction},
     prelude::{Pack, Unpack},
 };
 use core::fmt::{self, Formatter};
@@ -388,6 +388,12 @@ pub enum OnionPacketError {
     #[error("Fail to deserialize the hop data")]
     InvalidHopData,
 
+    #[error("Unknown onion packet version {0}")]
+    InvalidVersion(u8),
+
+    #[error("Invalid onion packet length {0}, expect {1}")]
+    InvalidLength(usize, usize),
+
     #[error("Sphinx protocol error")]
     Sphinx(#[from] SphinxError),
 }
@@ -523,6 +529,12 @@ pub struct OpenChannel {
     pub channel_announcement_nonce: Option<PubNonce>,
     pub next_local_nonce: PubNonce,
     pub channel_flags: ChannelFlags,
+    pub push_amount: u128,
+    pub tlc_dust_limit: u128,
+    // The hash algorithms allowed by the opener for tlcs, empty if the opener doesn't restrict them.
+    pub allowed_hash_algorithms: Vec<HashAlgorithm>,
+    // The optional features supported by the opener.
+    pub features: FeatureBits,
 }
 
 impl OpenChannel {
@@ -537,6 +549,14 @@ impl OpenChannel {
     pub fn is_public(&self) -> bool {
         self.channel_flags.contains(ChannelFlags::PUBLIC)
     }
+
+    pub fn has_anchor_outputs(&self) -> bool {
+        self.channel_flags.contains(ChannelFlags::ANCHOR_OUTPUTS)
+    }
+
+    pub fn has_sorted_settlement_outputs(&self) -> bool {
+        self.channel_flags.contains(ChannelFlags::SORTED_SETTLEMENT_OUTPUTS)
+    }
 }
 
 impl From<OpenChannel> for molecule_fiber::OpenChannel {
@@ -564,6 +584,10 @@ impl From<OpenChannel> for molecule_fiber::OpenChannel {
                     .build(),
             )
             .channel_flags(open_channel.channel_flags.bits().into())
+            .push_amount(open_channel.push_amount.pack())
+            .tlc_dust_limit(open_channel.tlc_dust_limit.pack())
+            .allowed_hash_algorithms(pack_hash_algorithms(&open_channel.allowed_hash_algorithms))
+            .features(open_channel.features.bits().pack())
             .build()
     }
 }
@@ -601,6 +625,13 @@ impl TryFrom<molecule_fiber::OpenChannel> for OpenChannel {
             channel_flags: ChannelFlags::from_bits(open_channel.channel_flags().into()).ok_or(
                 anyhow!("Invalid channel flags: {}", open_channel.channel_flags()),
             )?,
+            push_amount: open_channel.push_amount().unpack(),
+            tlc_dust_limit: open_channel.tlc_dust_limit().unpack(),
+            allowed_hash_algorithms: unpack_hash_algorithms(
+                open_channel.allowed_hash_algorithms().unpack(),
+            ),
+            // The features unknown to us are dropped.
+            features: FeatureBits::from_bits_truncate(open_channel.features().unpack()),
         })
     }
 }
@@ -619,6 +650,31 @@ pub struct AcceptChannel {
     pub second_per_commitment_point: Pubkey,
     pub channel_announcement_nonce: Option<PubNonce>,
     pub next_local_nonce: PubNonce,
+    // The commitment delay epoch used by the acceptor, it may be different from
+    // the one proposed in the OpenChannel message.
+    pub commitment_delay_epoch: u64,
+    // The hash algorithms allowed by both parties for tlcs, empty if the acceptor doesn't
+    // restrict them.
+    pub allowed_hash_algorithms: Vec<HashAlgorithm>,
+    // The optional features supported by the acceptor.
+    pub features: FeatureBits,
+}
+
+// The hash algorithms are encoded as the bytes of their ids in the messages.
+fn pack_hash_algorithms(hash_algorithms: &[HashAlgorithm]) -> Bytes {
+    hash_algorithms
+        .iter()
+        .map(|algorithm| *algorithm as u8)
+        .collect::<Vec<_>>()
+        .pack()
+}
+
+// Unknown hash algorithms are ignored as we can't use them anyway.
+fn unpack_hash_algorithms(bytes: Vec<u8>) -> Vec<HashAlgorithm> {
+    bytes
+        .into_iter()
+        .filter_map(|byte| HashAlgorithm::try_from(byte).ok())
+        .collect()
 }
 
 impl From<AcceptChannel> for molecule_fiber::AcceptChannel {
@@ -644,6 +700,9 @@ impl From<AcceptChannel> for molecule_fiber::AcceptChannel {
                     .build(),
             )
             .next_local_nonce((&accept_channel.next_local_nonce).into())
+            .commitment_delay_epoch(accept_channel.commitment_delay_epoch.pack())
+            .allowed_hash_algorithms(pack_hash_algorithms(&accept_channel.allowed_hash_algorithms))
+            .features(accept_channel.features.bits().pack())
             .build()
     }
 }
@@ -673,6 +732,12 @@ impl TryFrom<molecule_fiber::AcceptChannel> for AcceptChannel {
                 .next_local_nonce()
                 .try_into()
                 .map_err(|err| Error::Musig2(format!("{err}")))?,
+            commitment_delay_epoch: accept_channel.commitment_delay_epoch().unpack(),
+            allowed_hash_algorithms: unpack_hash_algorithms(
+                accept_channel.allowed_hash_algorithms().unpack(),
+            ),
+            // The features unknown to us are dropped.
+            features: FeatureBits::from_bits_truncate(accept_channel.features().unpack()),
         })
     }
 }
@@ -1042,12 +1107,17 @@ impl TryFrom<molecule_fiber::AddTlc> for AddTlc {
     }
 }
 
+/// Placeholder of `RevokeAndAck::last_per_commitment_secret` on the wire when no secret is revealed.
+const NO_COMMITMENT_SECRET: [u8; 32] = [0u8; 32];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RevokeAndAck {
     pub channel_id: Hash256,
     pub revocation_partial_signature: PartialSignature,
     pub commitment_tx_partial_signature: PartialSignature,
     pub next_per_commitment_point: Pubkey,
+    // The secret of the commitment revoked by this message, if revealed.
+    pub last_per_commitment_secret: Option<[u8; 32]>,
 }
 
 impl From<RevokeAndAck> for molecule_fiber::RevokeAndAck {
@@ -1061,6 +1131,11 @@ impl From<RevokeAndAck> for molecule_fiber::RevokeAndAck {
                 revoke_and_ack.commitment_tx_partial_signature,
             ))
             .next_per_commitment_point(revoke_and_ack.next_per_commitment_point.into())
+            .last_per_commitment_secret(u8_32_as_byte_32(
+                &revoke_and_ack
+                    .last_per_commitment_secret
+                    .unwrap_or(NO_COMMITMENT_SECRET),
+            ))
             .build()
     }
 }
@@ -1080,6 +1155,14 @@ impl TryFrom<molecule_fiber::RevokeAndAck> for RevokeAndAck {
             )
             .map_err(|e| anyhow!(e))?,
             next_per_commitment_point: revoke_and_ack.next_per_commitment_point().try_into()?,
+            last_per_commitment_secret: {
+                let secret: [u8; 32] = revoke_and_ack
+                    .last_per_commitment_secret()
+                    .as_slice()
+                    .try_into()
+                    .expect("Byte32 to [u8; 32]");
+                (secret != NO_COMMITMENT_SECRET).then_some(secret)
+            },
         })
     }
 }
@@ -1216,6 +1299,8 @@ pub struct TlcErrPacket {
 }
 
 pub const NO_SHARED_SECRET: [u8; 32] = [0u8; 32];
+/// The version of sphinx onion packets, which is the first byte of the serialized packet.
+pub const ONION_PACKET_VERSION: u8 = 0;
 const NO_ERROR_PACKET_HMAC: [u8; 32] = [0u8; 32];
 
 /// Always decrypting 27 times so the erroring node cannot learn its relative position in the route
@@ -1278,6 +1363,36 @@ impl TlcErrPacket {
                 error
             })
     }
+
+    /// Decode the error packet with the shared secrets of the hops in the payment route, the first
+    /// hop goes first. Unlike [`TlcErrPacket::decode`], the shared secrets are given directly, so
+    /// that the obfuscation of the error packets can be checked against other implementations.
+    #[cfg(any(test, feature = "onion-error-debug"))]
+    pub fn decode_with_shared_secrets(&self, shared_secrets: &[[u8; 32]]) -> Option<TlcErr> {
+        if self.is_plaintext() {
+            return TlcErr::deserialize(&self.onion_packet[32..]);
+        }
+
+        let mut onion_packet = self.onion_packet.clone();
+        for shared_secret in shared_secrets {
+            let decrypted = OnionErrorPacket::from_bytes(onion_packet.clone())
+                .xor_cipher_stream(shared_secret)
+                .into_bytes();
+            if decrypted.len() >= 32 {
+                let payload = decrypted[32..].to_vec();
+                // The erring hop creates the error packet with its own shared secret, so the
+                // packet created again from the payload must be the one before this pass.
+                if let Some(error) = TlcErr::deserialize(&payload) {
+                    if OnionErrorPacket::create(shared_secret, payload).into_bytes() == onion_packet
+                    {
+                        return Some(error);
+                    }
+                }
+            }
+            onion_packet = decrypted;
+        }
+        None
+    }
 }
 
 impl From<TlcErrPacket> for molecule_fiber::TlcErrPacket {
@@ -1314,7 +1429,7 @@ const NODE: u16 = 0x2000;
 const UPDATE: u16 = 0x1000;
 
 #[repr(u16)]
-#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, AsRefStr, EnumString)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, AsRefStr, EnumString)]
 pub enum TlcErrorCode {
     TemporaryNodeFailure = NODE | 2,
     PermanentNodeFailure = PERM | NODE | 2,
@@ -1341,6 +1456,8 @@ pub enum TlcErrorCode {
     ChannelDisabled = UPDATE | 20,
     ExpiryTooFar = PERM | 21,
     InvalidOnionPayload = PERM | 22,
+    // The parts of a multi-part payment are not all received before the earliest part expires.
+    MppTimeout = 23,
     InvalidOnionError = BADONION | PERM | 25,
 }
 
@@ -1470,6 +1587,8 @@ pub struct ReestablishChannel {
     pub channel_id: Hash256,
     pub local_commitment_number: u64,
     pub remote_commitment_number: u64,
+    // Request the peer to force close the channel, sent by the node restoring the channel from a backup.
+    pub force_close_request: bool,
 }
 
 impl From<ReestablishChannel> for molecule_fiber::ReestablishChannel {
@@ -1478,6 +1597,7 @@ impl From<ReestablishChannel> for molecule_fiber::ReestablishChannel {
             .channel_id(reestablish_channel.channel_id.into())
             .local_commitment_number(reestablish_channel.local_commitment_number.pack())
             .remote_commitment_number(reestablish_channel.remote_commitment_number.pack())
+            .force_close_request(Byte::new(reestablish_channel.force_close_request as u8))
             .build()
     }
 }
@@ -1492,6 +1612,7 @@ impl TryFrom<molecule_fiber::ReestablishChannel> for ReestablishChannel {
             channel_id: reestablish_channel.channel_id().into(),
             local_commitment_number: reestablish_channel.local_commitment_number().unpack(),
             remote_commitment_number: reestablish_channel.remote_commitment_number().unpack(),
+            force_close_request: u8::from(reestablish_channel.force_close_request()) != 0,
         })
     }
 }
@@ -1535,6 +1656,37 @@ impl TryFrom<molecule_fiber::AnnouncementSignatures> for AnnouncementSignatures
     }
 }
 
+// Proposes to update the commitment fee rate of the channel, or replies to such a proposal
+// with the commitment fee rate the replier uses after handling it.
+#[derive(Debug, Clone)]
+pub struct UpdateFee {
+    pub channel_id: Hash256,
+    pub fee_rate: u64,
+    pub ack: bool,
+}
+
+impl From<UpdateFee> for molecule_fiber::UpdateFee {
+    fn from(update_fee: UpdateFee) -> Self {
+        molecule_fiber::UpdateFee::new_builder()
+            .channel_id(update_fee.channel_id.into())
+            .fee_rate(update_fee.fee_rate.pack())
+            .ack(Byte::new(update_fee.ack as u8))
+            .build()
+    }
+}
+
+impl TryFrom<molecule_fiber::UpdateFee> for UpdateFee {
+    type Error = Error;
+
+    fn try_from(update_fee: molecule_fiber::UpdateFee) -> Result<Self, Self::Error> {
+        Ok(UpdateFee {
+            channel_id: update_fee.channel_id().into(),
+            fee_rate: update_fee.fee_rate().unpack(),
+            ack: u8::from(update_fee.ack()) != 0,
+        })
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
 pub struct NodeAnnouncement {
     // Signature to this message, may be empty the message is not signed yet.
@@ -1710,6 +1862,7 @@ impl From<molecule_fiber::UdtArgInfo> for UdtArgInfo {
                 .into_iter()
                 .map(|cell_dep| cell_dep.into())
                 .collect(),
+            metadata: None,
         }
     }
 }
@@ -2141,6 +2294,10 @@ impl FiberMessage {
             announcement_signatures,
         ))
     }
+
+    pub fn update_fee(update_fee: UpdateFee) -> Self {
+        FiberMessage::ChannelNormalOperation(FiberChannelMessage::UpdateFee(update_fee))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -2161,6 +2318,7 @@ pub enum FiberChannelMessage {
     RemoveTlc(RemoveTlc),
     ReestablishChannel(ReestablishChannel),
     AnnouncementSignatures(AnnouncementSignatures),
+    UpdateFee(UpdateFee),
 }
 
 impl FiberChannelMessage {
@@ -2188,6 +2346,7 @@ impl FiberChannelMessage {
             FiberChannelMessage::AnnouncementSignatures(annoucement_signatures) => {
                 annoucement_signatures.channel_id
             }
+            FiberChannelMessage::UpdateFee(update_fee) => update_fee.channel_id,
         }
     }
 }
@@ -3159,6 +3318,9 @@ impl From<FiberMessage> for molecule_fiber::FiberMessageUnion {
                         announcement_signatures.into(),
                     )
                 }
+                FiberChannelMessage::UpdateFee(update_fee) => {
+                    molecule_fiber::FiberMessageUnion::UpdateFee(update_fee.into())
+                }
             },
         }
     }
@@ -3252,6 +3414,11 @@ impl TryFrom<molecule_fiber::FiberMessageUnion> for FiberMessage {
                     announcement_signatures.try_into()?,
                 ))
             }
+            molecule_fiber::FiberMessageUnion::UpdateFee(update_fee) => {
+                FiberMessage::ChannelNormalOperation(FiberChannelMessage::UpdateFee(
+                    update_fee.try_into()?,
+                ))
+            }
         })
     }
 }
@@ -3310,6 +3477,50 @@ pub struct PaymentHopData {
     pub hash_algorithm: HashAlgorithm,
     pub funding_tx_hash: Hash256,
     pub next_hop: Option<Pubkey>,
+    // The custom records (type, value) attached by the sender for the final hop,
+    // omitted from the serialized hop data when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde_as(as = "Vec<(U64Hex, SliceHex)>")]
+    pub custom_records: Vec<(u64, Vec<u8>)>,
+    // The total amount of a multi-part payment, only specified in the last hop of each part,
+    // omitted from the serialized hop data when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_amount: Option<u128>,
+}
+
+/// The custom record type (`probe` in ASCII) marking a probe payment, which is used to measure
+/// the liquidity of the route and always failed by the final hop.
+pub const PROBE_CUSTOM_RECORD_TYPE: u64 = 0x7072_6f62_65;
+
+impl PaymentHopData {
+    /// Whether the hop data of the final hop marks the payment as a probe.
+    pub fn is_probe(&self) -> bool {
+        self.custom_records
+            .iter()
+            .any(|(record_type, _)| *record_type == PROBE_CUSTOM_RECORD_TYPE)
+    }
+
+    /// The number of bytes the custom records add to the serialized hop data, which is the
+    /// space they take in the onion packet.
+    pub fn serialized_custom_records_size(custom_records: &[(u64, Vec<u8>)]) -> usize {
+        let hop_data = PaymentHopData {
+            amount: 0,
+            expiry: 0,
+            payment_preimage: None,
+            hash_algorithm: HashAlgorithm::default(),
+            funding_tx_hash: Hash256::default(),
+            next_hop: None,
+            custom_records: custom_records.to_vec(),
+            total_amount: None,
+        };
+        let with_records = deterministically_serialize(&hop_data).len();
+        let without_records = deterministically_serialize(&PaymentHopData {
+            custom_records: vec![],
+            ..hop_data
+        })
+        .len();
+        with_records - without_records
+    }
 }
 
 /// Trait for hop data
@@ -3385,6 +3596,27 @@ impl<T> OnionPacket<T> {
 }
 
 impl<T: HopData> OnionPacket<T> {
+    /// The serialized length of packets: version (1 byte), ephemeral public key (33 bytes),
+    /// packet data and hmac (32 bytes).
+    pub const PACKET_LEN: usize = 1 + 33 + T::PACKET_DATA_LEN + 32;
+
+    /// Cheap format checks before peeling the packet, which requires an ECDH and
+    /// decrypting the whole packet data.
+    pub fn check_format(&self) -> Result<(), Error> {
+        if self.data.len() != Self::PACKET_LEN {
+            return Err(Error::OnionPacket(OnionPacketError::InvalidLength(
+                self.data.len(),
+                Self::PACKET_LEN,
+            )));
+        }
+        if self.data[0] != ONION_PACKET_VERSION {
+            return Err(Error::OnionPacket(OnionPacketError::InvalidVersion(
+                self.data[0],
+            )));
+        }
+        Ok(())
+    }
+
     /// Peels the next layer of the onion packet using the privkey of the current node.
     ///
     /// Returns errors when:
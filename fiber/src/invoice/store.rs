@@ -15,4 +15,7 @@ pub trait InvoiceStore {
         status: CkbInvoiceStatus,
     ) -> Result<(), InvoiceError>;
     fn get_invoice_status(&self, id: &Hash256) -> Option<CkbInvoiceStatus>;
+    /// A held invoice keeps its received TLCs pending until they are released explicitly.
+    fn set_invoice_hold(&self, id: &Hash256, hold: bool) -> Result<(), InvoiceError>;
+    fn is_invoice_held(&self, id: &Hash256) -> bool;
 }
@@ -101,6 +101,9 @@ pub async fn main() -> Result<(), ExitMessage> {
                         .to_string(),
                 )
             })?;
+            fiber_config
+                .fee_policy_bounds()
+                .map_err(|err| ExitMessage(format!("invalid fiber config: {}", err)))?;
             let node_public_key = fiber_config.public_key();
 
             let chain = fiber_config.chain.as_str();
@@ -162,6 +165,9 @@ pub async fn main() -> Result<(), ExitMessage> {
                 subscribers.clone(),
                 network_graph.clone(),
                 default_shutdown_script,
+                None,
+                None,
+                None,
             )
             .await;
 
@@ -9,7 +9,7 @@ use crate::fiber::{
     graph::PaymentSessionStatus,
     hash_algorithm::HashAlgorithm,
     network::{AcceptChannelCommand, OpenChannelCommand, SendPaymentCommand},
-    serde_utils::{EntityHex, U128Hex, U64Hex},
+    serde_utils::{EntityHex, SliceHex, U128Hex, U64Hex},
     types::{
         Hash256, Pubkey, RemoveTlcFulfill, TlcErr, TlcErrPacket, TlcErrorCode, NO_SHARED_SECRET,
     },
@@ -55,7 +55,7 @@ pub(crate) struct OpenChannelParams {
     /// The script used to receive the channel balance, an optional parameter, default value is the secp256k1_blake160_sighash_all script corresponding to the configured private key.
     shutdown_script: Option<Script>,
 
-    /// The delay time for the commitment transaction, must be an [EpochNumberWithFraction](https://github.com/nervosnetwork/rfcs/blob/master/rfcs/0017-tx-valid-since/e-i-l-encoding.png) in u64 format, an optional parameter, default value is 24 hours.
+    /// The delay time for the commitment transaction of the peer, must be an [EpochNumberWithFraction](https://github.com/nervosnetwork/rfcs/blob/master/rfcs/0017-tx-valid-since/e-i-l-encoding.png) in u64 format, an optional parameter, default value is 24 hours.
     commitment_delay_epoch: Option<EpochNumberWithFraction>,
 
     /// The fee rate for the commitment transaction, an optional parameter.
@@ -92,6 +92,34 @@ pub(crate) struct OpenChannelParams {
     /// This parameter can not be updated after channel is opened.
     #[serde_as(as = "Option<U64Hex>")]
     max_tlc_number_in_flight: Option<u64>,
+
+    /// The minimal balance our side must keep in the channel after offering TLCs, default is 0.
+    /// It's a local policy which is not negotiated with the peer, the TLCs offered by the peer
+    /// are not limited by it.
+    /// This parameter can not be updated after channel is opened.
+    #[serde_as(as = "Option<U128Hex>")]
+    channel_reserve_amount: Option<u128>,
+
+    /// Whether to add an anchor output for each party to the commitment transactions, default is false.
+    /// The anchor output can be spent by a child transaction to bump the fee of the force close,
+    /// and it's paid by the reserved CKB amount of each party.
+    anchor_outputs: Option<bool>,
+
+    /// Whether to sort the outputs of the settlement transactions by the lock scripts and the amounts,
+    /// default is false. The sorted outputs don't reveal which party they belong to on-chain.
+    sorted_settlement_outputs: Option<bool>,
+
+    /// The amount to push to the peer from our funding amount at channel opening, default is 0.
+    /// The peer owns the pushed amount from the beginning, e.g. to provide inbound liquidity for us.
+    #[serde_as(as = "Option<U128Hex>")]
+    push_amount: Option<u128>,
+
+    /// The TLCs with amount below the dust limit are not included in the commitment transactions,
+    /// default is 0, which means no TLC is trimmed. For CKB channels, it's uneconomical to enforce
+    /// a TLC on chain if its amount is less than the occupied capacity of the cell to claim it.
+    /// This parameter can not be updated after channel is opened.
+    #[serde_as(as = "Option<U128Hex>")]
+    tlc_dust_limit: Option<u128>,
 }
 #[derive(Clone, Serialize)]
 pub(crate) struct OpenChannelResult {
@@ -138,6 +166,18 @@ pub(crate) struct AcceptChannelParams {
     /// The expiry delta to forward a tlc, in milliseconds, default to 1 day, which is 24 * 60 * 60 * 1000 milliseconds
     /// This parameter can be updated with rpc `update_channel` later.
     tlc_expiry_delta: Option<u64>,
+
+    /// The delay time for the commitment transaction of the opener, an optional parameter to
+    /// propose a different value from the one requested by the opener for our commitment transaction,
+    /// default is the opener's value.
+    commitment_delay_epoch: Option<EpochNumberWithFraction>,
+
+    /// The minimal balance our side must keep in the channel after offering TLCs, default is 0.
+    /// It's a local policy which is not negotiated with the peer, the TLCs offered by the peer
+    /// are not limited by it.
+    /// This parameter can not be updated after channel is opened.
+    #[serde_as(as = "Option<U128Hex>")]
+    channel_reserve_amount: Option<u128>,
 }
 
 #[derive(Clone, Serialize)]
@@ -274,6 +314,8 @@ pub(crate) struct AddTlcParams {
     expiry: u64,
     /// The hash algorithm of the TLC
     hash_algorithm: Option<HashAlgorithm>,
+    /// The key to retry adding the TLC safely, a retry with the same key returns the TLC added by the first attempt
+    idempotency_key: Option<String>,
 }
 
 #[serde_as]
@@ -296,6 +338,22 @@ pub(crate) struct RemoveTlcParams {
     reason: RemoveTlcReason,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct SettleHeldTlcParams {
+    /// The channel ID of the channel which received the held TLC
+    channel_id: Hash256,
+    /// The payment hash of the hold invoice to settle
+    payment_hash: Hash256,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ApproveTlcParams {
+    /// The channel ID of the channel which received the TLC pending approval
+    channel_id: Hash256,
+    /// The payment hash of the TLC without an invoice to approve
+    payment_hash: Hash256,
+}
+
 /// The reason for removing a TLC
 #[serde_as]
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -319,6 +377,15 @@ pub(crate) struct ShutdownChannelParams {
     /// The fee rate for the closing transaction, the fee will be deducted from the closing initiator's channel balance
     #[serde_as(as = "U64Hex")]
     fee_rate: u64,
+    /// Whether to allow closing to a lock script whose code hash is not in the node's allowed list, default is false
+    allow_unknown_close_script: Option<bool>,
+    /// The number of blocks within which the closing transaction is expected to be confirmed,
+    /// the fee rate is estimated from it instead of using `fee_rate` if it's set, only for the cooperative close
+    #[serde_as(as = "Option<U64Hex>")]
+    target_blocks: Option<u64>,
+    /// The minimal CKB amount to get back after paying the fee, the cooperative close is rejected if the balance is below it
+    #[serde_as(as = "Option<U64Hex>")]
+    min_net_close_balance: Option<u64>,
 }
 
 #[serde_as]
@@ -338,6 +405,12 @@ pub struct UpdateChannelParams {
     /// The fee proportional millionths for a TLC
     #[serde_as(as = "Option<U128Hex>")]
     tlc_fee_proportional_millionths: Option<u128>,
+    /// The minimal expiry delta for the TLC locktime of this channel
+    #[serde_as(as = "Option<U64Hex>")]
+    min_tlc_expiry_delta: Option<u64>,
+    /// The maximal expiry delta for the TLC locktime of this channel
+    #[serde_as(as = "Option<U64Hex>")]
+    max_tlc_expiry_delta: Option<u64>,
 }
 
 #[serde_as]
@@ -401,7 +474,8 @@ pub(crate) struct SendPaymentCommandParams {
     #[serde_as(as = "Option<U128Hex>")]
     max_fee_amount: Option<u128>,
 
-    /// max parts for the payment, only used for multi-part payments
+    /// max parts for the payment, the payment is split evenly into this many parts sent through
+    /// different first hop channels, at most 16
     #[serde_as(as = "Option<U64Hex>")]
     max_parts: Option<u64>,
 
@@ -418,6 +492,11 @@ pub(crate) struct SendPaymentCommandParams {
     /// it's useful for the sender to double check the payment before sending it to the network,
     /// default is false
     dry_run: Option<bool>,
+
+    /// the custom records (type, value) attached to the final hop's onion payload,
+    /// the total size of the values should be at most 2048 bytes
+    #[serde_as(as = "Option<Vec<(U64Hex, SliceHex)>>")]
+    custom_records: Option<Vec<(u64, Vec<u8>)>>,
 }
 
 /// RPC module for channel management.
@@ -459,6 +538,14 @@ trait ChannelRpc {
     #[method(name = "remove_tlc")]
     async fn remove_tlc(&self, params: RemoveTlcParams) -> Result<(), ErrorObjectOwned>;
 
+    /// Settles the TLCs held by a hold invoice.
+    #[method(name = "settle_held_tlc")]
+    async fn settle_held_tlc(&self, params: SettleHeldTlcParams) -> Result<(), ErrorObjectOwned>;
+
+    /// Approves the TLCs without invoices held by the `hold_for_approval` policy.
+    #[method(name = "approve_tlc")]
+    async fn approve_tlc(&self, params: ApproveTlcParams) -> Result<(), ErrorObjectOwned>;
+
     /// Shuts down a channel.
     #[method(name = "shutdown_channel")]
     async fn shutdown_channel(&self, params: ShutdownChannelParams)
@@ -525,6 +612,11 @@ where
                     tlc_fee_proportional_millionths: params.tlc_fee_proportional_millionths,
                     max_tlc_value_in_flight: params.max_tlc_value_in_flight,
                     max_tlc_number_in_flight: params.max_tlc_number_in_flight,
+                    channel_reserve_amount: params.channel_reserve_amount,
+                    anchor_outputs: params.anchor_outputs.unwrap_or_default(),
+                    sorted_settlement_outputs: params.sorted_settlement_outputs.unwrap_or_default(),
+                    push_amount: params.push_amount,
+                    tlc_dust_limit: params.tlc_dust_limit,
                 },
                 rpc_reply,
             ))
@@ -549,6 +641,8 @@ where
                     min_tlc_value: params.tlc_min_value,
                     tlc_fee_proportional_millionths: params.tlc_fee_proportional_millionths,
                     tlc_expiry_delta: params.tlc_expiry_delta,
+                    commitment_delay_epoch: params.commitment_delay_epoch,
+                    channel_reserve_amount: params.channel_reserve_amount,
                 },
                 rpc_reply,
             ))
@@ -627,6 +721,8 @@ where
                             onion_packet: None,
                             shared_secret: NO_SHARED_SECRET.clone(),
                             previous_tlc: None,
+                            payment_preimage: None,
+                            idempotency_key: params.idempotency_key.clone(),
                         },
                         rpc_reply,
                     ),
@@ -685,6 +781,32 @@ where
         handle_actor_call!(self.actor, message, params)
     }
 
+    async fn settle_held_tlc(&self, params: SettleHeldTlcParams) -> Result<(), ErrorObjectOwned> {
+        let message = |rpc_reply| -> NetworkActorMessage {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: params.channel_id,
+                    command: ChannelCommand::SettleHeldTlc(params.payment_hash, rpc_reply),
+                },
+            ))
+        };
+
+        handle_actor_call!(self.actor, message, params)
+    }
+
+    async fn approve_tlc(&self, params: ApproveTlcParams) -> Result<(), ErrorObjectOwned> {
+        let message = |rpc_reply| -> NetworkActorMessage {
+            NetworkActorMessage::Command(NetworkActorCommand::ControlFiberChannel(
+                ChannelCommandWithId {
+                    channel_id: params.channel_id,
+                    command: ChannelCommand::ApproveTlc(params.payment_hash, rpc_reply),
+                },
+            ))
+        };
+
+        handle_actor_call!(self.actor, message, params)
+    }
+
     async fn shutdown_channel(
         &self,
         params: ShutdownChannelParams,
@@ -698,6 +820,11 @@ where
                             close_script: params.close_script.clone().into(),
                             fee_rate: FeeRate::from_u64(params.fee_rate),
                             force: params.force.unwrap_or(false),
+                            allow_unknown_close_script: params
+                                .allow_unknown_close_script
+                                .unwrap_or(false),
+                            target_blocks: params.target_blocks,
+                            min_net_close_balance: params.min_net_close_balance,
                         },
                         rpc_reply,
                     ),
@@ -718,6 +845,8 @@ where
                             tlc_expiry_delta: params.tlc_expiry_delta,
                             tlc_minimum_value: params.tlc_minimum_value,
                             tlc_fee_proportional_millionths: params.tlc_fee_proportional_millionths,
+                            min_tlc_expiry_delta: params.min_tlc_expiry_delta,
+                            max_tlc_expiry_delta: params.max_tlc_expiry_delta,
                         },
                         rpc_reply,
                     ),
@@ -747,6 +876,7 @@ where
                     udt_type_script: params.udt_type_script.clone().map(|s| s.into()),
                     allow_self_payment: params.allow_self_payment.unwrap_or(false),
                     dry_run: params.dry_run.unwrap_or(false),
+                    custom_records: params.custom_records.clone().unwrap_or_default(),
                 },
                 rpc_reply,
             ))
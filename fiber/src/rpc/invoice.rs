@@ -37,6 +37,8 @@ pub(crate) struct NewInvoiceParams {
     udt_type_script: Option<Script>,
     /// The hash algorithm of the invoice.
     hash_algorithm: Option<HashAlgorithm>,
+    /// Whether the invoice is a hold invoice, the received TLC will not be settled until it's released by `settle_held_tlc`, default is false.
+    hold: Option<bool>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -209,7 +211,13 @@ where
             Ok(invoice) => match self
                 .store
                 .insert_invoice(invoice.clone(), Some(params.payment_preimage))
-            {
+                .and_then(|_| {
+                    if params.hold.unwrap_or(false) {
+                        self.store.set_invoice_hold(invoice.payment_hash(), true)
+                    } else {
+                        Ok(())
+                    }
+                }) {
                 Ok(_) => Ok(InvoiceResult {
                     invoice_address: invoice.to_string(),
                     invoice,
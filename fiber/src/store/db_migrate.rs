@@ -1,4 +1,5 @@
 use super::migration::{DefaultMigration, Migrations};
+use super::migrations::mig_20261016::ChannelStateLayoutMigration;
 use crate::Error;
 use rocksdb::DB;
 use std::{cmp::Ordering, sync::Arc};
@@ -14,6 +15,7 @@ impl DbMigrate {
     pub fn new(db: Arc<DB>) -> Self {
         let mut migrations = Migrations::default();
         migrations.add_migration(Arc::new(DefaultMigration::new()));
+        migrations.add_migration(Arc::new(ChannelStateLayoutMigration::new()));
         DbMigrate { migrations, db }
     }
 
@@ -0,0 +1,474 @@
+// Migrate the channel states and the payment sessions persisted with the initial layouts to the
+// current ones. They are serialized by bincode, which can't skip the fields appended or inserted
+// since then with `#[serde(default)]`, so the initial layouts are kept here as versioned structs
+// to deserialize the old data, which are then converted to the current layouts.
+use crate::{
+    fiber::{
+        channel::{
+            default_clock, default_fee_estimator, AddTlcInfo, ChannelActorState,
+            ChannelBasePublicKeys, ChannelConstraints, ChannelState, CommitmentNumbers,
+            FeatureBits, InMemorySigner, PendingTlcs, PublicChannelInfo, RemoveTlcInfo,
+            RetryableRemoveTlc, ShutdownInfo, TLCId, TlcKind, TlcState,
+        },
+        graph::{PaymentSession, PaymentSessionStatus, SessionRoute},
+        hash_algorithm::HashAlgorithm,
+        network::SendPaymentData,
+        serde_utils::{EntityHex, PubNonceAsBytes, U64Hex},
+        types::{
+            ChannelAnnouncement, ChannelUpdate, EcdsaSignature, Hash256, PaymentOnionPacket,
+            Privkey, Pubkey, RemoveTlcReason,
+        },
+    },
+    store::{
+        migration::{Migration, MIGRATION_VERSION_KEY},
+        schema::{CHANNEL_ACTOR_STATE_PREFIX, PAYMENT_SESSION_PREFIX},
+    },
+    Error,
+};
+use ckb_jsonrpc_types::BlockNumber;
+use ckb_types::packed::{OutPoint, Script, Transaction};
+use indicatif::ProgressBar;
+use musig2::{PartialSignature, PubNonce};
+use rocksdb::{prelude::*, WriteBatch, DB};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, OnceLock},
+    time::SystemTime,
+};
+
+const MIGRATION_DB_VERSION: &str = "20261016120000";
+
+pub struct ChannelStateLayoutMigration {
+    version: String,
+}
+
+impl ChannelStateLayoutMigration {
+    pub fn new() -> Self {
+        Self {
+            version: MIGRATION_DB_VERSION.to_string(),
+        }
+    }
+}
+
+fn migrate_values<Old, New>(
+    db: &DB,
+    batch: &mut WriteBatch,
+    prefix: u8,
+    name: &str,
+) -> Result<u64, Error>
+where
+    Old: for<'a> Deserialize<'a> + Into<New>,
+    New: Serialize,
+{
+    let mut count = 0;
+    for (key, value) in db
+        .prefix_iterator(&[prefix])
+        .take_while(|(key, _)| key.starts_with(&[prefix]))
+    {
+        let old: Old = bincode::deserialize(&value).map_err(|err| {
+            Error::DBInternalError(format!("failed to deserialize the old {}: {}", name, err))
+        })?;
+        let new = bincode::serialize(&old.into()).map_err(|err| {
+            Error::DBInternalError(format!("failed to serialize the new {}: {}", name, err))
+        })?;
+        batch
+            .put(key, new)
+            .map_err(|err| Error::DBInternalError(err.to_string()))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+impl Migration for ChannelStateLayoutMigration {
+    fn migrate(
+        &self,
+        db: Arc<DB>,
+        pb: Arc<dyn Fn(u64) -> ProgressBar + Send + Sync>,
+    ) -> Result<Arc<DB>, Error> {
+        let pb = pb(2);
+        let mut batch = WriteBatch::default();
+        let channels = migrate_values::<ChannelActorStateV0, ChannelActorState>(
+            &db,
+            &mut batch,
+            CHANNEL_ACTOR_STATE_PREFIX,
+            "ChannelActorState",
+        )?;
+        pb.inc(1);
+        let sessions = migrate_values::<PaymentSessionV0, PaymentSession>(
+            &db,
+            &mut batch,
+            PAYMENT_SESSION_PREFIX,
+            "PaymentSession",
+        )?;
+        pb.inc(1);
+        // The values are written together with the version, so that an interrupted migration
+        // can be rerun without deserializing the migrated values with the old layouts.
+        batch
+            .put(MIGRATION_VERSION_KEY, MIGRATION_DB_VERSION)
+            .map_err(|err| Error::DBInternalError(err.to_string()))?;
+        db.write(&batch)
+            .map_err(|err| Error::DBInternalError(err.to_string()))?;
+        pb.finish_with_message(format!(
+            "migrated {} channel states and {} payment sessions",
+            channels, sessions
+        ));
+        Ok(db)
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct ChannelActorStateV0 {
+    pub state: ChannelState,
+    pub public_channel_info: Option<PublicChannelInfoV0>,
+    pub local_pubkey: Pubkey,
+    pub remote_pubkey: Pubkey,
+    pub id: Hash256,
+    #[serde_as(as = "Option<EntityHex>")]
+    pub funding_tx: Option<Transaction>,
+    pub funding_tx_confirmed_at: Option<(BlockNumber, u32)>,
+    #[serde_as(as = "Option<EntityHex>")]
+    pub funding_udt_type_script: Option<Script>,
+    pub is_acceptor: bool,
+    pub to_local_amount: u128,
+    pub to_remote_amount: u128,
+    pub local_reserved_ckb_amount: u64,
+    pub remote_reserved_ckb_amount: u64,
+    pub commitment_fee_rate: u64,
+    // Split into `local_delay_epoch` and `remote_delay_epoch`, which were the same.
+    pub commitment_delay_epoch: u64,
+    pub funding_fee_rate: u64,
+    pub signer: InMemorySignerV0,
+    pub local_channel_public_keys: ChannelBasePublicKeys,
+    pub commitment_numbers: CommitmentNumbers,
+    pub local_constraints: ChannelConstraintsV0,
+    pub remote_constraints: ChannelConstraintsV0,
+    pub tlc_state: TlcStateV0,
+    #[serde_as(as = "Option<EntityHex>")]
+    pub remote_shutdown_script: Option<Script>,
+    #[serde_as(as = "EntityHex")]
+    pub local_shutdown_script: Script,
+    #[serde_as(as = "Option<PubNonceAsBytes>")]
+    pub last_used_nonce_in_commitment_signed: Option<PubNonce>,
+    #[serde_as(as = "Vec<(U64Hex, PubNonceAsBytes)>")]
+    pub remote_nonces: Vec<(u64, PubNonce)>,
+    #[serde_as(as = "Option<EntityHex>")]
+    pub latest_commitment_transaction: Option<Transaction>,
+    pub remote_commitment_points: Vec<(u64, Pubkey)>,
+    pub remote_channel_public_keys: Option<ChannelBasePublicKeys>,
+    pub local_shutdown_info: Option<ShutdownInfo>,
+    pub remote_shutdown_info: Option<ShutdownInfo>,
+    pub reestablishing: bool,
+    pub created_at: SystemTime,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct PublicChannelInfoV0 {
+    pub enabled: bool,
+    pub tlc_fee_proportional_millionths: u128,
+    pub tlc_expiry_delta: u64,
+    pub tlc_min_value: u128,
+    pub local_channel_announcement_signature: Option<(EcdsaSignature, PartialSignature)>,
+    pub remote_channel_announcement_signature: Option<(EcdsaSignature, PartialSignature)>,
+    #[serde_as(as = "Option<PubNonceAsBytes>")]
+    pub remote_channel_announcement_nonce: Option<PubNonce>,
+    pub channel_announcement: Option<ChannelAnnouncement>,
+    pub channel_update: Option<ChannelUpdate>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct InMemorySignerV0 {
+    pub funding_key: Privkey,
+    pub tlc_base_key: Privkey,
+    pub musig2_base_nonce: Privkey,
+    pub commitment_seed: [u8; 32],
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct ChannelConstraintsV0 {
+    pub max_tlc_value_in_flight: u128,
+    pub max_tlc_number_in_flight: u64,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub(crate) struct TlcStateV0 {
+    pub local_pending_tlcs: PendingTlcsV0,
+    pub remote_pending_tlcs: PendingTlcsV0,
+    pub retryable_remove_tlcs: Vec<RetryableRemoveTlc>,
+    pub waiting_ack: bool,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub(crate) struct PendingTlcsV0 {
+    pub tlcs: Vec<TlcKindV0>,
+    pub committed_index: usize,
+    pub next_tlc_id: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) enum TlcKindV0 {
+    AddTlc(AddTlcInfoV0),
+    RemoveTlc(RemoveTlcInfo),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct AddTlcInfoV0 {
+    pub channel_id: Hash256,
+    pub tlc_id: TLCId,
+    pub amount: u128,
+    pub payment_hash: Hash256,
+    pub expiry: u64,
+    pub hash_algorithm: HashAlgorithm,
+    pub onion_packet: Option<PaymentOnionPacket>,
+    pub shared_secret: [u8; 32],
+    pub created_at: CommitmentNumbers,
+    pub removed_at: Option<(CommitmentNumbers, RemoveTlcReason)>,
+    pub payment_preimage: Option<Hash256>,
+    pub previous_tlc: Option<(Hash256, TLCId)>,
+}
+
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct PaymentSessionV0 {
+    pub request: SendPaymentDataV0,
+    pub retried_times: u32,
+    pub last_error: Option<String>,
+    pub try_limit: u32,
+    pub status: PaymentSessionStatus,
+    pub created_at: u64,
+    pub last_updated_at: u64,
+    #[serde_as(as = "Option<EntityHex>")]
+    pub first_hop_channel_outpoint: Option<OutPoint>,
+    pub first_hop_tlc_id: Option<u64>,
+    pub route: SessionRoute,
+    pub session_key: [u8; 32],
+}
+
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct SendPaymentDataV0 {
+    pub target_pubkey: Pubkey,
+    pub amount: u128,
+    pub payment_hash: Hash256,
+    pub invoice: Option<String>,
+    pub final_tlc_expiry_delta: u64,
+    pub tlc_expiry_limit: u64,
+    pub timeout: Option<u64>,
+    pub max_fee_amount: Option<u128>,
+    pub max_parts: Option<u64>,
+    pub keysend: bool,
+    #[serde_as(as = "Option<EntityHex>")]
+    pub udt_type_script: Option<Script>,
+    pub preimage: Option<Hash256>,
+    pub allow_self_payment: bool,
+    pub dry_run: bool,
+}
+
+impl From<ChannelActorStateV0> for ChannelActorState {
+    fn from(old: ChannelActorStateV0) -> Self {
+        ChannelActorState {
+            state: old.state,
+            public_channel_info: old.public_channel_info.map(Into::into),
+            local_pubkey: old.local_pubkey,
+            remote_pubkey: old.remote_pubkey,
+            id: old.id,
+            funding_tx: old.funding_tx,
+            funding_tx_confirmed_at: old.funding_tx_confirmed_at,
+            funding_udt_type_script: old.funding_udt_type_script,
+            is_acceptor: old.is_acceptor,
+            to_local_amount: old.to_local_amount,
+            to_remote_amount: old.to_remote_amount,
+            push_amount: 0,
+            local_reserved_ckb_amount: old.local_reserved_ckb_amount,
+            remote_reserved_ckb_amount: old.remote_reserved_ckb_amount,
+            commitment_fee_rate: old.commitment_fee_rate,
+            local_delay_epoch: old.commitment_delay_epoch,
+            remote_delay_epoch: old.commitment_delay_epoch,
+            funding_fee_rate: old.funding_fee_rate,
+            signer: old.signer.into(),
+            local_channel_public_keys: old.local_channel_public_keys,
+            commitment_numbers: old.commitment_numbers,
+            local_constraints: old.local_constraints.into(),
+            remote_constraints: old.remote_constraints.into(),
+            tlc_state: old.tlc_state.into(),
+            remote_shutdown_script: old.remote_shutdown_script,
+            local_shutdown_script: old.local_shutdown_script,
+            last_used_nonce_in_commitment_signed: old.last_used_nonce_in_commitment_signed,
+            remote_nonces: old.remote_nonces,
+            latest_commitment_transaction: old.latest_commitment_transaction,
+            remote_commitment_points: old.remote_commitment_points,
+            remote_commitment_secrets: vec![],
+            remote_channel_public_keys: old.remote_channel_public_keys,
+            local_shutdown_info: old.local_shutdown_info,
+            remote_shutdown_info: old.remote_shutdown_info,
+            reestablishing: old.reestablishing,
+            created_at: old.created_at,
+            balance_history: VecDeque::new(),
+            draining: false,
+            pending_force_close_tx_hashes: vec![],
+            latest_settlement_data: None,
+            anchor_outputs: false,
+            tlc_dust_limit: 0,
+            allowed_hash_algorithms: vec![],
+            commitment_signed_cache: None,
+            pending_commitment_fee_rate: None,
+            chain_hash: None,
+            total_fees_earned: 0,
+            mpp_payments: HashMap::new(),
+            sorted_settlement_outputs: false,
+            balance_invariant_violation: None,
+            features: FeatureBits::before_negotiation(),
+            rate_limited_tlcs: HashSet::new(),
+            tlcs_pending_approval: HashSet::new(),
+            clock: default_clock(),
+            fee_estimator: default_fee_estimator(),
+            state_transition_subscribers: None,
+            commitment_signer: OnceLock::new(),
+            peer_disconnected_at: None,
+            max_remote_commitment_points: None,
+            pending_settlement_data: vec![],
+            remote_funding_contribution: Default::default(),
+        }
+    }
+}
+
+impl From<PublicChannelInfoV0> for PublicChannelInfo {
+    fn from(old: PublicChannelInfoV0) -> Self {
+        PublicChannelInfo {
+            enabled: old.enabled,
+            tlc_fee_proportional_millionths: old.tlc_fee_proportional_millionths,
+            tlc_expiry_delta: old.tlc_expiry_delta,
+            tlc_min_value: old.tlc_min_value,
+            min_tlc_expiry_delta: None,
+            max_tlc_expiry_delta: None,
+            local_channel_announcement_signature: old.local_channel_announcement_signature,
+            remote_channel_announcement_signature: old.remote_channel_announcement_signature,
+            remote_channel_announcement_nonce: old.remote_channel_announcement_nonce,
+            channel_announcement: old.channel_announcement,
+            channel_update: old.channel_update,
+        }
+    }
+}
+
+impl From<InMemorySignerV0> for InMemorySigner {
+    fn from(old: InMemorySignerV0) -> Self {
+        InMemorySigner {
+            funding_key: Some(old.funding_key),
+            tlc_base_key: old.tlc_base_key,
+            musig2_base_nonce: old.musig2_base_nonce,
+            commitment_seed: old.commitment_seed,
+        }
+    }
+}
+
+impl From<ChannelConstraintsV0> for ChannelConstraints {
+    fn from(old: ChannelConstraintsV0) -> Self {
+        // No reserve was kept by the old channels, whose balances may be lower than the default.
+        ChannelConstraints {
+            max_tlc_value_in_flight: old.max_tlc_value_in_flight,
+            max_tlc_number_in_flight: old.max_tlc_number_in_flight,
+            channel_reserve_amount: 0,
+            max_tlc_value_in_flight_percentage: None,
+        }
+    }
+}
+
+impl From<TlcStateV0> for TlcState {
+    fn from(old: TlcStateV0) -> Self {
+        TlcState {
+            local_pending_tlcs: old.local_pending_tlcs.into(),
+            remote_pending_tlcs: old.remote_pending_tlcs.into(),
+            retryable_remove_tlcs: old.retryable_remove_tlcs,
+            retryable_remove_attempts: vec![],
+            waiting_ack: old.waiting_ack,
+        }
+    }
+}
+
+impl From<PendingTlcsV0> for PendingTlcs {
+    fn from(old: PendingTlcsV0) -> Self {
+        PendingTlcs {
+            tlcs: old.tlcs.into_iter().map(Into::into).collect(),
+            committed_index: old.committed_index,
+            next_tlc_id: old.next_tlc_id,
+        }
+    }
+}
+
+impl From<TlcKindV0> for TlcKind {
+    fn from(old: TlcKindV0) -> Self {
+        match old {
+            TlcKindV0::AddTlc(add_tlc) => TlcKind::AddTlc(add_tlc.into()),
+            TlcKindV0::RemoveTlc(remove_tlc) => TlcKind::RemoveTlc(remove_tlc),
+        }
+    }
+}
+
+impl From<AddTlcInfoV0> for AddTlcInfo {
+    fn from(old: AddTlcInfoV0) -> Self {
+        AddTlcInfo {
+            channel_id: old.channel_id,
+            tlc_id: old.tlc_id,
+            amount: old.amount,
+            payment_hash: old.payment_hash,
+            expiry: old.expiry,
+            hash_algorithm: old.hash_algorithm,
+            onion_packet: old.onion_packet,
+            shared_secret: old.shared_secret,
+            created_at: old.created_at,
+            removed_at: old.removed_at,
+            payment_preimage: old.payment_preimage,
+            previous_tlc: old.previous_tlc,
+            custom_records: vec![],
+            next_hop: None,
+        }
+    }
+}
+
+impl From<PaymentSessionV0> for PaymentSession {
+    fn from(old: PaymentSessionV0) -> Self {
+        PaymentSession {
+            request: old.request.into(),
+            retried_times: old.retried_times,
+            last_error: old.last_error,
+            try_limit: old.try_limit,
+            status: old.status,
+            created_at: old.created_at,
+            last_updated_at: old.last_updated_at,
+            first_hop_channel_outpoint: old.first_hop_channel_outpoint,
+            first_hop_tlc_id: old.first_hop_tlc_id,
+            route: old.route,
+            session_key: old.session_key,
+            parts: vec![],
+        }
+    }
+}
+
+impl From<SendPaymentDataV0> for SendPaymentData {
+    fn from(old: SendPaymentDataV0) -> Self {
+        SendPaymentData {
+            target_pubkey: old.target_pubkey,
+            amount: old.amount,
+            payment_hash: old.payment_hash,
+            invoice: old.invoice,
+            final_tlc_expiry_delta: old.final_tlc_expiry_delta,
+            tlc_expiry_limit: old.tlc_expiry_limit,
+            timeout: old.timeout,
+            max_fee_amount: old.max_fee_amount,
+            max_parts: old.max_parts,
+            keysend: old.keysend,
+            udt_type_script: old.udt_type_script,
+            preimage: old.preimage,
+            allow_self_payment: old.allow_self_payment,
+            dry_run: old.dry_run,
+            custom_records: vec![],
+        }
+    }
+}
@@ -1,2 +1,3 @@
 // following new migration should be added here ...
 // pub(crate) mod sample;
+pub(crate) mod mig_20261016;
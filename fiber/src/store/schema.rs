@@ -7,8 +7,12 @@
 /// | 32           | Hash256              | CkbInvoice                  |
 /// | 33           | Payment_hash         | CkbInvoice Preimage         |
 /// | 34           | Payment_hash         | CkbInvoice Status           |
+/// | 35           | Payment_hash         | CkbInvoice Hold Flag        |
 /// | 64           | PeerId | Hash256     | ChannelState                |
 /// | 65...........| OutPoint             | ChannelId                   |
+/// | 66           | Hash256              | ClosedChannelRecord         |
+/// | 67           | Hash256              | StaticChannelBackup         |
+/// | 68           | Hash256 | bool | u64 | SettlementData              |
 /// | 96           | Cursor               | BroadcastMessage            |
 /// | 97           | BroadcastMessageID   | u64                         |
 /// | 192          | Hash256              | PaymentSession              |
@@ -22,8 +26,12 @@ pub(crate) const PEER_ID_NETWORK_ACTOR_STATE_PREFIX: u8 = 16;
 pub(crate) const CKB_INVOICE_PREFIX: u8 = 32;
 pub(crate) const CKB_INVOICE_PREIMAGE_PREFIX: u8 = 33;
 pub(crate) const CKB_INVOICE_STATUS_PREFIX: u8 = 34;
+pub(crate) const CKB_INVOICE_HOLD_PREFIX: u8 = 35;
 pub(crate) const PEER_ID_CHANNEL_ID_PREFIX: u8 = 64;
 pub(crate) const CHANNEL_OUTPOINT_CHANNEL_ID_PREFIX: u8 = 65;
+pub(crate) const CLOSED_CHANNEL_RECORD_PREFIX: u8 = 66;
+pub(crate) const RESTORED_CHANNEL_BACKUP_PREFIX: u8 = 67;
+pub(crate) const SETTLEMENT_DATA_PREFIX: u8 = 68;
 pub(crate) const BROADCAST_MESSAGE_PREFIX: u8 = 96;
 pub(crate) const BROADCAST_MESSAGE_TIMESTAMP_PREFIX: u8 = 97;
 pub(crate) const PAYMENT_SESSION_PREFIX: u8 = 192;
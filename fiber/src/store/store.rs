@@ -2,8 +2,10 @@ use super::db_migrate::DbMigrate;
 use super::schema::*;
 use crate::{
     fiber::{
+        backup::StaticChannelBackup,
         channel::{
-            ChannelActorState, ChannelActorStateStore, ChannelState, RevocationData, SettlementData,
+            ChannelActorState, ChannelActorStateStore, ChannelState, ClosedChannelRecord,
+            RevocationData, SettlementData, MAX_SETTLEMENT_DATA_HISTORY_LENGTH,
         },
         gossip::GossipMessageStore,
         graph::{NetworkGraphStateStore, PaymentSession},
@@ -222,8 +224,12 @@ enum KeyValue {
     CkbInvoice(Hash256, CkbInvoice),
     CkbInvoicePreimage(Hash256, Hash256),
     CkbInvoiceStatus(Hash256, CkbInvoiceStatus),
+    CkbInvoiceHold(Hash256, bool),
     PeerIdChannelId((PeerId, Hash256), ChannelState),
     OutPointChannelId(OutPoint, Hash256),
+    ClosedChannelRecord(Hash256, ClosedChannelRecord),
+    RestoredChannelBackup(Hash256, StaticChannelBackup),
+    SettlementData((Hash256, bool, u64), SettlementData),
     BroadcastMessageTimestamp(BroadcastMessageID, u64),
     BroadcastMessage(Cursor, BroadcastMessage),
     WatchtowerChannel(Hash256, ChannelData),
@@ -263,6 +269,7 @@ impl StoreKeyValue for KeyValue {
             KeyValue::CkbInvoiceStatus(id, _) => {
                 [&[CKB_INVOICE_STATUS_PREFIX], id.as_ref()].concat()
             }
+            KeyValue::CkbInvoiceHold(id, _) => [&[CKB_INVOICE_HOLD_PREFIX], id.as_ref()].concat(),
             KeyValue::PeerIdChannelId((peer_id, channel_id), _) => [
                 &[PEER_ID_CHANNEL_ID_PREFIX],
                 peer_id.as_bytes(),
@@ -272,6 +279,15 @@ impl StoreKeyValue for KeyValue {
             KeyValue::OutPointChannelId(outpoint, _) => {
                 [&[CHANNEL_OUTPOINT_CHANNEL_ID_PREFIX], outpoint.as_slice()].concat()
             }
+            KeyValue::ClosedChannelRecord(channel_id, _) => {
+                [&[CLOSED_CHANNEL_RECORD_PREFIX], channel_id.as_ref()].concat()
+            }
+            KeyValue::RestoredChannelBackup(channel_id, _) => {
+                [&[RESTORED_CHANNEL_BACKUP_PREFIX], channel_id.as_ref()].concat()
+            }
+            KeyValue::SettlementData((channel_id, local, commitment_number), _) => {
+                settlement_data_key(channel_id, *local, *commitment_number)
+            }
             KeyValue::PaymentSession(payment_hash, _) => {
                 [&[PAYMENT_SESSION_PREFIX], payment_hash.as_ref()].concat()
             }
@@ -304,8 +320,18 @@ impl StoreKeyValue for KeyValue {
             KeyValue::CkbInvoice(_, invoice) => serialize_to_vec(invoice, "CkbInvoice"),
             KeyValue::CkbInvoicePreimage(_, preimage) => serialize_to_vec(preimage, "Hash256"),
             KeyValue::CkbInvoiceStatus(_, status) => serialize_to_vec(status, "CkbInvoiceStatus"),
+            KeyValue::CkbInvoiceHold(_, hold) => serialize_to_vec(hold, "bool"),
             KeyValue::PeerIdChannelId(_, state) => serialize_to_vec(state, "ChannelState"),
             KeyValue::OutPointChannelId(_, channel_id) => serialize_to_vec(channel_id, "ChannelId"),
+            KeyValue::ClosedChannelRecord(_, record) => {
+                serialize_to_vec(record, "ClosedChannelRecord")
+            }
+            KeyValue::RestoredChannelBackup(_, backup) => {
+                serialize_to_vec(backup, "StaticChannelBackup")
+            }
+            KeyValue::SettlementData(_, settlement_data) => {
+                serialize_to_vec(settlement_data, "SettlementData")
+            }
             KeyValue::PaymentSession(_, payment_session) => {
                 serialize_to_vec(payment_session, "PaymentSession")
             }
@@ -405,6 +431,10 @@ impl ChannelActorStateStore for Store {
                 ]
                 .concat(),
             );
+            let prefix = [&[SETTLEMENT_DATA_PREFIX], id.as_ref()].concat();
+            for (key, _) in self.prefix_iterator(&prefix) {
+                batch.delete(key);
+            }
             batch.commit();
         }
     }
@@ -446,6 +476,74 @@ impl ChannelActorStateStore for Store {
             .map(|channel_id| deserialize_from(channel_id.as_ref(), "Hash256"))
             .and_then(|channel_id: Hash256| self.get_channel_actor_state(&channel_id))
     }
+
+    fn insert_closed_channel_record(&self, record: ClosedChannelRecord) {
+        let mut batch = self.batch();
+        batch.put_kv(KeyValue::ClosedChannelRecord(record.channel_id, record));
+        batch.commit();
+    }
+
+    fn get_closed_channel_records(&self) -> Vec<ClosedChannelRecord> {
+        let prefix = vec![CLOSED_CHANNEL_RECORD_PREFIX];
+        self.prefix_iterator(&prefix)
+            .map(|(_key, value)| deserialize_from(value.as_ref(), "ClosedChannelRecord"))
+            .collect()
+    }
+
+    fn insert_restored_channel_backup(&self, backup: StaticChannelBackup) {
+        let mut batch = self.batch();
+        batch.put_kv(KeyValue::RestoredChannelBackup(backup.channel_id, backup));
+        batch.commit();
+    }
+
+    fn get_restored_channel_backups(&self) -> Vec<StaticChannelBackup> {
+        let prefix = vec![RESTORED_CHANNEL_BACKUP_PREFIX];
+        self.prefix_iterator(&prefix)
+            .map(|(_key, value)| deserialize_from(value.as_ref(), "StaticChannelBackup"))
+            .collect()
+    }
+
+    fn insert_settlement_data(
+        &self,
+        channel_id: Hash256,
+        local: bool,
+        commitment_number: u64,
+        settlement_data: SettlementData,
+    ) {
+        let mut batch = self.batch();
+        batch.put_kv(KeyValue::SettlementData(
+            (channel_id, local, commitment_number),
+            settlement_data,
+        ));
+        // Prune the settlement data of the oldest commitment to keep the history bounded.
+        if let Some(pruned_number) =
+            commitment_number.checked_sub(MAX_SETTLEMENT_DATA_HISTORY_LENGTH as u64)
+        {
+            batch.delete(settlement_data_key(&channel_id, local, pruned_number));
+        }
+        batch.commit();
+    }
+
+    fn get_settlement_data(
+        &self,
+        channel_id: &Hash256,
+        local: bool,
+        commitment_number: u64,
+    ) -> Option<SettlementData> {
+        self.get(settlement_data_key(channel_id, local, commitment_number))
+            .map(|v| deserialize_from(v.as_ref(), "SettlementData"))
+    }
+}
+
+// The commitment number is big endian so that the settlement data of a channel are sorted by it.
+fn settlement_data_key(channel_id: &Hash256, local: bool, commitment_number: u64) -> Vec<u8> {
+    [
+        &[SETTLEMENT_DATA_PREFIX],
+        channel_id.as_ref(),
+        &[local as u8],
+        commitment_number.to_be_bytes().as_slice(),
+    ]
+    .concat()
 }
 
 impl InvoiceStore for Store {
@@ -501,6 +599,21 @@ impl InvoiceStore for Store {
         self.get(key)
             .map(|v| deserialize_from(v.as_ref(), "CkbInvoiceStatus"))
     }
+
+    fn set_invoice_hold(&self, id: &Hash256, hold: bool) -> Result<(), InvoiceError> {
+        self.get_invoice(id).ok_or(InvoiceError::InvoiceNotFound)?;
+        let mut batch = self.batch();
+        batch.put_kv(KeyValue::CkbInvoiceHold(*id, hold));
+        batch.commit();
+        Ok(())
+    }
+
+    fn is_invoice_held(&self, id: &Hash256) -> bool {
+        let key = [&[CKB_INVOICE_HOLD_PREFIX], id.as_ref()].concat();
+        self.get(key)
+            .map(|v| deserialize_from(v.as_ref(), "bool"))
+            .unwrap_or(false)
+    }
 }
 
 impl NetworkGraphStateStore for Store {
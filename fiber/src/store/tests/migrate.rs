@@ -1,15 +1,32 @@
+use crate::fiber::channel::{
+    AddTlcInfo, ChannelActorState, ChannelBasePublicKeys, ChannelState, CommitmentNumbers,
+    FeatureBits, TLCId, TlcKind,
+};
+use crate::fiber::graph::{PaymentSession, PaymentSessionStatus, SessionRoute};
+use crate::fiber::hash_algorithm::HashAlgorithm;
 use crate::store::db_migrate::DbMigrate;
 use crate::store::migration::DefaultMigration;
 use crate::store::migration::Migration;
 use crate::store::migration::Migrations;
+use crate::store::migration::MIGRATION_VERSION_KEY;
+use crate::store::migrations::mig_20261016::{
+    AddTlcInfoV0, ChannelActorStateV0, ChannelConstraintsV0, InMemorySignerV0, PaymentSessionV0,
+    PendingTlcsV0, PublicChannelInfoV0, SendPaymentDataV0, TlcKindV0, TlcStateV0,
+};
+use crate::store::schema::{CHANNEL_ACTOR_STATE_PREFIX, PAYMENT_SESSION_PREFIX};
 use crate::Error;
+use crate::{gen_rand_fiber_private_key, gen_rand_fiber_public_key, gen_rand_sha256_hash};
+use ckb_types::packed::Script;
 use indicatif::ProgressBar;
+use rocksdb::ops::Get;
 use rocksdb::ops::Open;
+use rocksdb::ops::Put;
 use rocksdb::DBCompressionType;
 use rocksdb::Options;
 use rocksdb::DB;
 use std::cmp::Ordering;
 use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
 
 fn gen_path() -> std::path::PathBuf {
     let tmp_dir = tempfile::Builder::new()
@@ -84,15 +101,15 @@ fn test_run_migration() {
     )));
 
     migrations.add_migration(Arc::new(DummyMigration::new(
-        "20251116135521",
+        "20271116135521",
         run_count.clone(),
     )));
     migrations.add_migration(Arc::new(DummyMigration::new(
-        "20251116135522",
+        "20271116135522",
         run_count.clone(),
     )));
     migrations.add_migration(Arc::new(DummyMigration::new(
-        "20251116135523",
+        "20271116135523",
         run_count.clone(),
     )));
 
@@ -105,3 +122,194 @@ fn test_run_migration() {
     migrations.add_migration(Arc::new(DefaultMigration::new()));
     assert_eq!(migrations.check(db), Ordering::Greater);
 }
+
+fn gen_channel_actor_state_v0() -> ChannelActorStateV0 {
+    let channel_id = gen_rand_sha256_hash();
+    let add_tlc = AddTlcInfoV0 {
+        channel_id,
+        tlc_id: TLCId::Offered(0),
+        amount: 1000,
+        payment_hash: gen_rand_sha256_hash(),
+        expiry: 100,
+        hash_algorithm: HashAlgorithm::Sha256,
+        onion_packet: None,
+        shared_secret: [0u8; 32],
+        created_at: CommitmentNumbers::default(),
+        removed_at: None,
+        payment_preimage: None,
+        previous_tlc: None,
+    };
+    let constraints = ChannelConstraintsV0 {
+        max_tlc_value_in_flight: 100000,
+        max_tlc_number_in_flight: 10,
+    };
+    ChannelActorStateV0 {
+        state: ChannelState::ChannelReady(),
+        public_channel_info: Some(PublicChannelInfoV0 {
+            enabled: true,
+            tlc_fee_proportional_millionths: 1000,
+            tlc_expiry_delta: 86400000,
+            tlc_min_value: 1,
+            local_channel_announcement_signature: None,
+            remote_channel_announcement_signature: None,
+            remote_channel_announcement_nonce: None,
+            channel_announcement: None,
+            channel_update: None,
+        }),
+        local_pubkey: gen_rand_fiber_public_key(),
+        remote_pubkey: gen_rand_fiber_public_key(),
+        id: channel_id,
+        funding_tx: None,
+        funding_tx_confirmed_at: None,
+        funding_udt_type_script: None,
+        is_acceptor: false,
+        to_local_amount: 90000,
+        to_remote_amount: 10000,
+        local_reserved_ckb_amount: 9900000000,
+        remote_reserved_ckb_amount: 9900000000,
+        commitment_fee_rate: 1000,
+        commitment_delay_epoch: 3600,
+        funding_fee_rate: 1000,
+        signer: InMemorySignerV0 {
+            funding_key: gen_rand_fiber_private_key(),
+            tlc_base_key: gen_rand_fiber_private_key(),
+            musig2_base_nonce: gen_rand_fiber_private_key(),
+            commitment_seed: [1u8; 32],
+        },
+        local_channel_public_keys: ChannelBasePublicKeys {
+            funding_pubkey: gen_rand_fiber_public_key(),
+            tlc_base_key: gen_rand_fiber_public_key(),
+        },
+        commitment_numbers: CommitmentNumbers::default(),
+        local_constraints: constraints.clone(),
+        remote_constraints: constraints,
+        tlc_state: TlcStateV0 {
+            local_pending_tlcs: PendingTlcsV0 {
+                tlcs: vec![TlcKindV0::AddTlc(add_tlc)],
+                committed_index: 1,
+                next_tlc_id: 1,
+            },
+            ..Default::default()
+        },
+        remote_shutdown_script: None,
+        local_shutdown_script: Script::default(),
+        last_used_nonce_in_commitment_signed: None,
+        remote_nonces: vec![],
+        latest_commitment_transaction: None,
+        remote_commitment_points: vec![(0, gen_rand_fiber_public_key())],
+        remote_channel_public_keys: None,
+        local_shutdown_info: None,
+        remote_shutdown_info: None,
+        reestablishing: false,
+        created_at: SystemTime::UNIX_EPOCH,
+    }
+}
+
+fn gen_payment_session_v0() -> PaymentSessionV0 {
+    PaymentSessionV0 {
+        request: SendPaymentDataV0 {
+            target_pubkey: gen_rand_fiber_public_key(),
+            amount: 1000,
+            payment_hash: gen_rand_sha256_hash(),
+            invoice: None,
+            final_tlc_expiry_delta: 86400000,
+            tlc_expiry_limit: 1209600000,
+            timeout: Some(60),
+            max_fee_amount: Some(10),
+            max_parts: None,
+            keysend: true,
+            udt_type_script: None,
+            preimage: Some(gen_rand_sha256_hash()),
+            allow_self_payment: false,
+            dry_run: false,
+        },
+        retried_times: 1,
+        last_error: Some("temporary failure".to_string()),
+        try_limit: 5,
+        status: PaymentSessionStatus::Inflight,
+        created_at: 1,
+        last_updated_at: 2,
+        first_hop_channel_outpoint: None,
+        first_hop_tlc_id: Some(0),
+        route: SessionRoute::default(),
+        session_key: [2u8; 32],
+    }
+}
+
+#[test]
+fn test_migrate_baseline_channel_state_and_payment_session() {
+    let migrate = gen_migrate();
+    let db = migrate.db();
+    // The data were persisted by the binary with the initial database version.
+    db.put(MIGRATION_VERSION_KEY, DefaultMigration::new().version())
+        .unwrap();
+
+    let old_state = gen_channel_actor_state_v0();
+    let state_key = [&[CHANNEL_ACTOR_STATE_PREFIX], old_state.id.as_ref()].concat();
+    db.put(&state_key, bincode::serialize(&old_state).unwrap())
+        .unwrap();
+    let old_session = gen_payment_session_v0();
+    let session_key = [
+        &[PAYMENT_SESSION_PREFIX],
+        old_session.request.payment_hash.as_ref(),
+    ]
+    .concat();
+    db.put(&session_key, bincode::serialize(&old_session).unwrap())
+        .unwrap();
+
+    assert_eq!(migrate.check(), Ordering::Less);
+    let db = migrate.migrate().unwrap();
+    assert_eq!(migrate.check(), Ordering::Equal);
+
+    let state: ChannelActorState = bincode::deserialize(&db.get(&state_key).unwrap().unwrap())
+        .expect("deserialize the migrated channel state");
+    assert_eq!(state.id, old_state.id);
+    assert_eq!(state.to_local_amount, 90000);
+    assert_eq!(state.to_remote_amount, 10000);
+    assert_eq!(state.push_amount, 0);
+    assert_eq!(state.local_delay_epoch, 3600);
+    assert_eq!(state.remote_delay_epoch, 3600);
+    assert_eq!(
+        state.signer.funding_key,
+        Some(old_state.signer.funding_key.clone())
+    );
+    assert_eq!(state.local_constraints.max_tlc_value_in_flight, 100000);
+    assert_eq!(state.local_constraints.channel_reserve_amount, 0);
+    assert_eq!(state.features, FeatureBits::before_negotiation());
+    assert_eq!(state.chain_hash, None);
+    assert_eq!(state.total_fees_earned, 0);
+    assert_eq!(
+        state.remote_commitment_points,
+        old_state.remote_commitment_points
+    );
+    let public_channel_info = state.public_channel_info.expect("public channel info");
+    assert_eq!(public_channel_info.tlc_fee_proportional_millionths, 1000);
+    assert_eq!(public_channel_info.min_tlc_expiry_delta, None);
+    match state.tlc_state.local_pending_tlcs.tlcs.as_slice() {
+        [TlcKind::AddTlc(AddTlcInfo {
+            tlc_id,
+            amount,
+            custom_records,
+            next_hop,
+            ..
+        })] => {
+            assert_eq!(*tlc_id, TLCId::Offered(0));
+            assert_eq!(*amount, 1000);
+            assert!(custom_records.is_empty());
+            assert_eq!(*next_hop, None);
+        }
+        tlcs => panic!("unexpected tlcs {:?}", tlcs),
+    }
+
+    let session: PaymentSession = bincode::deserialize(&db.get(&session_key).unwrap().unwrap())
+        .expect("deserialize the migrated payment session");
+    assert_eq!(
+        session.request.payment_hash,
+        old_session.request.payment_hash
+    );
+    assert_eq!(session.request.preimage, old_session.request.preimage);
+    assert!(session.request.custom_records.is_empty());
+    assert_eq!(session.status, PaymentSessionStatus::Inflight);
+    assert_eq!(session.retried_times, 1);
+    assert!(session.parts.is_empty());
+}
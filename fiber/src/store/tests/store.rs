@@ -24,6 +24,7 @@ use musig2::CompactSignature;
 use musig2::SecNonce;
 use secp256k1::SecretKey;
 use secp256k1::{Keypair, Secp256k1};
+use std::sync::Arc;
 use std::time::SystemTime;
 
 fn gen_rand_key_pair() -> Keypair {
@@ -101,6 +102,36 @@ fn test_store_invoice() {
     assert_eq!(store.get_invoice_status(hash), Some(status));
 }
 
+#[test]
+fn test_store_invoice_hold() {
+    let path = TempDir::new("invoice_hold_store");
+
+    let store = Store::new(path).expect("created store failed");
+
+    let preimage = gen_rand_sha256_hash();
+    let invoice = InvoiceBuilder::new(Currency::Fibb)
+        .amount(Some(1280))
+        .payment_preimage(preimage)
+        .build()
+        .unwrap();
+
+    let hash = invoice.payment_hash();
+    store
+        .insert_invoice(invoice.clone(), Some(preimage))
+        .unwrap();
+    assert!(!store.is_invoice_held(hash));
+
+    store.set_invoice_hold(hash, true).unwrap();
+    assert!(store.is_invoice_held(hash));
+
+    store.set_invoice_hold(hash, false).unwrap();
+    assert!(!store.is_invoice_held(hash));
+
+    assert!(store
+        .set_invoice_hold(&gen_rand_sha256_hash(), true)
+        .is_err());
+}
+
 #[test]
 fn test_store_get_broadcast_messages_iter() {
     let path = TempDir::new("test-gossip-store");
@@ -316,6 +347,8 @@ fn test_channel_actor_state_store() {
             tlc_fee_proportional_millionths: 123,
             tlc_expiry_delta: 3,
             tlc_min_value: 10,
+            min_tlc_expiry_delta: None,
+            max_tlc_expiry_delta: None,
             local_channel_announcement_signature: Some((
                 mock_ecdsa_signature(),
                 MaybeScalar::two(),
@@ -336,8 +369,10 @@ fn test_channel_actor_state_store() {
         funding_udt_type_script: Some(Script::default()),
         to_local_amount: 100,
         to_remote_amount: 100,
+        push_amount: 0,
         commitment_fee_rate: 100,
-        commitment_delay_epoch: 100,
+        local_delay_epoch: 100,
+        remote_delay_epoch: 100,
         funding_fee_rate: 100,
         id: gen_rand_sha256_hash(),
         tlc_state: Default::default(),
@@ -359,6 +394,7 @@ fn test_channel_actor_state_store() {
             (0, gen_rand_fiber_public_key()),
             (1, gen_rand_fiber_public_key()),
         ],
+        remote_commitment_secrets: vec![(0, [1u8; 32])],
         local_shutdown_info: None,
         remote_shutdown_info: None,
         local_reserved_ckb_amount: 100,
@@ -368,6 +404,31 @@ fn test_channel_actor_state_store() {
         remote_constraints: ChannelConstraints::default(),
         reestablishing: false,
         created_at: SystemTime::now(),
+        balance_history: Default::default(),
+        draining: false,
+        pending_force_close_tx_hashes: vec![],
+        latest_settlement_data: None,
+        anchor_outputs: false,
+        tlc_dust_limit: 0,
+        allowed_hash_algorithms: vec![],
+        commitment_signed_cache: None,
+        pending_commitment_fee_rate: None,
+        tlcs_pending_approval: Default::default(),
+        chain_hash: None,
+        total_fees_earned: 0,
+        mpp_payments: Default::default(),
+        sorted_settlement_outputs: false,
+        balance_invariant_violation: None,
+        features: FeatureBits::all(),
+        rate_limited_tlcs: Default::default(),
+        clock: Arc::new(SystemClock),
+        fee_estimator: Arc::new(StaticFeeEstimator::default()),
+        state_transition_subscribers: None,
+        commitment_signer: Default::default(),
+        peer_disconnected_at: None,
+        max_remote_commitment_points: None,
+        pending_settlement_data: Default::default(),
+        remote_funding_contribution: Default::default(),
     };
 
     let bincode_encoded = bincode::serialize(&state).unwrap();
@@ -410,6 +471,71 @@ fn test_channel_actor_state_store() {
         .is_none());
 }
 
+#[test]
+fn test_store_closed_channel_records() {
+    let store = generate_store();
+    assert!(store.get_closed_channel_records().is_empty());
+
+    let record = ClosedChannelRecord {
+        channel_id: gen_rand_sha256_hash(),
+        remote_pubkey: gen_rand_fiber_public_key(),
+        to_local_amount: 100,
+        to_remote_amount: 200,
+        close_flags: CloseFlags::UNCOOPERATIVE,
+        closing_tx_hash: gen_rand_sha256_hash(),
+        closed_at: now_timestamp_as_millis_u64(),
+    };
+    store.insert_closed_channel_record(record.clone());
+    // Inserting the record of the same channel again overwrites the old one.
+    store.insert_closed_channel_record(record.clone());
+    assert_eq!(store.get_closed_channel_records(), vec![record.clone()]);
+
+    // The record is kept after the channel state is pruned.
+    store.delete_channel_actor_state(&record.channel_id);
+    assert_eq!(store.get_closed_channel_records(), vec![record]);
+}
+
+#[test]
+fn test_store_settlement_data() {
+    let store = generate_store();
+    let channel_id = gen_rand_sha256_hash();
+    let settlement_data = |x_only_aggregated_pubkey| SettlementData {
+        x_only_aggregated_pubkey,
+        aggregated_signature: CompactSignature::from_bytes(&[0u8; 64]).unwrap(),
+        to_local_output: CellOutput::default(),
+        to_local_output_data: Bytes::default(),
+        to_remote_output: CellOutput::default(),
+        to_remote_output_data: Bytes::default(),
+    };
+
+    store.insert_settlement_data(channel_id, true, 0, settlement_data([1u8; 32]));
+    store.insert_settlement_data(channel_id, false, 0, settlement_data([2u8; 32]));
+    assert_eq!(
+        store.get_settlement_data(&channel_id, true, 0),
+        Some(settlement_data([1u8; 32]))
+    );
+    assert_eq!(
+        store.get_settlement_data(&channel_id, false, 0),
+        Some(settlement_data([2u8; 32]))
+    );
+    assert_eq!(store.get_settlement_data(&channel_id, true, 1), None);
+
+    // The settlement data of the oldest commitment is pruned once the history is full.
+    let commitment_number = MAX_SETTLEMENT_DATA_HISTORY_LENGTH as u64;
+    store.insert_settlement_data(
+        channel_id,
+        true,
+        commitment_number,
+        settlement_data([3u8; 32]),
+    );
+    assert_eq!(store.get_settlement_data(&channel_id, true, 0), None);
+    assert!(store.get_settlement_data(&channel_id, false, 0).is_some());
+    assert_eq!(
+        store.get_settlement_data(&channel_id, true, commitment_number),
+        Some(settlement_data([3u8; 32]))
+    );
+}
+
 #[test]
 fn test_store_payment_session() {
     let path = TempDir::new("payment-history-store-test");
@@ -430,6 +556,7 @@ fn test_store_payment_session() {
         preimage: None,
         allow_self_payment: false,
         dry_run: false,
+        custom_records: vec![],
     };
     let payment_session = PaymentSession::new(payment_data.clone(), 10);
     store.insert_payment_session(payment_session.clone());
@@ -156,7 +156,7 @@ where
                                             match tx.inner {
                                                 Either::Left(tx) => {
                                                     let tx: Transaction = tx.inner.into();
-                                                    if tx.raw().outputs().len() == 1 {
+                                                    if is_commitment_tx(&tx) {
                                                         let output = tx
                                                             .raw()
                                                             .outputs()
@@ -298,6 +298,17 @@ where
     }
 }
 
+// The commitment cell is always the first output of a commitment tx, which may be followed by
+// the anchor outputs. The shutdown tx has no output locked by the commitment lock.
+fn is_commitment_tx(tx: &Transaction) -> bool {
+    let commitment_lock = get_script_by_contract(Contract::CommitmentLock, &[]);
+    tx.raw().outputs().get(0).is_some_and(|output| {
+        let lock = output.lock();
+        lock.code_hash().as_slice() == commitment_lock.code_hash().as_slice()
+            && lock.hash_type().as_slice() == commitment_lock.hash_type().as_slice()
+    })
+}
+
 fn build_revocation_tx(
     commitment_tx_out_point: OutPoint,
     revocation_data: RevocationData,